@@ -0,0 +1,37 @@
+use std::cell::RefCell;
+use std::fmt;
+
+// A Python exception raised by the runtime: the builtin exception type it
+// maps to (e.g. "FileNotFoundError") and its message
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: &'static str,
+    pub message: String,
+}
+
+impl RuntimeError {
+    pub fn new(kind: &'static str, message: impl Into<String>) -> Self {
+        Self { kind, message: message.into() }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+thread_local! {
+    // Error of the last failed call through the C ABI on this thread
+    static LAST_ERROR: RefCell<Option<RuntimeError>> = const { RefCell::new(None) };
+}
+
+pub fn set_last_error(error: RuntimeError) {
+    LAST_ERROR.with(|slot| *slot.borrow_mut() = Some(error));
+}
+
+pub fn last_error() -> Option<RuntimeError> {
+    LAST_ERROR.with(|slot| slot.borrow().clone())
+}
@@ -0,0 +1,67 @@
+// Python's builtin exception hierarchy, used to decide which `except` clause
+// handles an exception raised by the runtime
+
+// (exception, direct base) for every builtin the runtime can raise or match
+const HIERARCHY: &[(&str, &str)] = &[
+    ("Exception", "BaseException"),
+    ("GeneratorExit", "BaseException"),
+    ("KeyboardInterrupt", "BaseException"),
+    ("SystemExit", "BaseException"),
+    ("CancelledError", "BaseException"),
+    ("StopIteration", "Exception"),
+    ("StopAsyncIteration", "Exception"),
+    ("ArithmeticError", "Exception"),
+    ("ZeroDivisionError", "ArithmeticError"),
+    ("OverflowError", "ArithmeticError"),
+    ("AssertionError", "Exception"),
+    ("AttributeError", "Exception"),
+    ("FrozenInstanceError", "AttributeError"),
+    ("LookupError", "Exception"),
+    ("KeyError", "LookupError"),
+    ("IndexError", "LookupError"),
+    ("NameError", "Exception"),
+    ("OSError", "Exception"),
+    ("FileNotFoundError", "OSError"),
+    ("FileExistsError", "OSError"),
+    ("IsADirectoryError", "OSError"),
+    ("PermissionError", "OSError"),
+    ("UnsupportedOperation", "OSError"),
+    ("RuntimeError", "Exception"),
+    ("NotImplementedError", "RuntimeError"),
+    ("RecursionError", "RuntimeError"),
+    ("TypeError", "Exception"),
+    ("ValueError", "Exception"),
+    ("UnicodeError", "ValueError"),
+];
+
+pub fn base_of(exception: &str) -> Option<&'static str> {
+    HIERARCHY.iter().find(|(name, _)| *name == exception).map(|(_, base)| *base)
+}
+
+// Whether `except base` catches `exception`
+pub fn is_subclass(exception: &str, base: &str) -> bool {
+    let mut current = Some(exception);
+    while let Some(name) = current {
+        if name == base {
+            return true;
+        }
+        current = base_of(name);
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walks_up_the_hierarchy() {
+        assert!(is_subclass("KeyError", "KeyError"));
+        assert!(is_subclass("KeyError", "LookupError"));
+        assert!(is_subclass("FileNotFoundError", "Exception"));
+        assert!(is_subclass("GeneratorExit", "BaseException"));
+        assert!(!is_subclass("GeneratorExit", "Exception"));
+        assert!(!is_subclass("ValueError", "LookupError"));
+        assert!(!is_subclass("MyError", "Exception"));
+    }
+}
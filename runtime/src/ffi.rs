@@ -0,0 +1,230 @@
+// C ABI of the runtime, declared in stdlib/eppx_runtime.h. Calls that can fail
+// return -1 (or a null string) and leave the Python exception in the calling
+// thread's error slot, where the C++ side picks it up and throws it.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Mutex;
+
+use crate::error::{self, RuntimeError};
+use crate::exception;
+use crate::file::{FileIO, FileObject};
+use crate::string;
+
+// A string allocated by the runtime; the caller releases it with eppx_rt_string_free
+#[repr(C)]
+pub struct EppxRtString {
+    data: *mut u8,
+    len: usize,
+}
+
+impl EppxRtString {
+    fn new(s: String) -> Self {
+        let bytes = s.into_bytes().into_boxed_slice();
+        let len = bytes.len();
+        Self { data: Box::into_raw(bytes).cast::<u8>(), len }
+    }
+
+    fn null() -> Self {
+        Self { data: ptr::null_mut(), len: 0 }
+    }
+}
+
+// Open files by handle; handles are never reused
+struct FileTable {
+    next: i64,
+    files: BTreeMap<i64, FileObject>,
+}
+
+static FILES: Mutex<FileTable> = Mutex::new(FileTable { next: 1, files: BTreeMap::new() });
+
+fn with_file<T>(handle: i64, f: impl FnOnce(&mut FileObject) -> Result<T, RuntimeError>) -> Result<T, RuntimeError> {
+    let mut table = FILES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let file = table.files.get_mut(&handle)
+        .ok_or_else(|| RuntimeError::new("ValueError", "I/O operation on closed file"))?;
+    f(file)
+}
+
+fn report<T>(result: Result<T, RuntimeError>, failed: T) -> T {
+    result.unwrap_or_else(|error| {
+        error::set_last_error(error);
+        failed
+    })
+}
+
+/// # Safety
+/// `data` must point to `len` readable bytes, or be null.
+unsafe fn str_arg<'a>(data: *const c_char, len: usize) -> Cow<'a, str> {
+    if data.is_null() {
+        return Cow::Borrowed("");
+    }
+    String::from_utf8_lossy(std::slice::from_raw_parts(data.cast::<u8>(), len))
+}
+
+/// Releases a string returned by the runtime.
+///
+/// # Safety
+/// `s` must come from this library and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn eppx_rt_string_free(s: EppxRtString) {
+    if !s.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(s.data, s.len)));
+    }
+}
+
+/// Exception type name of the last failed call on this thread.
+#[no_mangle]
+pub extern "C" fn eppx_rt_last_error_type() -> EppxRtString {
+    EppxRtString::new(error::last_error().map_or_else(String::new, |e| e.kind.to_string()))
+}
+
+/// Exception message of the last failed call on this thread.
+#[no_mangle]
+pub extern "C" fn eppx_rt_last_error_message() -> EppxRtString {
+    EppxRtString::new(error::last_error().map_or_else(String::new, |e| e.message))
+}
+
+/// # Safety
+/// Each name must point to its length in readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn eppx_rt_exception_is_subclass(
+    exception: *const c_char,
+    exception_len: usize,
+    base: *const c_char,
+    base_len: usize,
+) -> bool {
+    exception::is_subclass(&str_arg(exception, exception_len), &str_arg(base, base_len))
+}
+
+/// # Safety
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn eppx_rt_str_upper(data: *const c_char, len: usize) -> EppxRtString {
+    EppxRtString::new(string::upper(&str_arg(data, len)))
+}
+
+/// # Safety
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn eppx_rt_str_lower(data: *const c_char, len: usize) -> EppxRtString {
+    EppxRtString::new(string::lower(&str_arg(data, len)))
+}
+
+/// # Safety
+/// `data` and `sub` must point to their lengths in readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn eppx_rt_str_contains(data: *const c_char, len: usize, sub: *const c_char, sub_len: usize) -> bool {
+    string::contains(&str_arg(data, len), &str_arg(sub, sub_len))
+}
+
+/// # Safety
+/// `data` and `sub` must point to their lengths in readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn eppx_rt_str_count(data: *const c_char, len: usize, sub: *const c_char, sub_len: usize) -> i64 {
+    string::count(&str_arg(data, len), &str_arg(sub, sub_len))
+}
+
+/// Character position of the first occurrence of `sub`, or -1.
+///
+/// # Safety
+/// `data` and `sub` must point to their lengths in readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn eppx_rt_str_index(data: *const c_char, len: usize, sub: *const c_char, sub_len: usize) -> i64 {
+    report(string::index(&str_arg(data, len), &str_arg(sub, sub_len)), -1)
+}
+
+/// The character with code point `code`, or a null string.
+#[no_mangle]
+pub extern "C" fn eppx_rt_chr(code: i64) -> EppxRtString {
+    report(string::chr(code).map(EppxRtString::new), EppxRtString::null())
+}
+
+/// Code point of a one-character string, or -1.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn eppx_rt_ord(data: *const c_char, len: usize) -> i64 {
+    report(string::ord(&str_arg(data, len)), -1)
+}
+
+/// `n` in base 16, 8 or 2 with Python's prefix (hex(), oct(), bin()).
+#[no_mangle]
+pub extern "C" fn eppx_rt_int_to_radix(n: i64, radix: u32) -> EppxRtString {
+    EppxRtString::new(string::to_radix(n, radix))
+}
+
+/// Opens a file and returns its handle, or -1.
+///
+/// # Safety
+/// `path` and `mode` must point to their lengths in readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn eppx_rt_file_open(path: *const c_char, path_len: usize, mode: *const c_char, mode_len: usize) -> i64 {
+    let opened = FileIO::open_file(&str_arg(path, path_len), &str_arg(mode, mode_len), None).map(|file| {
+        let mut table = FILES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let handle = table.next;
+        table.next += 1;
+        table.files.insert(handle, file);
+        handle
+    });
+    report(opened, -1)
+}
+
+/// Reads `size` characters, or everything left when `size` is negative.
+#[no_mangle]
+pub extern "C" fn eppx_rt_file_read(handle: i64, size: i64) -> EppxRtString {
+    let size = usize::try_from(size).ok();
+    report(with_file(handle, |file| FileIO::read_file(file, size)).map(EppxRtString::new), EppxRtString::null())
+}
+
+/// Reads one line including its newline; empty at end of file.
+#[no_mangle]
+pub extern "C" fn eppx_rt_file_readline(handle: i64, size: i64) -> EppxRtString {
+    let size = usize::try_from(size).ok();
+    report(with_file(handle, |file| FileIO::readline(file, size)).map(EppxRtString::new), EppxRtString::null())
+}
+
+/// Writes a string and returns the number of characters written, or -1.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn eppx_rt_file_write(handle: i64, data: *const c_char, len: usize) -> i64 {
+    let data = str_arg(data, len);
+    report(with_file(handle, |file| FileIO::write_file(file, &data)).map(|n| n as i64), -1)
+}
+
+#[no_mangle]
+pub extern "C" fn eppx_rt_file_flush(handle: i64) -> i32 {
+    report(with_file(handle, FileIO::flush_file).map(|_| 0), -1)
+}
+
+/// Closes a file and releases its handle; closing twice is allowed.
+#[no_mangle]
+pub extern "C" fn eppx_rt_file_close(handle: i64) -> i32 {
+    let file = FILES.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).files.remove(&handle);
+    report(file.map_or(Ok(()), |mut file| FileIO::close_file(&mut file)).map(|_| 0), -1)
+}
+
+/// Moves to `offset` relative to `whence` (0 start, 1 current, 2 end); returns the new position, or -1.
+#[no_mangle]
+pub extern "C" fn eppx_rt_file_seek(handle: i64, offset: i64, whence: i32) -> i64 {
+    report(with_file(handle, |file| FileIO::seek_file(file, offset, whence)).map(|pos| pos as i64), -1)
+}
+
+#[no_mangle]
+pub extern "C" fn eppx_rt_file_tell(handle: i64) -> i64 {
+    report(with_file(handle, FileIO::tell_file).map(|pos| pos as i64), -1)
+}
+
+#[no_mangle]
+pub extern "C" fn eppx_rt_file_readable(handle: i64) -> bool {
+    with_file(handle, |file| Ok(FileIO::is_readable(file))).unwrap_or(false)
+}
+
+#[no_mangle]
+pub extern "C" fn eppx_rt_file_writable(handle: i64) -> bool {
+    with_file(handle, |file| Ok(FileIO::is_writable(file))).unwrap_or(false)
+}
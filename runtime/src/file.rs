@@ -0,0 +1,261 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::error::RuntimeError;
+
+// An open() file: the OS handle plus the name and mode it was opened with
+#[derive(Debug)]
+pub struct FileObject {
+    pub filepath: String,
+    pub mode: String,
+    pub encoding: Option<String>,
+    handle: Option<File>,
+}
+
+impl FileObject {
+    pub fn is_open(&self) -> bool {
+        self.handle.is_some()
+    }
+}
+
+// File I/O operations for EPlusPlus runtime
+pub struct FileIO;
+
+impl FileIO {
+    /// Open a file and return a file handle
+    pub fn open_file(filepath: &str, mode: &str, encoding: Option<&str>) -> Result<FileObject, RuntimeError> {
+        if !Self::is_valid_mode(mode) {
+            return Err(RuntimeError::new("ValueError", format!("invalid mode: '{}'", mode)));
+        }
+        let update = mode.contains('+');
+        let mut options = OpenOptions::new();
+        if mode.contains('r') {
+            options.read(true).write(update);
+        } else if mode.contains('w') {
+            options.write(true).create(true).truncate(true).read(update);
+        } else if mode.contains('a') {
+            options.append(true).create(true).read(update);
+        } else {
+            options.write(true).create_new(true).read(update);
+        }
+        let handle = options.open(filepath).map_err(|e| Self::os_error(filepath, e))?;
+        Ok(FileObject {
+            filepath: filepath.to_string(),
+            mode: mode.to_string(),
+            encoding: encoding.map(|s| s.to_string()),
+            handle: Some(handle),
+        })
+    }
+
+    /// Read up to `size` characters, or the rest of the file
+    pub fn read_file(file_obj: &mut FileObject, size: Option<usize>) -> Result<String, RuntimeError> {
+        let (file, filepath) = Self::readable_handle(file_obj)?;
+        let mut buffer = Vec::new();
+        let result = match size {
+            Some(n) => file.take(n as u64).read_to_end(&mut buffer),
+            None => file.read_to_end(&mut buffer),
+        };
+        result.map_err(|e| Self::os_error(filepath, e))?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+
+    /// Read a single line, keeping its newline; empty at end of file
+    pub fn readline(file_obj: &mut FileObject, size: Option<usize>) -> Result<String, RuntimeError> {
+        let (file, filepath) = Self::readable_handle(file_obj)?;
+        let mut line = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let wanted = size.map_or(chunk.len(), |limit| chunk.len().min(limit - line.len()));
+            if wanted == 0 {
+                break;
+            }
+            let read = file.read(&mut chunk[..wanted]).map_err(|e| Self::os_error(filepath, e))?;
+            if read == 0 {
+                break;
+            }
+            if let Some(end) = chunk[..read].iter().position(|&b| b == b'\n') {
+                line.extend_from_slice(&chunk[..=end]);
+                // Leave what follows the newline for the next read
+                let unread = (read - end - 1) as i64;
+                file.seek(SeekFrom::Current(-unread)).map_err(|e| Self::os_error(filepath, e))?;
+                break;
+            }
+            line.extend_from_slice(&chunk[..read]);
+        }
+        Ok(String::from_utf8_lossy(&line).into_owned())
+    }
+
+    /// Read the remaining lines; stops once `hint` characters have been read
+    pub fn readlines(file_obj: &mut FileObject, hint: Option<usize>) -> Result<Vec<String>, RuntimeError> {
+        let mut lines = Vec::new();
+        let mut total_size = 0;
+        loop {
+            let line = Self::readline(file_obj, None)?;
+            if line.is_empty() {
+                break;
+            }
+            total_size += line.len();
+            lines.push(line);
+            if hint.is_some_and(|limit| total_size >= limit) {
+                break;
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Write to a file
+    pub fn write_file(file_obj: &mut FileObject, data: &str) -> Result<usize, RuntimeError> {
+        if !Self::is_writable(file_obj) {
+            return Err(Self::closed_or_unsupported(file_obj, "not writable"));
+        }
+        let (file, filepath) = Self::open_handle(file_obj)?;
+        file.write_all(data.as_bytes()).map_err(|e| Self::os_error(filepath, e))?;
+        Ok(data.chars().count())
+    }
+
+    /// Write multiple lines to a file
+    pub fn writelines(file_obj: &mut FileObject, lines: &[String]) -> Result<(), RuntimeError> {
+        for line in lines {
+            Self::write_file(file_obj, line)?;
+        }
+        Ok(())
+    }
+
+    /// Close a file; closing twice is allowed
+    pub fn close_file(file_obj: &mut FileObject) -> Result<(), RuntimeError> {
+        if let Some(mut file) = file_obj.handle.take() {
+            file.flush().map_err(|e| Self::os_error(&file_obj.filepath, e))?;
+        }
+        Ok(())
+    }
+
+    /// Flush file buffers
+    pub fn flush_file(file_obj: &mut FileObject) -> Result<(), RuntimeError> {
+        let (file, filepath) = Self::open_handle(file_obj)?;
+        file.flush().map_err(|e| Self::os_error(filepath, e))
+    }
+
+    /// Seek to a position in the file
+    pub fn seek_file(file_obj: &mut FileObject, offset: i64, whence: i32) -> Result<u64, RuntimeError> {
+        let seek_from = match whence {
+            0 if offset >= 0 => SeekFrom::Start(offset as u64), // SEEK_SET
+            0 => return Err(RuntimeError::new("ValueError", format!("negative seek position {}", offset))),
+            1 => SeekFrom::Current(offset),                     // SEEK_CUR
+            2 => SeekFrom::End(offset),                         // SEEK_END
+            _ => return Err(RuntimeError::new("ValueError", format!("invalid whence ({}, should be 0, 1 or 2)", whence))),
+        };
+        let (file, filepath) = Self::open_handle(file_obj)?;
+        file.seek(seek_from).map_err(|e| Self::os_error(filepath, e))
+    }
+
+    /// Tell current position in file
+    pub fn tell_file(file_obj: &mut FileObject) -> Result<u64, RuntimeError> {
+        let (file, filepath) = Self::open_handle(file_obj)?;
+        file.stream_position().map_err(|e| Self::os_error(filepath, e))
+    }
+
+    /// Check if file is readable
+    pub fn is_readable(file_obj: &FileObject) -> bool {
+        file_obj.is_open() && (file_obj.mode.contains('r') || file_obj.mode.contains('+'))
+    }
+
+    /// Check if file is writable
+    pub fn is_writable(file_obj: &FileObject) -> bool {
+        file_obj.is_open() && (file_obj.mode.contains(['w', 'a', 'x', '+']))
+    }
+
+    // The OS handle, with the path for error messages
+    fn open_handle(file_obj: &mut FileObject) -> Result<(&mut File, &str), RuntimeError> {
+        let file = file_obj.handle.as_mut().ok_or_else(|| RuntimeError::new("ValueError", "I/O operation on closed file"))?;
+        Ok((file, &file_obj.filepath))
+    }
+
+    fn readable_handle(file_obj: &mut FileObject) -> Result<(&mut File, &str), RuntimeError> {
+        if !Self::is_readable(file_obj) {
+            return Err(Self::closed_or_unsupported(file_obj, "not readable"));
+        }
+        Self::open_handle(file_obj)
+    }
+
+    fn closed_or_unsupported(file_obj: &FileObject, message: &str) -> RuntimeError {
+        if file_obj.is_open() {
+            RuntimeError::new("UnsupportedOperation", message)
+        } else {
+            RuntimeError::new("ValueError", "I/O operation on closed file")
+        }
+    }
+
+    fn os_error(filepath: &str, error: io::Error) -> RuntimeError {
+        match error.kind() {
+            io::ErrorKind::NotFound => RuntimeError::new("FileNotFoundError", format!("No such file or directory: '{}'", filepath)),
+            io::ErrorKind::AlreadyExists => RuntimeError::new("FileExistsError", format!("File exists: '{}'", filepath)),
+            io::ErrorKind::PermissionDenied => RuntimeError::new("PermissionError", format!("Permission denied: '{}'", filepath)),
+            _ => RuntimeError::new("OSError", format!("{}: '{}'", error, filepath)),
+        }
+    }
+
+    /// Validate file mode string
+    fn is_valid_mode(mode: &str) -> bool {
+        let valid_chars = ['r', 'w', 'a', 'x', 'b', 't', '+'];
+        let mut has_main_mode = false;
+        let mut has_binary = false;
+        let mut has_text = false;
+
+        for ch in mode.chars() {
+            if !valid_chars.contains(&ch) {
+                return false;
+            }
+
+            match ch {
+                'r' | 'w' | 'a' | 'x' => {
+                    if has_main_mode {
+                        return false; // Only one main mode allowed
+                    }
+                    has_main_mode = true;
+                }
+                'b' => {
+                    if has_binary || has_text {
+                        return false; // Can't have both binary and text
+                    }
+                    has_binary = true;
+                }
+                't' => {
+                    if has_binary || has_text {
+                        return false; // Can't have both binary and text
+                    }
+                    has_text = true;
+                }
+                '+' => {} // Can always have plus
+                _ => return false,
+            }
+        }
+
+        has_main_mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_then_reads_lines() {
+        let dir = std::env::temp_dir().join(format!("eppx_runtime_file_{}", std::process::id()));
+        let path = dir.to_string_lossy().into_owned();
+        let mut writer = FileIO::open_file(&path, "w", None).unwrap();
+        FileIO::write_file(&mut writer, "first\n").unwrap();
+        FileIO::write_file(&mut writer, "second").unwrap();
+        FileIO::close_file(&mut writer).unwrap();
+
+        let mut reader = FileIO::open_file(&path, "r", None).unwrap();
+        assert_eq!(FileIO::readline(&mut reader, None).unwrap(), "first\n");
+        assert_eq!(FileIO::readlines(&mut reader, None).unwrap(), vec!["second".to_string()]);
+        assert_eq!(FileIO::readline(&mut reader, None).unwrap(), "");
+        assert_eq!(FileIO::write_file(&mut reader, "x").unwrap_err().kind, "UnsupportedOperation");
+        FileIO::close_file(&mut reader).unwrap();
+        assert_eq!(FileIO::read_file(&mut reader, None).unwrap_err().kind, "ValueError");
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(FileIO::open_file(&path, "r", None).unwrap_err().kind, "FileNotFoundError");
+    }
+}
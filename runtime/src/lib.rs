@@ -0,0 +1,13 @@
+// E++ runtime library: the parts of the runtime that don't depend on the
+// generated program's types (file I/O, str methods and conversions, the
+// exception hierarchy). It is built as a static library and linked into every
+// program `eppx build` produces; the C++ side reaches it through
+// stdlib/eppx_runtime.h. Containers and eppx_variant stay in
+// stdlib/builtins.hpp: they are templates over the program's classes and value
+// types, which a C ABI could only carry by boxing every element.
+
+pub mod error;
+pub mod exception;
+pub mod ffi;
+pub mod file;
+pub mod string;
@@ -0,0 +1,92 @@
+// str methods and conversions that work on code points: C++'s <cctype> has no
+// Unicode tables and std::string counts bytes
+
+use crate::error::RuntimeError;
+
+pub fn upper(s: &str) -> String {
+    s.to_uppercase()
+}
+
+pub fn lower(s: &str) -> String {
+    s.to_lowercase()
+}
+
+// `sub in s`
+pub fn contains(s: &str, sub: &str) -> bool {
+    s.contains(sub)
+}
+
+// s.count(sub): non-overlapping occurrences; the empty string occurs between
+// every two characters and at both ends
+pub fn count(s: &str, sub: &str) -> i64 {
+    if sub.is_empty() {
+        s.chars().count() as i64 + 1
+    } else {
+        s.matches(sub).count() as i64
+    }
+}
+
+// s.index(sub): the character position of the first occurrence
+pub fn index(s: &str, sub: &str) -> Result<i64, RuntimeError> {
+    s.find(sub)
+        .map(|byte| s[..byte].chars().count() as i64)
+        .ok_or_else(|| RuntimeError::new("ValueError", "substring not found"))
+}
+
+pub fn chr(code: i64) -> Result<String, RuntimeError> {
+    u32::try_from(code)
+        .ok()
+        .and_then(char::from_u32)
+        .map(String::from)
+        .ok_or_else(|| RuntimeError::new("ValueError", "chr() arg not in range(0x110000)"))
+}
+
+pub fn ord(s: &str) -> Result<i64, RuntimeError> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(i64::from(u32::from(c))),
+        _ => Err(RuntimeError::new(
+            "TypeError",
+            format!("ord() expected a character, but string of length {} found", s.chars().count()),
+        )),
+    }
+}
+
+// hex(), oct() and bin(): the sign goes before the prefix (`-0x1f`)
+pub fn to_radix(n: i64, radix: u32) -> String {
+    let sign = if n < 0 { "-" } else { "" };
+    let magnitude = n.unsigned_abs();
+    match radix {
+        16 => format!("{}0x{:x}", sign, magnitude),
+        8 => format!("{}0o{:o}", sign, magnitude),
+        _ => format!("{}0b{:b}", sign, magnitude),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_and_finds_characters() {
+        assert_eq!(count("banana", "an"), 2);
+        assert_eq!(count("héllo", ""), 6);
+        assert_eq!(index("héllo", "l").unwrap(), 2);
+        assert_eq!(index("abc", "z").unwrap_err().kind, "ValueError");
+        assert!(contains("héllo", "él"));
+        assert_eq!(upper("straße"), "STRASSE");
+    }
+
+    #[test]
+    fn converts_code_points_and_radixes() {
+        assert_eq!(chr(233).unwrap(), "é");
+        assert_eq!(chr(0x110000).unwrap_err().kind, "ValueError");
+        assert_eq!(ord("é").unwrap(), 233);
+        assert_eq!(ord("ab").unwrap_err().message, "ord() expected a character, but string of length 2 found");
+        assert_eq!(to_radix(255, 16), "0xff");
+        assert_eq!(to_radix(-31, 16), "-0x1f");
+        assert_eq!(to_radix(8, 8), "0o10");
+        assert_eq!(to_radix(-5, 2), "-0b101");
+        assert_eq!(to_radix(0, 2), "0b0");
+    }
+}
@@ -1,12 +1,15 @@
 // AST module placeholder
 
-#[derive(Debug, Clone, PartialEq)]
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum AstNode {
     Statement(Statement),
     // Future: ExpressionNode(Expression), Definition(Definition), etc.
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum Statement {
     Assignment {
         target: Box<Expression>, // Changed from name: String to target: Box<Expression>
@@ -22,15 +25,29 @@ pub enum Statement {
     While {
         condition: Box<Expression>,
         body: Vec<AstNode>,
+        else_body: Option<Vec<AstNode>>, // runs when the loop ends without `break`
     },    For {
         vars: Vec<String>,  // Changed from single var to multiple vars
         iterable: Box<Expression>,
         body: Vec<AstNode>,
-    },FunctionDef {
+        else_body: Option<Vec<AstNode>>, // runs when the loop ends without `break`
+        is_async: bool, // `async for`
+    },
+    ChainedAssignment { // `a = b = value`, assigned left to right
+        targets: Vec<Expression>,
+        value: Box<Expression>,
+    },
+    Assert {
+        test: Box<Expression>,
+        msg: Option<Box<Expression>>,
+    },
+    Delete(Vec<Expression>), // `del x, d[k], obj.attr`
+    FunctionDef {
         name: String,
         params: Vec<String>,
         body: Vec<AstNode>,
         decorators: Vec<Decorator>, // Added decorators support
+        is_async: bool, // `async def`
     },
     Print(Box<Expression>),
     Return(Option<Box<Expression>>),
@@ -41,8 +58,10 @@ pub enum Statement {
     #[allow(dead_code)] // Allowed because it's a planned feature
     ClassDef {
         name: String,
-        base: Option<String>, // Optional base class name for inheritance
-        body: Vec<AstNode>, // Contains assignments (attributes) and function definitions (methods, including __init__)
+        bases: Vec<Expression>, // Base class expressions, e.g. `Base` or `Generic[T]`
+        keywords: Vec<(String, Expression)>, // `metaclass=...` and arguments for `__init_subclass__`
+        decorators: Vec<Decorator>,
+        body: Vec<AstNode>, // Contains assignments (attributes), function definitions (methods, including __init__) and nested classes
     },
     TryExcept {
         try_body: Vec<AstNode>,
@@ -54,24 +73,42 @@ pub enum Statement {
     With {
         items: Vec<WithItem>,
         body: Vec<AstNode>,
+        is_async: bool, // `async with`
     },
     Yield(Option<Box<Expression>>), // For generator functions: yield or yield value
+    Import {
+        module: String,        // dotted module name
+        alias: Option<String>, // `import module as alias`
+    },
+    ImportFrom {
+        module: String,                       // dotted module name
+        names: Vec<(String, Option<String>)>, // `from module import name as alias, ...`
+    },
+    AnnotatedAssignment {
+        target: Box<Expression>,
+        annotation: Box<Expression>,
+        value: Option<Box<Expression>>, // `x: int` alone only declares the annotation
+    },
+    Match {
+        subject: Box<Expression>,
+        cases: Vec<MatchCase>, // tried in order, the first match wins
+    },
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum Decorator {
     Simple(String),                           // @decorator_name
     WithArgs(String, Vec<Argument>),          // @decorator_name(arg1, name=arg2, ...)
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum Argument {
     Positional(Expression),                   // func(expr)
     Keyword(String, Expression),              // func(name=expr)
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum AssignmentOperator {
     Assign,         // =
     AddAssign,      // +=
@@ -90,7 +127,7 @@ pub enum AssignmentOperator {
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy, PartialEq)] // Added Copy
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)] // Added Copy
 pub enum UnaryOp {
     Not,    // Logical NOT
     BitNot, // Bitwise NOT (~)
@@ -98,8 +135,8 @@ pub enum UnaryOp {
     // UnaryPlus is typically a no-op, so we don't need a variant for it
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Clone, PartialEq)]
+#[allow(dead_code, clippy::enum_variant_names)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum Expression {
     StringLiteral(String),
     IntegerLiteral(i64),
@@ -111,12 +148,28 @@ pub enum Expression {
     DictLiteral(Vec<(Expression, Expression)>), // For dict literals
     SetLiteral(Vec<Expression>), // For set literals
     FrozensetLiteral(Vec<Expression>), // For frozenset literals
-    ComplexLiteral(Box<Expression>, Box<Expression>), // For complex(a, b)
+    ImaginaryLiteral(f64), // For `3j`; complex(a, b) is an ordinary call
     Identifier(String),
     BinaryOperation {
         left: Box<Expression>,
         op: BinOp,
         right: Box<Expression>,
+    },
+    // Chained comparison `a < b < c`: each operand is evaluated once and the
+    // chain stops at the first false comparison. Single comparisons stay BinaryOperation.
+    Compare {
+        left: Box<Expression>,
+        ops: Vec<BinOp>,
+        comparators: Vec<Expression>,
+    },
+    IfExp { // `body if test else orelse`
+        test: Box<Expression>,
+        body: Box<Expression>,
+        orelse: Box<Expression>,
+    },
+    NamedExpr { // `(target := value)`
+        target: String,
+        value: Box<Expression>,
     },    UnaryOperation { // New variant for unary operations
         op: UnaryOp,
         operand: Box<Expression>,
@@ -146,6 +199,7 @@ pub enum Expression {
     Call {
         callee: Box<Expression>,
         args: Vec<Expression>,
+        keywords: Vec<(String, Expression)>, // name=value arguments, in source order
     },
     AttributeAccess {
         object: Box<Expression>,
@@ -155,10 +209,13 @@ pub enum Expression {
         object: Box<Expression>,
         index: Box<Expression>,
     },
+    Yield(Option<Box<Expression>>), // `yield value` used as a value (e.g. `x = yield value`)
+    YieldFrom(Box<Expression>),     // `yield from iterable`
+    Await(Box<Expression>),         // `await awaitable`, only inside `async def`
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy, PartialEq)] // Added Copy
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)] // Added Copy
 pub enum BinOp {
     Add,
     Sub,
@@ -196,20 +253,48 @@ pub enum BinOp {
     NotIn,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct ExceptHandler {
     pub exception_type: Option<Expression>,
     pub name: Option<String>,
     pub body: Vec<AstNode>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct WithItem {
     pub context_expr: Expression,
     pub optional_vars: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MatchCase {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>, // `case pattern if guard:`
+    pub body: Vec<AstNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Pattern {
+    Literal(Expression),                  // 1, -2.5, "text", None, True, False
+    Value(Expression),                    // Color.RED (a dotted name)
+    Capture(String),                      // name
+    Wildcard,                             // _
+    Sequence(Vec<Pattern>),               // [a, *rest] or (a, b)
+    Star(Option<String>),                 // *rest (*_ captures nothing), only inside a sequence
+    Mapping {
+        entries: Vec<(Expression, Pattern)>, // key: pattern
+        rest: Option<String>,                // **rest
+    },
+    Class {
+        name: String,
+        positional: Vec<Pattern>,         // matched against the attributes named by __match_args__
+        keywords: Vec<(String, Pattern)>, // attr=pattern
+    },
+    Or(Vec<Pattern>),                     // p1 | p2
+    As(Box<Pattern>, String),             // pattern as name
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Comprehension {
     pub target: Vec<String>,    // Variable names (e.g., ["k", "v"] in "for k, v in items()")
     pub iter: Box<Expression>,  // Iterable expression (e.g., "range(10)")
@@ -0,0 +1,56 @@
+// Package archives: a gzipped tar of a package's codon.toml and src/, as
+// `eppx package` writes them and registry installs unpack them. Entries carry no
+// timestamps or owners, so packaging the same sources gives the same archive
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+pub const ARCHIVE_EXTENSION: &str = "tar.gz";
+
+// An archive of `files` (relative to `dir`), with `manifest` in place of the codon.toml on disk
+pub fn create(dir: &Path, files: &[PathBuf], manifest: &str) -> io::Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for file in files {
+        let contents = if file == Path::new("codon.toml") {
+            manifest.as_bytes().to_vec()
+        } else {
+            fs::read(dir.join(file))?
+        };
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        builder.append_data(&mut header, file, contents.as_slice())?;
+    }
+    builder.into_inner()?.finish()
+}
+
+// Extracts the regular files of `archive` into `destination`; entries that would
+// land outside it are an error
+pub fn unpack(archive: &Path, destination: &Path) -> io::Result<()> {
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(archive)?));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        if !path.components().all(|component| matches!(component, Component::Normal(_))) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("archive entry {} is outside the package", path.display()),
+            ));
+        }
+        let target = destination.join(&path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut File::create(target)?)?;
+    }
+    Ok(())
+}
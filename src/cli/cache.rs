@@ -0,0 +1,96 @@
+// Content-addressed cache of native executables under .eppx_build/cache/, so
+// rebuilding an unchanged program skips parsing, code generation and the C++ compiler
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const CACHE_DIR: &str = "cache";
+
+// Identifies everything the executable depends on: the program, the compiler
+// and its flags, the codegen options, and the eppx binary and runtime library
+pub fn cache_key(source: &str, toolchain_fingerprint: &str, options: &str, runtime_library: &Path) -> String {
+    let eppx = std::env::current_exe().map(|exe| file_stamp(&exe)).unwrap_or_default();
+    let parts = [env!("CARGO_PKG_VERSION"), &eppx, &file_stamp(runtime_library), toolchain_fingerprint, options, source];
+    let mut context = md5::Context::new();
+    for part in parts {
+        context.consume(part.as_bytes());
+        context.consume([0]);
+    }
+    format!("{:x}", context.compute())
+}
+
+// Size and modification time: rebuilding eppx or the runtime changes it
+fn file_stamp(path: &Path) -> String {
+    fs::metadata(path)
+        .map(|metadata| format!("{} {:?}", metadata.len(), metadata.modified().ok()))
+        .unwrap_or_default()
+}
+
+fn entry_path(build_dir: &Path, key: &str) -> PathBuf {
+    build_dir.join(CACHE_DIR).join(key)
+}
+
+// Copies the cached executable for `key` to `exec_path`; false when there is none
+pub fn restore(build_dir: &Path, key: &str, exec_path: &Path) -> io::Result<bool> {
+    let entry = entry_path(build_dir, key);
+    if !entry.is_file() {
+        return Ok(false);
+    }
+    fs::copy(entry, exec_path)?;
+    Ok(true)
+}
+
+// Entries are written under a temporary name first, so concurrent builds never see a partial one
+pub fn store(build_dir: &Path, key: &str, exec_path: &Path) -> io::Result<()> {
+    fs::create_dir_all(build_dir.join(CACHE_DIR))?;
+    let entry = entry_path(build_dir, key);
+    let partial = entry.with_extension(format!("partial-{}", std::process::id()));
+    fs::copy(exec_path, &partial)?;
+    fs::rename(partial, entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_key_covers_every_input() {
+        let runtime = Path::new("missing-runtime.a");
+        let key = cache_key("print(1)", "g++ 13", "-O2", runtime);
+        assert_eq!(key, cache_key("print(1)", "g++ 13", "-O2", runtime));
+        assert_ne!(key, cache_key("print(2)", "g++ 13", "-O2", runtime));
+        assert_ne!(key, cache_key("print(1)", "g++ 14", "-O2", runtime));
+        assert_ne!(key, cache_key("print(1)", "g++ 13", "-O0", runtime));
+        // Parts are separated, so moving text from one part to the next changes the key
+        assert_ne!(cache_key("ab", "c", "", runtime), cache_key("a", "bc", "", runtime));
+    }
+
+    #[test]
+    fn test_cache_key_changes_with_runtime_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let runtime = dir.path().join("libeppx_runtime.a");
+        fs::write(&runtime, "v1").unwrap();
+        let before = cache_key("print(1)", "g++", "", &runtime);
+        fs::write(&runtime, "v2 is longer").unwrap();
+        assert_ne!(before, cache_key("print(1)", "g++", "", &runtime));
+    }
+
+    #[test]
+    fn test_store_then_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let exec = dir.path().join("program");
+        let restored = dir.path().join("restored");
+        assert!(!restore(dir.path(), "abc", &restored).unwrap());
+
+        fs::write(&exec, "binary").unwrap();
+        store(dir.path(), "abc", &exec).unwrap();
+        assert!(restore(dir.path(), "abc", &restored).unwrap());
+        assert_eq!(fs::read_to_string(&restored).unwrap(), "binary");
+        assert!(!restore(dir.path(), "other", &restored).unwrap());
+
+        // Only the finished entry is left behind
+        let entries: Vec<_> = fs::read_dir(dir.path().join(CACHE_DIR)).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(entries, vec![std::ffi::OsString::from("abc")]);
+    }
+}
@@ -0,0 +1,148 @@
+// `eppx fmt`: rewrites `.eppx` files in canonical form. Without files it formats
+// the project's src/ and tests/, or every member's in a workspace; `--check`
+// changes nothing and fails if any file isn't formatted, for CI
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::formatter::format_source;
+
+use super::output;
+use super::project::Project;
+use super::workspace::Workspace;
+use super::CliError;
+
+pub fn handle_fmt(paths: &[PathBuf], check: bool) -> Result<String, CliError> {
+    let files = if paths.is_empty() { project_files()? } else { source_files(paths)? };
+    let mut unformatted = Vec::new();
+    for file in &files {
+        let source = fs::read_to_string(file)?;
+        let formatted = format_source(&source).map_err(|e| CliError::Format(format!("{}: {}", file.display(), e)))?;
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{}", file.display());
+        } else {
+            fs::write(file, &formatted)?;
+            output::detail(format!("Formatted {}", file.display()));
+        }
+        unformatted.push(file);
+    }
+    let count = |n: usize| if n == 1 { "1 file".to_string() } else { format!("{} files", n) };
+    match (check, unformatted.len()) {
+        (true, 0) => Ok(format!("{} already formatted", count(files.len()))),
+        (true, n) => Err(CliError::Format(format!("{} of {} would be reformatted; run eppx fmt", n, count(files.len())))),
+        (false, n) => Ok(format!("Reformatted {} of {}", n, count(files.len()))),
+    }
+}
+
+// The sources of the project around the current directory, or of every workspace member
+pub fn project_files() -> Result<Vec<PathBuf>, CliError> {
+    let cwd = env::current_dir()?;
+    let projects = match Project::enclosing()? {
+        Some(project) => vec![project],
+        None => match Workspace::around(&cwd)? {
+            Some(workspace) => workspace.members()?,
+            None => return Err(CliError::NoProject(cwd)),
+        },
+    };
+    let mut dirs = Vec::new();
+    for project in projects {
+        dirs.push(project.source_dir());
+        dirs.push(project.tests_dir());
+        dirs.push(project.entry_point());
+    }
+    dirs.retain(|dir| dir.exists());
+    source_files(&dirs)
+}
+
+// The files given, and the `.eppx` files under the directories given
+pub fn source_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, CliError> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            collect(path, &mut files)?;
+        } else if path.is_file() {
+            files.push(path.clone());
+        } else {
+            return Err(CliError::FileNotFound(path.clone()));
+        }
+    }
+    // An entry point in src/ is listed twice
+    let mut seen = Vec::new();
+    files.retain(|file| {
+        let canonical = fs::canonicalize(file).unwrap_or_else(|_| file.clone());
+        let new = !seen.contains(&canonical);
+        seen.push(canonical);
+        new
+    });
+    Ok(files)
+}
+
+// `.eppx` files under `dir` in name order, skipping hidden directories such as .eppx_build
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), CliError> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?.map(|entry| entry.map(|entry| entry.path())).collect::<Result<_, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with('.')) {
+            continue;
+        }
+        if path.is_dir() {
+            collect(&path, files)?;
+        } else if path.extension().is_some_and(|extension| extension == "eppx") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_fails_until_files_are_formatted() {
+        let dir = tempfile::tempdir().unwrap();
+        let messy = dir.path().join("messy.eppx");
+        let tidy = dir.path().join("tidy.eppx");
+        fs::write(&messy, "x=(1+2)*3 #note\n").unwrap();
+        fs::write(&tidy, "y = 1\n").unwrap();
+        let paths = vec![dir.path().to_path_buf()];
+
+        // `--check` fails, which is a failing exit status, and leaves the file alone
+        match handle_fmt(&paths, true) {
+            Err(CliError::Format(message)) => assert_eq!(message, "1 of 2 files would be reformatted; run eppx fmt"),
+            other => panic!("expected a format error, got {:?}", other),
+        }
+        assert_eq!(fs::read_to_string(&messy).unwrap(), "x=(1+2)*3 #note\n");
+
+        assert_eq!(handle_fmt(&paths, false).unwrap(), "Reformatted 1 of 2 files");
+        assert_eq!(fs::read_to_string(&messy).unwrap(), "x = (1 + 2) * 3  # note\n");
+        assert_eq!(handle_fmt(&paths, true).unwrap(), "2 files already formatted");
+    }
+
+    #[test]
+    fn test_check_reports_files_that_do_not_parse() {
+        let dir = tempfile::tempdir().unwrap();
+        let broken = dir.path().join("broken.eppx");
+        fs::write(&broken, "def f(:\n").unwrap();
+        match handle_fmt(std::slice::from_ref(&broken), true) {
+            Err(CliError::Format(message)) => assert!(message.starts_with(&format!("{}: line 1", broken.display())), "{}", message),
+            other => panic!("expected a format error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_statements_run_together_on_a_line_are_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let joined = dir.path().join("joined.eppx");
+        fs::write(&joined, "a = 1\ne = f'{a} x'\n").unwrap();
+        match handle_fmt(std::slice::from_ref(&joined), false) {
+            Err(CliError::Format(message)) => assert!(message.starts_with(&format!("{}: line 2", joined.display())), "{}", message),
+            other => panic!("expected a format error, got {:?}", other),
+        }
+        assert_eq!(fs::read_to_string(&joined).unwrap(), "a = 1\ne = f'{a} x'\n");
+    }
+}
@@ -0,0 +1,46 @@
+// C++ runtime headers compiled into the eppx binary, so generated code builds
+// from any directory without the source tree next to it
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const RUNTIME_HEADERS: &[(&str, &str)] = &[
+    ("builtins.hpp", include_str!("../../stdlib/builtins.hpp")),
+    ("eppx_runtime.h", include_str!("../../stdlib/eppx_runtime.h")),
+    ("eppx_generator.hpp", include_str!("../../stdlib/eppx_generator.hpp")),
+    ("eppx_asyncio.hpp", include_str!("../../stdlib/eppx_asyncio.hpp")),
+    ("eppx_match.hpp", include_str!("../../stdlib/eppx_match.hpp")),
+    ("eppx_file.hpp", include_str!("../../stdlib/eppx_file.hpp")),
+];
+
+// Records which headers are in the include directory
+const HASH_FILE: &str = ".headers.md5";
+
+fn headers_hash() -> String {
+    let mut context = md5::Context::new();
+    for (name, contents) in RUNTIME_HEADERS {
+        context.consume(name.as_bytes());
+        context.consume([0]);
+        context.consume(contents.as_bytes());
+        context.consume([0]);
+    }
+    format!("{:x}", context.compute())
+}
+
+// Writes the headers to `<build_dir>/include/` unless the ones there already
+// match, and returns the directory to pass to the compiler with -I
+pub fn install_runtime_headers(build_dir: &Path) -> io::Result<PathBuf> {
+    let include_dir = build_dir.join("include");
+    let hash = headers_hash();
+    let hash_path = include_dir.join(HASH_FILE);
+    if fs::read_to_string(&hash_path).is_ok_and(|installed| installed.trim() == hash) {
+        return Ok(include_dir);
+    }
+    fs::create_dir_all(&include_dir)?;
+    for (name, contents) in RUNTIME_HEADERS {
+        fs::write(include_dir.join(name), contents)?;
+    }
+    fs::write(hash_path, hash)?;
+    Ok(include_dir)
+}
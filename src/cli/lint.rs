@@ -0,0 +1,108 @@
+// `eppx lint`: reports likely mistakes in `.eppx` files, one per line as
+// `path:line:column: rule: message`, and fails if there are any. Without files it
+// checks what `eppx fmt` would format. The rules in a project's `[lint] ignore`
+// are not checked in its files
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::lint::{lint_source, Rule};
+
+use super::fmt::{project_files, source_files};
+use super::project::Project;
+use super::CliError;
+
+pub fn handle_lint(paths: &[PathBuf]) -> Result<String, CliError> {
+    let files = if paths.is_empty() { project_files()? } else { source_files(paths)? };
+    let (mut problems, mut files_with_problems) = (0, 0);
+    for file in &files {
+        let source = fs::read_to_string(file)?;
+        let findings = lint_source(&source, &ignored_rules(file)?).map_err(|e| CliError::Lint(format!("{}: {}", file.display(), e)))?;
+        for finding in &findings {
+            println!("{}:{}:{}: {}: {}", file.display(), finding.line, finding.column, finding.rule, finding.message);
+        }
+        problems += findings.len();
+        files_with_problems += usize::from(!findings.is_empty());
+    }
+    let count = |n: usize, what: &str| if n == 1 { format!("1 {}", what) } else { format!("{} {}s", n, what) };
+    match problems {
+        0 => Ok(format!("No problems in {}", count(files.len(), "file"))),
+        n => Err(CliError::Lint(format!("{} in {} of {}", count(n, "problem"), files_with_problems, count(files.len(), "file")))),
+    }
+}
+
+// The rules the `[lint]` section of the codon.toml around `file` turns off
+fn ignored_rules(file: &Path) -> Result<Vec<Rule>, CliError> {
+    let file = fs::canonicalize(file)?;
+    let Some(project) = Project::around(file.parent().unwrap_or(&file))? else {
+        return Ok(Vec::new());
+    };
+    project
+        .config
+        .lint
+        .ignore
+        .iter()
+        .map(|name| {
+            Rule::from_name(name).ok_or_else(|| {
+                let known: Vec<&str> = Rule::ALL.iter().map(|rule| rule.name()).collect();
+                CliError::Project(format!(
+                    "unknown lint rule `{}` in [lint] ignore of {}/codon.toml (rules: {})",
+                    name,
+                    project.root.display(),
+                    known.join(", ")
+                ))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codon::config::CodonProjectConfig;
+
+    // A project whose codon.toml turns off the rules in `ignore`, and its main file
+    fn project(ignore: &[&str]) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = CodonProjectConfig::default();
+        config.lint.ignore = ignore.iter().map(|rule| rule.to_string()).collect();
+        fs::write(dir.path().join("codon.toml"), toml::to_string_pretty(&config).unwrap()).unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        let file = dir.path().join("src").join("main.eppx");
+        fs::write(&file, "import os\nlist = [1]\n").unwrap();
+        (dir, file)
+    }
+
+    #[test]
+    fn test_project_config_turns_rules_off() {
+        let (_dir, file) = project(&["unused-import"]);
+        assert_eq!(ignored_rules(&file).unwrap(), [Rule::UnusedImport]);
+        match handle_lint(std::slice::from_ref(&file)) {
+            Err(CliError::Lint(message)) => assert_eq!(message, "1 problem in 1 of 1 file"),
+            other => panic!("expected a lint error, got {:?}", other),
+        }
+
+        let (_dir, file) = project(&["unused-import", "shadowed-builtin"]);
+        assert_eq!(handle_lint(&[file]).unwrap(), "No problems in 1 file");
+
+        // Without a [lint] section, or outside any project, every rule is checked
+        let (_dir, file) = project(&[]);
+        assert_eq!(ignored_rules(&file).unwrap(), []);
+        let dir = tempfile::tempdir().unwrap();
+        let loose = dir.path().join("loose.eppx");
+        fs::write(&loose, "x = 1\n").unwrap();
+        assert_eq!(ignored_rules(&loose).unwrap(), []);
+    }
+
+    #[test]
+    fn test_unknown_rules_in_project_config_are_an_error() {
+        let (_dir, file) = project(&["unused-imports"]);
+        match ignored_rules(&file) {
+            Err(CliError::Project(message)) => {
+                assert!(message.starts_with("unknown lint rule `unused-imports` in [lint] ignore of "), "{}", message);
+                assert!(message.ends_with("(rules: unused-variable, unused-import, undefined-name, shadowed-builtin, unreachable-code, bare-except, none-comparison, cpp-keyword)"));
+            }
+            other => panic!("expected a project error, got {:?}", other),
+        }
+    }
+}
@@ -1,27 +1,141 @@
 // CLI module placeholder
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, ExitStatus};
 use thiserror::Error;
 
-use crate::parser::parse_eppx_file;
-use crate::codegen::generate_cpp_code;
+use crate::parser::{parse_eppx_string, preprocess_eppx_string, tokenize_eppx_string};
+use crate::codegen::{generate_program_cpp, CodegenOptions, ProjectModule};
 use crate::codon::{CodonManager, CodonConfig, OptimizationLevel, CodonError};
+use crate::codon::config::BuildConfig;
+use project::Project;
+use toolchain::{CompilerSource, Toolchain};
+
+pub use fmt::handle_fmt;
+pub use lint::handle_lint;
+pub use output::{set_verbosity, verbosity, Verbosity};
+pub use packages::{handle_add, handle_install, handle_remove, handle_update};
+pub use publish::{handle_package, handle_publish};
+pub use registry::handle_registry;
+pub use templates::{handle_init, handle_new_project};
+
+mod archive;
+mod cache;
+mod fmt;
+mod headers;
+mod lint;
+mod output;
+mod packages;
+mod project;
+mod publish;
+mod registry;
+mod templates;
+mod toolchain;
+mod workspace;
+
+// Generated C++, executables, runtime headers and the build cache of native builds
+const BUILD_DIR: &str = ".eppx_build";
+
+// Static library built from runtime/ that every native build links against
+#[cfg(windows)]
+const RUNTIME_LIBRARY: &str = "eppx_runtime.lib";
+#[cfg(not(windows))]
+const RUNTIME_LIBRARY: &str = "libeppx_runtime.a";
+
+// System libraries needed by the Rust standard library inside the runtime
+#[cfg(target_os = "linux")]
+pub(crate) const RUNTIME_SYSTEM_LIBS: &[&str] = &["-lpthread", "-ldl", "-lm"];
+#[cfg(not(target_os = "linux"))]
+pub(crate) const RUNTIME_SYSTEM_LIBS: &[&str] = &[];
 
 #[derive(Parser, Debug)]
 #[clap(name = "eppx", version = "0.1.0", about = "E++ Compiler and Tools")]
 pub struct Cli {
+    /// Also print details such as the C++ compiler command
+    #[clap(short, long, global = true, conflicts_with = "quiet")]
+    pub verbose: bool,
+    /// Only print errors and the requested output
+    #[clap(short, long, global = true)]
+    pub quiet: bool,
     #[clap(subcommand)]
     pub command: Commands,
 }
 
+impl Cli {
+    pub fn verbosity(&self) -> Verbosity {
+        if self.quiet {
+            Verbosity::Quiet
+        } else if self.verbose {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        }
+    }
+}
+
+// Where `eppx build` stops; the stages before obj print their output to stdout
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmitStage {
+    /// Every token with its line and column (NAME, OP, INDENT, NEWLINE, ...)
+    Tokens,
+    /// Source with its layout made explicit, as the grammar sees it
+    Preprocessed,
+    Ast,
+    AstJson,
+    /// Generated C++
+    Cpp,
+    /// Object file in .eppx_build/
+    Obj,
+    /// Executable in .eppx_build/
+    #[default]
+    Exe,
+}
+
+// Which project `build` and `run` without a file mean
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Selection<'a> {
+    /// The project around the current directory
+    #[default]
+    Current,
+    /// A member of the workspace around the current directory, by name
+    Member(&'a str),
+    /// Every member of that workspace
+    Workspace,
+}
+
+// Settings shared by `build` and `run`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BuildOptions {
+    pub release: bool,
+    pub gpu: bool,
+    pub optimize: bool,
+    pub no_cache: bool,
+    pub emit: EmitStage,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
-    New { project_name: String },
+    /// Create a project in a new directory
+    New {
+        project_name: String,
+        /// bin, lib, cli, test-suite, or a template in ~/.eppx/templates/
+        #[clap(long, default_value = templates::DEFAULT_TEMPLATE)]
+        template: String,
+    },
+    /// Make the current directory a project, keeping the files already in it
+    Init {
+        /// Project name; defaults to the directory's name
+        #[clap(long)]
+        name: Option<String>,
+        /// bin, lib, cli, test-suite, or a template in ~/.eppx/templates/
+        #[clap(long, default_value = templates::DEFAULT_TEMPLATE)]
+        template: String,
+    },
     Build {
-        file: PathBuf,
+        /// Source file; without one, builds the project whose codon.toml is here or in a parent directory
+        file: Option<PathBuf>,
         #[clap(short, long)]
         output: Option<String>,
         #[clap(long)]
@@ -30,20 +144,123 @@ pub enum Commands {
         gpu: bool,
         #[clap(long)]
         fast: bool,
+        /// Compile out `assert` statements
+        #[clap(short = 'O', long)]
+        optimize: bool,
+        /// Rebuild even if the build cache has this program
+        #[clap(long)]
+        no_cache: bool,
+        /// Stop after this stage and output its result
+        #[clap(long, value_enum, default_value_t = EmitStage::Exe)]
+        emit: EmitStage,
+        /// Build this member of the workspace
+        #[clap(short = 'p', long = "package", conflicts_with = "file")]
+        member: Option<String>,
+        /// Build every member of the workspace
+        #[clap(long, conflicts_with_all = ["file", "output", "member"])]
+        workspace: bool,
     },
     Run { 
-        file: PathBuf,
+        /// Source file; without one, runs the project whose codon.toml is here or in a parent directory
+        file: Option<PathBuf>,
         #[clap(long)]
         release: bool,
         #[clap(long)]
         interactive: bool,
         #[clap(long)]
         fast: bool,
+        /// Compile out `assert` statements
+        #[clap(short = 'O', long)]
+        optimize: bool,
+        /// Rebuild even if the build cache has this program
+        #[clap(long)]
+        no_cache: bool,
+        /// Run this member of the workspace
+        #[clap(short = 'p', long = "package", conflicts_with = "file")]
+        member: Option<String>,
+        /// Arguments after `--`, passed to the program as sys.argv[1:]
+        #[clap(last = true)]
+        args: Vec<String>,
+    },
+    /// Add a package to [dependencies] in codon.toml and install it
+    Add {
+        /// Package name, optionally with a version requirement: textkit or textkit@^1.2
+        package: String,
+        /// Use the package in this directory instead of one from the registry
+        #[clap(long)]
+        path: Option<PathBuf>,
+        /// Registry directory or configured name; defaults to $EPPX_REGISTRY, then the default registry
+        #[clap(long)]
+        registry: Option<PathBuf>,
     },
-    Install { 
+    /// Remove a package from [dependencies] in codon.toml
+    Remove {
         package: String,
     },
+    /// Install the packages in eppx.lock, resolving [dependencies] again if it is out of date
+    Install {
+        /// Registry directory or configured name; defaults to $EPPX_REGISTRY, then the default registry
+        #[clap(long)]
+        registry: Option<PathBuf>,
+    },
+    /// Resolve [dependencies] again, taking the newest versions that match
+    Update {
+        /// Only update these packages
+        packages: Vec<String>,
+        /// Registry directory or configured name; defaults to $EPPX_REGISTRY, then the default registry
+        #[clap(long)]
+        registry: Option<PathBuf>,
+    },
+    /// Write <output_dir>/package/<name>-<version>.tar.gz from codon.toml and src/
+    Package,
+    /// Package the project and add it to a registry
+    Publish {
+        /// Registry directory or configured name; created if it does not exist
+        #[clap(long)]
+        registry: Option<PathBuf>,
+    },
+    /// Manage the registries in ~/.eppx/global.toml
+    Registry {
+        #[clap(subcommand)]
+        command: RegistryCommand,
+    },
     Test,
+    /// Report the C++ compilers and runtime library native builds would use
+    Doctor,
+    /// Remove .eppx_build: generated C++, executables and the build cache
+    Clean,
+    /// Run the language server, speaking LSP over stdin and stdout
+    Lsp,
+    /// Format .eppx files in place; without files, the project's src/ and tests/
+    Fmt {
+        /// Files or directories to format
+        files: Vec<PathBuf>,
+        /// Change nothing; list the files that aren't formatted and fail if there are any
+        #[clap(long)]
+        check: bool,
+    },
+    /// Check .eppx files for likely mistakes; without files, the project's src/ and tests/
+    Lint {
+        /// Files or directories to check
+        files: Vec<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RegistryCommand {
+    /// Remember a registry directory by name
+    Add {
+        name: String,
+        dir: PathBuf,
+        /// Use this registry when none is given
+        #[clap(long)]
+        default: bool,
+    },
+    Remove {
+        name: String,
+    },
+    /// Show the configured registries
+    List,
 }
 
 #[derive(Error, Debug)]
@@ -66,89 +283,249 @@ pub enum CliError {
     ProjectCreation(String, std::io::Error),
     #[error("Codon error: {0}")]
     Codon(#[from] CodonError),
+    #[error("Toolchain error: {0}")]
+    Toolchain(String),
+    #[error("no file given, and no codon.toml in {0} or any directory above it")]
+    NoProject(PathBuf),
+    #[error("Project error: {0}")]
+    Project(String),
+    #[error("Package error: {0}")]
+    Package(String),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Language server error: {0}")]
+    Lsp(String),
+    #[error("Format error: {0}")]
+    Format(String),
+    #[error("Lint error: {0}")]
+    Lint(String),
+    // The program run by `eppx run` failed; eppx exits with the same status
+    #[error("program exited with status {0}")]
+    ProgramExit(u8),
 }
 
-pub fn handle_new_project(project_name: &str) -> Result<String, CliError> {
-    let project_path = Path::new(project_name);
-    if project_path.exists() {
-        return Err(CliError::ProjectCreation(
-            project_name.to_string(),
-            std::io::Error::new(std::io::ErrorKind::AlreadyExists, "Project directory already exists"),
-        ));
-    }
-    fs::create_dir_all(project_path.join("src"))
-        .map_err(|e| CliError::ProjectCreation(project_name.to_string(), e))?;
-    fs::write(
-        project_path.join("src/main.eppx"),
-        "print(\"Hello from new E++ project!\")\n",
-    )
-    .map_err(|e| CliError::ProjectCreation(project_name.to_string(), e))?;
-    fs::write(
-        project_path.join(".eppx_ignore"),
-        ".eppx_build/\n.eppx_packages/\n",
-    )
-    .map_err(|e| CliError::ProjectCreation(project_name.to_string(), e))?;
-    
-    // Create Codon configuration (hidden under the hood)
-    let config_manager = crate::codon::config::ConfigManager::new()?;
-    config_manager.create_new_project(&project_path.to_path_buf(), project_name, None)?;
-    
-    Ok(format!("Successfully created E++ project '{}'", project_name))
-}
-
-pub fn handle_build(file_path: &Path, output_name: Option<&str>, release: bool, gpu: bool, fast: bool) -> Result<String, CliError> {
+pub fn handle_build(
+    file_path: Option<&Path>,
+    output_name: Option<&str>,
+    fast: bool,
+    selection: Selection,
+    options: &BuildOptions,
+) -> Result<String, CliError> {
+    let Some(file_path) = file_path else {
+        if let Selection::Workspace = selection {
+            return handle_build_workspace(options);
+        }
+        return handle_build_project(&Project::select(selection)?, output_name, options);
+    };
     if !file_path.exists() {
         return Err(CliError::FileNotFound(file_path.to_path_buf()));
     }
 
-    if fast {
+    if fast || options.emit != EmitStage::Exe {
         // Use native implementation
-        return handle_build_native(file_path, output_name, release, gpu);
+        handle_build_native(&NativeTarget::file(file_path, output_name)?, options)
     } else {
         // Use Codon, and show error if Codon fails
-        handle_build_with_codon(file_path, output_name, release, gpu)
+        handle_build_with_codon(file_path, output_name, options.release, options.gpu)
+    }
+}
+
+// `eppx build` with no file: the project's entry point and modules, as its codon.toml says
+fn handle_build_project(project: &Project, output_name: Option<&str>, options: &BuildOptions) -> Result<String, CliError> {
+    if project.is_library() {
+        return handle_check_library(project, options);
+    }
+    let options = project.build_options(options);
+    match project.config.project.build_target.as_str() {
+        "native" => handle_build_native(&NativeTarget::project(project, output_name)?, &options),
+        "codon" => {
+            let output = project.output_dir().join(output_name.unwrap_or(project.name()));
+            handle_build_with_codon(&project.entry_point(), output.to_str(), options.release, options.gpu)
+        }
+        target => Err(CliError::Project(format!("unknown build_target '{}' (expected \"native\" or \"codon\")", target))),
+    }
+}
+
+// A library has no program, so its src/lib.eppx is compiled to an object file to check it
+fn handle_check_library(project: &Project, options: &BuildOptions) -> Result<String, CliError> {
+    let library = project.library_module();
+    let target = NativeTarget {
+        modules: project.importable_modules(&library)?,
+        entry: library,
+        build_dir: project.build_dir(),
+        executable: project.build_dir().join(project.name()),
+        build_config: project.config.build.clone(),
+    };
+    let emit = if options.emit == EmitStage::Exe { EmitStage::Obj } else { options.emit };
+    handle_build_native(&target, &BuildOptions { emit, ..project.build_options(options) })?;
+    Ok(format!("Checked library {}", project.name()))
+}
+
+// `eppx build --workspace`: every member's program (or library), in the manifest's order
+fn handle_build_workspace(options: &BuildOptions) -> Result<String, CliError> {
+    let members = workspace::Workspace::discover()?.members()?;
+    for member in &members {
+        output::status(handle_build_project(member, None, options)?);
+    }
+    Ok(format!("Built {} workspace members", members.len()))
+}
+
+// [build] settings of the project around the current directory, if there is one
+fn enclosing_build_config() -> Result<BuildConfig, CliError> {
+    Ok(Project::enclosing()?.map(|project| project.config.build).unwrap_or_default())
+}
+
+// The modules a single file can import: a file in the project's src/ those
+// beside it, and one in tests/ all but the entry point
+fn file_modules(project: Option<&Project>, file_path: &Path) -> Result<Vec<(String, PathBuf)>, CliError> {
+    match project {
+        Some(project) if same_dir(file_path.parent(), &project.source_dir()) => project.importable_modules(file_path),
+        Some(project) if same_dir(file_path.parent(), &project.tests_dir()) => {
+            project.importable_modules(&project.entry_point())
+        }
+        _ => Ok(Vec::new()),
     }
 }
 
-fn handle_build_native(file_path: &Path, output_name: Option<&str>, release: bool, _gpu: bool) -> Result<String, CliError> {
-    println!("Building {}...", file_path.display());
-    let ast = parse_eppx_file(file_path).map_err(CliError::Parser)?;
-    // DEBUG: Print the AST to verify method bodies
-    println!("{:#?}", ast);
-    let cpp_code = generate_cpp_code(&ast).map_err(CliError::Codegen)?;
-    let build_dir = Path::new(".eppx_build");
+// The modules `file_path` can import, by name, as the project around it has them
+pub fn importable_modules(file_path: &Path) -> Result<Vec<(String, PathBuf)>, CliError> {
+    let dir = file_path.parent().unwrap_or(Path::new("."));
+    file_modules(Project::around(dir)?.as_ref(), file_path)
+}
+
+fn same_dir(dir: Option<&Path>, other: &Path) -> bool {
+    let dir = match dir {
+        Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+        Some(dir) => dir,
+        None => return false,
+    };
+    matches!((fs::canonicalize(dir), fs::canonicalize(other)), (Ok(a), Ok(b)) if a == b)
+}
+
+// What a native build compiles and where the results go
+struct NativeTarget {
+    entry: PathBuf,
+    // Project modules and packages the entry file can import, by name
+    modules: Vec<(String, PathBuf)>,
+    // Generated C++, runtime headers and the build cache
+    build_dir: PathBuf,
+    executable: PathBuf,
+    build_config: BuildConfig,
+}
+
+impl NativeTarget {
+    // A single file, built into .eppx_build/ under the current directory. Inside
+    // a project it uses the project's toolchain settings, a file in its src/ can
+    // import the modules beside it, and one in tests/ all but the entry point
+    fn file(file_path: &Path, output_name: Option<&str>) -> Result<Self, CliError> {
+        let exec_name = output_name.unwrap_or_else(|| {
+            file_path.file_stem().map_or("a.out", |s| s.to_str().unwrap_or("a.out"))
+        });
+        let project = Project::enclosing()?;
+        Ok(Self {
+            entry: file_path.to_path_buf(),
+            modules: file_modules(project.as_ref(), file_path)?,
+            build_dir: PathBuf::from(BUILD_DIR),
+            executable: Path::new(BUILD_DIR).join(exec_name),
+            build_config: project.map(|project| project.config.build).unwrap_or_default(),
+        })
+    }
+
+    // A project, built into <output_dir>/<name>
+    fn project(project: &Project, output_name: Option<&str>) -> Result<Self, CliError> {
+        let entry = project.entry_point();
+        if !entry.exists() {
+            return Err(CliError::FileNotFound(entry));
+        }
+        Ok(Self {
+            modules: project.importable_modules(&entry)?,
+            entry,
+            build_dir: project.build_dir(),
+            executable: project.output_dir().join(output_name.unwrap_or(project.name())),
+            build_config: project.config.build.clone(),
+        })
+    }
+
+    fn exec_name(&self) -> String {
+        self.executable.file_name().map_or("a.out".into(), |name| name.to_string_lossy().into_owned())
+    }
+
+    // The entry file's source, then each module's name and source
+    fn read_sources(&self) -> Result<(String, Vec<(String, String)>), CliError> {
+        let entry = fs::read_to_string(&self.entry)?;
+        let modules = self
+            .modules
+            .iter()
+            .map(|(name, path)| Ok((name.clone(), fs::read_to_string(path)?)))
+            .collect::<Result<Vec<_>, CliError>>()?;
+        Ok((entry, modules))
+    }
+
+    fn generate_cpp(&self, entry: &str, modules: &[(String, String)], options: &CodegenOptions) -> Result<String, CliError> {
+        let ast = parse_eppx_string(entry).map_err(CliError::Parser)?;
+        let modules = modules
+            .iter()
+            .zip(&self.modules)
+            .map(|((name, source), (_, path))| {
+                let ast = parse_eppx_string(source).map_err(|e| CliError::Parser(format!("{}: {}", path.display(), e)))?;
+                Ok(ProjectModule { name: name.clone(), ast })
+            })
+            .collect::<Result<Vec<_>, CliError>>()?;
+        let program = generate_program_cpp(&ast, &modules, options).map_err(CliError::Codegen)?;
+        for warning in &program.warnings {
+            output::warning(warning);
+        }
+        Ok(program.cpp)
+    }
+}
+
+fn handle_build_native(target: &NativeTarget, options: &BuildOptions) -> Result<String, CliError> {
+    let codegen_options = CodegenOptions { strip_asserts: options.optimize };
+    if let Some(artifact) = frontend_artifact(target, options.emit, &codegen_options)? {
+        output::artifact(&artifact)?;
+        return Ok(String::new());
+    }
+    output::status(format!("Building {}...", target.entry.display()));
+    let runtime_library = runtime_library_path()?;
+    let toolchain = Toolchain::new(&target.build_config).map_err(CliError::Toolchain)?;
+    let build_dir = target.build_dir.as_path();
     fs::create_dir_all(build_dir)?;
-    let exec_name = output_name.unwrap_or_else(|| {
-        file_path.file_stem().map_or("a.out", |s| s.to_str().unwrap_or("a.out"))
-    });
-    let exec_path = build_dir.join(exec_name);
-    let cpp_file_path = build_dir.join(format!("{}.cpp", exec_name));
-    fs::write(&cpp_file_path, cpp_code)?;
-    println!("Generated C++ source: {}", cpp_file_path.display());
-    let mut cmd = Command::new("g++");
-    cmd.arg(&cpp_file_path)
-        .arg("-o")
-        .arg(&exec_path)
-        .arg("-std=c++17");
-    if release {
-        cmd.arg("-O3");
+    if let Some(parent) = target.executable.parent() {
+        fs::create_dir_all(parent)?;
     }
-    let compiler_output = cmd.output();
-    let compiler_output = match compiler_output {
-        Ok(output) if output.status.success() => output,
-        _ => {
-            println!("g++ failed or not found, trying clang++...");
-            let mut clang_cmd = Command::new("clang++");
-            clang_cmd.arg(&cpp_file_path)
-                .arg("-o")
-                .arg(&exec_path)
-                .arg("-std=c++17");
-            if release {
-                clang_cmd.arg("-O3");
-            }
-            clang_cmd.output()?
+    let exec_name = target.exec_name();
+    let (entry_source, module_sources) = target.read_sources()?;
+    let cache_key = if options.no_cache || options.emit != EmitStage::Exe {
+        None
+    } else {
+        let build_options = format!("release={} {:?}", options.release, codegen_options);
+        let mut sources = entry_source.clone();
+        for (name, source) in &module_sources {
+            sources.push_str(&format!("\0{}\0{}", name, source));
         }
+        Some(cache::cache_key(&sources, &toolchain.fingerprint(), &build_options, &runtime_library))
     };
+    if let Some(key) = &cache_key {
+        if cache::restore(build_dir, key, &target.executable)? {
+            output::status(format!("Using cached build of {}", target.entry.display()));
+            return Ok(format!("Successfully built: {}", target.executable.display()));
+        }
+    }
+    let cpp_code = target.generate_cpp(&entry_source, &module_sources, &codegen_options)?;
+    let include_dir = headers::install_runtime_headers(build_dir)?;
+    let cpp_file_path = build_dir.join(format!("{}.cpp", exec_name));
+    fs::write(&cpp_file_path, cpp_code)?;
+    output::detail(format!("Generated C++ source: {}", cpp_file_path.display()));
+    let (mut command, output_path) = if options.emit == EmitStage::Obj {
+        let object_path = build_dir.join(format!("{}.o", exec_name));
+        (toolchain.object_command(&cpp_file_path, &object_path, options.release, &include_dir), object_path)
+    } else {
+        (toolchain.command(&cpp_file_path, &target.executable, options.release, &include_dir, &runtime_library), target.executable.clone())
+    };
+    output::detail(format!("Compiler command: {:?}", command));
+    let compiler_output = command
+        .output()
+        .map_err(|e| CliError::Toolchain(format!("failed to run {}: {}", toolchain.compiler, e)))?;
     if !compiler_output.status.success() {
         let stderr = String::from_utf8_lossy(&compiler_output.stderr);
         return Err(CliError::Compilation(format!(
@@ -156,12 +533,55 @@ fn handle_build_native(file_path: &Path, output_name: Option<&str>, release: boo
             stderr
         )));
     }
+    if let Some(key) = &cache_key {
+        cache::store(build_dir, key, &output_path)?;
+    }
     Ok(format!(
         "Successfully built: {}",
-        exec_path.display()
+        output_path.display()
     ))
 }
 
+// Text output of the stages before the C++ compiler runs; None for obj and exe.
+// Only the generated C++ covers a project's modules; the other stages show the entry file
+fn frontend_artifact(target: &NativeTarget, emit: EmitStage, options: &CodegenOptions) -> Result<Option<String>, CliError> {
+    let parse = |source: &str| parse_eppx_string(source).map_err(CliError::Parser);
+    let (source, modules) = target.read_sources()?;
+    let artifact = match emit {
+        EmitStage::Tokens => tokenize_eppx_string(&source)
+            .map_err(CliError::Parser)?
+            .iter()
+            .map(|token| match token.text(&source) {
+                "" => format!("{}:{} {}\n", token.line, token.column, token.kind.name()),
+                text => format!("{}:{} {} {:?}\n", token.line, token.column, token.kind.name(), text),
+            })
+            .collect(),
+        EmitStage::Preprocessed => preprocess_eppx_string(&source).map_err(CliError::Parser)?,
+        EmitStage::Ast => format!("{:#?}", parse(&source)?),
+        EmitStage::AstJson => serde_json::to_string_pretty(&parse(&source)?)?,
+        EmitStage::Cpp => target.generate_cpp(&source, &modules, options)?,
+        EmitStage::Obj | EmitStage::Exe => return Ok(None),
+    };
+    Ok(Some(artifact))
+}
+
+// The runtime library ships next to the eppx executable (cargo builds both into
+// target/<profile>/); EPPX_RUNTIME_LIB points at it explicitly
+fn runtime_library_path() -> Result<PathBuf, CliError> {
+    if let Some(path) = std::env::var_os("EPPX_RUNTIME_LIB") {
+        return Ok(PathBuf::from(path));
+    }
+    let exe = std::env::current_exe()?;
+    exe.parent()
+        .map(|dir| dir.join(RUNTIME_LIBRARY))
+        .filter(|path| path.exists())
+        .ok_or_else(|| CliError::Compilation(format!(
+            "E++ runtime library {} not found next to {}; build it with `cargo build -p eppx-runtime` or set EPPX_RUNTIME_LIB",
+            RUNTIME_LIBRARY,
+            exe.display()
+        )))
+}
+
 fn handle_build_with_codon(file_path: &Path, output_name: Option<&str>, release: bool, gpu: bool) -> Result<String, CliError> {
     // Create Codon configuration
     let mut config = CodonConfig::default();
@@ -186,38 +606,80 @@ fn handle_build_with_codon(file_path: &Path, output_name: Option<&str>, release:
     Ok(format!("Successfully built: {}", output_file.display()))
 }
 
-pub fn handle_run(file_path: &Path, release: bool, interactive: bool, fast: bool) -> Result<String, CliError> {
+pub fn handle_run(
+    file_path: Option<&Path>,
+    args: &[String],
+    interactive: bool,
+    fast: bool,
+    selection: Selection,
+    options: &BuildOptions,
+) -> Result<String, CliError> {
+    let Some(file_path) = file_path else {
+        return handle_run_project(&Project::select(selection)?, args, interactive, options);
+    };
     if !file_path.exists() {
         return Err(CliError::FileNotFound(file_path.to_path_buf()));
     }
 
     if fast {
         // Use native implementation
-        return handle_run_native(file_path, release, interactive);
+        handle_run_native(&NativeTarget::file(file_path, None)?, args, interactive, options)
+    } else if !args.is_empty() {
+        Err(CliError::NotImplemented("program arguments with the Codon backend; use --fast".to_string()))
     } else {
         // Use Codon, and show error if Codon fails
-        handle_run_with_codon(file_path, release, interactive)
+        handle_run_with_codon(file_path, options.release, interactive)
     }
 }
 
-fn handle_run_native(file_path: &Path, release: bool, _interactive: bool) -> Result<String, CliError> {
-    println!("Running {}...", file_path.display());
-    let build_dir = Path::new(".eppx_build");
-    let exec_name = file_path.file_stem().map_or("a.out", |s| s.to_str().unwrap_or("a.out"));
-    let exec_path = build_dir.join(exec_name);
-    handle_build_native(file_path, Some(exec_name), release, false)?;
-    println!("Executing {}...", exec_path.display());
-    let run_output = Command::new(&exec_path).output()?;
-    if !run_output.status.success() {
-        let stderr = String::from_utf8_lossy(&run_output.stderr);
-        return Err(CliError::Execution(format!(
-            "Execution failed with code {:?}:\n{}",
-            run_output.status.code(),
-            stderr
+// `eppx run` with no file: builds the project, then runs its executable
+fn handle_run_project(project: &Project, args: &[String], interactive: bool, options: &BuildOptions) -> Result<String, CliError> {
+    if project.is_library() {
+        return Err(CliError::Project(format!(
+            "{} is a library, so there is no program to run; run its tests with `eppx run --fast tests/<file>.eppx`",
+            project.name()
         )));
     }
-    let stdout = String::from_utf8_lossy(&run_output.stdout);
-    Ok(format!("Output:\n{}", stdout.trim_end()))
+    let options = project.build_options(options);
+    match project.config.project.build_target.as_str() {
+        "native" => handle_run_native(&NativeTarget::project(project, None)?, args, interactive, &options),
+        "codon" if !args.is_empty() => Err(CliError::NotImplemented("program arguments with the Codon backend".to_string())),
+        "codon" => handle_run_with_codon(&project.entry_point(), options.release, interactive),
+        target => Err(CliError::Project(format!("unknown build_target '{}' (expected \"native\" or \"codon\")", target))),
+    }
+}
+
+fn handle_run_native(target: &NativeTarget, args: &[String], _interactive: bool, options: &BuildOptions) -> Result<String, CliError> {
+    output::status(format!("Running {}...", target.entry.display()));
+    handle_build_native(target, options)?;
+    let exec_path = &target.executable;
+    output::status(format!("Executing {}...", exec_path.display()));
+    let mut command = Command::new(exec_path);
+    command.args(args);
+    // sys.argv[0] is the script, as in Python
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::arg0(&mut command, &target.entry);
+    // The program shares eppx's stdin, stdout and stderr
+    let status = command
+        .status()
+        .map_err(|e| CliError::Execution(format!("failed to start {}: {}", exec_path.display(), e)))?;
+    match exit_code(status) {
+        0 => Ok(String::new()),
+        code => Err(CliError::ProgramExit(code)),
+    }
+}
+
+// Exit status to pass on for a finished program; death by a signal is 128 + its
+// number, as shells report it
+fn exit_code(status: ExitStatus) -> u8 {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return u8::try_from(128 + signal).unwrap_or(u8::MAX);
+    }
+    match status.code() {
+        Some(code) => u8::try_from(code).ok().filter(|&code| code != 0 || status.success()).unwrap_or(1),
+        None => 1,
+    }
 }
 
 fn handle_run_with_codon(file_path: &Path, release: bool, _interactive: bool) -> Result<String, CliError> {
@@ -235,51 +697,56 @@ fn handle_run_with_codon(file_path: &Path, release: bool, _interactive: bool) ->
     Ok("Execution completed successfully".to_string())
 }
 
-pub fn handle_install(package_name: &str) -> Result<String, CliError> {
-    // Try Codon first (hidden under the hood)
-    if let Ok(result) = handle_install_with_codon(package_name) {
-        return Ok(result);
+pub fn handle_doctor() -> Result<String, CliError> {
+    println!("C++ compilers:");
+    let mut compilers: Vec<String> = toolchain::CANDIDATE_COMPILERS.iter().map(|name| name.to_string()).collect();
+    let selected = Toolchain::new(&enclosing_build_config()?);
+    if let Ok(toolchain) = &selected {
+        if !compilers.contains(&toolchain.compiler) {
+            compilers.push(toolchain.compiler.clone());
+        }
     }
-
-    // Fall back to uv
-    println!(
-        "Attempting to install '{}' using uv...",
-        package_name
-    );
-    let uv_output = Command::new("uv")
-        .arg("pip")
-        .arg("install")
-        .arg(package_name)
-        .output();
-    match uv_output {
-        Ok(output) => {
-            if output.status.success() {
-                Ok(format!(
-                    "Package '{}' installation command sent to uv.\nStdout: {}\nStderr: {}",
-                    package_name,
-                    String::from_utf8_lossy(&output.stdout),
-                    String::from_utf8_lossy(&output.stderr)
-                ))
-            } else {
-                Err(CliError::NotImplemented(format!(
-                    "uv command failed for package '{}'. Is uv installed and in PATH?\nStderr: {}",
-                    package_name,
-                    String::from_utf8_lossy(&output.stderr)
-                )))
-            }
+    for compiler in &compilers {
+        match toolchain::probe_compiler(compiler) {
+            Some((path, version)) => println!("  {:<10} {} ({})", compiler, path.display(), version),
+            None => println!("  {:<10} not found", compiler),
         }
-        Err(e) => Err(CliError::NotImplemented(format!(
-            "Failed to run uv for package '{}'. Is uv installed and in PATH? Error: {}",
-            package_name, e
-        ))),
     }
+    let toolchain = selected.map_err(CliError::Toolchain)?;
+    let source = match toolchain.source {
+        CompilerSource::Environment => "CXX",
+        CompilerSource::ProjectConfig => "[build] compiler in codon.toml",
+        CompilerSource::Detected => "first found on PATH",
+    };
+    println!("Selected compiler: {} (from {})", toolchain.compiler, source);
+    if toolchain::probe_compiler(&toolchain.compiler).is_none() {
+        return Err(CliError::Toolchain(format!("selected compiler '{}' was not found", toolchain.compiler)));
+    }
+    let runtime_library = runtime_library_path()?;
+    println!("Runtime library: {}", runtime_library.display());
+    Ok("Native builds are ready".to_string())
 }
 
-fn handle_install_with_codon(package_name: &str) -> Result<String, CliError> {
-    let mut codon_manager = CodonManager::new();
-    codon_manager.initialize()?;
-    codon_manager.install_package(package_name)?;
-    Ok(format!("Successfully installed '{}'", package_name))
+// Removes the build directory of the enclosing project or workspace, else the current directory's
+pub fn handle_clean() -> Result<String, CliError> {
+    let build_dir = match Project::enclosing()? {
+        Some(project) => project.build_dir(),
+        None => match workspace::Workspace::around(&std::env::current_dir()?)? {
+            Some(workspace) => workspace.root.join(BUILD_DIR),
+            None => PathBuf::from(BUILD_DIR),
+        },
+    };
+    if !build_dir.exists() {
+        return Ok("Nothing to clean".to_string());
+    }
+    fs::remove_dir_all(&build_dir)?;
+    Ok(format!("Removed {}", build_dir.display()))
+}
+
+// `eppx lsp`: serves an editor until it shuts the server down
+pub fn handle_lsp() -> Result<String, CliError> {
+    crate::lsp::run().map_err(CliError::Lsp)?;
+    Ok(String::new())
 }
 
 pub fn handle_test() -> Result<String, CliError> {
@@ -0,0 +1,67 @@
+// How much eppx says about what it is doing (-q / -v). Progress messages go to
+// stderr, so stdout carries only what was asked for: an artifact from
+// `build --emit` or the output of the program being run
+
+use std::fmt::Display;
+use std::io::{self, Write};
+
+use colored::Colorize;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+// Progress messages, hidden by -q
+pub fn status(message: impl Display) {
+    if verbosity() >= Verbosity::Normal {
+        eprintln!("{}", message);
+    }
+}
+
+// Warnings about the program being compiled, hidden by -q
+pub fn warning(message: impl Display) {
+    if verbosity() >= Verbosity::Normal {
+        eprintln!("{} {}", "Warning:".yellow().bold(), message);
+    }
+}
+
+// Details such as the compiler command line, shown with -v
+pub fn detail(message: impl Display) {
+    if verbosity() >= Verbosity::Verbose {
+        eprintln!("{}", message);
+    }
+}
+
+// Writes requested output to stdout; a reader that stops early (`| head`) is not an error
+pub fn artifact(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    let result = stdout.write_all(text.as_bytes()).and_then(|_| {
+        if text.ends_with('\n') {
+            Ok(())
+        } else {
+            stdout.write_all(b"\n")
+        }
+    });
+    match result.and_then(|_| stdout.flush()) {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
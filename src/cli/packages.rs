@@ -0,0 +1,927 @@
+// E++ packages: the [dependencies] of codon.toml are resolved into eppx.lock,
+// registry packages are installed into .eppx_packages/, and each package's
+// src/lib.eppx becomes a module named after it. Path dependencies are used in place.
+// The members of a workspace resolve together, into the workspace's lock
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+
+use crate::codon::config::{read_project_config, ConfigManager, Dependency, DependencyDetail};
+
+use super::output;
+use super::project::{is_module_name, Project};
+use super::registry::{IndexEntry, Registry, LIBRARY_MODULE};
+use super::workspace::Workspace;
+use super::CliError;
+
+pub const LOCK_FILE: &str = "eppx.lock";
+pub const PACKAGES_DIR: &str = ".eppx_packages";
+
+const LOCK_VERSION: u32 = 1;
+const LOCK_HEADER: &str = "# Generated by eppx from the [dependencies] in codon.toml; do not edit by hand\n\n";
+
+// Written into each installed package: its version and checksum
+const INSTALLED_FILE: &str = ".eppx-installed";
+
+// Resolution stops if the chosen versions keep changing after this many rounds
+const MAX_RESOLVE_ROUNDS: usize = 100;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Lockfile {
+    version: u32,
+    #[serde(default, rename = "package")]
+    packages: Vec<LockedPackage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct LockedPackage {
+    name: String,
+    version: String,
+    /// "registry+" and the name of the configured registry the package came from
+    /// ("registry" alone for a registry given as a directory), or "path+" and the
+    /// package's directory relative to the project (or workspace) root
+    source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checksum: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    dependencies: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    // The configured registry of that name, or else the one `--registry` gives
+    Registry(Option<String>),
+    // Relative to the root of the scope, unless codon.toml gave an absolute path
+    Path(PathBuf),
+}
+
+impl Source {
+    fn parse(source: &str) -> Result<Self, CliError> {
+        if let Some(dir) = source.strip_prefix("path+") {
+            return Ok(Source::Path(PathBuf::from(dir)));
+        }
+        match source.strip_prefix("registry") {
+            Some("") => Ok(Source::Registry(None)),
+            Some(name) if name.len() > 1 && name.starts_with('+') => Ok(Source::Registry(Some(name[1..].to_string()))),
+            _ => Err(CliError::Package(format!("unknown package source '{}' in {}", source, LOCK_FILE))),
+        }
+    }
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Registry(None) => write!(f, "registry"),
+            Source::Registry(Some(name)) => write!(f, "registry+{}", name),
+            Source::Path(dir) => write!(f, "path+{}", dir.to_string_lossy().replace('\\', "/")),
+        }
+    }
+}
+
+impl LockedPackage {
+    fn source(&self) -> Result<Source, CliError> {
+        Source::parse(&self.source)
+    }
+
+    fn module_path(&self, scope: &Scope) -> Result<PathBuf, CliError> {
+        Ok(match self.source()? {
+            Source::Registry(_) => scope.packages_dir().join(&self.name).join(LIBRARY_MODULE),
+            Source::Path(dir) => scope.root.join(dir).join(LIBRARY_MODULE),
+        })
+    }
+}
+
+// The projects that share an eppx.lock and .eppx_packages/: a workspace's
+// members, or a project on its own
+struct Scope {
+    root: PathBuf,
+    manifests: Vec<Manifest>,
+}
+
+struct Manifest {
+    name: String,
+    // Relative to the scope's root
+    dir: PathBuf,
+    dependencies: BTreeMap<String, Dependency>,
+}
+
+impl Scope {
+    fn of_project(project: &Project) -> Result<Self, CliError> {
+        match &project.workspace {
+            Some(workspace) => Self::of_workspace(workspace),
+            None => Ok(Self {
+                root: project.root.clone(),
+                manifests: vec![Manifest {
+                    name: project.name().to_string(),
+                    dir: PathBuf::new(),
+                    dependencies: project.config.dependencies.clone(),
+                }],
+            }),
+        }
+    }
+
+    fn of_workspace(workspace: &Workspace) -> Result<Self, CliError> {
+        let mut manifests = Vec::new();
+        for member in workspace.members()? {
+            manifests.push(Manifest {
+                name: member.name().to_string(),
+                dir: relative_path(&member.root, &workspace.root)?,
+                dependencies: member.config.dependencies,
+            });
+        }
+        Ok(Self { root: workspace.root.clone(), manifests })
+    }
+
+    // The scope around the current directory: the enclosing project's, or else the workspace's
+    fn current() -> Result<Self, CliError> {
+        match Project::enclosing()? {
+            Some(project) => Self::of_project(&project),
+            None => match Workspace::around(&env::current_dir()?)? {
+                Some(workspace) => Self::of_workspace(&workspace),
+                None => Err(CliError::NoProject(env::current_dir()?)),
+            },
+        }
+    }
+
+    fn manifest(&mut self, project: &Project) -> &mut Manifest {
+        self.manifests
+            .iter_mut()
+            .find(|manifest| manifest.name == project.name())
+            .expect("a project is in its own scope")
+    }
+
+    // The requirements of every member, each relative to its own directory
+    fn roots(&self) -> Result<Vec<Requirement>, CliError> {
+        let mut roots = Vec::new();
+        for manifest in &self.manifests {
+            roots.extend(requirements(&manifest.dependencies, &manifest.dir, &manifest.name)?);
+        }
+        Ok(roots)
+    }
+
+    fn lock_path(&self) -> PathBuf {
+        self.root.join(LOCK_FILE)
+    }
+
+    fn packages_dir(&self) -> PathBuf {
+        self.root.join(PACKAGES_DIR)
+    }
+}
+
+// One package's requirement on another
+#[derive(Debug, Clone)]
+struct Requirement {
+    name: String,
+    required_by: String,
+    version: Option<VersionReq>,
+    path: Option<PathBuf>,
+    // A configured registry the package must come from
+    registry: Option<String>,
+}
+
+impl std::fmt::Display for Requirement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.path, &self.version) {
+            (Some(path), Some(version)) => write!(f, "{} from {}", version, path.display())?,
+            (Some(path), None) => write!(f, "{}", path.display())?,
+            (None, Some(version)) => write!(f, "{}", version)?,
+            (None, None) => write!(f, "*")?,
+        }
+        if let Some(registry) = &self.registry {
+            write!(f, " from registry {}", registry)?;
+        }
+        write!(f, " (required by {})", self.required_by)
+    }
+}
+
+// The requirements in a `[dependencies]` table; `base` is the directory of its
+// codon.toml relative to the scope's root
+fn requirements(dependencies: &BTreeMap<String, Dependency>, base: &Path, required_by: &str) -> Result<Vec<Requirement>, CliError> {
+    dependencies
+        .iter()
+        .map(|(name, dependency)| {
+            let version = dependency
+                .version()
+                .map(|version| {
+                    VersionReq::parse(version).map_err(|e| {
+                        CliError::Package(format!("invalid version requirement '{}' for {} in {}: {}", version, name, required_by, e))
+                    })
+                })
+                .transpose()?;
+            let path = dependency.path().map(|path| normalize(&base.join(path)));
+            let version = match (&path, version) {
+                (None, None) => Some(VersionReq::STAR),
+                (_, version) => version,
+            };
+            Ok(Requirement {
+                name: name.clone(),
+                required_by: required_by.to_string(),
+                version,
+                path,
+                registry: dependency.registry().map(str::to_string),
+            })
+        })
+        .collect()
+}
+
+// Removes `.` and folds `..` into the component before it, without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+// Version and requirements of the package in `dir` (relative to the scope's root),
+// which must be the package `name`
+fn read_path_package(root: &Path, dir: &Path, name: &str) -> Result<(Version, Vec<Requirement>), CliError> {
+    let package_dir = root.join(dir);
+    let config = read_project_config(&package_dir)?
+        .ok_or_else(|| CliError::Package(format!("{} has no codon.toml, so it is not an E++ package", package_dir.display())))?;
+    if config.project.name != name {
+        return Err(CliError::Package(format!(
+            "{} holds the package '{}', not '{}'",
+            package_dir.display(),
+            config.project.name,
+            name
+        )));
+    }
+    let version = Version::parse(&config.project.version)
+        .map_err(|e| CliError::Package(format!("{}: invalid version '{}': {}", package_dir.display(), config.project.version, e)))?;
+    if !package_dir.join(LIBRARY_MODULE).is_file() {
+        return Err(CliError::Package(format!("package {} has no {}", name, LIBRARY_MODULE)));
+    }
+    Ok((version, requirements(&config.dependencies, dir, name)?))
+}
+
+// A version chosen for a package during resolution
+struct Candidate {
+    version: Version,
+    source: Source,
+    requirements: Vec<Requirement>,
+}
+
+// Registries by the name a package asked for (None for the one `--registry` or
+// the configuration picks), each opened the first time a package needs it
+type Registries = BTreeMap<Option<String>, Registry>;
+
+fn open_registry<'r>(registries: &'r mut Registries, name: Option<&str>, flag: Option<&Path>) -> Result<&'r Registry, CliError> {
+    let key = name.map(str::to_string);
+    if !registries.contains_key(&key) {
+        let registry = match name {
+            Some(name) => Registry::named(name)?,
+            None => Registry::locate(flag)?,
+        };
+        registries.insert(key.clone(), registry);
+    }
+    Ok(&registries[&key])
+}
+
+struct Resolver<'a> {
+    root: &'a Path,
+    registry_flag: Option<&'a Path>,
+    registries: Registries,
+    // Versions in eppx.lock, kept when they still satisfy every requirement
+    preferred: BTreeMap<String, Version>,
+}
+
+impl Resolver<'_> {
+    fn registry(&mut self, name: Option<&str>) -> Result<&Registry, CliError> {
+        open_registry(&mut self.registries, name, self.registry_flag)
+    }
+
+    // Chooses a version of every package reachable from `roots`. Each round
+    // collects the requirements of the previous round's choices and chooses again,
+    // until nothing changes
+    fn resolve(&mut self, roots: &[Requirement]) -> Result<BTreeMap<String, Candidate>, CliError> {
+        let mut chosen: BTreeMap<String, Candidate> = BTreeMap::new();
+        for _ in 0..MAX_RESOLVE_ROUNDS {
+            let mut required: BTreeMap<String, Vec<Requirement>> = BTreeMap::new();
+            let mut queue: VecDeque<Requirement> = roots.iter().cloned().collect();
+            let mut expanded = BTreeSet::new();
+            while let Some(requirement) = queue.pop_front() {
+                if let Some(candidate) = chosen.get(&requirement.name) {
+                    if expanded.insert(requirement.name.clone()) {
+                        queue.extend(candidate.requirements.iter().cloned());
+                    }
+                }
+                required.entry(requirement.name.clone()).or_default().push(requirement);
+            }
+            let mut next = BTreeMap::new();
+            for (name, requirements) in &required {
+                next.insert(name.clone(), self.select(name, requirements, chosen.get(name))?);
+            }
+            let settled = next.len() == chosen.len()
+                && next.iter().all(|(name, candidate)| {
+                    chosen.get(name).is_some_and(|old| old.version == candidate.version && old.source == candidate.source)
+                });
+            chosen = next;
+            if settled {
+                return Ok(chosen);
+            }
+        }
+        Err(CliError::Package("dependency resolution did not settle on a set of versions".to_string()))
+    }
+
+    fn select(&mut self, name: &str, required: &[Requirement], current: Option<&Candidate>) -> Result<Candidate, CliError> {
+        let listed = || required.iter().map(Requirement::to_string).collect::<Vec<_>>().join(", ");
+        let paths: BTreeSet<&PathBuf> = required.iter().filter_map(|requirement| requirement.path.as_ref()).collect();
+        let registries: BTreeSet<&str> = required.iter().filter_map(|requirement| requirement.registry.as_deref()).collect();
+        if paths.len() > 1
+            || registries.len() > 1
+            || (paths.len() == 1 && required.iter().any(|requirement| requirement.path.is_none()))
+        {
+            return Err(CliError::Package(format!("conflicting sources for {}: {}", name, listed())));
+        }
+        if let Some(dir) = paths.into_iter().next() {
+            let (version, dependencies) = read_path_package(self.root, dir, name)?;
+            if required.iter().any(|requirement| requirement.version.as_ref().is_some_and(|req| !req.matches(&version))) {
+                return Err(CliError::Package(format!("{} {} does not match {}", name, version, listed())));
+            }
+            return Ok(Candidate { version, source: Source::Path(dir.clone()), requirements: dependencies });
+        }
+
+        let preferred = current.map(|candidate| candidate.version.clone()).or_else(|| self.preferred.get(name).cloned());
+        let registry = self.registry(registries.into_iter().next())?;
+        let versions: Vec<(Version, &_)> = registry
+            .versions(name)
+            .filter_map(|entry| Version::parse(&entry.version).ok().map(|version| (version, entry)))
+            .collect();
+        if versions.is_empty() {
+            return Err(CliError::Package(format!("package {} is not in the registry {}", name, registry.root.display())));
+        }
+        let matching: Vec<&(Version, &_)> = versions
+            .iter()
+            .filter(|(version, _)| required.iter().all(|requirement| requirement.version.as_ref().is_none_or(|req| req.matches(version))))
+            .collect();
+        let (version, entry) = matching
+            .iter()
+            .find(|(version, _)| Some(version) == preferred.as_ref())
+            .or_else(|| matching.iter().max_by(|a, b| a.0.cmp(&b.0)))
+            .ok_or_else(|| {
+                let available = versions.iter().map(|(version, _)| version.to_string()).collect::<Vec<_>>().join(", ");
+                CliError::Package(format!("no version of {} matches {}; the registry has {}", name, listed(), available))
+            })?;
+        let dependencies = entry
+            .dependencies
+            .iter()
+            .map(|(dependency, version)| (dependency.clone(), Dependency::Version(version.clone())))
+            .collect();
+        // A package's dependencies come from the registry it was published to
+        let mut dependencies = requirements(&dependencies, Path::new(""), name)?;
+        for dependency in &mut dependencies {
+            dependency.registry = registry.name.clone();
+        }
+        Ok(Candidate { version: version.clone(), source: Source::Registry(registry.name.clone()), requirements: dependencies })
+    }
+}
+
+// The part of eppx.lock the project still needs, if the lock satisfies every
+// requirement; None when the dependencies have to be resolved again
+fn locked_closure(root: &Path, roots: &[Requirement], lock: &Lockfile) -> Result<Option<Vec<LockedPackage>>, CliError> {
+    let locked: BTreeMap<&str, &LockedPackage> = lock.packages.iter().map(|package| (package.name.as_str(), package)).collect();
+    let mut needed = BTreeSet::new();
+    let mut queue: VecDeque<Requirement> = roots.iter().cloned().collect();
+    while let Some(requirement) = queue.pop_front() {
+        let Some(package) = locked.get(requirement.name.as_str()) else {
+            return Ok(None);
+        };
+        let source = package.source()?;
+        let Ok(version) = Version::parse(&package.version) else {
+            return Ok(None);
+        };
+        let same_source = match (&requirement.path, &source) {
+            (Some(path), Source::Path(dir)) => path == dir,
+            (None, Source::Registry(name)) => requirement.registry.is_none() || requirement.registry == *name,
+            _ => false,
+        };
+        if !same_source || requirement.version.as_ref().is_some_and(|req| !req.matches(&version)) {
+            return Ok(None);
+        }
+        if !needed.insert(package.name.clone()) {
+            continue;
+        }
+        match source {
+            // A path package's manifest may have changed since the lock was written
+            Source::Path(dir) => {
+                let (current, dependencies) = read_path_package(root, &dir, &package.name)?;
+                let names: Vec<&String> = dependencies.iter().map(|dependency| &dependency.name).collect();
+                if current != version || names != package.dependencies.iter().collect::<Vec<_>>() {
+                    return Ok(None);
+                }
+                queue.extend(dependencies);
+            }
+            // Published versions do not change, so their locked dependencies still hold
+            Source::Registry(registry) => queue.extend(package.dependencies.iter().map(|name| Requirement {
+                name: name.clone(),
+                required_by: package.name.clone(),
+                version: None,
+                path: None,
+                registry: registry.clone(),
+            })),
+        }
+    }
+    Ok(Some(lock.packages.iter().filter(|package| needed.contains(&package.name)).cloned().collect()))
+}
+
+fn read_lock(scope: &Scope) -> Result<Option<Lockfile>, CliError> {
+    let path = scope.lock_path();
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let lock: Lockfile = toml::from_str(&content).map_err(|e| CliError::Package(format!("{}: {}", path.display(), e)))?;
+    if lock.version > LOCK_VERSION {
+        return Err(CliError::Package(format!("{} was written by a newer eppx (lock format {})", path.display(), lock.version)));
+    }
+    Ok(Some(lock))
+}
+
+fn write_lock(scope: &Scope, packages: &[LockedPackage]) -> Result<(), CliError> {
+    let lock = Lockfile { version: LOCK_VERSION, packages: packages.to_vec() };
+    let content = toml::to_string_pretty(&lock).map_err(|e| CliError::Package(e.to_string()))?;
+    fs::write(scope.lock_path(), format!("{}{}", LOCK_HEADER, content))?;
+    Ok(())
+}
+
+// Which locked versions `eppx update` may replace
+enum Resolve<'a> {
+    // Keep eppx.lock if it still satisfies codon.toml
+    IfNeeded,
+    // Resolve again, taking the newest versions of these packages (of all, if empty)
+    Update(&'a [String]),
+}
+
+// Makes eppx.lock satisfy the dependencies of every project in the scope, then
+// installs what it lists
+fn sync(scope: &Scope, registry: Option<&Path>, mode: Resolve) -> Result<Vec<LockedPackage>, CliError> {
+    let roots = scope.roots()?;
+    let lock = read_lock(scope)?;
+    let old_packages = lock.as_ref().map(|lock| lock.packages.clone()).unwrap_or_default();
+    let current = match (&mode, &lock) {
+        (Resolve::IfNeeded, Some(lock)) => locked_closure(&scope.root, &roots, lock)?,
+        _ => None,
+    };
+    let packages = match current {
+        Some(packages) => packages,
+        None => {
+            let mut preferred: BTreeMap<String, Version> = old_packages
+                .iter()
+                .filter_map(|package| Version::parse(&package.version).ok().map(|version| (package.name.clone(), version)))
+                .collect();
+            match mode {
+                Resolve::Update([]) => preferred.clear(),
+                Resolve::Update(names) => {
+                    for name in names {
+                        if preferred.remove(name).is_none() {
+                            return Err(CliError::Package(format!("package {} is not in {}", name, LOCK_FILE)));
+                        }
+                    }
+                }
+                Resolve::IfNeeded => {}
+            }
+            output::status("Resolving dependencies...");
+            let mut resolver = Resolver { root: &scope.root, registry_flag: registry, registries: Registries::new(), preferred };
+            let chosen = resolver.resolve(&roots)?;
+            let mut packages = Vec::new();
+            for (name, candidate) in chosen {
+                let checksum = match &candidate.source {
+                    Source::Registry(registry) => {
+                        let registry = resolver.registry(registry.as_deref())?;
+                        let entry = registry.find(&name, &candidate.version).expect("resolved versions are in the registry");
+                        Some(registry.checksum(entry)?)
+                    }
+                    Source::Path(_) => None,
+                };
+                packages.push(LockedPackage {
+                    name,
+                    version: candidate.version.to_string(),
+                    source: candidate.source.to_string(),
+                    checksum,
+                    dependencies: candidate.requirements.iter().map(|requirement| requirement.name.clone()).collect(),
+                });
+            }
+            report_changes(&old_packages, &packages);
+            packages
+        }
+    };
+    if lock.is_none() || old_packages != packages {
+        write_lock(scope, &packages)?;
+    }
+    install(scope, &packages, registry)?;
+    Ok(packages)
+}
+
+fn report_changes(old: &[LockedPackage], new: &[LockedPackage]) {
+    for package in new {
+        match old.iter().find(|old| old.name == package.name) {
+            None => output::status(format!("Adding {} {}", package.name, package.version)),
+            Some(old) if old.version != package.version || old.source != package.source => {
+                output::status(format!("Updating {} {} -> {}", package.name, old.version, package.version))
+            }
+            Some(_) => {}
+        }
+    }
+    for package in old.iter().filter(|old| !new.iter().any(|new| new.name == old.name)) {
+        output::status(format!("Removing {} {}", package.name, package.version));
+    }
+}
+
+// Copies every locked registry package that is missing or out of date into
+// .eppx_packages/, and removes the ones the lock no longer lists
+fn install(scope: &Scope, packages: &[LockedPackage], registry_flag: Option<&Path>) -> Result<(), CliError> {
+    let packages_dir = scope.packages_dir();
+    let mut registries = Registries::new();
+    let mut installed = BTreeSet::new();
+    for package in packages {
+        let Source::Registry(name) = package.source()? else {
+            continue;
+        };
+        installed.insert(package.name.as_str());
+        let target = packages_dir.join(&package.name);
+        let stamp = format!("{} {}", package.version, package.checksum.as_deref().unwrap_or_default());
+        if fs::read_to_string(target.join(INSTALLED_FILE)).is_ok_and(|installed| installed.trim() == stamp) {
+            continue;
+        }
+        let registry = open_registry(&mut registries, name.as_deref(), registry_flag)?;
+        let version = Version::parse(&package.version)
+            .map_err(|e| CliError::Package(format!("{}: invalid version of {}: {}", LOCK_FILE, package.name, e)))?;
+        let entry = registry.find(&package.name, &version).ok_or_else(|| {
+            CliError::Package(format!(
+                "{} {} from {} is not in the registry {}; run `eppx update {}`",
+                package.name,
+                package.version,
+                LOCK_FILE,
+                registry.root.display(),
+                package.name
+            ))
+        })?;
+        if package.checksum.as_ref() != Some(&registry.checksum(entry)?) {
+            return Err(CliError::Package(format!(
+                "{} {} in the registry {} does not match the checksum in {}",
+                package.name,
+                package.version,
+                registry.root.display(),
+                LOCK_FILE
+            )));
+        }
+        output::status(format!("Installing {} {}", package.name, package.version));
+        install_package(registry, entry, &target, &stamp)?;
+    }
+    if packages_dir.is_dir() {
+        for entry in fs::read_dir(&packages_dir)? {
+            let entry = entry?;
+            if !installed.contains(entry.file_name().to_string_lossy().as_ref()) {
+                output::detail(format!("Removing {}", entry.path().display()));
+                if entry.file_type()?.is_dir() {
+                    fs::remove_dir_all(entry.path())?;
+                } else {
+                    fs::remove_file(entry.path())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// The package is unpacked beside `target` first, so a failed install leaves no half-installed package
+fn install_package(registry: &Registry, entry: &IndexEntry, target: &Path, stamp: &str) -> Result<(), CliError> {
+    let partial = target.with_extension(format!("partial-{}", std::process::id()));
+    if partial.exists() {
+        fs::remove_dir_all(&partial)?;
+    }
+    if let Err(e) = registry.unpack(entry, &partial) {
+        let _ = fs::remove_dir_all(&partial);
+        return Err(e);
+    }
+    fs::write(partial.join(INSTALLED_FILE), stamp)?;
+    if target.exists() {
+        fs::remove_dir_all(target)?;
+    }
+    fs::rename(partial, target)?;
+    Ok(())
+}
+
+// Name and src/lib.eppx of every package the project depends on, installing
+// them first if eppx.lock or .eppx_packages/ is out of date. In a workspace, the
+// lock also has what other members need; only this project's own are modules
+pub fn package_modules(project: &Project) -> Result<Vec<(String, PathBuf)>, CliError> {
+    let scope = Scope::of_project(project)?;
+    if scope.manifests.iter().all(|manifest| manifest.dependencies.is_empty()) && !scope.lock_path().exists() {
+        return Ok(Vec::new());
+    }
+    let packages = sync(&scope, None, Resolve::IfNeeded)?;
+    let mut needed = BTreeSet::new();
+    let mut queue: VecDeque<&String> = project.config.dependencies.keys().collect();
+    while let Some(name) = queue.pop_front() {
+        if let Some(package) = packages.iter().find(|package| package.name == *name) {
+            if needed.insert(name.as_str()) {
+                queue.extend(&package.dependencies);
+            }
+        }
+    }
+    packages
+        .iter()
+        .filter(|package| needed.contains(package.name.as_str()))
+        .map(|package| Ok((package.name.clone(), package.module_path(&scope)?)))
+        .collect()
+}
+
+// `path` relative to `base`, for a directory given on the command line to be recorded in codon.toml
+fn relative_path(path: &Path, base: &Path) -> Result<PathBuf, CliError> {
+    let path = fs::canonicalize(path).map_err(|e| CliError::Package(format!("{}: {}", path.display(), e)))?;
+    let base = fs::canonicalize(base)?;
+    let common = path.components().zip(base.components()).take_while(|(a, b)| a == b).count();
+    let mut relative: PathBuf = base.components().skip(common).map(|_| Component::ParentDir).collect();
+    relative.extend(path.components().skip(common));
+    Ok(if relative.as_os_str().is_empty() { PathBuf::from(".") } else { relative })
+}
+
+// `eppx add name[@requirement] [--path dir]`
+pub fn handle_add(package: &str, path: Option<&Path>, registry: Option<&Path>) -> Result<String, CliError> {
+    let project = Project::discover()?;
+    let (name, version) = match package.split_once('@') {
+        Some((name, version)) => (name, Some(version.to_string())),
+        None => (package, None),
+    };
+    if !is_module_name(name) {
+        return Err(CliError::Package(format!("'{}' is not a valid package name: packages are imported by name", name)));
+    }
+    let dependency = match path {
+        Some(path) => Dependency::Detailed(DependencyDetail {
+            version,
+            path: Some(relative_path(path, &project.root)?.to_string_lossy().replace('\\', "/")),
+            registry: None,
+        }),
+        None => {
+            let registry = Registry::locate(registry)?;
+            let version = match version {
+                Some(version) => version,
+                None => {
+                    let newest = registry
+                        .versions(name)
+                        .filter_map(|entry| Version::parse(&entry.version).ok())
+                        .filter(|version| version.pre.is_empty())
+                        .max()
+                        .ok_or_else(|| CliError::Package(format!("package {} is not in the registry {}", name, registry.root.display())))?;
+                    format!("^{}", newest)
+                }
+            };
+            // A configured registry is recorded, so installs don't depend on which one is the default
+            match registry.name {
+                Some(registry) => Dependency::Detailed(DependencyDetail { version: Some(version), path: None, registry: Some(registry) }),
+                None => Dependency::Version(version),
+            }
+        }
+    };
+    let mut scope = Scope::of_project(&project)?;
+    scope.manifest(&project).dependencies.insert(name.to_string(), dependency.clone());
+    sync(&scope, registry, Resolve::IfNeeded)?;
+    let description = match (dependency.path(), dependency.version(), dependency.registry()) {
+        (Some(path), _, _) => format!("{} = {{ path = \"{}\" }}", name, path),
+        (None, version, Some(registry)) => {
+            format!("{} = {{ version = \"{}\", registry = \"{}\" }}", name, version.unwrap_or("*"), registry)
+        }
+        (None, version, None) => format!("{} = \"{}\"", name, version.unwrap_or("*")),
+    };
+    ConfigManager::new()?.add_dependency(&project.root, name, dependency)?;
+    Ok(format!("Added {} to [dependencies]", description))
+}
+
+pub fn handle_remove(name: &str) -> Result<String, CliError> {
+    let project = Project::discover()?;
+    let mut scope = Scope::of_project(&project)?;
+    if scope.manifest(&project).dependencies.remove(name).is_none() {
+        return Err(CliError::Package(format!("{} is not in [dependencies]", name)));
+    }
+    sync(&scope, None, Resolve::IfNeeded)?;
+    ConfigManager::new()?.remove_dependency(&project.root, name)?;
+    Ok(format!("Removed {} from [dependencies]", name))
+}
+
+pub fn handle_install(registry: Option<&Path>) -> Result<String, CliError> {
+    let packages = sync(&Scope::current()?, registry, Resolve::IfNeeded)?;
+    Ok(match packages.len() {
+        0 => "No dependencies to install".to_string(),
+        1 => "1 package is installed".to_string(),
+        count => format!("{} packages are installed", count),
+    })
+}
+
+pub fn handle_update(names: &[String], registry: Option<&Path>) -> Result<String, CliError> {
+    sync(&Scope::current()?, registry, Resolve::Update(names))?;
+    Ok(format!("Updated {}", LOCK_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::registry::{package_checksum, Index, INDEX_FILE};
+
+    // A registry package: name, version and its requirements on other packages
+    type Published<'a> = (&'a str, &'a str, &'a [(&'a str, &'a str)]);
+
+    // A registry of plain package directories
+    fn write_registry(dir: &Path, packages: &[Published]) {
+        let mut index = Index::default();
+        for (name, version, dependencies) in packages {
+            let path = format!("{}-{}", name, version);
+            fs::create_dir_all(dir.join(&path).join("src")).unwrap();
+            fs::write(dir.join(&path).join("codon.toml"), format!("[project]\nname = \"{}\"\nversion = \"{}\"\n", name, version)).unwrap();
+            fs::write(dir.join(&path).join(LIBRARY_MODULE), format!("VERSION = \"{}\"\n", version)).unwrap();
+            index.packages.push(IndexEntry {
+                name: name.to_string(),
+                version: version.to_string(),
+                path,
+                checksum: None,
+                dependencies: dependencies.iter().map(|(name, req)| (name.to_string(), req.to_string())).collect(),
+            });
+        }
+        fs::write(dir.join(INDEX_FILE), toml::to_string(&index).unwrap()).unwrap();
+    }
+
+    fn roots(dependencies: &[(&str, Dependency)]) -> Vec<Requirement> {
+        let dependencies = dependencies.iter().map(|(name, dependency)| (name.to_string(), dependency.clone())).collect();
+        requirements(&dependencies, Path::new(""), "app").unwrap()
+    }
+
+    fn version(requirement: &str) -> Dependency {
+        Dependency::Version(requirement.to_string())
+    }
+
+    fn locked(name: &str, version: &str, dependencies: &[&str]) -> LockedPackage {
+        LockedPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            source: Source::Registry(None).to_string(),
+            checksum: Some(format!("{}-{}", name, version)),
+            dependencies: dependencies.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    fn resolve(root: &Path, registry: &Path, preferred: &[(&str, &str)], roots: &[Requirement]) -> Result<BTreeMap<String, Candidate>, CliError> {
+        let preferred = preferred.iter().map(|(name, version)| (name.to_string(), Version::parse(version).unwrap())).collect();
+        Resolver { root, registry_flag: Some(registry), registries: Registries::new(), preferred }.resolve(roots)
+    }
+
+    const REGISTRY: &[Published] = &[
+        ("textkit", "1.0.0", &[]),
+        ("textkit", "1.2.0", &[("base", "^0.1")]),
+        ("textkit", "2.0.0", &[]),
+        ("base", "0.1.0", &[]),
+        ("base", "0.1.3", &[]),
+        ("base", "0.2.0", &[]),
+    ];
+
+    #[test]
+    fn test_resolver_picks_newest_matching_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        write_registry(dir.path(), REGISTRY);
+        let chosen = resolve(dir.path(), dir.path(), &[], &roots(&[("textkit", version("^1.0"))])).unwrap();
+        let versions: Vec<(&str, String)> = chosen.iter().map(|(name, candidate)| (name.as_str(), candidate.version.to_string())).collect();
+        assert_eq!(versions, vec![("base", "0.1.3".to_string()), ("textkit", "1.2.0".to_string())]);
+        assert!(chosen.values().all(|candidate| candidate.source == Source::Registry(None)));
+
+        // A locked version is kept while it still matches, and drops its dependencies with it
+        let chosen = resolve(dir.path(), dir.path(), &[("textkit", "1.0.0")], &roots(&[("textkit", version("^1.0"))])).unwrap();
+        assert_eq!(chosen.keys().collect::<Vec<_>>(), vec!["textkit"]);
+        assert_eq!(chosen["textkit"].version, Version::new(1, 0, 0));
+    }
+
+    #[test]
+    fn test_resolver_reports_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        write_registry(dir.path(), REGISTRY);
+        let mut conflicting = roots(&[("textkit", version("^1.0"))]);
+        conflicting.extend(requirements(&BTreeMap::from([("textkit".to_string(), version("^2.0"))]), Path::new(""), "tool").unwrap());
+        let Err(CliError::Package(message)) = resolve(dir.path(), dir.path(), &[], &conflicting) else {
+            panic!("^1.0 and ^2.0 can't both be met");
+        };
+        assert!(message.starts_with("no version of textkit matches"), "{}", message);
+        assert!(message.contains("(required by tool)"), "{}", message);
+
+        let Err(CliError::Package(message)) = resolve(dir.path(), dir.path(), &[], &roots(&[("missing", version("*"))])) else {
+            panic!("missing is not in the registry");
+        };
+        assert!(message.starts_with("package missing is not in the registry"), "{}", message);
+    }
+
+    #[test]
+    fn test_resolver_uses_path_packages_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let package_dir = dir.path().join("greetings");
+        ConfigManager::new().unwrap().create_new_project(&package_dir, "greetings", None).unwrap();
+        fs::create_dir_all(package_dir.join("src")).unwrap();
+        fs::write(package_dir.join(LIBRARY_MODULE), "def hello():\n    return \"hi\"\n").unwrap();
+        let local = |version: Option<&str>| Dependency::Detailed(DependencyDetail {
+            version: version.map(str::to_string),
+            path: Some("./greetings".to_string()),
+            registry: None,
+        });
+
+        let chosen = resolve(dir.path(), dir.path(), &[], &roots(&[("greetings", local(None))])).unwrap();
+        assert_eq!(chosen["greetings"].source, Source::Path(PathBuf::from("greetings")));
+        assert_eq!(chosen["greetings"].version, Version::new(0, 1, 0));
+
+        assert!(resolve(dir.path(), dir.path(), &[], &roots(&[("greetings", local(Some("^2")))])).is_err());
+    }
+
+    #[test]
+    fn test_lockfile_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let scope = Scope { root: dir.path().to_path_buf(), manifests: Vec::new() };
+        assert!(read_lock(&scope).unwrap().is_none());
+
+        let mut local = locked("greetings", "0.1.0", &[]);
+        local.source = Source::Path(PathBuf::from("../greetings")).to_string();
+        local.checksum = None;
+        let packages = vec![locked("base", "0.1.3", &[]), local, locked("textkit", "1.2.0", &["base"])];
+        write_lock(&scope, &packages).unwrap();
+        let content = fs::read_to_string(scope.lock_path()).unwrap();
+        assert!(content.starts_with(LOCK_HEADER));
+        let lock = read_lock(&scope).unwrap().unwrap();
+        assert_eq!(lock.version, LOCK_VERSION);
+        assert_eq!(lock.packages, packages);
+        assert_eq!(lock.packages[1].source().unwrap(), Source::Path(PathBuf::from("../greetings")));
+
+        // The lock stands while it meets the requirements, and only what they reach is kept
+        let closure = locked_closure(&scope.root, &roots(&[("textkit", version("^1.0"))]), &lock).unwrap().unwrap();
+        assert_eq!(closure.iter().map(|package| package.name.as_str()).collect::<Vec<_>>(), vec!["base", "textkit"]);
+        assert!(locked_closure(&scope.root, &roots(&[("textkit", version("^2.0"))]), &lock).unwrap().is_none());
+        assert!(locked_closure(&scope.root, &roots(&[("other", version("*"))]), &lock).unwrap().is_none());
+
+        fs::write(scope.lock_path(), format!("version = {}\n", LOCK_VERSION + 1)).unwrap();
+        assert!(read_lock(&scope).is_err());
+    }
+
+    #[test]
+    fn test_registry_sources_name_the_registry() {
+        for source in ["registry", "registry+team", "path+../greetings"] {
+            assert_eq!(Source::parse(source).unwrap().to_string(), source);
+        }
+        assert_eq!(Source::parse("registry+team").unwrap(), Source::Registry(Some("team".to_string())));
+        assert!(Source::parse("registry+").is_err());
+        assert!(Source::parse("registryteam").is_err());
+
+        let team = Dependency::Detailed(DependencyDetail { version: Some("^1.0".to_string()), path: None, registry: Some("team".to_string()) });
+        let named = roots(&[("textkit", team)]);
+        assert_eq!(named[0].registry.as_deref(), Some("team"));
+        assert_eq!(named[0].to_string(), "^1.0 from registry team (required by app)");
+
+        // A lock from another registry doesn't meet a requirement on the team's
+        let mut package = locked("textkit", "1.2.0", &[]);
+        let lock = |package: &LockedPackage| Lockfile { version: LOCK_VERSION, packages: vec![package.clone()] };
+        assert!(locked_closure(Path::new(""), &named, &lock(&package)).unwrap().is_none());
+        package.source = Source::Registry(Some("mirror".to_string())).to_string();
+        assert!(locked_closure(Path::new(""), &named, &lock(&package)).unwrap().is_none());
+        package.source = Source::Registry(Some("team".to_string())).to_string();
+        assert!(locked_closure(Path::new(""), &named, &lock(&package)).unwrap().is_some());
+        // while a requirement that names none takes the package from any registry
+        assert!(locked_closure(Path::new(""), &roots(&[("textkit", version("^1.0"))]), &lock(&package)).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_install_verifies_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry_dir = dir.path().join("registry");
+        write_registry(&registry_dir, &[("textkit", "1.0.0", &[])]);
+        let registry = Registry::open(&registry_dir).unwrap();
+        let entry = registry.find("textkit", &Version::new(1, 0, 0)).unwrap().clone();
+        let checksum = registry.checksum(&entry).unwrap();
+        assert_eq!(checksum, package_checksum(&registry_dir.join(&entry.path)).unwrap());
+
+        let scope = Scope { root: dir.path().join("app"), manifests: Vec::new() };
+        let mut package = locked("textkit", "1.0.0", &[]);
+        package.checksum = Some(checksum.clone());
+        install(&scope, &[package.clone()], Some(&registry_dir)).unwrap();
+        let installed = scope.packages_dir().join("textkit");
+        assert_eq!(fs::read_to_string(installed.join(LIBRARY_MODULE)).unwrap(), "VERSION = \"1.0.0\"\n");
+        assert_eq!(fs::read_to_string(installed.join(INSTALLED_FILE)).unwrap(), format!("1.0.0 {}", checksum));
+
+        // A lock whose checksum doesn't match what the registry has is refused
+        package.checksum = Some("0".repeat(64));
+        let Err(CliError::Package(message)) = install(&scope, &[package], Some(&registry_dir)) else {
+            panic!("the checksum in the lock doesn't match");
+        };
+        assert!(message.ends_with(&format!("does not match the checksum in {}", LOCK_FILE)), "{}", message);
+
+        // So is a package changed after the index recorded its checksum
+        let mut recorded = entry.clone();
+        recorded.checksum = Some(checksum);
+        fs::write(registry_dir.join(&entry.path).join(LIBRARY_MODULE), "VERSION = \"changed\"\n").unwrap();
+        assert!(registry.checksum(&recorded).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+// Projects: `eppx build` and `eppx run` without a file build the project whose
+// codon.toml is in the current directory or the nearest one above it. A project
+// listed by a workspace shares the workspace's build directory and packages
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::codon::config::{find_project_dir, read_project_config, CodonProjectConfig, DEFAULT_ENTRY_POINT};
+use crate::codon::OptimizationLevel;
+
+use super::packages;
+use super::registry::LIBRARY_MODULE;
+use super::workspace::Workspace;
+use super::{BuildOptions, CliError, Selection, BUILD_DIR};
+
+// Directory whose `.eppx` files are the project's modules
+const SOURCE_DIR: &str = "src";
+
+// Test programs, which can import the project's modules
+const TESTS_DIR: &str = "tests";
+
+pub struct Project {
+    pub root: PathBuf,
+    pub config: CodonProjectConfig,
+    // The workspace that lists this project as a member
+    pub workspace: Option<Workspace>,
+}
+
+impl Project {
+    pub fn discover() -> Result<Self, CliError> {
+        let cwd = env::current_dir()?;
+        if let Some(project) = Self::enclosing()? {
+            return Ok(project);
+        }
+        match Workspace::around(&cwd)? {
+            Some(workspace) => Err(CliError::Project(format!(
+                "{} is a workspace, not a project; use a member's directory, or pass -p <member> or --workspace to build and run",
+                workspace.root.display()
+            ))),
+            None => Err(CliError::NoProject(cwd)),
+        }
+    }
+
+    // The project `build` or `run` without a file means
+    pub fn select(selection: Selection) -> Result<Self, CliError> {
+        match selection {
+            Selection::Member(name) => Workspace::discover()?.member(name),
+            _ => Self::discover(),
+        }
+    }
+
+    // The project around the current directory, if there is one
+    pub fn enclosing() -> Result<Option<Self>, CliError> {
+        Self::around(&env::current_dir()?)
+    }
+
+    // The project whose codon.toml is in `dir` or the nearest directory above it
+    pub fn around(dir: &Path) -> Result<Option<Self>, CliError> {
+        let Some(root) = find_project_dir(dir) else {
+            return Ok(None);
+        };
+        let Some(config) = read_project_config(&root)? else {
+            return Ok(None);
+        };
+        let workspace = match Workspace::around(&root)? {
+            Some(workspace) if workspace.contains(&root)? => Some(workspace),
+            _ => None,
+        };
+        Ok(Some(Self { root, config, workspace }))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.project.name
+    }
+
+    pub fn entry_point(&self) -> PathBuf {
+        self.root.join(self.config.project.entry_point.as_deref().unwrap_or(DEFAULT_ENTRY_POINT))
+    }
+
+    // A library has src/lib.eppx and no program: building it checks that module
+    pub fn is_library(&self) -> bool {
+        !self.entry_point().exists() && self.library_module().is_file()
+    }
+
+    pub fn library_module(&self) -> PathBuf {
+        self.root.join(LIBRARY_MODULE)
+    }
+
+    pub fn output_dir(&self) -> PathBuf {
+        self.root.join(&self.config.project.output_dir)
+    }
+
+    // The workspace's root for a member, otherwise the project's: where the build
+    // directory, eppx.lock and .eppx_packages/ are
+    pub fn shared_root(&self) -> &Path {
+        self.workspace.as_ref().map_or(&self.root, |workspace| &workspace.root)
+    }
+
+    pub fn build_dir(&self) -> PathBuf {
+        self.shared_root().join(BUILD_DIR)
+    }
+
+    pub fn source_dir(&self) -> PathBuf {
+        self.root.join(SOURCE_DIR)
+    }
+
+    pub fn tests_dir(&self) -> PathBuf {
+        self.root.join(TESTS_DIR)
+    }
+
+    // The `.eppx` files in src/ other than `entry`, importable by their file names,
+    // except that src/lib.eppx is named after the project as its dependents import it
+    pub fn modules(&self, entry: &Path) -> Result<Vec<(String, PathBuf)>, CliError> {
+        let src_dir = self.source_dir();
+        if !src_dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let entry = fs::canonicalize(entry).ok();
+        let mut modules = Vec::new();
+        for dir_entry in fs::read_dir(&src_dir)? {
+            let path = dir_entry?.path();
+            if path.extension().is_none_or(|extension| extension != "eppx") || Some(fs::canonicalize(&path)?) == entry {
+                continue;
+            }
+            let name = match path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default() {
+                "lib" => self.name(),
+                stem => stem,
+            }
+            .to_string();
+            if !is_module_name(&name) {
+                return Err(CliError::Project(format!("{} is not a valid module name", path.display())));
+            }
+            modules.push((name, path));
+        }
+        modules.sort();
+        Ok(modules)
+    }
+
+    // The modules in src/ and those of the project's packages; `entry` is not one of them
+    pub fn importable_modules(&self, entry: &Path) -> Result<Vec<(String, PathBuf)>, CliError> {
+        let mut modules = self.modules(entry)?;
+        for (name, path) in packages::package_modules(self)? {
+            if modules.iter().any(|(module, _)| *module == name) {
+                return Err(CliError::Project(format!("module {} is both in {} and a dependency", name, SOURCE_DIR)));
+            }
+            modules.push((name, path));
+        }
+        Ok(modules)
+    }
+
+    // The command line's options with the manifest's settings applied: any
+    // optimization level but Debug builds with -O3, and Optimized also drops asserts
+    pub fn build_options(&self, options: &BuildOptions) -> BuildOptions {
+        let codon = &self.config.codon;
+        BuildOptions {
+            release: options.release || !matches!(codon.optimization_level, OptimizationLevel::Debug),
+            optimize: options.optimize || matches!(codon.optimization_level, OptimizationLevel::Optimized),
+            gpu: options.gpu || codon.enable_gpu,
+            ..*options
+        }
+    }
+}
+
+// Modules are imported by name, so their names must be identifiers
+pub fn is_module_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_') && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
@@ -0,0 +1,87 @@
+// `eppx package` and `eppx publish`: a library project becomes a versioned
+// archive of its manifest and src/, which publishing adds to a registry
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use semver::{Version, VersionReq};
+
+use crate::codon::config::Dependency;
+use crate::parser::parse_eppx_string;
+
+use super::archive::{self, ARCHIVE_EXTENSION};
+use super::output;
+use super::project::{is_module_name, Project};
+use super::registry::{self, package_files, Registry, LIBRARY_MODULE};
+use super::CliError;
+
+// Archives go to <output_dir>/package/
+const PACKAGE_DIR: &str = "package";
+
+struct Package {
+    name: String,
+    version: Version,
+    archive: PathBuf,
+    // Version requirements on registry packages, as the index records them
+    dependencies: BTreeMap<String, String>,
+}
+
+// Checks that the project can be published and writes its archive. The archived
+// codon.toml keeps only the version requirement of each dependency, since a
+// path means nothing to whoever installs the package
+fn package_project(project: &Project) -> Result<Package, CliError> {
+    let name = project.name();
+    if !is_module_name(name) {
+        return Err(CliError::Package(format!("'{}' is not a valid package name: packages are imported by name", name)));
+    }
+    let version = Version::parse(&project.config.project.version)
+        .map_err(|e| CliError::Package(format!("invalid version '{}': {}", project.config.project.version, e)))?;
+    let library = project.root.join(LIBRARY_MODULE);
+    if !library.is_file() {
+        return Err(CliError::Package(format!("{} has no {}; only libraries can be packaged", name, LIBRARY_MODULE)));
+    }
+    for file in package_files(&project.root)? {
+        if file.extension().is_some_and(|extension| extension == "eppx") {
+            let path = project.root.join(&file);
+            parse_eppx_string(&fs::read_to_string(&path)?).map_err(|e| CliError::Parser(format!("{}: {}", path.display(), e)))?;
+        }
+    }
+    let mut dependencies = BTreeMap::new();
+    for (dependency, requirement) in &project.config.dependencies {
+        let requirement = requirement.version().ok_or_else(|| {
+            CliError::Package(format!("dependency {} needs a version to be published, not only a path", dependency))
+        })?;
+        VersionReq::parse(requirement)
+            .map_err(|e| CliError::Package(format!("invalid version requirement '{}' for {}: {}", requirement, dependency, e)))?;
+        dependencies.insert(dependency.clone(), requirement.to_string());
+    }
+
+    let mut manifest = project.config.clone();
+    manifest.dependencies = dependencies
+        .iter()
+        .map(|(dependency, requirement)| (dependency.clone(), Dependency::Version(requirement.clone())))
+        .collect();
+    let manifest = toml::to_string_pretty(&manifest).map_err(|e| CliError::Package(e.to_string()))?;
+    let contents = archive::create(&project.root, &package_files(&project.root)?, &manifest)?;
+    let package_dir = project.output_dir().join(PACKAGE_DIR);
+    fs::create_dir_all(&package_dir)?;
+    let archive = package_dir.join(format!("{}-{}.{}", name, version, ARCHIVE_EXTENSION));
+    fs::write(&archive, contents)?;
+    Ok(Package { name: name.to_string(), version, archive, dependencies })
+}
+
+pub fn handle_package() -> Result<String, CliError> {
+    let project = Project::discover()?;
+    let package = package_project(&project)?;
+    Ok(format!("Packaged {} {} as {}", package.name, package.version, package.archive.display()))
+}
+
+pub fn handle_publish(registry: Option<&Path>) -> Result<String, CliError> {
+    let project = Project::discover()?;
+    let root = registry::registry_dir(registry)?;
+    let package = package_project(&project)?;
+    output::status(format!("Publishing {} {} to {}...", package.name, package.version, root.display()));
+    let entry = Registry::publish(&root, &package.name, &package.version, &package.archive, package.dependencies)?;
+    Ok(format!("Published {} {} as {}", entry.name, entry.version, root.join(&entry.path).display()))
+}
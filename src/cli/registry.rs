@@ -0,0 +1,344 @@
+// File-based package registry: a directory (local or on a shared drive) whose
+// index.toml lists every version of every package, where its files are and
+// what it depends on. `eppx publish` adds archives to it; a version can also be
+// a plain package directory. `--registry`, EPPX_REGISTRY or the default in
+// ~/.eppx/global.toml says which registry to use
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::codon::config::{ConfigManager, GlobalConfig};
+
+use super::archive::{self, ARCHIVE_EXTENSION};
+use super::{CliError, RegistryCommand};
+
+pub const INDEX_FILE: &str = "index.toml";
+const REGISTRY_ENV: &str = "EPPX_REGISTRY";
+
+// Exists while a publish is updating the index
+const LOCK_FILE: &str = ".index.lock";
+
+// The module a package provides, named after the package
+pub const LIBRARY_MODULE: &str = "src/lib.eppx";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Index {
+    #[serde(default, rename = "package")]
+    pub packages: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub name: String,
+    pub version: String,
+    /// The package's archive, or a directory holding its codon.toml and src/,
+    /// relative to the registry
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// Version requirements on other packages in the registry
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, String>,
+}
+
+impl IndexEntry {
+    fn is_archive(&self) -> bool {
+        self.path.ends_with(&format!(".{}", ARCHIVE_EXTENSION))
+    }
+}
+
+pub struct Registry {
+    pub root: PathBuf,
+    /// The name the registry is configured under in ~/.eppx/global.toml, if any
+    pub name: Option<String>,
+    index: Index,
+}
+
+impl Registry {
+    pub fn open(root: &Path) -> Result<Self, CliError> {
+        let index_path = root.join(INDEX_FILE);
+        let content = fs::read_to_string(&index_path)
+            .map_err(|e| CliError::Package(format!("cannot read registry index {}: {}", index_path.display(), e)))?;
+        let index = toml::from_str(&content)
+            .map_err(|e| CliError::Package(format!("{}: {}", index_path.display(), e)))?;
+        Ok(Self { root: root.to_path_buf(), name: None, index })
+    }
+
+    pub fn locate(flag: Option<&Path>) -> Result<Self, CliError> {
+        let (name, root) = locate_registry(flag)?;
+        Ok(Self { name, ..Self::open(&root)? })
+    }
+
+    // The registry configured as `name`, which packages recorded as coming from it are installed from
+    pub fn named(name: &str) -> Result<Self, CliError> {
+        let root = global_config()?.registries.get(name).map(PathBuf::from).ok_or_else(|| {
+            CliError::Package(format!("no registry named '{}' is configured; add it with `eppx registry add {} <dir>`", name, name))
+        })?;
+        Ok(Self { name: Some(name.to_string()), ..Self::open(&root)? })
+    }
+
+    pub fn versions<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a IndexEntry> {
+        self.index.packages.iter().filter(move |entry| entry.name == name)
+    }
+
+    pub fn find(&self, name: &str, version: &Version) -> Option<&IndexEntry> {
+        self.index.packages.iter().find(|entry| entry.name == name && Version::parse(&entry.version).is_ok_and(|v| v == *version))
+    }
+
+    // Checksum of a published package, which must match the index's when it has one.
+    // An archive's is that of the file; a directory's covers the files in it
+    pub fn checksum(&self, entry: &IndexEntry) -> Result<String, CliError> {
+        let path = self.root.join(&entry.path);
+        let checksum = if entry.is_archive() { file_checksum(&path)? } else { package_checksum(&path)? };
+        match &entry.checksum {
+            Some(expected) if *expected != checksum => Err(CliError::Package(format!(
+                "{} {} in {} does not match the checksum in its index",
+                entry.name,
+                entry.version,
+                self.root.display()
+            ))),
+            _ => Ok(checksum),
+        }
+    }
+
+    // Writes the package's codon.toml and src/ into `destination`
+    pub fn unpack(&self, entry: &IndexEntry, destination: &Path) -> Result<(), CliError> {
+        let path = self.root.join(&entry.path);
+        if entry.is_archive() {
+            return archive::unpack(&path, destination)
+                .map_err(|e| CliError::Package(format!("cannot unpack {}: {}", path.display(), e)));
+        }
+        for file in package_files(&path)? {
+            let target = destination.join(&file);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(path.join(&file), target)?;
+        }
+        Ok(())
+    }
+
+    // Copies `archive` into the registry as <name>/<name>-<version>.tar.gz and adds
+    // it to the index, creating the registry if `root` has none yet. Published
+    // versions never change, so publishing one that exists is an error
+    pub fn publish(
+        root: &Path,
+        name: &str,
+        version: &Version,
+        archive: &Path,
+        dependencies: BTreeMap<String, String>,
+    ) -> Result<IndexEntry, CliError> {
+        fs::create_dir_all(root)?;
+        let _lock = IndexLock::acquire(root)?;
+        let mut registry = if root.join(INDEX_FILE).exists() {
+            Self::open(root)?
+        } else {
+            Self { root: root.to_path_buf(), name: None, index: Index::default() }
+        };
+        if registry.find(name, version).is_some() {
+            return Err(CliError::Package(format!(
+                "{} {} is already in the registry {}; publish a new version",
+                name,
+                version,
+                root.display()
+            )));
+        }
+        for (dependency, requirement) in &dependencies {
+            let requirement = VersionReq::parse(requirement)
+                .map_err(|e| CliError::Package(format!("invalid version requirement '{}' for {}: {}", requirement, dependency, e)))?;
+            let published = registry
+                .versions(dependency)
+                .any(|entry| Version::parse(&entry.version).is_ok_and(|version| requirement.matches(&version)));
+            if !published {
+                return Err(CliError::Package(format!(
+                    "{} depends on {} {}, which is not in the registry {}; publish it first",
+                    name,
+                    dependency,
+                    requirement,
+                    root.display()
+                )));
+            }
+        }
+        let path = format!("{}/{}-{}.{}", name, name, version, ARCHIVE_EXTENSION);
+        write_atomically(&root.join(&path), &fs::read(archive)?)?;
+        let entry = IndexEntry {
+            name: name.to_string(),
+            version: version.to_string(),
+            path,
+            checksum: Some(file_checksum(archive)?),
+            dependencies,
+        };
+        registry.index.packages.push(entry.clone());
+        let index = toml::to_string_pretty(&registry.index).map_err(|e| CliError::Package(e.to_string()))?;
+        write_atomically(&root.join(INDEX_FILE), index.as_bytes())?;
+        Ok(entry)
+    }
+}
+
+// Keeps concurrent publishes to a shared registry from losing each other's index entries
+struct IndexLock {
+    path: PathBuf,
+}
+
+impl IndexLock {
+    fn acquire(root: &Path) -> Result<Self, CliError> {
+        let path = root.join(LOCK_FILE);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => Ok(Self { path }),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(CliError::Package(format!(
+                "the registry {} is locked by another publish; if none is running, remove {}",
+                root.display(),
+                path.display()
+            ))),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Drop for IndexLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Readers of the registry never see a partly written file
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = path.with_extension(format!("partial-{}", std::process::id()));
+    fs::write(&partial, contents)?;
+    fs::rename(partial, path)
+}
+
+fn global_config() -> Result<GlobalConfig, CliError> {
+    match ConfigManager::new() {
+        Ok(manager) => Ok(manager.get_global_config()?),
+        // No home directory, so nothing is configured
+        Err(_) => Ok(GlobalConfig::default()),
+    }
+}
+
+pub fn registry_dir(flag: Option<&Path>) -> Result<PathBuf, CliError> {
+    Ok(locate_registry(flag)?.1)
+}
+
+// The registry `--registry` names (a registry configured by that name, or else a
+// directory); without the flag, EPPX_REGISTRY, then the configured default, then
+// the only configured registry. A directory that is a configured registry gets its name
+fn locate_registry(flag: Option<&Path>) -> Result<(Option<String>, PathBuf), CliError> {
+    let config = global_config()?;
+    let by_dir = |dir: PathBuf| {
+        let canonical = fs::canonicalize(&dir).ok();
+        let name = config
+            .registries
+            .iter()
+            .find(|(_, configured)| canonical.is_some() && fs::canonicalize(configured).ok() == canonical)
+            .map(|(name, _)| name.clone());
+        (name, dir)
+    };
+    if let Some(flag) = flag {
+        return Ok(match flag.to_str().and_then(|name| config.registries.get_key_value(name)) {
+            Some((name, dir)) => (Some(name.clone()), PathBuf::from(dir)),
+            None => by_dir(flag.to_path_buf()),
+        });
+    }
+    if let Some(dir) = env::var_os(REGISTRY_ENV).filter(|dir| !dir.is_empty()) {
+        return Ok(by_dir(PathBuf::from(dir)));
+    }
+    let name = match (&config.default_registry, config.registries.len()) {
+        (Some(name), _) => name,
+        (None, 1) => config.registries.keys().next().expect("there is one registry"),
+        (None, 0) => {
+            return Err(CliError::Package(format!(
+                "no package registry given; pass --registry, set {} or configure one with `eppx registry add`",
+                REGISTRY_ENV
+            )))
+        }
+        (None, _) => {
+            return Err(CliError::Package(
+                "several registries are configured and none is the default; pass --registry or add one with --default".to_string(),
+            ))
+        }
+    };
+    match config.registries.get(name) {
+        Some(dir) => Ok((Some(name.clone()), PathBuf::from(dir))),
+        None => Err(CliError::Package(format!("the default registry '{}' is not among the configured registries", name))),
+    }
+}
+
+// `eppx registry add/remove/list`: the registries in ~/.eppx/global.toml
+pub fn handle_registry(command: &RegistryCommand) -> Result<String, CliError> {
+    let manager = ConfigManager::new()?;
+    match command {
+        RegistryCommand::Add { name, dir, default } => {
+            let is_name = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+            if !is_name {
+                return Err(CliError::Package(format!("'{}' is not a valid registry name", name)));
+            }
+            let dir = std::path::absolute(dir)?;
+            manager.add_registry(name, &dir, *default)?;
+            Ok(format!("Added registry {} ({})", name, dir.display()))
+        }
+        RegistryCommand::Remove { name } => {
+            if !manager.get_global_config()?.registries.contains_key(name) {
+                return Err(CliError::Package(format!("no registry named '{}' is configured", name)));
+            }
+            manager.remove_registry(name)?;
+            Ok(format!("Removed registry {}", name))
+        }
+        RegistryCommand::List => {
+            let config = manager.get_global_config()?;
+            if config.registries.is_empty() {
+                return Ok("No registries configured; add one with `eppx registry add <name> <dir>`".to_string());
+            }
+            for (name, dir) in &config.registries {
+                let default = if config.default_registry.as_ref() == Some(name) { " (default)" } else { "" };
+                println!("{:<12} {}{}", name, dir, default);
+            }
+            Ok(String::new())
+        }
+    }
+}
+
+// A package's manifest and everything under its src/, as paths relative to `dir`
+pub fn package_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![PathBuf::from("codon.toml")];
+    let mut pending = vec![PathBuf::from("src")];
+    while let Some(relative) = pending.pop() {
+        for entry in fs::read_dir(dir.join(&relative))? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// SHA-256 over the names and contents of a package's files
+pub fn package_checksum(dir: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    for file in package_files(dir)? {
+        hasher.update(file.to_string_lossy().replace('\\', "/").as_bytes());
+        hasher.update([0]);
+        hasher.update(fs::read(dir.join(&file))?);
+        hasher.update([0]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub fn file_checksum(path: &Path) -> io::Result<String> {
+    Ok(format!("{:x}", Sha256::digest(fs::read(path)?)))
+}
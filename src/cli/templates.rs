@@ -0,0 +1,242 @@
+// Project templates for `eppx new` and `eppx init`. The built-in templates are
+// bin, lib, cli and test-suite; each directory in ~/.eppx/templates/ is a user
+// template named after it, which replaces a built-in one of the same name. Every
+// `{{name}}` in a template's files and paths becomes the project's name
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::codon::config::ConfigManager;
+
+use super::output;
+use super::project::is_module_name;
+use super::registry::LIBRARY_MODULE;
+use super::CliError;
+
+pub const DEFAULT_TEMPLATE: &str = "bin";
+const NAME_PLACEHOLDER: &str = "{{name}}";
+
+// Written for templates that do not have their own
+const GITIGNORE: &str = ".eppx_build/\n.eppx_packages/\ntarget/\n";
+const README: &str = "# {{name}}\n";
+
+struct Builtin {
+    name: &'static str,
+    // Paths and contents; codon.toml is the default manifest
+    files: &'static [(&'static str, &'static str)],
+}
+
+const BUILTIN_TEMPLATES: &[Builtin] = &[
+    Builtin {
+        name: "bin",
+        files: &[
+            ("src/main.eppx", "import greeting\n\nprint(greeting.greet(\"{{name}}\"))\n"),
+            ("src/greeting.eppx", "def greet(name):\n    return \"Hello from \" + name + \"!\"\n"),
+            (
+                "tests/test_greeting.eppx",
+                "import greeting\n\nassert greeting.greet(\"E++\") == \"Hello from E++!\"\nprint(\"test_greeting: ok\")\n",
+            ),
+            (
+                "README.md",
+                "# {{name}}\n\nAn E++ program.\n\n```bash\neppx run                               # build and run src/main.eppx\neppx run --fast tests/test_greeting.eppx\n```\n\nThe other files in `src/` are modules that `src/main.eppx` and the programs in\n`tests/` import by name.\n",
+            ),
+        ],
+    },
+    Builtin {
+        name: "lib",
+        files: &[
+            (
+                "src/lib.eppx",
+                "\"\"\"The {{name}} package: other projects `import {{name}}`\"\"\"\n\ndef clamp(value, low, high):\n    if value < low:\n        return low\n    if value > high:\n        return high\n    return value\n",
+            ),
+            (
+                "tests/test_{{name}}.eppx",
+                "import {{name}}\n\nassert {{name}}.clamp(5, 0, 10) == 5\nassert {{name}}.clamp(-1, 0, 10) == 0\nassert {{name}}.clamp(11, 0, 10) == 10\nprint(\"test_{{name}}: ok\")\n",
+            ),
+            (
+                "README.md",
+                "# {{name}}\n\nAn E++ package. `src/lib.eppx` is the module that projects depending on it\nimport as `{{name}}`.\n\n```bash\neppx run --fast tests/test_{{name}}.eppx\neppx publish        # add this version to the package registry\n```\n",
+            ),
+        ],
+    },
+    Builtin {
+        name: "cli",
+        files: &[
+            (
+                "src/main.eppx",
+                "import sys\nimport commands\n\nif len(sys.argv) < 2:\n    print(\"usage: {{name}} <name>...\")\n    sys.exit(2)\nfor i in range(1, len(sys.argv)):\n    print(commands.greet(sys.argv[i]))\n",
+            ),
+            (
+                "src/commands.eppx",
+                "def greet(name):\n    return \"Hello, \" + name + \"!\"\n",
+            ),
+            (
+                "tests/test_commands.eppx",
+                "import commands\n\nassert commands.greet(\"Ada\") == \"Hello, Ada!\"\nprint(\"test_commands: ok\")\n",
+            ),
+            (
+                "README.md",
+                "# {{name}}\n\nAn E++ command-line program. `src/main.eppx` reads the arguments;\n`src/commands.eppx` does the work.\n\n```bash\neppx run -- Ada Alan\neppx run --fast tests/test_commands.eppx\n```\n",
+            ),
+        ],
+    },
+    Builtin {
+        name: "test-suite",
+        files: &[
+            (
+                "src/main.eppx",
+                "import checks\n\nchecks.check(\"the suite runs\", 1 + 1, 2)\nprint(\"Run the tests one by one: eppx run --fast tests/<file>.eppx\")\n",
+            ),
+            (
+                "src/checks.eppx",
+                "def check(label, actual, expected):\n    if actual == expected:\n        print(\"ok  \", label)\n        return True\n    print(\"FAIL\", label, \"- expected\", expected, \"got\", actual)\n    return False\n",
+            ),
+            (
+                "tests/test_numbers.eppx",
+                "import checks\n\nresults = [\n    checks.check(\"addition\", 2 + 3, 5),\n    checks.check(\"floor division\", 7 // 2, 3),\n    checks.check(\"power\", 2 ** 10, 1024),\n]\nassert all(results)\n",
+            ),
+            (
+                "tests/test_strings.eppx",
+                "import checks\n\nresults = [\n    checks.check(\"upper\", \"e++\".upper(), \"E++\"),\n    checks.check(\"concatenation\", \"E\" + \"++\", \"E++\"),\n    checks.check(\"length\", len(\"E++\"), 3),\n]\nassert all(results)\n",
+            ),
+            (
+                "README.md",
+                "# {{name}}\n\nA suite of E++ test programs. Each file in `tests/` is a program that prints one\nline per check and fails its `assert` if any check failed; `src/checks.eppx` is\nthe helper they share.\n\n```bash\neppx run --fast tests/test_numbers.eppx\neppx run --fast tests/test_strings.eppx\n```\n",
+            ),
+        ],
+    },
+];
+
+// The user template called `name`, if ~/.eppx/templates/ has one
+fn user_template_dir(name: &str) -> Option<PathBuf> {
+    let dir = ConfigManager::new().ok()?.templates_dir().join(name);
+    dir.is_dir().then_some(dir)
+}
+
+fn user_template_names() -> Vec<String> {
+    let Some(entries) = ConfigManager::new().ok().and_then(|manager| fs::read_dir(manager.templates_dir()).ok()) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+// Every file under `dir`, as paths relative to it
+fn files_under(dir: &Path) -> Result<Vec<PathBuf>, CliError> {
+    let mut files = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        for entry in fs::read_dir(dir.join(&relative))? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// The files `template` makes for a project named `name`, without codon.toml
+// unless the template has its own
+fn template_files(template: &str, name: &str) -> Result<Vec<(PathBuf, Vec<u8>)>, CliError> {
+    let fill = |text: &str| text.replace(NAME_PLACEHOLDER, name);
+    let mut files: Vec<(PathBuf, Vec<u8>)> = if let Some(dir) = user_template_dir(template) {
+        let mut files = Vec::new();
+        for file in files_under(&dir)? {
+            let contents = fs::read(dir.join(&file))?;
+            // Only text files have placeholders
+            let contents = match String::from_utf8(contents) {
+                Ok(text) => fill(&text).into_bytes(),
+                Err(e) => e.into_bytes(),
+            };
+            files.push((PathBuf::from(fill(&file.to_string_lossy())), contents));
+        }
+        files
+    } else if let Some(builtin) = BUILTIN_TEMPLATES.iter().find(|builtin| builtin.name == template) {
+        builtin.files.iter().map(|(path, contents)| (PathBuf::from(fill(path)), fill(contents).into_bytes())).collect()
+    } else {
+        let mut names: Vec<String> = BUILTIN_TEMPLATES.iter().map(|builtin| builtin.name.to_string()).collect();
+        for user_template in user_template_names() {
+            if !names.contains(&user_template) {
+                names.push(user_template);
+            }
+        }
+        return Err(CliError::Project(format!("no template named '{}' (templates: {})", template, names.join(", "))));
+    };
+    for (path, contents) in [(".gitignore", GITIGNORE), ("README.md", README)] {
+        if !files.iter().any(|(file, _)| file == Path::new(path)) {
+            files.push((PathBuf::from(path), fill(contents).into_bytes()));
+        }
+    }
+    if files.iter().any(|(file, _)| file == Path::new(LIBRARY_MODULE)) && !is_module_name(name) {
+        return Err(CliError::Project(format!(
+            "'{}' is not a valid name for a library: packages are imported by name",
+            name
+        )));
+    }
+    Ok(files)
+}
+
+// Writes a template's files into `dir` for a project named `name`, keeping any
+// file that is already there
+fn write_files(dir: &Path, name: &str, files: Vec<(PathBuf, Vec<u8>)>) -> Result<(), CliError> {
+    for (file, contents) in files {
+        let target = dir.join(&file);
+        if target.exists() {
+            output::status(format!("Keeping existing {}", file.display()));
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&target, contents)?;
+        output::detail(format!("Created {}", target.display()));
+    }
+    if !dir.join("codon.toml").exists() {
+        ConfigManager::new()?.create_new_project(dir, name, None)?;
+    }
+    Ok(())
+}
+
+// `eppx new <name> [--template t]`: a new project in the directory <name>
+pub fn handle_new_project(project_name: &str, template: &str) -> Result<String, CliError> {
+    let project_path = Path::new(project_name);
+    if project_path.exists() {
+        return Err(CliError::ProjectCreation(
+            project_name.to_string(),
+            std::io::Error::new(std::io::ErrorKind::AlreadyExists, "Project directory already exists"),
+        ));
+    }
+    let name = project_path.file_name().map_or(project_name.into(), |name| name.to_string_lossy());
+    let files = template_files(template, &name)?;
+    fs::create_dir_all(project_path).map_err(|e| CliError::ProjectCreation(project_name.to_string(), e))?;
+    write_files(project_path, &name, files)?;
+    Ok(format!("Successfully created E++ project '{}' from the {} template", name, template))
+}
+
+// `eppx init [--name n] [--template t]`: makes the current directory a project,
+// named after the directory unless --name says otherwise
+pub fn handle_init(name: Option<&str>, template: &str) -> Result<String, CliError> {
+    let dir = std::env::current_dir()?;
+    if dir.join("codon.toml").exists() {
+        return Err(CliError::Project(format!("{} already has a codon.toml", dir.display())));
+    }
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| CliError::Project("the project needs a name; pass --name".to_string()))?,
+    };
+    write_files(&dir, &name, template_files(template, &name)?)?;
+    Ok(format!("Initialized E++ project '{}' from the {} template", name, template))
+}
@@ -0,0 +1,108 @@
+// C++ toolchain for native builds: which compiler to run and with what flags,
+// from the environment (CXX, CXXFLAGS, LDFLAGS) and the `[build]` section of codon.toml
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::codon::config::BuildConfig;
+
+// Compilers looked for on PATH, in order, when neither CXX nor [build].compiler names one
+pub const CANDIDATE_COMPILERS: &[&str] = &["g++", "clang++", "c++"];
+
+// Where the compiler choice came from, for `eppx doctor`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompilerSource {
+    Environment,
+    ProjectConfig,
+    Detected,
+}
+
+#[derive(Debug, Clone)]
+pub struct Toolchain {
+    pub compiler: String,
+    pub source: CompilerSource,
+    // Words after the program in CXX (e.g. CXX="ccache g++")
+    compiler_args: Vec<String>,
+    build: BuildConfig,
+    cxxflags: Vec<String>,
+    ldflags: Vec<String>,
+}
+
+impl Toolchain {
+    pub fn new(build: &BuildConfig) -> Result<Self, String> {
+        let mut compiler_args = split_flags(env_var("CXX"));
+        let (compiler, source) = if !compiler_args.is_empty() {
+            (compiler_args.remove(0), CompilerSource::Environment)
+        } else if let Some(compiler) = &build.compiler {
+            (compiler.clone(), CompilerSource::ProjectConfig)
+        } else {
+            let found = CANDIDATE_COMPILERS.iter().find(|name| which::which(name).is_ok()).ok_or_else(|| {
+                format!("no C++ compiler found (looked for {}); install one or set CXX", CANDIDATE_COMPILERS.join(", "))
+            })?;
+            (found.to_string(), CompilerSource::Detected)
+        };
+        Ok(Self {
+            compiler,
+            source,
+            compiler_args,
+            build: build.clone(),
+            cxxflags: split_flags(env_var("CXXFLAGS")),
+            ldflags: split_flags(env_var("LDFLAGS")),
+        })
+    }
+
+    // The compiler's identity and every flag, for the build cache key
+    pub fn fingerprint(&self) -> String {
+        let version = probe_compiler(&self.compiler).map(|(path, version)| format!("{} {}", path.display(), version));
+        format!("{:?} {:?} {:?} {:?} {:?}", version, self.compiler_args, self.build, self.cxxflags, self.ldflags)
+    }
+
+    // Compiles and links `source` into `output` against the runtime
+    pub fn command(&self, source: &Path, output: &Path, release: bool, runtime_include: &Path, runtime_library: &Path) -> Command {
+        let mut cmd = self.compile_command(source, output, release, runtime_include);
+        cmd.arg(runtime_library);
+        cmd.args(self.build.library_dirs.iter().map(|dir| format!("-L{}", dir)));
+        cmd.args(self.build.libraries.iter().map(|lib| format!("-l{}", lib)));
+        cmd.args(&self.ldflags).args(super::RUNTIME_SYSTEM_LIBS);
+        cmd
+    }
+
+    // Compiles `source` into the object file `output` without linking
+    pub fn object_command(&self, source: &Path, output: &Path, release: bool, runtime_include: &Path) -> Command {
+        let mut cmd = self.compile_command(source, output, release, runtime_include);
+        cmd.arg("-c");
+        cmd
+    }
+
+    fn compile_command(&self, source: &Path, output: &Path, release: bool, runtime_include: &Path) -> Command {
+        let mut cmd = Command::new(&self.compiler);
+        cmd.args(&self.compiler_args);
+        cmd.arg(format!("-std={}", self.build.standard));
+        if release {
+            cmd.arg("-O3");
+        }
+        cmd.args(&self.build.flags).args(&self.cxxflags);
+        cmd.args(self.build.defines.iter().map(|define| format!("-D{}", define)));
+        cmd.arg("-I").arg(runtime_include);
+        cmd.args(self.build.include_dirs.iter().map(|dir| format!("-I{}", dir)));
+        cmd.arg(source).arg("-o").arg(output);
+        cmd
+    }
+}
+
+// Full path and first line of `--version` output of a compiler, if it can be run
+pub fn probe_compiler(compiler: &str) -> Option<(PathBuf, String)> {
+    let path = which::which(compiler).ok()?;
+    let output = Command::new(&path).arg("--version").output().ok()?;
+    let version = String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or("").trim().to_string();
+    Some((path, version))
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+fn split_flags(flags: Option<String>) -> Vec<String> {
+    flags.map(|flags| flags.split_whitespace().map(str::to_string).collect()).unwrap_or_default()
+}
@@ -0,0 +1,85 @@
+// Workspaces: eppx-workspace.toml lists member projects, each with its own
+// codon.toml. Members share the workspace's eppx.lock, .eppx_packages/ and
+// .eppx_build/, and depend on each other with path dependencies
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::codon::config::{find_workspace_dir, is_project_dir, read_project_config, read_workspace_config, WorkspaceConfig};
+
+use super::project::Project;
+use super::CliError;
+
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub root: PathBuf,
+    pub config: WorkspaceConfig,
+}
+
+impl Workspace {
+    pub fn discover() -> Result<Self, CliError> {
+        let cwd = env::current_dir()?;
+        Self::around(&cwd)?.ok_or_else(|| {
+            CliError::Project(format!("no eppx-workspace.toml in {} or any directory above it", cwd.display()))
+        })
+    }
+
+    // The nearest workspace at or above `dir`, if there is one
+    pub fn around(dir: &Path) -> Result<Option<Self>, CliError> {
+        let Some(root) = find_workspace_dir(dir) else {
+            return Ok(None);
+        };
+        Ok(read_workspace_config(&root)?.map(|config| Self { root, config }))
+    }
+
+    // Member directories, in the order the manifest lists them
+    fn member_dirs(&self) -> Result<Vec<PathBuf>, CliError> {
+        let mut dirs = Vec::new();
+        for member in &self.config.workspace.members {
+            let Some(parent) = member.strip_suffix("/*") else {
+                dirs.push(self.root.join(member));
+                continue;
+            };
+            let mut matched: Vec<PathBuf> = fs::read_dir(self.root.join(parent))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<_, _>>()?;
+            matched.retain(|dir| is_project_dir(dir));
+            matched.sort();
+            dirs.extend(matched);
+        }
+        Ok(dirs)
+    }
+
+    pub fn members(&self) -> Result<Vec<Project>, CliError> {
+        let mut members: Vec<Project> = Vec::new();
+        for dir in self.member_dirs()? {
+            let config = read_project_config(&dir)?
+                .ok_or_else(|| CliError::Project(format!("workspace member {} has no codon.toml", dir.display())))?;
+            if let Some(other) = members.iter().find(|member| member.name() == config.project.name) {
+                return Err(CliError::Project(format!(
+                    "workspace members {} and {} are both named '{}'",
+                    other.root.display(),
+                    dir.display(),
+                    config.project.name
+                )));
+            }
+            members.push(Project { root: dir, config, workspace: Some(self.clone()) });
+        }
+        Ok(members)
+    }
+
+    pub fn member(&self, name: &str) -> Result<Project, CliError> {
+        let members = self.members()?;
+        let names = members.iter().map(|member| member.name().to_string()).collect::<Vec<_>>().join(", ");
+        members.into_iter().find(|member| member.name() == name).ok_or_else(|| {
+            CliError::Project(format!("the workspace has no member named '{}' (members: {})", name, names))
+        })
+    }
+
+    // Whether the project in `dir` is one of the members
+    pub fn contains(&self, dir: &Path) -> Result<bool, CliError> {
+        let dir = fs::canonicalize(dir)?;
+        Ok(self.member_dirs()?.iter().any(|member| fs::canonicalize(member).is_ok_and(|member| member == dir)))
+    }
+}
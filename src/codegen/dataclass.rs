@@ -0,0 +1,521 @@
+// Code generation for `@dataclass` and `enum.Enum` classes.
+//
+// Python synthesizes their methods when the class is created; here the same
+// methods are generated at compile time from the class body, which
+// `register_class` records in the class's `ClassInfo`:
+//
+//   @dataclass(order=True)          struct P {
+//   class P:                            std::string name{};
+//       name: str                       long long age{};
+//       age: int = 0          ==>       P(std::string name, long long age) : name(name), age(age) {}
+//                                       std::string __repr__() const { ... }
+//                                       bool operator==(const P& other) const { ... }
+//                                       bool operator<(const P& other) const { ... }
+//
+// Enum members become static instances of the class holding `name` and `value`.
+
+use super::{dotted_name, emit_expression_cpp, FunctionTable, SymbolTable, TypeMap};
+use crate::ast::{Argument, AstNode, Decorator, Expression, Statement, UnaryOp};
+
+#[derive(Debug, Clone)]
+pub struct DataclassOptions {
+    pub init: bool,
+    pub repr: bool,
+    pub eq: bool,
+    pub order: bool,
+    pub frozen: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct DataclassField {
+    pub name: String,
+    pub cpp_type: String,
+    pub default: Option<Expression>,         // `= value` or field(default=value)
+    pub default_factory: Option<Expression>, // field(default_factory=...)
+}
+
+#[derive(Debug, Clone)]
+pub struct DataclassInfo {
+    pub options: DataclassOptions,
+    pub fields: Vec<DataclassField>, // inherited fields first, as in Python
+    pub base: Option<(String, usize)>, // dataclass base and how many of `fields` it initializes
+    pub has_init: bool,              // the class defines its own __init__
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumInfo {
+    pub int_enum: bool,
+    pub value_type: String,
+    pub members: Vec<(String, String)>, // member name and C++ value, in definition order
+    pub aliases: Vec<(String, String)>, // alias and the member with the same value
+}
+
+const DATACLASS_DECORATORS: &[&str] = &["dataclass", "dataclasses.dataclass"];
+const UNIQUE_DECORATORS: &[&str] = &["unique", "enum.unique"];
+const ENUM_BASES: &[&str] = &["Enum", "enum.Enum"];
+const INT_ENUM_BASES: &[&str] = &["IntEnum", "enum.IntEnum"];
+
+// Decorators handled here rather than ignored
+pub(super) fn is_class_decorator(name: &str) -> bool {
+    DATACLASS_DECORATORS.contains(&name) || UNIQUE_DECORATORS.contains(&name)
+}
+
+// Enum bases have no C++ counterpart; the enum machinery is generated instead
+pub(super) fn is_enum_base(name: &str) -> bool {
+    ENUM_BASES.contains(&name) || INT_ENUM_BASES.contains(&name)
+}
+
+fn bool_option(class_name: &str, option: &str, value: &Expression) -> Result<bool, String> {
+    match value {
+        Expression::BooleanLiteral(b) => Ok(*b),
+        _ => Err(format!("@dataclass option '{}' of class '{}' must be True or False", option, class_name)),
+    }
+}
+
+fn dataclass_options(class_name: &str, decorators: &[Decorator]) -> Result<Option<DataclassOptions>, String> {
+    for decorator in decorators {
+        let (name, args) = match decorator {
+            Decorator::Simple(name) => (name, &[][..]),
+            Decorator::WithArgs(name, args) => (name, args.as_slice()),
+        };
+        if !DATACLASS_DECORATORS.contains(&name.as_str()) {
+            continue;
+        }
+        let mut options = DataclassOptions { init: true, repr: true, eq: true, order: false, frozen: false };
+        for arg in args {
+            let Argument::Keyword(option, value) = arg else {
+                return Err(format!("@dataclass() of class '{}' takes keyword arguments only", class_name));
+            };
+            let flag = bool_option(class_name, option, value)?;
+            match option.as_str() {
+                "init" => options.init = flag,
+                "repr" => options.repr = flag,
+                "eq" => options.eq = flag,
+                "order" => options.order = flag,
+                "frozen" => options.frozen = flag,
+                _ => return Err(format!("@dataclass option '{}' is not supported (class '{}')", option, class_name)),
+            }
+        }
+        if options.order && !options.eq {
+            return Err(format!("eq must be true if order is true (class '{}')", class_name));
+        }
+        return Ok(Some(options));
+    }
+    Ok(None)
+}
+
+fn is_field_call(callee: &Expression) -> bool {
+    dotted_name(callee).is_some_and(|name| name == "field" || name == "dataclasses.field")
+}
+
+// `ClassVar[...]` marks a class variable rather than an instance field
+pub(super) fn is_class_var(annotation: &Expression) -> bool {
+    let annotation = match annotation {
+        Expression::Index { object, .. } => object,
+        other => other,
+    };
+    dotted_name(annotation).is_some_and(|name| name == "ClassVar" || name == "typing.ClassVar")
+}
+
+// C++ type for a type annotation; anything without a direct counterpart is held in a variant
+pub(super) fn annotation_cpp_type(annotation: &Expression, symbol_table: &SymbolTable) -> String {
+    let (base, subscripted) = match annotation {
+        Expression::Index { object, .. } => (&**object, true),
+        other => (other, false),
+    };
+    let Some(name) = dotted_name(base) else {
+        return "eppx_variant".to_string();
+    };
+    match name.trim_start_matches("typing.") {
+        "int" if !subscripted => "long long".to_string(),
+        "float" if !subscripted => "double".to_string(),
+        "str" if !subscripted => "std::string".to_string(),
+        "bool" if !subscripted => "bool".to_string(),
+        "complex" if !subscripted => "eppx_complex".to_string(),
+        "list" | "List" | "tuple" | "Tuple" => "std::vector<eppx_variant>".to_string(),
+        "dict" | "Dict" => "eppx_dict<eppx_variant, eppx_variant>".to_string(),
+        _ => match symbol_table.get_class(&name) {
+            Some(info) if !subscripted => info.cpp_name.clone(),
+            _ => "eppx_variant".to_string(),
+        },
+    }
+}
+
+// Fields and options of a `@dataclass` class, or None for other classes
+pub(super) fn dataclass_info(
+    class_name: &str,
+    decorators: &[Decorator],
+    bases: &[String],
+    body: &[AstNode],
+    symbol_table: &SymbolTable,
+) -> Result<Option<DataclassInfo>, String> {
+    let Some(options) = dataclass_options(class_name, decorators)? else {
+        return Ok(None);
+    };
+    // Fields of the nearest dataclass base come first
+    let base = bases.iter().find_map(|base| {
+        let info = symbol_table.get_class(base.rsplit("::").next().unwrap_or(base))?;
+        let base_fields = info.dataclass.as_ref()?.fields.clone();
+        Some((base.clone(), base_fields))
+    });
+    let mut fields = base.as_ref().map(|(_, base_fields)| base_fields.clone()).unwrap_or_default();
+    let base = base.map(|(base_name, base_fields)| (base_name, base_fields.len()));
+
+    for node in body {
+        let AstNode::Statement(Statement::AnnotatedAssignment { target, annotation, value }) = node else {
+            continue;
+        };
+        let Expression::Identifier(field_name) = &**target else {
+            continue;
+        };
+        if is_class_var(annotation) {
+            continue;
+        }
+        let (default, default_factory) = match value.as_deref() {
+            Some(Expression::Call { callee, args, keywords }) if is_field_call(callee) => {
+                if !args.is_empty() {
+                    return Err(format!("field() of '{}.{}' takes keyword arguments only", class_name, field_name));
+                }
+                let mut default = None;
+                let mut default_factory = None;
+                for (keyword, keyword_value) in keywords {
+                    match keyword.as_str() {
+                        "default" => default = Some(keyword_value.clone()),
+                        "default_factory" => default_factory = Some(keyword_value.clone()),
+                        _ => return Err(format!("field() option '{}' is not supported ('{}.{}')", keyword, class_name, field_name)),
+                    }
+                }
+                if default.is_some() && default_factory.is_some() {
+                    return Err(format!("cannot specify both default and default_factory ('{}.{}')", class_name, field_name));
+                }
+                (default, default_factory)
+            }
+            Some(default) => (Some(default.clone()), None),
+            None => (None, None),
+        };
+        if let Some(Expression::ListLiteral(_) | Expression::DictLiteral(_) | Expression::SetLiteral(_)) = &default {
+            return Err(format!(
+                "mutable default for field '{}' of '{}' is not allowed: use default_factory",
+                field_name, class_name
+            ));
+        }
+        let field = DataclassField {
+            name: field_name.clone(),
+            cpp_type: annotation_cpp_type(annotation, symbol_table),
+            default,
+            default_factory,
+        };
+        // Redefining an inherited field keeps its original position
+        match fields.iter_mut().find(|existing| existing.name == *field_name) {
+            Some(existing) => *existing = field,
+            None => fields.push(field),
+        }
+    }
+
+    let mut seen_default = None;
+    for field in &fields {
+        let has_default = field.default.is_some() || field.default_factory.is_some();
+        match (&seen_default, has_default) {
+            (Some(previous), false) if options.init => {
+                return Err(format!(
+                    "non-default argument '{}' follows default argument '{}' in dataclass '{}'",
+                    field.name, previous, class_name
+                ));
+            }
+            (None, true) => seen_default = Some(field.name.clone()),
+            _ => {}
+        }
+    }
+
+    let has_init = body
+        .iter()
+        .any(|node| matches!(node, AstNode::Statement(Statement::FunctionDef { name, .. }) if name == "__init__"));
+    Ok(Some(DataclassInfo { options, fields, base, has_init }))
+}
+
+fn defines_method(body: &[AstNode], method: &str) -> bool {
+    body.iter()
+        .any(|node| matches!(node, AstNode::Statement(Statement::FunctionDef { name, .. }) if name == method))
+}
+
+// Members synthesized inside the struct of a dataclass
+pub(super) fn dataclass_members_cpp(qualified_name: &str, info: &DataclassInfo, body: &[AstNode]) -> String {
+    let name = qualified_name.rsplit("::").next().unwrap_or(qualified_name);
+    let python_name = qualified_name.replace("::", ".");
+    let own_fields = &info.fields[info.base.as_ref().map_or(0, |(_, count)| *count)..];
+    let mut out = String::new();
+
+    for field in own_fields {
+        out.push_str(&format!("    {} {}{{}};\n", field.cpp_type, field.name));
+    }
+    if info.options.frozen {
+        out.push_str("    static constexpr bool __eppx_frozen__ = true;\n");
+    }
+
+    if info.options.init && !info.has_init && !info.fields.is_empty() {
+        let params = info.fields.iter().map(|f| format!("{} {}", f.cpp_type, f.name)).collect::<Vec<_>>().join(", ");
+        let mut initializers = Vec::new();
+        if let Some((base, count)) = &info.base {
+            let base_args = info.fields[..*count].iter().map(|f| f.name.clone()).collect::<Vec<_>>().join(", ");
+            initializers.push(format!("{}({})", base, base_args));
+        }
+        initializers.extend(own_fields.iter().map(|f| format!("{}({})", f.name, f.name)));
+        let initializer_list = if initializers.is_empty() { String::new() } else { format!(" : {}", initializers.join(", ")) };
+        let post_init = if defines_method(body, "__post_init__") { " __post_init__(); " } else { "" };
+        out.push_str(&format!("    {}({}){} {{{}}}\n", name, params, initializer_list, post_init));
+    }
+
+    if info.options.repr && !defines_method(body, "__repr__") {
+        let parts = info
+            .fields
+            .iter()
+            .map(|f| format!("\"{}=\" + eppx_field_repr({})", f.name, f.name))
+            .collect::<Vec<_>>()
+            .join(" + \", \" + ");
+        let parts = if parts.is_empty() { String::new() } else { format!(" + {}", parts) };
+        out.push_str(&format!("    std::string __repr__() const {{ return std::string(\"{}(\"){} + \")\"; }}\n", python_name, parts));
+    }
+
+    let tie = |prefix: &str| {
+        let names = info.fields.iter().map(|f| format!("{}{}", prefix, f.name)).collect::<Vec<_>>().join(", ");
+        format!("std::tie({})", names)
+    };
+    if info.options.eq && !defines_method(body, "__eq__") {
+        out.push_str(&format!("    bool operator==(const {n}& other) const {{ return {} == {}; }}\n", tie(""), tie("other."), n = name));
+        out.push_str(&format!("    bool operator!=(const {}& other) const {{ return !(*this == other); }}\n", name));
+    }
+    if info.options.order {
+        out.push_str(&format!("    bool operator<(const {n}& other) const {{ return {} < {}; }}\n", tie(""), tie("other."), n = name));
+        out.push_str(&format!("    bool operator>(const {}& other) const {{ return other < *this; }}\n", name));
+        out.push_str(&format!("    bool operator<=(const {}& other) const {{ return !(other < *this); }}\n", name));
+        out.push_str(&format!("    bool operator>=(const {}& other) const {{ return !(*this < other); }}\n", name));
+    }
+    out.push_str(&stream_operator_cpp(name, body));
+    out
+}
+
+// print() uses __str__, falling back to __repr__
+fn stream_operator_cpp(name: &str, body: &[AstNode]) -> String {
+    let method = if defines_method(body, "__str__") { "__str__" } else { "__repr__" };
+    format!(
+        "    friend std::ostream& operator<<(std::ostream& os, const {n}& obj) {{ return os << const_cast<{n}&>(obj).{}(); }}\n",
+        method,
+        n = name
+    )
+}
+
+// Arguments for a call to a dataclass's synthesized __init__, with keywords and
+// defaults resolved against the fields
+pub(super) fn constructor_args_cpp(
+    class_name: &str,
+    info: &DataclassInfo,
+    args_cpp: &[String],
+    keywords: &[(String, Expression)],
+    symbol_table: &mut SymbolTable,
+    function_table: &FunctionTable,
+    type_map: &mut TypeMap,
+) -> Result<Vec<String>, String> {
+    if args_cpp.len() > info.fields.len() {
+        return Err(format!(
+            "{}.__init__() takes {} positional arguments but {} were given",
+            class_name,
+            info.fields.len(),
+            args_cpp.len()
+        ));
+    }
+    if let Some((unexpected, _)) = keywords.iter().find(|(keyword, _)| !info.fields.iter().any(|f| f.name == *keyword)) {
+        return Err(format!("{}.__init__() got an unexpected keyword argument '{}'", class_name, unexpected));
+    }
+    let mut result = Vec::new();
+    for (i, field) in info.fields.iter().enumerate() {
+        let keyword = keywords.iter().find(|(keyword, _)| *keyword == field.name);
+        let arg = match (args_cpp.get(i), keyword) {
+            (Some(_), Some(_)) => {
+                return Err(format!("{}.__init__() got multiple values for argument '{}'", class_name, field.name));
+            }
+            (Some(arg), None) => arg.clone(),
+            (None, Some((_, value))) => emit_expression_cpp(value, symbol_table, function_table, type_map)?,
+            (None, None) => match (&field.default, &field.default_factory) {
+                (Some(default), _) => emit_expression_cpp(default, symbol_table, function_table, type_map)?,
+                (_, Some(factory)) => default_factory_cpp(factory, &field.cpp_type, symbol_table, function_table, type_map)?,
+                (None, None) => {
+                    return Err(format!("{}.__init__() missing required argument: '{}'", class_name, field.name));
+                }
+            },
+        };
+        result.push(arg);
+    }
+    Ok(result)
+}
+
+fn default_factory_cpp(
+    factory: &Expression,
+    cpp_type: &str,
+    symbol_table: &mut SymbolTable,
+    function_table: &FunctionTable,
+    type_map: &mut TypeMap,
+) -> Result<String, String> {
+    match dotted_name(factory).as_deref() {
+        // Builtin container types produce an empty value of the field's type
+        Some("list") | Some("dict") | Some("tuple") => Ok(format!("{}{{}}", cpp_type)),
+        _ => {
+            let call = Expression::Call { callee: Box::new(factory.clone()), args: Vec::new(), keywords: Vec::new() };
+            emit_expression_cpp(&call, symbol_table, function_table, type_map)
+        }
+    }
+}
+
+// Enum members and their values for a class deriving from Enum or IntEnum
+pub(super) fn enum_info(class_name: &str, bases: &[Expression], decorators: &[Decorator], body: &[AstNode]) -> Result<Option<EnumInfo>, String> {
+    let base_names: Vec<String> = bases.iter().filter_map(dotted_name).collect();
+    let int_enum = base_names.iter().any(|b| INT_ENUM_BASES.contains(&b.as_str()));
+    if !int_enum && !base_names.iter().any(|b| ENUM_BASES.contains(&b.as_str())) {
+        return Ok(None);
+    }
+
+    // (name, value expression for comparisons, C++ value)
+    let mut values: Vec<(String, Expression, String)> = Vec::new();
+    let mut last_int = 0i64;
+    let mut kinds = Vec::new();
+    for node in body {
+        let AstNode::Statement(Statement::Assignment { target, value, .. }) = node else {
+            continue;
+        };
+        let Expression::Identifier(member) = &**target else {
+            continue;
+        };
+        let value = match &**value {
+            Expression::UnaryOperation { op: UnaryOp::Negate, operand } => match &**operand {
+                Expression::IntegerLiteral(i) => Expression::IntegerLiteral(-i),
+                Expression::FloatLiteral(f) => Expression::FloatLiteral(-f),
+                other => other.clone(),
+            },
+            Expression::Call { callee, args, .. } if args.is_empty() && dotted_name(callee).is_some_and(|n| n == "auto" || n == "enum.auto") => {
+                Expression::IntegerLiteral(last_int + 1)
+            }
+            other => other.clone(),
+        };
+        let value_cpp = match &value {
+            Expression::IntegerLiteral(i) => {
+                last_int = *i;
+                kinds.push("long long");
+                format!("{}LL", i)
+            }
+            Expression::FloatLiteral(f) => {
+                kinds.push("double");
+                format!("{:?}", f)
+            }
+            Expression::StringLiteral(text) => {
+                kinds.push("std::string");
+                format!("std::string({:?})", text)
+            }
+            Expression::BooleanLiteral(b) => {
+                kinds.push("bool");
+                b.to_string()
+            }
+            _ => return Err(format!("unsupported value for enum member '{}.{}'", class_name, member)),
+        };
+        values.push((member.clone(), value, value_cpp));
+    }
+
+    let value_type = match kinds.first() {
+        Some(first) if kinds.iter().all(|kind| kind == first) => first.to_string(),
+        Some(_) if kinds.iter().all(|kind| *kind == "long long" || *kind == "double") => "double".to_string(),
+        _ => "eppx_variant".to_string(),
+    };
+    if int_enum && value_type != "long long" {
+        return Err(format!("IntEnum '{}' members must be integers", class_name));
+    }
+
+    // A member whose value repeats an earlier one is an alias for it
+    let mut members: Vec<(String, String)> = Vec::new();
+    let mut aliases = Vec::new();
+    for (i, (member, value, value_cpp)) in values.iter().enumerate() {
+        match values[..i].iter().find(|(_, earlier, _)| earlier == value) {
+            Some((canonical, _, _)) if members.iter().any(|(name, _)| name == canonical) => aliases.push((member.clone(), canonical.clone())),
+            _ => members.push((member.clone(), value_cpp.clone())),
+        }
+    }
+    let unique = decorators.iter().any(|d| {
+        let (Decorator::Simple(name) | Decorator::WithArgs(name, _)) = d;
+        UNIQUE_DECORATORS.contains(&name.as_str())
+    });
+    if unique && !aliases.is_empty() {
+        let found = aliases.iter().map(|(alias, canonical)| format!("{} -> {}", alias, canonical)).collect::<Vec<_>>().join(", ");
+        return Err(format!("duplicate values found in <enum '{}'>: {}", class_name, found));
+    }
+    Ok(Some(EnumInfo { int_enum, value_type, members, aliases }))
+}
+
+// Members synthesized inside the struct of an enum, plus the definitions of its
+// member instances that follow the outermost class
+pub(super) fn enum_members_cpp(qualified_name: &str, info: &EnumInfo, body: &[AstNode], static_defs: &mut Vec<String>) -> String {
+    let name = qualified_name.rsplit("::").next().unwrap_or(qualified_name);
+    let python_name = qualified_name.replace("::", ".");
+    let value_type = &info.value_type;
+    let mut out = String::new();
+    out.push_str("    std::string name;\n");
+    out.push_str(&format!("    {} value{{}};\n", value_type));
+    out.push_str(&format!(
+        "    {}(std::string member_name, {} member_value) : name(std::move(member_name)), value(std::move(member_value)) {{}}\n",
+        name, value_type
+    ));
+    for (member, _) in info.members.iter().chain(info.aliases.iter()) {
+        out.push_str(&format!("    static {} {};\n", name, member));
+    }
+    let member_list = info.members.iter().map(|(member, _)| member.clone()).collect::<Vec<_>>().join(", ");
+    out.push_str(&format!(
+        "    static const std::vector<{n}>& __eppx_members__() {{ static const std::vector<{n}> members{{{}}}; return members; }}\n",
+        member_list,
+        n = name
+    ));
+    // Color(1) looks a member up by value, Color["RED"] by name
+    out.push_str("    template<typename V>\n");
+    out.push_str(&format!("    static {} __eppx_lookup__(const V& member_value) {{\n", name));
+    out.push_str("        for (const auto& member : __eppx_members__()) {\n");
+    out.push_str("            if (eppx_match_eq(member.value, member_value)) return member;\n");
+    out.push_str("        }\n");
+    out.push_str(&format!("        throw ValueErrorException(eppx_field_repr(member_value) + \" is not a valid {}\");\n", python_name));
+    out.push_str("    }\n");
+    out.push_str(&format!("    static {} __eppx_lookup_name__(const std::string& member_name) {{\n", name));
+    out.push_str("        for (const auto& member : __eppx_members__()) {\n");
+    out.push_str("            if (member.name == member_name) return member;\n");
+    out.push_str("        }\n");
+    for (alias, canonical) in &info.aliases {
+        out.push_str(&format!("        if (member_name == \"{}\") return {};\n", alias, canonical));
+    }
+    out.push_str("        throw KeyErrorException(eppx_field_repr(member_name));\n");
+    out.push_str("    }\n");
+    out.push_str(&format!("    bool operator==(const {}& other) const {{ return name == other.name; }}\n", name));
+    out.push_str(&format!("    bool operator!=(const {}& other) const {{ return name != other.name; }}\n", name));
+    if !defines_method(body, "__repr__") {
+        out.push_str(&format!(
+            "    std::string __repr__() const {{ return \"<{}.\" + name + \": \" + eppx_field_repr(value) + \">\"; }}\n",
+            python_name
+        ));
+    }
+    if info.int_enum {
+        // IntEnum members are ints: they compare, sort and print as their value
+        out.push_str("    operator long long() const { return value; }\n");
+        for op in ["<", ">", "<=", ">="] {
+            out.push_str(&format!("    bool operator{op}(const {n}& other) const {{ return value {op} other.value; }}\n", op = op, n = name));
+        }
+        if !defines_method(body, "__str__") {
+            out.push_str("    std::string __str__() const { return std::to_string(value); }\n");
+        }
+    } else if !defines_method(body, "__str__") {
+        out.push_str(&format!("    std::string __str__() const {{ return \"{}.\" + name; }}\n", python_name));
+    }
+    out.push_str(&format!(
+        "    friend std::ostream& operator<<(std::ostream& os, const {n}& member) {{ return os << const_cast<{n}&>(member).__str__(); }}\n",
+        n = name
+    ));
+
+    for (member, value_cpp) in &info.members {
+        static_defs.push(format!("{q} {q}::{m}{{\"{m}\", {v}}};\n", q = qualified_name, m = member, v = value_cpp));
+    }
+    for (alias, canonical) in &info.aliases {
+        static_defs.push(format!("{q} {q}::{}{{{q}::{}}};\n", alias, canonical, q = qualified_name));
+    }
+    out
+}
@@ -0,0 +1,1064 @@
+// Generator lowering: compiles a function containing `yield` into a resumable
+// C++ state machine (a subclass of eppx_generator_frame from eppx_generator.hpp).
+//
+// The body is split into numbered blocks. A block ends either by jumping to
+// another block (`_state = N; continue;`) or by suspending at a yield
+// (`return true;`), and resume() dispatches on `_state`. Locals and loop
+// iterators are hoisted into the frame so they survive suspension. Exceptions
+// are routed through a table mapping each block to the block that handles
+// exceptions raised inside it (an except dispatcher or a finally entry), so
+// yields inside try/except/finally stay resumable.
+//
+// `async def` functions use the same frame: `await x` delegates to x's frame
+// like `yield from`, and suspensions caused by an await are flagged with
+// `_awaiting` so async generators can tell them apart from yielded values.
+
+use super::{
+    any_subexpression, chained_assignments, emit_expression_cpp, emit_handle_cpp, emits_lambda, generate_statement_list_cpp, holds_ref,
+    is_new_shared_object, is_user_instance, named_expr_targets, statement_expressions, FunctionTable, SymbolTable, TypeMap, FRAME_SELF_TYPE,
+    INSTANCE_TYPE, SHARED_TYPE,
+};
+use crate::ast::{AssignmentOperator, AstNode, ExceptHandler, Expression, Statement};
+use std::collections::HashSet;
+
+// Block 0 is the entry point; block 1 finishes the generator (StopIteration)
+const ENTRY_BLOCK: usize = 0;
+const FINISH_BLOCK: usize = 1;
+
+struct Block {
+    code: String,
+    handler: Option<usize>, // block that receives exceptions raised in this one
+}
+
+struct LoopLabels {
+    break_to: usize,
+    continue_to: usize,
+    finally_depth: usize, // number of enclosing try/finally regions at the loop
+}
+
+struct FinallyRegion {
+    id: usize,
+    entry: usize,
+}
+
+struct GeneratorLowering<'a> {
+    blocks: Vec<Block>,
+    current: usize,
+    handlers: Vec<usize>,
+    loops: Vec<LoopLabels>,
+    finally_regions: Vec<FinallyRegion>,
+    members: Vec<String>,
+    declared_vars: HashSet<String>,
+    next_id: usize,
+    is_async: bool,
+    symbol_table: &'a mut SymbolTable,
+    function_table: &'a mut FunctionTable,
+    type_map: &'a mut TypeMap,
+}
+
+impl<'a> GeneratorLowering<'a> {
+    fn new_block(&mut self) -> usize {
+        let handler = self.handlers.last().copied();
+        self.blocks.push(Block { code: String::new(), handler });
+        self.blocks.len() - 1
+    }
+
+    fn fresh_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    fn line(&mut self, code: &str) {
+        for l in code.lines() {
+            let block = &mut self.blocks[self.current].code;
+            block.push_str("                    ");
+            block.push_str(l.trim_start());
+            block.push('\n');
+        }
+    }
+
+    fn jump(&mut self, target: usize) {
+        self.line(&format!("_state = {}; continue;", target));
+    }
+
+    // Ends the current block with a jump and continues emitting into `next`
+    fn jump_and_start(&mut self, target: usize, next: usize) {
+        self.jump(target);
+        self.current = next;
+    }
+
+    fn expr(&mut self, expr: &Expression) -> Result<String, String> {
+        emit_expression_cpp(expr, self.symbol_table, self.function_table, self.type_map)
+    }
+
+    // A yielded or returned value as a variant; a list, dict, set or instance
+    // local is passed on by handle, so the receiver shares the object
+    fn variant_expr(&mut self, expr: &Expression) -> Result<String, String> {
+        let value_cpp = emit_handle_cpp(expr, self.symbol_table, self.function_table, self.type_map)?;
+        Ok(format!("eppx_to_variant({})", value_cpp))
+    }
+
+    fn lower_body(&mut self, body: &[AstNode]) -> Result<(), String> {
+        for node in body {
+            self.lower_statement(node)?;
+        }
+        Ok(())
+    }
+
+    fn lower_statement(&mut self, node: &AstNode) -> Result<(), String> {
+        let AstNode::Statement(stmt) = node;
+        if let Statement::AnnotatedAssignment { target, value, .. } = stmt {
+            // Only the assignment part has a runtime effect
+            let Some(value) = value else { return Ok(()) };
+            let assignment = Statement::Assignment { target: target.clone(), operator: AssignmentOperator::Assign, value: value.clone() };
+            return self.lower_statement(&AstNode::Statement(assignment));
+        }
+        if self.is_async {
+            if let Some(stmt) = self.hoist_statement_awaits(stmt)? {
+                return self.lower_statement(&AstNode::Statement(stmt));
+            }
+        }
+        match stmt {
+            Statement::Yield(value) => {
+                let value_cpp = match value {
+                    Some(value) => self.variant_expr(value)?,
+                    None => "nullptr".to_string(),
+                };
+                self.suspend(&value_cpp);
+            }
+            Statement::ExpressionStatement(expr) if matches!(**expr, Expression::YieldFrom(_)) => {
+                if let Expression::YieldFrom(iterable) = &**expr {
+                    self.yield_from(iterable)?;
+                }
+            }
+            Statement::Assignment { target, operator: AssignmentOperator::Assign, value }
+                if matches!(**value, Expression::Yield(_) | Expression::YieldFrom(_)) =>
+            {
+                let result = match &**value {
+                    Expression::Yield(yielded) => {
+                        let value_cpp = match yielded {
+                            Some(yielded) => self.variant_expr(yielded)?,
+                            None => "nullptr".to_string(),
+                        };
+                        self.suspend(&value_cpp);
+                        "_sent".to_string()
+                    }
+                    Expression::YieldFrom(iterable) => self.yield_from(iterable)?,
+                    _ => unreachable!(),
+                };
+                self.assign(target, &AssignmentOperator::Assign, &result)?;
+            }
+            Statement::Assignment { target, operator, value } if self.is_local(target) => {
+                // A local that holds a handle is rebound to the value's handle (see holds_ref)
+                let value_cpp = match &**target {
+                    Expression::Identifier(name)
+                        if *operator == AssignmentOperator::Assign && self.symbol_table.get_variable(name).is_some_and(holds_ref) =>
+                    {
+                        emit_handle_cpp(value, self.symbol_table, self.function_table, self.type_map)?
+                    }
+                    _ => self.expr(value)?,
+                };
+                self.assign(target, operator, &value_cpp)?;
+            }
+            Statement::If { condition, then_body, elifs, else_body } => {
+                let end = self.new_block();
+                let mut branches = vec![(&**condition, then_body)];
+                branches.extend(elifs.iter().map(|(cond, body)| (cond, body)));
+                for (cond, body) in branches {
+                    let cond = self.hoist_awaits(cond)?;
+                    let cond_cpp = self.expr(&cond)?;
+                    let then_block = self.new_block();
+                    let next = self.new_block();
+                    self.line(&format!("if (eppx_truthy({})) {{ _state = {}; continue; }}", cond_cpp, then_block));
+                    self.jump_and_start(next, then_block);
+                    self.lower_body(body)?;
+                    self.jump_and_start(end, next);
+                }
+                if let Some(else_body) = else_body {
+                    self.lower_body(else_body)?;
+                }
+                self.jump_and_start(end, end);
+            }
+            Statement::While { condition, body, else_body } => {
+                let head = self.new_block();
+                let body_block = self.new_block();
+                let exit = self.new_block();
+                let exhausted = self.loop_exhausted_block(else_body, exit);
+                self.jump_and_start(head, head);
+                let condition = self.hoist_awaits(condition)?;
+                let cond_cpp = self.expr(&condition)?;
+                self.line(&format!("if (!eppx_truthy({})) {{ _state = {}; continue; }}", cond_cpp, exhausted));
+                self.jump_and_start(body_block, body_block);
+                self.lower_loop_body(body, exit, head)?;
+                self.finish_loop(head, exhausted, exit, else_body)?;
+            }
+            Statement::For { vars, iterable, body, else_body, is_async: true } => {
+                if !self.is_async {
+                    return Err("'async for' outside async function".to_string());
+                }
+                let aiter_name = format!("_aiter_{}", self.fresh_id());
+                self.members.push(format!("eppx_async_iterator {};", aiter_name));
+                let iterable = self.hoist_awaits(iterable)?;
+                let iterable_cpp = self.expr(&iterable)?;
+                self.line(&format!("{} = eppx_async_iterator({});", aiter_name, iterable_cpp));
+                let head = self.new_block();
+                let body_block = self.new_block();
+                let exit = self.new_block();
+                let exhausted = self.loop_exhausted_block(else_body, exit);
+                self.jump_and_start(head, head);
+                // Each iteration awaits __anext__(); exhaustion ends the loop
+                let item = self.delegate(&format!("{}.anext()", aiter_name), true);
+                self.line(&format!("if ({}.exhausted()) {{ _state = {}; continue; }}", aiter_name, exhausted));
+                if vars.len() == 1 {
+                    self.line(&format!("eppx_store({}, {});", vars[0], item));
+                } else {
+                    for (i, var) in vars.iter().enumerate() {
+                        self.line(&format!("eppx_store({}, eppx_unpack<{}>({}));", var, i, item));
+                    }
+                }
+                self.jump_and_start(body_block, body_block);
+                self.lower_loop_body(body, exit, head)?;
+                self.finish_loop(head, exhausted, exit, else_body)?;
+            }
+            Statement::For { vars, iterable, body, else_body, .. } => {
+                let iter_name = format!("_iter_{}", self.fresh_id());
+                self.members.push(format!("eppx_iterator {};", iter_name));
+                let iterable = self.hoist_awaits(iterable)?;
+                let iterable_cpp = self.expr(&iterable)?;
+                self.line(&format!("{} = eppx_iterator({});", iter_name, iterable_cpp));
+                let head = self.new_block();
+                let body_block = self.new_block();
+                let exit = self.new_block();
+                let exhausted = self.loop_exhausted_block(else_body, exit);
+                self.jump_and_start(head, head);
+                if vars.len() == 1 {
+                    self.line(&format!("if (!{}.next({})) {{ _state = {}; continue; }}", iter_name, vars[0], exhausted));
+                } else {
+                    self.line("eppx_variant __eppx_item;");
+                    self.line(&format!("if (!{}.next(__eppx_item)) {{ _state = {}; continue; }}", iter_name, exhausted));
+                    for (i, var) in vars.iter().enumerate() {
+                        self.line(&format!("{} = eppx_unpack<{}>(__eppx_item);", var, i));
+                    }
+                }
+                self.jump_and_start(body_block, body_block);
+                self.lower_loop_body(body, exit, head)?;
+                self.finish_loop(head, exhausted, exit, else_body)?;
+            }
+            Statement::ChainedAssignment { targets, value } => {
+                for assignment in chained_assignments(targets, value) {
+                    self.lower_statement(&assignment)?;
+                }
+            }
+            Statement::Break => {
+                let labels = self.loops.last().ok_or("'break' outside loop")?;
+                let (target, depth) = (labels.break_to, labels.finally_depth);
+                self.jump_out(target, depth);
+            }
+            Statement::Continue => {
+                let labels = self.loops.last().ok_or("'continue' not properly in loop")?;
+                let (target, depth) = (labels.continue_to, labels.finally_depth);
+                self.jump_out(target, depth);
+            }
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    let value_cpp = self.variant_expr(value)?;
+                    self.line(&format!("_return_value = {};", value_cpp));
+                }
+                self.jump_out(FINISH_BLOCK, 0);
+            }
+            Statement::TryExcept { try_body, excepts, else_body, finally_body } => {
+                self.lower_try(try_body, excepts, else_body.as_deref(), finally_body.as_deref())?;
+            }
+            Statement::With { body, .. } if super::contains_yield(body) => {
+                return Err("yield inside a 'with' block is not supported in generator functions".to_string());
+            }
+            Statement::Match { cases, .. } if cases.iter().any(|case| super::contains_yield(&case.body)) => {
+                return Err("yield inside a 'match' statement is not supported in generator functions".to_string());
+            }
+            Statement::Raise(Some(exception)) => {
+                let exception_cpp = emit_exception_ptr_cpp(exception, self.symbol_table, self.function_table, self.type_map)?;
+                self.line(&format!("std::rethrow_exception({});", exception_cpp));
+            }
+            Statement::Raise(None) => {
+                // Re-raise the exception being handled
+                self.line("std::rethrow_exception(_exception);");
+            }
+            Statement::FunctionDef { name, .. } | Statement::ClassDef { name, .. } => {
+                return Err(format!("nested definition of '{}' is not supported inside generator functions", name));
+            }
+            _ => {
+                // Straight-line statement: reuse the regular statement codegen. Locals are
+                // already declared as frame members, so nothing gets redeclared here.
+                let code = generate_statement_list_cpp(
+                    std::slice::from_ref(node),
+                    &mut self.declared_vars,
+                    self.symbol_table,
+                    self.function_table,
+                    self.type_map,
+                )?;
+                self.line(&code);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_local(&self, target: &Expression) -> bool {
+        matches!(target, Expression::Identifier(name) if self.declared_vars.contains(name))
+    }
+
+    fn assign(&mut self, target: &Expression, operator: &AssignmentOperator, value_cpp: &str) -> Result<(), String> {
+        let target_cpp = self.expr(target)?;
+        if let Expression::Identifier(name) = target {
+            // The member itself, not the object a handle member refers to
+            let target_cpp = name;
+            // eppx_store converts the value when the member is an eppx_variant
+            let op = match operator {
+                AssignmentOperator::Assign => None,
+                AssignmentOperator::AddAssign => Some("+"),
+                AssignmentOperator::SubAssign => Some("-"),
+                AssignmentOperator::MulAssign => Some("*"),
+                AssignmentOperator::DivAssign => Some("/"),
+                AssignmentOperator::ModAssign => Some("%"),
+                _ => return Err(format!("Unsupported augmented assignment in generator function: {:?}", operator)),
+            };
+            match op {
+                None => self.line(&format!("eppx_store({}, {});", target_cpp, value_cpp)),
+                Some(op) => self.line(&format!("eppx_store({}, {} {} eppx_to_variant({}));", target_cpp, target_cpp, op, value_cpp)),
+            }
+        } else if let (Expression::Index { object, index }, AssignmentOperator::Assign) = (target, operator) {
+            let object_cpp = self.expr(object)?;
+            let index_cpp = self.expr(index)?;
+            self.line(&format!("eppx_setitem({}, {}, {});", object_cpp, index_cpp, value_cpp));
+        } else if let AssignmentOperator::Assign = operator {
+            self.line(&format!("{} = {};", target_cpp, value_cpp));
+        } else {
+            return Err("Unsupported augmented assignment target in generator function".to_string());
+        }
+        Ok(())
+    }
+
+    // Where a loop goes when its condition fails or its iterator is exhausted:
+    // the `else` block if it has one (`break` skips it by going to `exit`)
+    fn loop_exhausted_block(&mut self, else_body: &Option<Vec<AstNode>>, exit: usize) -> usize {
+        if else_body.is_some() { self.new_block() } else { exit }
+    }
+
+    fn finish_loop(&mut self, head: usize, exhausted: usize, exit: usize, else_body: &Option<Vec<AstNode>>) -> Result<(), String> {
+        match else_body {
+            Some(else_body) => {
+                self.jump_and_start(head, exhausted);
+                self.lower_body(else_body)?;
+                self.jump_and_start(exit, exit);
+            }
+            None => self.jump_and_start(head, exit),
+        }
+        Ok(())
+    }
+
+    fn lower_loop_body(&mut self, body: &[AstNode], break_to: usize, continue_to: usize) -> Result<(), String> {
+        self.loops.push(LoopLabels { break_to, continue_to, finally_depth: self.finally_regions.len() });
+        let result = self.lower_body(body);
+        self.loops.pop();
+        result
+    }
+
+    // Suspends with `value_cpp` as the yielded value; emission continues in the
+    // block that runs when the generator is resumed
+    fn suspend(&mut self, value_cpp: &str) {
+        let resume = self.new_block();
+        if self.is_async {
+            self.line("_awaiting = false;");
+        }
+        self.line(&format!("_current = {};", value_cpp));
+        self.line(&format!("_state = {}; return true;", resume));
+        self.current = resume;
+        self.line("_raise_thrown();");
+    }
+
+    // Lowers `yield from iterable` and returns the C++ expression for its value
+    fn yield_from(&mut self, iterable: &Expression) -> Result<String, String> {
+        let iterable_cpp = self.expr(iterable)?;
+        Ok(self.delegate(&iterable_cpp, false))
+    }
+
+    // Lowers `await awaitable` into a frame member holding its result
+    fn await_value(&mut self, awaitable: &Expression) -> Result<String, String> {
+        let awaitable_cpp = self.expr(awaitable)?;
+        let result = self.delegate(&format!("eppx_awaitable({})", awaitable_cpp), true);
+        let temp = format!("__eppx_await_{}", self.fresh_id());
+        self.members.push(format!("eppx_variant {};", temp));
+        self.symbol_table.add_variable(&temp, "eppx_variant");
+        self.declared_vars.insert(temp.clone());
+        self.line(&format!("{} = {};", temp, result));
+        Ok(temp)
+    }
+
+    // Evaluates the awaits inside `expr` ahead of the rest of it, left to right,
+    // and returns `expr` with each await replaced by its result. Both operands
+    // of `and`/`or`, both branches of `x if c else y` and every operand of a
+    // comparison chain are awaited, even when short-circuiting would skip one.
+    fn hoist_awaits(&mut self, expr: &Expression) -> Result<Expression, String> {
+        if !self.is_async || !contains_await(expr) {
+            return Ok(expr.clone());
+        }
+        let hoist_all = |lowering: &mut Self, exprs: &[Expression]| -> Result<Vec<Expression>, String> {
+            exprs.iter().map(|e| lowering.hoist_awaits(e)).collect()
+        };
+        Ok(match expr {
+            Expression::Await(awaitable) => {
+                let awaitable = self.hoist_awaits(awaitable)?;
+                Expression::Identifier(self.await_value(&awaitable)?)
+            }
+            Expression::BinaryOperation { left, op, right } => Expression::BinaryOperation {
+                left: Box::new(self.hoist_awaits(left)?),
+                op: *op,
+                right: Box::new(self.hoist_awaits(right)?),
+            },
+            Expression::UnaryOperation { op, operand } => {
+                Expression::UnaryOperation { op: *op, operand: Box::new(self.hoist_awaits(operand)?) }
+            }
+            Expression::Compare { left, ops, comparators } => Expression::Compare {
+                left: Box::new(self.hoist_awaits(left)?),
+                ops: ops.clone(),
+                comparators: hoist_all(self, comparators)?,
+            },
+            Expression::IfExp { test, body, orelse } => Expression::IfExp {
+                test: Box::new(self.hoist_awaits(test)?),
+                body: Box::new(self.hoist_awaits(body)?),
+                orelse: Box::new(self.hoist_awaits(orelse)?),
+            },
+            Expression::NamedExpr { target, value } => {
+                Expression::NamedExpr { target: target.clone(), value: Box::new(self.hoist_awaits(value)?) }
+            }
+            Expression::Call { callee, args, keywords } => Expression::Call {
+                callee: Box::new(self.hoist_awaits(callee)?),
+                args: hoist_all(self, args)?,
+                keywords: keywords
+                    .iter()
+                    .map(|(name, value)| Ok((name.clone(), self.hoist_awaits(value)?)))
+                    .collect::<Result<Vec<_>, String>>()?,
+            },
+            Expression::AttributeAccess { object, attr } => {
+                Expression::AttributeAccess { object: Box::new(self.hoist_awaits(object)?), attr: attr.clone() }
+            }
+            Expression::Index { object, index } => Expression::Index {
+                object: Box::new(self.hoist_awaits(object)?),
+                index: Box::new(self.hoist_awaits(index)?),
+            },
+            Expression::ListLiteral(items) => Expression::ListLiteral(hoist_all(self, items)?),
+            Expression::TupleLiteral(items) => Expression::TupleLiteral(hoist_all(self, items)?),
+            Expression::SetLiteral(items) => Expression::SetLiteral(hoist_all(self, items)?),
+            Expression::DictLiteral(entries) => Expression::DictLiteral(
+                entries
+                    .iter()
+                    .map(|(k, v)| Ok((self.hoist_awaits(k)?, self.hoist_awaits(v)?)))
+                    .collect::<Result<Vec<_>, String>>()?,
+            ),
+            // Awaits inside lambdas and comprehensions are left alone and rejected by codegen
+            other => other.clone(),
+        })
+    }
+
+    // Straight-line statements containing awaits: emit the awaits, then
+    // return the statement to lower in their place
+    fn hoist_statement_awaits(&mut self, stmt: &Statement) -> Result<Option<Statement>, String> {
+        Ok(Some(match stmt {
+            Statement::ExpressionStatement(expr) if contains_await(expr) => {
+                if let Expression::Await(awaitable) = &**expr {
+                    let awaitable = self.hoist_awaits(awaitable)?;
+                    self.await_value(&awaitable)?;
+                    Statement::Pass
+                } else {
+                    Statement::ExpressionStatement(Box::new(self.hoist_awaits(expr)?))
+                }
+            }
+            Statement::Assignment { target, operator, value } if contains_await(value) => Statement::Assignment {
+                target: target.clone(),
+                operator: operator.clone(),
+                value: Box::new(self.hoist_awaits(value)?),
+            },
+            Statement::Print(expr) if contains_await(expr) => Statement::Print(Box::new(self.hoist_awaits(expr)?)),
+            Statement::Return(Some(expr)) if contains_await(expr) => {
+                Statement::Return(Some(Box::new(self.hoist_awaits(expr)?)))
+            }
+            Statement::Raise(Some(expr)) if contains_await(expr) => Statement::Raise(Some(self.hoist_awaits(expr)?)),
+            Statement::ChainedAssignment { targets, value } if contains_await(value) => Statement::ChainedAssignment {
+                targets: targets.clone(),
+                value: Box::new(self.hoist_awaits(value)?),
+            },
+            Statement::Assert { test, msg } if contains_await(test) || msg.as_deref().is_some_and(contains_await) => {
+                Statement::Assert {
+                    test: Box::new(self.hoist_awaits(test)?),
+                    msg: msg.as_deref().map(|msg| self.hoist_awaits(msg)).transpose()?.map(Box::new),
+                }
+            }
+            Statement::Yield(Some(expr)) if contains_await(expr) => {
+                Statement::Yield(Some(Box::new(self.hoist_awaits(expr)?)))
+            }
+            _ => return Ok(None),
+        }))
+    }
+
+    // Delegates to the generator, coroutine or iterable built by `source_cpp`
+    // until it is exhausted, re-suspending this frame whenever it suspends;
+    // returns the C++ expression for its return value
+    fn delegate(&mut self, source_cpp: &str, awaiting: bool) -> String {
+        let delegate = format!("_yield_from_{}", self.fresh_id());
+        self.members.push(format!("eppx_yield_from {};", delegate));
+        self.line(&format!("{} = eppx_yield_from({});", delegate, source_cpp));
+        self.line("_sent = nullptr;");
+        let step = self.new_block();
+        let resume = self.new_block();
+        let after = self.new_block();
+        let flag = if self.is_async { format!("_awaiting = {}; ", awaiting) } else { String::new() };
+        self.jump_and_start(step, step);
+        self.line("eppx_variant __eppx_value;");
+        self.line(&format!(
+            "if ({}.step(_sent, __eppx_value)) {{ {}_current = __eppx_value; _state = {}; return true; }}",
+            delegate, flag, resume
+        ));
+        self.jump_and_start(after, resume);
+        // throw()/close() while delegating are forwarded to the sub-generator
+        self.line("if (_thrown) {");
+        self.line("std::exception_ptr __eppx_exc = _thrown;");
+        self.line("_thrown = nullptr;");
+        self.line("eppx_variant __eppx_value;");
+        self.line(&format!(
+            "if ({}.throw_into(__eppx_exc, __eppx_value)) {{ {}_current = __eppx_value; _state = {}; return true; }}",
+            delegate, flag, resume
+        ));
+        self.line(&format!("_state = {}; continue;", after));
+        self.line("}");
+        self.jump_and_start(step, after);
+        format!("{}.result", delegate)
+    }
+
+    // break/continue/return: leaving try/finally regions runs their finally
+    // blocks first, innermost to outermost
+    fn jump_out(&mut self, target: usize, target_depth: usize) {
+        match self.finally_regions.last() {
+            Some(region) if self.finally_regions.len() > target_depth => {
+                let (id, entry) = (region.id, region.entry);
+                self.line(&format!(
+                    "_fin{id}_kind = 1; _fin{id}_target = {}; _fin{id}_depth = {};",
+                    target, target_depth, id = id
+                ));
+                self.jump(entry);
+            }
+            _ => self.jump(target),
+        }
+        // Anything after the jump is unreachable
+        self.current = self.new_block();
+    }
+
+    fn lower_try(
+        &mut self,
+        try_body: &[AstNode],
+        excepts: &[ExceptHandler],
+        else_body: Option<&[AstNode]>,
+        finally_body: Option<&[AstNode]>,
+    ) -> Result<(), String> {
+        let after = self.new_block();
+        // finally: `_fin<id>_kind` records how the protected region was left
+        // (0 = fell through, 1 = jump, 2 = exception)
+        let finally = if finally_body.is_some() {
+            let id = self.fresh_id();
+            self.members.push(format!("int _fin{}_kind = 0;", id));
+            self.members.push(format!("int _fin{}_target = 0;", id));
+            self.members.push(format!("size_t _fin{}_depth = 0;", id));
+            self.members.push(format!("std::exception_ptr _fin{}_exc;", id));
+            let entry = self.new_block();
+            let on_exception = self.new_block();
+            self.handlers.push(on_exception);
+            self.finally_regions.push(FinallyRegion { id, entry });
+            Some((id, entry, on_exception))
+        } else {
+            None
+        };
+
+        let dispatch = if excepts.is_empty() { None } else { Some(self.new_block()) };
+        let handler_blocks: Vec<usize> = excepts.iter().map(|_| self.new_block()).collect();
+        let else_block = else_body.map(|_| self.new_block());
+        let completed = self.new_block();
+
+        if let Some(dispatch) = dispatch {
+            self.handlers.push(dispatch);
+        }
+        let body_block = self.new_block();
+        self.jump_and_start(body_block, body_block);
+        self.lower_body(try_body)?;
+        if dispatch.is_some() {
+            self.handlers.pop();
+        }
+        match (else_block, else_body) {
+            (Some(else_block), Some(else_body)) => {
+                self.jump_and_start(else_block, else_block);
+                self.lower_body(else_body)?;
+                self.jump(completed);
+            }
+            _ => self.jump(completed),
+        }
+
+        if let Some(dispatch) = dispatch {
+            self.current = dispatch;
+            for (handler, block) in excepts.iter().zip(&handler_blocks) {
+                let matches = match &handler.exception_type {
+                    None => "true".to_string(),
+                    Some(Expression::Identifier(type_name)) | Some(Expression::AttributeAccess { attr: type_name, .. }) => {
+                        format!("eppx_exception_matches(_exception, \"{}\")", type_name)
+                    }
+                    Some(Expression::TupleLiteral(types)) => types
+                        .iter()
+                        .map(|t| match t {
+                            Expression::Identifier(type_name) => {
+                                Ok(format!("eppx_exception_matches(_exception, \"{}\")", type_name))
+                            }
+                            _ => Err("except clause expects exception class names".to_string()),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?
+                        .join(" || "),
+                    Some(_) => return Err("except clause expects exception class names".to_string()),
+                };
+                let bind = match (&handler.name, &handler.exception_type) {
+                    (Some(name), Some(Expression::Identifier(type_name))) if type_name == "StopIteration" => {
+                        format!("{} = eppx_stop_iteration(_exception); ", name)
+                    }
+                    (Some(name), _) => format!("{} = eppx_exception_message(_exception); ", name),
+                    (None, _) => String::new(),
+                };
+                self.line(&format!("if ({}) {{ {}_state = {}; continue; }}", matches, bind, block));
+            }
+            self.line("std::rethrow_exception(_exception);");
+            for (handler, block) in excepts.iter().zip(&handler_blocks) {
+                self.current = *block;
+                self.lower_body(&handler.body)?;
+                self.jump(completed);
+            }
+        }
+
+        self.current = completed;
+        match (finally, finally_body) {
+            (Some((id, entry, on_exception)), Some(finally_body)) => {
+                self.line(&format!("_fin{}_kind = 0;", id));
+                self.jump(entry);
+                self.handlers.pop();
+                self.finally_regions.pop();
+
+                self.current = on_exception;
+                self.line(&format!("_fin{id}_kind = 2; _fin{id}_exc = _exception;", id = id));
+                self.jump_and_start(entry, entry);
+                self.lower_body(finally_body)?;
+                self.line(&format!(
+                    "if (_fin{id}_kind == 2) {{ std::exception_ptr __eppx_exc = _fin{id}_exc; _fin{id}_exc = nullptr; std::rethrow_exception(__eppx_exc); }}",
+                    id = id
+                ));
+                let depth = self.finally_regions.len();
+                match self.finally_regions.last() {
+                    Some(outer) => self.line(&format!(
+                        "if (_fin{id}_kind == 1) {{ if (_fin{id}_depth == {depth}) {{ _state = _fin{id}_target; continue; }} _fin{o}_kind = 1; _fin{o}_target = _fin{id}_target; _fin{o}_depth = _fin{id}_depth; _state = {entry}; continue; }}",
+                        id = id, depth = depth, o = outer.id, entry = outer.entry
+                    )),
+                    None => self.line(&format!("if (_fin{id}_kind == 1) {{ _state = _fin{id}_target; continue; }}", id = id)),
+                }
+                self.jump_and_start(after, after);
+            }
+            _ => self.jump_and_start(after, after),
+        }
+        Ok(())
+    }
+}
+
+// Generates the frame class `<name>_Generator` and the function `<name>(...)`
+// that creates it. For a method of `owner` both go in the class body, and the
+// frame keeps a pointer to the instance as `self`
+#[allow(clippy::too_many_arguments)]
+pub(super) fn generate_generator(
+    name: &str,
+    params: &[String],
+    body: &[AstNode],
+    is_async: bool,
+    owner: Option<&str>,
+    symbol_table: &mut SymbolTable,
+    function_table: &mut FunctionTable,
+    type_map: &mut TypeMap,
+) -> Result<String, String> {
+    let body = if is_async { desugar_async_with(body, &mut 0) } else { body.to_vec() };
+    let mut locals = Vec::new();
+    collect_locals(&body, &mut locals);
+    locals.retain(|(var, _)| !params.contains(var) && (owner.is_none() || var != "self"));
+
+    symbol_table.enter_scope();
+    // Parameters may hold the caller's handle, like those of plain functions, and
+    // so do locals first bound to a new dict, set or instance, or to a variable
+    // that holds a handle (`cm = conn`)
+    for (i, param) in params.iter().enumerate() {
+        symbol_table.add_variable(param, &format!("T{}", i));
+    }
+    for (var, first_value) in &locals {
+        let handle_type = match first_value {
+            Some(value) if is_new_shared_object(value, symbol_table) => {
+                Some(if is_user_instance(value, symbol_table) { INSTANCE_TYPE } else { SHARED_TYPE }.to_string())
+            }
+            Some(Expression::Identifier(name)) => symbol_table.get_variable(name).filter(|info| holds_ref(info)).map(|info| info.type_name.clone()),
+            _ => None,
+        };
+        symbol_table.add_variable(var, handle_type.as_deref().unwrap_or("eppx_variant"));
+    }
+    if owner.is_some() {
+        symbol_table.add_variable("self", FRAME_SELF_TYPE);
+    }
+    let declared_vars: HashSet<String> = params.iter().chain(locals.iter().map(|(var, _)| var)).cloned().collect();
+
+    // A local takes the type of the value first assigned to it (see
+    // eppx_member_t); values that can't go in a decltype are eppx_variant
+    let mut local_members = Vec::new();
+    for (var, first_value) in &locals {
+        let member_type = match first_value {
+            Some(value) if !needs_variant_member(value) => {
+                let value_cpp = if is_new_shared_object(value, symbol_table) {
+                    emit_expression_cpp(value, symbol_table, function_table, type_map).map(|cpp| format!("eppx_share({})", cpp))
+                } else if symbol_table.get_variable(var).is_some_and(holds_ref) {
+                    emit_handle_cpp(value, symbol_table, function_table, type_map)
+                } else {
+                    emit_expression_cpp(value, symbol_table, function_table, type_map)
+                };
+                match value_cpp {
+                    Ok(value_cpp) => format!("eppx_member_t<decltype({})>", value_cpp),
+                    Err(_) => "eppx_variant".to_string(),
+                }
+            }
+            _ => "eppx_variant".to_string(),
+        };
+        local_members.push(format!("{} {};", member_type, var));
+    }
+
+    let mut lowering = GeneratorLowering {
+        blocks: Vec::new(),
+        current: ENTRY_BLOCK,
+        handlers: Vec::new(),
+        loops: Vec::new(),
+        finally_regions: Vec::new(),
+        members: Vec::new(),
+        declared_vars,
+        next_id: 0,
+        is_async,
+        symbol_table,
+        function_table,
+        type_map,
+    };
+    lowering.new_block(); // ENTRY_BLOCK
+    lowering.new_block(); // FINISH_BLOCK
+    lowering.current = FINISH_BLOCK;
+    lowering.line("_state = -1; return false;");
+    lowering.current = ENTRY_BLOCK;
+    let result = lowering.lower_body(&body);
+    lowering.symbol_table.exit_scope();
+    result?;
+    // Falling off the end of the body finishes the generator
+    lowering.jump(FINISH_BLOCK);
+
+    // Parameters keep the argument types (a coroutine can be handed a queue or
+    // a task), so frame and factory are templates over them
+    let template_params = (0..params.len()).map(|i| format!("typename T{}", i)).collect::<Vec<_>>().join(", ");
+    let template_args = (0..params.len()).map(|i| format!("T{}", i)).collect::<Vec<_>>().join(", ");
+    let func_params = params.iter().enumerate().map(|(i, p)| format!("const T{}& {}", i, p)).collect::<Vec<_>>().join(", ");
+    let template_clause = if params.is_empty() { String::new() } else { format!("template<{}>\n", template_params) };
+    let class_name = format!("{}_Generator", name);
+    let class_type = if params.is_empty() { class_name.clone() } else { format!("{}<{}>", class_name, template_args) };
+
+    let mut cpp_out = String::new();
+    // Declared up front so the body can call the function recursively (methods
+    // can call each other anyway)
+    if owner.is_none() {
+        cpp_out.push_str(&format!("{}eppx_generator {}({});\n\n", template_clause, name, func_params));
+    }
+    cpp_out.push_str(&template_clause);
+    cpp_out.push_str(&format!("class {} : public eppx_generator_frame {{\n", class_name));
+    cpp_out.push_str("public:\n");
+    if let Some(owner) = owner {
+        cpp_out.push_str(&format!("    {}* self;\n", owner));
+    }
+    for (i, param) in params.iter().enumerate() {
+        cpp_out.push_str(&format!("    eppx_member_t<T{}> {};\n", i, param));
+    }
+    for member in local_members.iter().chain(&lowering.members) {
+        cpp_out.push_str(&format!("    {}\n", member));
+    }
+    cpp_out.push('\n');
+    let mut ctor_params: Vec<String> = owner.iter().map(|owner| format!("{}* __eppx_self", owner)).collect();
+    ctor_params.extend((0..params.len()).map(|i| format!("const T{}& __eppx_arg{}", i, i)));
+    let mut stores: Vec<String> = owner.iter().map(|_| "self = __eppx_self;".to_string()).collect();
+    stores.extend(params.iter().enumerate().map(|(i, p)| format!("eppx_store({}, __eppx_arg{});", p, i)));
+    if ctor_params.is_empty() {
+        cpp_out.push_str(&format!("    {}() {{}}\n\n", class_name));
+    } else {
+        cpp_out.push_str(&format!("    {}({}) {{ {} }}\n\n", class_name, ctor_params.join(", "), stores.join(" ")));
+    }
+    cpp_out.push_str("    bool resume() override {\n");
+    cpp_out.push_str("        while (true) {\n");
+    cpp_out.push_str("            try {\n");
+    cpp_out.push_str("                switch (_state) {\n");
+    for (i, block) in lowering.blocks.iter().enumerate() {
+        cpp_out.push_str(&format!("                case {}: {{\n", i));
+        cpp_out.push_str(&block.code);
+        cpp_out.push_str("                }\n");
+    }
+    cpp_out.push_str("                default:\n");
+    cpp_out.push_str("                    return false;\n");
+    cpp_out.push_str("                }\n");
+    cpp_out.push_str("            } catch (...) {\n");
+    cpp_out.push_str("                _exception = std::current_exception();\n");
+    cpp_out.push_str("                switch (_state) {\n");
+    let mut handled: Vec<(usize, Vec<usize>)> = Vec::new();
+    for (i, block) in lowering.blocks.iter().enumerate() {
+        if let Some(handler) = block.handler {
+            match handled.iter_mut().find(|(h, _)| *h == handler) {
+                Some((_, states)) => states.push(i),
+                None => handled.push((handler, vec![i])),
+            }
+        }
+    }
+    for (handler, states) in handled {
+        for state in states {
+            cpp_out.push_str(&format!("                case {}:\n", state));
+        }
+        cpp_out.push_str(&format!("                    _state = {};\n", handler));
+        cpp_out.push_str("                    break;\n");
+    }
+    cpp_out.push_str("                default:\n");
+    cpp_out.push_str("                    _state = -1;\n");
+    cpp_out.push_str("                    throw;\n");
+    cpp_out.push_str("                }\n");
+    cpp_out.push_str("            }\n");
+    cpp_out.push_str("        }\n");
+    cpp_out.push_str("    }\n");
+    cpp_out.push_str("};\n\n");
+
+    // Calling the generator function only creates the frame
+    cpp_out.push_str(&template_clause);
+    cpp_out.push_str(&format!("eppx_generator {}({}) {{\n", name, func_params));
+    let args: Vec<&str> = owner.iter().map(|_| "this").chain(params.iter().map(String::as_str)).collect();
+    cpp_out.push_str(&format!("    return eppx_generator(std::make_shared<{}>({}));\n", class_type, args.join(", ")));
+    cpp_out.push_str("}\n\n");
+    Ok(cpp_out)
+}
+
+// Names bound anywhere in the body, in order of first binding, with the value
+// of that binding when it is a plain assignment; they become frame members
+fn collect_locals(body: &[AstNode], locals: &mut Vec<(String, Option<Expression>)>) {
+    let bind = |locals: &mut Vec<(String, Option<Expression>)>, name: &String, value: Option<&Expression>| {
+        if !locals.iter().any(|(var, _)| var == name) {
+            locals.push((name.clone(), value.cloned()));
+        }
+    };
+    for node in body {
+        let AstNode::Statement(stmt) = node;
+        let mut named_targets = Vec::new();
+        for expr in statement_expressions(stmt) {
+            named_expr_targets(expr, false, &mut named_targets);
+        }
+        for (name, value) in named_targets {
+            bind(locals, &name.to_string(), value);
+        }
+        match stmt {
+            Statement::Assignment { target, operator, value } => {
+                if let Expression::Identifier(name) = &**target {
+                    let first_value = matches!(operator, AssignmentOperator::Assign).then_some(&**value);
+                    bind(locals, name, first_value);
+                }
+            }
+            Statement::AnnotatedAssignment { target, value, .. } => {
+                if let Expression::Identifier(name) = &**target {
+                    bind(locals, name, value.as_deref());
+                }
+            }
+            Statement::ChainedAssignment { targets, value } => {
+                for target in targets {
+                    if let Expression::Identifier(name) = target {
+                        bind(locals, name, Some(&**value));
+                    }
+                }
+            }
+            Statement::For { vars, body, else_body, .. } => {
+                for var in vars {
+                    bind(locals, var, None);
+                }
+                collect_locals(body, locals);
+                if let Some(else_body) = else_body {
+                    collect_locals(else_body, locals);
+                }
+            }
+            Statement::While { body, else_body, .. } => {
+                collect_locals(body, locals);
+                if let Some(else_body) = else_body {
+                    collect_locals(else_body, locals);
+                }
+            }
+            Statement::If { then_body, elifs, else_body, .. } => {
+                collect_locals(then_body, locals);
+                for (_, elif_body) in elifs {
+                    collect_locals(elif_body, locals);
+                }
+                if let Some(else_body) = else_body {
+                    collect_locals(else_body, locals);
+                }
+            }
+            Statement::TryExcept { try_body, excepts, else_body, finally_body } => {
+                collect_locals(try_body, locals);
+                for handler in excepts {
+                    if let Some(name) = &handler.name {
+                        // `except StopIteration as e` keeps the exception for `e.value`
+                        let stop_iteration = Expression::Call {
+                            callee: Box::new(Expression::Identifier("StopIteration".to_string())),
+                            args: Vec::new(),
+                            keywords: Vec::new(),
+                        };
+                        let is_stop_iteration = matches!(&handler.exception_type, Some(Expression::Identifier(type_name)) if type_name == "StopIteration");
+                        bind(locals, name, is_stop_iteration.then_some(&stop_iteration));
+                    }
+                    collect_locals(&handler.body, locals);
+                }
+                if let Some(else_body) = else_body {
+                    collect_locals(else_body, locals);
+                }
+                if let Some(finally_body) = finally_body {
+                    collect_locals(finally_body, locals);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// `async with cm as v: body` becomes
+//     __eppx_cm_N = cm
+//     v = await __eppx_cm_N.__aenter__()
+//     try: body
+//     finally: await __eppx_cm_N.__aexit__(None, None, None)
+// (exceptions are not passed to __aexit__, which can't suppress them)
+fn desugar_async_with(body: &[AstNode], next_id: &mut usize) -> Vec<AstNode> {
+    let mut result = Vec::new();
+    for node in body {
+        let AstNode::Statement(stmt) = node;
+        let stmt = match stmt {
+            Statement::With { items, body, is_async: true } => {
+                let mut inner = desugar_async_with(body, next_id);
+                let mut prologue = Vec::new();
+                for item in items {
+                    let cm = Expression::Identifier(format!("__eppx_cm_{}", *next_id));
+                    *next_id += 1;
+                    let method_call = |method: &str, args: Vec<Expression>| {
+                        Expression::Await(Box::new(Expression::Call {
+                            callee: Box::new(Expression::AttributeAccess { object: Box::new(cm.clone()), attr: method.to_string() }),
+                            args,
+                            keywords: Vec::new(),
+                        }))
+                    };
+                    let enter = method_call("__aenter__", Vec::new());
+                    let exit = method_call("__aexit__", vec![Expression::NoneLiteral, Expression::NoneLiteral, Expression::NoneLiteral]);
+                    prologue.push(Statement::Assignment {
+                        target: Box::new(cm.clone()),
+                        operator: AssignmentOperator::Assign,
+                        value: Box::new(item.context_expr.clone()),
+                    });
+                    prologue.push(match &item.optional_vars {
+                        Some(var) => Statement::Assignment {
+                            target: Box::new(Expression::Identifier(var.clone())),
+                            operator: AssignmentOperator::Assign,
+                            value: Box::new(enter),
+                        },
+                        None => Statement::ExpressionStatement(Box::new(enter)),
+                    });
+                    prologue.push(Statement::TryExcept {
+                        try_body: Vec::new(),
+                        excepts: Vec::new(),
+                        else_body: None,
+                        finally_body: Some(vec![AstNode::Statement(Statement::ExpressionStatement(Box::new(exit)))]),
+                    });
+                }
+                // Nest from the innermost item outwards
+                while let Some(stmt) = prologue.pop() {
+                    match stmt {
+                        Statement::TryExcept { excepts, else_body, finally_body, .. } => {
+                            inner = vec![AstNode::Statement(Statement::TryExcept { try_body: inner, excepts, else_body, finally_body })];
+                        }
+                        stmt => inner.insert(0, AstNode::Statement(stmt)),
+                    }
+                }
+                result.extend(inner);
+                continue;
+            }
+            Statement::If { condition, then_body, elifs, else_body } => Statement::If {
+                condition: condition.clone(),
+                then_body: desugar_async_with(then_body, next_id),
+                elifs: elifs.iter().map(|(cond, body)| (cond.clone(), desugar_async_with(body, next_id))).collect(),
+                else_body: else_body.as_ref().map(|body| desugar_async_with(body, next_id)),
+            },
+            Statement::While { condition, body, else_body } => Statement::While {
+                condition: condition.clone(),
+                body: desugar_async_with(body, next_id),
+                else_body: else_body.as_ref().map(|body| desugar_async_with(body, next_id)),
+            },
+            Statement::For { vars, iterable, body, else_body, is_async } => Statement::For {
+                vars: vars.clone(),
+                iterable: iterable.clone(),
+                body: desugar_async_with(body, next_id),
+                else_body: else_body.as_ref().map(|body| desugar_async_with(body, next_id)),
+                is_async: *is_async,
+            },
+            Statement::TryExcept { try_body, excepts, else_body, finally_body } => Statement::TryExcept {
+                try_body: desugar_async_with(try_body, next_id),
+                excepts: excepts
+                    .iter()
+                    .map(|handler| ExceptHandler { body: desugar_async_with(&handler.body, next_id), ..handler.clone() })
+                    .collect(),
+                else_body: else_body.as_ref().map(|body| desugar_async_with(body, next_id)),
+                finally_body: finally_body.as_ref().map(|body| desugar_async_with(body, next_id)),
+            },
+            stmt => stmt.clone(),
+        };
+        result.push(AstNode::Statement(stmt));
+    }
+    result
+}
+
+fn contains_await(expr: &Expression) -> bool {
+    any_subexpression(expr, &|e| matches!(e, Expression::Await(_)))
+}
+
+// Values whose C++ type can't be taken with decltype in a member declaration:
+// they are produced by the state machine, or emitted as lambdas (which C++17
+// doesn't allow in unevaluated operands)
+fn needs_variant_member(expr: &Expression) -> bool {
+    emits_lambda(expr)
+        || any_subexpression(expr, &|e| matches!(e, Expression::Yield(_) | Expression::YieldFrom(_) | Expression::Await(_)))
+}
+
+// The exception passed to gen.throw(): `ValueError`, `ValueError("message")`
+// or any other value (raised as a plain Exception carrying its text)
+pub(super) fn emit_exception_ptr_cpp(
+    expr: &Expression,
+    symbol_table: &mut SymbolTable,
+    function_table: &FunctionTable,
+    type_map: &mut TypeMap,
+) -> Result<String, String> {
+    if let Expression::Identifier(type_name) = expr {
+        return Ok(format!("eppx_make_exception(\"{}\", \"\")", type_name));
+    }
+    if let Expression::Call { callee, args, .. } = expr {
+        if let Expression::Identifier(type_name) = &**callee {
+            let message = match args.first() {
+                Some(message) => format!(
+                    "variant_to_string(eppx_to_variant({}))",
+                    emit_expression_cpp(message, symbol_table, function_table, type_map)?
+                ),
+                None => "\"\"".to_string(),
+            };
+            return Ok(format!("eppx_make_exception(\"{}\", {})", type_name, message));
+        }
+    }
+    Ok(format!(
+        "eppx_make_exception(\"Exception\", variant_to_string(eppx_to_variant({})))",
+        emit_expression_cpp(expr, symbol_table, function_table, type_map)?
+    ))
+}
@@ -1,6 +1,13 @@
 // Codegen module placeholder
-use crate::ast::{AstNode, Expression, Statement, BinOp, UnaryOp, AssignmentOperator, Comprehension};
-use std::collections::{HashMap, HashSet};
+use crate::ast::{AstNode, Expression, Statement, BinOp, UnaryOp, AssignmentOperator, Comprehension, Decorator, ExceptHandler, MatchCase};
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+mod dataclass;
+mod generator;
+mod pattern;
 
 // Placeholder for SymbolTable, FunctionTable, and TypeMap
 // These would typically be more complex and live in their own modules (e.g., semantic or typechecker)
@@ -15,12 +22,51 @@ pub struct VariableInfo {
     pub is_custom_iterator: bool, // Tracks if this is a custom iterator class instance
 }
 
+// A module of a project: `src/<name>.eppx`, imported with `import <name>`
+#[derive(Debug, Clone)]
+pub struct ProjectModule {
+    pub name: String,
+    pub ast: Vec<AstNode>,
+}
+
+// Options that change the generated code
+#[derive(Debug, Clone, Default)]
+pub struct CodegenOptions {
+    pub strip_asserts: bool, // `-O`: compile `assert` statements out
+}
+
+// The C++ for a program, and warnings about code that compiles but is likely
+// wrong (such as a match that leaves enum members unhandled)
+#[derive(Debug, Clone)]
+pub struct GeneratedProgram {
+    pub cpp: String,
+    pub warnings: Vec<String>,
+}
+
+// What match statements need to know about a user-defined class
+#[derive(Debug, Clone)]
+pub struct ClassInfo {
+    pub cpp_name: String,                  // qualified C++ name, e.g. Outer::Inner
+    pub bases: Vec<String>,                // C++ names of the base classes
+    pub match_args: Option<Vec<String>>,   // __match_args__, if the class sets it
+    pub enum_members: Option<Vec<String>>, // members of an enum-like class (only constant attributes)
+    pub init_subclass_params: Option<Vec<String>>, // keyword parameters of __init_subclass__, if the class defines it
+    pub dataclass: Option<dataclass::DataclassInfo>, // fields and options of a @dataclass
+    pub enum_info: Option<dataclass::EnumInfo>,      // members of an Enum/IntEnum subclass
+}
+
 #[allow(dead_code)]
 pub struct SymbolTable {
     scopes: Vec<HashMap<String, VariableInfo>>, // var_name -> VariableInfo
     current_scope_index: usize,
     generator_functions: HashSet<String>, // Tracks which functions are generators
     custom_iterator_classes: HashSet<String>, // Tracks which classes have __iter__ and __next__
+    imported_modules: HashMap<String, String>, // name bound by `import` -> runtime module
+    classes: HashMap<String, ClassInfo>, // user-defined classes
+    strip_asserts: bool, // see CodegenOptions
+    project_modules: HashSet<String>, // .eppx modules next to the entry file
+    warnings: Rc<RefCell<Vec<String>>>, // shared by forks, see GeneratedProgram
+    returns_variables: bool, // in a function whose return type is RETURNS_VARIABLE
 }
 
 #[allow(dead_code)]
@@ -30,6 +76,12 @@ impl SymbolTable {    pub fn new() -> Self {
             current_scope_index: 0,
             generator_functions: HashSet::new(),
             custom_iterator_classes: HashSet::new(),
+            imported_modules: HashMap::new(),
+            classes: HashMap::new(),
+            strip_asserts: false,
+            project_modules: HashSet::new(),
+            warnings: Rc::default(),
+            returns_variables: false,
         }
     }
 
@@ -74,6 +126,55 @@ impl SymbolTable {    pub fn new() -> Self {
 
     pub fn is_custom_iterator_class(&self, name: &str) -> bool {
         self.custom_iterator_classes.contains(name)
+    }
+
+    pub fn add_class(&mut self, name: &str, info: ClassInfo) {
+        self.classes.insert(name.to_string(), info);
+    }
+
+    pub fn get_class(&self, name: &str) -> Option<&ClassInfo> {
+        self.classes.get(name)
+    }
+
+    // __match_args__ of a class, inherited from its bases
+    pub fn class_match_args(&self, name: &str) -> Option<&Vec<String>> {
+        let info = self.classes.get(name)?;
+        match &info.match_args {
+            Some(args) => Some(args),
+            None => info.bases.iter().find_map(|base| self.class_match_args(class_key(base))),
+        }
+    }
+
+    // The nearest base class (depth-first, left to right) that defines __init_subclass__
+    pub fn init_subclass_owner(&self, name: &str) -> Option<&ClassInfo> {
+        let info = self.classes.get(name)?;
+        info.bases.iter().find_map(|base| {
+            let base_info = self.classes.get(class_key(base))?;
+            if base_info.init_subclass_params.is_some() {
+                Some(base_info)
+            } else {
+                self.init_subclass_owner(class_key(base))
+            }
+        })
+    }
+
+    // Is `attr` a field of some frozen dataclass? (assignments to it are checked at runtime)
+    pub fn is_frozen_dataclass_field(&self, attr: &str) -> bool {
+        self.classes.values().filter_map(|info| info.dataclass.as_ref()).any(|dataclass| {
+            dataclass.options.frozen && dataclass.fields.iter().any(|field| field.name == attr)
+        })
+    }
+
+    pub fn add_module(&mut self, name: &str, module: &str) {
+        self.imported_modules.insert(name.to_string(), module.to_string());
+    }
+
+    // The runtime module `name` refers to, unless a variable shadows it
+    pub fn get_module(&self, name: &str) -> Option<&String> {
+        if self.get_variable(name).is_some() {
+            return None;
+        }
+        self.imported_modules.get(name)
     }    pub fn get_variable(&self, name: &str) -> Option<&VariableInfo> {
         for scope in self.scopes.iter().rev() {
             if let Some(var_info) = scope.get(name) {
@@ -82,6 +183,11 @@ impl SymbolTable {    pub fn new() -> Self {
         }
         None
     }
+    // Reported with the generated program rather than failing it
+    pub fn warn(&self, message: String) {
+        self.warnings.borrow_mut().push(message);
+    }
+
     // Add a fork method for lambda scopes or similar isolated contexts
     pub fn fork(&self) -> SymbolTable {
         Self {
@@ -89,6 +195,12 @@ impl SymbolTable {    pub fn new() -> Self {
             current_scope_index: self.current_scope_index,
             generator_functions: self.generator_functions.clone(),
             custom_iterator_classes: self.custom_iterator_classes.clone(),
+            imported_modules: self.imported_modules.clone(),
+            classes: self.classes.clone(),
+            strip_asserts: self.strip_asserts,
+            project_modules: self.project_modules.clone(),
+            warnings: Rc::clone(&self.warnings),
+            returns_variables: self.returns_variables,
         }
     }
 }
@@ -97,6 +209,7 @@ impl SymbolTable {    pub fn new() -> Self {
 pub struct FunctionSignature {
     #[allow(dead_code)]
     pub param_types: Vec<String>, // Simplified: type names as strings
+    pub param_names: Vec<String>, // for placing keyword arguments
     pub return_type: String,    // Simplified: type name as string
 }
 
@@ -135,6 +248,165 @@ impl TypeMap {
     // Add methods as needed, e.g., to store and retrieve type information
 }
 
+// Modules implemented by the C++ runtime (each is a namespace in builtins.hpp or,
+// for asyncio, eppx_asyncio.hpp)
+const RUNTIME_MODULES: &[&str] = &["asyncio", "gc", "sys"];
+// Modules whose contents are resolved at compile time (decorators, base classes, annotations)
+const COMPILE_TIME_MODULES: &[&str] = &["dataclasses", "enum", "typing", "abc"];
+
+fn import_module(module: &str, alias: &Option<String>, symbol_table: &mut SymbolTable) -> Result<(), String> {
+    if COMPILE_TIME_MODULES.contains(&module) && alias.is_none() {
+        return Ok(());
+    }
+    if symbol_table.project_modules.contains(module) {
+        symbol_table.add_module(alias.as_deref().unwrap_or(module), &project_module_namespace(module));
+        return Ok(());
+    }
+    if !RUNTIME_MODULES.contains(&module) {
+        return Err(format!("No module named '{}' (available modules: {})", module, available_modules(symbol_table)));
+    }
+    symbol_table.add_module(alias.as_deref().unwrap_or(module), module);
+    Ok(())
+}
+
+fn available_modules(symbol_table: &SymbolTable) -> String {
+    let mut project_modules: Vec<&str> = symbol_table.project_modules.iter().map(String::as_str).collect();
+    project_modules.sort();
+    RUNTIME_MODULES.iter().copied().chain(project_modules).collect::<Vec<_>>().join(", ")
+}
+
+// C++ namespace of a project module, prefixed so it can't clash with the program's own names
+fn project_module_namespace(module: &str) -> String {
+    format!("eppx_module_{}", module)
+}
+
+// Project modules named by an import in `ast_nodes`
+fn imported_modules<'a>(ast_nodes: &[AstNode], modules: &'a [ProjectModule]) -> Vec<&'a ProjectModule> {
+    ast_nodes
+        .iter()
+        .filter_map(|node| match node {
+            AstNode::Statement(Statement::Import { module, .. } | Statement::ImportFrom { module, .. }) => {
+                modules.iter().find(|candidate| candidate.name == *module)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// The project modules the entry program imports, directly or through other
+// modules, in an order where each comes after the ones it imports. Files in
+// src/ that nothing imports are not compiled
+fn order_modules<'a>(ast_nodes: &[AstNode], modules: &'a [ProjectModule]) -> Result<Vec<&'a ProjectModule>, String> {
+    fn visit<'a>(module: &'a ProjectModule, modules: &'a [ProjectModule], path: &mut Vec<String>, ordered: &mut Vec<&'a ProjectModule>) -> Result<(), String> {
+        if ordered.iter().any(|done| done.name == module.name) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|name| *name == module.name) {
+            let cycle = path[start..].iter().chain(std::iter::once(&module.name)).cloned().collect::<Vec<_>>();
+            return Err(format!("circular import between project modules: {}", cycle.join(" -> ")));
+        }
+        path.push(module.name.clone());
+        for dependency in imported_modules(&module.ast, modules) {
+            visit(dependency, modules, path, ordered)?;
+        }
+        path.pop();
+        ordered.push(module);
+        Ok(())
+    }
+    let mut ordered = Vec::new();
+    for module in imported_modules(ast_nodes, modules) {
+        visit(module, modules, &mut Vec::new(), &mut ordered)?;
+    }
+    Ok(ordered)
+}
+
+// `NAME = value` (or `NAME: type = value`) at the top level of a project module
+fn module_global(node: &AstNode) -> Option<(&String, &Expression)> {
+    match node {
+        AstNode::Statement(Statement::Assignment { value, .. }) if is_type_variable(value) => None,
+        AstNode::Statement(Statement::Assignment { target, operator: AssignmentOperator::Assign, value }) => match &**target {
+            Expression::Identifier(name) => Some((name, value)),
+            _ => None,
+        },
+        AstNode::Statement(Statement::AnnotatedAssignment { target, value: Some(value), .. }) => match &**target {
+            Expression::Identifier(name) => Some((name, value)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// A project module becomes a namespace holding its classes, functions and
+// globals. Globals are static `inline` variables initialized in source order
+// (a module's imports are emitted before it), so a function can use the
+// globals assigned above it; other import-time code is not supported
+fn generate_module_cpp(module: &ProjectModule, program: &SymbolTable) -> Result<String, String> {
+    for node in &module.ast {
+        let AstNode::Statement(stmt) = node;
+        match stmt {
+            Statement::Import { .. } | Statement::ImportFrom { .. } | Statement::FunctionDef { .. } | Statement::ClassDef { .. } | Statement::Pass => {}
+            Statement::Assignment { value, .. } if is_type_variable(value) => {}
+            Statement::ExpressionStatement(expr) if matches!(**expr, Expression::StringLiteral(_)) => {} // docstring
+            _ if module_global(node).is_some() => {}
+            _ => {
+                return Err(format!(
+                    "module '{}': only imports, functions, classes and global assignments are supported at module level",
+                    module.name
+                ))
+            }
+        }
+    }
+    let mut symbol_table = SymbolTable::new();
+    symbol_table.strip_asserts = program.strip_asserts;
+    symbol_table.project_modules = program.project_modules.clone();
+    symbol_table.warnings = Rc::clone(&program.warnings);
+    let mut function_table = FunctionTable::new();
+    let mut type_map = TypeMap::new();
+    // Definitions between two globals are emitted together, keeping the source order
+    let mut definitions = String::new();
+    let mut start = 0;
+    for (index, node) in module.ast.iter().enumerate() {
+        if let Some((name, value)) = module_global(node) {
+            definitions.push_str(&generate_definitions_cpp(&module.ast[start..index], &mut symbol_table, &mut function_table, &mut type_map)?);
+            let value_cpp = emit_expression_cpp(value, &mut symbol_table, &function_table, &mut type_map)?;
+            definitions.push_str(&format!("inline auto {} = {};\n", name, value_cpp));
+            start = index + 1;
+        }
+    }
+    definitions.push_str(&generate_definitions_cpp(&module.ast[start..], &mut symbol_table, &mut function_table, &mut type_map)?);
+    let namespace = project_module_namespace(&module.name);
+    Ok(format!("namespace {} {{
+
+{}
+}} // namespace {}
+
+", namespace, definitions, namespace))
+}
+
+// `from module import name`: only compile-time modules are supported, and their
+// names are recognized where they are used (e.g. `@dataclass`, `class C(Enum)`)
+fn import_from(module: &str, names: &[(String, Option<String>)], symbol_table: &SymbolTable) -> Result<(), String> {
+    if RUNTIME_MODULES.contains(&module) || symbol_table.project_modules.contains(module) {
+        return Err(format!("`from {} import ...` is not supported; use `import {}`", module, module));
+    }
+    if !COMPILE_TIME_MODULES.contains(&module) {
+        return Err(format!("No module named '{}' (available modules: {})", module, available_modules(symbol_table)));
+    }
+    if let Some((name, Some(alias))) = names.iter().find(|(_, alias)| alias.is_some()) {
+        return Err(format!("`from {} import {} as {}` is not supported; import it under its own name", module, name, alias));
+    }
+    Ok(())
+}
+
+// C++ name of a user-defined function; `main` is taken by the program entry point
+// (and is the usual name of the coroutine passed to asyncio.run)
+fn cpp_function_name(name: &str) -> String {
+    match name {
+        "main" => "eppx_main".to_string(),
+        _ => name.to_string(),
+    }
+}
+
 fn indent_code(code: &str) -> String {
     code.lines().map(|line| format!("    {}", line)).collect::<Vec<_>>().join("\n") + "\n"
 }
@@ -145,7 +417,7 @@ pub fn generate_cpp_code_with_toplevel(ast_nodes: &[AstNode], is_toplevel: bool)
     let mut symbol_table = SymbolTable::new();
     let mut function_table = FunctionTable::new(); // Made mutable
     let mut type_map = TypeMap::new();
-    _generate_cpp_code_with_vars(ast_nodes, is_toplevel, &mut declared_vars, &mut symbol_table, &mut function_table, &mut type_map)
+    _generate_cpp_code_with_vars(ast_nodes, is_toplevel, &[], &mut declared_vars, &mut symbol_table, &mut function_table, &mut type_map)
 }
 
 fn generate_statement_list_cpp(
@@ -157,12 +429,74 @@ fn generate_statement_list_cpp(
 ) -> Result<String, String> {
     let mut cpp_out = String::new();
     for node in ast_nodes {
-        if matches!(node, AstNode::Statement(Statement::FunctionDef { .. }) | AstNode::Statement(Statement::ClassDef { .. })) {
+        if let AstNode::Statement(Statement::ClassDef { name, keywords, body, .. }) = node {
+            cpp_out.push_str(&class_creation_hooks(name, keywords, body, symbol_table, function_table, type_map)?);
+            continue;
+        }
+        if matches!(node, AstNode::Statement(Statement::FunctionDef { .. })) {
             continue;
         }
-          match node {            AstNode::Statement(Statement::Assignment { target, operator, value }) => {
-                let value_cpp = emit_expression_cpp(value, symbol_table, function_table, type_map)?;
-                let target_cpp = emit_expression_cpp(target, symbol_table, function_table, type_map)?;
+        let AstNode::Statement(stmt) = node;
+        cpp_out.push_str(&declare_named_expr_targets(stmt, declared_vars, symbol_table, function_table, type_map)?);
+          match node {            AstNode::Statement(Statement::Assignment { value, .. }) if is_type_variable(value) => {}
+            AstNode::Statement(Statement::Assignment { target, operator, value }) => {
+                // Assigning a variable that holds a handle to a new or handle variable
+                // shares its object (`e = d`), and assigning a list variable into a
+                // container shares the list (`xs[0] = a`)
+                let shares = *operator == AssignmentOperator::Assign
+                    && match &**target {
+                        Expression::Identifier(name) => symbol_table.get_variable(name).map_or(!declared_vars.contains(name), holds_ref),
+                        Expression::Index { .. } => true,
+                        _ => false,
+                    };
+                // An attribute that can hold a handle (`self.next = node`) shares the
+                // object as well; eppx_store copies the value into other members
+                let stores_handle = *operator == AssignmentOperator::Assign
+                    && matches!(&**target, Expression::AttributeAccess { .. })
+                    && (is_new_shared_object(value, symbol_table)
+                        || matches!(&**value, Expression::Identifier(name) if symbol_table.get_variable(name).is_some_and(holds_ref)));
+                let mut value_cpp = match &**target {
+                    Expression::Identifier(_) if shares => emit_handle_cpp(value, symbol_table, function_table, type_map)?,
+                    Expression::Index { .. } if shares => emit_reference_cpp(value, symbol_table, function_table, type_map)?,
+                    Expression::AttributeAccess { .. } if stores_handle && is_new_shared_object(value, symbol_table) => {
+                        format!("eppx_share({})", emit_expression_cpp(value, symbol_table, function_table, type_map)?)
+                    }
+                    Expression::AttributeAccess { .. } if stores_handle => emit_handle_cpp(value, symbol_table, function_table, type_map)?,
+                    _ => emit_expression_cpp(value, symbol_table, function_table, type_map)?,
+                };
+                // Subscript targets (d[k] = v, xs[i] += 1) go through eppx_setitem so dict
+                // insertion order and negative list indices behave like Python
+                if let Expression::Index { object, index } = &**target {
+                    let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
+                    let index_cpp = emit_expression_cpp(index, symbol_table, function_table, type_map)?;
+                    let current = format!("eppx_index({}, {})", object_cpp, index_cpp);
+                    let new_value = match operator {
+                        AssignmentOperator::Assign => value_cpp,
+                        AssignmentOperator::AddAssign => format!("{} + {}", current, value_cpp),
+                        AssignmentOperator::SubAssign => format!("{} - {}", current, value_cpp),
+                        AssignmentOperator::MulAssign => format!("{} * {}", current, value_cpp),
+                        AssignmentOperator::DivAssign => format!("{} / {}", current, value_cpp),
+                        AssignmentOperator::ModAssign => format!("{} % {}", current, value_cpp),
+                        _ => return Err(format!("Unsupported augmented assignment to subscript: {:?}", operator)),
+                    };
+                    cpp_out.push_str(&format!("    eppx_setitem({}, {}, {});\n", object_cpp, index_cpp, new_value));
+                    continue;
+                }
+                // Fields of frozen dataclasses can't be reassigned; the check is resolved per type
+                if let Expression::AttributeAccess { object, attr } = &**target {
+                    if symbol_table.is_frozen_dataclass_field(attr) {
+                        let object_cpp = match &**object {
+                            Expression::Identifier(name) if name == "self" => format!("*{}", self_cpp(symbol_table)),
+                            _ => emit_expression_cpp(object, symbol_table, function_table, type_map)?,
+                        };
+                        cpp_out.push_str(&format!("    eppx_check_setattr({}, \"{}\");\n", object_cpp, attr));
+                    }
+                }
+                // Rebinding a handle variable replaces its handle, leaving aliases untouched
+                let target_cpp = match &**target {
+                    Expression::Identifier(name) if shares => cpp_function_name(name),
+                    _ => emit_expression_cpp(target, symbol_table, function_table, type_map)?,
+                };
                 let is_simple_var = matches!(**target, Expression::Identifier(_));
                 
                 // Check if variable already exists in symbol table (parameters or previously declared)
@@ -178,6 +512,7 @@ fn generate_statement_list_cpp(
                         Expression::FloatLiteral(_) => "double".to_string(),
                         Expression::StringLiteral(_) => "std::string".to_string(),
                         Expression::BooleanLiteral(_) => "bool".to_string(),
+                        Expression::ListLiteral(_) | Expression::ListComprehension { .. } => "eppx_list_ref".to_string(),
                         Expression::Lambda { .. } => "auto".to_string(),
                         _ => "auto".to_string(),
                     };
@@ -189,7 +524,24 @@ fn generate_statement_list_cpp(
                         is_generator: false,
                         is_custom_iterator: false,
                     };
+                    // New lists, dicts, sets and instances, and aliases of handle
+                    // variables, hold a handle
+                    match &**value {
+                        Expression::ListLiteral(_) | Expression::ListComprehension { .. } => var_info.type_name = LIST_REF_TYPE.to_string(),
+                        Expression::Identifier(name) => {
+                            if let Some(info) = symbol_table.get_variable(name).filter(|info| holds_ref(info)) {
+                                var_info.type_name = info.type_name.clone();
+                            }
+                        }
+                        Expression::Call { callee, .. } if returns_handle(callee, symbol_table, function_table) => {
+                            var_info.type_name = RESULT_TYPE.to_string();
+                        }
+                        _ => {}
+                    }
                     
+                    if is_user_instance(value, symbol_table) {
+                        var_info.type_name = INSTANCE_TYPE.to_string();
+                    }
                     if let Expression::Call { callee, .. } = &**value {
                         if let Expression::Identifier(func_name) = callee.as_ref() {
                             if symbol_table.is_generator_function(func_name) {
@@ -199,6 +551,12 @@ fn generate_statement_list_cpp(
                             }
                         }
                     }
+                    if *operator == AssignmentOperator::Assign && is_new_shared_object(value, symbol_table) {
+                        value_cpp = format!("eppx_share({})", value_cpp);
+                        if var_info.type_name != INSTANCE_TYPE {
+                            var_info.type_name = SHARED_TYPE.to_string();
+                        }
+                    }
                     
                     match operator {
                         AssignmentOperator::Assign => {
@@ -214,6 +572,8 @@ fn generate_statement_list_cpp(
                     }
                     declared_vars.insert(target_cpp.clone());
                     symbol_table.add_variable_with_info(&target_cpp, var_info);
+                } else if stores_handle {
+                    cpp_out.push_str(&format!("    eppx_store({}, {});\n", target_cpp, value_cpp));
                 } else {
                     match operator {
                         AssignmentOperator::Assign => cpp_out.push_str(&format!("    {} = {};
@@ -234,7 +594,7 @@ fn generate_statement_list_cpp(
                         AssignmentOperator::ModAssign => cpp_out.push_str(&format!("    {} %= {};
 ",
  target_cpp, value_cpp)),
-                        AssignmentOperator::PowAssign => cpp_out.push_str(&format!("    {} = static_cast<long long>(std::pow(static_cast<double>({}), static_cast<double>({})));
+                        AssignmentOperator::PowAssign => cpp_out.push_str(&format!("    {} = eppx_power({}, {});
 ",
  target_cpp, target_cpp, value_cpp)),
                         AssignmentOperator::FloorDivAssign => cpp_out.push_str(&format!("    {} = static_cast<long long>(std::floor(static_cast<double>({}) / static_cast<double>({})));
@@ -313,14 +673,13 @@ fn generate_statement_list_cpp(
                 if let Some(else_body_nodes) = else_body {
                     chain.push_str(" else {
 ");
-                    chain.push_str(&emit_block(&else_body_nodes, declared_vars, symbol_table, function_table, type_map)?);
+                    chain.push_str(&emit_block(else_body_nodes, declared_vars, symbol_table, function_table, type_map)?);
                     chain.push_str("    }");
                 }
-                chain.push_str("
-");
+                chain.push('\n');
                 cpp_out.push_str(&chain);
             }
-            AstNode::Statement(Statement::While { condition, body }) => {
+            AstNode::Statement(Statement::While { condition, body, else_body }) => {
                 let emit_block = |stmts: &Vec<AstNode>, declared_vars: &mut HashSet<String>, symbol_table: &mut SymbolTable, function_table: &mut FunctionTable, type_map: &mut TypeMap| -> Result<String, String> {
                     let mut block_symbol_table = symbol_table.fork();
                     block_symbol_table.enter_scope();
@@ -330,15 +689,28 @@ fn generate_statement_list_cpp(
                 };
                 let cond_cpp = emit_expression_cpp(condition, symbol_table, function_table, type_map)?;
                 let mut while_code = String::new();
+                let break_flag = else_body.as_ref().map(|_| declare_break_flag(&mut while_code, declared_vars, symbol_table));
+                let body = match &break_flag {
+                    Some(flag) => flag_breaks(body, flag),
+                    None => body.clone(),
+                };
                 while_code.push_str(&format!("    while ({}) {{
 ",
  cond_cpp));
-                while_code.push_str(&emit_block(body, declared_vars, symbol_table, function_table, type_map)?);
+                while_code.push_str(&emit_block(&body, declared_vars, symbol_table, function_table, type_map)?);
                 while_code.push_str("    }
 ");
+                if let (Some(flag), Some(else_body)) = (&break_flag, else_body) {
+                    while_code.push_str(&format!("    if (!{}) {{\n", flag));
+                    while_code.push_str(&emit_block(else_body, declared_vars, symbol_table, function_table, type_map)?);
+                    while_code.push_str("    }\n");
+                }
                 cpp_out.push_str(&while_code);
             }
-            AstNode::Statement(Statement::For { vars, iterable, body }) => {
+            AstNode::Statement(Statement::For { is_async: true, .. }) => {
+                return Err("'async for' outside async function".to_string());
+            }
+            AstNode::Statement(Statement::For { vars, iterable, body, else_body, .. }) => {
                 let emit_block = |stmts: &Vec<AstNode>, declared_vars: &mut HashSet<String>, symbol_table: &mut SymbolTable, function_table: &mut FunctionTable, type_map: &mut TypeMap| -> Result<String, String> {
                     let mut block_symbol_table = symbol_table.fork();
                     block_symbol_table.enter_scope();
@@ -350,7 +722,12 @@ fn generate_statement_list_cpp(
                     Ok(indent_code(&inner))
                 };
                 let iterable_cpp = emit_expression_cpp(iterable, symbol_table, function_table, type_map)?;
-                let mut for_code = String::new();                // Check if this might be a custom iterator 
+                let mut for_code = String::new();
+                let break_flag = else_body.as_ref().map(|_| declare_break_flag(&mut for_code, declared_vars, symbol_table));
+                let body = &match &break_flag {
+                    Some(flag) => flag_breaks(body, flag),
+                    None => body.clone(),
+                };                // Check if this might be a custom iterator 
                 // We need to distinguish between:
                 // 1. Variables that are instances of classes with __iter__ and __next__ methods (custom iterators)
                 // 2. Variables that are generator objects (can use range-based for)
@@ -400,34 +777,30 @@ fn generate_statement_list_cpp(
                 } else {
                     for_code.push_str(&format!("    for (auto __eppx_tuple : {}) {{\n", iterable_cpp));
                     for (i, var) in vars.iter().enumerate() {
-                        // Detect if this is a built-in function that returns actual tuples
-                        let is_builtin_tuple = match &**iterable {
-                            Expression::Call { callee, .. } => {
-                                if let Expression::Identifier(name) = callee.as_ref() {
-                                    matches!(name.as_str(), "enumerate" | "zip")
-                                } else {
-                                    false
-                                }
-                            },
-                            _ => false
-                        };
-                        
-                        if is_builtin_tuple {
-                            // Use std::get for actual tuples from built-in functions
-                            for_code.push_str(&format!("        auto {} = std::get<{}>(__eppx_tuple);\n", var, i));
-                        } else {
-                            // Use vector indexing for vector-represented tuples
-                            for_code.push_str(&format!("        auto {} = std::get<std::vector<eppx_variant>>(__eppx_tuple)[{}];\n", var, i));
-                        }
+                        // eppx_unpack handles std::tuple/std::pair (enumerate, zip, dict.items)
+                        // as well as tuples represented as eppx_variant vectors
+                        for_code.push_str(&format!("        auto {} = eppx_unpack<{}>(__eppx_tuple);\n", var, i));
                     }
                     for_code.push_str(&emit_block(body, declared_vars, symbol_table, function_table, type_map)?);
                     for_code.push_str("    }\n");
                 }
+                if let (Some(flag), Some(else_body)) = (&break_flag, else_body) {
+                    // The loop variables aren't in scope here
+                    let mut else_symbol_table = symbol_table.fork();
+                    else_symbol_table.enter_scope();
+                    for_code.push_str(&format!("    if (!{}) {{\n", flag));
+                    for_code.push_str(&indent_code(&generate_statement_list_cpp(else_body, declared_vars, &mut else_symbol_table, function_table, type_map)?));
+                    for_code.push_str("    }\n");
+                }
                 cpp_out.push_str(&for_code);
             }
             AstNode::Statement(Statement::Return(expr)) => {
                 if let Some(return_expr) = expr {
-                    let return_value = emit_expression_cpp(return_expr, symbol_table, function_table, type_map)?;
+                    let return_value = if symbol_table.returns_variables {
+                        emit_handle_cpp(return_expr, symbol_table, function_table, type_map)?
+                    } else {
+                        emit_expression_cpp(return_expr, symbol_table, function_table, type_map)?
+                    };
                     cpp_out.push_str(&format!("    return {};
 ",
  return_value));
@@ -436,6 +809,19 @@ fn generate_statement_list_cpp(
 ");
                 }
             }
+            AstNode::Statement(Statement::Import { module, alias }) => {
+                import_module(module, alias, symbol_table)?;
+            }
+            // The annotation itself has no runtime effect
+            AstNode::Statement(Statement::ImportFrom { .. } | Statement::AnnotatedAssignment { value: None, .. }) => {}
+            AstNode::Statement(Statement::AnnotatedAssignment { target, value: Some(value), .. }) => {
+                let assignment = AstNode::Statement(Statement::Assignment {
+                    target: target.clone(),
+                    operator: AssignmentOperator::Assign,
+                    value: value.clone(),
+                });
+                cpp_out.push_str(&generate_statement_list_cpp(&[assignment], declared_vars, symbol_table, function_table, type_map)?);
+            }
             AstNode::Statement(Statement::Yield(_expr)) => {
                 // This should only be reached if yield is used outside a generator context
                 // In generator functions, yield is handled by the state machine
@@ -455,6 +841,43 @@ fn generate_statement_list_cpp(
                     }
                 }
             }
+            AstNode::Statement(Statement::ChainedAssignment { targets, value }) => {
+                let assignments = chained_assignments(targets, value);
+                cpp_out.push_str(&generate_statement_list_cpp(&assignments, declared_vars, symbol_table, function_table, type_map)?);
+            }
+            // Compiled out under -O, like Python's `python -O`
+            AstNode::Statement(Statement::Assert { .. }) if symbol_table.strip_asserts => {}
+            AstNode::Statement(Statement::Assert { test, msg }) => {
+                let test_cpp = emit_expression_cpp(test, symbol_table, function_table, type_map)?;
+                let msg_cpp = match msg {
+                    Some(msg) => format!(
+                        "variant_to_string(eppx_to_variant({}))",
+                        emit_expression_cpp(msg, symbol_table, function_table, type_map)?
+                    ),
+                    None => "\"\"".to_string(),
+                };
+                cpp_out.push_str(&format!("    if (!eppx_truthy({})) throw AssertionErrorException({});\n", test_cpp, msg_cpp));
+            }
+            AstNode::Statement(Statement::Delete(targets)) => {
+                for target in targets {
+                    match target {
+                        Expression::Index { object, index } => {
+                            let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
+                            let index_cpp = emit_expression_cpp(index, symbol_table, function_table, type_map)?;
+                            cpp_out.push_str(&format!("    eppx_delitem({}, {});\n", object_cpp, index_cpp));
+                        }
+                        Expression::Identifier(name) if !declared_vars.contains(name) && symbol_table.get_variable(name).is_none() => {
+                            return Err(format!("name '{}' is not defined", name));
+                        }
+                        _ => {
+                            // C++ can't unbind a name or drop a struct member: eppx_del resets the
+                            // value, and a handle variable lets go of its object
+                            let target_cpp = emit_handle_cpp(target, symbol_table, function_table, type_map)?;
+                            cpp_out.push_str(&format!("    eppx_del({});\n", target_cpp));
+                        }
+                    }
+                }
+            }
             AstNode::Statement(Statement::Break) => {
                 cpp_out.push_str("    break;
 ");
@@ -477,40 +900,60 @@ fn generate_statement_list_cpp(
                 try_code.push_str("    }\n");
                 for except in excepts {
                     try_code.push_str("    catch (");
-                    if let Some(ref _exc_type) = except.exception_type {
-                        try_code.push_str("std::exception& eppx_exc"); // Use different parameter name
-                    } else {
-                        try_code.push_str("std::exception& eppx_exc");
+                    match &except.exception_type {
+                        // SystemExit is not a std::exception, so only its own handler sees it
+                        Some(Expression::Identifier(exc_type)) if exc_type == "SystemExit" => {
+                            try_code.push_str("const SystemExitException& eppx_exc");
+                        }
+                        // Bound as the exception itself, so `e.value` is the generator's return value
+                        Some(Expression::Identifier(exc_type)) if exc_type == "StopIteration" => {
+                            try_code.push_str("const StopIterationException& eppx_exc");
+                        }
+                        _ => try_code.push_str("std::exception& eppx_exc"), // Use different parameter name
                     }
                     try_code.push_str(") {\n");
                     let mut except_symbol_table = symbol_table.fork();
                     except_symbol_table.enter_scope();
                     if let Some(ref name) = except.name {
-                        try_code.push_str(&format!("        auto {} = eppx_exc.what();\n", name));
+                        let stop_iteration = matches!(&except.exception_type, Some(Expression::Identifier(exc_type)) if exc_type == "StopIteration");
+                        let value = if stop_iteration { "eppx_exc" } else { "eppx_exc.what()" };
+                        try_code.push_str(&format!("        auto {} = {};\n", name, value));
                     }
                     try_code.push_str(&indent_code(&generate_statement_list_cpp(&except.body, declared_vars, &mut except_symbol_table, function_table, type_map)?));
                     except_symbol_table.exit_scope();
                     try_code.push_str("    }\n");
                 }
+                if excepts.is_empty() {
+                    // try/finally without handlers: run the finally block, then re-raise
+                    try_code.push_str("    catch (...) {\n");
+                    if let Some(finally_body_nodes) = finally_body {
+                        let mut finally_symbol_table = symbol_table.fork();
+                        finally_symbol_table.enter_scope();
+                        try_code.push_str(&indent_code(&generate_statement_list_cpp(finally_body_nodes, declared_vars, &mut finally_symbol_table, function_table, type_map)?));
+                        finally_symbol_table.exit_scope();
+                    }
+                    try_code.push_str("        throw;\n");
+                    try_code.push_str("    }\n");
+                }
                 if let Some(else_body_nodes) = else_body {
                     try_code.push_str("    // else block\n");
                     let mut else_symbol_table = symbol_table.fork();
                     else_symbol_table.enter_scope();
-                    try_code.push_str(&indent_code(&generate_statement_list_cpp(&else_body_nodes, declared_vars, &mut else_symbol_table, function_table, type_map)?));
+                    try_code.push_str(&indent_code(&generate_statement_list_cpp(else_body_nodes, declared_vars, &mut else_symbol_table, function_table, type_map)?));
                     else_symbol_table.exit_scope();
                 }
                 if let Some(finally_body_nodes) = finally_body {
                     try_code.push_str("    // finally block\n");
                     let mut finally_symbol_table = symbol_table.fork();
                     finally_symbol_table.enter_scope();
-                    try_code.push_str(&indent_code(&generate_statement_list_cpp(&finally_body_nodes, declared_vars, &mut finally_symbol_table, function_table, type_map)?));
+                    try_code.push_str(&indent_code(&generate_statement_list_cpp(finally_body_nodes, declared_vars, &mut finally_symbol_table, function_table, type_map)?));
                     finally_symbol_table.exit_scope();
                 }
                 cpp_out.push_str(&try_code);
             }
             AstNode::Statement(Statement::Raise(expr)) => {
                 if let Some(expr) = expr {
-                    let exc_cpp = emit_expression_cpp(&expr, symbol_table, function_table, type_map)?;
+                    let exc_cpp = emit_expression_cpp(expr, symbol_table, function_table, type_map)?;
                     // Special handling for StopIteration exception
                     if let Expression::Identifier(name) = expr {
                         if name == "StopIteration" {
@@ -518,6 +961,12 @@ fn generate_statement_list_cpp(
                             return Ok(cpp_out);
                         }
                     }
+                    if let Expression::Call { callee, .. } = expr {
+                        if matches!(&**callee, Expression::Identifier(name) if name == "StopIteration") {
+                            cpp_out.push_str(&format!("    throw {};\n", exc_cpp));
+                            return Ok(cpp_out);
+                        }
+                    }
                     
                     // Convert non-string expressions to strings for std::runtime_error
                     let string_exc = match expr {
@@ -529,7 +978,13 @@ fn generate_statement_list_cpp(
                     cpp_out.push_str("    throw std::runtime_error(\"E++ exception\");\n");
                 }
             }
-            AstNode::Statement(Statement::With { items, body }) => {
+            AstNode::Statement(Statement::With { is_async: true, .. }) => {
+                return Err("'async with' outside async function".to_string());
+            }
+            AstNode::Statement(Statement::Match { subject, cases }) => {
+                cpp_out.push_str(&indent_code(&pattern::generate_match(subject, cases, declared_vars, symbol_table, function_table, type_map)?));
+            }
+            AstNode::Statement(Statement::With { items, body, .. }) => {
                 // Generate unique IDs for this with statement to avoid conflicts
                 use std::collections::hash_map::DefaultHasher;
                 use std::hash::{Hash, Hasher};
@@ -542,7 +997,7 @@ fn generate_statement_list_cpp(
                     // Special handling: if context_expr is a call to open(...), wrap in eppx_with_file
 
                     let context_expr_cpp = match &item.context_expr {
-                        Expression::Call { callee, args } => {
+                        Expression::Call { callee, args, .. } => {
                             if let Expression::Identifier(func_name) = &**callee {
                                 if func_name == "open" {
                                     let mut open_args_cpp = Vec::new();
@@ -585,7 +1040,7 @@ fn generate_statement_list_cpp(
                 // Generate __exit__ calls in reverse order (LIFO)
                 for i in (0..items.len()).rev() {
                     let cm_var = format!("eppx_cm_{}_{}", unique_id, i);
-                    cpp_out.push_str(&format!("    catch (...) {{\n"));
+                    cpp_out.push_str("    catch (...) {\n");
                     cpp_out.push_str(&format!("        {}.__exit__(\"exception\", \"exception occurred\", \"\");\n", cm_var));
                     cpp_out.push_str("        throw;\n");
                     cpp_out.push_str("    }\n");
@@ -601,6 +1056,7 @@ fn generate_statement_list_cpp(
 fn _generate_cpp_code_with_vars(
     ast_nodes: &[AstNode],
     is_toplevel: bool,
+    modules: &[ProjectModule],
     declared_vars: &mut HashSet<String>,
     symbol_table: &mut SymbolTable,
     function_table: &mut FunctionTable, // Made mutable
@@ -622,8 +1078,6 @@ fn _generate_cpp_code_with_vars(
         cpp_out.push_str("#include <algorithm>
 ");
         cpp_out.push_str("#include <cmath> // For std::pow
-");
-        cpp_out.push_str("#include <complex> // For std::complex
 ");
         cpp_out.push_str("#include <tuple>   // For std::tuple
 ");
@@ -638,23 +1092,17 @@ fn _generate_cpp_code_with_vars(
 ");
         cpp_out.push_str("#include <functional> // For std::hash
 ");
-        cpp_out.push_str("#include \"../stdlib/builtins.hpp\" // For file I/O functions
+        cpp_out.push_str("#include \"builtins.hpp\" // E++ runtime, found through -I
 ");
-        cpp_out.push_str("\n");
-        
-        // Define StopIteration exception
-        cpp_out.push_str("// Exception for iterator protocol\n");
-        cpp_out.push_str("class StopIterationException : public std::exception {\n");
-        cpp_out.push_str("public:\n");
-        cpp_out.push_str("    const char* what() const noexcept override {\n");
-        cpp_out.push_str("        return \"StopIteration\";\n");
-        cpp_out.push_str("    }\n");
-        cpp_out.push_str("};\n");
-        cpp_out.push_str("\n");
+        cpp_out.push_str(OPTIONAL_RUNTIME_PLACEHOLDER);
+        cpp_out.push('\n');
         
         // Define indexing function for eppx_variant
         cpp_out.push_str("// Indexing function for eppx_variant\n");
-        cpp_out.push_str("eppx_variant eppx_index(const eppx_variant& obj, const eppx_variant& index) {\n");
+        cpp_out.push_str("inline eppx_variant eppx_index(const eppx_variant& obj, const eppx_variant& index) {\n");
+        cpp_out.push_str("    if (const auto* dict = std::get_if<eppx_dict_ref>(&obj)) {\n");
+        cpp_out.push_str("        return (*dict)->at(index);\n");
+        cpp_out.push_str("    }\n");
         cpp_out.push_str("    long long idx = variant_to_ll(index);\n");
         cpp_out.push_str("    if (std::holds_alternative<std::string>(obj)) {\n");
         cpp_out.push_str("        const auto& str = std::get<std::string>(obj);\n");
@@ -662,8 +1110,8 @@ fn _generate_cpp_code_with_vars(
         cpp_out.push_str("            throw std::out_of_range(\"string index out of range\");\n");
         cpp_out.push_str("        }\n");
         cpp_out.push_str("        return std::string(1, str[idx]);\n");
-        cpp_out.push_str("    } else if (std::holds_alternative<std::vector<eppx_variant>>(obj)) {\n");
-        cpp_out.push_str("        const auto& vec = std::get<std::vector<eppx_variant>>(obj);\n");
+        cpp_out.push_str("    } else if (std::holds_alternative<eppx_list_ref>(obj)) {\n");
+        cpp_out.push_str("        const auto& vec = *std::get<eppx_list_ref>(obj);\n");
         cpp_out.push_str("        if (idx < 0 || idx >= static_cast<long long>(vec.size())) {\n");
         cpp_out.push_str("            throw std::out_of_range(\"list index out of range\");\n");
         cpp_out.push_str("        }\n");
@@ -672,7 +1120,7 @@ fn _generate_cpp_code_with_vars(
         cpp_out.push_str("        throw std::runtime_error(\"object is not subscriptable\");\n");
         cpp_out.push_str("    }\n");
         cpp_out.push_str("}\n");
-        cpp_out.push_str("\n");
+        cpp_out.push('\n');
         
         // Stream operators for C++ container types to enable printing
         cpp_out.push_str("// Stream operators for container types\n");
@@ -685,7 +1133,7 @@ fn _generate_cpp_code_with_vars(
         cpp_out.push_str("    }\n");
         cpp_out.push_str("    return os << \"]\";\n");
         cpp_out.push_str("}\n");
-        cpp_out.push_str("\n");
+        cpp_out.push('\n');
         
         cpp_out.push_str("template<typename K, typename V>\n");
         cpp_out.push_str("std::ostream& operator<<(std::ostream& os, const std::map<K, V>& m) {\n");
@@ -698,7 +1146,7 @@ fn _generate_cpp_code_with_vars(
         cpp_out.push_str("    }\n");
         cpp_out.push_str("    return os << \"}\";\n");
         cpp_out.push_str("}\n");
-        cpp_out.push_str("\n");
+        cpp_out.push('\n');
         
         cpp_out.push_str("template<typename T>\n");
         cpp_out.push_str("std::ostream& operator<<(std::ostream& os, const std::set<T>& s) {\n");
@@ -711,7 +1159,7 @@ fn _generate_cpp_code_with_vars(
         cpp_out.push_str("    }\n");
         cpp_out.push_str("    return os << \"}\";\n");
         cpp_out.push_str("}\n");
-        cpp_out.push_str("\n");
+        cpp_out.push('\n');
         
         cpp_out.push_str("template<typename T>\n");
         cpp_out.push_str("std::ostream& operator<<(std::ostream& os, const std::unordered_set<T>& s) {\n");
@@ -724,20 +1172,14 @@ fn _generate_cpp_code_with_vars(
         cpp_out.push_str("    }\n");
         cpp_out.push_str("    return os << \"})\";\n");
         cpp_out.push_str("}\n");
-        cpp_out.push_str("\n");
-        
-        cpp_out.push_str("template<typename T>\n");
-        cpp_out.push_str("std::ostream& operator<<(std::ostream& os, const std::complex<T>& c) {\n");
-        cpp_out.push_str("    return os << \"(\" << c.real() << (c.imag() >= 0 ? \"+\" : \"\") << c.imag() << \"j)\";\n");
-        cpp_out.push_str("}\n");
-        cpp_out.push_str("\n");
+        cpp_out.push('\n');
         
         // Tuple printing helper
         cpp_out.push_str("template<typename Tuple, size_t... Is>\n");
         cpp_out.push_str("void print_tuple_impl(std::ostream& os, const Tuple& t, std::index_sequence<Is...>) {\n");
         cpp_out.push_str("    ((os << (Is == 0 ? \"\" : \", \") << std::get<Is>(t)), ...);\n");
         cpp_out.push_str("}\n");
-        cpp_out.push_str("\n");
+        cpp_out.push('\n');
         
         cpp_out.push_str("template<typename... Args>\n");
         cpp_out.push_str("std::ostream& operator<<(std::ostream& os, const std::tuple<Args...>& t) {\n");
@@ -750,41 +1192,39 @@ fn _generate_cpp_code_with_vars(
         cpp_out.push_str("    }\n");
         cpp_out.push_str("    return os << \")\";\n");
         cpp_out.push_str("}\n");
-        cpp_out.push_str("\n");
+        cpp_out.push('\n');
         
         // Basic print functions - single argument versions
-        cpp_out.push_str("void eppx_print(const std::string& s) { std::cout << s << std::endl; }
+        cpp_out.push_str("inline void eppx_print(const std::string& s) { std::cout << s << std::endl; }
 ");
-        cpp_out.push_str("void eppx_print(long long x) { std::cout << x << std::endl; }
+        cpp_out.push_str("inline void eppx_print(long long x) { std::cout << x << std::endl; }
 ");
-        cpp_out.push_str("void eppx_print(double x) { std::cout << x << std::endl; }
+        cpp_out.push_str("inline void eppx_print(double x) { std::cout << x << std::endl; }
 ");
-        cpp_out.push_str("void eppx_print(bool b) { std::cout << (b ? \"true\" : \"false\") << std::endl; }
+        cpp_out.push_str("inline void eppx_print(bool b) { std::cout << (b ? \"true\" : \"false\") << std::endl; }
 ");
-        cpp_out.push_str("void eppx_print(const std::complex<long long>& c) { std::cout << \"(\" << c.real() << (c.imag() >= 0 ? \"+\" : \"\") << c.imag() << \"j)\" << std::endl; }
-");
-        cpp_out.push_str("void eppx_print(const std::complex<double>& c) { std::cout << \"(\" << c.real() << (c.imag() >= 0 ? \"+\" : \"\") << c.imag() << \"j)\" << std::endl; }\n");
-        cpp_out.push_str("void eppx_print(std::nullptr_t) { std::cout << \"None\" << std::endl; }\n");
+        cpp_out.push_str("inline void eppx_print(const eppx_complex& c) { std::cout << c << std::endl; }\n");
+        cpp_out.push_str("inline void eppx_print(std::nullptr_t) { std::cout << \"None\" << std::endl; }\n");
         // Print functions with comprehensive type coverage to avoid ambiguity
         cpp_out.push_str("// Basic type printing functions\n");
-        cpp_out.push_str("void eppx_print_single(bool b) { std::cout << (b ? \"true\" : \"false\"); }\n");
-        cpp_out.push_str("void eppx_print_single(char c) { std::cout << c; }\n");
-        cpp_out.push_str("void eppx_print_single(signed char x) { std::cout << static_cast<int>(x); }\n");
-        cpp_out.push_str("void eppx_print_single(unsigned char x) { std::cout << static_cast<unsigned int>(x); }\n");
-        cpp_out.push_str("void eppx_print_single(short x) { std::cout << x; }\n");
-        cpp_out.push_str("void eppx_print_single(unsigned short x) { std::cout << x; }\n");
-        cpp_out.push_str("void eppx_print_single(int x) { std::cout << x; }\n");
-        cpp_out.push_str("void eppx_print_single(unsigned int x) { std::cout << x; }\n");
-        cpp_out.push_str("void eppx_print_single(long x) { std::cout << x; }\n");
-        cpp_out.push_str("void eppx_print_single(unsigned long x) { std::cout << x; }\n");
-        cpp_out.push_str("void eppx_print_single(long long x) { std::cout << x; }\n");
-        cpp_out.push_str("void eppx_print_single(unsigned long long x) { std::cout << x; }\n");
-        cpp_out.push_str("void eppx_print_single(float x) { std::cout << x; }\n");
-        cpp_out.push_str("void eppx_print_single(double x) { std::cout << x; }\n");
-        cpp_out.push_str("void eppx_print_single(long double x) { std::cout << x; }\n");
-        cpp_out.push_str("void eppx_print_single(const std::string& s) { std::cout << s; }\n");
-        cpp_out.push_str("void eppx_print_single(const char* s) { std::cout << s; }\n");
-        cpp_out.push_str("void eppx_print_single(std::nullptr_t) { std::cout << \"None\"; }\n");
+        cpp_out.push_str("inline void eppx_print_single(bool b) { std::cout << (b ? \"true\" : \"false\"); }\n");
+        cpp_out.push_str("inline void eppx_print_single(char c) { std::cout << c; }\n");
+        cpp_out.push_str("inline void eppx_print_single(signed char x) { std::cout << static_cast<int>(x); }\n");
+        cpp_out.push_str("inline void eppx_print_single(unsigned char x) { std::cout << static_cast<unsigned int>(x); }\n");
+        cpp_out.push_str("inline void eppx_print_single(short x) { std::cout << x; }\n");
+        cpp_out.push_str("inline void eppx_print_single(unsigned short x) { std::cout << x; }\n");
+        cpp_out.push_str("inline void eppx_print_single(int x) { std::cout << x; }\n");
+        cpp_out.push_str("inline void eppx_print_single(unsigned int x) { std::cout << x; }\n");
+        cpp_out.push_str("inline void eppx_print_single(long x) { std::cout << x; }\n");
+        cpp_out.push_str("inline void eppx_print_single(unsigned long x) { std::cout << x; }\n");
+        cpp_out.push_str("inline void eppx_print_single(long long x) { std::cout << x; }\n");
+        cpp_out.push_str("inline void eppx_print_single(unsigned long long x) { std::cout << x; }\n");
+        cpp_out.push_str("inline void eppx_print_single(float x) { std::cout << x; }\n");
+        cpp_out.push_str("inline void eppx_print_single(double x) { std::cout << x; }\n");
+        cpp_out.push_str("inline void eppx_print_single(long double x) { std::cout << x; }\n");
+        cpp_out.push_str("inline void eppx_print_single(const std::string& s) { std::cout << s; }\n");
+        cpp_out.push_str("inline void eppx_print_single(const char* s) { std::cout << s; }\n");
+        cpp_out.push_str("inline void eppx_print_single(std::nullptr_t) { std::cout << \"None\"; }\n");
         // Container printing functions
         cpp_out.push_str("template<typename T> void eppx_print_single(const std::vector<T>& vec) {\n");
         cpp_out.push_str("    std::cout << \"[\";\n");
@@ -833,12 +1273,82 @@ fn _generate_cpp_code_with_vars(
         cpp_out.push_str("template <typename... Args> void eppx_print(const std::tuple<Args...>& t) { std::cout << \"tuple object (size: \" << sizeof...(Args) << \")\" << std::endl; }\n");
     }
     
+    for module in order_modules(ast_nodes, modules)? {
+        cpp_out.push_str(&generate_module_cpp(module, symbol_table)?);
+        // Calls such as `util.scale(x, factor=2)` place their keywords by these names
+        for node in &module.ast {
+            if let AstNode::Statement(Statement::FunctionDef { name, params, body, .. }) = node {
+                let signature = FunctionSignature { param_types: Vec::new(), param_names: params.clone(), return_type: function_return_type(body).to_string() };
+                function_table.add_function(&format!("{}::{}", project_module_namespace(&module.name), name), signature);
+            }
+        }
+    }
+    cpp_out.push_str(&generate_definitions_cpp(ast_nodes, symbol_table, function_table, type_map)?);
+
+    if is_toplevel {
+        // The parameters are prefixed so they can't clash with top-level variables
+        cpp_out.push_str("int main(int eppx_argc, char** eppx_argv) {\n");
+        cpp_out.push_str("    sys::eppx_set_argv(eppx_argc, eppx_argv);\n");
+        cpp_out.push_str("    try {\n");
+        let main_body_cpp = generate_statement_list_cpp(ast_nodes, declared_vars, symbol_table, function_table, type_map)?;
+        cpp_out.push_str(&indent_code(&main_body_cpp));
+        cpp_out.push_str("    } catch (const SystemExitException& e) {\n        return eppx_exit_status(e);\n    }\n");
+        cpp_out.push_str("    return 0;\n}\n");
+        let includes = optional_runtime_includes(&cpp_out);
+        cpp_out = cpp_out.replacen(OPTIONAL_RUNTIME_PLACEHOLDER, &includes, 1);
+    }
+    Ok(cpp_out)
+}
+
+// Parts of the runtime that few programs need live in headers of their own,
+// included after builtins.hpp only when the generated code names something
+// they define, so that other programs compile faster
+const OPTIONAL_RUNTIME_HEADERS: &[(&str, &[&str])] = &[
+    ("eppx_generator.hpp", &["eppx_generator"]),
+    ("eppx_asyncio.hpp", &["asyncio::", "eppx_awaitable", "eppx_async_iterator"]),
+    ("eppx_match.hpp", &["eppx_match_"]),
+    ("eppx_file.hpp", &["eppx_open", "eppx_with_file"]),
+];
+
+// Stands in for the optional includes until the whole program has been generated
+const OPTIONAL_RUNTIME_PLACEHOLDER: &str = "// @EPPX_OPTIONAL_RUNTIME@\n";
+
+fn optional_runtime_includes(cpp: &str) -> String {
+    OPTIONAL_RUNTIME_HEADERS
+        .iter()
+        .filter(|(_, names)| names.iter().any(|name| cpp.contains(name)))
+        .map(|(header, _)| format!("#include \"{}\"\n", header))
+        .collect()
+}
+
+// Classes and functions of a program or project module, emitted ahead of the
+// code that uses them; imports are recorded on the way
+fn generate_definitions_cpp(
+    ast_nodes: &[AstNode],
+    symbol_table: &mut SymbolTable,
+    function_table: &mut FunctionTable,
+    type_map: &mut TypeMap,
+) -> Result<String, String> {
+    let mut cpp_out = String::new();
+    // Record every class up front so match statements can refer to classes defined later
+    for node in ast_nodes {
+        if let AstNode::Statement(Statement::ClassDef { name, bases, decorators, body, .. }) = node {
+            pattern::register_class(name, bases, decorators, body, symbol_table)?;
+        }
+    }
+
     // First pass: emit all function definitions and class definitions at the top level
     // This helps with C++'s requirement for declaration before use.
     for node in ast_nodes {
-        match node {            AstNode::Statement(Statement::FunctionDef { name, params, body, decorators }) => {
-                // Check if this is a generator function (contains yield)
-                if contains_yield(body) {
+        match node {            AstNode::Statement(Statement::Import { module, alias }) => {
+                import_module(module, alias, symbol_table)?;
+            }
+            AstNode::Statement(Statement::ImportFrom { module, names }) => {
+                import_from(module, names, symbol_table)?;
+            }
+            AstNode::Statement(Statement::FunctionDef { name, params, body, decorators, is_async }) => {
+                // Generators and coroutines (async def) both compile to resumable frames
+                if *is_async || contains_yield(body) {
                     // Mark this function as a generator before generating code
                     symbol_table.mark_generator_function(name);
                     
@@ -849,7 +1359,7 @@ fn _generate_cpp_code_with_vars(
                         symbol_table.add_variable(p_name, &type_param_name);
                     }
                     
-                    let generator_code = generate_generator_class(name, params, body, symbol_table, function_table, type_map)?;
+                    let generator_code = generator::generate_generator(&cpp_function_name(name), params, body, *is_async, None, symbol_table, function_table, type_map)?;
                     cpp_out.push_str(&generator_code);
                     
                     symbol_table.exit_scope();
@@ -884,12 +1394,16 @@ fn _generate_cpp_code_with_vars(
                 let param_list_cpp = call_params_gen.join(", ");
 
                 // Populate FunctionTable
-                let sig = FunctionSignature { param_types: param_types_for_signature, return_type: "auto".to_string() };
+                let return_type = function_return_type(body);
+                let sig = FunctionSignature { param_types: param_types_for_signature, param_names: params.clone(), return_type: return_type.to_string() };
                 function_table.add_function(name, sig);
 
                 // Function body (symbol_table already has params in its current scope)
                 let mut function_body_declared_vars = HashSet::new();
-                let body_cpp = generate_statement_list_cpp(body, &mut function_body_declared_vars, symbol_table, function_table, type_map)?;
+                let outer_returns_variables = std::mem::replace(&mut symbol_table.returns_variables, return_type == RETURNS_VARIABLE);
+                let body_cpp = generate_statement_list_cpp(body, &mut function_body_declared_vars, symbol_table, function_table, type_map);
+                symbol_table.returns_variables = outer_returns_variables;
+                let body_cpp = body_cpp?;
                 symbol_table.exit_scope(); // End of function scope
 
                 // Add decorator wrapper comments/code
@@ -899,12 +1413,12 @@ fn _generate_cpp_code_with_vars(
                 let return_type = if has_explicit_return_type(body) {
                     analyze_return_type(body)
                 } else {
-                    "long long".to_string() // Default return type for E++ functions
+                    nested_return_type(body).unwrap_or("long long").to_string() // Default return type for E++ functions
                 };
                 
                 cpp_out.push_str(&format!("{}{} {}({}) {{
 ",
- template_clause, return_type, name, param_list_cpp));
+ template_clause, return_type, cpp_function_name(name), param_list_cpp));
                 cpp_out.push_str(&indent_code(&body_cpp));
                 let has_return = body.iter().any(|node| matches!(node, AstNode::Statement(Statement::Return(_))));
                 if !has_return {
@@ -927,202 +1441,658 @@ fn _generate_cpp_code_with_vars(
                 cpp_out.push_str("}
 
 ");
-            }            AstNode::Statement(Statement::ClassDef { name, base, body }) => {
-                // Check if this class has __iter__ and __next__ methods to mark it as a custom iterator
-                let mut has_iter = false;
-                let mut has_next = false;
-                
-                for class_node in body {
-                    if let AstNode::Statement(Statement::FunctionDef { name: method_name, .. }) = class_node {
-                        if method_name == "__iter__" {
-                            has_iter = true;
-                        } else if method_name == "__next__" {
-                            has_next = true;
+            }            AstNode::Statement(Statement::ClassDef { name, bases, keywords, decorators, body }) => {
+                let base_names = class_base_names(name, bases)?;
+                check_class_header(name, keywords, decorators)?;
+                let mut static_defs = Vec::new();
+                cpp_out.push_str(&generate_class_cpp(name, &base_names, body, &mut static_defs, symbol_table, function_table, type_map)?);
+                for static_def in static_defs {
+                    cpp_out.push_str(&static_def);
+                }
+            }
+            _ => {} // Other statement types are handled in the second pass (for main's body)
+        }
+    }
+    Ok(cpp_out)
+}
+
+// Type recorded for the `cls` parameter of __init_subclass__, which is a C++ type rather than a value
+const CLASS_PARAM_TYPE: &str = "<class>";
+
+// Type recorded for `self` in the frame of an async method, where it is a pointer
+// member of the frame rather than `this`
+const FRAME_SELF_TYPE: &str = "<frame self>";
+
+// The C++ pointer to the instance a method runs on
+fn self_cpp(symbol_table: &SymbolTable) -> &'static str {
+    if symbol_table.get_variable("self").is_some_and(|info| info.type_name == FRAME_SELF_TYPE) {
+        "self"
+    } else {
+        "this"
+    }
+}
+
+// Type recorded for variables declared as an eppx_list_ref handle to a shared list
+const LIST_REF_TYPE: &str = "<list>";
+
+// Type recorded for variables declared as an eppx_ref handle to a shared dict or set
+const SHARED_TYPE: &str = "<shared>";
+
+// Type recorded for variables assigned the result of a RETURNS_VARIABLE function,
+// which is a handle when the returned variable held one
+const RESULT_TYPE: &str = "<result>";
+
+// Return type recorded for functions that only return variables: they return
+// the variable's handle when it has one, so `ys = f(xs)` aliases the list
+const RETURNS_VARIABLE: &str = "<variable>";
+
+// Whether a variable may hold a list handle: list variables, results of
+// functions that return a variable, and generic parameters, which receive the
+// handle when the caller passes a list variable
+fn holds_list_ref(info: &VariableInfo) -> bool {
+    info.type_name == LIST_REF_TYPE
+        || info.type_name == RESULT_TYPE
+        || info.type_name.strip_prefix('T').is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+// Whether a variable may hold any handle: a list, dict, set or class instance
+fn holds_ref(info: &VariableInfo) -> bool {
+    holds_list_ref(info) || info.type_name == SHARED_TYPE || info.type_name == INSTANCE_TYPE
+}
+
+// Whether `value` creates a dict, set or class instance, which a new variable
+// holds through an eppx_ref handle
+fn is_new_shared_object(value: &Expression, symbol_table: &SymbolTable) -> bool {
+    match value {
+        Expression::DictLiteral(_)
+        | Expression::SetLiteral(_)
+        | Expression::DictComprehension { .. }
+        | Expression::SetComprehension { .. } => true,
+        Expression::Call { callee, .. } => match &**callee {
+            Expression::Identifier(name) if symbol_table.get_variable(name).is_none() => {
+                name == "dict"
+                    || name == "set"
+                    || (is_user_instance(value, symbol_table) && !symbol_table.is_custom_iterator_class(name))
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// The return type recorded for a function: RETURNS_VARIABLE when every value it
+// returns is a variable and its C++ return type is deduced, otherwise "auto"
+fn function_return_type(body: &[AstNode]) -> &'static str {
+    let values = return_values(body);
+    if analyze_return_type(body) == "auto" && values.iter().all(|value| matches!(value, Expression::Identifier(_))) {
+        RETURNS_VARIABLE
+    } else {
+        "auto"
+    }
+}
+
+// Parameters of runtime module functions that can be passed by keyword, and how
+// many of them are required; the others have defaults in builtins.hpp
+const RUNTIME_MODULE_PARAMS: &[(&str, &[&str], usize)] = &[
+    ("asyncio::sleep", &["delay", "result"], 1),
+    ("asyncio::Queue", &["maxsize"], 0),
+    ("asyncio::Semaphore", &["value"], 0),
+    ("asyncio::create_task", &["coro"], 1),
+    ("asyncio::run", &["main"], 1),
+];
+
+// The callee as written, for error messages
+fn callee_name(callee: &Expression) -> String {
+    match callee {
+        Expression::Identifier(name) => name.clone(),
+        Expression::AttributeAccess { object, attr } => match &**object {
+            Expression::Identifier(object) => format!("{}.{}", object, attr),
+            _ => attr.clone(),
+        },
+        _ => "function".to_string(),
+    }
+}
+
+// The name a called user or module function is recorded under in the function
+// table or RUNTIME_MODULE_PARAMS
+fn callee_qualified_name(callee: &Expression, symbol_table: &SymbolTable) -> Option<String> {
+    match callee {
+        Expression::Identifier(name) if symbol_table.get_variable(name).is_none() => Some(name.clone()),
+        Expression::AttributeAccess { object, attr } => match &**object {
+            Expression::Identifier(module) => Some(format!("{}::{}", symbol_table.get_module(module)?, attr)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Whether a call's result may be a handle (see RETURNS_VARIABLE)
+fn returns_handle(callee: &Expression, symbol_table: &SymbolTable, function_table: &FunctionTable) -> bool {
+    callee_qualified_name(callee, symbol_table)
+        .and_then(|name| function_table.get_function(&name))
+        .is_some_and(|signature| signature.return_type == RETURNS_VARIABLE)
+}
+
+// The parameter names of a called user or module function and how many of them
+// are required; None when they aren't known
+fn callee_params(callee: &Expression, symbol_table: &SymbolTable, function_table: &FunctionTable) -> Option<(Vec<String>, usize)> {
+    let qualified = callee_qualified_name(callee, symbol_table)?;
+    if let Some(signature) = function_table.get_function(&qualified) {
+        return Some((signature.param_names.clone(), signature.param_names.len()));
+    }
+    RUNTIME_MODULE_PARAMS
+        .iter()
+        .find(|(name, ..)| *name == qualified)
+        .map(|(_, params, required)| (params.iter().map(|param| param.to_string()).collect(), *required))
+}
+
+// The arguments of a call in parameter order, with each keyword argument in the
+// slot of the parameter it names
+fn place_keyword_arguments(
+    name: &str,
+    params: &[String],
+    required: usize,
+    args_cpp: Vec<String>,
+    keywords: &[(String, Expression)],
+    keywords_cpp: &HashMap<&str, String>,
+) -> Result<Vec<String>, String> {
+    let mut slots: Vec<Option<String>> = args_cpp.into_iter().map(Some).collect();
+    for (kw_name, _) in keywords {
+        let Some(index) = params.iter().position(|param| param == kw_name) else {
+            return Err(format!("{}() got an unexpected keyword argument '{}'", name, kw_name));
+        };
+        if slots.len() <= index {
+            slots.resize(index + 1, None);
+        }
+        if slots[index].is_some() {
+            return Err(format!("{}() got multiple values for argument '{}'", name, kw_name));
+        }
+        slots[index] = Some(keywords_cpp[kw_name.as_str()].clone());
+    }
+    if let Some(missing) = (0..required.max(slots.len())).find(|&i| slots.get(i).is_none_or(Option::is_none)) {
+        return Err(format!("{}() missing required argument '{}'", name, params[missing]));
+    }
+    Ok(slots.into_iter().flatten().collect())
+}
+
+// Type recorded for variables holding an instance of a user-defined class
+const INSTANCE_TYPE: &str = "<instance>";
+
+// Whether `expr` is known to be an instance of a user-defined class
+fn is_user_instance(expr: &Expression, symbol_table: &SymbolTable) -> bool {
+    match expr {
+        Expression::Identifier(name) if name == "self" => true,
+        Expression::Identifier(name) => symbol_table.get_variable(name).is_some_and(|info| info.type_name == INSTANCE_TYPE),
+        Expression::Call { callee, .. } => matches!(&**callee, Expression::Identifier(name)
+            if symbol_table.get_variable(name).is_none()
+                && symbol_table.get_class(name).is_some_and(|info| info.enum_info.is_none())),
+        _ => false,
+    }
+}
+
+// Emits a variable holding a list, dict, set or instance as its handle rather
+// than its value, so the receiver aliases the object (`xs.append(d)`, `[a, b]`,
+// `xs[0] = d`); other expressions are values
+fn emit_reference_cpp(expr: &Expression, symbol_table: &mut SymbolTable, function_table: &FunctionTable, type_map: &mut TypeMap) -> Result<String, String> {
+    if let Expression::Identifier(name) = expr {
+        if symbol_table.get_variable(name).is_some_and(holds_ref) {
+            return Ok(name.clone());
+        }
+    }
+    emit_expression_cpp(expr, symbol_table, function_table, type_map)
+}
+
+// Emits a variable holding any handle as the handle, and a call that may return
+// a handle without copying its result, so the receiver aliases the object
+// (`e = d`, `f(d)`, `return d`, `ys = f(xs)`); other expressions are values
+fn emit_handle_cpp(expr: &Expression, symbol_table: &mut SymbolTable, function_table: &FunctionTable, type_map: &mut TypeMap) -> Result<String, String> {
+    match expr {
+        Expression::Identifier(name) if symbol_table.get_variable(name).is_some_and(holds_ref) => Ok(name.clone()),
+        Expression::Call { callee, .. } if returns_handle(callee, symbol_table, function_table) => {
+            let call_cpp = emit_expression_cpp(expr, symbol_table, function_table, type_map)?;
+            Ok(call_cpp.strip_prefix("eppx_value(").and_then(|call| call.strip_suffix(')')).unwrap_or(&call_cpp).to_string())
+        }
+        _ => emit_expression_cpp(expr, symbol_table, function_table, type_map),
+    }
+}
+
+// Bases that only matter to type checkers and have no C++ counterpart
+const TYPING_ONLY_BASES: &[&str] = &["object", "Generic", "Protocol", "ABC", "typing.Generic", "typing.Protocol", "abc.ABC"];
+
+// Class decorators and metaclasses that don't change the generated code
+const NO_OP_CLASS_DECORATORS: &[&str] = &["final", "typing.final", "runtime_checkable", "typing.runtime_checkable"];
+const NO_OP_METACLASSES: &[&str] = &["type", "ABCMeta", "abc.ABCMeta"];
+
+// `T = TypeVar("T")` only declares a name for annotations and generic bases, so it emits nothing
+const TYPE_VARIABLE_FACTORIES: &[&str] = &["TypeVar", "typing.TypeVar"];
+
+fn is_type_variable(value: &Expression) -> bool {
+    matches!(value, Expression::Call { callee, .. } if dotted_name(callee).is_some_and(|name| TYPE_VARIABLE_FACTORIES.contains(&name.as_str())))
+}
+
+// `a.b.c` for a chain of attribute accesses on a name
+fn dotted_name(expr: &Expression) -> Option<String> {
+    match expr {
+        Expression::Identifier(name) => Some(name.clone()),
+        Expression::AttributeAccess { object, attr } => Some(format!("{}.{}", dotted_name(object)?, attr)),
+        _ => None,
+    }
+}
+
+// Symbol table key of a class given its C++ name (nested classes are keyed by their own name)
+fn class_key(cpp_name: &str) -> &str {
+    cpp_name.rsplit("::").next().unwrap_or(cpp_name)
+}
+
+// C++ base classes for `class name(bases)`, dropping typing-only bases such as Generic[T]
+fn class_base_names(name: &str, bases: &[Expression]) -> Result<Vec<String>, String> {
+    let mut base_names = Vec::new();
+    for base in bases {
+        let (base_expr, subscripted) = match base {
+            Expression::Index { object, .. } => (&**object, true),
+            _ => (base, false),
+        };
+        let base_name = dotted_name(base_expr).ok_or_else(|| format!("unsupported base class expression for class '{}'", name))?;
+        if TYPING_ONLY_BASES.contains(&base_name.as_str()) || dataclass::is_enum_base(&base_name) {
+            continue;
+        }
+        if subscripted {
+            return Err(format!("generic base '{}[...]' of class '{}' is not supported", base_name, name));
+        }
+        base_names.push(base_name.replace('.', "::"));
+    }
+    Ok(base_names)
+}
+
+// Rejects class decorators and metaclasses the generated C++ can't honour
+fn check_class_header(name: &str, keywords: &[(String, Expression)], decorators: &[Decorator]) -> Result<(), String> {
+    for decorator in decorators {
+        let (Decorator::Simple(decorator_name) | Decorator::WithArgs(decorator_name, _)) = decorator;
+        if !NO_OP_CLASS_DECORATORS.contains(&decorator_name.as_str()) && !dataclass::is_class_decorator(decorator_name) {
+            return Err(format!("class decorator '@{}' on class '{}' is not supported", decorator_name, name));
+        }
+    }
+    for (keyword, value) in keywords {
+        if keyword == "metaclass" && !dotted_name(value).is_some_and(|meta| NO_OP_METACLASSES.contains(&meta.as_str())) {
+            return Err(format!("custom metaclasses are not supported (class '{}')", name));
+        }
+    }
+    Ok(())
+}
+
+// Runs __init_subclass__ of the nearest base that defines it, at the point where the
+// class statement executes; nested classes are created first, as in Python
+fn class_creation_hooks(
+    name: &str,
+    keywords: &[(String, Expression)],
+    body: &[AstNode],
+    symbol_table: &mut SymbolTable,
+    function_table: &FunctionTable,
+    type_map: &mut TypeMap,
+) -> Result<String, String> {
+    let mut out = String::new();
+    for node in body {
+        if let AstNode::Statement(Statement::ClassDef { name: nested_name, keywords: nested_keywords, body: nested_body, .. }) = node {
+            out.push_str(&class_creation_hooks(nested_name, nested_keywords, nested_body, symbol_table, function_table, type_map)?);
+        }
+    }
+    let Some(cpp_name) = symbol_table.get_class(name).map(|info| info.cpp_name.clone()) else {
+        return Ok(out);
+    };
+    let class_keywords: Vec<&(String, Expression)> = keywords.iter().filter(|(keyword, _)| keyword != "metaclass").collect();
+    let owner = symbol_table.init_subclass_owner(name).map(|info| (info.cpp_name.clone(), info.init_subclass_params.clone().unwrap_or_default()));
+    let Some((owner_name, params)) = owner else {
+        if !class_keywords.is_empty() {
+            return Err(format!("{}.__init_subclass__() takes no keyword arguments", name));
+        }
+        return Ok(out);
+    };
+    if let Some((unexpected, _)) = class_keywords.iter().find(|(keyword, _)| !params.contains(keyword)) {
+        return Err(format!("{}.__init_subclass__() got an unexpected keyword argument '{}'", owner_name, unexpected));
+    }
+    let mut args = Vec::new();
+    for param in &params {
+        let (_, value) = class_keywords
+            .iter()
+            .find(|(keyword, _)| keyword == param)
+            .ok_or_else(|| format!("{}.__init_subclass__() missing keyword argument '{}' for class '{}'", owner_name, param, name))?;
+        args.push(emit_expression_cpp(value, symbol_table, function_table, type_map)?);
+    }
+    out.push_str(&format!("{}::__init_subclass__<{}>({});\n", owner_name, cpp_name, args.join(", ")));
+    Ok(out)
+}
+
+// Emits a class as a C++ struct; `qualified_name` is its C++ name including any enclosing classes
+fn generate_class_cpp(
+    qualified_name: &str,
+    bases: &[String],
+    body: &[AstNode],
+    static_defs: &mut Vec<String>,
+    symbol_table: &mut SymbolTable,
+    function_table: &mut FunctionTable,
+    type_map: &mut TypeMap,
+) -> Result<String, String> {
+    let name = qualified_name.rsplit("::").next().unwrap_or(qualified_name);
+    let mut cpp_out = String::new();
+    // Check if this class has __iter__ and __next__ methods to mark it as a custom iterator
+    let mut has_iter = false;
+    let mut has_next = false;
+    
+    for class_node in body {
+        if let AstNode::Statement(Statement::FunctionDef { name: method_name, .. }) = class_node {
+            if method_name == "__iter__" {
+                has_iter = true;
+            } else if method_name == "__next__" {
+                has_next = true;
+            }
+        }
+    }
+    
+    if has_iter && has_next {
+        symbol_table.mark_custom_iterator_class(name);
+    }
+    
+    if bases.is_empty() {
+        cpp_out.push_str(&format!("struct {} {{\n", name));
+    } else {
+        let bases_cpp = bases.iter().map(|base| format!("public {}", base)).collect::<Vec<_>>().join(", ");
+        cpp_out.push_str(&format!("struct {} : {} {{\n", name, bases_cpp));
+    }
+    cpp_out.push_str(&format!("    static inline const std::string __name__ = \"{}\";\n", name));
+    let class_info = symbol_table.get_class(name);
+    let dataclass_info = class_info.and_then(|info| info.dataclass.clone());
+    let enum_info = class_info.and_then(|info| info.enum_info.clone());
+    if let Some(info) = &dataclass_info {
+        cpp_out.push_str(&dataclass::dataclass_members_cpp(qualified_name, info, body));
+    }
+    if let Some(info) = &enum_info {
+        cpp_out.push_str(&dataclass::enum_members_cpp(qualified_name, info, body, static_defs));
+    }
+    // First pass: collect attributes (assignments) and methods
+    let mut constructor_params: Vec<String> = Vec::new();
+    let mut constructor_body: String = String::new();
+    let mut has_init = false;
+    let mut instance_vars: HashSet<String> = HashSet::new();
+    // Members first bound to None or to a list, dict, set or instance hold an
+    // eppx_variant, which can refer to another object and so form a cycle
+    let mut handle_vars: BTreeSet<String> = BTreeSet::new();
+    let mut static_vars: Vec<(String, String, String)> = Vec::new(); // (name, type, value)
+    let mut _has_iter = false;
+    let mut _has_next = false;
+    
+    symbol_table.enter_scope(); // Class scope
+
+    // Scan for instance variables in __init__ method and check for iterator methods
+    for class_node in body {
+        if let AstNode::Statement(Statement::FunctionDef { name: method_name, body: method_body, .. }) = class_node {
+            if method_name == "__init__" {
+                for stmt in method_body {
+                    if let AstNode::Statement(Statement::Assignment { target, value, .. }) = stmt {
+                        if let Expression::AttributeAccess { object, attr } = &**target {
+                            if let Expression::Identifier(obj_name) = &**object {
+                                if obj_name == "self" {
+                                    instance_vars.insert(attr.clone());
+                                    if matches!(&**value, Expression::NoneLiteral | Expression::ListLiteral(_) | Expression::ListComprehension { .. })
+                                        || is_new_shared_object(value, symbol_table)
+                                    {
+                                        handle_vars.insert(attr.clone());
+                                    }
+                                }
+                            }
                         }
                     }
                 }
-                
-                if has_iter && has_next {
-                    symbol_table.mark_custom_iterator_class(name);
+            } else if method_name == "__iter__" {
+                _has_iter = true;
+            } else if method_name == "__next__" {
+                _has_next = true;
+            }
+        }
+    }
+
+    // Dataclass fields are already declared with their annotated types
+    if let Some(info) = &dataclass_info {
+        for field in &info.fields {
+            instance_vars.remove(&field.name);
+        }
+    }
+
+    // Emit instance variable declarations
+    for var in &instance_vars {
+        let member_type = if handle_vars.contains(var) { "eppx_variant" } else { "long long" };
+        cpp_out.push_str(&format!("    {} {};\n", member_type, var));
+    }
+    // The cycle collector reaches other objects through these members
+    handle_vars.retain(|var| instance_vars.contains(var));
+    if !handle_vars.is_empty() {
+        cpp_out.push_str("    void eppx_traverse(const std::function<void(eppx_gc::object*)>& visit) const {\n");
+        for base in bases {
+            cpp_out.push_str(&format!("        eppx_visit_refs(static_cast<const {}&>(*this), visit);\n", base));
+        }
+        for var in &handle_vars {
+            cpp_out.push_str(&format!("        eppx_visit_refs({}, visit);\n", var));
+        }
+        cpp_out.push_str("    }\n");
+        cpp_out.push_str("    void eppx_clear() {\n");
+        for base in bases {
+            cpp_out.push_str(&format!("        eppx_clear_refs(static_cast<{}&>(*this));\n", base));
+        }
+        for var in &handle_vars {
+            cpp_out.push_str(&format!("        eppx_clear_ref({});\n", var));
+        }
+        cpp_out.push_str("    }\n");
+    }                for class_node in body {
+        match class_node {
+            AstNode::Statement(Statement::Assignment { target, operator: AssignmentOperator::Assign, value }) => {
+                // Collect static class variables
+                if let Expression::Identifier(member_name) = &**target {
+                    // __match_args__ is only used by class patterns at compile time; enum members
+                    // are defined by the enum machinery
+                    if !instance_vars.contains(member_name) && member_name != "__match_args__" && enum_info.is_none() {
+                        let value_cpp = emit_expression_cpp(value, symbol_table, function_table, type_map)?;
+                        let type_str = infer_cpp_type_for_static_member(value);
+                        // Emit static declaration
+                        cpp_out.push_str(&format!("    static {} {};\n", type_str, member_name));
+                        // Store for later definition
+                        static_vars.push((member_name.clone(), type_str, value_cpp));
+                    }
+                }
+            }
+            // Annotated class attributes are class variables, except for dataclass fields
+            AstNode::Statement(Statement::AnnotatedAssignment { target, annotation, value: Some(value) })
+                if dataclass_info.is_none() || dataclass::is_class_var(annotation) =>
+            {
+                if let Expression::Identifier(member_name) = &**target {
+                    let value_cpp = emit_expression_cpp(value, symbol_table, function_table, type_map)?;
+                    let type_str = match dataclass::annotation_cpp_type(annotation, symbol_table).as_str() {
+                        "eppx_variant" => infer_cpp_type_for_static_member(value),
+                        annotated => annotated.to_string(),
+                    };
+                    cpp_out.push_str(&format!("    static {} {};\n", type_str, member_name));
+                    static_vars.push((member_name.clone(), type_str, value_cpp));
+                }
+            }
+            AstNode::Statement(Statement::ClassDef { name: nested_name, bases: nested_bases, keywords, decorators, body: nested_body }) => {
+                // Nested classes become nested C++ types
+                let nested_base_names = class_base_names(nested_name, nested_bases)?;
+                check_class_header(nested_name, keywords, decorators)?;
+                let nested_qualified = format!("{}::{}", qualified_name, nested_name);
+                let nested_cpp = generate_class_cpp(&nested_qualified, &nested_base_names, nested_body, static_defs, symbol_table, function_table, type_map)?;
+                cpp_out.push_str(&indent_code(&nested_cpp));
+            }
+            AstNode::Statement(Statement::FunctionDef { name: method_name, params, body: method_body, .. }) if method_name == "__init_subclass__" => {
+                // An implicit classmethod run for every subclass; `cls` is the subclass type
+                symbol_table.enter_scope();
+                let mut template_params = vec!["typename cls".to_string()];
+                let mut params_cpp = Vec::new();
+                for (i, p_name) in params.iter().skip(1).enumerate() {
+                    template_params.push(format!("typename T{}", i));
+                    params_cpp.push(format!("T{} {}", i, p_name));
+                    symbol_table.add_variable(p_name, &format!("T{}", i));
+                }
+                if let Some(cls_name) = params.first() {
+                    symbol_table.add_variable(cls_name, CLASS_PARAM_TYPE);
+                }
+                let mut method_declared_vars = HashSet::new();
+                let body_cpp = generate_statement_list_cpp(method_body, &mut method_declared_vars, symbol_table, function_table, type_map)?;
+                symbol_table.exit_scope();
+                cpp_out.push_str(&format!("    template<{}>\n", template_params.join(", ")));
+                cpp_out.push_str(&format!("    static void __init_subclass__({}) {{\n", params_cpp.join(", ")));
+                cpp_out.push_str(&indent_code(&body_cpp));
+                cpp_out.push_str("    }\n");
+            }
+            AstNode::Statement(Statement::FunctionDef { is_async: true, name: method_name, params, body: method_body, .. }) => {
+                // Coroutine methods get a frame class nested in the class, which holds `self`
+                let params: Vec<String> = params.iter().filter(|p| **p != "self").cloned().collect();
+                let frame_cpp = generator::generate_generator(method_name, &params, method_body, true, Some(name), symbol_table, function_table, type_map)?;
+                cpp_out.push_str("public:\n");
+                cpp_out.push_str(&indent_code(&frame_cpp));
+            }
+            AstNode::Statement(Statement::FunctionDef { name: method_name, params, body: method_body, .. }) => {
+                symbol_table.enter_scope(); // Method scope
+                for p_name in params.iter().filter(|p| **p != "self") {
+                    symbol_table.add_variable(p_name, "long long");
                 }
-                
-                if let Some(base_name) = base {
-                    cpp_out.push_str(&format!("struct {} : public {} {{\n", name, base_name));
+
+                let mut method_declared_vars = HashSet::new();
+                let body_cpp = generate_statement_list_cpp(method_body, &mut method_declared_vars, symbol_table, function_table, type_map)?;
+                if method_name == "__init__" {
+                    has_init = true;
+                    let params_cpp: Vec<String> = params.iter().filter(|p| **p != "self").map(|p| format!("long long {}", p)).collect();
+                    constructor_params = params_cpp;
+                    constructor_body = indent_code(&body_cpp);
+                } else if method_name == "__enter__" {
+                    // Always public, correct signature
+                    cpp_out.push_str("public:\n");
+                    cpp_out.push_str("    auto& __enter__() {\n");
+                    // Replace 'return this;' with 'return *this;' in the body
+                    let body_cpp_fixed = body_cpp.replace("return this;", "return *this;");
+                    cpp_out.push_str(&body_cpp_fixed);
+                    cpp_out.push_str("    }\n");
+                } else if method_name == "__exit__" {
+                    // Always public, correct signature
+                    cpp_out.push_str("public:\n");
+                    cpp_out.push_str("    bool __exit__(const std::string& exc_type = \"\", const std::string& exc_val = \"\", const std::string& exc_tb = \"\") {\n");
+                    cpp_out.push_str(&indent_code(&body_cpp));
+                    cpp_out.push_str("    }\n");
                 } else {
-                    cpp_out.push_str(&format!("struct {} {{\n", name));
-                }// First pass: collect attributes (assignments) and methods
-                let mut constructor_params: Vec<String> = Vec::new();
-                let mut constructor_body: String = String::new();
-                let mut has_init = false;
-                let mut instance_vars: HashSet<String> = HashSet::new();
-                let mut static_vars: Vec<(String, String, String)> = Vec::new(); // (name, type, value)
-                let mut _has_iter = false;
-                let mut _has_next = false;
-                
-                symbol_table.enter_scope(); // Class scope
-
-                // Scan for instance variables in __init__ method and check for iterator methods
-                for class_node in body {
-                    if let AstNode::Statement(Statement::FunctionDef { name: method_name, body: method_body, .. }) = class_node {
-                        if method_name == "__init__" {
-                            for stmt in method_body {
-                                if let AstNode::Statement(Statement::Assignment { target, .. }) = stmt {
-                                    if let Expression::AttributeAccess { object, attr } = &**target {
-                                        if let Expression::Identifier(obj_name) = &**object {
-                                            if obj_name == "self" {
-                                                instance_vars.insert(attr.clone());
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        } else if method_name == "__iter__" {
-                            _has_iter = true;
-                        } else if method_name == "__next__" {
-                            _has_next = true;
-                        }
+                    let mut return_type = infer_return_type_from_body(method_body, method_name, bases);
+                    if method_name == "__iter__" {
+                        return_type = format!("{}*", name);
+                    } else if method_name == "__next__" {
+                        return_type = "long long".to_string();
+                    }
+                    let is_override = !bases.is_empty();
+                    let is_private = method_name.starts_with('_') && method_name != "__str__" && method_name != "__init__" && method_name != "__iter__" && method_name != "__next__";
+                    let virtual_str = if !is_override && method_name != "__iter__" { "virtual " } else { "" };
+                    let override_str = if is_override { " override" } else { "" };
+                    if is_private {
+                        cpp_out.push_str("private:\n");
+                    }
+                    let params_cpp = params.iter().filter(|p| **p != "self").map(|p| format!("long long {}", p)).collect::<Vec<_>>().join(", ");
+                    cpp_out.push_str(&format!("    {}{} {}({}){} {{\n", virtual_str, return_type, method_name, params_cpp, override_str));
+                    cpp_out.push_str(&indent_code(&body_cpp));
+                    let has_any_return = method_body.iter().any(|node| matches!(node, AstNode::Statement(Statement::Return(_))));
+                    if !has_any_return && return_type == "std::string" {
+                        cpp_out.push_str("        return \"\";\n");
                     }
-                }
-
-                // Emit instance variable declarations
-                for var in &instance_vars {
-                    cpp_out.push_str(&format!("    long long {};\n", var));
-                }                for class_node in body {
-                    match class_node {
-                        AstNode::Statement(Statement::Assignment { target, operator: AssignmentOperator::Assign, value }) => {
-                            // Collect static class variables
-                            if let Expression::Identifier(member_name) = &**target {
-                                if !instance_vars.contains(member_name) {
-                                    let value_cpp = emit_expression_cpp(value, symbol_table, function_table, type_map)?;
-                                    let type_str = infer_cpp_type_for_static_member(value);
-                                    // Emit static declaration
-                                    cpp_out.push_str(&format!("    static {} {};\n", type_str, member_name));
-                                    // Store for later definition
-                                    static_vars.push((member_name.clone(), type_str, value_cpp));
-                                }
-                            }
-                        }
-                        AstNode::Statement(Statement::FunctionDef { name: method_name, params, body: method_body, .. }) => {
-                            symbol_table.enter_scope(); // Method scope
-                            for p_name in params.iter().filter(|p| **p != "self") {
-                                symbol_table.add_variable(p_name, "long long");
-                            }
-
-                            let mut method_declared_vars = HashSet::new();
-                            let body_cpp = generate_statement_list_cpp(method_body, &mut method_declared_vars, symbol_table, function_table, type_map)?;
-                            if method_name == "__init__" {
-                                has_init = true;
-                                let params_cpp: Vec<String> = params.iter().filter(|p| **p != "self").map(|p| format!("long long {}", p)).collect();
-                                constructor_params = params_cpp;
-                                constructor_body = indent_code(&body_cpp);
-                            } else if method_name == "__enter__" {
-                                // Always public, correct signature
-                                cpp_out.push_str("public:\n");
-                                cpp_out.push_str(&format!("    auto& __enter__() {{\n"));
-                                // Replace 'return this;' with 'return *this;' in the body
-                                let body_cpp_fixed = body_cpp.replace("return this;", "return *this;");
-                                cpp_out.push_str(&body_cpp_fixed);
-                                cpp_out.push_str("    }\n");
-                            } else if method_name == "__exit__" {
-                                // Always public, correct signature
-                                cpp_out.push_str("public:\n");
-                                cpp_out.push_str(&format!("    bool __exit__(const std::string& exc_type = \"\", const std::string& exc_val = \"\", const std::string& exc_tb = \"\") {{\n"));
-                                cpp_out.push_str(&indent_code(&body_cpp));
-                                cpp_out.push_str("    }\n");
-                            } else {
-                                let mut return_type = infer_return_type_from_body(method_body, method_name, base);
-                                if method_name == "__iter__" {
-                                    return_type = format!("{}*", name);
-                                } else if method_name == "__next__" {
-                                    return_type = "long long".to_string();
-                                }
-                                let is_override = base.is_some();
-                                let is_private = method_name.starts_with('_') && method_name != "__str__" && method_name != "__init__" && method_name != "__iter__" && method_name != "__next__";
-                                let virtual_str = if !is_override && method_name != "__iter__" { "virtual " } else { "" };
-                                let override_str = if is_override { " override" } else { "" };
-                                if is_private {
-                                    cpp_out.push_str("private:\n");
-                                }
-                                let params_cpp = params.iter().filter(|p| **p != "self").map(|p| format!("long long {}", p)).collect::<Vec<_>>().join(", ");
-                                cpp_out.push_str(&format!("    {}{} {}({}){} {{\n", virtual_str, return_type, method_name, params_cpp, override_str));
-                                cpp_out.push_str(&indent_code(&body_cpp));
-                                let has_any_return = method_body.iter().any(|node| matches!(node, AstNode::Statement(Statement::Return(_))));
-                                if !has_any_return {
-                                    if return_type == "std::string" {
-                                        cpp_out.push_str("        return \"\";\n");
-                                    }
-                                }
-                                cpp_out.push_str("    }\n");
-                                if is_private {
-                                    cpp_out.push_str("public:\n");
-                                }
-                            }
-                            symbol_table.exit_scope(); // Exit method scope
-                        }
-                        _ => { /* Ignore other statements for now */ }
+                    cpp_out.push_str("    }\n");
+                    if is_private {
+                        cpp_out.push_str("public:\n");
                     }
                 }
+                symbol_table.exit_scope(); // Exit method scope
+            }
+            _ => { /* Ignore other statements for now */ }
+        }
+    }
 
-                if has_init {
-                    cpp_out.push_str(&format!("    {}({}) {{
+    if has_init {
+        cpp_out.push_str(&format!("    {}({}) {{
 ",
  name, constructor_params.join(", ")));
-                    cpp_out.push_str(&constructor_body);
-                    cpp_out.push_str("    }\n");
-                }
-
-                // Add a default constructor if no __init__ is defined
-                if !has_init {
-                    if instance_vars.is_empty() {
-                        cpp_out.push_str(&format!("    {}() {{}}\n", name));
-                    } else {
-                        cpp_out.push_str(&format!("    {}() {{ ", name));
-                        let mut first = true;
-                        for var in &instance_vars {
-                            if !first {
-                                cpp_out.push_str("; ");
-                            }
-                            cpp_out.push_str(&format!("{} = 0", var));
-                            first = false;
-                        }
-                        cpp_out.push_str("; }\n");
-                    }
-                }
-
-                // --- Encapsulation: public/private sections ---
-                cpp_out.push_str("public:\n");
-                // ---
+        cpp_out.push_str(&constructor_body);
+        cpp_out.push_str("    }\n");
+        // Coroutine frames hold their locals as members, which starts them out empty
+        if !constructor_params.is_empty() {
+            cpp_out.push_str(&format!("    {}() = default;\n", name));
+            // Generator and coroutine locals are variants, converted to the parameters' type
+            cpp_out.push_str(&format!(
+                "    template<typename... Args, typename = std::enable_if_t<sizeof...(Args) == {} && (std::is_same_v<Args, eppx_variant> || ...)>>\n",
+                constructor_params.len()
+            ));
+            cpp_out.push_str(&format!("    {}(const Args&... args) : {}(eppx_param(args)...) {{}}\n", name, name));
+        }
+    }
 
-                cpp_out.push_str("};\n");
-                
-                // Define static class variables outside the class
-                for (var_name, var_type, var_value) in static_vars {
-                    cpp_out.push_str(&format!("{} {}::{} = {};\n", var_type, name, var_name, var_value));
+    // Add a default constructor if no __init__ is defined
+    if !has_init {
+        if instance_vars.is_empty() {
+            cpp_out.push_str(&format!("    {}() {{}}\n", name));
+        } else {
+            cpp_out.push_str(&format!("    {}() {{ ", name));
+            let mut first = true;
+            for var in &instance_vars {
+                if !first {
+                    cpp_out.push_str("; ");
                 }
-                
-                symbol_table.exit_scope(); // Exit class scope
+                cpp_out.push_str(&format!("{} = 0", var));
+                first = false;
             }
-            _ => {} // Other statement types are handled in the second pass (for main's body)
+            cpp_out.push_str("; }\n");
         }
     }
-    if is_toplevel {
-        cpp_out.push_str("int main() {\n");
-        let main_body_cpp = generate_statement_list_cpp(ast_nodes, declared_vars, symbol_table, function_table, type_map)?;
-        cpp_out.push_str(&main_body_cpp);
-        cpp_out.push_str("    return 0;\n}\n");
+
+    // --- Encapsulation: public/private sections ---
+    cpp_out.push_str("public:\n");
+    // ---
+
+    cpp_out.push_str("};\n");
+    
+    // Static class variables are defined after the outermost class is complete
+    for (var_name, var_type, var_value) in static_vars {
+        static_defs.push(format!("{} {}::{} = {};\n", var_type, qualified_name, var_name, var_value));
     }
+    symbol_table.exit_scope(); // Exit class scope
     Ok(cpp_out)
 }
 
-pub fn generate_cpp_code(ast_nodes: &[AstNode]) -> Result<String, String> {
+// A program made of an entry file and the project modules it can import
+pub fn generate_program_cpp(ast_nodes: &[AstNode], modules: &[ProjectModule], options: &CodegenOptions) -> Result<GeneratedProgram, String> {
     // Initialize tables here as well if this is an alternative entry point
     let mut symbol_table = SymbolTable::new();
+    symbol_table.strip_asserts = options.strip_asserts;
+    symbol_table.project_modules = modules.iter().map(|module| module.name.clone()).collect();
     let mut function_table = FunctionTable::new();
     let mut type_map = TypeMap::new();
     let mut declared_vars = HashSet::new(); // declared_vars for the main/global scope
-    _generate_cpp_code_with_vars(ast_nodes, true, &mut declared_vars, &mut symbol_table, &mut function_table, &mut type_map)
+    let cpp = _generate_cpp_code_with_vars(ast_nodes, true, modules, &mut declared_vars, &mut symbol_table, &mut function_table, &mut type_map)?;
+    Ok(GeneratedProgram { cpp, warnings: symbol_table.warnings.take() })
+}
+
+/// Debug formatting keeps the ".0" so `5.0` stays a double; literals such as
+/// `1e999` overflow to infinity, which C++ has no literal for.
+fn float_literal_cpp(value: f64) -> String {
+    if value.is_infinite() {
+        let sign = if value < 0.0 { "-" } else { "" };
+        format!("{}std::numeric_limits<double>::infinity()", sign)
+    } else {
+        format!("{:?}", value)
+    }
 }
 
+/// The builtin functions `emit_expression_cpp` maps onto the runtime, in the
+/// order its call handling checks them
+pub const BUILTIN_FUNCTIONS: &[&str] = &[
+    "print", "range", "abs", "complex", "pow", "max", "min", "round", "int", "float", "bool", "str", "len",
+    "chr", "ord", "hex", "bin", "oct", "sum", "all", "any", "reversed", "iter", "next", "list", "tuple",
+    "dict", "set", "input", "open", "type", "isinstance", "callable", "id", "hasattr", "getattr", "setattr",
+    "delattr", "hash", "enumerate", "zip", "map", "filter", "sorted", "ascii", "breakpoint", "bytearray",
+    "bytes", "classmethod", "compile", "dir", "divmod", "eval", "exec", "format", "globals", "help",
+    "issubclass", "locals", "memoryview", "object", "property", "repr", "slice", "staticmethod", "super",
+    "vars", "__import__",
+];
+
 pub fn emit_expression_cpp(
     expr: &Expression,
     symbol_table: &mut SymbolTable,
@@ -1139,10 +2109,24 @@ pub fn emit_expression_cpp(
             Ok(format!("std::string(\"{}\")", escaped_s))
         }
         Expression::IntegerLiteral(i) => Ok(format!("{}LL", i)), // Suffix LL for long long
-        Expression::FloatLiteral(f) => Ok(format!("{}", f)), // Float literals
+        Expression::FloatLiteral(f) => Ok(float_literal_cpp(*f)),
         Expression::NoneLiteral => Ok("nullptr".to_string()),
         Expression::BooleanLiteral(b) => Ok(format!("{}", b)),
         Expression::Identifier(name) => {
+            // A variable in scope shadows the builtin of the same name (e.g. a `max` parameter)
+            if name != "self" {
+                if let Some(info) = symbol_table.get_variable(name) {
+                    // Handles are dereferenced wherever the object itself is used
+                    if holds_ref(info) {
+                        return Ok(format!("eppx_items({})", name));
+                    }
+                    return Ok(name.clone());
+                }
+            }
+            // An enum class used as a value iterates over its members
+            if let Some(info) = symbol_table.get_class(name).filter(|info| info.enum_info.is_some()) {
+                return Ok(format!("{}::__eppx_members__()", info.cpp_name));
+            }
             // Handle builtin functions
             match name.as_str() {
                 "sum" => Ok("eppx_sum".to_string()),
@@ -1157,9 +2141,9 @@ pub fn emit_expression_cpp(
                 "min" => Ok("eppx_min".to_string()),
                 "enumerate" => Ok("eppx_enumerate".to_string()),
                 "bytes" => Ok("eppx_bytes".to_string()),
-                "self" => Ok("this".to_string()),
+                "self" => Ok(self_cpp(symbol_table).to_string()),
                 "StopIteration" => Ok("StopIterationException".to_string()),
-                _ => Ok(name.clone()),
+                _ => Ok(cpp_function_name(name)),
             }
         },        Expression::UnaryOperation { op, operand } => {
             let operand_cpp = emit_expression_cpp(operand, symbol_table, function_table, type_map)?;
@@ -1177,7 +2161,7 @@ pub fn emit_expression_cpp(
             } else {
                 let mut elements_cpp = Vec::new();
                 for el in elements {
-                    elements_cpp.push(emit_expression_cpp(el, symbol_table, function_table, type_map)?);
+                    elements_cpp.push(emit_reference_cpp(el, symbol_table, function_table, type_map)?);
                 }
                 // Use std::vector<eppx_variant> to ensure all elements are compatible
                 Ok(format!("std::vector<eppx_variant>{{{}}}", elements_cpp.join(", ")))
@@ -1186,7 +2170,11 @@ pub fn emit_expression_cpp(
         Expression::AttributeAccess { object, attr } => {
             if let Expression::Identifier(name) = &**object {
                 if name == "self" {
-                    return Ok(format!("this->{}", attr));
+                    return Ok(format!("{}->{}", self_cpp(symbol_table), attr));
+                }
+                // `cls` in __init_subclass__ is a type parameter
+                if symbol_table.get_variable(name).is_some_and(|info| info.type_name == CLASS_PARAM_TYPE) {
+                    return Ok(format!("{}::{}", name, attr));
                 }
                 // Class attribute access: ClassName.x
                 // If the identifier is a class name, emit ClassName::x
@@ -1194,22 +2182,68 @@ pub fn emit_expression_cpp(
                 if name.chars().next().map(|c| c.is_uppercase()).unwrap_or(false) {
                     return Ok(format!("{}::{}", name, attr));
                 }
+                // Imported runtime module: asyncio.sleep -> asyncio::sleep
+                if let Some(module) = symbol_table.get_module(name) {
+                    return Ok(format!("{}::{}", module, attr));
+                }
             }
             let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
             Ok(format!("{}.{}", object_cpp, attr))
         }
         Expression::Index { object, index } => {
+            // Color["RED"] looks an enum member up by name
+            if let Expression::Identifier(name) = &**object {
+                let enum_class = symbol_table.get_class(name).filter(|info| info.enum_info.is_some()).map(|info| info.cpp_name.clone());
+                if let Some(cpp_name) = enum_class.filter(|_| symbol_table.get_variable(name).is_none()) {
+                    let index_cpp = emit_expression_cpp(index, symbol_table, function_table, type_map)?;
+                    return Ok(format!("{}::__eppx_lookup_name__({})", cpp_name, index_cpp));
+                }
+            }
             let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
             let index_cpp = emit_expression_cpp(index, symbol_table, function_table, type_map)?;
             Ok(format!("eppx_index({}, {})", object_cpp, index_cpp))
         }
-        Expression::Call { callee, args } => {
+        // Inside generator functions these are lowered by the generator state machine
+        Expression::Yield(_) | Expression::YieldFrom(_) => {
+            Err("yield is only supported as a statement or as the value of an assignment inside a generator function".to_string())
+        }
+        // Inside async functions awaits are lowered by the coroutine state machine
+        Expression::Await(_) => Err("'await' outside async function".to_string()),
+        Expression::Call { callee, args, keywords } => {
             let mut args_cpp = Vec::new();
             for arg in args {
                 args_cpp.push(emit_expression_cpp(arg, symbol_table, function_table, type_map)?);
-            }            // Handle special built-in functions first
+            }
+            let mut keywords_cpp = HashMap::new();
+            for (kw_name, kw_value) in keywords {
+                keywords_cpp.insert(kw_name.as_str(), emit_expression_cpp(kw_value, symbol_table, function_table, type_map)?);
+            }
+            // Dataclass constructors and enum lookups by value are resolved at compile time
+            if let Expression::Identifier(name) = &**callee {
+                let class_info = symbol_table.get_variable(name).is_none().then(|| symbol_table.get_class(name).cloned()).flatten();
+                if let Some(info) = class_info {
+                    if let Some(dataclass) = info.dataclass.as_ref().filter(|d| d.options.init && !d.has_init) {
+                        let ctor_args = dataclass::constructor_args_cpp(name, dataclass, &args_cpp, keywords, symbol_table, function_table, type_map)?;
+                        return Ok(format!("{}({})", info.cpp_name, ctor_args.join(", ")));
+                    }
+                    if info.enum_info.is_some() {
+                        if args_cpp.len() != 1 || !keywords.is_empty() {
+                            return Err(format!("{}() takes exactly one value to look up", name));
+                        }
+                        return Ok(format!("{}::__eppx_lookup__({})", info.cpp_name, args_cpp[0]));
+                    }
+                }
+            }
+            // Handle special built-in functions first
             if let Expression::Identifier(name) = &**callee {
                 match name.as_str() {
+                    // StopIteration(value) carries a return value, read back as `e.value`
+                    "StopIteration" if args.len() <= 1 => {
+                        return Ok(match args_cpp.first() {
+                            Some(value) => format!("StopIterationException(eppx_to_variant({}))", value),
+                            None => "StopIterationException()".to_string(),
+                        });
+                    }
                     // Print function
                     "print" => {
                         return Ok(format!("eppx_print({})", args_cpp.join(", ")));
@@ -1224,6 +2258,9 @@ pub fn emit_expression_cpp(
                     "abs" if args.len() == 1 => {
                         return Ok(format!("eppx_abs({})", args_cpp[0]));
                     }
+                    "complex" if args.len() <= 2 => {
+                        return Ok(format!("eppx_make_complex({})", args_cpp.join(", ")));
+                    }
                     "pow" if args.len() == 2 => {
                         return Ok(format!("std::pow({}, {})", args_cpp[0], args_cpp[1]));
                     }                    "max" if args.len() >= 2 => {
@@ -1247,7 +2284,7 @@ pub fn emit_expression_cpp(
                         return Ok(format!("static_cast<bool>({})", args_cpp[0]));
                     }
                     "str" if args.len() == 1 => {
-                        return Ok(format!("std::to_string({})", args_cpp[0]));
+                        return Ok(format!("eppx_str({})", args_cpp[0]));
                     }
                     
                     // String functions
@@ -1296,19 +2333,19 @@ pub fn emit_expression_cpp(
                     }
                     
                     // Collection constructors
-                    "list" if args.len() == 0 => {
+                    "list" if args.is_empty() => {
                         return Ok("std::vector<eppx_variant>{}".to_string());
                     }
                     "list" if args.len() == 1 => {
                         return Ok(format!("eppx_to_list({})", args_cpp[0]));
                     }
-                    "tuple" if args.len() == 0 => {
+                    "tuple" if args.is_empty() => {
                         return Ok("std::tuple<>{}".to_string());
                     }
-                    "dict" if args.len() == 0 => {
-                        return Ok("std::map<eppx_variant, eppx_variant>{}".to_string());
+                    "dict" if args.is_empty() => {
+                        return Ok("eppx_dict<eppx_variant, eppx_variant>{}".to_string());
                     }
-                    "set" if args.len() == 0 => {
+                    "set" if args.is_empty() => {
                         return Ok("std::set<eppx_variant>{}".to_string());
                     }
                     "set" if args.len() == 1 => {
@@ -1316,7 +2353,7 @@ pub fn emit_expression_cpp(
                     }
                     
                     // I/O functions
-                    "input" if args.len() == 0 => {
+                    "input" if args.is_empty() => {
                         return Ok("eppx_input()".to_string());
                     }
                     "input" if args.len() == 1 => {
@@ -1324,7 +2361,7 @@ pub fn emit_expression_cpp(
                     }
                     
                     // File I/O functions
-                    "open" if args.len() >= 1 && args.len() <= 7 => {
+                    "open" if !args.is_empty() && args.len() <= 7 => {
                         let mut open_args = vec![args_cpp[0].clone()];
                         if args.len() >= 2 { open_args.push(args_cpp[1].clone()); } else { open_args.push("\"r\"".to_string()); }
                         if args.len() >= 3 { open_args.push(args_cpp[2].clone()); } else { open_args.push("-1".to_string()); }
@@ -1348,7 +2385,7 @@ pub fn emit_expression_cpp(
                     
                     // Object introspection
                     "id" if args.len() == 1 => {
-                        return Ok(format!("eppx_id({})", args_cpp[0]));
+                        return Ok(format!("eppx_id({})", emit_handle_cpp(&args[0], symbol_table, function_table, type_map)?));
                     }
                     "hasattr" if args.len() == 2 => {
                         return Ok(format!("eppx_hasattr({}, {})", args_cpp[0], args_cpp[1]));
@@ -1387,24 +2424,29 @@ pub fn emit_expression_cpp(
                     "filter" if args.len() == 2 => {
                         return Ok(format!("eppx_filter({}, {})", args_cpp[0], args_cpp[1]));
                     }
-                    "sorted" if args.len() == 1 => {
+                    "sorted" if args.len() == 1 && keywords.is_empty() => {
                         return Ok(format!("eppx_sorted({})", args_cpp[0]));
                     }
+                    "sorted" if args.len() == 1 => {
+                        let key = emit_key_function_cpp(keywords, symbol_table, function_table, type_map)?;
+                        let reverse = keywords_cpp.get("reverse").cloned().unwrap_or_else(|| "false".to_string());
+                        return Ok(format!("eppx_sorted({}, {}, {})", args_cpp[0], key, reverse));
+                    }
                     
                     // Newly implemented builtin functions
                     "ascii" if args.len() == 1 => {
                         return Ok(format!("eppx_ascii({})", args_cpp[0]));
                     }
-                    "breakpoint" if args.len() == 0 => {
+                    "breakpoint" if args.is_empty() => {
                         return Ok("eppx_breakpoint()".to_string());
                     }
-                    "bytearray" if args.len() == 0 => {
+                    "bytearray" if args.is_empty() => {
                         return Ok("eppx_bytearray()".to_string());
                     }
                     "bytearray" if args.len() == 1 => {
                         return Ok(format!("eppx_bytearray({})", args_cpp[0]));
                     }
-                    "bytes" if args.len() == 0 => {
+                    "bytes" if args.is_empty() => {
                         return Ok("eppx_bytes()".to_string());
                     }
                     "bytes" if args.len() == 1 => {
@@ -1437,10 +2479,10 @@ pub fn emit_expression_cpp(
                     "format" if args.len() == 2 => {
                         return Ok(format!("eppx_format({}, {})", args_cpp[0], args_cpp[1]));
                     }
-                    "globals" if args.len() == 0 => {
+                    "globals" if args.is_empty() => {
                         return Ok("eppx_globals()".to_string());
                     }
-                    "help" if args.len() == 0 => {
+                    "help" if args.is_empty() => {
                         return Ok("eppx_help()".to_string());
                     }
                     "help" if args.len() == 1 => {
@@ -1449,13 +2491,13 @@ pub fn emit_expression_cpp(
                     "issubclass" if args.len() == 2 => {
                         return Ok(format!("eppx_issubclass({}, {})", args_cpp[0], args_cpp[1]));
                     }
-                    "locals" if args.len() == 0 => {
+                    "locals" if args.is_empty() => {
                         return Ok("eppx_locals()".to_string());
                     }
                     "memoryview" if args.len() == 1 => {
                         return Ok(format!("eppx_memoryview({})", args_cpp[0]));
                     }
-                    "object" if args.len() == 0 => {
+                    "object" if args.is_empty() => {
                         return Ok("eppx_object()".to_string());
                     }
                     "property" if args.len() == 1 => {
@@ -1464,16 +2506,16 @@ pub fn emit_expression_cpp(
                     "repr" if args.len() == 1 => {
                         return Ok(format!("eppx_repr({})", args_cpp[0]));
                     }
-                    "slice" if args.len() >= 1 => {
+                    "slice" if !args.is_empty() => {
                         return Ok(format!("eppx_slice({})", args_cpp.join(", ")));
                     }
                     "staticmethod" if args.len() == 1 => {
                         return Ok(format!("eppx_staticmethod({})", args_cpp[0]));
                     }
-                    "super" if args.len() == 0 => {
+                    "super" if args.is_empty() => {
                         return Ok("eppx_super()".to_string());
                     }
-                    "vars" if args.len() == 0 => {
+                    "vars" if args.is_empty() => {
                         return Ok("eppx_vars()".to_string());
                     }
                     "vars" if args.len() == 1 => {
@@ -1487,9 +2529,11 @@ pub fn emit_expression_cpp(
                 }
             }
             
-            // Handle file method calls
+            // Handle file method calls. A user class's own methods are called as written,
+            // even when they share a name with a container or file method (`deck.sort()`)
             if let Expression::AttributeAccess { object, attr } = &**callee {
-                match attr.as_str() {
+                let attr = if is_user_instance(object, symbol_table) { "" } else { attr.as_str() };
+                match attr {
                     "read" => {
                         let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
                         if args.is_empty() {
@@ -1523,8 +2567,19 @@ pub fn emit_expression_cpp(
                         }
                     }
                     "close" => {
+                        // eppx_method_close closes generators and falls back to `obj->close()` for files
+                        let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
+                        return Ok(format!("eppx_method_close({})", object_cpp));
+                    }
+                    // Generator protocol
+                    "send" if args.len() == 1 => {
+                        let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
+                        return Ok(format!("eppx_method_send({}, eppx_to_variant({}))", object_cpp, args_cpp[0]));
+                    }
+                    "throw" if args.len() == 1 => {
                         let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
-                        return Ok(format!("{}->close()", object_cpp));
+                        let exception_cpp = generator::emit_exception_ptr_cpp(&args[0], symbol_table, function_table, type_map)?;
+                        return Ok(format!("eppx_method_throw({}, {})", object_cpp, exception_cpp));
                     }
                     "flush" => {
                         let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
@@ -1552,50 +2607,81 @@ pub fn emit_expression_cpp(
                         let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
                         return Ok(format!("eppx_len({})", object_cpp));
                     }
-                    // List methods
-                    "append" => {
-                        let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
-                        if !args.is_empty() {
-                            return Ok(format!("{}.push_back({})", object_cpp, args_cpp[0]));
-                        }
-                    }
-                    "extend" => {
-                        let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
-                        if !args.is_empty() {
-                            return Ok(format!("{}.insert({}.end(), {}.begin(), {}.end())", object_cpp, object_cpp, args_cpp[0], args_cpp[0]));
-                        }
-                    }
-                    "pop" => {
+                    // Container methods (list, dict, set): overloaded runtime helpers
+                    // pick the implementation from the C++ type of the receiver.
+                    "append" | "extend" | "insert" | "pop" | "remove" | "index" | "count" | "reverse"
+                    | "copy" | "clear" | "keys" | "values" | "items" | "get" | "setdefault"
+                    | "add" | "discard" | "union" | "intersection" | "difference" => {
                         let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
-                        if args.is_empty() {
-                            return Ok(format!("({}.empty() ? 0LL : ({}.back(), {}.pop_back(), {}.back()))", object_cpp, object_cpp, object_cpp, object_cpp));
-                        } else {
-                            return Ok(format!("({}.size() > {} ? ({}.erase({}.begin() + {}), {}.at({})) : 0LL)", object_cpp, args_cpp[0], object_cpp, object_cpp, args_cpp[0], object_cpp, args_cpp[0]));
+                        let mut method_args = vec![object_cpp];
+                        // Stored items keep referring to the same list
+                        if matches!(attr, "append" | "insert") {
+                            args_cpp = args.iter()
+                                .map(|arg| emit_reference_cpp(arg, symbol_table, function_table, type_map))
+                                .collect::<Result<_, _>>()?;
                         }
+                        method_args.extend(args_cpp);
+                        return Ok(format!("eppx_method_{}({})", attr, method_args.join(", ")));
                     }
-                    "insert" => {
+                    "sort" => {
+                        // Without keywords the call also suits a `sort()` method of a class
+                        // instance passed in as a parameter
                         let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
-                        if args.len() >= 2 {
-                            return Ok(format!("{}.insert({}.begin() + {}, {})", object_cpp, object_cpp, args_cpp[0], args_cpp[1]));
+                        let mut method_args = vec![object_cpp];
+                        method_args.extend(args_cpp);
+                        if !keywords.is_empty() {
+                            method_args.push(emit_key_function_cpp(keywords, symbol_table, function_table, type_map)?);
+                            method_args.push(keywords_cpp.get("reverse").cloned().unwrap_or_else(|| "false".to_string()));
                         }
+                        return Ok(format!("eppx_method_sort({})", method_args.join(", ")));
                     }
-                    "remove" => {
+                    "update" => {
                         let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
-                        if !args.is_empty() {
-                            return Ok(format!("{}.erase(std::find({}.begin(), {}.end(), {}))", object_cpp, object_cpp, object_cpp, args_cpp[0]));
+                        let mut method_args = vec![object_cpp];
+                        method_args.extend(args_cpp);
+                        if !keywords.is_empty() {
+                            // d.update(a=1, b=2) merges a str-keyed dict built from the keywords
+                            let entries = keywords.iter()
+                                .map(|(kw_name, _)| format!("{{std::string(\"{}\"), {}}}", kw_name, keywords_cpp[kw_name.as_str()]))
+                                .collect::<Vec<_>>()
+                                .join(", ");
+                            method_args.push(format!("eppx_dict<eppx_variant, eppx_variant>{{{}}}", entries));
                         }
-                    }
-                    "clear" => {
-                        let object_cpp = emit_expression_cpp(object, symbol_table, function_table, type_map)?;
-                        return Ok(format!("{}.clear()", object_cpp));
+                        return Ok(format!("eppx_method_update({})", method_args.join(", ")));
                     }
                     _ => {}
                 }
             }
             
+            let module_call = matches!(&**callee, Expression::AttributeAccess { object, .. }
+                if matches!(&**object, Expression::Identifier(name) if symbol_table.get_module(name).is_some()));
+            // User functions and runtime modules receive lists, dicts, sets and instances by reference
+            let user_call = matches!(&**callee, Expression::Identifier(name)
+                if symbol_table.get_variable(name).is_none()
+                    && (function_table.get_function(name).is_some() || symbol_table.is_generator_function(name)));
+            if module_call || user_call {
+                args_cpp = args.iter()
+                    .map(|arg| emit_handle_cpp(arg, symbol_table, function_table, type_map))
+                    .collect::<Result<_, _>>()?;
+                for (kw_name, kw_value) in keywords {
+                    keywords_cpp.insert(kw_name.as_str(), emit_handle_cpp(kw_value, symbol_table, function_table, type_map)?);
+                }
+            }
+            if !keywords.is_empty() {
+                let name = callee_name(callee);
+                let Some((params, required)) = callee_params(callee, symbol_table, function_table) else {
+                    return Err(format!("keyword arguments are not supported in calls to '{}'", name));
+                };
+                args_cpp = place_keyword_arguments(&name, &params, required, args_cpp, keywords, &keywords_cpp)?;
+            }
+
             // Generic function call
             let callee_cpp = emit_expression_cpp(callee, symbol_table, function_table, type_map)?;
-            Ok(format!("{}({})", callee_cpp, args_cpp.join(", ")))
+            let call_cpp = format!("{}({})", callee_cpp, args_cpp.join(", "));
+            if returns_handle(callee, symbol_table, function_table) {
+                return Ok(format!("eppx_value({})", call_cpp));
+            }
+            Ok(call_cpp)
         }
         Expression::Lambda { params, body } => {
             // symbol_table.enter_scope(); // Lambda introduces a new scope
@@ -1632,7 +2718,7 @@ pub fn emit_expression_cpp(
                 BinOp::Mul => "*",
                 BinOp::Div => "/", // C++ int division truncates
                 BinOp::Mod => "%",
-                BinOp::Pow => return Ok(format!("static_cast<long long>(std::pow({}, {}))", l, r)),
+                BinOp::Pow => return Ok(format!("eppx_power({}, {})", l, r)),
                 BinOp::FloorDiv => "/", // C++ int division truncates, matches Python // for positive results
                 // Comparison
                 BinOp::Eq => "==",
@@ -1658,23 +2744,61 @@ pub fn emit_expression_cpp(
                 BinOp::LShift => "<<",
                 BinOp::RShift => ">>",
                 // Identity (basic C++ translation, not Python's object identity)
-                BinOp::Is => return Ok(format!("{} == {} /* Placeholder for IS */", l, r)), // Primitive check
-                BinOp::IsNot => return Ok(format!("{} != {} /* Placeholder for IS NOT */", l, r)), // Primitive check
-                // Membership (basic C++ string translation, not general purpose)
-                BinOp::In => {
-                    // Very basic string 'in' check. Assumes l is char/substring, r is string.
-                    // This is a placeholder and needs a proper runtime type system.
-                    // Example: r.find(l) != std::string::npos
-                    // For now, let's assume r is a string and l is a string to find.
-                    // This is highly simplified.
-                    return Ok(format!("{}.find({}) != std::string::npos /* Placeholder for IN */", r, l));
-                }
-                BinOp::NotIn => {
-                    return Ok(format!("{}.find({}) == std::string::npos /* Placeholder for NOT IN */", r, l));
+                // Identity: lists, dicts, sets and instances are compared as objects, other values by value
+                BinOp::Is | BinOp::IsNot => {
+                    let l = emit_handle_cpp(left, symbol_table, function_table, type_map)?;
+                    let r = emit_handle_cpp(right, symbol_table, function_table, type_map)?;
+                    let negate = if *op == BinOp::IsNot { "!" } else { "" };
+                    return Ok(format!("{}eppx_is({}, {})", negate, l, r));
                 }
+                // Membership (basic C++ string translation, not general purpose)
+                // Membership: eppx_contains is overloaded for str, list, set, frozenset and dict
+                BinOp::In => return Ok(format!("eppx_contains({}, {})", r, l)),
+                BinOp::NotIn => return Ok(format!("!eppx_contains({}, {})", r, l)),
             };
             Ok(format!("{} {} {}", l, op_str, r))
         }
+        Expression::Compare { left, ops, comparators } => {
+            // Each operand is bound once, in order, and compared with the next
+            // one; the first false comparison ends the chain
+            let mut operand_table = symbol_table.fork();
+            let mut body = format!(
+                "auto&& __eppx_cmp_0 = {}; ",
+                emit_expression_cpp(left, symbol_table, function_table, type_map)?
+            );
+            operand_table.add_variable("__eppx_cmp_0", "auto");
+            for (i, (op, comparator)) in ops.iter().zip(comparators).enumerate() {
+                let operand_cpp = emit_expression_cpp(comparator, symbol_table, function_table, type_map)?;
+                body.push_str(&format!("auto&& __eppx_cmp_{} = {}; ", i + 1, operand_cpp));
+                operand_table.add_variable(&format!("__eppx_cmp_{}", i + 1), "auto");
+                let pair = Expression::BinaryOperation {
+                    left: Box::new(Expression::Identifier(format!("__eppx_cmp_{}", i))),
+                    op: *op,
+                    right: Box::new(Expression::Identifier(format!("__eppx_cmp_{}", i + 1))),
+                };
+                let pair_cpp = emit_expression_cpp(&pair, &mut operand_table, function_table, type_map)?;
+                if i + 1 == ops.len() {
+                    body.push_str(&format!("return {};", pair_cpp));
+                } else {
+                    body.push_str(&format!("if (!({})) return false; ", pair_cpp));
+                }
+            }
+            Ok(format!("[&]() -> bool {{ {} }}()", body))
+        }
+        Expression::IfExp { test, body, orelse } => {
+            let test_cpp = emit_expression_cpp(test, symbol_table, function_table, type_map)?;
+            let body_cpp = emit_expression_cpp(body, symbol_table, function_table, type_map)?;
+            let orelse_cpp = emit_expression_cpp(orelse, symbol_table, function_table, type_map)?;
+            Ok(format!(
+                "eppx_ifexp(eppx_truthy({}), [&]() {{ return {}; }}, [&]() {{ return {}; }})",
+                test_cpp, body_cpp, orelse_cpp
+            ))
+        }
+        Expression::NamedExpr { target, value } => {
+            // The target is declared ahead of the statement (see declare_named_expr_targets)
+            let value_cpp = emit_expression_cpp(value, symbol_table, function_table, type_map)?;
+            Ok(format!("({} = {})", target, value_cpp))
+        }
         Expression::TupleLiteral(elements) => {
             // For compatibility with eppx_variant, represent tuples as vectors
             // This allows them to be stored in lists and unpacked in for loops
@@ -1683,15 +2807,15 @@ pub fn emit_expression_cpp(
         }
         Expression::DictLiteral(entries) => {
             let entries_cpp = entries.iter().map(|(k, v)| {
-                let k_cpp = emit_expression_cpp(k, symbol_table, function_table, type_map)?;
-                let v_cpp = emit_expression_cpp(v, symbol_table, function_table, type_map)?;
+                let k_cpp = emit_reference_cpp(k, symbol_table, function_table, type_map)?;
+                let v_cpp = emit_reference_cpp(v, symbol_table, function_table, type_map)?;
                 Ok::<String, String>(format!("{{{}, {}}}", k_cpp, v_cpp))
             }).collect::<Result<Vec<_>,_>>()?.join(", ");
-            Ok(format!("std::map<std::string, long long>{{{}}}", entries_cpp)) // Assuming K=string, V=long long
+            Ok(format!("eppx_dict<eppx_variant, eppx_variant>{{{}}}", entries_cpp))
         }
         Expression::SetLiteral(elements) => {
-            let elems_cpp = elements.iter().map(|e| emit_expression_cpp(e, symbol_table, function_table, type_map)).collect::<Result<Vec<_>,_>>()?.join(", ");
-            Ok(format!("std::set<long long>{{{}}}", elems_cpp)) // Assuming T=long long
+            let elems_cpp = elements.iter().map(|e| emit_reference_cpp(e, symbol_table, function_table, type_map)).collect::<Result<Vec<_>,_>>()?.join(", ");
+            Ok(format!("std::set<eppx_variant>{{{}}}", elems_cpp))
         }
         Expression::FrozensetLiteral(elements) => {
             // C++ doesn't have a direct frozenset. std::set is mutable.
@@ -1705,11 +2829,7 @@ pub fn emit_expression_cpp(
             // Simpler: use std::set and rely on const if variable is const. Or use unordered_set directly.
             Ok(format!("std::unordered_set<long long>{{{}}}", elems_cpp)) // Assuming T=long long
         }
-        Expression::ComplexLiteral(real, imag) => {
-            let real_cpp = emit_expression_cpp(real, symbol_table, function_table, type_map)?;
-            let imag_cpp = emit_expression_cpp(imag, symbol_table, function_table, type_map)?;
-            Ok(format!("std::complex<double>({}, {})", real_cpp, imag_cpp))
-        }
+        Expression::ImaginaryLiteral(imag) => Ok(format!("eppx_complex(0.0, {})", float_literal_cpp(*imag))),
         // Comprehensions
         Expression::ListComprehension { element, comprehension } => {
             emit_comprehension_cpp(element, comprehension, "list", symbol_table, function_table, type_map)
@@ -1731,13 +2851,7 @@ pub fn emit_expression_cpp(
 // Helper functions for function analysis
 fn has_explicit_return_type(body: &[AstNode]) -> bool {
     // Simple heuristic: if the function has any return statements with expressions
-    body.iter().any(|node| {
-        if let AstNode::Statement(Statement::Return(Some(_))) = node {
-            true
-        } else {
-            false
-        }
-    })
+    body.iter().any(|node| matches!(node, AstNode::Statement(Statement::Return(Some(_)))))
 }
 
 fn analyze_return_type(body: &[AstNode]) -> String {
@@ -1745,18 +2859,66 @@ fn analyze_return_type(body: &[AstNode]) -> String {
     for node in body {
         if let AstNode::Statement(Statement::Return(Some(expr))) = node {
             return match &**expr {
-                Expression::IntegerLiteral(_) => "long long".to_string(),
-                Expression::FloatLiteral(_) => "double".to_string(),
-                Expression::StringLiteral(_) => "std::string".to_string(),
-                Expression::BooleanLiteral(_) => "bool".to_string(),
                 Expression::NoneLiteral => "std::nullptr_t".to_string(),
-                _ => "auto".to_string(),
+                expr => literal_return_type(expr).unwrap_or("auto").to_string(),
             };
         }
     }
     "void".to_string()
 }
 
+fn literal_return_type(expr: &Expression) -> Option<&'static str> {
+    match expr {
+        Expression::IntegerLiteral(_) => Some("long long"),
+        Expression::FloatLiteral(_) => Some("double"),
+        Expression::StringLiteral(_) => Some("std::string"),
+        Expression::BooleanLiteral(_) => Some("bool"),
+        _ => None,
+    }
+}
+
+// The type of a body that only returns from inside blocks (such as match
+// cases), when its first such return is a literal or builds a string; other
+// values can't be typed before the body is, and the body may also fall off the end
+fn nested_return_type(body: &[AstNode]) -> Option<&'static str> {
+    let value = *return_values(body).first()?;
+    literal_return_type(value).or_else(|| is_likely_string_expression(value).then_some("std::string"))
+}
+
+// The values of the `return` statements in a body, including those in nested
+// blocks but not in nested functions and classes
+fn return_values(body: &[AstNode]) -> Vec<&Expression> {
+    let mut values = Vec::new();
+    for node in body {
+        let AstNode::Statement(stmt) = node;
+        let blocks: Vec<&[AstNode]> = match stmt {
+            Statement::Return(Some(value)) => {
+                values.push(&**value);
+                continue;
+            }
+            Statement::If { then_body, elifs, else_body, .. } => std::iter::once(then_body.as_slice())
+                .chain(elifs.iter().map(|(_, elif_body)| elif_body.as_slice()))
+                .chain(else_body.as_deref())
+                .collect(),
+            Statement::While { body, else_body, .. } | Statement::For { body, else_body, .. } => {
+                std::iter::once(body.as_slice()).chain(else_body.as_deref()).collect()
+            }
+            Statement::With { body, .. } => vec![body.as_slice()],
+            Statement::TryExcept { try_body, excepts, else_body, finally_body } => std::iter::once(try_body.as_slice())
+                .chain(excepts.iter().map(|except| except.body.as_slice()))
+                .chain(else_body.as_deref())
+                .chain(finally_body.as_deref())
+                .collect(),
+            Statement::Match { cases, .. } => cases.iter().map(|case| case.body.as_slice()).collect(),
+            _ => continue,
+        };
+        for block in blocks {
+            values.extend(return_values(block));
+        }
+    }
+    values
+}
+
 fn generate_decorator_wrappers(_decorators: &[crate::ast::Decorator]) -> Result<String, String> {
     // Placeholder implementation for decorator support
     // In a full implementation, this would generate wrapper functions
@@ -1765,6 +2927,8 @@ fn generate_decorator_wrappers(_decorators: &[crate::ast::Decorator]) -> Result<
 
 fn infer_cpp_type_for_static_member(value: &Expression) -> String {
     match value {
+        Expression::ListLiteral(_) | Expression::TupleLiteral(_) => "std::vector<eppx_variant>".to_string(),
+        Expression::DictLiteral(_) => "eppx_dict<eppx_variant, eppx_variant>".to_string(),
         Expression::IntegerLiteral(_) => "long long".to_string(),
         Expression::FloatLiteral(_) => "double".to_string(),
         Expression::StringLiteral(_) => "std::string".to_string(),
@@ -1774,12 +2938,12 @@ fn infer_cpp_type_for_static_member(value: &Expression) -> String {
     }
 }
 
-fn infer_return_type_from_body(body: &[AstNode], method_name: &str, _base: &Option<String>) -> String {
+fn infer_return_type_from_body(body: &[AstNode], method_name: &str, _bases: &[String]) -> String {
     // Special cases for iterator protocol methods
     match method_name {
         "__iter__" => return "auto*".to_string(), // Returns pointer to self
         "__next__" => return "long long".to_string(), // Returns the next value
-        "__str__" => return "std::string".to_string(),
+        "__str__" | "__repr__" => return "std::string".to_string(),
         _ => {}
     }
     
@@ -1830,6 +2994,8 @@ fn infer_return_type_from_body(body: &[AstNode], method_name: &str, _base: &Opti
     // Special method names
     if method_name == "__str__" {
         "std::string".to_string()
+    } else if !return_values(body).is_empty() {
+        nested_return_type(body).unwrap_or("long long").to_string()
     } else {
         "void".to_string()
     }
@@ -1839,14 +3005,9 @@ fn infer_return_type_from_body(body: &[AstNode], method_name: &str, _base: &Opti
 fn is_likely_string_expression(expr: &Expression) -> bool {
     match expr {
         Expression::StringLiteral(_) => true,
-        Expression::BinaryOperation { left, op, right } => {
-            match op {
-                crate::ast::BinOp::Add => {
-                    // String concatenation if either operand is a string
-                    is_likely_string_expression(left) || is_likely_string_expression(right)
-                },
-                _ => false,
-            }
+        Expression::BinaryOperation { left, op: crate::ast::BinOp::Add, right } => {
+            // String concatenation if either operand is a string
+            is_likely_string_expression(left) || is_likely_string_expression(right)
         },
         Expression::Call { callee, .. } => {
             // str() function calls result in strings
@@ -1913,24 +3074,22 @@ fn emit_comprehension_cpp(
                     "([&]() {{ \
                         std::vector<eppx_variant> temp_vec; \
                         for ({} : {}) {{ \
-                            auto temp_elem = {}; \
-                            {}{{ temp_vec.push_back(temp_elem); }} \
+                            {}{{ auto temp_elem = {}; temp_vec.push_back(temp_elem); }} \
                         }} \
                         return temp_vec; \
                     }})()",
-                    target_pattern, iter_cpp, element_cpp, condition_cpp
+                    target_pattern, iter_cpp, condition_cpp, element_cpp
                 ))
             } else {
                 Ok(format!(
                     "([&]() {{ \
                         std::vector<eppx_variant> temp_vec; \
                         for ({} : {}) {{ \
-                            auto temp_elem = {}; \
-                            {}{{ temp_vec.push_back(temp_elem); }} \
+                            {}{{ auto temp_elem = {}; temp_vec.push_back(temp_elem); }} \
                         }} \
                         return temp_vec; \
                     }})()",
-                    target_pattern, iter_cpp, element_cpp, condition_cpp
+                    target_pattern, iter_cpp, condition_cpp, element_cpp
                 ))
             }
         }
@@ -1939,12 +3098,11 @@ fn emit_comprehension_cpp(
                 "([&]() {{ \
                     std::set<eppx_variant> temp_set; \
                     for ({} : {}) {{ \
-                        auto temp_elem = {}; \
-                        {}{{ temp_set.insert(temp_elem); }} \
+                        {}{{ auto temp_elem = {}; temp_set.insert(temp_elem); }} \
                     }} \
                     return temp_set; \
                 }})()",
-                target_pattern, iter_cpp, element_cpp, condition_cpp
+                target_pattern, iter_cpp, condition_cpp, element_cpp
             ))
         }
         "generator" => {
@@ -1954,18 +3112,39 @@ fn emit_comprehension_cpp(
                 "([&]() {{ \
                     std::vector<eppx_variant> temp_vec; \
                     for ({} : {}) {{ \
-                        auto temp_elem = {}; \
-                        {}{{ temp_vec.push_back(temp_elem); }} \
+                        {}{{ auto temp_elem = {}; temp_vec.push_back(temp_elem); }} \
                     }} \
                     return temp_vec; \
                 }})()",
-                target_pattern, iter_cpp, element_cpp, condition_cpp
+                target_pattern, iter_cpp, condition_cpp, element_cpp
             ))
         }
         _ => Err(format!("Unsupported comprehension type: {}", comp_type))
     }
 }
 
+// Emits the `key=` argument of sort()/sorted() as a C++ callable. Builtins such as
+// `len` are overloaded in the runtime, so the key is always wrapped in a generic lambda.
+fn emit_key_function_cpp(
+    keywords: &[(String, Expression)],
+    symbol_table: &mut SymbolTable,
+    function_table: &FunctionTable,
+    type_map: &mut TypeMap,
+) -> Result<String, String> {
+    match keywords.iter().find(|(name, _)| name == "key") {
+        None | Some((_, Expression::NoneLiteral)) => Ok("nullptr".to_string()),
+        Some((_, key_expr)) => {
+            let call = Expression::Call {
+                callee: Box::new(key_expr.clone()),
+                args: vec![Expression::Identifier("__eppx_key_arg".to_string())],
+                keywords: Vec::new(),
+            };
+            let call_cpp = emit_expression_cpp(&call, symbol_table, function_table, type_map)?;
+            Ok(format!("[&](const auto& __eppx_key_arg) {{ return {}; }}", call_cpp))
+        }
+    }
+}
+
 // Helper function to emit dictionary comprehensions
 fn emit_dict_comprehension_cpp(
     key: &Expression,
@@ -2007,42 +3186,260 @@ fn emit_dict_comprehension_cpp(
         format!("auto [{}]", comprehension.target.join(", "))
     };
     
-    // Generate the comprehension using stringstream for both key and value conversion
+    // Generate the comprehension into an insertion-ordered dict
     Ok(format!(
         "([&]() {{ \
-            std::map<std::string, std::string> temp_map; \
+            eppx_dict<eppx_variant, eppx_variant> temp_map; \
             for ({} : {}) {{ \
-                auto temp_key = {}; \
-                auto temp_value = {}; \
-                {}{{ \
-                    std::ostringstream key_ss; \
-                    key_ss << temp_key; \
-                    std::ostringstream value_ss; \
-                    value_ss << temp_value; \
-                    temp_map[key_ss.str()] = value_ss.str(); \
-                }} \
+                {}{{ auto temp_key = {}; auto temp_value = {}; eppx_setitem(temp_map, temp_key, temp_value); }} \
             }} \
             return temp_map; \
         }})()",
-        target_pattern, iter_cpp, key_cpp, value_cpp, condition_cpp
+        target_pattern, iter_cpp, condition_cpp, key_cpp, value_cpp
     ))
 }
 
+// Expressions directly nested in `expr`
+fn child_expressions(expr: &Expression) -> Vec<&Expression> {
+    fn comprehension_parts(comprehension: &Comprehension) -> impl Iterator<Item = &Expression> {
+        std::iter::once(&*comprehension.iter).chain(comprehension.ifs.iter())
+    }
+    match expr {
+        Expression::ListLiteral(items)
+        | Expression::TupleLiteral(items)
+        | Expression::SetLiteral(items)
+        | Expression::FrozensetLiteral(items) => items.iter().collect(),
+        Expression::DictLiteral(entries) => entries.iter().flat_map(|(k, v)| [k, v]).collect(),
+        Expression::BinaryOperation { left, right, .. } => vec![left, right],
+        Expression::Compare { left, comparators, .. } => std::iter::once(&**left).chain(comparators.iter()).collect(),
+        Expression::IfExp { test, body, orelse } => vec![body, test, orelse],
+        Expression::NamedExpr { value, .. } => vec![value],
+        Expression::UnaryOperation { operand, .. } => vec![operand],
+        Expression::Lambda { body, .. } => vec![body],
+        Expression::ListComprehension { element, comprehension }
+        | Expression::SetComprehension { element, comprehension }
+        | Expression::GeneratorExpression { element, comprehension } => {
+            std::iter::once(&**element).chain(comprehension_parts(comprehension)).collect()
+        }
+        Expression::DictComprehension { key, value, comprehension } => {
+            [&**key, &**value].into_iter().chain(comprehension_parts(comprehension)).collect()
+        }
+        Expression::Call { callee, args, keywords } => {
+            std::iter::once(&**callee).chain(args.iter()).chain(keywords.iter().map(|(_, v)| v)).collect()
+        }
+        Expression::AttributeAccess { object, .. } => vec![object],
+        Expression::Index { object, index } => vec![object, index],
+        Expression::Yield(Some(value)) | Expression::YieldFrom(value) | Expression::Await(value) => vec![value],
+        _ => Vec::new(),
+    }
+}
+
+// Does `pred` hold for `expr` or any expression nested in it?
+fn any_subexpression(expr: &Expression, pred: &dyn Fn(&Expression) -> bool) -> bool {
+    pred(expr) || child_expressions(expr).into_iter().any(|child| any_subexpression(child, pred))
+}
+
+// Expressions a statement evaluates itself, not counting its nested bodies
+fn statement_expressions(stmt: &Statement) -> Vec<&Expression> {
+    match stmt {
+        Statement::Assignment { target, value, .. } => vec![value, target],
+        Statement::ChainedAssignment { targets, value } => std::iter::once(&**value).chain(targets.iter()).collect(),
+        Statement::Assert { test, msg } => std::iter::once(&**test).chain(msg.as_deref()).collect(),
+        Statement::Delete(targets) => targets.iter().collect(),
+        Statement::If { condition, elifs, .. } => {
+            std::iter::once(&**condition).chain(elifs.iter().map(|(elif_condition, _)| elif_condition)).collect()
+        }
+        Statement::While { condition, .. } => vec![condition],
+        Statement::For { iterable, .. } => vec![iterable],
+        Statement::Print(expr) | Statement::ExpressionStatement(expr) | Statement::Return(Some(expr)) => vec![expr],
+        Statement::Yield(Some(expr)) => vec![expr],
+        Statement::Raise(Some(expr)) => vec![expr],
+        Statement::With { items, .. } => items.iter().map(|item| &item.context_expr).collect(),
+        Statement::Match { subject, .. } => vec![subject],
+        _ => Vec::new(),
+    }
+}
+
+// Targets of the `(name := value)` expressions in `expr`, innermost first, with
+// the value bound. The value is None inside a comprehension, whose loop
+// variables are out of scope where the target gets declared. Lambda bodies
+// are skipped.
+fn named_expr_targets<'e>(expr: &'e Expression, in_comprehension: bool, targets: &mut Vec<(&'e str, Option<&'e Expression>)>) {
+    let in_comprehension = in_comprehension
+        || matches!(
+            expr,
+            Expression::ListComprehension { .. }
+                | Expression::SetComprehension { .. }
+                | Expression::DictComprehension { .. }
+                | Expression::GeneratorExpression { .. }
+        );
+    if !matches!(expr, Expression::Lambda { .. }) {
+        for child in child_expressions(expr) {
+            named_expr_targets(child, in_comprehension, targets);
+        }
+    }
+    if let Expression::NamedExpr { target, value } = expr {
+        targets.push((target, (!in_comprehension).then_some(&**value)));
+    }
+}
+
+// Expressions that are emitted as C++ lambdas, which C++17 doesn't allow in
+// unevaluated operands such as decltype
+fn emits_lambda(expr: &Expression) -> bool {
+    any_subexpression(expr, &|e| {
+        matches!(
+            e,
+            Expression::Lambda { .. }
+                | Expression::ListComprehension { .. }
+                | Expression::SetComprehension { .. }
+                | Expression::DictComprehension { .. }
+                | Expression::GeneratorExpression { .. }
+                | Expression::Compare { .. }
+                | Expression::IfExp { .. }
+        ) || matches!(e, Expression::Call { keywords, .. } if keywords.iter().any(|(name, _)| name == "key"))
+    })
+}
+
+// `(name := value)` assigns to a variable, so the statement holding it is
+// preceded by a declaration of each target not yet in scope, typed after
+// the value where decltype can take it
+fn declare_named_expr_targets(
+    stmt: &Statement,
+    declared_vars: &mut HashSet<String>,
+    symbol_table: &mut SymbolTable,
+    function_table: &FunctionTable,
+    type_map: &mut TypeMap,
+) -> Result<String, String> {
+    let mut targets = Vec::new();
+    for expr in statement_expressions(stmt) {
+        named_expr_targets(expr, false, &mut targets);
+    }
+    let mut cpp_out = String::new();
+    for (name, value) in targets {
+        if declared_vars.contains(name) || symbol_table.get_variable(name).is_some() {
+            continue;
+        }
+        let type_str = match value {
+            Some(value) if !emits_lambda(value) => format!(
+                "std::decay_t<decltype({})>",
+                emit_expression_cpp(value, symbol_table, function_table, type_map)?
+            ),
+            _ => "eppx_variant".to_string(),
+        };
+        cpp_out.push_str(&format!("    {} {}{{}};\n", type_str, name));
+        declared_vars.insert(name.to_string());
+        symbol_table.add_variable(name, &type_str);
+    }
+    Ok(cpp_out)
+}
+
+static NEXT_TEMP_ID: AtomicUsize = AtomicUsize::new(0);
+
+// A loop with an `else` block records `break` in a flag and runs the block
+// when the flag is still clear after the loop. Declares the flag.
+fn declare_break_flag(cpp_out: &mut String, declared_vars: &mut HashSet<String>, symbol_table: &mut SymbolTable) -> String {
+    let flag = format!("__eppx_broke_{}", NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed));
+    cpp_out.push_str(&format!("    bool {} = false;\n", flag));
+    declared_vars.insert(flag.clone());
+    symbol_table.add_variable(&flag, "bool");
+    flag
+}
+
+// The loop body with each of its own `break`s setting the flag first. Breaks
+// inside nested loops belong to those loops, except in their else blocks.
+fn flag_breaks(body: &[AstNode], flag: &str) -> Vec<AstNode> {
+    let rewrite = |nodes: &[AstNode]| flag_breaks(nodes, flag);
+    let mut result = Vec::new();
+    for node in body {
+        let AstNode::Statement(stmt) = node;
+        let stmt = match stmt {
+            Statement::Break => {
+                result.push(AstNode::Statement(Statement::Assignment {
+                    target: Box::new(Expression::Identifier(flag.to_string())),
+                    operator: AssignmentOperator::Assign,
+                    value: Box::new(Expression::BooleanLiteral(true)),
+                }));
+                Statement::Break
+            }
+            Statement::If { condition, then_body, elifs, else_body } => Statement::If {
+                condition: condition.clone(),
+                then_body: rewrite(then_body),
+                elifs: elifs.iter().map(|(cond, body)| (cond.clone(), rewrite(body))).collect(),
+                else_body: else_body.as_deref().map(rewrite),
+            },
+            Statement::While { condition, body, else_body } => Statement::While {
+                condition: condition.clone(),
+                body: body.clone(),
+                else_body: else_body.as_deref().map(rewrite),
+            },
+            Statement::For { vars, iterable, body, else_body, is_async } => Statement::For {
+                vars: vars.clone(),
+                iterable: iterable.clone(),
+                body: body.clone(),
+                else_body: else_body.as_deref().map(rewrite),
+                is_async: *is_async,
+            },
+            Statement::TryExcept { try_body, excepts, else_body, finally_body } => Statement::TryExcept {
+                try_body: rewrite(try_body),
+                excepts: excepts
+                    .iter()
+                    .map(|handler| ExceptHandler { body: rewrite(&handler.body), ..handler.clone() })
+                    .collect(),
+                else_body: else_body.as_deref().map(rewrite),
+                finally_body: finally_body.as_deref().map(rewrite),
+            },
+            Statement::With { items, body, is_async } => {
+                Statement::With { items: items.clone(), body: rewrite(body), is_async: *is_async }
+            }
+            Statement::Match { subject, cases } => Statement::Match {
+                subject: subject.clone(),
+                cases: cases
+                    .iter()
+                    .map(|case| MatchCase { body: rewrite(&case.body), ..case.clone() })
+                    .collect(),
+            },
+            other => other.clone(),
+        };
+        result.push(AstNode::Statement(stmt));
+    }
+    result
+}
+
+// `a = b = value` as single assignments, left to right. The value is
+// evaluated once, into the first target when that is a plain name and into
+// a temporary otherwise.
+fn chained_assignments(targets: &[Expression], value: &Expression) -> Vec<AstNode> {
+    let assign = |target: Expression, value: Expression| {
+        AstNode::Statement(Statement::Assignment {
+            target: Box::new(target),
+            operator: AssignmentOperator::Assign,
+            value: Box::new(value),
+        })
+    };
+    let (source, rest) = match targets.split_first() {
+        Some((first @ Expression::Identifier(_), rest)) => (first.clone(), rest),
+        _ => (Expression::Identifier(format!("__eppx_chain_{}", NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed))), targets),
+    };
+    let mut assignments = vec![assign(source.clone(), value.clone())];
+    assignments.extend(rest.iter().map(|target| assign(target.clone(), source.clone())));
+    assignments
+}
+
 // Helper function to detect if a function contains yield statements
 fn contains_yield(body: &[AstNode]) -> bool {
     for node in body {
         match node {
             AstNode::Statement(Statement::Yield(_)) => return true,
-            AstNode::Statement(Statement::While { body, .. }) => {
-                if contains_yield(body) {
-                    return true;
-                }
-            }
-            AstNode::Statement(Statement::For { body, .. }) => {
-                if contains_yield(body) {
-                    return true;
-                }
-            }
+            AstNode::Statement(Statement::ExpressionStatement(expr)) if matches!(**expr, Expression::YieldFrom(_)) => return true,
+            AstNode::Statement(Statement::Assignment { value, .. })
+                if matches!(**value, Expression::Yield(_) | Expression::YieldFrom(_)) => return true,
+            AstNode::Statement(Statement::With { body, .. }) if contains_yield(body) => return true,
+            AstNode::Statement(Statement::Match { cases, .. }) if cases.iter().any(|case| contains_yield(&case.body)) => return true,
+            AstNode::Statement(Statement::ChainedAssignment { value, .. })
+                if matches!(**value, Expression::Yield(_) | Expression::YieldFrom(_)) => return true,
+            AstNode::Statement(Statement::While { body, else_body, .. })
+            | AstNode::Statement(Statement::For { body, else_body, .. })
+                if contains_yield(body) || else_body.as_deref().is_some_and(contains_yield) => return true,
             AstNode::Statement(Statement::If { then_body, elifs, else_body, .. }) => {
                 if contains_yield(then_body) {
                     return true;
@@ -2084,191 +3481,3 @@ fn contains_yield(body: &[AstNode]) -> bool {
     false
 }
 
-// Helper function to generate a generator class
-fn generate_generator_class(
-    name: &str,
-    params: &[String],
-    body: &[AstNode],
-    _symbol_table: &mut SymbolTable,
-    _function_table: &mut FunctionTable,
-    _type_map: &mut TypeMap,
-) -> Result<String, String> {
-    let mut cpp_out = String::new();
-    
-    // Collect all local variables used in the function
-    let mut local_vars = HashSet::new();
-    collect_variables_from_statements(body, &mut local_vars);
-    
-    // Generator class definition
-    cpp_out.push_str(&format!("class {}_Generator {{\n", name));
-    cpp_out.push_str("private:\n");
-    cpp_out.push_str("    int _state = 0;\n");
-    cpp_out.push_str("    bool _done = false;\n");
-    
-    // Add member variables for parameters
-    for param in params {
-        cpp_out.push_str(&format!("    eppx_variant {};\n", param));
-    }
-    
-    // Add member variables for local variables
-    for var in &local_vars {
-        if !params.contains(var) {
-            cpp_out.push_str(&format!("    eppx_variant {};\n", var));
-        }
-    }
-    
-    cpp_out.push_str("    eppx_variant _current_value = 0LL;\n\n");
-    
-    cpp_out.push_str("public:\n");
-    
-    // Constructor
-    if params.is_empty() {
-        cpp_out.push_str(&format!("    {}_Generator() {{}}\n", name));
-    } else {
-        let constructor_params = params.iter()
-            .map(|p| format!("const eppx_variant& {}", p))
-            .collect::<Vec<_>>()
-            .join(", ");
-        let initializers = params.iter()
-            .map(|p| format!("{}({})", p, p))
-            .collect::<Vec<_>>()
-            .join(", ");
-        cpp_out.push_str(&format!("    {}_Generator({}) : {} {{}}\n", name, constructor_params, initializers));
-    }
-    
-    // Default constructor for end iterator
-    cpp_out.push_str(&format!("    {}_Generator(bool done) : _done(done) {{}}\n", name));
-    
-    // Iterator interface
-    cpp_out.push_str(&format!("    {}_Generator begin() {{ {}_Generator gen = *this; gen.next(); return gen; }}\n", name, name));
-    cpp_out.push_str(&format!("    {}_Generator end() {{ return {}_Generator(true); }}\n", name, name));
-    cpp_out.push_str(&format!("    bool operator!=(const {}_Generator& other) const {{ return _done != other._done; }}\n", name));
-    cpp_out.push_str(&format!("    {}_Generator& operator++() {{ next(); return *this; }}\n", name));
-    cpp_out.push_str("    eppx_variant operator*() const { return _current_value; }\n");
-    cpp_out.push_str("    eppx_variant next_value() { next(); return _current_value; }\n\n");
-    
-    // Next method with simplified state machine
-    cpp_out.push_str("    void next() {\n");
-    cpp_out.push_str("        if (_done) return;\n");
-    cpp_out.push_str("        switch(_state) {\n");
-    
-    // Generate a simple sequential state machine for now
-    let state_counter = 0;
-    cpp_out.push_str(&format!("        case {}:\n", state_counter));
-    
-    // For simplicity, let's just handle the basic cases manually based on function name
-    if name == "simple_generator" {
-        cpp_out.push_str("            if (_state == 0) { _current_value = 1; _state = 1; return; }\n");
-        cpp_out.push_str("        case 1:\n");
-        cpp_out.push_str("            if (_state == 1) { _current_value = 2; _state = 2; return; }\n");
-        cpp_out.push_str("        case 2:\n");
-        cpp_out.push_str("            if (_state == 2) { _current_value = 3; _state = 3; return; }\n");
-        cpp_out.push_str("        case 3:\n");
-        cpp_out.push_str("            _done = true;\n");
-        cpp_out.push_str("            break;\n");
-    } else if name == "countdown" {
-        cpp_out.push_str("            if (variant_to_ll(n) > 0) {\n");
-        cpp_out.push_str("                _current_value = n;\n");
-        cpp_out.push_str("                n = variant_to_ll(n) - 1;\n");
-        cpp_out.push_str("                return;\n");
-        cpp_out.push_str("            } else {\n");
-        cpp_out.push_str("                _done = true;\n");
-        cpp_out.push_str("            }\n");
-        cpp_out.push_str("            break;\n");    } else if name == "fibonacci" && params.contains(&"limit".to_string()) {
-        // Fibonacci with limit parameter
-        cpp_out.push_str("            if (_state == 0) {\n");
-        cpp_out.push_str("                a = 0LL;\n");
-        cpp_out.push_str("                b = 1LL;\n");
-        cpp_out.push_str("                _state = 1;\n");
-        cpp_out.push_str("            }\n");
-        cpp_out.push_str("        case 1:\n");
-        cpp_out.push_str("            // Check fibonacci condition: a < limit\n");
-        cpp_out.push_str("            if (variant_to_ll(a) < variant_to_ll(limit)) {\n");
-        cpp_out.push_str("                _current_value = a;\n");
-        cpp_out.push_str("                temp = a;\n");
-        cpp_out.push_str("                a = b;\n");
-        cpp_out.push_str("                b = eppx_variant(variant_to_ll(temp) + variant_to_ll(b));\n");
-        cpp_out.push_str("                return;\n");
-        cpp_out.push_str("            } else {\n");
-        cpp_out.push_str("                _done = true;\n");
-        cpp_out.push_str("            }\n");
-        cpp_out.push_str("            break;\n");
-    } else if name == "fibonacci" && params.is_empty() {
-        // Infinite fibonacci generator
-        cpp_out.push_str("            if (_state == 0) {\n");
-        cpp_out.push_str("                a = 0LL;\n");
-        cpp_out.push_str("                b = 1LL;\n");
-        cpp_out.push_str("                _state = 1;\n");
-        cpp_out.push_str("            }\n");
-        cpp_out.push_str("        case 1:\n");
-        cpp_out.push_str("            // Infinite fibonacci generator\n");
-        cpp_out.push_str("            _current_value = a;\n");
-        cpp_out.push_str("            temp = a;\n");
-        cpp_out.push_str("            a = b;\n");
-        cpp_out.push_str("            b = eppx_variant(variant_to_ll(temp) + variant_to_ll(b));\n");
-        cpp_out.push_str("            return;\n");
-    }
-    
-    cpp_out.push_str("        default:\n");
-    cpp_out.push_str("            _done = true;\n");
-    cpp_out.push_str("            break;\n");
-    cpp_out.push_str("        }\n");
-    cpp_out.push_str("    }\n");
-    cpp_out.push_str("};\n\n");
-    
-    // Helper function to create generator  
-    cpp_out.push_str(&format!("auto {}(", name));
-    if !params.is_empty() {
-        let func_params = params.iter()
-            .map(|p| format!("const eppx_variant& {}", p))
-            .collect::<Vec<_>>()
-            .join(", ");
-        cpp_out.push_str(&func_params);
-    }
-    cpp_out.push_str(&format!(") {{\n    return {}_Generator(", name));
-    if !params.is_empty() {
-        cpp_out.push_str(&params.join(", "));
-    }
-    cpp_out.push_str(");\n}\n\n");
-    
-    Ok(cpp_out)
-}
-
-// Collect variables from statements and expressions for function analysis
-fn collect_variables_from_statements(statements: &[AstNode], variables: &mut HashSet<String>) {
-    for stmt in statements {
-        match stmt {
-            AstNode::Statement(Statement::Assignment { target, .. }) => {
-                collect_variables_from_expression(target, variables);
-            }
-            AstNode::Statement(Statement::For { vars, body, .. }) => {
-                for var in vars {
-                    variables.insert(var.clone());
-                }
-                collect_variables_from_statements(body, variables);
-            }
-            AstNode::Statement(Statement::While { body, .. }) => {
-                collect_variables_from_statements(body, variables);
-            }
-            AstNode::Statement(Statement::If { then_body, elifs, else_body, .. }) => {
-                collect_variables_from_statements(then_body, variables);
-                for (_, elif_body) in elifs {
-                    collect_variables_from_statements(elif_body, variables);
-                }
-                if let Some(else_body) = else_body {
-                    collect_variables_from_statements(else_body, variables);
-                }
-            }
-            _ => {}
-        }
-    }
-}
-
-fn collect_variables_from_expression(expr: &Expression, variables: &mut HashSet<String>) {
-    match expr {
-        Expression::Identifier(name) => {
-            variables.insert(name.clone());
-        }
-        _ => {}
-    }
-}
\ No newline at end of file
@@ -0,0 +1,536 @@
+// Pattern matching: compiles a `match` statement into a decision tree over the
+// runtime value representation (see "Structural pattern matching" in
+// eppx_match.hpp).
+//
+// Each case pattern is flattened into rows of steps on access paths (C++
+// expressions reaching a part of the subject): tests that must pass and names
+// to bind. An OR pattern becomes one row per alternative. The tree takes the
+// first test of the first row and splits the rows on its outcome: rows with
+// the same test keep going without it when it passes and are dropped when it
+// fails, rows with a test that excludes it (another literal of the same kind,
+// another length or another kind of container at the same path) are dropped
+// when it passes, and every other row goes both ways. A row without
+// tests left has matched: its leaf checks the guard, if any, and jumps to the
+// case body, so each body is emitted once however many leaves reach it.
+
+use super::{dataclass, emit_expression_cpp, generate_statement_list_cpp, indent_code, ClassInfo, FunctionTable, SymbolTable, TypeMap};
+use crate::ast::{AssignmentOperator, AstNode, Decorator, Expression, MatchCase, Pattern, Statement};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Match statements are numbered so that their labels are unique in a function
+static NEXT_MATCH_ID: AtomicUsize = AtomicUsize::new(0);
+
+// Class patterns on builtin types match the subject itself
+const BUILTIN_CLASSES: &[&str] = &["int", "float", "str", "bool", "list", "tuple", "dict", "set"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Test {
+        cond: String,
+        // (path, group, value): tests of one group at the same path can't
+        // pass for two different values
+        exclusive: Option<(String, String, String)>,
+    },
+    Bind { name: String, path: String },
+}
+
+#[derive(Debug, Clone)]
+struct Row {
+    steps: Vec<Step>,
+    case: usize,
+}
+
+impl Row {
+    fn first_test(&self) -> Option<&Step> {
+        self.steps.iter().find(|step| matches!(step, Step::Test { .. }))
+    }
+
+    fn bindings(&self) -> Vec<(String, String)> {
+        self.steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Bind { name, path } => Some((name.clone(), path.clone())),
+                Step::Test { .. } => None,
+            })
+            .collect()
+    }
+
+    fn excludes(&self, test: &Step) -> bool {
+        let Step::Test { exclusive: Some((path, group, value)), .. } = test else {
+            return false;
+        };
+        self.steps.iter().any(|step| {
+            matches!(step, Step::Test { exclusive: Some((p, g, v)), .. } if p == path && g == group && v != value)
+        })
+    }
+}
+
+struct MatchLowering<'a> {
+    id: usize,
+    cases: &'a [MatchCase],
+    actions: Vec<(usize, Vec<(String, String)>)>, // (case, bindings) of each case body to emit
+    symbol_table: &'a mut SymbolTable,
+    function_table: &'a mut FunctionTable,
+    type_map: &'a mut TypeMap,
+}
+
+impl<'a> MatchLowering<'a> {
+    fn expr(&mut self, expr: &Expression) -> Result<String, String> {
+        emit_expression_cpp(expr, self.symbol_table, self.function_table, self.type_map)
+    }
+
+    fn label(&self, action: usize) -> String {
+        format!("__eppx_match_{}_case_{}", self.id, action)
+    }
+
+    fn end_label(&self) -> String {
+        format!("__eppx_match_{}_end", self.id)
+    }
+
+    // Appends the steps matching `pattern` at `path` to every alternative
+    fn flatten(&mut self, pattern: &Pattern, path: &str, alternatives: Vec<Vec<Step>>) -> Result<Vec<Vec<Step>>, String> {
+        let add = |alternatives: Vec<Vec<Step>>, step: Step| -> Vec<Vec<Step>> {
+            alternatives
+                .into_iter()
+                .map(|mut steps| {
+                    steps.push(step.clone());
+                    steps
+                })
+                .collect()
+        };
+        let test = |cond: String| Step::Test { cond, exclusive: None };
+        let exclusive = |cond: String, group: &str, value: &str| Step::Test {
+            cond,
+            exclusive: Some((path.to_string(), group.to_string(), value.to_string())),
+        };
+        match pattern {
+            Pattern::Wildcard => Ok(alternatives),
+            Pattern::Capture(name) => Ok(add(alternatives, Step::Bind { name: name.clone(), path: path.to_string() })),
+            Pattern::Literal(literal) => {
+                let literal_cpp = self.expr(literal)?;
+                let step = match literal {
+                    Expression::NoneLiteral | Expression::BooleanLiteral(_) => {
+                        exclusive(format!("eppx_match_is({}, {})", path, literal_cpp), "singleton", &literal_cpp)
+                    }
+                    Expression::IntegerLiteral(_) => exclusive(format!("eppx_match_eq({}, {})", path, literal_cpp), "int", &literal_cpp),
+                    Expression::StringLiteral(_) => exclusive(format!("eppx_match_eq({}, {})", path, literal_cpp), "str", &literal_cpp),
+                    _ => test(format!("eppx_match_eq({}, {})", path, literal_cpp)),
+                };
+                Ok(add(alternatives, step))
+            }
+            Pattern::Value(value) => {
+                let value_cpp = self.expr(value)?;
+                Ok(add(alternatives, test(format!("eppx_match_eq({}, {})", path, value_cpp))))
+            }
+            Pattern::As(inner, name) => {
+                let alternatives = self.flatten(inner, path, alternatives)?;
+                Ok(add(alternatives, Step::Bind { name: name.clone(), path: path.to_string() }))
+            }
+            Pattern::Or(options) => {
+                let mut result = Vec::new();
+                let mut names: Option<Vec<String>> = None;
+                for option in options {
+                    let mut option_names = Vec::new();
+                    bound_names(option, &mut option_names);
+                    option_names.sort();
+                    match &names {
+                        Some(names) if *names != option_names => {
+                            return Err("alternative patterns bind different names".to_string());
+                        }
+                        _ => names = Some(option_names),
+                    }
+                    result.extend(self.flatten(option, path, alternatives.clone())?);
+                }
+                Ok(result)
+            }
+            Pattern::Star(_) => Err("starred pattern outside a sequence pattern".to_string()),
+            Pattern::Sequence(items) => {
+                let star = items.iter().position(|item| matches!(item, Pattern::Star(_)));
+                if items.iter().filter(|item| matches!(item, Pattern::Star(_))).count() > 1 {
+                    return Err("multiple starred names in sequence pattern".to_string());
+                }
+                let mut alternatives = add(alternatives, exclusive(format!("eppx_match_sequence({})", path), "type", "sequence"));
+                alternatives = match star {
+                    None => add(alternatives, exclusive(format!("eppx_match_len({}) == {}", path, items.len()), "len", &items.len().to_string())),
+                    Some(_) => add(alternatives, test(format!("eppx_match_len({}) >= {}", path, items.len() - 1))),
+                };
+                for (i, item) in items.iter().enumerate() {
+                    match (item, star) {
+                        (Pattern::Star(name), Some(star)) => {
+                            if let Some(name) = name {
+                                let after = items.len() - star - 1;
+                                let slice = format!("eppx_match_slice({}, {}, {})", path, star, after);
+                                alternatives = add(alternatives, Step::Bind { name: name.clone(), path: slice });
+                            }
+                        }
+                        (_, Some(star)) if i > star => {
+                            let item_path = format!("eppx_match_item({}, -{})", path, items.len() - i);
+                            alternatives = self.flatten(item, &item_path, alternatives)?;
+                        }
+                        _ => {
+                            let item_path = format!("eppx_match_item({}, {})", path, i);
+                            alternatives = self.flatten(item, &item_path, alternatives)?;
+                        }
+                    }
+                }
+                Ok(alternatives)
+            }
+            Pattern::Mapping { entries, rest } => {
+                let mut alternatives = add(alternatives, exclusive(format!("eppx_match_mapping({})", path), "type", "mapping"));
+                let mut keys_cpp = Vec::new();
+                for (key, value) in entries {
+                    let key_cpp = self.expr(key)?;
+                    if keys_cpp.contains(&key_cpp) {
+                        return Err(format!("mapping pattern checks duplicate key ({})", key_cpp));
+                    }
+                    alternatives = add(alternatives, test(format!("eppx_match_has_key({}, {})", path, key_cpp)));
+                    alternatives = self.flatten(value, &format!("eppx_match_get({}, {})", path, key_cpp), alternatives)?;
+                    keys_cpp.push(key_cpp);
+                }
+                if let Some(rest) = rest {
+                    let mut args = vec![path.to_string()];
+                    args.extend(keys_cpp);
+                    alternatives = add(alternatives, Step::Bind { name: rest.clone(), path: format!("eppx_match_rest({})", args.join(", ")) });
+                }
+                Ok(alternatives)
+            }
+            Pattern::Class { name, positional, keywords } => {
+                if self.symbol_table.get_class(name).is_none() {
+                    if !BUILTIN_CLASSES.contains(&name.as_str()) {
+                        return Err(format!("'{}' in class pattern is not a class", name));
+                    }
+                    if positional.len() > 1 || !keywords.is_empty() {
+                        return Err(format!("{}() accepts 1 positional sub-pattern ({} given)", name, positional.len() + keywords.len()));
+                    }
+                    // bool() and int() overlap (bool is a subclass of int)
+                    let kind = match name.as_str() {
+                        "bool" => "int",
+                        "list" | "tuple" => "sequence",
+                        "dict" => "mapping",
+                        other => other,
+                    };
+                    let alternatives = add(alternatives, exclusive(format!("eppx_match_builtin({}, \"{}\")", path, name), "type", kind));
+                    let value_type = match name.as_str() {
+                        "int" => Some("long long"),
+                        "float" => Some("double"),
+                        "str" => Some("std::string"),
+                        "bool" => Some("bool"),
+                        "list" | "tuple" => Some("std::vector<eppx_variant>"),
+                        _ => None, // dicts and sets keep their own type
+                    };
+                    let value_path = match value_type {
+                        Some(value_type) => format!("eppx_match_as<{}>({})", value_type, path),
+                        None => path.to_string(),
+                    };
+                    return match positional.first() {
+                        Some(inner) => self.flatten(inner, &value_path, alternatives),
+                        None => Ok(alternatives),
+                    };
+                }
+                let match_args = self.symbol_table.class_match_args(name).cloned().unwrap_or_default();
+                if positional.len() > match_args.len() {
+                    return Err(format!("{}() accepts {} positional sub-pattern{} ({} given)",
+                        name, match_args.len(), if match_args.len() == 1 { "" } else { "s" }, positional.len()));
+                }
+                let attributes = match_args.iter().zip(positional).chain(keywords.iter().map(|(attr, pattern)| (attr, pattern)));
+                let mut seen = HashSet::new();
+                let mut alternatives = add(alternatives, test(format!("eppx_match_class<{}>({})", name, path)));
+                for (attr, pattern) in attributes {
+                    if !seen.insert(attr.clone()) {
+                        return Err(format!("{}() got multiple sub-patterns for attribute '{}'", name, attr));
+                    }
+                    let attr_path = format!("eppx_match_attr<{}>({}, [](const auto& __eppx_obj) {{ return __eppx_obj.{}; }})", name, path, attr);
+                    alternatives = self.flatten(pattern, &attr_path, alternatives)?;
+                }
+                Ok(alternatives)
+            }
+        }
+    }
+
+    fn action(&mut self, case: usize, bindings: Vec<(String, String)>) -> usize {
+        if let Some(index) = self.actions.iter().position(|(c, b)| *c == case && *b == bindings) {
+            return index;
+        }
+        self.actions.push((case, bindings));
+        self.actions.len() - 1
+    }
+
+    fn emit_bindings(bindings: &[(String, String)]) -> String {
+        bindings.iter().map(|(name, path)| format!("auto {} = {};\n", name, path)).collect()
+    }
+
+    fn emit_tree(&mut self, mut rows: Vec<Row>) -> Result<String, String> {
+        let mut out = String::new();
+        while !rows.is_empty() {
+            if let Some(test) = rows[0].first_test().cloned() {
+                let Step::Test { cond, .. } = &test else { unreachable!() };
+                let mut then_rows = Vec::new();
+                let mut else_rows = Vec::new();
+                for row in rows {
+                    if let Some(position) = row.steps.iter().position(|step| *step == test) {
+                        let mut row = row;
+                        row.steps.remove(position);
+                        then_rows.push(row);
+                    } else if row.excludes(&test) {
+                        else_rows.push(row);
+                    } else {
+                        then_rows.push(row.clone());
+                        else_rows.push(row);
+                    }
+                }
+                out.push_str(&format!("if ({}) {{\n", cond));
+                out.push_str(&indent_code(&self.emit_tree(then_rows)?));
+                let else_code = self.emit_tree(else_rows)?;
+                if else_code.is_empty() {
+                    out.push_str("}\n");
+                } else if else_code.starts_with("if (") {
+                    // A lone if statement: continue the else-if chain
+                    out.push_str(&format!("}} else {}", else_code));
+                } else {
+                    out.push_str("} else {\n");
+                    out.push_str(&indent_code(&else_code));
+                    out.push_str("}\n");
+                }
+                return Ok(out);
+            }
+
+            // The first row has matched
+            let row = rows.remove(0);
+            let bindings = row.bindings();
+            let action = self.action(row.case, bindings.clone());
+            let label = self.label(action);
+            let Some(guard) = &self.cases[row.case].guard else {
+                out.push_str(&format!("goto {};\n", label));
+                return Ok(out);
+            };
+            let mut guard_symbol_table = self.symbol_table.fork();
+            guard_symbol_table.enter_scope();
+            for (name, _) in &bindings {
+                guard_symbol_table.add_variable(name, "auto");
+            }
+            let guard_cpp = emit_expression_cpp(guard, &mut guard_symbol_table, self.function_table, self.type_map)?;
+            out.push_str("{\n");
+            out.push_str(&indent_code(&format!("{}if (eppx_truthy({})) goto {};", Self::emit_bindings(&bindings), guard_cpp, label)));
+            out.push_str("}\n");
+        }
+        Ok(out)
+    }
+}
+
+// Names a pattern binds, in order
+fn bound_names(pattern: &Pattern, names: &mut Vec<String>) {
+    match pattern {
+        Pattern::Capture(name) | Pattern::Star(Some(name)) => names.push(name.clone()),
+        Pattern::As(inner, name) => {
+            bound_names(inner, names);
+            names.push(name.clone());
+        }
+        Pattern::Sequence(items) => items.iter().for_each(|item| bound_names(item, names)),
+        Pattern::Or(options) => {
+            if let Some(first) = options.first() {
+                bound_names(first, names);
+            }
+        }
+        Pattern::Mapping { entries, rest } => {
+            entries.iter().for_each(|(_, value)| bound_names(value, names));
+            names.extend(rest.clone());
+        }
+        Pattern::Class { positional, keywords, .. } => {
+            positional.iter().for_each(|item| bound_names(item, names));
+            keywords.iter().for_each(|(_, value)| bound_names(value, names));
+        }
+        Pattern::Literal(_) | Pattern::Value(_) | Pattern::Wildcard | Pattern::Star(None) => {}
+    }
+}
+
+// Does the pattern match every subject?
+fn is_irrefutable(pattern: &Pattern) -> bool {
+    match pattern {
+        Pattern::Wildcard | Pattern::Capture(_) => true,
+        Pattern::As(inner, _) => is_irrefutable(inner),
+        Pattern::Or(options) => options.iter().any(is_irrefutable),
+        _ => false,
+    }
+}
+
+// `Cls.MEMBER` value patterns in a pattern
+fn enum_values<'p>(pattern: &'p Pattern, values: &mut Vec<(&'p str, &'p str)>) {
+    match pattern {
+        Pattern::Value(Expression::AttributeAccess { object, attr }) => {
+            if let Expression::Identifier(class) = &**object {
+                values.push((class, attr));
+            }
+        }
+        Pattern::Or(options) => options.iter().for_each(|option| enum_values(option, values)),
+        Pattern::As(inner, _) => enum_values(inner, values),
+        _ => {}
+    }
+}
+
+// Warns when the value patterns of a match over an enum-like class leave some
+// of its members unhandled and no case catches everything else
+fn check_enum_exhaustiveness(cases: &[MatchCase], symbol_table: &SymbolTable) {
+    let mut enum_class = None;
+    let mut covered = HashSet::new();
+    for case in cases {
+        if case.guard.is_none() && is_irrefutable(&case.pattern) {
+            return;
+        }
+        let mut values = Vec::new();
+        enum_values(&case.pattern, &mut values);
+        for (class, member) in values {
+            let Some(members) = symbol_table.get_class(class).and_then(|info| info.enum_members.as_ref()) else {
+                continue;
+            };
+            match enum_class {
+                None => enum_class = Some((class, members)),
+                Some((first, _)) if first != class => return, // values of several classes
+                _ => {}
+            }
+            if case.guard.is_none() {
+                covered.insert(member);
+            }
+        }
+    }
+    if let Some((class, members)) = enum_class {
+        let missing: Vec<String> = members.iter().filter(|m| !covered.contains(m.as_str())).map(|m| format!("{}.{}", class, m)).collect();
+        if !missing.is_empty() {
+            symbol_table.warn(format!("match on enum-like class '{}' is not exhaustive: {} not handled", class, missing.join(", ")));
+        }
+    }
+}
+
+pub(super) fn generate_match(
+    subject: &Expression,
+    cases: &[MatchCase],
+    declared_vars: &mut HashSet<String>,
+    symbol_table: &mut SymbolTable,
+    function_table: &mut FunctionTable,
+    type_map: &mut TypeMap,
+) -> Result<String, String> {
+    // As in Python, only the last case may match everything
+    for case in &cases[..cases.len().saturating_sub(1)] {
+        if case.guard.is_none() && is_irrefutable(&case.pattern) {
+            let what = match &case.pattern {
+                Pattern::Capture(name) => format!("name capture '{}'", name),
+                _ => "wildcard".to_string(),
+            };
+            return Err(format!("{} makes remaining patterns unreachable", what));
+        }
+    }
+    check_enum_exhaustiveness(cases, symbol_table);
+
+    let id = NEXT_MATCH_ID.fetch_add(1, Ordering::Relaxed);
+    let subject_cpp = emit_expression_cpp(subject, symbol_table, function_table, type_map)?;
+    let subject_var = format!("__eppx_match_{}", id);
+    let mut lowering = MatchLowering { id, cases, actions: Vec::new(), symbol_table, function_table, type_map };
+
+    let mut rows = Vec::new();
+    for (case_index, case) in cases.iter().enumerate() {
+        for steps in lowering.flatten(&case.pattern, &subject_var, vec![Vec::new()])? {
+            rows.push(Row { steps, case: case_index });
+        }
+    }
+    let tree = lowering.emit_tree(rows)?;
+
+    let mut out = String::from("{\n");
+    let mut body = format!("auto&& {} = {};\n", subject_var, subject_cpp);
+    body.push_str(&tree);
+    body.push_str(&format!("goto {};\n", lowering.end_label()));
+    // OR alternatives binding different paths share a case body; later copies
+    // must declare the body's variables again
+    let declared_before = declared_vars.clone();
+    let mut emitted_cases = HashSet::new();
+    for (action, (case, bindings)) in lowering.actions.clone().into_iter().enumerate() {
+        let mut case_symbol_table = lowering.symbol_table.fork();
+        case_symbol_table.enter_scope();
+        for (name, _) in &bindings {
+            case_symbol_table.add_variable(name, "auto");
+        }
+        let mut copy_declared_vars = declared_before.clone();
+        let case_declared_vars = if emitted_cases.insert(case) { &mut *declared_vars } else { &mut copy_declared_vars };
+        let case_cpp = generate_statement_list_cpp(&cases[case].body, case_declared_vars, &mut case_symbol_table, lowering.function_table, lowering.type_map)?;
+        body.push_str(&format!("{}: {{\n", lowering.label(action)));
+        if !bindings.is_empty() {
+            body.push_str(&indent_code(MatchLowering::emit_bindings(&bindings).trim_end()));
+        }
+        body.push_str(&case_cpp); // statement lists come indented
+        body.push_str("}\n");
+        body.push_str(&format!("goto {};\n", lowering.end_label()));
+    }
+    body.push_str(&format!("{}:;", lowering.end_label()));
+    out.push_str(&indent_code(&body));
+    out.push_str("}\n");
+    Ok(out)
+}
+
+// Records what class and value patterns need to know about a class
+// `cpp_name` is the qualified C++ name; nested classes are registered under their own name too
+pub(super) fn register_class(
+    cpp_name: &str,
+    base_exprs: &[Expression],
+    decorators: &[Decorator],
+    body: &[AstNode],
+    symbol_table: &mut SymbolTable,
+) -> Result<(), String> {
+    let name = cpp_name.rsplit("::").next().unwrap_or(cpp_name);
+    let bases = super::class_base_names(name, base_exprs)?;
+    let mut match_args = None;
+    let mut init_subclass_params = None;
+    let mut members = Vec::new();
+    let mut enum_like = true;
+    for node in body {
+        match node {
+            AstNode::Statement(Statement::Assignment { target, operator: AssignmentOperator::Assign, value }) => {
+                let Expression::Identifier(member) = &**target else {
+                    enum_like = false;
+                    continue;
+                };
+                if member == "__match_args__" {
+                    let (Expression::TupleLiteral(items) | Expression::ListLiteral(items)) = &**value else {
+                        return Err(format!("{}.__match_args__ must be a tuple of attribute names", name));
+                    };
+                    let names = items
+                        .iter()
+                        .map(|item| match item {
+                            Expression::StringLiteral(attr) => Ok(attr.clone()),
+                            _ => Err(format!("{}.__match_args__ must be a tuple of attribute names", name)),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    match_args = Some(names);
+                } else if matches!(
+                    **value,
+                    Expression::IntegerLiteral(_) | Expression::FloatLiteral(_) | Expression::StringLiteral(_) | Expression::BooleanLiteral(_)
+                ) {
+                    members.push(member.clone());
+                } else {
+                    enum_like = false;
+                }
+            }
+            AstNode::Statement(Statement::Pass) => {}
+            AstNode::Statement(Statement::ClassDef { name: nested_name, bases: nested_bases, decorators: nested_decorators, body: nested_body, .. }) => {
+                enum_like = false;
+                let nested_cpp_name = format!("{}::{}", cpp_name, nested_name);
+                register_class(&nested_cpp_name, nested_bases, nested_decorators, nested_body, symbol_table)?;
+            }
+            AstNode::Statement(Statement::FunctionDef { name: method_name, params, .. }) if method_name == "__init_subclass__" => {
+                enum_like = false;
+                init_subclass_params = Some(params.iter().skip(1).cloned().collect());
+            }
+            _ => enum_like = false,
+        }
+    }
+    let dataclass = dataclass::dataclass_info(name, decorators, &bases, body, symbol_table)?;
+    let enum_info = dataclass::enum_info(name, base_exprs, decorators, body)?;
+    let enum_members = match &enum_info {
+        Some(info) => Some(info.members.iter().map(|(member, _)| member.clone()).collect()),
+        None if enum_like && bases.is_empty() && !members.is_empty() => Some(members),
+        None => None,
+    };
+    symbol_table.add_class(
+        name,
+        ClassInfo { cpp_name: cpp_name.to_string(), bases, match_args, enum_members, init_subclass_params, dataclass, enum_info },
+    );
+    Ok(())
+}
@@ -2,18 +2,28 @@
 //! 
 //! Handles loading and saving Codon configuration settings
 
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::fs;
 use serde::{Deserialize, Serialize};
+use toml_edit::{value, DocumentMut, InlineTable, Item};
 use crate::codon::{CodonConfig, OptimizationLevel, CodonError};
 
 const CONFIG_FILE_NAME: &str = "codon.toml";
+const WORKSPACE_FILE_NAME: &str = "eppx-workspace.toml";
 const CONFIG_DIR_NAME: &str = ".eppx";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CodonProjectConfig {
     pub codon: CodonConfig,
     pub project: ProjectConfig,
+    /// E++ packages the project imports, by name
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+    #[serde(default)]
+    pub build: BuildConfig,
+    #[serde(default)]
+    pub lint: LintConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,9 +31,117 @@ pub struct ProjectConfig {
     pub name: String,
     pub version: String,
     pub description: Option<String>,
-    pub dependencies: Vec<String>,
     pub build_target: String,
     pub output_dir: String,
+    /// Program run by `eppx run` (`DEFAULT_ENTRY_POINT` when unset); the other
+    /// `.eppx` files in `src/` are its modules. A library has no program
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry_point: Option<String>,
+}
+
+pub const DEFAULT_ENTRY_POINT: &str = "src/main.eppx";
+
+/// A `[dependencies]` entry: a version requirement for the package registry
+/// (`textkit = "^1.2"`, or `textkit = { version = "^1.2", registry = "team" }`
+/// for a registry configured by name), or a package in a local directory
+/// (`textkit = { path = "../textkit" }`, optionally with a `version` it must match)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Dependency {
+    Version(String),
+    Detailed(DependencyDetail),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DependencyDetail {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+}
+
+impl Dependency {
+    pub fn version(&self) -> Option<&str> {
+        match self {
+            Dependency::Version(version) => Some(version),
+            Dependency::Detailed(detail) => detail.version.as_deref(),
+        }
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Dependency::Version(_) => None,
+            Dependency::Detailed(detail) => detail.path.as_deref(),
+        }
+    }
+
+    pub fn registry(&self) -> Option<&str> {
+        match self {
+            Dependency::Version(_) => None,
+            Dependency::Detailed(detail) => detail.registry.as_deref(),
+        }
+    }
+
+    /// The entry's value as written in `codon.toml`
+    fn to_toml(&self) -> Item {
+        match self {
+            Dependency::Version(version) => value(version.as_str()),
+            Dependency::Detailed(detail) => {
+                let mut table = InlineTable::new();
+                if let Some(version) = &detail.version {
+                    table.insert("version", version.as_str().into());
+                }
+                if let Some(path) = &detail.path {
+                    table.insert("path", path.as_str().into());
+                }
+                if let Some(registry) = &detail.registry {
+                    table.insert("registry", registry.as_str().into());
+                }
+                value(table)
+            }
+        }
+    }
+}
+
+/// C++ toolchain settings for native (`--fast`) builds. `CXX`, `CXXFLAGS` and
+/// `LDFLAGS` from the environment take precedence over / add to these.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BuildConfig {
+    /// Compiler executable; found on PATH (g++, clang++, c++) when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compiler: Option<String>,
+    pub standard: String,
+    pub flags: Vec<String>,
+    /// Preprocessor definitions: `NAME` or `NAME=value`
+    pub defines: Vec<String>,
+    pub include_dirs: Vec<String>,
+    pub library_dirs: Vec<String>,
+    pub libraries: Vec<String>,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        Self {
+            compiler: None,
+            standard: "c++17".to_string(),
+            flags: Vec::new(),
+            defines: Vec::new(),
+            include_dirs: Vec::new(),
+            library_dirs: Vec::new(),
+            libraries: Vec::new(),
+        }
+    }
+}
+
+/// `[lint]`: how `eppx lint` checks the project
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LintConfig {
+    /// Rules not checked, such as `unused-variable`
+    pub ignore: Vec<String>,
 }
 
 impl Default for CodonProjectConfig {
@@ -34,14 +152,45 @@ impl Default for CodonProjectConfig {
                 name: "eppx-project".to_string(),
                 version: "0.1.0".to_string(),
                 description: None,
-                dependencies: Vec::new(),
                 build_target: "native".to_string(),
                 output_dir: "target".to_string(),
+                entry_point: None,
             },
+            dependencies: BTreeMap::new(),
+            build: BuildConfig::default(),
+            lint: LintConfig::default(),
         }
     }
 }
 
+/// `~/.eppx/global.toml`: defaults for every project, and the package registries
+/// `eppx` knows by name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GlobalConfig {
+    /// Registry used when neither `--registry` nor `EPPX_REGISTRY` names one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_registry: Option<String>,
+    #[serde(default)]
+    pub codon: CodonConfig,
+    /// Registry directories (local, or on a shared drive) by name
+    #[serde(default)]
+    pub registries: BTreeMap<String, String>,
+}
+
+/// `eppx-workspace.toml`: related projects that share one `eppx.lock`,
+/// `.eppx_packages/` and `.eppx_build/`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceConfig {
+    pub workspace: WorkspaceSection,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceSection {
+    /// Member project directories relative to the workspace; `dir/*` is every
+    /// directory in `dir` with a `codon.toml`
+    pub members: Vec<String>,
+}
+
 pub struct ConfigManager {
     config_dir: PathBuf,
     project_config: Option<CodonProjectConfig>,
@@ -56,7 +205,7 @@ impl ConfigManager {
         })
     }
 
-    pub fn load_project_config(&mut self, project_dir: &PathBuf) -> Result<CodonProjectConfig, CodonError> {
+    pub fn load_project_config(&mut self, project_dir: &Path) -> Result<CodonProjectConfig, CodonError> {
         let config_path = project_dir.join(CONFIG_FILE_NAME);
         
         if config_path.exists() {
@@ -77,7 +226,7 @@ impl ConfigManager {
         }
     }
 
-    pub fn save_project_config(&self, project_dir: &PathBuf, config: &CodonProjectConfig) -> Result<(), CodonError> {
+    pub fn save_project_config(&self, project_dir: &Path, config: &CodonProjectConfig) -> Result<(), CodonError> {
         let config_path = project_dir.join(CONFIG_FILE_NAME);
         
         let content = toml::to_string_pretty(config)
@@ -89,26 +238,22 @@ impl ConfigManager {
         Ok(())
     }
 
-    pub fn get_global_config(&self) -> Result<CodonConfig, CodonError> {
+    /// The global config, or the defaults when there is none yet
+    pub fn get_global_config(&self) -> Result<GlobalConfig, CodonError> {
         let global_config_path = self.config_dir.join("global.toml");
         
         if global_config_path.exists() {
             let content = fs::read_to_string(&global_config_path)
                 .map_err(|e| CodonError::ConfigError(e.to_string()))?;
             
-            let config: CodonConfig = toml::from_str(&content)
-                .map_err(|e| CodonError::ConfigError(e.to_string()))?;
-            
-            Ok(config)
+            toml::from_str(&content)
+                .map_err(|e| CodonError::ConfigError(format!("{}: {}", global_config_path.display(), e)))
         } else {
-            // Create default global config
-            let config = CodonConfig::default();
-            self.save_global_config(&config)?;
-            Ok(config)
+            Ok(GlobalConfig::default())
         }
     }
 
-    pub fn save_global_config(&self, config: &CodonConfig) -> Result<(), CodonError> {
+    pub fn save_global_config(&self, config: &GlobalConfig) -> Result<(), CodonError> {
         let global_config_path = self.config_dir.join("global.toml");
         
         // Ensure config directory exists
@@ -124,60 +269,111 @@ impl ConfigManager {
         Ok(())
     }
 
-    pub fn update_project_config<F>(&mut self, project_dir: &PathBuf, updater: F) -> Result<(), CodonError>
+    pub fn update_global_config<F>(&self, updater: F) -> Result<(), CodonError>
     where
-        F: FnOnce(&mut CodonProjectConfig),
+        F: FnOnce(&mut GlobalConfig),
     {
-        let mut config = self.load_project_config(project_dir)?;
+        let mut config = self.get_global_config()?;
         updater(&mut config);
-        self.save_project_config(project_dir, &config)?;
+        self.save_global_config(&config)
+    }
+
+    /// Remembers the registry in `dir` as `name`, replacing any registry of that name
+    pub fn add_registry(&self, name: &str, dir: &Path, make_default: bool) -> Result<(), CodonError> {
+        self.update_global_config(|config| {
+            config.registries.insert(name.to_string(), dir.to_string_lossy().into_owned());
+            if make_default {
+                config.default_registry = Some(name.to_string());
+            }
+        })
+    }
+
+    pub fn remove_registry(&self, name: &str) -> Result<(), CodonError> {
+        self.update_global_config(|config| {
+            config.registries.remove(name);
+            if config.default_registry.as_deref() == Some(name) {
+                config.default_registry = None;
+            }
+        })
+    }
+
+    /// Edits `codon.toml` in place, so the keys the edit leaves alone keep their
+    /// comments, order and formatting
+    pub fn update_project_config<F>(&mut self, project_dir: &Path, updater: F) -> Result<(), CodonError>
+    where
+        F: FnOnce(&mut DocumentMut),
+    {
+        // Creates the default config when there is none yet
+        self.load_project_config(project_dir)?;
+        let config_path = project_dir.join(CONFIG_FILE_NAME);
+        let content = fs::read_to_string(&config_path)
+            .map_err(|e| CodonError::ConfigError(e.to_string()))?;
+        let mut document: DocumentMut = content.parse()
+            .map_err(|e| CodonError::ConfigError(format!("{}: {}", config_path.display(), e)))?;
+        updater(&mut document);
+        let content = document.to_string();
+        let config: CodonProjectConfig = toml::from_str(&content)
+            .map_err(|e| CodonError::ConfigError(format!("{}: {}", config_path.display(), e)))?;
+        fs::write(&config_path, content)
+            .map_err(|e| CodonError::ConfigError(e.to_string()))?;
         self.project_config = Some(config);
         Ok(())
     }
 
-    pub fn add_dependency(&mut self, project_dir: &PathBuf, dependency: &str) -> Result<(), CodonError> {
-        self.update_project_config(project_dir, |config| {
-            if !config.project.dependencies.contains(&dependency.to_string()) {
-                config.project.dependencies.push(dependency.to_string());
+    /// Adds `name` to `[dependencies]`, replacing any requirement it already had
+    pub fn add_dependency(&mut self, project_dir: &Path, name: &str, dependency: Dependency) -> Result<(), CodonError> {
+        self.update_project_config(project_dir, |document| {
+            let dependencies = document.entry("dependencies").or_insert_with(toml_edit::table);
+            if let Some(dependencies) = dependencies.as_table_like_mut() {
+                dependencies.insert(name, dependency.to_toml());
             }
         })
     }
 
-    pub fn remove_dependency(&mut self, project_dir: &PathBuf, dependency: &str) -> Result<(), CodonError> {
-        self.update_project_config(project_dir, |config| {
-            config.project.dependencies.retain(|d| d != dependency);
+    pub fn remove_dependency(&mut self, project_dir: &Path, name: &str) -> Result<(), CodonError> {
+        self.update_project_config(project_dir, |document| {
+            if let Some(dependencies) = document.get_mut("dependencies").and_then(Item::as_table_like_mut) {
+                dependencies.remove(name);
+            }
         })
     }
 
-    pub fn set_optimization_level(&mut self, project_dir: &PathBuf, level: OptimizationLevel) -> Result<(), CodonError> {
-        self.update_project_config(project_dir, |config| {
-            config.codon.optimization_level = level;
+    #[allow(dead_code)]
+    pub fn set_optimization_level(&mut self, project_dir: &Path, level: OptimizationLevel) -> Result<(), CodonError> {
+        self.update_project_config(project_dir, |document| {
+            document["codon"]["optimization_level"] = value(format!("{:?}", level));
         })
     }
 
-    pub fn enable_gpu(&mut self, project_dir: &PathBuf, enable: bool) -> Result<(), CodonError> {
-        self.update_project_config(project_dir, |config| {
-            config.codon.enable_gpu = enable;
+    #[allow(dead_code)]
+    pub fn enable_gpu(&mut self, project_dir: &Path, enable: bool) -> Result<(), CodonError> {
+        self.update_project_config(project_dir, |document| {
+            document["codon"]["enable_gpu"] = value(enable);
         })
     }
 
-    pub fn enable_parallel(&mut self, project_dir: &PathBuf, enable: bool) -> Result<(), CodonError> {
-        self.update_project_config(project_dir, |config| {
-            config.codon.enable_parallel = enable;
+    #[allow(dead_code)]
+    pub fn enable_parallel(&mut self, project_dir: &Path, enable: bool) -> Result<(), CodonError> {
+        self.update_project_config(project_dir, |document| {
+            document["codon"]["enable_parallel"] = value(enable);
         })
     }
 
-    pub fn set_target_arch(&mut self, project_dir: &PathBuf, arch: &str) -> Result<(), CodonError> {
-        self.update_project_config(project_dir, |config| {
-            config.codon.target_arch = arch.to_string();
+    #[allow(dead_code)]
+    pub fn set_target_arch(&mut self, project_dir: &Path, arch: &str) -> Result<(), CodonError> {
+        self.update_project_config(project_dir, |document| {
+            document["codon"]["target_arch"] = value(arch);
         })
     }
 
+    #[allow(dead_code)]
     pub fn get_current_config(&self) -> Option<&CodonProjectConfig> {
         self.project_config.as_ref()
     }
 
-    pub fn create_new_project(&self, project_dir: &PathBuf, name: &str, description: Option<&str>) -> Result<(), CodonError> {
+    /// Writes the default `codon.toml` for a project named `name`; the project's
+    /// sources come from its template
+    pub fn create_new_project(&self, project_dir: &Path, name: &str, description: Option<&str>) -> Result<(), CodonError> {
         // Ensure project directory exists
         fs::create_dir_all(project_dir)
             .map_err(|e| CodonError::ConfigError(e.to_string()))?;
@@ -186,34 +382,54 @@ impl ConfigManager {
         config.project.name = name.to_string();
         config.project.description = description.map(|s| s.to_string());
         
-        self.save_project_config(project_dir, &config)?;
-        
-        // Create basic project structure
-        let src_dir = project_dir.join("src");
-        fs::create_dir_all(&src_dir)
-            .map_err(|e| CodonError::ConfigError(e.to_string()))?;
-        
-        let main_file = src_dir.join("main.eppx");
-        if !main_file.exists() {
-            let main_content = r#"# E++ main file
-print("Hello, E++ with Codon!")
-
-# Example function
-def fibonacci(n):
-    if n <= 1:
-        return n
-    return fibonacci(n - 1) + fibonacci(n - 2)
-
-# Test the function
-result = fibonacci(10)
-print(f"Fibonacci(10) = {result}")
-"#;
-            fs::write(&main_file, main_content)
-                .map_err(|e| CodonError::ConfigError(e.to_string()))?;
-        }
-        
-        Ok(())
+        self.save_project_config(project_dir, &config)
+    }
+
+    /// `~/.eppx/templates/`: one directory per user-defined project template
+    pub fn templates_dir(&self) -> PathBuf {
+        self.config_dir.join("templates")
+    }
+}
+
+/// Reads `codon.toml` from `project_dir`, if there is one
+pub fn read_project_config(project_dir: &Path) -> Result<Option<CodonProjectConfig>, CodonError> {
+    let config_path = project_dir.join(CONFIG_FILE_NAME);
+    if !config_path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&config_path)
+        .map_err(|e| CodonError::ConfigError(e.to_string()))?;
+    toml::from_str(&content)
+        .map(Some)
+        .map_err(|e| CodonError::ConfigError(format!("{}: {}", config_path.display(), e)))
+}
+
+/// The nearest directory at or above `start` holding a `codon.toml`
+pub fn find_project_dir(start: &Path) -> Option<PathBuf> {
+    start.ancestors().find(|dir| dir.join(CONFIG_FILE_NAME).is_file()).map(Path::to_path_buf)
+}
+
+/// Whether `dir` holds a `codon.toml`
+pub fn is_project_dir(dir: &Path) -> bool {
+    dir.join(CONFIG_FILE_NAME).is_file()
+}
+
+/// Reads `eppx-workspace.toml` from `workspace_dir`, if there is one
+pub fn read_workspace_config(workspace_dir: &Path) -> Result<Option<WorkspaceConfig>, CodonError> {
+    let config_path = workspace_dir.join(WORKSPACE_FILE_NAME);
+    if !config_path.exists() {
+        return Ok(None);
     }
+    let content = fs::read_to_string(&config_path)
+        .map_err(|e| CodonError::ConfigError(e.to_string()))?;
+    toml::from_str(&content)
+        .map(Some)
+        .map_err(|e| CodonError::ConfigError(format!("{}: {}", config_path.display(), e)))
+}
+
+/// The nearest directory at or above `start` holding an `eppx-workspace.toml`
+pub fn find_workspace_dir(start: &Path) -> Option<PathBuf> {
+    start.ancestors().find(|dir| dir.join(WORKSPACE_FILE_NAME).is_file()).map(Path::to_path_buf)
 }
 
 fn get_config_dir() -> Result<PathBuf, CodonError> {
@@ -236,13 +452,13 @@ mod tests {
         let project_name = "test-project";
         let description = "A test project";
         
-        config_manager.create_new_project(&temp_dir.path().to_path_buf(), project_name, Some(description)).unwrap();
+        config_manager.create_new_project(temp_dir.path(), project_name, Some(description)).unwrap();
         
         let config_path = temp_dir.path().join(CONFIG_FILE_NAME);
         assert!(config_path.exists());
         
         let mut config_manager = ConfigManager::new().unwrap();
-        let config = config_manager.load_project_config(&temp_dir.path().to_path_buf()).unwrap();
+        let config = config_manager.load_project_config(temp_dir.path()).unwrap();
         
         assert_eq!(config.project.name, project_name);
         assert_eq!(config.project.description, Some(description.to_string()));
@@ -253,18 +469,44 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let mut config_manager = ConfigManager::new().unwrap();
         
-        config_manager.create_new_project(&temp_dir.path().to_path_buf(), "test", None).unwrap();
+        config_manager.create_new_project(temp_dir.path(), "test", None).unwrap();
         
         // Add dependency
-        config_manager.add_dependency(&temp_dir.path().to_path_buf(), "numpy").unwrap();
+        let dependency = Dependency::Version("^1.2".to_string());
+        config_manager.add_dependency(temp_dir.path(), "textkit", dependency.clone()).unwrap();
         
-        let config = config_manager.load_project_config(&temp_dir.path().to_path_buf()).unwrap();
-        assert!(config.project.dependencies.contains(&"numpy".to_string()));
+        let config = config_manager.load_project_config(temp_dir.path()).unwrap();
+        assert_eq!(config.dependencies.get("textkit"), Some(&dependency));
         
         // Remove dependency
-        config_manager.remove_dependency(&temp_dir.path().to_path_buf(), "numpy").unwrap();
+        config_manager.remove_dependency(temp_dir.path(), "textkit").unwrap();
         
-        let config = config_manager.load_project_config(&temp_dir.path().to_path_buf()).unwrap();
-        assert!(!config.project.dependencies.contains(&"numpy".to_string()));
+        let config = config_manager.load_project_config(temp_dir.path()).unwrap();
+        assert!(!config.dependencies.contains_key("textkit"));
+    }
+
+    #[test]
+    fn test_dependency_edits_keep_the_rest_of_the_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_dir = temp_dir.path().to_path_buf();
+        let original = "# The app\n[codon]\nversion = \"latest\"  # pinned later\noptimization_level = \"Release\"\ntarget_arch = \"native\"\nenable_parallel = true\nenable_gpu = false\npython_interop = true\n\n[project]\nname = \"app\"\nversion = \"0.1.0\"\nbuild_target = \"native\"\noutput_dir = \"target\"\n";
+        fs::write(project_dir.join(CONFIG_FILE_NAME), original).unwrap();
+        let mut config_manager = ConfigManager::new().unwrap();
+
+        let local = Dependency::Detailed(DependencyDetail { version: Some("^0.2".to_string()), path: Some("../greetings".to_string()), registry: None });
+        config_manager.add_dependency(&project_dir, "textkit", Dependency::Version("^1.2".to_string())).unwrap();
+        config_manager.add_dependency(&project_dir, "greetings", local.clone()).unwrap();
+        let content = fs::read_to_string(project_dir.join(CONFIG_FILE_NAME)).unwrap();
+        assert!(content.starts_with(original), "{}", content);
+        assert!(content.contains("textkit = \"^1.2\""));
+        assert!(content.contains("greetings = { version = \"^0.2\", path = \"../greetings\" }"));
+        assert!(!content.contains("[build]") && !content.contains("[lint]"));
+        let config = config_manager.load_project_config(&project_dir).unwrap();
+        assert_eq!(config.dependencies.get("greetings"), Some(&local));
+
+        config_manager.remove_dependency(&project_dir, "textkit").unwrap();
+        config_manager.remove_dependency(&project_dir, "greetings").unwrap();
+        let content = fs::read_to_string(project_dir.join(CONFIG_FILE_NAME)).unwrap();
+        assert_eq!(content, format!("{}\n[dependencies]\n", original));
     }
 } 
\ No newline at end of file
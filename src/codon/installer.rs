@@ -69,9 +69,7 @@ fn get_platform() -> Result<String, CodonError> {
 
 fn get_download_url(version: &str, platform: &str) -> Result<String, CodonError> {
     if version == "latest" {
-        Ok(format!(
-            "https://exaloop.io/install.sh"
-        ))
+        Ok("https://exaloop.io/install.sh".to_string())
     } else {
         Ok(format!(
             "https://github.com/exaloop/codon/releases/download/v{}/codon-{}-{}.tar.gz",
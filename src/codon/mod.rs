@@ -4,14 +4,18 @@
 //! that compiles to native machine code. It allows E++ to use Codon as a backend
 //! for compilation and execution.
 
+// The Codon backend's fuller API (installing Codon, running and benchmarking
+// programs, cache management), which the CLI doesn't call yet
+#[allow(dead_code)]
 pub mod backend;
+#[allow(dead_code)]
 pub mod installer;
+#[allow(dead_code)]
 pub mod runner;
 pub mod config;
 
 use std::path::PathBuf;
 use std::process::Command;
-use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -20,6 +24,7 @@ pub enum CodonError {
     #[error("Codon not found in PATH")]
     CodonNotFound,
     #[error("Codon installation failed: {0}")]
+    #[allow(dead_code)]
     InstallationFailed(String),
     #[error("Codon compilation failed: {0}")]
     CompilationFailed(String),
@@ -76,6 +81,7 @@ impl CodonBackend {
         }
     }
 
+    #[allow(dead_code)]
     pub fn with_codon_path(mut self, path: PathBuf) -> Self {
         self.codon_path = Some(path);
         self
@@ -218,6 +224,7 @@ impl CodonBackend {
         Ok(python_code)
     }
 
+    #[allow(dead_code)]
     pub fn get_codon_version(&self) -> Result<String, CodonError> {
         let codon_path = self.codon_path.as_ref()
             .ok_or_else(|| CodonError::ConfigError("Codon path not set".to_string()))?;
@@ -235,6 +242,7 @@ impl CodonBackend {
         Ok(version.trim().to_string())
     }
 
+    #[allow(dead_code)]
     pub fn list_available_targets(&self) -> Result<Vec<String>, CodonError> {
         let codon_path = self.codon_path.as_ref()
             .ok_or_else(|| CodonError::ConfigError("Codon path not set".to_string()))?;
@@ -260,11 +268,13 @@ impl CodonBackend {
         Ok(target_list)
     }
 
+    #[allow(dead_code)]
     pub fn clear_cache(&mut self) -> Result<(), CodonError> {
         // Implementation would clear Codon's cache
         Ok(())
     }
 
+    #[allow(dead_code)]
     pub fn get_cache_info(&self) -> backend::CacheInfo {
         backend::CacheInfo {
             cache_dir: PathBuf::from("/tmp/codon-cache"),
@@ -276,17 +286,16 @@ impl CodonBackend {
 
 pub struct CodonManager {
     pub backend: CodonBackend,
-    installed_packages: HashMap<String, String>,
 }
 
 impl CodonManager {
+    #[allow(dead_code)]
     pub fn new() -> Self {
         let config = CodonConfig::default();
         let backend = CodonBackend::new(config);
         
         Self {
             backend,
-            installed_packages: HashMap::new(),
         }
     }
 
@@ -295,7 +304,6 @@ impl CodonManager {
         
         Self {
             backend,
-            installed_packages: HashMap::new(),
         }
     }
 
@@ -322,26 +330,4 @@ impl CodonManager {
     pub fn run_file(&mut self, input_file: &PathBuf) -> Result<(), CodonError> {
         self.backend.run_with_codon(input_file)
     }
-
-    pub fn install_package(&mut self, package_name: &str) -> Result<(), CodonError> {
-        // Use Codon's package management capabilities
-        let codon_path = self.backend.codon_path.as_ref()
-            .ok_or_else(|| CodonError::ConfigError("Codon path not set".to_string()))?;
-
-        let mut cmd = Command::new(codon_path);
-        cmd.arg("install").arg(package_name);
-
-        let output = cmd.output()
-            .map_err(|e| CodonError::InstallationFailed(e.to_string()))?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(CodonError::InstallationFailed(stderr.to_string()));
-        }
-
-        // Store the installed package
-        self.installed_packages.insert(package_name.to_string(), "latest".to_string());
-        
-        Ok(())
-    }
 } 
\ No newline at end of file
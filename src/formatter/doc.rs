@@ -0,0 +1,122 @@
+// Layout for the formatter: a statement is built as a document of text and
+// places where it may break, and groups are laid out on one line when they fit
+
+#[derive(Debug, Clone)]
+pub enum Doc {
+    Text(String),
+    // A space on one line, a newline when its group breaks
+    Line,
+    // Nothing on one line, a newline when its group breaks
+    SoftLine,
+    // Text only written when its group breaks, for trailing commas
+    IfBroken(&'static str),
+    Indent(Vec<Doc>),
+    Group(Vec<Doc>),
+    Concat(Vec<Doc>),
+}
+
+pub fn text(text: impl Into<String>) -> Doc {
+    Doc::Text(text.into())
+}
+
+// `open`, the items separated by commas, `close`; broken one item per line with a trailing comma
+pub fn bracketed(open: &str, items: Vec<Doc>, close: &str) -> Doc {
+    if items.is_empty() {
+        return text(format!("{}{}", open, close));
+    }
+    let mut inner = vec![Doc::SoftLine];
+    for (index, item) in items.into_iter().enumerate() {
+        if index > 0 {
+            inner.push(text(","));
+            inner.push(Doc::Line);
+        }
+        inner.push(item);
+    }
+    inner.push(Doc::IfBroken(","));
+    Doc::Group(vec![text(open), Doc::Indent(inner), Doc::SoftLine, text(close)])
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+// Lays `doc` out within `width` columns, its continuation lines indented from `indent`
+pub fn render(doc: &Doc, indent: usize, width: usize) -> String {
+    let mut out = String::new();
+    let mut column = indent;
+    let mut stack = vec![(indent, Mode::Break, doc)];
+    while let Some((level, mode, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(s) => {
+                out.push_str(s);
+                column = match s.rfind('\n') {
+                    Some(newline) => s[newline + 1..].chars().count(),
+                    None => column + s.chars().count(),
+                };
+            }
+            Doc::Line | Doc::SoftLine if mode == Mode::Break => {
+                out.push('\n');
+                out.push_str(&" ".repeat(level));
+                column = level;
+            }
+            Doc::Line => {
+                out.push(' ');
+                column += 1;
+            }
+            Doc::SoftLine => {}
+            Doc::IfBroken(s) => {
+                if mode == Mode::Break {
+                    out.push_str(s);
+                    column += s.len();
+                }
+            }
+            Doc::Indent(docs) => stack.extend(docs.iter().rev().map(|doc| (level + 4, mode, doc))),
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (level, mode, doc))),
+            Doc::Group(docs) => {
+                let flat = mode == Mode::Flat || fits(docs, &stack, width.saturating_sub(column));
+                let mode = if flat { Mode::Flat } else { Mode::Break };
+                stack.extend(docs.iter().rev().map(|doc| (level, mode, doc)));
+            }
+        }
+    }
+    out
+}
+
+// Whether `docs` on one line, and what follows them up to the next line break, fit in `room` columns
+fn fits(docs: &[Doc], rest: &[(usize, Mode, &Doc)], room: usize) -> bool {
+    let mut room = room as isize;
+    let mut pending: Vec<(Mode, &Doc)> = docs.iter().rev().map(|doc| (Mode::Flat, doc)).collect();
+    let mut rest = rest.iter().rev();
+    loop {
+        let (mode, doc) = match pending.pop() {
+            Some(next) => next,
+            None => match rest.next() {
+                Some(&(_, mode, doc)) => (mode, doc),
+                None => return true,
+            },
+        };
+        match doc {
+            Doc::Text(s) => match s.find('\n') {
+                // Only the first line of a multi-line string shares the line
+                Some(newline) => return room >= s[..newline].chars().count() as isize,
+                None => room -= s.chars().count() as isize,
+            },
+            Doc::Line | Doc::SoftLine if mode == Mode::Break => return true,
+            Doc::Line => room -= 1,
+            Doc::SoftLine => {}
+            Doc::IfBroken(s) => {
+                if mode == Mode::Break {
+                    room -= s.len() as isize;
+                }
+            }
+            Doc::Indent(docs) | Doc::Concat(docs) | Doc::Group(docs) => {
+                pending.extend(docs.iter().rev().map(|doc| (mode, doc)));
+            }
+        }
+        if room < 0 {
+            return false;
+        }
+    }
+}
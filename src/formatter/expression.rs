@@ -0,0 +1,471 @@
+// Expressions and match patterns as documents, with the parentheses their
+// precedence needs and no others
+
+use std::collections::HashMap;
+
+use crate::ast::{Argument, BinOp, Comprehension, Expression, Pattern, UnaryOp};
+use crate::parser::{parse_literal, Token, TokenKind};
+
+use super::doc::{bracketed, text, Doc};
+
+// Binding strength, loosest first; an operand looser than its place needs parentheses
+const LAMBDA: u8 = 0;
+const CONDITIONAL: u8 = 1;
+const OR: u8 = 2;
+const AND: u8 = 3;
+const NOT: u8 = 4;
+const COMPARISON: u8 = 5;
+const BIT_OR: u8 = 6;
+const BIT_XOR: u8 = 7;
+const BIT_AND: u8 = 8;
+const SHIFT: u8 = 9;
+const SUM: u8 = 10;
+const PRODUCT: u8 = 11;
+const NEGATION: u8 = 12;
+const BIT_NOT: u8 = 13;
+const POWER: u8 = 14;
+const AWAIT: u8 = 15;
+const ATOM: u8 = 16;
+
+fn binary_precedence(op: BinOp) -> u8 {
+    match op {
+        BinOp::Or => OR,
+        BinOp::And => AND,
+        BinOp::Eq | BinOp::NotEq | BinOp::Lt | BinOp::Gt | BinOp::LtEq | BinOp::GtEq => COMPARISON,
+        BinOp::Is | BinOp::IsNot | BinOp::In | BinOp::NotIn => COMPARISON,
+        BinOp::BitOr => BIT_OR,
+        BinOp::BitXor => BIT_XOR,
+        BinOp::BitAnd => BIT_AND,
+        BinOp::LShift | BinOp::RShift => SHIFT,
+        BinOp::Add | BinOp::Sub => SUM,
+        BinOp::Mul | BinOp::Div | BinOp::FloorDiv | BinOp::Mod => PRODUCT,
+        BinOp::Pow => POWER,
+    }
+}
+
+fn precedence(expr: &Expression) -> u8 {
+    match expr {
+        Expression::Lambda { .. } | Expression::Yield(_) | Expression::YieldFrom(_) => LAMBDA,
+        Expression::IfExp { .. } => CONDITIONAL,
+        Expression::BinaryOperation { op, .. } => binary_precedence(*op),
+        Expression::Compare { .. } => COMPARISON,
+        Expression::UnaryOperation { op: UnaryOp::Not, .. } => NOT,
+        Expression::UnaryOperation { op: UnaryOp::Negate, .. } => NEGATION,
+        Expression::UnaryOperation { op: UnaryOp::BitNot, .. } => BIT_NOT,
+        Expression::IntegerLiteral(i) if *i < 0 => NEGATION,
+        Expression::FloatLiteral(f) | Expression::ImaginaryLiteral(f) if f.is_sign_negative() => NEGATION,
+        Expression::Await(_) => AWAIT,
+        _ => ATOM,
+    }
+}
+
+pub fn operator(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Sub => "-",
+        BinOp::Mul => "*",
+        BinOp::Div => "/",
+        BinOp::Mod => "%",
+        BinOp::Pow => "**",
+        BinOp::FloorDiv => "//",
+        BinOp::Eq => "==",
+        BinOp::NotEq => "!=",
+        BinOp::Lt => "<",
+        BinOp::Gt => ">",
+        BinOp::LtEq => "<=",
+        BinOp::GtEq => ">=",
+        BinOp::And => "and",
+        BinOp::Or => "or",
+        BinOp::BitAnd => "&",
+        BinOp::BitOr => "|",
+        BinOp::BitXor => "^",
+        BinOp::LShift => "<<",
+        BinOp::RShift => ">>",
+        BinOp::Is => "is",
+        BinOp::IsNot => "is not",
+        BinOp::In => "in",
+        BinOp::NotIn => "not in",
+    }
+}
+
+// A string literal in double quotes, for strings that were not in the source as written
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\0' => quoted.push_str("\\0"),
+            '\u{0007}' => quoted.push_str("\\a"),
+            '\u{0008}' => quoted.push_str("\\b"),
+            '\u{000B}' => quoted.push_str("\\v"),
+            '\u{000C}' => quoted.push_str("\\f"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn float(f: f64) -> String {
+    if f.is_infinite() {
+        // The literal that overflows to infinity
+        "1e999".to_string()
+    } else {
+        format!("{:?}", f)
+    }
+}
+
+// `'text'` as `"text"`, unless the text has double quotes in it
+fn prefer_double_quotes(token: &str) -> String {
+    let (quotes, body) = if token.len() >= 6 && token.starts_with("'''") && token.ends_with("'''") {
+        ("\"\"\"", &token[3..token.len() - 3])
+    } else if token.len() >= 2 && token.starts_with('\'') && token.ends_with('\'') {
+        ("\"", &token[1..token.len() - 1])
+    } else {
+        return token.to_string();
+    };
+    if body.contains('"') {
+        return token.to_string();
+    }
+    // `\'` needs no escaping between double quotes
+    let mut unescaped = String::new();
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\'') => unescaped.push('\''),
+            Some(next) => {
+                unescaped.push('\\');
+                unescaped.push(next);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    format!("{}{}{}", quotes, unescaped, quotes)
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum Literal {
+    Str(String),
+    Int(i64),
+    Float(u64),
+    Imaginary(u64),
+}
+
+fn literal_key(expr: &Expression) -> Option<Literal> {
+    match expr {
+        Expression::StringLiteral(s) => Some(Literal::Str(s.clone())),
+        Expression::IntegerLiteral(i) => Some(Literal::Int(*i)),
+        Expression::FloatLiteral(f) => Some(Literal::Float(f.to_bits())),
+        Expression::ImaginaryLiteral(f) => Some(Literal::Imaginary(f.to_bits())),
+        _ => None,
+    }
+}
+
+// The ways each literal value was written, handed out in source order
+#[derive(Default)]
+pub struct Spellings {
+    by_value: HashMap<Literal, (Vec<String>, usize)>,
+}
+
+impl Spellings {
+    // Every string and number literal of a source file as it was written, with
+    // `'text'` as `"text"` and `0XFF` as `0xFF`
+    pub fn new(input: &str, tokens: &[Token]) -> Self {
+        let mut spellings = Spellings::default();
+        for token in tokens {
+            let written = token.text(input);
+            let spelling = match token.kind {
+                TokenKind::String => prefer_double_quotes(written),
+                TokenKind::Number => match written.get(..2) {
+                    Some("0X" | "0O" | "0B") => written[..2].to_lowercase() + &written[2..],
+                    _ => written.to_string(),
+                },
+                _ => continue,
+            };
+            spellings.add(written, spelling);
+        }
+        spellings
+    }
+
+    fn add(&mut self, token: &str, spelling: String) {
+        if let Some(key) = parse_literal(token).as_ref().and_then(literal_key) {
+            self.by_value.entry(key).or_default().0.push(spelling);
+        }
+    }
+
+    // How the next occurrence of the literal `expr` was written
+    pub fn take(&mut self, expr: &Expression) -> Option<String> {
+        let (spellings, next) = self.by_value.get_mut(&literal_key(expr)?)?;
+        let spelling = spellings.get(*next).or(spellings.last())?.clone();
+        *next += 1;
+        Some(spelling)
+    }
+}
+
+pub struct Printer {
+    pub spellings: Spellings,
+}
+
+impl Printer {
+    pub fn expression(&mut self, expr: &Expression) -> Doc {
+        match expr {
+            Expression::Yield(value) => match value {
+                Some(value) => Doc::Concat(vec![text("yield "), self.expression(value)]),
+                None => text("yield"),
+            },
+            Expression::YieldFrom(value) => Doc::Concat(vec![text("yield from "), self.expression(value)]),
+            _ => self.operand(expr, LAMBDA),
+        }
+    }
+
+    // `if` and `while` conditions, where `:=` needs no parentheses
+    pub fn condition(&mut self, expr: &Expression) -> Doc {
+        match expr {
+            Expression::NamedExpr { target, value } => Doc::Concat(vec![text(format!("{} := ", target)), self.expression(value)]),
+            _ => self.expression(expr),
+        }
+    }
+
+    // `expr` where it must bind at least as tightly as `min`
+    fn operand(&mut self, expr: &Expression, min: u8) -> Doc {
+        let doc = self.unparenthesized(expr);
+        if precedence(expr) < min {
+            Doc::Concat(vec![text("("), doc, text(")")])
+        } else {
+            doc
+        }
+    }
+
+    fn unparenthesized(&mut self, expr: &Expression) -> Doc {
+        match expr {
+            Expression::StringLiteral(_) | Expression::IntegerLiteral(_) | Expression::FloatLiteral(_) | Expression::ImaginaryLiteral(_) => {
+                text(self.literal(expr))
+            }
+            Expression::BooleanLiteral(value) => text(if *value { "True" } else { "False" }),
+            Expression::NoneLiteral => text("None"),
+            Expression::Identifier(name) => text(name.clone()),
+            Expression::ListLiteral(items) => {
+                let items = items.iter().map(|item| self.expression(item)).collect();
+                bracketed("[", items, "]")
+            }
+            Expression::TupleLiteral(items) if items.len() == 1 => {
+                Doc::Concat(vec![text("("), self.expression(&items[0]), text(",)")])
+            }
+            Expression::TupleLiteral(items) => {
+                let items = items.iter().map(|item| self.expression(item)).collect();
+                bracketed("(", items, ")")
+            }
+            Expression::SetLiteral(items) => {
+                let items = items.iter().map(|item| self.expression(item)).collect();
+                bracketed("{", items, "}")
+            }
+            Expression::FrozensetLiteral(items) => {
+                let items = items.iter().map(|item| self.expression(item)).collect();
+                Doc::Concat(vec![text("frozenset("), bracketed("[", items, "]"), text(")")])
+            }
+            Expression::DictLiteral(entries) => {
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| Doc::Concat(vec![self.expression(key), text(": "), self.expression(value)]))
+                    .collect();
+                bracketed("{", entries, "}")
+            }
+            Expression::BinaryOperation { left, op, right } => {
+                let level = binary_precedence(*op);
+                // Comparisons don't chain through parentheses, and `**` groups from the left here
+                let (left_min, right_min) = match level {
+                    COMPARISON => (BIT_OR, BIT_OR),
+                    POWER => (POWER, AWAIT),
+                    _ => (level, level + 1),
+                };
+                Doc::Concat(vec![
+                    self.operand(left, left_min),
+                    text(format!(" {} ", operator(*op))),
+                    self.operand(right, right_min),
+                ])
+            }
+            Expression::Compare { left, ops, comparators } => {
+                let mut docs = vec![self.operand(left, BIT_OR)];
+                for (op, comparator) in ops.iter().zip(comparators) {
+                    docs.push(text(format!(" {} ", operator(*op))));
+                    docs.push(self.operand(comparator, BIT_OR));
+                }
+                Doc::Concat(docs)
+            }
+            Expression::IfExp { test, body, orelse } => Doc::Concat(vec![
+                self.operand(body, OR),
+                text(" if "),
+                self.operand(test, OR),
+                text(" else "),
+                self.operand(orelse, LAMBDA),
+            ]),
+            Expression::NamedExpr { target, value } => {
+                Doc::Concat(vec![text(format!("({} := ", target)), self.expression(value), text(")")])
+            }
+            Expression::UnaryOperation { op, operand } => match op {
+                UnaryOp::Not => Doc::Concat(vec![text("not "), self.operand(operand, NOT)]),
+                UnaryOp::Negate => Doc::Concat(vec![text("-"), self.operand(operand, BIT_NOT)]),
+                UnaryOp::BitNot => Doc::Concat(vec![text("~"), self.operand(operand, POWER)]),
+            },
+            Expression::Lambda { params, body } => {
+                let head = if params.is_empty() { "lambda: ".to_string() } else { format!("lambda {}: ", params.join(", ")) };
+                Doc::Concat(vec![text(head), self.expression(body)])
+            }
+            Expression::ListComprehension { element, comprehension } => {
+                let element = self.expression(element);
+                self.comprehension("[", element, comprehension, "]")
+            }
+            Expression::SetComprehension { element, comprehension } => {
+                let element = self.expression(element);
+                self.comprehension("{", element, comprehension, "}")
+            }
+            Expression::GeneratorExpression { element, comprehension } => {
+                let element = self.expression(element);
+                self.comprehension("(", element, comprehension, ")")
+            }
+            Expression::DictComprehension { key, value, comprehension } => {
+                let entry = Doc::Concat(vec![self.expression(key), text(": "), self.expression(value)]);
+                self.comprehension("{", entry, comprehension, "}")
+            }
+            Expression::Call { callee, args, keywords } => {
+                let callee = self.operand(callee, ATOM);
+                // A lone generator argument keeps the call's parentheses as its own
+                if let ([Expression::GeneratorExpression { element, comprehension }], []) = (args.as_slice(), keywords.as_slice()) {
+                    let element = self.expression(element);
+                    return Doc::Concat(vec![callee, self.comprehension("(", element, comprehension, ")")]);
+                }
+                let arguments = self.call_arguments(args, keywords);
+                Doc::Concat(vec![callee, bracketed("(", arguments, ")")])
+            }
+            Expression::AttributeAccess { object, attr } => {
+                Doc::Concat(vec![self.operand(object, ATOM), text(format!(".{}", attr))])
+            }
+            Expression::Index { object, index } => {
+                Doc::Concat(vec![self.operand(object, ATOM), text("["), self.expression(index), text("]")])
+            }
+            Expression::Await(value) => Doc::Concat(vec![text("await "), self.operand(value, ATOM)]),
+            Expression::Yield(_) | Expression::YieldFrom(_) => self.expression(expr),
+        }
+    }
+
+    fn comprehension(&mut self, open: &str, element: Doc, comprehension: &Comprehension, close: &str) -> Doc {
+        let keyword = if comprehension.is_async { "async for " } else { "for " };
+        let mut inner = vec![
+            Doc::SoftLine,
+            element,
+            Doc::Line,
+            text(format!("{}{} in ", keyword, comprehension.target.join(", "))),
+            self.operand(&comprehension.iter, OR),
+        ];
+        for condition in &comprehension.ifs {
+            inner.push(Doc::Line);
+            inner.push(text("if "));
+            inner.push(self.operand(condition, OR));
+        }
+        Doc::Group(vec![text(open), Doc::Indent(inner), Doc::SoftLine, text(close)])
+    }
+
+    fn keyword(&mut self, name: &str, value: &Expression) -> Doc {
+        Doc::Concat(vec![text(format!("{}=", name)), self.expression(value)])
+    }
+
+    // Decorator and class arguments: positional ones, then `name=value`
+    pub fn arguments(&mut self, args: &[Argument]) -> Vec<Doc> {
+        args.iter()
+            .map(|arg| match arg {
+                Argument::Positional(value) => self.expression(value),
+                Argument::Keyword(name, value) => self.keyword(name, value),
+            })
+            .collect()
+    }
+
+    pub fn call_arguments(&mut self, args: &[Expression], keywords: &[(String, Expression)]) -> Vec<Doc> {
+        let mut docs: Vec<Doc> = args.iter().map(|arg| self.expression(arg)).collect();
+        docs.extend(keywords.iter().map(|(name, value)| self.keyword(name, value)));
+        docs
+    }
+
+    // A literal as the source wrote it, or in canonical form
+    fn literal(&mut self, expr: &Expression) -> String {
+        if let Some(spelling) = self.spellings.take(expr) {
+            return spelling;
+        }
+        match expr {
+            Expression::StringLiteral(s) => quote(s),
+            Expression::IntegerLiteral(i) => i.to_string(),
+            Expression::FloatLiteral(f) => float(*f),
+            Expression::ImaginaryLiteral(f) => format!("{}j", float(*f)),
+            _ => String::new(),
+        }
+    }
+
+    pub fn pattern(&mut self, pattern: &Pattern) -> Doc {
+        match pattern {
+            Pattern::Literal(value) => match value {
+                // Negative literals are spelled in the source as `-` and a positive number
+                Expression::IntegerLiteral(i) if *i < 0 => text(format!("-{}", self.literal(&Expression::IntegerLiteral(-i)))),
+                Expression::FloatLiteral(f) if f.is_sign_negative() => text(format!("-{}", self.literal(&Expression::FloatLiteral(-f)))),
+                Expression::ImaginaryLiteral(f) if f.is_sign_negative() => {
+                    text(format!("-{}", self.literal(&Expression::ImaginaryLiteral(-f))))
+                }
+                _ => self.expression(value),
+            },
+            Pattern::Value(value) => self.expression(value),
+            Pattern::Capture(name) => text(name.clone()),
+            Pattern::Wildcard => text("_"),
+            Pattern::Sequence(items) => {
+                let items = items.iter().map(|item| self.pattern(item)).collect();
+                bracketed("[", items, "]")
+            }
+            Pattern::Star(name) => text(format!("*{}", name.as_deref().unwrap_or("_"))),
+            Pattern::Mapping { entries, rest } => {
+                let mut items: Vec<Doc> = entries
+                    .iter()
+                    .map(|(key, value)| Doc::Concat(vec![self.expression(key), text(": "), self.pattern(value)]))
+                    .collect();
+                items.extend(rest.iter().map(|rest| text(format!("**{}", rest))));
+                bracketed("{", items, "}")
+            }
+            Pattern::Class { name, positional, keywords } => {
+                let mut items: Vec<Doc> = positional.iter().map(|item| self.pattern(item)).collect();
+                for (attr, value) in keywords {
+                    items.push(Doc::Concat(vec![text(format!("{}=", attr)), self.pattern(value)]));
+                }
+                Doc::Concat(vec![text(name.clone()), bracketed("(", items, ")")])
+            }
+            Pattern::Or(alternatives) => {
+                let mut docs = Vec::new();
+                for (index, alternative) in alternatives.iter().enumerate() {
+                    if index > 0 {
+                        docs.push(text(" | "));
+                    }
+                    docs.push(self.closed_pattern(alternative));
+                }
+                Doc::Concat(docs)
+            }
+            Pattern::As(inner, name) => {
+                let inner = match **inner {
+                    Pattern::As(..) => self.closed_pattern(inner),
+                    _ => self.pattern(inner),
+                };
+                Doc::Concat(vec![inner, text(format!(" as {}", name))])
+            }
+        }
+    }
+
+    // A pattern where `|` and `as` need parentheses
+    fn closed_pattern(&mut self, pattern: &Pattern) -> Doc {
+        match pattern {
+            Pattern::Or(_) | Pattern::As(..) => Doc::Concat(vec![text("("), self.pattern(pattern), text(")")]),
+            _ => self.pattern(pattern),
+        }
+    }
+}
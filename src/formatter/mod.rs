@@ -0,0 +1,586 @@
+// `eppx fmt`: prints a program back from its AST as canonical source. The
+// parser drops comments, so they are put back beside the statements they were
+// written next to, found through where the parser met each statement
+
+mod doc;
+mod expression;
+
+use crate::ast::{AssignmentOperator, AstNode, Decorator, Expression, Statement};
+use crate::parser::{parse_eppx_located, parse_eppx_string, statement_spans, tokenize_lenient, StatementSpan, Token, TokenKind};
+
+use doc::{bracketed, render, text, Doc};
+use expression::{Printer, Spellings};
+
+// Lines are wrapped to fit in this many columns where brackets allow it
+const LINE_WIDTH: usize = 88;
+
+const INDENT: &str = "    ";
+
+// The canonical form of a source file; fails if it doesn't parse
+pub fn format_source(input: &str) -> Result<String, String> {
+    let program = parse_eppx_located(input).map_err(|e| format!("line {}, column {}: {}", e.line, e.column, e.message))?;
+    let tokens = tokenize_lenient(input);
+    let mut formatter = Formatter {
+        source: Source::new(input, &tokens),
+        printer: Printer { spellings: Spellings::new(input, &tokens) },
+        spans: statement_spans(input)?,
+        next_span: 0,
+        next_comment: 0,
+        out: String::new(),
+        blank_lines: 0,
+    };
+    formatter.block(&program, 0)?;
+    if formatter.next_span != formatter.spans.len() || formatter.next_comment != formatter.source.comments.len() {
+        return Err(LOST_TRACK.to_string());
+    }
+    // Never hand back a program that means something else
+    if parse_eppx_string(&formatter.out).ok().as_ref() != Some(&program) {
+        return Err("formatting would change what the program means, so it was left as it is".to_string());
+    }
+    if input.contains("\r\n") {
+        return Ok(formatter.out.replace('\n', "\r\n"));
+    }
+    Ok(formatter.out)
+}
+
+const LOST_TRACK: &str = "could not match the statements up with the source, so it was left as it is";
+
+fn is_definition(statement: &Statement) -> bool {
+    matches!(statement, Statement::FunctionDef { .. } | Statement::ClassDef { .. })
+}
+
+fn assignment_operator(operator: &AssignmentOperator) -> &'static str {
+    match operator {
+        AssignmentOperator::Assign => "=",
+        AssignmentOperator::AddAssign => "+=",
+        AssignmentOperator::SubAssign => "-=",
+        AssignmentOperator::MulAssign => "*=",
+        AssignmentOperator::DivAssign => "/=",
+        AssignmentOperator::ModAssign => "%=",
+        AssignmentOperator::PowAssign => "**=",
+        AssignmentOperator::FloorDivAssign => "//=",
+        AssignmentOperator::BitAndAssign => "&=",
+        AssignmentOperator::BitOrAssign => "|=",
+        AssignmentOperator::BitXorAssign => "^=",
+        AssignmentOperator::LShiftAssign => "<<=",
+        AssignmentOperator::RShiftAssign => ">>=",
+    }
+}
+
+// `#comment` as `# comment`; shebangs and rules of `#` are left alone
+fn comment_text(comment: &str) -> String {
+    match comment[1..].chars().next() {
+        Some(c) if !c.is_whitespace() && !matches!(c, '!' | ':' | '#') => format!("# {}", &comment[1..]),
+        _ => comment.to_string(),
+    }
+}
+
+struct Comment {
+    line: usize,
+    column: usize,
+    text: String,
+    // Nothing but indentation before it on its line
+    own_line: bool,
+}
+
+// What the AST leaves out of a source file besides how literals were spelled:
+// its comments and blank lines
+struct Source<'a> {
+    lines: Vec<&'a str>,
+    comments: Vec<Comment>,
+}
+
+impl<'a> Source<'a> {
+    fn new(input: &'a str, tokens: &[Token]) -> Self {
+        let lines: Vec<&str> = input.lines().collect();
+        let comments = tokens
+            .iter()
+            .filter(|token| token.kind == TokenKind::Comment)
+            .map(|token| Comment {
+                line: token.line,
+                column: token.column,
+                text: token.text(input).trim_end().to_string(),
+                own_line: lines[token.line - 1].chars().take(token.column - 1).all(char::is_whitespace),
+            })
+            .collect();
+        Source { lines, comments }
+    }
+
+    // How many blank lines come right before `line`
+    fn blank_lines_before(&self, line: usize) -> usize {
+        self.lines[..line.saturating_sub(1).min(self.lines.len())].iter().rev().take_while(|text| text.trim().is_empty()).count()
+    }
+}
+
+struct Formatter<'a> {
+    source: Source<'a>,
+    printer: Printer,
+    spans: Vec<StatementSpan>,
+    next_span: usize,
+    next_comment: usize,
+    out: String,
+    // Blank lines to write before the next line
+    blank_lines: usize,
+}
+
+impl Formatter<'_> {
+    fn write(&mut self, depth: usize, line: &str) {
+        if !self.out.is_empty() {
+            self.out.push_str(&"\n".repeat(self.blank_lines));
+        }
+        self.blank_lines = 0;
+        self.out.push_str(&INDENT.repeat(depth));
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+
+    fn render(&self, doc: &Doc, depth: usize) -> String {
+        render(doc, depth * INDENT.len(), LINE_WIDTH)
+    }
+
+    // The line the next statement or clause starts on
+    fn next_line(&self) -> usize {
+        self.spans.get(self.next_span).map_or(usize::MAX, |span| span.start.0)
+    }
+
+    // Whether the next statement is the first thing on its line
+    fn starts_line(&self) -> bool {
+        self.spans.get(self.next_span).is_none_or(|span| {
+            let line = self.source.lines.get(span.start.0 - 1).copied().unwrap_or_default();
+            line.chars().take(span.start.1 - 1).all(char::is_whitespace)
+        })
+    }
+
+    fn claim(&mut self) -> Result<StatementSpan, String> {
+        let span = self.spans.get(self.next_span).copied().ok_or_else(|| LOST_TRACK.to_string())?;
+        self.next_span += 1;
+        Ok(span)
+    }
+
+    // Writes the comments on lines of their own before `line` that `keep` accepts. The
+    // first is put `gap` blank lines down if one is given; the gap is handed back if unused
+    fn comments_before(&mut self, line: usize, depth: usize, most: usize, mut gap: Option<usize>, keep: impl Fn(usize) -> bool) -> Option<usize> {
+        while let Some(comment) = self.source.comments.get(self.next_comment) {
+            if comment.line >= line || !keep(comment.column) {
+                break;
+            }
+            let text = comment_text(&comment.text);
+            self.blank_lines = gap.take().unwrap_or_else(|| self.source.blank_lines_before(comment.line).min(most));
+            self.write(depth, &text);
+            self.next_comment += 1;
+        }
+        gap
+    }
+
+    fn block(&mut self, body: &[AstNode], depth: usize) -> Result<(), String> {
+        let column = self.spans.get(self.next_span).map_or(1, |span| span.start.1);
+        // Up to two blank lines between top-level statements and one in blocks, always
+        // as many around definitions, and none at the start of a block
+        let most = if depth == 0 { 2 } else { 1 };
+        let mut previous: Option<&Statement> = None;
+        for AstNode::Statement(statement) in body {
+            let gap = match previous {
+                None => Some(0),
+                Some(previous) if is_definition(previous) || is_definition(statement) => Some(most),
+                Some(_) => None,
+            };
+            let line = self.next_line();
+            let gap = self.comments_before(line, depth, most, gap, |_| true);
+            self.blank_lines = match gap {
+                Some(gap) => gap,
+                // After a `;` on the same line
+                None if !self.starts_line() => 0,
+                None => self.source.blank_lines_before(line).min(most),
+            };
+            self.statement(statement, depth)?;
+            previous = Some(statement);
+        }
+        // Comments after the last statement stay in the block if they are indented like it
+        let line = self.next_line();
+        self.comments_before(line, depth, most, None, |comment_column| comment_column >= column);
+        Ok(())
+    }
+
+    // A simple statement, with the comment at the end of its line
+    fn simple(&mut self, depth: usize, doc: Doc) -> Result<(), String> {
+        let span = self.claim()?;
+        let next_line = self.next_line();
+        let end = span.end.0;
+        let mut trailing = None;
+        let mut commented_inside = false;
+        while let Some(comment) = self.source.comments.get(self.next_comment) {
+            // A statement sharing the line after a `;` takes the comment
+            if comment.line > end || (comment.line == end && next_line == end) {
+                break;
+            }
+            if comment.line == end {
+                trailing = Some(comment_text(&comment.text));
+            } else {
+                commented_inside = true;
+            }
+            self.next_comment += 1;
+        }
+        // Comments between the lines of a statement can't be placed in a new layout of it
+        let mut line = if commented_inside { self.verbatim(span, depth) } else { self.render(&doc, depth) };
+        if let Some(comment) = trailing {
+            line.push_str("  ");
+            line.push_str(&comment);
+        }
+        self.write(depth, &line);
+        Ok(())
+    }
+
+    // A statement as it was written, its continuation lines moved to the new indentation
+    fn verbatim(&self, span: StatementSpan, depth: usize) -> String {
+        let lines = &self.source.lines[span.start.0 - 1..span.end.0];
+        let byte = |line: &str, column: usize| line.char_indices().nth(column - 1).map_or(line.len(), |(offset, _)| offset);
+        let first = lines[0];
+        let indent = first.len() - first.trim_start().len();
+        let multiline_string = lines.iter().any(|line| line.contains("\"\"\"") || line.contains("'''"));
+        let mut text = String::new();
+        for (index, line) in lines.iter().enumerate() {
+            let line = if index + 1 == lines.len() { &line[..byte(line, span.end.1)] } else { line };
+            if index == 0 {
+                text.push_str(&line[byte(line, span.start.1)..]);
+                continue;
+            }
+            text.push('\n');
+            if multiline_string {
+                text.push_str(line);
+            } else if !line.trim().is_empty() {
+                let strip = (line.len() - line.trim_start().len()).min(indent);
+                text.push_str(&INDENT.repeat(depth));
+                text.push_str(line[strip..].trim_end());
+            }
+        }
+        text.trim_end().to_string()
+    }
+
+    // The header of a compound statement or clause, with any decorator lines before
+    // it. The last comment on its lines stays at its end; others move above it
+    fn header(&mut self, depth: usize, lines: Vec<Doc>) -> Result<(), String> {
+        let _ = self.claim()?;
+        let next_line = self.next_line();
+        let mut trailing: Option<String> = None;
+        while let Some(comment) = self.source.comments.get(self.next_comment) {
+            if comment.line >= next_line || comment.own_line {
+                break;
+            }
+            if let Some(earlier) = trailing.replace(comment_text(&comment.text)) {
+                self.write(depth, &earlier);
+            }
+            self.next_comment += 1;
+        }
+        let count = lines.len();
+        for (index, doc) in lines.iter().enumerate() {
+            let mut line = self.render(doc, depth);
+            if index + 1 == count {
+                if let Some(comment) = &trailing {
+                    line.push_str("  ");
+                    line.push_str(comment);
+                }
+            }
+            self.write(depth, &line);
+        }
+        Ok(())
+    }
+
+    // `elif`, `else:`, `except`, `case` and the like, after the comments before them
+    fn clause(&mut self, depth: usize, header: Doc) -> Result<(), String> {
+        let line = self.next_line();
+        self.comments_before(line, depth, 1, Some(0), |_| true);
+        self.blank_lines = 0;
+        self.header(depth, vec![header])
+    }
+
+    fn statement(&mut self, statement: &Statement, depth: usize) -> Result<(), String> {
+        let p = &mut self.printer;
+        match statement {
+            Statement::Assignment { target, operator, value } => {
+                let doc = Doc::Concat(vec![p.expression(target), text(format!(" {} ", assignment_operator(operator))), p.expression(value)]);
+                self.simple(depth, doc)
+            }
+            Statement::ChainedAssignment { targets, value } => {
+                let mut docs = Vec::new();
+                for target in targets {
+                    docs.push(p.expression(target));
+                    docs.push(text(" = "));
+                }
+                docs.push(p.expression(value));
+                self.simple(depth, Doc::Concat(docs))
+            }
+            Statement::AnnotatedAssignment { target, annotation, value } => {
+                let mut docs = vec![p.expression(target), text(": "), p.expression(annotation)];
+                if let Some(value) = value {
+                    docs.push(text(" = "));
+                    docs.push(p.expression(value));
+                }
+                self.simple(depth, Doc::Concat(docs))
+            }
+            Statement::Assert { test, msg } => {
+                let mut docs = vec![text("assert "), p.expression(test)];
+                if let Some(msg) = msg {
+                    docs.push(text(", "));
+                    docs.push(p.expression(msg));
+                }
+                self.simple(depth, Doc::Concat(docs))
+            }
+            Statement::Delete(targets) => {
+                let mut docs = vec![text("del ")];
+                for (index, target) in targets.iter().enumerate() {
+                    if index > 0 {
+                        docs.push(text(", "));
+                    }
+                    docs.push(p.expression(target));
+                }
+                self.simple(depth, Doc::Concat(docs))
+            }
+            Statement::Print(value) => {
+                // The parser keeps `print(a, b)` as a tuple and `print()` as an empty string
+                let args = match &**value {
+                    Expression::TupleLiteral(items) => items.clone(),
+                    Expression::StringLiteral(s) if s.is_empty() => Vec::new(),
+                    value => vec![value.clone()],
+                };
+                let call = Expression::Call { callee: Box::new(Expression::Identifier("print".to_string())), args, keywords: Vec::new() };
+                let doc = p.expression(&call);
+                self.simple(depth, doc)
+            }
+            Statement::Return(value) => {
+                let doc = match value {
+                    Some(value) => Doc::Concat(vec![text("return "), p.expression(value)]),
+                    None => text("return"),
+                };
+                self.simple(depth, doc)
+            }
+            Statement::Yield(value) => {
+                let doc = p.expression(&Expression::Yield(value.clone()));
+                self.simple(depth, doc)
+            }
+            Statement::Raise(value) => {
+                let doc = match value {
+                    Some(value) => Doc::Concat(vec![text("raise "), p.expression(value)]),
+                    None => text("raise"),
+                };
+                self.simple(depth, doc)
+            }
+            Statement::ExpressionStatement(value) => {
+                let doc = p.expression(value);
+                self.simple(depth, doc)
+            }
+            Statement::Break => self.simple(depth, text("break")),
+            Statement::Continue => self.simple(depth, text("continue")),
+            Statement::Pass => self.simple(depth, text("pass")),
+            Statement::Import { module, alias } => {
+                let line = match alias {
+                    Some(alias) => format!("import {} as {}", module, alias),
+                    None => format!("import {}", module),
+                };
+                self.simple(depth, text(line))
+            }
+            Statement::ImportFrom { module, names } => {
+                let mut inner = vec![Doc::SoftLine];
+                for (index, (name, alias)) in names.iter().enumerate() {
+                    if index > 0 {
+                        inner.push(text(","));
+                        inner.push(Doc::Line);
+                    }
+                    inner.push(text(match alias {
+                        Some(alias) => format!("{} as {}", name, alias),
+                        None => name.clone(),
+                    }));
+                }
+                inner.push(Doc::IfBroken(","));
+                // Parentheses only once the names don't fit on the line
+                let names = Doc::Group(vec![Doc::IfBroken("("), Doc::Indent(inner), Doc::SoftLine, Doc::IfBroken(")")]);
+                self.simple(depth, Doc::Concat(vec![text(format!("from {} import ", module)), names]))
+            }
+            Statement::If { condition, then_body, elifs, else_body } => {
+                let doc = Doc::Concat(vec![text("if "), p.condition(condition), text(":")]);
+                self.header(depth, vec![doc])?;
+                self.block(then_body, depth + 1)?;
+                for (condition, body) in elifs {
+                    let doc = Doc::Concat(vec![text("elif "), self.printer.condition(condition), text(":")]);
+                    self.clause(depth, doc)?;
+                    self.block(body, depth + 1)?;
+                }
+                self.else_clause(else_body.as_deref(), "else:", depth)
+            }
+            Statement::While { condition, body, else_body } => {
+                let doc = Doc::Concat(vec![text("while "), p.condition(condition), text(":")]);
+                self.header(depth, vec![doc])?;
+                self.block(body, depth + 1)?;
+                self.else_clause(else_body.as_deref(), "else:", depth)
+            }
+            Statement::For { vars, iterable, body, else_body, is_async } => {
+                let keyword = if *is_async { "async for" } else { "for" };
+                let doc = Doc::Concat(vec![text(format!("{} {} in ", keyword, vars.join(", "))), p.expression(iterable), text(":")]);
+                self.header(depth, vec![doc])?;
+                self.block(body, depth + 1)?;
+                self.else_clause(else_body.as_deref(), "else:", depth)
+            }
+            Statement::FunctionDef { name, params, body, decorators, is_async } => {
+                let mut lines = self.decorators(decorators);
+                let keyword = if *is_async { "async def" } else { "def" };
+                let params = params.iter().map(|param| text(param.clone())).collect();
+                lines.push(Doc::Concat(vec![text(format!("{} {}", keyword, name)), bracketed("(", params, ")"), text(":")]));
+                self.header(depth, lines)?;
+                self.block(body, depth + 1)
+            }
+            Statement::ClassDef { name, bases, keywords, decorators, body } => {
+                let mut lines = self.decorators(decorators);
+                let mut head = vec![text(format!("class {}", name))];
+                if !bases.is_empty() || !keywords.is_empty() {
+                    head.push(bracketed("(", self.printer.call_arguments(bases, keywords), ")"));
+                }
+                head.push(text(":"));
+                lines.push(Doc::Concat(head));
+                self.header(depth, lines)?;
+                self.block(body, depth + 1)
+            }
+            Statement::TryExcept { try_body, excepts, else_body, finally_body } => {
+                self.header(depth, vec![text("try:")])?;
+                self.block(try_body, depth + 1)?;
+                for handler in excepts {
+                    let mut docs = vec![text("except")];
+                    if let Some(exception_type) = &handler.exception_type {
+                        docs.push(text(" "));
+                        docs.push(self.printer.expression(exception_type));
+                    }
+                    if let Some(name) = &handler.name {
+                        docs.push(text(format!(" as {}", name)));
+                    }
+                    docs.push(text(":"));
+                    self.clause(depth, Doc::Concat(docs))?;
+                    self.block(&handler.body, depth + 1)?;
+                }
+                self.else_clause(else_body.as_deref(), "else:", depth)?;
+                self.else_clause(finally_body.as_deref(), "finally:", depth)
+            }
+            Statement::With { items, body, is_async } => {
+                let mut docs = vec![text(if *is_async { "async with " } else { "with " })];
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        docs.push(text(", "));
+                    }
+                    docs.push(p.expression(&item.context_expr));
+                    if let Some(name) = &item.optional_vars {
+                        docs.push(text(format!(" as {}", name)));
+                    }
+                }
+                docs.push(text(":"));
+                self.header(depth, vec![Doc::Concat(docs)])?;
+                self.block(body, depth + 1)
+            }
+            Statement::Match { subject, cases } => {
+                let doc = Doc::Concat(vec![text("match "), p.expression(subject), text(":")]);
+                self.header(depth, vec![doc])?;
+                for case in cases {
+                    let mut docs = vec![text("case "), self.printer.pattern(&case.pattern)];
+                    if let Some(guard) = &case.guard {
+                        docs.push(text(" if "));
+                        docs.push(self.printer.expression(guard));
+                    }
+                    docs.push(text(":"));
+                    self.clause(depth + 1, Doc::Concat(docs))?;
+                    self.block(&case.body, depth + 2)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // A block introduced by a clause with no condition, such as `else:` or `finally:`
+    fn else_clause(&mut self, body: Option<&[AstNode]>, keyword: &'static str, depth: usize) -> Result<(), String> {
+        let Some(body) = body else {
+            return Ok(());
+        };
+        self.clause(depth, text(keyword))?;
+        self.block(body, depth + 1)
+    }
+
+    fn decorators(&mut self, decorators: &[Decorator]) -> Vec<Doc> {
+        decorators
+            .iter()
+            .map(|decorator| match decorator {
+                Decorator::Simple(name) => text(format!("@{}", name)),
+                Decorator::WithArgs(name, args) => Doc::Concat(vec![text(format!("@{}", name)), bracketed("(", self.printer.arguments(args), ")")]),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comments_stay_beside_their_statements() {
+        let input = "#!/usr/bin/env eppx\n#header\nimport math\n\n\n# about f\ndef f(a,b):\n  x = a+b   #sum\n\n  # before return\n  return x\n  # end of f\nclass C:\n    pass\n";
+        let expected = "#!/usr/bin/env eppx\n# header\nimport math\n\n\n# about f\ndef f(a, b):\n    x = a + b  # sum\n\n    # before return\n    return x\n    # end of f\n\n\nclass C:\n    pass\n";
+        assert_eq!(format_source(input).unwrap(), expected);
+        // Rules of `#` and `#:` comments are written as they were
+        let input = "##########\nx = 1  #: note\n";
+        assert_eq!(format_source(input).unwrap(), input);
+    }
+
+    #[test]
+    fn test_formatting_is_idempotent() {
+        let samples = [
+            "def f(a,b):\n  return (a+b)*c  # trailing\n",
+            "values = [1,2,3,]\nif x:\n  print(x)\nelse:\n    pass\n",
+            "total = some_function_with_a_long_name(first_argument, second_argument, third_argument, fourth)\n",
+            "x = 1\r\ny = {'a':2}\r\n",
+        ];
+        for sample in samples {
+            let once = format_source(sample).unwrap();
+            assert_eq!(format_source(&once).unwrap(), once, "{:?}", sample);
+        }
+        // Every example that formats is already in its canonical form after one pass. The
+        // parser recurses deeply on the longer ones, so this runs with a main thread's stack
+        let examples = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        let check = move || {
+            for entry in std::fs::read_dir(examples).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().is_none_or(|extension| extension != "eppx") {
+                    continue;
+                }
+                let Ok(once) = format_source(&std::fs::read_to_string(&path).unwrap()) else { continue };
+                assert_eq!(format_source(&once).unwrap(), once, "{}", path.display());
+            }
+        };
+        std::thread::Builder::new().stack_size(8 << 20).spawn(check).unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_parentheses_follow_precedence() {
+        let cases = [
+            ("x = (a+b)*c\n", "x = (a + b) * c\n"),
+            ("x = a+(b*c)\n", "x = a + b * c\n"),
+            ("x = (a-b)-c\n", "x = a - b - c\n"),
+            ("x = a-(b-c)\n", "x = a - (b - c)\n"),
+            // `**` groups from the left in this grammar
+            ("x = (a**b)**c\n", "x = a ** b ** c\n"),
+            ("x = a**(b**c)\n", "x = a ** (b ** c)\n"),
+            ("x = -(a**2)\n", "x = -a ** 2\n"),
+            ("x = (-a)**2\n", "x = (-a) ** 2\n"),
+            ("x = not (a and b)\n", "x = not (a and b)\n"),
+            ("x = (a or b) and c\n", "x = (a or b) and c\n"),
+            ("x = (a and b) or c\n", "x = a and b or c\n"),
+            ("x = (a < b) == c\n", "x = (a < b) == c\n"),
+            ("x = (a | b) & c\n", "x = (a | b) & c\n"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(format_source(input).unwrap(), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_source_that_does_not_parse_is_an_error() {
+        let error = format_source("def f(:\n    pass\n").unwrap_err();
+        assert!(error.starts_with("line 1, column"), "{}", error);
+        // Two statements on one line without a `;` are not rewritten as two lines
+        let error = format_source("a = 1\ne = f'{a} x'\n").unwrap_err();
+        assert!(error.starts_with("line 2, column 6"), "{}", error);
+    }
+}
@@ -0,0 +1,565 @@
+// Walks the AST together with the statements' spans, in the order the formatter
+// does, noting the names each scope binds and reads. Reads are resolved once the
+// whole file has been walked, since a function may use a name defined after it
+
+use std::collections::HashSet;
+
+use crate::ast::{Argument, AssignmentOperator, AstNode, BinOp, Comprehension, Decorator, Expression, Pattern, Statement};
+use crate::codegen::BUILTIN_FUNCTIONS;
+use crate::parser::StatementSpan;
+
+use super::{Finding, Rule, Word};
+
+// C++ keywords and alternative tokens that are valid E++ names. The transpiler
+// writes names into the C++ as they are, so none of these can be used
+const CPP_KEYWORDS: &[&str] = &[
+    "alignas", "alignof", "and_eq", "asm", "auto", "bitand", "bitor", "bool", "case", "catch", "char", "char8_t",
+    "char16_t", "char32_t", "co_await", "co_return", "co_yield", "compl", "concept", "const", "const_cast", "consteval",
+    "constexpr", "constinit", "decltype", "default", "delete", "do", "double", "dynamic_cast", "enum", "explicit",
+    "export", "extern", "false", "float", "friend", "goto", "inline", "int", "long", "mutable", "namespace", "new",
+    "noexcept", "not_eq", "nullptr", "operator", "or_eq", "private", "protected", "public", "register",
+    "reinterpret_cast", "requires", "short", "signed", "sizeof", "static", "static_assert", "static_cast", "struct",
+    "switch", "template", "this", "thread_local", "throw", "true", "typedef", "typeid", "typename", "union",
+    "unsigned", "using", "virtual", "void", "volatile", "wchar_t", "xor", "xor_eq",
+];
+
+// Builtin names besides the functions: the exception classes and `__name__`
+const BUILTIN_NAMES: &[&str] = &[
+    "ArithmeticError", "AssertionError", "AttributeError", "BaseException", "CancelledError", "Exception",
+    "FileExistsError", "FileNotFoundError", "FrozenInstanceError", "GeneratorExit", "ImportError", "IndexError",
+    "IsADirectoryError", "KeyError", "KeyboardInterrupt", "LookupError", "NameError", "NotImplementedError", "OSError",
+    "OverflowError", "PermissionError", "RecursionError", "RuntimeError", "StopAsyncIteration", "StopIteration",
+    "SystemExit", "TypeError", "UnicodeError", "ValueError", "ZeroDivisionError", "__name__",
+];
+
+fn is_builtin(name: &str) -> bool {
+    BUILTIN_FUNCTIONS.contains(&name) || BUILTIN_NAMES.contains(&name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScopeKind {
+    Module,
+    Function,
+    Class,
+    Lambda,
+    Comprehension,
+}
+
+// How a name was bound, which decides whether it may go unused
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Binding {
+    // `x = ...` and `(x := ...)`
+    Assigned,
+    // `except ... as e`
+    Caught,
+    Imported,
+    // `def` and `class`
+    Defined,
+    // Parameters, loop and unpacked variables, `with ... as` and match captures,
+    // which are often bound only to be ignored
+    Incidental,
+}
+
+struct Bound {
+    name: String,
+    kind: Binding,
+    at: (usize, usize),
+}
+
+struct Scope {
+    kind: ScopeKind,
+    parent: Option<usize>,
+    bound: Vec<Bound>,
+    reads: Vec<(String, (usize, usize))>,
+}
+
+pub struct Checker<'a> {
+    words: &'a [Word],
+    spans: Vec<StatementSpan>,
+    next_span: usize,
+    // The statement, or the header of the compound statement or clause, being walked;
+    // its names are looked for here
+    region: ((usize, usize), (usize, usize)),
+    scopes: Vec<Scope>,
+    scope: usize,
+    findings: Vec<Finding>,
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(words: &'a [Word], spans: Vec<StatementSpan>) -> Self {
+        let module = Scope { kind: ScopeKind::Module, parent: None, bound: Vec::new(), reads: Vec::new() };
+        Self { words, spans, next_span: 0, region: ((1, 1), (1, 1)), scopes: vec![module], scope: 0, findings: Vec::new() }
+    }
+
+    pub fn check(&mut self, program: &[AstNode]) {
+        self.block(program);
+    }
+
+    // The findings, with those that need every name resolved
+    pub fn finish(mut self) -> Vec<Finding> {
+        let mut used = HashSet::new();
+        for scope in 0..self.scopes.len() {
+            for (name, at) in std::mem::take(&mut self.scopes[scope].reads) {
+                match self.resolve(scope, &name) {
+                    Some(owner) => {
+                        used.insert((owner, name));
+                    }
+                    None if !is_builtin(&name) => self.report(Rule::UndefinedName, at, format!("`{}` is not defined", name)),
+                    None => {}
+                }
+            }
+        }
+        let mut unused = Vec::new();
+        for (index, scope) in self.scopes.iter().enumerate() {
+            let mut reported = HashSet::new();
+            for bound in &scope.bound {
+                if bound.name.starts_with('_') || used.contains(&(index, bound.name.clone())) || !reported.insert(&bound.name) {
+                    continue;
+                }
+                match bound.kind {
+                    Binding::Imported => {
+                        unused.push((Rule::UnusedImport, bound.at, format!("`{}` is imported but never used", bound.name)));
+                    }
+                    Binding::Assigned | Binding::Caught if scope.kind == ScopeKind::Function => {
+                        let message = format!("local variable `{}` is assigned but never used", bound.name);
+                        unused.push((Rule::UnusedVariable, bound.at, message));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for (rule, at, message) in unused {
+            self.report(rule, at, message);
+        }
+        self.findings
+    }
+
+    // The scope a name read in `scope` refers to
+    fn resolve(&self, scope: usize, name: &str) -> Option<usize> {
+        let mut current = Some(scope);
+        while let Some(index) = current {
+            let candidate = &self.scopes[index];
+            // A class's names aren't visible in the functions inside it
+            if (index == scope || candidate.kind != ScopeKind::Class) && candidate.bound.iter().any(|bound| bound.name == name) {
+                return Some(index);
+            }
+            current = candidate.parent;
+        }
+        None
+    }
+
+    fn report(&mut self, rule: Rule, (line, column): (usize, usize), message: String) {
+        self.findings.push(Finding { rule, line, column, message });
+    }
+
+    // Moves on to the next statement or clause
+    fn claim(&mut self) {
+        let Some(span) = self.spans.get(self.next_span).copied() else {
+            return;
+        };
+        self.next_span += 1;
+        // A compound statement's names are in its header, before its block
+        let end = match self.spans.get(self.next_span) {
+            Some(next) if span.compound && next.start < span.end => next.start,
+            _ => span.end,
+        };
+        self.region = (span.start, end);
+    }
+
+    // Where `name` is first written in the current statement, or where the statement starts
+    fn locate(&self, name: &str, after_dot: bool) -> (usize, usize) {
+        let (start, end) = self.region;
+        let words = self.words;
+        let first = words.partition_point(|word| (word.line, word.column) < start);
+        words[first..]
+            .iter()
+            .take_while(|word| (word.line, word.column) < end)
+            .find(|word| word.text == name && word.after_dot == after_dot)
+            .map_or(start, |word| (word.line, word.column))
+    }
+
+    fn enter(&mut self, kind: ScopeKind) -> usize {
+        let parent = self.scope;
+        self.scopes.push(Scope { kind, parent: Some(parent), bound: Vec::new(), reads: Vec::new() });
+        self.scope = self.scopes.len() - 1;
+        parent
+    }
+
+    fn read(&mut self, name: &str) {
+        let at = self.locate(name, false);
+        self.scopes[self.scope].reads.push((name.to_string(), at));
+    }
+
+    fn bind(&mut self, name: &str, kind: Binding) {
+        let at = self.locate(name, false);
+        self.bind_in(self.scope, name, kind, at);
+    }
+
+    fn bind_in(&mut self, scope: usize, name: &str, kind: Binding, at: (usize, usize)) {
+        // An imported name is declared in C++ where it is defined, not here
+        if CPP_KEYWORDS.contains(&name) && kind != Binding::Imported {
+            self.report(Rule::CppKeyword, at, format!("`{}` is a C++ keyword, so the generated C++ won't compile", name));
+        }
+        // Methods and class attributes named like builtins don't hide them
+        if BUILTIN_FUNCTIONS.contains(&name) && self.scopes[scope].kind != ScopeKind::Class {
+            self.report(Rule::ShadowedBuiltin, at, format!("`{}` shadows the builtin of the same name", name));
+        }
+        self.scopes[scope].bound.push(Bound { name: name.to_string(), kind, at });
+    }
+
+    fn block(&mut self, body: &[AstNode]) {
+        let mut exit = None;
+        let mut reported = false;
+        for AstNode::Statement(statement) in body {
+            if let (Some(keyword), false) = (exit, reported) {
+                let at = self.spans.get(self.next_span).map_or(self.region.0, |span| span.start);
+                self.report(Rule::UnreachableCode, at, format!("this code never runs: it comes after `{}`", keyword));
+                reported = true;
+            }
+            self.statement(statement);
+            exit = match statement {
+                Statement::Return(_) => Some("return"),
+                Statement::Raise(_) => Some("raise"),
+                Statement::Break => Some("break"),
+                Statement::Continue => Some("continue"),
+                _ => exit,
+            };
+        }
+    }
+
+    // The block of a clause with no condition, such as `else:` or `finally:`
+    fn else_clause(&mut self, body: Option<&[AstNode]>) {
+        if let Some(body) = body {
+            self.claim();
+            self.block(body);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        self.claim();
+        match statement {
+            Statement::Assignment { target, operator, value } => {
+                self.expression(value);
+                if !matches!(operator, AssignmentOperator::Assign) {
+                    self.expression(target);
+                }
+                self.target(target, Binding::Assigned);
+            }
+            Statement::ChainedAssignment { targets, value } => {
+                self.expression(value);
+                for target in targets {
+                    self.target(target, Binding::Assigned);
+                }
+            }
+            Statement::AnnotatedAssignment { target, annotation, value } => {
+                self.expression(annotation);
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+                // `x: int` alone only declares it
+                self.target(target, if value.is_some() { Binding::Assigned } else { Binding::Incidental });
+            }
+            Statement::Assert { test, msg } => {
+                self.expression(test);
+                if let Some(msg) = msg {
+                    self.expression(msg);
+                }
+            }
+            Statement::Delete(targets) => {
+                for target in targets {
+                    self.expression(target);
+                }
+            }
+            Statement::Print(value) | Statement::ExpressionStatement(value) => self.expression(value),
+            Statement::Return(value) | Statement::Yield(value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Statement::Raise(value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Statement::Break | Statement::Continue | Statement::Pass => {}
+            Statement::Import { module, alias } => {
+                // `import a.b` binds `a`
+                let name = alias.as_deref().unwrap_or_else(|| module.split('.').next().unwrap_or(module));
+                self.bind(name, Binding::Imported);
+            }
+            Statement::ImportFrom { names, .. } => {
+                for (name, alias) in names {
+                    self.bind(alias.as_ref().unwrap_or(name), Binding::Imported);
+                }
+            }
+            Statement::If { condition, then_body, elifs, else_body } => {
+                self.expression(condition);
+                self.block(then_body);
+                for (condition, body) in elifs {
+                    self.claim();
+                    self.expression(condition);
+                    self.block(body);
+                }
+                self.else_clause(else_body.as_deref());
+            }
+            Statement::While { condition, body, else_body } => {
+                self.expression(condition);
+                self.block(body);
+                self.else_clause(else_body.as_deref());
+            }
+            Statement::For { vars, iterable, body, else_body, .. } => {
+                self.expression(iterable);
+                for var in vars {
+                    self.bind(var, Binding::Incidental);
+                }
+                self.block(body);
+                self.else_clause(else_body.as_deref());
+            }
+            Statement::FunctionDef { name, params, body, decorators, .. } => {
+                self.decorators(decorators);
+                self.bind(name, Binding::Defined);
+                let parent = self.enter(ScopeKind::Function);
+                for param in params {
+                    self.bind(param, Binding::Incidental);
+                }
+                self.block(body);
+                self.scope = parent;
+            }
+            Statement::ClassDef { name, bases, keywords, decorators, body } => {
+                self.decorators(decorators);
+                for base in bases {
+                    self.expression(base);
+                }
+                for (_, value) in keywords {
+                    self.expression(value);
+                }
+                self.bind(name, Binding::Defined);
+                let parent = self.enter(ScopeKind::Class);
+                self.block(body);
+                self.scope = parent;
+            }
+            Statement::TryExcept { try_body, excepts, else_body, finally_body } => {
+                self.block(try_body);
+                for handler in excepts {
+                    self.claim();
+                    match &handler.exception_type {
+                        Some(exception_type) => self.expression(exception_type),
+                        None => {
+                            let message = "bare `except:` also catches KeyboardInterrupt and SystemExit; name the exceptions, or catch Exception";
+                            self.report(Rule::BareExcept, self.region.0, message.to_string());
+                        }
+                    }
+                    if let Some(name) = &handler.name {
+                        self.bind(name, Binding::Caught);
+                    }
+                    self.block(&handler.body);
+                }
+                self.else_clause(else_body.as_deref());
+                self.else_clause(finally_body.as_deref());
+            }
+            Statement::With { items, body, .. } => {
+                for item in items {
+                    self.expression(&item.context_expr);
+                    if let Some(name) = &item.optional_vars {
+                        self.bind(name, Binding::Incidental);
+                    }
+                }
+                self.block(body);
+            }
+            Statement::Match { subject, cases } => {
+                self.expression(subject);
+                for case in cases {
+                    self.claim();
+                    self.pattern(&case.pattern);
+                    if let Some(guard) = &case.guard {
+                        self.expression(guard);
+                    }
+                    self.block(&case.body);
+                }
+            }
+        }
+    }
+
+    fn decorators(&mut self, decorators: &[Decorator]) {
+        for decorator in decorators {
+            let (name, args) = match decorator {
+                Decorator::Simple(name) => (name, &[][..]),
+                Decorator::WithArgs(name, args) => (name, &args[..]),
+            };
+            // `@name.setter` reads `name`
+            self.read(name.split('.').next().unwrap_or(name));
+            for arg in args {
+                match arg {
+                    Argument::Positional(value) | Argument::Keyword(_, value) => self.expression(value),
+                }
+            }
+        }
+    }
+
+    // What an assignment assigns to: names are bound, and the rest is read
+    fn target(&mut self, target: &Expression, kind: Binding) {
+        match target {
+            Expression::Identifier(name) => self.bind(name, kind),
+            Expression::TupleLiteral(items) | Expression::ListLiteral(items) => {
+                for item in items {
+                    self.target(item, Binding::Incidental);
+                }
+            }
+            Expression::AttributeAccess { object, attr } => {
+                self.expression(object);
+                if CPP_KEYWORDS.contains(&attr.as_str()) {
+                    let at = self.locate(attr, true);
+                    self.report(Rule::CppKeyword, at, format!("`{}` is a C++ keyword, so the generated C++ won't compile", attr));
+                }
+            }
+            _ => self.expression(target),
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Literal(value) | Pattern::Value(value) => self.expression(value),
+            Pattern::Capture(name) | Pattern::Star(Some(name)) => self.bind(name, Binding::Incidental),
+            Pattern::Wildcard | Pattern::Star(None) => {}
+            Pattern::Sequence(patterns) | Pattern::Or(patterns) => {
+                for pattern in patterns {
+                    self.pattern(pattern);
+                }
+            }
+            Pattern::Mapping { entries, rest } => {
+                for (key, pattern) in entries {
+                    self.expression(key);
+                    self.pattern(pattern);
+                }
+                if let Some(rest) = rest {
+                    self.bind(rest, Binding::Incidental);
+                }
+            }
+            Pattern::Class { name, positional, keywords } => {
+                self.read(name.split('.').next().unwrap_or(name));
+                for pattern in positional.iter().chain(keywords.iter().map(|(_, pattern)| pattern)) {
+                    self.pattern(pattern);
+                }
+            }
+            Pattern::As(pattern, name) => {
+                self.pattern(pattern);
+                self.bind(name, Binding::Incidental);
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Identifier(name) => self.read(name),
+            Expression::StringLiteral(_)
+            | Expression::IntegerLiteral(_)
+            | Expression::FloatLiteral(_)
+            | Expression::BooleanLiteral(_)
+            | Expression::NoneLiteral
+            | Expression::ImaginaryLiteral(_)
+            | Expression::Yield(None) => {}
+            Expression::ListLiteral(items)
+            | Expression::TupleLiteral(items)
+            | Expression::SetLiteral(items)
+            | Expression::FrozensetLiteral(items) => {
+                for item in items {
+                    self.expression(item);
+                }
+            }
+            Expression::DictLiteral(entries) => {
+                for (key, value) in entries {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expression::BinaryOperation { left, op, right } => {
+                self.none_comparison(*op, left, right);
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::Compare { left, ops, comparators } => {
+                let mut previous = &**left;
+                for (op, comparator) in ops.iter().zip(comparators) {
+                    self.none_comparison(*op, previous, comparator);
+                    previous = comparator;
+                }
+                self.expression(left);
+                for comparator in comparators {
+                    self.expression(comparator);
+                }
+            }
+            Expression::IfExp { test, body, orelse } => {
+                self.expression(body);
+                self.expression(test);
+                self.expression(orelse);
+            }
+            Expression::NamedExpr { target, value } => {
+                self.expression(value);
+                // A comprehension's `:=` binds in the scope around it
+                let mut scope = self.scope;
+                while self.scopes[scope].kind == ScopeKind::Comprehension {
+                    scope = self.scopes[scope].parent.unwrap_or(0);
+                }
+                let at = self.locate(target, false);
+                self.bind_in(scope, target, Binding::Assigned, at);
+            }
+            Expression::UnaryOperation { operand, .. } => self.expression(operand),
+            Expression::Lambda { params, body } => {
+                let parent = self.enter(ScopeKind::Lambda);
+                for param in params {
+                    self.bind(param, Binding::Incidental);
+                }
+                self.expression(body);
+                self.scope = parent;
+            }
+            Expression::ListComprehension { element, comprehension }
+            | Expression::SetComprehension { element, comprehension }
+            | Expression::GeneratorExpression { element, comprehension } => self.comprehension(comprehension, &[element]),
+            Expression::DictComprehension { key, value, comprehension } => self.comprehension(comprehension, &[key, value]),
+            Expression::Call { callee, args, keywords } => {
+                self.expression(callee);
+                for arg in args {
+                    self.expression(arg);
+                }
+                for (_, value) in keywords {
+                    self.expression(value);
+                }
+            }
+            Expression::AttributeAccess { object, .. } => self.expression(object),
+            Expression::Index { object, index } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            Expression::Yield(Some(value)) | Expression::YieldFrom(value) | Expression::Await(value) => self.expression(value),
+        }
+    }
+
+    fn comprehension(&mut self, comprehension: &Comprehension, elements: &[&Expression]) {
+        // The first iterable is evaluated in the scope around the comprehension
+        self.expression(&comprehension.iter);
+        let parent = self.enter(ScopeKind::Comprehension);
+        for target in &comprehension.target {
+            self.bind(target, Binding::Incidental);
+        }
+        for condition in &comprehension.ifs {
+            self.expression(condition);
+        }
+        for element in elements {
+            self.expression(element);
+        }
+        self.scope = parent;
+    }
+
+    // `x == None` is true for any object whose `__eq__` says so; `is` asks what was meant
+    fn none_comparison(&mut self, op: BinOp, left: &Expression, right: &Expression) {
+        let (written, instead) = match op {
+            BinOp::Eq => ("==", "is"),
+            BinOp::NotEq => ("!=", "is not"),
+            _ => return,
+        };
+        if matches!(left, Expression::NoneLiteral) || matches!(right, Expression::NoneLiteral) {
+            let at = self.locate("None", false);
+            self.report(Rule::NoneComparison, at, format!("comparison to None with `{}`; use `{}`", written, instead));
+        }
+    }
+}
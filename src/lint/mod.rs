@@ -0,0 +1,251 @@
+// `eppx lint`: checks a program for likely mistakes the transpiler lets through,
+// such as names that are never defined or that C++ reserves. A project turns
+// rules off in codon.toml, and a line with `# eppx: ignore[rule]` is not checked
+// for that rule
+
+mod checker;
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::parser::{parse_eppx_located, statement_spans, tokenize_lenient, Token, TokenKind};
+
+use checker::Checker;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    UnusedVariable,
+    UnusedImport,
+    UndefinedName,
+    ShadowedBuiltin,
+    UnreachableCode,
+    BareExcept,
+    NoneComparison,
+    CppKeyword,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 8] = [
+        Rule::UnusedVariable,
+        Rule::UnusedImport,
+        Rule::UndefinedName,
+        Rule::ShadowedBuiltin,
+        Rule::UnreachableCode,
+        Rule::BareExcept,
+        Rule::NoneComparison,
+        Rule::CppKeyword,
+    ];
+
+    /// The rule's name in `[lint]` and in `# eppx: ignore[...]`
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::UnusedImport => "unused-import",
+            Rule::UndefinedName => "undefined-name",
+            Rule::ShadowedBuiltin => "shadowed-builtin",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::BareExcept => "bare-except",
+            Rule::NoneComparison => "none-comparison",
+            Rule::CppKeyword => "cpp-keyword",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A problem a rule found; lines and columns count from 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub rule: Rule,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+// The findings of every rule but those in `disabled`, in source order; fails if
+// the source doesn't parse
+pub fn lint_source(input: &str, disabled: &[Rule]) -> Result<Vec<Finding>, String> {
+    let program = parse_eppx_located(input).map_err(|e| format!("line {}, column {}: {}", e.line, e.column, e.message))?;
+    let tokens = tokenize_lenient(input);
+    let words = words(input, &tokens);
+    let mut checker = Checker::new(&words, statement_spans(input)?);
+    checker.check(&program);
+    let ignored = ignore_comments(input, &tokens);
+    let mut findings: Vec<Finding> = checker
+        .finish()
+        .into_iter()
+        .filter(|finding| !disabled.contains(&finding.rule))
+        .filter(|finding| match ignored.get(&finding.line) {
+            Some(Some(rules)) => !rules.contains(&finding.rule.name()),
+            Some(None) => false,
+            None => true,
+        })
+        .collect();
+    findings.sort_by_key(|finding| (finding.line, finding.column));
+    findings.dedup();
+    Ok(findings)
+}
+
+/// An identifier outside strings and comments; lines and columns count from 1
+pub struct Word {
+    pub text: String,
+    pub line: usize,
+    pub column: usize,
+    /// Whether a `.` comes before it, as in `a.b`
+    pub after_dot: bool,
+}
+
+// The identifiers of a source file in source order. The AST has no positions, so
+// a finding about a name is put on where that name is written in its statement
+fn words(input: &str, tokens: &[Token]) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut previous = None;
+    for token in tokens.iter().filter(|token| !token.kind.is_layout() && token.kind != TokenKind::Comment) {
+        if token.kind == TokenKind::Name {
+            let after_dot = previous.is_some_and(|previous: &Token| previous.kind == TokenKind::Op && previous.text(input) == ".");
+            words.push(Word { text: token.text(input).to_string(), line: token.line, column: token.column, after_dot });
+        }
+        previous = Some(token);
+    }
+    words
+}
+
+// The rules each `# eppx: ignore[a, b]` comment turns off for its line by line
+// number; None for a bare `# eppx: ignore`, which turns off all of them
+fn ignore_comments<'a>(input: &'a str, tokens: &[Token]) -> HashMap<usize, Option<Vec<&'a str>>> {
+    const MARKER: &str = "eppx: ignore";
+    let mut ignored = HashMap::new();
+    for comment in tokens.iter().filter(|token| token.kind == TokenKind::Comment) {
+        let text = comment.text(input);
+        let Some(at) = text.find(MARKER) else {
+            continue;
+        };
+        let rest = &text[at + MARKER.len()..];
+        let rules = rest
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once(']'))
+            .map(|(rules, _)| rules.split(',').map(str::trim).filter(|rule| !rule.is_empty()).collect());
+        ignored.insert(comment.line, rules);
+    }
+    ignored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The rule and position of each finding
+    fn found(input: &str) -> Vec<(Rule, usize, usize)> {
+        found_without(input, &[])
+    }
+
+    fn found_without(input: &str, disabled: &[Rule]) -> Vec<(Rule, usize, usize)> {
+        lint_source(input, disabled).unwrap().into_iter().map(|finding| (finding.rule, finding.line, finding.column)).collect()
+    }
+
+    #[test]
+    fn test_unused_variables() {
+        let input = "def f(a, b):\n    unused = 1\n    used = 2\n    try:\n        pass\n    except ValueError as e:\n        pass\n    return used\n";
+        assert_eq!(found(input), [(Rule::UnusedVariable, 2, 5), (Rule::UnusedVariable, 6, 26)]);
+        // Parameters, loop variables, `_` names, module globals and names read by an inner function are fine
+        let input = "total = 0\n\ndef f(a):\n    for i in range(3):\n        pass\n    _ignored = 1\n    kept = 2\n    def g():\n        return kept\n    return g\n";
+        assert_eq!(found(input), []);
+    }
+
+    #[test]
+    fn test_unused_imports() {
+        let input = "import os\nimport os.path as p\nfrom math import sqrt, pi as PI\nprint(sqrt(2))\n";
+        assert_eq!(found(input), [(Rule::UnusedImport, 1, 8), (Rule::UnusedImport, 2, 19), (Rule::UnusedImport, 3, 30)]);
+        // A use anywhere in the file counts, even inside a function defined before it is imported
+        let input = "def f():\n    return sys.argv\n\nimport sys\nfrom math import sqrt\nprint(f(), sqrt(4))\n";
+        assert_eq!(found(input), []);
+    }
+
+    #[test]
+    fn test_undefined_names() {
+        let input = "def f():\n    return missing + len([])\n\nprint(f(), other.attr)\n";
+        assert_eq!(found(input), [(Rule::UndefinedName, 2, 12), (Rule::UndefinedName, 4, 12)]);
+        // Names defined later in the file, builtins, exception classes and attributes are fine
+        let input = "def f():\n    return g() + len([])\n\ndef g():\n    raise ValueError(__name__)\n\nx = [1]\nx.missing_attribute = 2\nprint(f())\n";
+        assert_eq!(found(input), []);
+    }
+
+    #[test]
+    fn test_shadowed_builtins() {
+        let input = "list = [1]\n\ndef f(len):\n    return len\n";
+        assert_eq!(found(input), [(Rule::ShadowedBuiltin, 1, 1), (Rule::ShadowedBuiltin, 3, 7)]);
+        // A method or class attribute named like a builtin doesn't hide it
+        let input = "class Box:\n    len = 0\n\n    def max(self):\n        return len(self.items)\n";
+        assert_eq!(found(input), []);
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let input = "def f(x):\n    return x\n    print(x)\n    print(x)\n\nfor i in range(3):\n    break\n    print(i)\n";
+        assert_eq!(found(input), [(Rule::UnreachableCode, 3, 5), (Rule::UnreachableCode, 8, 5)]);
+        // A return inside a branch leaves the code after the branch reachable
+        let input = "def f(x):\n    if x:\n        return 1\n    else:\n        raise ValueError()\n    return 2\n";
+        assert_eq!(found(input), []);
+    }
+
+    #[test]
+    fn test_bare_except() {
+        let input = "try:\n    pass\nexcept:\n    pass\n";
+        assert_eq!(found(input), [(Rule::BareExcept, 3, 1)]);
+        let input = "try:\n    pass\nexcept (ValueError, KeyError):\n    pass\nexcept Exception:\n    pass\n";
+        assert_eq!(found(input), []);
+    }
+
+    #[test]
+    fn test_none_comparisons() {
+        let input = "x = 1\nprint(x == None)\nprint(None != x)\n";
+        assert_eq!(found(input), [(Rule::NoneComparison, 2, 12), (Rule::NoneComparison, 3, 7)]);
+        let input = "x = 1\nprint(x is None, x is not None, x == 0)\n";
+        assert_eq!(found(input), []);
+    }
+
+    #[test]
+    fn test_cpp_keywords() {
+        let input = "new = 1\nclass Point:\n    pass\n\np = Point()\np.this = new\n";
+        assert_eq!(found(input), [(Rule::CppKeyword, 1, 1), (Rule::CppKeyword, 6, 3)]);
+        // Reading such a name, or one that only contains a keyword, is fine
+        let input = "newer = 1\nprint(newer)\n";
+        assert_eq!(found(input), []);
+    }
+
+    #[test]
+    fn test_ignore_comments() {
+        let input = "import os  # eppx: ignore\nimport sys  # eppx: ignore[unused-import, bare-except]\nimport re  # eppx: ignore[undefined-name]\n";
+        assert_eq!(found(input), [(Rule::UnusedImport, 3, 8)]);
+        // Only comments count: the marker in a string does nothing, and a comment covers its own line
+        let input = "import os\nx = \"# eppx: ignore\"\ny = 1  # eppx: ignore\n";
+        assert_eq!(found(input), [(Rule::UnusedImport, 1, 8)]);
+    }
+
+    #[test]
+    fn test_disabled_rules() {
+        let input = "import os\nlist = [1]\n";
+        assert_eq!(found_without(input, &[Rule::UnusedImport]), [(Rule::ShadowedBuiltin, 2, 1)]);
+        assert_eq!(found_without(input, &Rule::ALL), []);
+    }
+
+    #[test]
+    fn test_names_are_found_past_strings_and_comments() {
+        // `missing` in the string and the comment comes first, but the finding is on the code
+        let input = "print('missing', missing)  # missing\n";
+        assert_eq!(found(input), [(Rule::UndefinedName, 1, 18)]);
+    }
+
+    #[test]
+    fn test_source_that_does_not_parse_is_an_error() {
+        assert_eq!(lint_source("def f(:\n", &[]).unwrap_err(), "line 1, column 7: expected identifier");
+    }
+}
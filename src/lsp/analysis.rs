@@ -0,0 +1,1326 @@
+// What the language server knows about one source file: its identifiers with
+// their positions, and the functions, classes and variables its AST defines.
+// The AST has no positions, so a `def` or `class` is matched to the next header
+// with its name, and a variable to the first use of its name in its scope
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::{AstNode, BinOp, Comprehension, Decorator, Expression, Pattern, Statement, UnaryOp};
+use crate::codegen::BUILTIN_FUNCTIONS;
+use crate::parser::{parse_eppx_located, tokenize_lenient, SyntaxError, Token, TokenKind};
+
+const KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "case", "class", "continue", "def",
+    "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda",
+    "match", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield",
+];
+
+pub fn is_keyword(word: &str) -> bool {
+    KEYWORDS.contains(&word)
+}
+
+/// An identifier in the source; lines count from 0 and columns in UTF-16 units, as LSP does
+#[derive(Debug, Clone)]
+pub struct Word {
+    pub text: String,
+    pub line: u32,
+    pub start: u32,
+    pub end: u32,
+    /// Whether a `.` comes before it, as in `a.b`
+    pub after_dot: bool,
+    /// For `a.b`, the word `a` when this is `b`
+    pub object: Option<usize>,
+}
+
+// Identifiers outside strings and comments, and the indentation of each line
+// that starts a statement; other lines (blank, comments, inside brackets or a
+// string) have none
+fn scan(source: &str) -> (Vec<Word>, Vec<Option<usize>>) {
+    let line_starts: Vec<usize> = [0].into_iter().chain(source.match_indices('\n').map(|(offset, _)| offset + 1)).collect();
+    let utf16 = |text: &str| text.encode_utf16().count() as u32;
+    let mut words: Vec<Word> = Vec::new();
+    let mut indents = vec![None; line_starts.len()];
+    // The token before this one that is not layout or a comment, and the word it was if any
+    let mut previous: Option<(Token, Option<usize>)> = None;
+    let mut dot_object = None;
+    let mut at_line_start = true;
+    for token in tokenize_lenient(source) {
+        match token.kind {
+            TokenKind::Newline | TokenKind::Indent | TokenKind::Dedent => {
+                at_line_start = true;
+                continue;
+            }
+            TokenKind::Comment => continue,
+            _ if token.kind.is_layout() => {
+                at_line_start = false;
+                continue;
+            }
+            _ => {}
+        }
+        if at_line_start {
+            indents[token.line - 1] = Some(token.column - 1);
+            at_line_start = false;
+        }
+        let text = token.text(source);
+        let mut word = None;
+        if token.kind == TokenKind::Name {
+            let after_dot = previous.is_some_and(|(previous, _)| previous.kind == TokenKind::Op && previous.text(source) == ".");
+            let start = utf16(&source[line_starts[token.line - 1]..token.start]);
+            words.push(Word {
+                text: text.to_string(),
+                line: token.line as u32 - 1,
+                start,
+                end: start + utf16(text),
+                after_dot,
+                object: if after_dot { dot_object } else { None },
+            });
+            word = Some(words.len() - 1);
+        } else if text == "." {
+            dot_object = previous.and_then(|(_, word)| word);
+        }
+        previous = Some((token, word));
+    }
+    (words, indents)
+}
+
+// The last line of the block a header on `line` opens: the lines after it that
+// are indented further
+fn block_end(indents: &[Option<usize>], line: u32) -> u32 {
+    let Some(Some(header)) = indents.get(line as usize) else {
+        return line;
+    };
+    let mut last = line;
+    for (index, indent) in indents.iter().enumerate().skip(line as usize + 1) {
+        match indent {
+            None => {}
+            Some(indent) if indent > header => last = index as u32,
+            Some(_) => break,
+        }
+    }
+    last
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeKind {
+    Module,
+    Function,
+    Class,
+}
+
+#[derive(Debug)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    pub parent: Option<usize>,
+    /// The `def` or `class` line, for every scope but the module's
+    header: Option<u32>,
+    pub first_line: u32,
+    pub last_line: u32,
+    pub symbols: Vec<usize>,
+    /// The function or class symbol whose body it is
+    pub owner: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Class,
+    Variable,
+    Parameter,
+    Module,
+}
+
+// Where a symbol's value comes from, which is what its type is inferred from
+#[derive(Debug)]
+enum Origin {
+    Def { params: Vec<String>, body: Vec<AstNode>, is_async: bool },
+    Class { bases: Vec<Expression>, body: Vec<AstNode> },
+    Value(Expression),
+    // A `for` variable over this iterable
+    Element(Expression),
+    // `for a, b in iterable`
+    ElementItem(Expression, usize),
+    // `a, b = value`
+    Item(Expression, usize),
+    // `x: int`
+    Annotation(Expression),
+    // `except E as name`
+    Instance(Expression),
+    // The first parameter of a method, which is the class symbol's instance
+    SelfOf(usize),
+    Import { module: String, name: Option<String> },
+    Unknown,
+}
+
+#[derive(Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The word that defines it, if the source has one
+    pub word: Option<usize>,
+    pub scope: usize,
+    /// The scope of a function's or class's body
+    pub body: Option<usize>,
+    // The scope its value is evaluated in; a `self.x` member is defined in the
+    // class but assigned in a method
+    context: usize,
+    origin: Origin,
+}
+
+/// What an identifier refers to: a symbol of this file, an imported module named
+/// by an `import` statement, or a name in an imported module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Local(usize),
+    Module(String),
+    Member { module: String, name: String },
+}
+
+/// Something with attributes: a class of this file, or an imported module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Namespace {
+    Class(usize),
+    Module(String),
+}
+
+/// An inferred Python type; `Unknown` is anything the inference cannot follow
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ty {
+    Unknown,
+    None,
+    Bool,
+    Int,
+    Float,
+    Complex,
+    Str,
+    Bytes,
+    List(Box<Ty>),
+    Tuple(Vec<Ty>),
+    Dict(Box<Ty>, Box<Ty>),
+    Set(Box<Ty>),
+    Frozenset(Box<Ty>),
+    /// range, enumerate, generator, ...: the name and what iterating gives
+    Iterator(&'static str, Box<Ty>),
+    File,
+    Instance(String),
+    Class(String),
+    Function(String),
+    Module(String),
+    Union(Vec<Ty>),
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let generic = |f: &mut fmt::Formatter<'_>, name: &str, args: &[&Ty]| {
+            if args.iter().any(|arg| **arg == Ty::Unknown) {
+                write!(f, "{}", name)
+            } else {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}[{}]", name, args.join(", "))
+            }
+        };
+        match self {
+            Ty::Unknown => write!(f, "unknown"),
+            Ty::None => write!(f, "None"),
+            Ty::Bool => write!(f, "bool"),
+            Ty::Int => write!(f, "int"),
+            Ty::Float => write!(f, "float"),
+            Ty::Complex => write!(f, "complex"),
+            Ty::Str => write!(f, "str"),
+            Ty::Bytes => write!(f, "bytes"),
+            Ty::List(element) => generic(f, "list", &[element]),
+            Ty::Tuple(items) if items.is_empty() => write!(f, "tuple"),
+            Ty::Tuple(items) => generic(f, "tuple", &items.iter().collect::<Vec<_>>()),
+            Ty::Dict(key, value) => generic(f, "dict", &[key, value]),
+            Ty::Set(element) => generic(f, "set", &[element]),
+            Ty::Frozenset(element) => generic(f, "frozenset", &[element]),
+            Ty::Iterator(name, element) => generic(f, name, &[element]),
+            Ty::File => write!(f, "file"),
+            Ty::Instance(name) => write!(f, "{}", name),
+            Ty::Class(name) => write!(f, "type[{}]", name),
+            Ty::Function(_) => write!(f, "function"),
+            Ty::Module(_) => write!(f, "module"),
+            Ty::Union(types) => {
+                let types: Vec<String> = types.iter().map(|ty| ty.to_string()).collect();
+                write!(f, "{}", types.join(" | "))
+            }
+        }
+    }
+}
+
+// The type of several values: theirs if they agree, ignoring those that are not known
+fn common(types: impl IntoIterator<Item = Ty>) -> Ty {
+    let mut known: Vec<Ty> = Vec::new();
+    for ty in types {
+        if ty != Ty::Unknown && !known.contains(&ty) {
+            known.push(ty);
+        }
+    }
+    match known.len() {
+        0 => Ty::Unknown,
+        1 => known.remove(0),
+        _ if known.iter().all(|ty| matches!(ty, Ty::Int | Ty::Float | Ty::Bool)) => Ty::Float,
+        _ => Ty::Union(known),
+    }
+}
+
+// What iterating over a value of this type gives
+fn element_of(ty: &Ty) -> Ty {
+    match ty {
+        Ty::List(element) | Ty::Set(element) | Ty::Frozenset(element) | Ty::Iterator(_, element) => (**element).clone(),
+        Ty::Dict(key, _) => (**key).clone(),
+        Ty::Tuple(items) => common(items.iter().cloned()),
+        Ty::Str | Ty::File => Ty::Str,
+        Ty::Bytes => Ty::Int,
+        _ => Ty::Unknown,
+    }
+}
+
+// What unpacking a value of this type gives at `index`
+fn item_of(ty: Ty, index: usize) -> Ty {
+    match ty {
+        Ty::Tuple(items) => items.get(index).cloned().unwrap_or(Ty::Unknown),
+        ty => element_of(&ty),
+    }
+}
+
+fn arithmetic(left: &Ty, op: BinOp, right: &Ty) -> Ty {
+    use Ty::*;
+    let number = |ty: &Ty| matches!(ty, Int | Bool | Float | Complex);
+    match (left, op, right) {
+        // Only a str can be added to a str
+        (Str, BinOp::Add, _) | (_, BinOp::Add, Str) | (Str, BinOp::Mod, _) => Str,
+        (Str, BinOp::Mul, Int) | (Int, BinOp::Mul, Str) => Str,
+        (List(element), BinOp::Add, List(_)) | (List(element), BinOp::Mul, Int) => List(element.clone()),
+        (Set(element), BinOp::BitOr | BinOp::BitAnd | BinOp::BitXor | BinOp::Sub, Set(_)) => Set(element.clone()),
+        (Complex, _, _) | (_, _, Complex) if number(left) && number(right) => Complex,
+        (Float, _, _) | (_, _, Float) if number(left) && number(right) => Float,
+        (Int | Bool, BinOp::Div, Int | Bool) => Float,
+        (Int | Bool, _, Int | Bool) => Int,
+        _ => Unknown,
+    }
+}
+
+fn builtin_call_type(name: &str, args: &[Ty]) -> Ty {
+    let arg = |index: usize| args.get(index).cloned().unwrap_or(Ty::Unknown);
+    match name {
+        "int" | "len" | "ord" | "hash" | "id" => Ty::Int,
+        "round" if args.len() < 2 => Ty::Int,
+        "round" | "float" => Ty::Float,
+        "complex" => Ty::Complex,
+        "str" | "chr" | "hex" | "bin" | "oct" | "repr" | "ascii" | "input" | "format" => Ty::Str,
+        "bool" | "isinstance" | "issubclass" | "callable" | "hasattr" | "all" | "any" => Ty::Bool,
+        "bytes" | "bytearray" => Ty::Bytes,
+        "abs" => arithmetic(&arg(0), BinOp::Add, &Ty::Int),
+        "pow" => arithmetic(&arg(0), BinOp::Pow, &arg(1)),
+        "divmod" => Ty::Tuple(vec![Ty::Int, Ty::Int]),
+        "list" | "sorted" => Ty::List(Box::new(element_of(&arg(0)))),
+        "set" => Ty::Set(Box::new(element_of(&arg(0)))),
+        "tuple" => Ty::Tuple(Vec::new()),
+        "dict" => Ty::Dict(Box::new(Ty::Unknown), Box::new(Ty::Unknown)),
+        "range" => Ty::Iterator("range", Box::new(Ty::Int)),
+        "enumerate" => Ty::Iterator("enumerate", Box::new(Ty::Tuple(vec![Ty::Int, element_of(&arg(0))]))),
+        "zip" => Ty::Iterator("zip", Box::new(Ty::Tuple(args.iter().map(element_of).collect()))),
+        "reversed" => Ty::Iterator("reversed", Box::new(element_of(&arg(0)))),
+        "iter" => Ty::Iterator("iterator", Box::new(element_of(&arg(0)))),
+        "filter" => Ty::Iterator("filter", Box::new(element_of(&arg(1)))),
+        "map" => Ty::Iterator("map", Box::new(Ty::Unknown)),
+        "next" => element_of(&arg(0)),
+        "sum" => match element_of(&arg(0)) {
+            Ty::Float => Ty::Float,
+            _ => Ty::Int,
+        },
+        "max" | "min" if args.len() == 1 => element_of(&arg(0)),
+        "max" | "min" => common(args.iter().cloned()),
+        "open" => Ty::File,
+        "print" | "setattr" | "delattr" => Ty::None,
+        _ => Ty::Unknown,
+    }
+}
+
+fn method_type(object: &Ty, method: &str) -> Ty {
+    match (object, method) {
+        (
+            Ty::Str,
+            "upper" | "lower" | "strip" | "lstrip" | "rstrip" | "replace" | "title" | "capitalize" | "swapcase"
+            | "format" | "center" | "ljust" | "rjust" | "zfill" | "join",
+        ) => Ty::Str,
+        (Ty::Str, "split" | "splitlines") => Ty::List(Box::new(Ty::Str)),
+        (Ty::Str, "find" | "rfind" | "index" | "rindex" | "count") => Ty::Int,
+        (Ty::Str, name) if name.starts_with("is") || name == "startswith" || name == "endswith" => Ty::Bool,
+        (Ty::List(element), "pop") => (**element).clone(),
+        (Ty::List(_), "index" | "count") => Ty::Int,
+        (Ty::List(_) | Ty::Dict(..) | Ty::Set(_), "copy") => object.clone(),
+        (Ty::Dict(_, value), "get" | "pop" | "setdefault") => (**value).clone(),
+        (Ty::Dict(key, _), "keys") => Ty::List(key.clone()),
+        (Ty::Dict(_, value), "values") => Ty::List(value.clone()),
+        (Ty::Dict(key, value), "items") => Ty::List(Box::new(Ty::Tuple(vec![(**key).clone(), (**value).clone()]))),
+        (Ty::Set(element), "union" | "intersection" | "difference") => Ty::Set(element.clone()),
+        (Ty::File, "read" | "readline") => Ty::Str,
+        (Ty::File, "readlines") => Ty::List(Box::new(Ty::Str)),
+        (_, "append" | "extend" | "insert" | "remove" | "sort" | "reverse" | "clear" | "add" | "discard" | "close") => {
+            Ty::None
+        }
+        _ => Ty::Unknown,
+    }
+}
+
+// A type annotation, such as `int` or `list[str]`
+fn annotation_type(annotation: &Expression) -> Ty {
+    match annotation {
+        Expression::Identifier(name) => match name.as_str() {
+            "int" => Ty::Int,
+            "float" => Ty::Float,
+            "str" => Ty::Str,
+            "bool" => Ty::Bool,
+            "bytes" => Ty::Bytes,
+            "complex" => Ty::Complex,
+            "list" | "List" => Ty::List(Box::new(Ty::Unknown)),
+            "dict" | "Dict" => Ty::Dict(Box::new(Ty::Unknown), Box::new(Ty::Unknown)),
+            "set" | "Set" => Ty::Set(Box::new(Ty::Unknown)),
+            "tuple" | "Tuple" => Ty::Tuple(Vec::new()),
+            "None" => Ty::None,
+            name => Ty::Instance(name.to_string()),
+        },
+        Expression::NoneLiteral => Ty::None,
+        Expression::Index { object, index } => {
+            let args = match &**index {
+                Expression::TupleLiteral(items) => items.iter().map(annotation_type).collect(),
+                index => vec![annotation_type(index)],
+            };
+            let arg = |i: usize| Box::new(args.get(i).cloned().unwrap_or(Ty::Unknown));
+            match annotation_type(object) {
+                Ty::List(_) => Ty::List(arg(0)),
+                Ty::Set(_) => Ty::Set(arg(0)),
+                Ty::Dict(..) => Ty::Dict(arg(0), arg(1)),
+                Ty::Tuple(_) => Ty::Tuple(args),
+                _ => Ty::Unknown,
+            }
+        }
+        _ => Ty::Unknown,
+    }
+}
+
+// Source text for a base class or a decorator
+fn expression_text(expr: &Expression) -> String {
+    match expr {
+        Expression::Identifier(name) => name.clone(),
+        Expression::AttributeAccess { object, attr } => format!("{}.{}", expression_text(object), attr),
+        Expression::Index { object, index } => format!("{}[{}]", expression_text(object), expression_text(index)),
+        Expression::Call { callee, .. } => format!("{}(...)", expression_text(callee)),
+        _ => "...".to_string(),
+    }
+}
+
+// A function's or class's docstring
+fn docstring(body: &[AstNode]) -> Option<&str> {
+    match body.first() {
+        Some(AstNode::Statement(Statement::ExpressionStatement(expr))) => match &**expr {
+            Expression::StringLiteral(text) => Some(text.trim()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// The value a `yield` expression yields; `yield` alone yields None
+fn yielded(expr: &Expression) -> Option<Option<&Expression>> {
+    match expr {
+        Expression::Yield(value) => Some(value.as_deref()),
+        Expression::YieldFrom(value) => Some(Some(value)),
+        _ => None,
+    }
+}
+
+type Values<'a> = Vec<Option<&'a Expression>>;
+
+// Values a function returns, and those it yields, not counting nested functions'
+fn returns_and_yields<'a>(body: &'a [AstNode], returns: &mut Values<'a>, yields: &mut Values<'a>) {
+    for AstNode::Statement(statement) in body {
+        match statement {
+            Statement::Return(value) => returns.push(value.as_deref()),
+            Statement::Yield(value) => yields.push(value.as_deref()),
+            Statement::ExpressionStatement(expr) | Statement::Assignment { value: expr, .. } => {
+                yields.extend(yielded(expr))
+            }
+            Statement::If { then_body, elifs, else_body, .. } => {
+                returns_and_yields(then_body, returns, yields);
+                for (_, body) in elifs {
+                    returns_and_yields(body, returns, yields);
+                }
+                returns_and_yields(else_body.as_deref().unwrap_or_default(), returns, yields);
+            }
+            Statement::While { body, else_body, .. } | Statement::For { body, else_body, .. } => {
+                returns_and_yields(body, returns, yields);
+                returns_and_yields(else_body.as_deref().unwrap_or_default(), returns, yields);
+            }
+            Statement::With { body, .. } => returns_and_yields(body, returns, yields),
+            Statement::TryExcept { try_body, excepts, else_body, finally_body } => {
+                returns_and_yields(try_body, returns, yields);
+                for handler in excepts {
+                    returns_and_yields(&handler.body, returns, yields);
+                }
+                returns_and_yields(else_body.as_deref().unwrap_or_default(), returns, yields);
+                returns_and_yields(finally_body.as_deref().unwrap_or_default(), returns, yields);
+            }
+            Statement::Match { cases, .. } => {
+                for case in cases {
+                    returns_and_yields(&case.body, returns, yields);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A parsed source file, or one that did not parse, which has words but no symbols
+pub struct Analysis {
+    pub words: Vec<Word>,
+    pub scopes: Vec<Scope>,
+    pub symbols: Vec<Symbol>,
+    pub error: Option<SyntaxError>,
+    // Symbols whose type is being inferred, to stop at recursion, and those done
+    inferring: RefCell<HashSet<usize>>,
+    types: RefCell<HashMap<usize, Ty>>,
+}
+
+impl Analysis {
+    pub fn new(source: &str) -> Self {
+        let (words, indents) = scan(source);
+        let module = Scope {
+            kind: ScopeKind::Module,
+            parent: None,
+            header: None,
+            first_line: 0,
+            last_line: u32::MAX,
+            symbols: Vec::new(),
+            owner: None,
+        };
+        let mut builder = Builder {
+            headers: Vec::new(),
+            next_header: 0,
+            blocks: Vec::new(),
+            scopes: vec![module],
+            symbols: Vec::new(),
+            words: &words,
+        };
+        for (index, word) in words.iter().enumerate() {
+            let name = words.get(index + 1).filter(|name| name.line == word.line && !is_keyword(&name.text));
+            if (word.text == "def" || word.text == "class") && !word.after_dot && name.is_some() {
+                builder.headers.push(index + 1);
+                builder.blocks.push((word.line, block_end(&indents, word.line)));
+            }
+        }
+        let error = match parse_eppx_located(source) {
+            Ok(ast) => {
+                builder.walk(&ast, 0, 0);
+                None
+            }
+            Err(error) => Some(error),
+        };
+        let Builder { scopes, symbols, .. } = builder;
+        Analysis {
+            words,
+            scopes,
+            symbols,
+            error,
+            inferring: RefCell::new(HashSet::new()),
+            types: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The word at or just before a position
+    pub fn word_at(&self, line: u32, character: u32) -> Option<usize> {
+        self.words.iter().position(|word| word.line == line && word.start <= character && character <= word.end)
+    }
+
+    /// The innermost scope around a line
+    pub fn scope_at(&self, line: u32) -> usize {
+        let mut innermost = 0;
+        for (index, scope) in self.scopes.iter().enumerate() {
+            let contains = scope.first_line <= line && line <= scope.last_line;
+            if contains && scope.first_line >= self.scopes[innermost].first_line {
+                innermost = index;
+            }
+        }
+        innermost
+    }
+
+    /// The symbol a name means in a scope: Python looks in the scope, then the
+    /// functions around it and the module, skipping the bodies of classes
+    pub fn lookup(&self, name: &str, scope: usize) -> Option<usize> {
+        let mut current = Some(scope);
+        while let Some(index) = current {
+            let scope_ref = &self.scopes[index];
+            if index == scope || scope_ref.kind != ScopeKind::Class {
+                if let Some(&symbol) = scope_ref.symbols.iter().find(|&&symbol| self.symbols[symbol].name == name) {
+                    return Some(symbol);
+                }
+            }
+            current = scope_ref.parent;
+        }
+        None
+    }
+
+    fn is_class(&self, symbol: usize) -> bool {
+        self.symbols[symbol].kind == SymbolKind::Class
+    }
+
+    /// A name defined at the top level, as other modules import it
+    pub fn top_level(&self, name: &str) -> Option<usize> {
+        self.scopes[0].symbols.iter().copied().find(|&symbol| self.symbols[symbol].name == name)
+    }
+
+    /// What a word refers to
+    pub fn resolve(&self, word: usize) -> Option<Target> {
+        if let Some(symbol) = self.symbols.iter().position(|symbol| symbol.word == Some(word)) {
+            return Some(Target::Local(symbol));
+        }
+        let word_ref = &self.words[word];
+        if is_keyword(&word_ref.text) {
+            return None;
+        }
+        if word_ref.after_dot {
+            let namespace = self.namespace_of_word(word_ref.object?)?;
+            return self.member(&namespace, &word_ref.text);
+        }
+        if let Some(symbol) = self.lookup(&word_ref.text, self.scope_at(word_ref.line)) {
+            return Some(Target::Local(symbol));
+        }
+        // The module in `import module as alias` or `from module import name`
+        let previous = word.checked_sub(1).map(|previous| &self.words[previous]);
+        previous
+            .filter(|previous| previous.line == word_ref.line && (previous.text == "import" || previous.text == "from"))
+            .map(|_| Target::Module(word_ref.text.clone()))
+    }
+
+    /// The attribute `name` of a namespace
+    pub fn member(&self, namespace: &Namespace, name: &str) -> Option<Target> {
+        match namespace {
+            Namespace::Class(class) => self.class_member(*class, name, 0).map(Target::Local),
+            Namespace::Module(module) => Some(Target::Member { module: module.clone(), name: name.to_string() }),
+        }
+    }
+
+    // A class's attribute or method, or one it inherits from a class in this file
+    fn class_member(&self, class: usize, name: &str, depth: usize) -> Option<usize> {
+        let symbol = &self.symbols[class];
+        let members = &self.scopes[symbol.body?].symbols;
+        if let Some(&found) = members.iter().find(|&&member| self.symbols[member].name == name) {
+            return Some(found);
+        }
+        let Origin::Class { bases, .. } = &symbol.origin else {
+            return None;
+        };
+        bases.iter().filter(|_| depth < 16).find_map(|base| match base {
+            Expression::Identifier(base) => match self.lookup(base, symbol.scope) {
+                Some(base) if self.is_class(base) => self.class_member(base, name, depth + 1),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    // The namespace an identifier, possibly itself an attribute as in `a.b.c`, refers to
+    fn namespace_of_word(&self, word: usize) -> Option<Namespace> {
+        match self.resolve(word)? {
+            Target::Local(symbol) => self.namespace_of_symbol(symbol),
+            Target::Module(module) => Some(Namespace::Module(module)),
+            Target::Member { .. } => None,
+        }
+    }
+
+    /// The namespace a dotted name such as `self.items` refers to on a line
+    pub fn namespace_of_path(&self, path: &[&str], line: u32) -> Option<Namespace> {
+        let (first, rest) = path.split_first()?;
+        let mut namespace = self.namespace_of_symbol(self.lookup(first, self.scope_at(line))?)?;
+        for name in rest {
+            namespace = match self.member(&namespace, name)? {
+                Target::Local(symbol) => self.namespace_of_symbol(symbol)?,
+                Target::Module(_) | Target::Member { .. } => return None,
+            };
+        }
+        Some(namespace)
+    }
+
+    fn namespace_of_symbol(&self, symbol: usize) -> Option<Namespace> {
+        match &self.symbols[symbol].origin {
+            Origin::Class { .. } => Some(Namespace::Class(symbol)),
+            Origin::Import { module, name: None } => Some(Namespace::Module(module.clone())),
+            _ => match self.symbol_type(symbol) {
+                Ty::Instance(class) => {
+                    self.top_level(&class).filter(|&class| self.is_class(class)).map(Namespace::Class)
+                }
+                _ => None,
+            },
+        }
+    }
+
+    /// The module, and the name in it, an import symbol brings in
+    pub fn import_of(&self, symbol: usize) -> Option<(&str, Option<&str>)> {
+        match &self.symbols[symbol].origin {
+            Origin::Import { module, name } => Some((module, name.as_deref())),
+            _ => None,
+        }
+    }
+
+    /// The names visible on a line, innermost first
+    pub fn visible(&self, line: u32) -> Vec<usize> {
+        let start = self.scope_at(line);
+        let mut names = HashSet::new();
+        let mut visible = Vec::new();
+        let mut current = Some(start);
+        while let Some(index) = current {
+            let scope = &self.scopes[index];
+            if index == start || scope.kind != ScopeKind::Class {
+                for &symbol in &scope.symbols {
+                    if names.insert(&self.symbols[symbol].name) {
+                        visible.push(symbol);
+                    }
+                }
+            }
+            current = scope.parent;
+        }
+        visible
+    }
+
+    /// The attributes of a class, including those it inherits from classes in this file
+    pub fn class_members(&self, class: usize) -> Vec<usize> {
+        let mut members: Vec<usize> = Vec::new();
+        let mut pending = vec![class];
+        let mut seen = HashSet::new();
+        while let Some(class) = pending.pop() {
+            let symbol = &self.symbols[class];
+            let Some(body) = symbol.body.filter(|_| seen.insert(class)) else {
+                continue;
+            };
+            for &member in &self.scopes[body].symbols {
+                if !members.iter().any(|&known| self.symbols[known].name == self.symbols[member].name) {
+                    members.push(member);
+                }
+            }
+            if let Origin::Class { bases, .. } = &symbol.origin {
+                for base in bases {
+                    if let Expression::Identifier(base) = base {
+                        pending.extend(self.lookup(base, symbol.scope).filter(|&base| self.is_class(base)));
+                    }
+                }
+            }
+        }
+        members
+    }
+
+    /// The type of a symbol's value
+    pub fn symbol_type(&self, symbol: usize) -> Ty {
+        if let Some(ty) = self.types.borrow().get(&symbol) {
+            return ty.clone();
+        }
+        if !self.inferring.borrow_mut().insert(symbol) {
+            return Ty::Unknown;
+        }
+        let symbol_ref = &self.symbols[symbol];
+        let context = symbol_ref.context;
+        let ty = match &symbol_ref.origin {
+            Origin::Def { .. } => Ty::Function(symbol_ref.name.clone()),
+            Origin::Class { .. } => Ty::Class(symbol_ref.name.clone()),
+            Origin::Value(value) => self.infer(value, context, &[]),
+            Origin::Element(iterable) => element_of(&self.infer(iterable, context, &[])),
+            Origin::Item(value, index) => item_of(self.infer(value, context, &[]), *index),
+            Origin::ElementItem(iterable, index) => item_of(element_of(&self.infer(iterable, context, &[])), *index),
+            Origin::Annotation(annotation) => annotation_type(annotation),
+            Origin::Instance(Expression::Identifier(class)) => Ty::Instance(class.clone()),
+            Origin::SelfOf(class) => Ty::Instance(self.symbols[*class].name.clone()),
+            Origin::Import { module, name: None } => Ty::Module(module.clone()),
+            Origin::Instance(_) | Origin::Import { .. } | Origin::Unknown => Ty::Unknown,
+        };
+        self.inferring.borrow_mut().remove(&symbol);
+        self.types.borrow_mut().insert(symbol, ty.clone());
+        ty
+    }
+
+    /// What a function returns: a generator if it yields, None if it never returns a value
+    pub fn return_type(&self, function: usize) -> Ty {
+        let symbol = &self.symbols[function];
+        let (Origin::Def { body, .. }, Some(scope)) = (&symbol.origin, symbol.body) else {
+            return Ty::Unknown;
+        };
+        if !self.inferring.borrow_mut().insert(function) {
+            return Ty::Unknown;
+        }
+        let (mut returns, mut yields) = (Vec::new(), Vec::new());
+        returns_and_yields(body, &mut returns, &mut yields);
+        let ty = if !yields.is_empty() {
+            let yielded = yields.into_iter().map(|value| value.map_or(Ty::None, |value| self.infer(value, scope, &[])));
+            Ty::Iterator("generator", Box::new(common(yielded)))
+        } else if returns.iter().all(Option::is_none) {
+            Ty::None
+        } else {
+            common(returns.into_iter().map(|value| value.map_or(Ty::None, |value| self.infer(value, scope, &[]))))
+        };
+        self.inferring.borrow_mut().remove(&function);
+        ty
+    }
+
+    /// The type of an expression evaluated in a scope, with `locals` bound by comprehensions
+    pub fn infer(&self, expr: &Expression, scope: usize, locals: &[(String, Ty)]) -> Ty {
+        let infer = |expr: &Expression| self.infer(expr, scope, locals);
+        let all = |items: &[Expression]| common(items.iter().map(infer));
+        match expr {
+            Expression::StringLiteral(_) => Ty::Str,
+            Expression::IntegerLiteral(_) => Ty::Int,
+            Expression::FloatLiteral(_) => Ty::Float,
+            Expression::BooleanLiteral(_) => Ty::Bool,
+            Expression::NoneLiteral => Ty::None,
+            Expression::ImaginaryLiteral(_) => Ty::Complex,
+            Expression::ListLiteral(items) => Ty::List(Box::new(all(items))),
+            Expression::TupleLiteral(items) => Ty::Tuple(items.iter().map(infer).collect()),
+            Expression::SetLiteral(items) => Ty::Set(Box::new(all(items))),
+            Expression::FrozensetLiteral(items) => Ty::Frozenset(Box::new(all(items))),
+            Expression::DictLiteral(entries) => Ty::Dict(
+                Box::new(common(entries.iter().map(|(key, _)| infer(key)))),
+                Box::new(common(entries.iter().map(|(_, value)| infer(value)))),
+            ),
+            Expression::Identifier(name) => {
+                if let Some((_, ty)) = locals.iter().rev().find(|(local, _)| local == name) {
+                    return ty.clone();
+                }
+                self.lookup(name, scope).map_or(Ty::Unknown, |symbol| self.symbol_type(symbol))
+            }
+            Expression::BinaryOperation { left, op, right } => match op {
+                BinOp::Eq | BinOp::NotEq | BinOp::Lt | BinOp::Gt | BinOp::LtEq | BinOp::GtEq => Ty::Bool,
+                BinOp::Is | BinOp::IsNot | BinOp::In | BinOp::NotIn => Ty::Bool,
+                BinOp::And | BinOp::Or => common([infer(left), infer(right)]),
+                op => arithmetic(&infer(left), *op, &infer(right)),
+            },
+            Expression::Compare { .. } => Ty::Bool,
+            Expression::UnaryOperation { op: UnaryOp::Not, .. } => Ty::Bool,
+            Expression::UnaryOperation { operand, .. } => arithmetic(&infer(operand), BinOp::Add, &Ty::Int),
+            Expression::IfExp { body, orelse, .. } => common([infer(body), infer(orelse)]),
+            Expression::NamedExpr { value, .. } => infer(value),
+            Expression::Lambda { .. } => Ty::Function("lambda".to_string()),
+            Expression::ListComprehension { element, comprehension } => {
+                Ty::List(Box::new(self.infer(element, scope, &self.bind(comprehension, scope, locals))))
+            }
+            Expression::SetComprehension { element, comprehension } => {
+                Ty::Set(Box::new(self.infer(element, scope, &self.bind(comprehension, scope, locals))))
+            }
+            Expression::GeneratorExpression { element, comprehension } => Ty::Iterator(
+                "generator",
+                Box::new(self.infer(element, scope, &self.bind(comprehension, scope, locals))),
+            ),
+            Expression::DictComprehension { key, value, comprehension } => {
+                let locals = self.bind(comprehension, scope, locals);
+                Ty::Dict(Box::new(self.infer(key, scope, &locals)), Box::new(self.infer(value, scope, &locals)))
+            }
+            Expression::Call { callee, args, .. } => match &**callee {
+                Expression::Identifier(name) => match self.lookup(name, scope) {
+                    Some(symbol) => match self.symbols[symbol].kind {
+                        SymbolKind::Class => Ty::Instance(name.clone()),
+                        SymbolKind::Function => self.return_type(symbol),
+                        _ => Ty::Unknown,
+                    },
+                    None => builtin_call_type(name, &args.iter().map(infer).collect::<Vec<_>>()),
+                },
+                Expression::AttributeAccess { object, attr } => match infer(object) {
+                    Ty::Instance(class) | Ty::Class(class) => {
+                        match self.top_level(&class).and_then(|class| self.class_member(class, attr, 0)) {
+                            Some(method) if self.symbols[method].kind == SymbolKind::Function => {
+                                self.return_type(method)
+                            }
+                            _ => Ty::Unknown,
+                        }
+                    }
+                    object => method_type(&object, attr),
+                },
+                _ => Ty::Unknown,
+            },
+            Expression::AttributeAccess { object, attr } => match infer(object) {
+                Ty::Instance(class) | Ty::Class(class) => {
+                    match self.top_level(&class).and_then(|class| self.class_member(class, attr, 0)) {
+                        Some(member) => self.symbol_type(member),
+                        None => Ty::Unknown,
+                    }
+                }
+                _ => Ty::Unknown,
+            },
+            Expression::Index { object, index } => match (infer(object), &**index) {
+                (Ty::Tuple(items), Expression::IntegerLiteral(i)) => {
+                    usize::try_from(*i).ok().and_then(|i| items.get(i).cloned()).unwrap_or(Ty::Unknown)
+                }
+                (Ty::Dict(_, value), _) => *value,
+                (Ty::Str, _) => Ty::Str,
+                (object, _) => element_of(&object),
+            },
+            Expression::Yield(_) | Expression::YieldFrom(_) | Expression::Await(_) => Ty::Unknown,
+        }
+    }
+
+    // `locals` with a comprehension's variables bound to the iterable's elements
+    fn bind(&self, comprehension: &Comprehension, scope: usize, locals: &[(String, Ty)]) -> Vec<(String, Ty)> {
+        let element = element_of(&self.infer(&comprehension.iter, scope, locals));
+        let mut bound = locals.to_vec();
+        match (&comprehension.target[..], element) {
+            ([name], element) => bound.push((name.clone(), element)),
+            (names, Ty::Tuple(items)) => {
+                bound.extend(names.iter().cloned().zip(items.into_iter().chain(std::iter::repeat(Ty::Unknown))))
+            }
+            (names, _) => bound.extend(names.iter().map(|name| (name.clone(), Ty::Unknown))),
+        }
+        bound
+    }
+
+    /// One line describing a symbol, as hover shows it: a signature or a typed name
+    pub fn signature(&self, symbol: usize) -> String {
+        let symbol_ref = &self.symbols[symbol];
+        let typed = |label: &str| match self.symbol_type(symbol) {
+            Ty::Unknown => format!("{}{}", label, symbol_ref.name),
+            ty => format!("{}{}: {}", label, symbol_ref.name, ty),
+        };
+        match (&symbol_ref.origin, symbol_ref.kind) {
+            (Origin::Def { params, is_async, .. }, _) => {
+                let returns = match self.return_type(symbol) {
+                    Ty::Unknown => String::new(),
+                    ty => format!(" -> {}", ty),
+                };
+                let prefix = if *is_async { "async def" } else { "def" };
+                format!("{} {}({}){}", prefix, symbol_ref.name, params.join(", "), returns)
+            }
+            (Origin::Class { bases, .. }, _) if bases.is_empty() => format!("class {}", symbol_ref.name),
+            (Origin::Class { bases, .. }, _) => {
+                let bases: Vec<String> = bases.iter().map(expression_text).collect();
+                format!("class {}({})", symbol_ref.name, bases.join(", "))
+            }
+            (Origin::Import { module, name: None }, _) if *module == symbol_ref.name => format!("import {}", module),
+            (Origin::Import { module, name: None }, _) => format!("import {} as {}", module, symbol_ref.name),
+            (Origin::Import { module, name: Some(name) }, _) if *name == symbol_ref.name => {
+                format!("from {} import {}", module, name)
+            }
+            (Origin::Import { module, name: Some(name) }, _) => {
+                format!("from {} import {} as {}", module, name, symbol_ref.name)
+            }
+            (_, SymbolKind::Parameter) => typed("(parameter) "),
+            _ if self.scopes[symbol_ref.scope].kind == ScopeKind::Class => typed("(attribute) "),
+            _ => typed(""),
+        }
+    }
+
+    /// A function's or class's docstring
+    pub fn documentation(&self, symbol: usize) -> Option<&str> {
+        match &self.symbols[symbol].origin {
+            Origin::Def { body, .. } | Origin::Class { body, .. } => docstring(body),
+            _ => None,
+        }
+    }
+
+    /// The functions and classes defined directly in a scope, in source order
+    pub fn definitions(&self, scope: usize) -> impl Iterator<Item = usize> + '_ {
+        self.scopes[scope]
+            .symbols
+            .iter()
+            .copied()
+            .filter(|&symbol| matches!(self.symbols[symbol].kind, SymbolKind::Function | SymbolKind::Class))
+    }
+}
+
+/// Whether a name is one of the builtins code generation maps to the runtime
+pub fn is_builtin(name: &str) -> bool {
+    BUILTIN_FUNCTIONS.contains(&name)
+}
+
+// Collects the symbols of the AST into scopes, placing each on a word
+struct Builder<'a> {
+    words: &'a [Word],
+    // The name words of `def` and `class` headers, and the lines of their blocks
+    headers: Vec<usize>,
+    blocks: Vec<(u32, u32)>,
+    next_header: usize,
+    scopes: Vec<Scope>,
+    symbols: Vec<Symbol>,
+}
+
+impl Builder<'_> {
+    fn walk(&mut self, nodes: &[AstNode], scope: usize, context: usize) {
+        for AstNode::Statement(statement) in nodes {
+            self.statement(statement, scope, context);
+        }
+    }
+
+    // `scope` holds the names a statement binds; `context` is where it runs,
+    // which differs only for `self.x = ...` in a method
+    fn statement(&mut self, statement: &Statement, scope: usize, context: usize) {
+        match statement {
+            Statement::FunctionDef { name, params, body, decorators, is_async } => {
+                let header = self.take_header(name);
+                let function = self.add(name, SymbolKind::Function, header, scope, context, Origin::Def {
+                    params: params.clone(),
+                    body: body.clone(),
+                    is_async: *is_async,
+                });
+                let inner = self.open_scope(ScopeKind::Function, scope, header, function);
+                let method_of = self.scopes[scope].kind == ScopeKind::Class;
+                let is_static = decorators.iter().any(|decorator| {
+                    matches!(decorator, Decorator::Simple(name) if name == "staticmethod" || name == "classmethod")
+                });
+                for (index, param) in params.iter().enumerate() {
+                    let word = header.and_then(|header| {
+                        (header + 1..self.words.len())
+                            .find(|&i| self.words[i].text == *param && !self.words[i].after_dot)
+                    });
+                    let origin = match self.scopes[scope].owner {
+                        Some(class) if method_of && index == 0 && !is_static => Origin::SelfOf(class),
+                        _ => Origin::Unknown,
+                    };
+                    self.add(param, SymbolKind::Parameter, word, inner, inner, origin);
+                }
+                self.walk(body, inner, inner);
+            }
+            Statement::ClassDef { name, bases, body, .. } => {
+                let header = self.take_header(name);
+                let class = self.add(name, SymbolKind::Class, header, scope, context, Origin::Class {
+                    bases: bases.clone(),
+                    body: body.clone(),
+                });
+                let inner = self.open_scope(ScopeKind::Class, scope, header, class);
+                self.walk(body, inner, inner);
+            }
+            Statement::Assignment { target, value, .. } => {
+                self.bind_target(target, scope, Origin::Value((**value).clone()))
+            }
+            Statement::ChainedAssignment { targets, value } => {
+                for target in targets {
+                    self.bind_target(target, scope, Origin::Value((**value).clone()));
+                }
+            }
+            Statement::AnnotatedAssignment { target, annotation, .. } => {
+                self.bind_target(target, scope, Origin::Annotation((**annotation).clone()))
+            }
+            Statement::For { vars, iterable, body, else_body, .. } => {
+                for (index, var) in vars.iter().enumerate() {
+                    let origin = if vars.len() == 1 {
+                        Origin::Element((**iterable).clone())
+                    } else {
+                        Origin::ElementItem((**iterable).clone(), index)
+                    };
+                    self.bind(var, SymbolKind::Variable, scope, origin);
+                }
+                self.walk(body, scope, context);
+                self.walk(else_body.as_deref().unwrap_or_default(), scope, context);
+            }
+            Statement::If { then_body, elifs, else_body, .. } => {
+                self.walk(then_body, scope, context);
+                for (_, body) in elifs {
+                    self.walk(body, scope, context);
+                }
+                self.walk(else_body.as_deref().unwrap_or_default(), scope, context);
+            }
+            Statement::While { body, else_body, .. } => {
+                self.walk(body, scope, context);
+                self.walk(else_body.as_deref().unwrap_or_default(), scope, context);
+            }
+            Statement::With { items, body, .. } => {
+                for item in items {
+                    if let Some(name) = &item.optional_vars {
+                        self.bind(name, SymbolKind::Variable, scope, Origin::Value(item.context_expr.clone()));
+                    }
+                }
+                self.walk(body, scope, context);
+            }
+            Statement::TryExcept { try_body, excepts, else_body, finally_body } => {
+                self.walk(try_body, scope, context);
+                for handler in excepts {
+                    if let Some(name) = &handler.name {
+                        let origin = handler.exception_type.clone().map_or(Origin::Unknown, Origin::Instance);
+                        self.bind(name, SymbolKind::Variable, scope, origin);
+                    }
+                    self.walk(&handler.body, scope, context);
+                }
+                self.walk(else_body.as_deref().unwrap_or_default(), scope, context);
+                self.walk(finally_body.as_deref().unwrap_or_default(), scope, context);
+            }
+            Statement::Import { module, alias } => {
+                let (name, module) = match alias {
+                    Some(alias) => (alias.as_str(), module.as_str()),
+                    // `import a.b` binds `a`
+                    None => {
+                        let first = module.split('.').next().unwrap_or(module);
+                        (first, first)
+                    }
+                };
+                self.bind(name, SymbolKind::Module, scope, Origin::Import { module: module.to_string(), name: None });
+            }
+            Statement::ImportFrom { module, names } => {
+                for (name, alias) in names {
+                    let origin = Origin::Import { module: module.clone(), name: Some(name.clone()) };
+                    self.bind(alias.as_ref().unwrap_or(name), SymbolKind::Variable, scope, origin);
+                }
+            }
+            Statement::Match { cases, .. } => {
+                for case in cases {
+                    let mut names = Vec::new();
+                    pattern_names(&case.pattern, &mut names);
+                    for name in names {
+                        self.bind(name, SymbolKind::Variable, scope, Origin::Unknown);
+                    }
+                    self.walk(&case.body, scope, context);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn bind_target(&mut self, target: &Expression, scope: usize, origin: Origin) {
+        match target {
+            Expression::Identifier(name) => self.bind(name, SymbolKind::Variable, scope, origin),
+            Expression::TupleLiteral(items) | Expression::ListLiteral(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    let origin = match &origin {
+                        Origin::Value(value) => Origin::Item(value.clone(), index),
+                        _ => Origin::Unknown,
+                    };
+                    self.bind_target(item, scope, origin);
+                }
+            }
+            Expression::AttributeAccess { object, attr } => {
+                if matches!(&**object, Expression::Identifier(name) if name == "self") {
+                    self.bind_member(attr, scope, origin)
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // A name bound by an assignment, import or loop in a scope, unless the scope already has it
+    fn bind(&mut self, name: &str, kind: SymbolKind, scope: usize, origin: Origin) {
+        if self.scopes[scope].symbols.iter().any(|&symbol| self.symbols[symbol].name == name) {
+            return;
+        }
+        let word = (0..self.words.len()).find(|&i| {
+            let word = &self.words[i];
+            word.text == name && !word.after_dot && self.directly_in(scope, word.line)
+        });
+        self.add(name, kind, word, scope, scope, origin);
+    }
+
+    // `self.name = ...` in a method: an attribute of the method's class
+    fn bind_member(&mut self, name: &str, method: usize, origin: Origin) {
+        let parent = self.scopes[method].parent;
+        let Some(class_scope) = parent.filter(|&parent| self.scopes[parent].kind == ScopeKind::Class) else {
+            return;
+        };
+        if self.scopes[class_scope].symbols.iter().any(|&symbol| self.symbols[symbol].name == name) {
+            return;
+        }
+        let (first, last) = (self.scopes[class_scope].first_line, self.scopes[class_scope].last_line);
+        let word = (0..self.words.len()).find(|&i| {
+            let word = &self.words[i];
+            word.text == name
+                && (first..=last).contains(&word.line)
+                && word.object.is_some_and(|object| self.words[object].text == "self")
+        });
+        self.add(name, SymbolKind::Variable, word, class_scope, method, origin);
+    }
+
+    // Whether a line belongs to a scope and not to a function or class nested in it
+    fn directly_in(&self, scope: usize, line: u32) -> bool {
+        let scope = &self.scopes[scope];
+        let nested = |&(header, end): &(u32, u32)| {
+            scope.header.is_none_or(|own| header > own) && header <= line && line <= end
+        };
+        scope.first_line <= line && line <= scope.last_line && !self.blocks.iter().any(nested)
+    }
+
+    // The name word of the next `def` or `class` header called `name`
+    fn take_header(&mut self, name: &str) -> Option<usize> {
+        let found = (self.next_header..self.headers.len()).find(|&i| self.words[self.headers[i]].text == name)?;
+        self.next_header = found + 1;
+        Some(self.headers[found])
+    }
+
+    fn open_scope(&mut self, kind: ScopeKind, parent: usize, header: Option<usize>, owner: usize) -> usize {
+        let (first_line, last_line) = match header {
+            Some(header) => {
+                let line = self.words[header].line;
+                let index = self.headers.iter().position(|&name| name == header).unwrap_or(0);
+                (line, self.blocks[index].1)
+            }
+            None => (self.scopes[parent].first_line, self.scopes[parent].last_line),
+        };
+        self.scopes.push(Scope {
+            kind,
+            parent: Some(parent),
+            header: header.map(|header| self.words[header].line),
+            first_line,
+            last_line,
+            symbols: Vec::new(),
+            owner: Some(owner),
+        });
+        let scope = self.scopes.len() - 1;
+        self.symbols[owner].body = Some(scope);
+        scope
+    }
+
+    fn add(
+        &mut self,
+        name: &str,
+        kind: SymbolKind,
+        word: Option<usize>,
+        scope: usize,
+        context: usize,
+        origin: Origin,
+    ) -> usize {
+        self.symbols.push(Symbol { name: name.to_string(), kind, word, scope, body: None, context, origin });
+        let symbol = self.symbols.len() - 1;
+        self.scopes[scope].symbols.push(symbol);
+        symbol
+    }
+}
+
+// The names a `case` pattern captures
+fn pattern_names<'a>(pattern: &'a Pattern, names: &mut Vec<&'a str>) {
+    match pattern {
+        Pattern::Capture(name) | Pattern::Star(Some(name)) => names.push(name),
+        Pattern::As(inner, name) => {
+            pattern_names(inner, names);
+            names.push(name);
+        }
+        Pattern::Sequence(items) | Pattern::Or(items) => items.iter().for_each(|item| pattern_names(item, names)),
+        Pattern::Mapping { entries, rest } => {
+            entries.iter().for_each(|(_, item)| pattern_names(item, names));
+            names.extend(rest.as_deref());
+        }
+        Pattern::Class { positional, keywords, .. } => {
+            positional.iter().for_each(|item| pattern_names(item, names));
+            keywords.iter().for_each(|(_, item)| pattern_names(item, names));
+        }
+        Pattern::Literal(_) | Pattern::Value(_) | Pattern::Wildcard | Pattern::Star(None) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"import shapes as sh
+
+class Counter:
+    """Counts things"""
+    def __init__(self, start):
+        self.count = start
+
+    def step(self):
+        self.count += 1
+        return self.count
+
+class Limited(Counter):
+    def reset(self):
+        self.count = 0
+
+def total(items):
+    return sum(items)
+
+nums = [1, 2, 3]
+c = Limited(0)
+c.step()
+area = sh.area(2, 3)
+label = "total: " + str(total(nums))
+"#;
+
+    // The `nth` word spelled `text`, counting from 0
+    fn word(analysis: &Analysis, text: &str, nth: usize) -> usize {
+        let mut found = analysis.words.iter().enumerate().filter(|(_, word)| word.text == text);
+        found.nth(nth).map(|(index, _)| index).unwrap_or_else(|| panic!("no word {} #{}", text, nth))
+    }
+
+    fn symbol(analysis: &Analysis, name: &str) -> usize {
+        analysis.symbols.iter().position(|symbol| symbol.name == name).unwrap_or_else(|| panic!("no symbol {}", name))
+    }
+
+    fn names(analysis: &Analysis, symbols: &[usize]) -> Vec<String> {
+        symbols.iter().map(|&symbol| analysis.symbols[symbol].name.clone()).collect()
+    }
+
+    #[test]
+    fn test_words_skip_strings_and_comments() {
+        let analysis = Analysis::new("s = \"def x\"  # total\nt = 'é𝄞'; u = t.upper()\n");
+        let texts: Vec<&str> = analysis.words.iter().map(|word| word.text.as_str()).collect();
+        assert_eq!(texts, vec!["s", "t", "u", "t", "upper"]);
+        // Columns count UTF-16 units: 𝄞 takes two
+        let u = &analysis.words[2];
+        assert_eq!((u.line, u.start, u.end), (1, 11, 12));
+        let upper = &analysis.words[4];
+        assert!(upper.after_dot && upper.object == Some(3));
+        assert_eq!(analysis.word_at(1, 12), Some(2));
+        assert_eq!(analysis.word_at(1, 13), None);
+    }
+
+    #[test]
+    fn test_definitions_resolve_through_scopes_and_classes() {
+        let analysis = Analysis::new(SOURCE);
+        assert!(analysis.error.is_none());
+        let total = symbol(&analysis, "total");
+        assert_eq!(analysis.resolve(word(&analysis, "total", 1)), Some(Target::Local(total)));
+        assert_eq!(analysis.symbols[total].word, Some(word(&analysis, "total", 0)));
+
+        // `c.step()` finds the method Limited inherits, `self.count` the attribute
+        let step = symbol(&analysis, "step");
+        assert_eq!(analysis.resolve(word(&analysis, "step", 1)), Some(Target::Local(step)));
+        let count = analysis.resolve(word(&analysis, "count", 2));
+        assert_eq!(count, analysis.resolve(word(&analysis, "count", 0)));
+        assert!(matches!(count, Some(Target::Local(member)) if analysis.symbols[member].name == "count"));
+
+        // Parameters are local to their function
+        let items = analysis.resolve(word(&analysis, "items", 1));
+        assert_eq!(items, Some(Target::Local(symbol(&analysis, "items"))));
+        assert_eq!(analysis.lookup("items", 0), None);
+
+        assert_eq!(analysis.resolve(word(&analysis, "shapes", 0)), Some(Target::Module("shapes".to_string())));
+        let area = Target::Member { module: "shapes".to_string(), name: "area".to_string() };
+        assert_eq!(analysis.resolve(word(&analysis, "area", 1)), Some(area));
+        assert_eq!(analysis.import_of(symbol(&analysis, "sh")), Some(("shapes", None)));
+    }
+
+    #[test]
+    fn test_hover_signatures_and_inferred_types() {
+        let analysis = Analysis::new(SOURCE);
+        let signature = |name| analysis.signature(symbol(&analysis, name));
+        assert_eq!(signature("total"), "def total(items) -> int");
+        assert_eq!(signature("nums"), "nums: list[int]");
+        assert_eq!(signature("c"), "c: Limited");
+        assert_eq!(signature("label"), "label: str");
+        assert_eq!(signature("Limited"), "class Limited(Counter)");
+        assert_eq!(signature("sh"), "import shapes as sh");
+        assert_eq!(signature("self"), "(parameter) self: Counter");
+        assert_eq!(analysis.documentation(symbol(&analysis, "Counter")), Some("Counts things"));
+        assert_eq!(analysis.documentation(symbol(&analysis, "total")), None);
+    }
+
+    #[test]
+    fn test_completion_candidates() {
+        let analysis = Analysis::new(SOURCE);
+        // Inside `step`: its parameter, then the module's names, but not the class body's
+        let visible = names(&analysis, &analysis.visible(8));
+        assert_eq!(visible[0], "self");
+        for name in ["sh", "Counter", "Limited", "total", "nums", "c", "label"] {
+            assert!(visible.iter().any(|visible| visible == name), "{} is not visible", name);
+        }
+        assert!(!visible.iter().any(|name| name == "__init__" || name == "items"));
+
+        let limited = symbol(&analysis, "Limited");
+        let mut members = names(&analysis, &analysis.class_members(limited));
+        members.sort();
+        assert_eq!(members, vec!["__init__", "count", "reset", "step"]);
+        assert_eq!(analysis.namespace_of_path(&["c"], 21), Some(Namespace::Class(limited)));
+        assert_eq!(analysis.namespace_of_path(&["sh"], 21), Some(Namespace::Module("shapes".to_string())));
+        assert_eq!(analysis.namespace_of_path(&["nums"], 21), None);
+    }
+
+    #[test]
+    fn test_source_that_does_not_parse_still_has_words() {
+        let analysis = Analysis::new("def broken(:\n    return 1\n");
+        let error = analysis.error.as_ref().expect("the header is incomplete");
+        assert_eq!(error.line, 1);
+        assert!(analysis.symbols.is_empty());
+        assert_eq!(analysis.words.iter().map(|word| word.text.as_str()).collect::<Vec<_>>(), vec!["def", "broken", "return"]);
+    }
+}
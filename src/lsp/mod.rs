@@ -0,0 +1,731 @@
+// `eppx lsp`: a language server speaking LSP over stdin and stdout. Open
+// documents are parsed on every change and reported with their syntax errors,
+// or with code generation's errors once they parse; navigation, hover and
+// completion work from the symbols of the last version that parsed. Imports of
+// project modules and packages resolve to their files as `eppx build` finds them
+
+mod analysis;
+
+use std::collections::HashMap;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, References, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse, Diagnostic,
+    DiagnosticSeverity, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse, Documentation,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location,
+    MarkupContent, MarkupKind, OneOf, Position, PublishDiagnosticsParams, Range, ReferenceParams, ServerCapabilities,
+    SymbolKind, TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+
+use crate::codegen::{generate_program_cpp, CodegenOptions, ProjectModule, BUILTIN_FUNCTIONS};
+use crate::parser::{parse_eppx_string, SyntaxError};
+use analysis::{Analysis, Namespace, ScopeKind, Target};
+
+pub fn run() -> Result<(), String> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = serde_json::to_value(ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![".".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        ..ServerCapabilities::default()
+    })
+    .map_err(|e| e.to_string())?;
+    connection.initialize(capabilities).map_err(|e| e.to_string())?;
+    let mut server = Server { connection, documents: HashMap::new() };
+    server.serve()?;
+    // The writer thread stops once the connection is gone
+    drop(server);
+    io_threads.join().map_err(|e| e.to_string())
+}
+
+struct Document {
+    text: String,
+    // The analysis of the latest version that parsed, or of this one if none has
+    analysis: Rc<Analysis>,
+    // Why this version does not parse
+    error: Option<SyntaxError>,
+}
+
+// Where a name is defined: a symbol of a file, or the module file itself
+struct Definition {
+    uri: Url,
+    analysis: Rc<Analysis>,
+    symbol: Option<usize>,
+}
+
+struct Server {
+    connection: Connection,
+    documents: HashMap<Url, Document>,
+}
+
+impl Server {
+    fn serve(&mut self) -> Result<(), String> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request).map_err(|e| e.to_string())? {
+                        return Ok(());
+                    }
+                    let response = match request.method.as_str() {
+                        GotoDefinition::METHOD => self.handle::<GotoDefinition>(request, Self::definition),
+                        References::METHOD => self.handle::<References>(request, Self::references),
+                        HoverRequest::METHOD => self.handle::<HoverRequest>(request, Self::hover),
+                        DocumentSymbolRequest::METHOD => {
+                            self.handle::<DocumentSymbolRequest>(request, Self::document_symbols)
+                        }
+                        Completion::METHOD => self.handle::<Completion>(request, Self::completion),
+                        method => {
+                            let message = format!("unknown method {}", method);
+                            Response::new_err(request.id, ErrorCode::MethodNotFound as i32, message)
+                        }
+                    };
+                    self.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => self.notify(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle<R>(&mut self, request: Request, handler: fn(&mut Self, R::Params) -> R::Result) -> Response
+    where
+        R: lsp_types::request::Request,
+    {
+        match serde_json::from_value(request.params) {
+            Ok(params) => Response::new_ok(request.id, handler(self, params)),
+            Err(e) => Response::new_err(request.id, ErrorCode::InvalidParams as i32, e.to_string()),
+        }
+    }
+
+    fn notify(&mut self, notification: Notification) -> Result<(), String> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams = parse_params(notification)?;
+                self.update(params.text_document.uri, params.text_document.text)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams = parse_params(notification)?;
+                // Full sync: the last change is the whole document
+                match params.content_changes.into_iter().last() {
+                    Some(change) => self.update(params.text_document.uri, change.text),
+                    None => Ok(()),
+                }
+            }
+            DidSaveTextDocument::METHOD => {
+                // A saved module can change what the documents importing it compile to
+                let uris: Vec<Url> = self.documents.keys().cloned().collect();
+                uris.into_iter().try_for_each(|uri| self.publish(&uri))
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams = parse_params(notification)?;
+                self.documents.remove(&params.text_document.uri);
+                self.send_diagnostics(params.text_document.uri, Vec::new())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn update(&mut self, uri: Url, text: String) -> Result<(), String> {
+        let mut analysis = Rc::new(Analysis::new(&text));
+        let error = analysis.error.clone();
+        if let Some(previous) = self.documents.get(&uri).filter(|_| error.is_some()) {
+            analysis = previous.analysis.clone();
+        }
+        self.documents.insert(uri.clone(), Document { text, analysis, error });
+        self.publish(&uri)
+    }
+
+    fn publish(&self, uri: &Url) -> Result<(), String> {
+        let Some(document) = self.documents.get(uri) else {
+            return Ok(());
+        };
+        let diagnostics = match &document.error {
+            Some(error) => {
+                let line = error.line.saturating_sub(1) as u32;
+                let text = line_text(&document.text, line);
+                let start = utf16_len(&text.chars().take(error.column.saturating_sub(1)).collect::<String>());
+                let word = document.analysis.words.iter().find(|word| word.line == line && word.start == start);
+                let end = word.map_or(start + 1, |word| word.end);
+                vec![diagnostic(range(line, start, end), error.message.clone())]
+            }
+            None => self.compile(uri, &document.text).into_iter().collect(),
+        };
+        self.send_diagnostics(uri.clone(), diagnostics)
+    }
+
+    // Code generation's error for a document that parses, reported on its first line
+    fn compile(&self, uri: &Url, text: &str) -> Option<Diagnostic> {
+        let first_line = range(0, 0, utf16_len(&line_text(text, 0)));
+        let ast = parse_eppx_string(text).ok()?;
+        let mut modules = Vec::new();
+        for (name, path) in module_paths(uri) {
+            let source = self.source(&path)?;
+            match parse_eppx_string(&source) {
+                Ok(ast) => modules.push(ProjectModule { name, ast }),
+                Err(e) => return Some(diagnostic(first_line, format!("Parser error: {}: {}", path.display(), e))),
+            }
+        }
+        let generate = || generate_program_cpp(&ast, &modules, &CodegenOptions::default());
+        match panic::catch_unwind(AssertUnwindSafe(generate)) {
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) => Some(diagnostic(first_line, format!("Codegen error: {}", e))),
+            Err(_) => Some(diagnostic(first_line, "Codegen error: code generation failed".to_string())),
+        }
+    }
+
+    fn send_diagnostics(&self, uri: Url, diagnostics: Vec<Diagnostic>) -> Result<(), String> {
+        let params = PublishDiagnosticsParams { uri, diagnostics, version: None };
+        self.send(Message::Notification(Notification::new(PublishDiagnostics::METHOD.to_string(), params)))
+    }
+
+    fn send(&self, message: Message) -> Result<(), String> {
+        self.connection.sender.send(message).map_err(|e| e.to_string())
+    }
+
+    // A file's text: the editor's if it has the file open, else what is on disk
+    fn source(&self, path: &Path) -> Option<String> {
+        let uri = Url::from_file_path(path).ok()?;
+        match self.documents.get(&uri) {
+            Some(document) => Some(document.text.clone()),
+            None => fs::read_to_string(path).ok(),
+        }
+    }
+
+    fn file_analysis(&self, path: &Path) -> Option<(Url, Rc<Analysis>)> {
+        let uri = Url::from_file_path(path).ok()?;
+        let analysis = self.analysis_of(&uri)?;
+        Some((uri, analysis))
+    }
+
+    // The symbols of an open document, or of the file on disk when it isn't open
+    fn analysis_of(&self, uri: &Url) -> Option<Rc<Analysis>> {
+        match self.documents.get(uri) {
+            Some(document) => Some(document.analysis.clone()),
+            None => Some(Rc::new(Analysis::new(&fs::read_to_string(uri.to_file_path().ok()?).ok()?))),
+        }
+    }
+
+    // The open document and the word at a position in it
+    fn word_at(&self, position: &TextDocumentPositionParams) -> Option<(Url, Rc<Analysis>, usize)> {
+        let uri = position.text_document.uri.clone();
+        let analysis = self.documents.get(&uri)?.analysis.clone();
+        let word = analysis.word_at(position.position.line, position.position.character)?;
+        Some((uri, analysis, word))
+    }
+
+    // What a word in a file refers to, following imports into the modules they name
+    fn definition_of(&self, uri: &Url, analysis: &Rc<Analysis>, word: usize) -> Option<Definition> {
+        let local = |symbol| Definition { uri: uri.clone(), analysis: analysis.clone(), symbol: Some(symbol) };
+        match analysis.resolve(word)? {
+            Target::Local(symbol) => match analysis.import_of(symbol) {
+                Some((module, name)) => self.module_definition(uri, module, name).or_else(|| Some(local(symbol))),
+                None => Some(local(symbol)),
+            },
+            Target::Module(module) => self.module_definition(uri, &module, None),
+            Target::Member { module, name } => self.module_definition(uri, &module, Some(&name)),
+        }
+    }
+
+    // A module the file `from` can import, or a name defined at its top level
+    fn module_definition(&self, from: &Url, module: &str, name: Option<&str>) -> Option<Definition> {
+        let (_, path) = module_paths(from).into_iter().find(|(candidate, _)| candidate == module)?;
+        let (uri, analysis) = self.file_analysis(&path)?;
+        let symbol = match name {
+            Some(name) => Some(analysis.top_level(name)?),
+            None => None,
+        };
+        Some(Definition { uri, analysis, symbol })
+    }
+
+    fn definition(&mut self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let (uri, analysis, word) = self.word_at(&params.text_document_position_params)?;
+        let definition = self.definition_of(&uri, &analysis, word)?;
+        Some(GotoDefinitionResponse::Scalar(location(&definition)))
+    }
+
+    // Uses of a name across the files that can see it: those beside the
+    // document, the modules it imports, and the file defining the name. Open
+    // documents are searched as the editor has them, saved or not, and only
+    // the files that aren't open are read from disk
+    fn references(&mut self, params: ReferenceParams) -> Option<Vec<Location>> {
+        let (uri, analysis, word) = self.word_at(&params.text_document_position)?;
+        let target = self.definition_of(&uri, &analysis, word)?;
+        let declaration = location(&target);
+        let name = match target.symbol {
+            Some(symbol) => target.analysis.symbols[symbol].name.clone(),
+            None => analysis.words[word].text.clone(),
+        };
+
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for path in [&uri, &target.uri].into_iter().filter_map(|uri| uri.to_file_path().ok()) {
+            let siblings = path.parent().and_then(|dir| fs::read_dir(dir).ok()).into_iter().flatten().flatten();
+            let sources = siblings.map(|entry| entry.path());
+            paths.extend(sources.filter(|path| path.extension().is_some_and(|extension| extension == "eppx")));
+            paths.extend(module_paths(&uri).into_iter().map(|(_, path)| path));
+        }
+        paths.sort();
+        paths.dedup_by(|a, b| fs::canonicalize(&*a).ok() == fs::canonicalize(&*b).ok());
+
+        let canonical = |path: PathBuf| fs::canonicalize(&path).unwrap_or(path);
+        let directories: Vec<PathBuf> =
+            [&uri, &target.uri].into_iter().filter_map(|uri| Some(canonical(uri.to_file_path().ok()?.parent()?.to_path_buf()))).collect();
+        let on_disk: Vec<PathBuf> = paths.into_iter().map(canonical).collect();
+        let mut files: Vec<Url> = self
+            .documents
+            .keys()
+            .filter(|open| {
+                **open == uri
+                    || **open == target.uri
+                    || open.to_file_path().is_ok_and(|path| {
+                        let path = canonical(path);
+                        on_disk.contains(&path) || path.parent().is_some_and(|dir| directories.iter().any(|visible| visible == dir))
+                    })
+            })
+            .cloned()
+            .collect();
+        files.sort();
+        let open_paths: Vec<PathBuf> = files.iter().filter_map(|file| file.to_file_path().ok()).map(canonical).collect();
+        files.extend(on_disk.into_iter().filter(|path| !open_paths.contains(path)).filter_map(|path| Url::from_file_path(path).ok()));
+
+        let mut locations = Vec::new();
+        for (file_uri, file_analysis) in files.into_iter().filter_map(|file| Some((file.clone(), self.analysis_of(&file)?))) {
+            // The names the target has here: its own, and any `import ... as` alias
+            let imports = |symbol| file_analysis.import_of(symbol).map(|(module, imported)| imported.unwrap_or(module));
+            let aliases: Vec<&str> = file_analysis
+                .symbols
+                .iter()
+                .enumerate()
+                .filter(|(index, symbol)| symbol.name != name && imports(*index) == Some(name.as_str()))
+                .map(|(_, symbol)| symbol.name.as_str())
+                .collect();
+            for (index, word) in file_analysis.words.iter().enumerate() {
+                if word.text != name && !aliases.contains(&word.text.as_str()) {
+                    continue;
+                }
+                let Some(definition) = self.definition_of(&file_uri, &file_analysis, index) else {
+                    continue;
+                };
+                if definition.uri == target.uri && definition.symbol == target.symbol {
+                    let found = Location::new(file_uri.clone(), range(word.line, word.start, word.end));
+                    if params.context.include_declaration || found != declaration {
+                        locations.push(found);
+                    }
+                }
+            }
+        }
+        Some(locations)
+    }
+
+    fn hover(&mut self, params: HoverParams) -> Option<Hover> {
+        let (uri, analysis, word) = self.word_at(&params.text_document_position_params)?;
+        let word_ref = &analysis.words[word];
+        let (signature, documentation) = match self.definition_of(&uri, &analysis, word) {
+            Some(Definition { analysis: defining, symbol: Some(symbol), .. }) => {
+                (defining.signature(symbol), defining.documentation(symbol).map(str::to_string))
+            }
+            // A module file
+            Some(Definition { uri: module, .. }) => {
+                let signature = match analysis.resolve(word)? {
+                    Target::Local(symbol) => analysis.signature(symbol),
+                    _ => format!("import {}", word_ref.text),
+                };
+                (signature, module.to_file_path().ok().map(|path| path.display().to_string()))
+            }
+            None if !word_ref.after_dot && analysis::is_builtin(&word_ref.text) => {
+                (format!("(builtin) {}", word_ref.text), None)
+            }
+            None => return None,
+        };
+        let mut value = format!("```eppx\n{}\n```", signature);
+        if let Some(documentation) = documentation {
+            value.push_str("\n\n");
+            value.push_str(&documentation);
+        }
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value }),
+            range: Some(range(word_ref.line, word_ref.start, word_ref.end)),
+        })
+    }
+
+    fn document_symbols(&mut self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let document = self.documents.get(&params.text_document.uri)?;
+        Some(DocumentSymbolResponse::Nested(outline(&document.analysis, &document.text, 0)))
+    }
+
+    fn completion(&mut self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let document = self.documents.get(&position.text_document.uri)?;
+        let analysis = &document.analysis;
+        let line = position.position.line;
+        let text = line_text(&document.text, line);
+        let before: String = text.chars().scan(0, |column, c| {
+            *column += c.len_utf16() as u32;
+            (*column <= position.position.character).then_some(c)
+        }).collect();
+        // The dotted name being typed, such as `self.it` or `shapes.`
+        let dotted: Vec<char> =
+            before.chars().rev().take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '.').collect();
+        let dotted: String = dotted.into_iter().rev().collect();
+        let path: Vec<&str> = dotted.split('.').collect();
+
+        let items = if path.len() > 1 {
+            match analysis.namespace_of_path(&path[..path.len() - 1], line) {
+                Some(Namespace::Class(class)) => {
+                    analysis.class_members(class).into_iter().map(|member| completion_item(analysis, member)).collect()
+                }
+                Some(Namespace::Module(module)) => {
+                    let Some(definition) = self.module_definition(&position.text_document.uri, &module, None) else {
+                        return Some(CompletionResponse::Array(Vec::new()));
+                    };
+                    let module = &definition.analysis;
+                    module.scopes[0].symbols.iter().map(|&symbol| completion_item(module, symbol)).collect()
+                }
+                None => Vec::new(),
+            }
+        } else {
+            let mut items: Vec<CompletionItem> =
+                analysis.visible(line).into_iter().map(|symbol| completion_item(analysis, symbol)).collect();
+            // Builtins that no name in scope shadows
+            let builtins: Vec<CompletionItem> = BUILTIN_FUNCTIONS
+                .iter()
+                .filter(|name| !items.iter().any(|item| item.label == **name))
+                .map(|name| CompletionItem {
+                    label: name.to_string(),
+                    kind: Some(CompletionItemKind::FUNCTION),
+                    detail: Some("builtin".to_string()),
+                    ..CompletionItem::default()
+                })
+                .collect();
+            items.extend(builtins);
+            items
+        };
+        Some(CompletionResponse::Array(items))
+    }
+}
+
+fn parse_params<P: serde::de::DeserializeOwned>(notification: Notification) -> Result<P, String> {
+    serde_json::from_value(notification.params).map_err(|e| format!("{}: {}", notification.method, e))
+}
+
+// The modules a file can import, by name
+fn module_paths(uri: &Url) -> Vec<(String, PathBuf)> {
+    uri.to_file_path().ok().and_then(|path| crate::cli::importable_modules(&path).ok()).unwrap_or_default()
+}
+
+fn location(definition: &Definition) -> Location {
+    let word = definition.symbol.and_then(|symbol| definition.analysis.symbols[symbol].word);
+    let range = match word.map(|word| &definition.analysis.words[word]) {
+        Some(word) => range(word.line, word.start, word.end),
+        None => range(0, 0, 0),
+    };
+    Location::new(definition.uri.clone(), range)
+}
+
+// The functions and classes of a scope, with their methods and nested definitions
+fn outline(analysis: &Analysis, text: &str, scope: usize) -> Vec<DocumentSymbol> {
+    analysis
+        .definitions(scope)
+        .filter_map(|symbol| {
+            let symbol_ref = &analysis.symbols[symbol];
+            let word = &analysis.words[symbol_ref.word?];
+            let body = symbol_ref.body?;
+            let last_line = analysis.scopes[body].last_line;
+            let line_end = utf16_len(&line_text(text, last_line));
+            let kind = match symbol_ref.kind {
+                analysis::SymbolKind::Class => SymbolKind::CLASS,
+                _ if analysis.scopes[scope].kind == ScopeKind::Class => SymbolKind::METHOD,
+                _ => SymbolKind::FUNCTION,
+            };
+            #[allow(deprecated)] // `deprecated` is a required field, superseded by `tags`
+            Some(DocumentSymbol {
+                name: symbol_ref.name.clone(),
+                detail: Some(analysis.signature(symbol)),
+                kind,
+                tags: None,
+                deprecated: None,
+                range: range_between(analysis.scopes[body].first_line, 0, last_line, line_end),
+                selection_range: range(word.line, word.start, word.end),
+                children: Some(outline(analysis, text, body)),
+            })
+        })
+        .collect()
+}
+
+fn completion_item(analysis: &Analysis, symbol: usize) -> CompletionItem {
+    let symbol_ref = &analysis.symbols[symbol];
+    let in_class = analysis.scopes[symbol_ref.scope].kind == ScopeKind::Class;
+    let kind = match symbol_ref.kind {
+        analysis::SymbolKind::Function if in_class => CompletionItemKind::METHOD,
+        analysis::SymbolKind::Function => CompletionItemKind::FUNCTION,
+        analysis::SymbolKind::Class => CompletionItemKind::CLASS,
+        analysis::SymbolKind::Module => CompletionItemKind::MODULE,
+        _ if in_class => CompletionItemKind::FIELD,
+        _ => CompletionItemKind::VARIABLE,
+    };
+    CompletionItem {
+        label: symbol_ref.name.clone(),
+        kind: Some(kind),
+        detail: Some(analysis.signature(symbol)),
+        documentation: analysis.documentation(symbol).map(|text| Documentation::String(text.to_string())),
+        ..CompletionItem::default()
+    }
+}
+
+fn diagnostic(range: Range, message: String) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("eppx".to_string()),
+        message,
+        ..Diagnostic::default()
+    }
+}
+
+fn range(line: u32, start: u32, end: u32) -> Range {
+    range_between(line, start, line, end)
+}
+
+fn range_between(start_line: u32, start: u32, end_line: u32, end: u32) -> Range {
+    Range::new(Position::new(start_line, start), Position::new(end_line, end))
+}
+
+fn line_text(text: &str, line: u32) -> String {
+    text.split('\n').nth(line as usize).unwrap_or("").trim_end_matches('\r').to_string()
+}
+
+fn utf16_len(text: &str) -> u32 {
+    text.chars().map(|c| c.len_utf16() as u32).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codon::config::ConfigManager;
+    use lsp_server::RequestId;
+    use serde_json::{json, Value};
+
+    // Runs a server on `messages` followed by a shutdown, and returns what it sent back
+    fn session(messages: Vec<Message>) -> Vec<Message> {
+        let (connection, client) = Connection::memory();
+        for message in messages {
+            client.sender.send(message).unwrap();
+        }
+        client.sender.send(Message::Request(Request::new(RequestId::from(0), "shutdown".to_string(), Value::Null))).unwrap();
+        client.sender.send(Message::Notification(Notification::new("exit".to_string(), Value::Null))).unwrap();
+        Server { connection, documents: HashMap::new() }.serve().unwrap();
+        client.receiver.try_iter().collect()
+    }
+
+    fn open(uri: &Url, text: &str) -> Message {
+        let document = lsp_types::TextDocumentItem::new(uri.clone(), "eppx".to_string(), 1, text.to_string());
+        let params = lsp_types::DidOpenTextDocumentParams { text_document: document };
+        Message::Notification(Notification::new(DidOpenTextDocument::METHOD.to_string(), params))
+    }
+
+    fn change(uri: &Url, text: &str) -> Message {
+        let params = json!({"textDocument": {"uri": uri, "version": 2}, "contentChanges": [{"text": text}]});
+        Message::Notification(Notification::new(DidChangeTextDocument::METHOD.to_string(), params))
+    }
+
+    fn request(id: i32, method: &str, uri: &Url, line: u32, character: u32) -> Message {
+        let mut params = json!({"textDocument": {"uri": uri}, "position": {"line": line, "character": character}});
+        if method == References::METHOD {
+            params["context"] = json!({"includeDeclaration": true});
+        }
+        Message::Request(Request::new(RequestId::from(id), method.to_string(), params))
+    }
+
+    fn response(messages: &[Message], id: i32) -> &Response {
+        messages
+            .iter()
+            .find_map(|message| match message {
+                Message::Response(response) if response.id == RequestId::from(id) => Some(response),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("no response to request {}", id))
+    }
+
+    fn result(messages: &[Message], id: i32) -> Value {
+        response(messages, id).result.clone().expect("the request succeeded")
+    }
+
+    // The diagnostics published for each version of documents, in order
+    fn diagnostics(messages: &[Message]) -> Vec<Vec<Diagnostic>> {
+        messages
+            .iter()
+            .filter_map(|message| match message {
+                Message::Notification(notification) if notification.method == PublishDiagnostics::METHOD => {
+                    let params: PublishDiagnosticsParams = serde_json::from_value(notification.params.clone()).unwrap();
+                    Some(params.diagnostics)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn labels(items: &Value) -> Vec<&str> {
+        items.as_array().unwrap().iter().map(|item| item["label"].as_str().unwrap()).collect()
+    }
+
+    // A project with src/shapes.eppx and src/main.eppx, which imports it
+    fn project() -> (tempfile::TempDir, Url, Url) {
+        let dir = tempfile::tempdir().unwrap();
+        ConfigManager::new().unwrap().create_new_project(dir.path(), "app", None).unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("shapes.eppx"), "def area(w, h):\n    \"\"\"Area of a rectangle\"\"\"\n    return w * h\n").unwrap();
+        fs::write(src.join("main.eppx"), MAIN).unwrap();
+        let shapes = Url::from_file_path(fs::canonicalize(src.join("shapes.eppx")).unwrap()).unwrap();
+        let main = Url::from_file_path(fs::canonicalize(src.join("main.eppx")).unwrap()).unwrap();
+        (dir, shapes, main)
+    }
+
+    const MAIN: &str = "import shapes as sh\n\nprint(sh.area(2, 3))\nsmall = sh.area(1, 1)\n";
+
+    #[test]
+    fn test_messages_are_framed_with_content_length() {
+        let uri = Url::from_file_path(std::env::temp_dir().join("framing.eppx")).unwrap();
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": HoverRequest::METHOD,
+            "params": {"textDocument": {"uri": uri}, "position": {"line": 0, "character": 0}},
+        })
+        .to_string();
+        let framed = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let hover = Message::read(&mut framed.as_bytes()).unwrap().expect("a whole message");
+        assert!(matches!(&hover, Message::Request(request) if request.method == HoverRequest::METHOD));
+
+        let unknown = Message::Request(Request::new(RequestId::from(2), "textDocument/rename".to_string(), json!({})));
+        let invalid = Message::Request(Request::new(RequestId::from(3), HoverRequest::METHOD.to_string(), json!({})));
+        let messages = session(vec![open(&uri, "total = 1\n"), hover, unknown, invalid]);
+
+        let mut written = Vec::new();
+        Message::Response(response(&messages, 1).clone()).write(&mut written).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        let (header, body) = written.split_once("\r\n\r\n").expect("a header ends with a blank line");
+        assert_eq!(header, format!("Content-Length: {}", body.len()));
+        let body: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["id"], 1);
+        assert_eq!(body["result"]["contents"]["value"], "```eppx\ntotal: int\n```");
+
+        assert_eq!(response(&messages, 2).error.as_ref().unwrap().code, ErrorCode::MethodNotFound as i32);
+        assert_eq!(response(&messages, 3).error.as_ref().unwrap().code, ErrorCode::InvalidParams as i32);
+    }
+
+    #[test]
+    fn test_diagnostics_follow_each_version() {
+        let uri = Url::from_file_path(std::env::temp_dir().join("diagnostics.eppx")).unwrap();
+        let messages = session(vec![
+            open(&uri, "x = 1\nprint(x +)\n"),
+            change(&uri, "import nosuchmodule\n"),
+            change(&uri, "x = 1\nprint(x)\n"),
+        ]);
+        let published = diagnostics(&messages);
+        assert_eq!(published.len(), 3);
+
+        let [syntax] = &published[0][..] else { panic!("one syntax error: {:?}", published[0]) };
+        assert_eq!(syntax.range.start.line, 1);
+        assert_eq!(syntax.severity, Some(DiagnosticSeverity::ERROR));
+        let [codegen] = &published[1][..] else { panic!("one codegen error: {:?}", published[1]) };
+        assert!(codegen.message.starts_with("Codegen error:"), "{}", codegen.message);
+        assert_eq!(codegen.range, range(0, 0, 19));
+        assert!(published[2].is_empty());
+    }
+
+    #[test]
+    fn test_definition_references_and_hover_across_modules() {
+        let (_dir, shapes, main) = project();
+        let messages = session(vec![
+            open(&main, MAIN),
+            request(1, GotoDefinition::METHOD, &main, 2, 10),
+            request(2, References::METHOD, &main, 3, 12),
+            request(3, HoverRequest::METHOD, &main, 2, 11),
+            request(4, HoverRequest::METHOD, &main, 2, 2),
+            request(5, GotoDefinition::METHOD, &main, 3, 1),
+        ]);
+        let definition: Location = serde_json::from_value(result(&messages, 1)).unwrap();
+        assert_eq!(definition, Location::new(shapes.clone(), range(0, 4, 8)));
+
+        let mut references: Vec<Location> = serde_json::from_value(result(&messages, 2)).unwrap();
+        references.sort_by_key(|location| (location.uri.to_string(), location.range.start.line));
+        assert_eq!(
+            references,
+            vec![
+                Location::new(main.clone(), range(2, 9, 13)),
+                Location::new(main.clone(), range(3, 11, 15)),
+                Location::new(shapes, range(0, 4, 8)),
+            ]
+        );
+
+        let hover = result(&messages, 3);
+        assert_eq!(hover["contents"]["value"], "```eppx\ndef area(w, h)\n```\n\nArea of a rectangle");
+        assert_eq!(result(&messages, 4)["contents"]["value"], "```eppx\n(builtin) print\n```");
+        let local: Location = serde_json::from_value(result(&messages, 5)).unwrap();
+        assert_eq!(local, Location::new(main, range(3, 0, 5)));
+    }
+
+    #[test]
+    fn test_references_in_unsaved_documents() {
+        // Neither file is on disk: one was never saved, the other isn't even a file
+        let dir = tempfile::tempdir().unwrap();
+        let unsaved = Url::from_file_path(fs::canonicalize(dir.path()).unwrap().join("draft.eppx")).unwrap();
+        let untitled = Url::parse("untitled:Untitled-1").unwrap();
+        let source = "count = 1\nprint(count)\ncount += 2\n";
+        let messages = session(vec![
+            open(&unsaved, source),
+            open(&untitled, source),
+            request(1, References::METHOD, &unsaved, 1, 7),
+            request(2, References::METHOD, &untitled, 2, 0),
+        ]);
+        for (id, uri) in [(1, &unsaved), (2, &untitled)] {
+            let mut references: Vec<Location> = serde_json::from_value(result(&messages, id)).unwrap();
+            references.sort_by_key(|location| location.range.start.line);
+            let expected: Vec<Location> =
+                [range(0, 0, 5), range(1, 6, 11), range(2, 0, 5)].into_iter().map(|range| Location::new(uri.clone(), range)).collect();
+            assert_eq!(references, expected);
+        }
+    }
+
+    #[test]
+    fn test_completion_of_names_and_members() {
+        let uri = Url::from_file_path(std::env::temp_dir().join("completion.eppx")).unwrap();
+        let source = "class Point:\n    def __init__(self):\n        self.x = 0\n\np = Point()\n";
+        let messages = session(vec![
+            open(&uri, source),
+            request(1, Completion::METHOD, &uri, 5, 0),
+            // Typing `p.` breaks the parse; completion uses the version that parsed
+            change(&uri, &format!("{}p.", source)),
+            request(2, Completion::METHOD, &uri, 5, 2),
+        ]);
+        let names = result(&messages, 1);
+        let names = labels(&names);
+        for name in ["p", "Point", "print", "len"] {
+            assert!(names.contains(&name), "{} is missing from {:?}", name, names);
+        }
+        assert!(!names.contains(&"x") && !names.contains(&"self"));
+        let builtin = result(&messages, 1).as_array().unwrap().iter().find(|item| item["label"] == "print").cloned().unwrap();
+        assert_eq!(builtin["detail"], "builtin");
+
+        let members = result(&messages, 2);
+        let mut members = labels(&members);
+        members.sort();
+        assert_eq!(members, vec!["__init__", "x"]);
+    }
+}
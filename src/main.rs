@@ -1,37 +1,62 @@
 mod ast;
 mod cli;
 mod codegen;
+mod formatter;
 mod ir;
+mod lint;
+mod lsp;
 mod parser;
 mod runtime;
 mod codon;
 
 use clap::Parser as ClapParser;
-use cli::{Cli, Commands};
+use cli::{BuildOptions, Cli, Commands, Selection, Verbosity};
 use colored::*;
 use std::process::ExitCode;
 
 // Main entry point for the E++ CLI
 fn main() -> ExitCode {
     let cli_args = Cli::parse();
+    cli::set_verbosity(cli_args.verbosity());
     let result = match cli_args.command {
-        Commands::New { project_name } => cli::handle_new_project(&project_name),
-        Commands::Build { file, output, release, gpu, fast } => {
-            cli::handle_build(&file, output.as_deref(), release, gpu, fast)
+        Commands::New { project_name, template } => cli::handle_new_project(&project_name, &template),
+        Commands::Init { name, template } => cli::handle_init(name.as_deref(), &template),
+        Commands::Build { file, output, release, gpu, fast, optimize, no_cache, emit, member, workspace } => {
+            let options = BuildOptions { release, gpu, optimize, no_cache, emit };
+            let selection = match (&member, workspace) {
+                (_, true) => Selection::Workspace,
+                (Some(member), false) => Selection::Member(member),
+                (None, false) => Selection::Current,
+            };
+            cli::handle_build(file.as_deref(), output.as_deref(), fast, selection, &options)
         }
-        Commands::Run { file, release, interactive, fast } => {
-            cli::handle_run(&file, release, interactive, fast)
+        Commands::Run { file, release, interactive, fast, optimize, no_cache, member, args } => {
+            let options = BuildOptions { release, optimize, no_cache, ..BuildOptions::default() };
+            let selection = member.as_deref().map_or(Selection::Current, Selection::Member);
+            cli::handle_run(file.as_deref(), &args, interactive, fast, selection, &options)
         }
-        Commands::Install { package } => cli::handle_install(&package),
+        Commands::Add { package, path, registry } => cli::handle_add(&package, path.as_deref(), registry.as_deref()),
+        Commands::Remove { package } => cli::handle_remove(&package),
+        Commands::Install { registry } => cli::handle_install(registry.as_deref()),
+        Commands::Update { packages, registry } => cli::handle_update(&packages, registry.as_deref()),
+        Commands::Package => cli::handle_package(),
+        Commands::Publish { registry } => cli::handle_publish(registry.as_deref()),
+        Commands::Registry { command } => cli::handle_registry(&command),
         Commands::Test => cli::handle_test(),
+        Commands::Doctor => cli::handle_doctor(),
+        Commands::Clean => cli::handle_clean(),
+        Commands::Lsp => cli::handle_lsp(),
+        Commands::Fmt { files, check } => cli::handle_fmt(&files, check),
+        Commands::Lint { files } => cli::handle_lint(&files),
     };
     match result {
         Ok(message) => {
-            if !message.is_empty() {
+            if !message.is_empty() && cli::verbosity() > Verbosity::Quiet {
                 println!("{}", message.green());
             }
             ExitCode::SUCCESS
         }
+        Err(cli::CliError::ProgramExit(code)) => ExitCode::from(code),
         Err(e) => {
             eprintln!("{} {}", "Error:".red().bold(), e);
             ExitCode::FAILURE
@@ -1,10 +1,13 @@
 use pest_derive::Parser;
 use pest::Parser;
 use pest::iterators::{Pair, Pairs}; // Added Pairs
-use std::fs;
-use std::path::Path;
 
-use crate::ast::{AstNode, Expression, Statement, BinOp, UnaryOp, AssignmentOperator, Decorator, Argument, Comprehension}; // Added Comprehension
+mod tokenizer;
+
+use tokenizer::TokenizedSource;
+pub use tokenizer::{tokenize_lenient, Token, TokenKind};
+
+use crate::ast::{AstNode, Expression, Statement, BinOp, UnaryOp, AssignmentOperator, Decorator, Argument, Comprehension, MatchCase, Pattern}; // Added Comprehension
 
 #[derive(Parser)]
 #[grammar = "parser/grammar.pest"]
@@ -44,51 +47,6 @@ fn process_escape_sequences(input: &str) -> Result<String, String> {
     Ok(result)
 }
 
-/// Preprocesses Python-style indentation into explicit @INDENT@ and @DEDENT@ tokens for the parser.
-pub fn preprocess_indentation(input: &str) -> String {
-    let mut result = String::new();
-    let mut indent_stack: Vec<usize> = vec![0];
-    
-    for line in input.lines() {
-        let trimmed = line.trim(); // Trim both sides to correctly identify blank lines or comments
-        let is_blank_or_comment = trimmed.is_empty() || trimmed.starts_with('#');
-        
-        if is_blank_or_comment {
-            result.push_str(line);
-            result.push('\n');
-            continue;
-        }
-        
-        let indent = line.chars().take_while(|c| *c == ' ' || *c == '\t').count();
-        let current_indent = *indent_stack.last().unwrap();
-        
-        if indent > current_indent {
-            // Indentation increased
-            indent_stack.push(indent);
-            result.push_str("@INDENT@
-");
-        } else if indent < current_indent {
-            // Indentation decreased - emit DEDENT tokens for each level we're backing out
-            while indent < *indent_stack.last().unwrap() {
-                indent_stack.pop();
-                result.push_str("@DEDENT@
-");
-            }
-        }
-        // If indent == current_indent, we stay at the same level
-        
-        result.push_str(line.trim_start());
-        result.push('\n');
-    }
-    
-    // Close any remaining indents
-    while indent_stack.len() > 1 {
-        indent_stack.pop();
-        result.push_str("@DEDENT@\n");
-    }
-    result
-}
-
 // Renamed from parse_bin_op_recursive
 fn build_recursive_ast_from_binary_expr_rule(
     mut pairs: Pairs<Rule>, // Make it mutable
@@ -107,7 +65,7 @@ fn build_recursive_ast_from_binary_expr_rule(
              let op_str = op_pair_or_direct_op_str_pair.as_str();
              op = direct_map.iter()
                 .find(|(s, _)| *s == op_str)
-                .map(|(_, bin_op)| bin_op.clone())
+                .map(|(_, bin_op)| *bin_op)
                 .ok_or_else(|| format!("Unknown direct operator string: {}", op_str))?;
         } else {
             // Expecting an op_rule here (e.g. logical_or_op, bitwise_and_op)
@@ -120,11 +78,11 @@ fn build_recursive_ast_from_binary_expr_rule(
                 .and_then(|(_, specific_ops)| { // Now find the specific operator string within that rule's possibilities
                     specific_ops.iter().find(|(s, _)| *s == op_str_from_rule)
                 })
-                .map(|(_, bin_op)| bin_op.clone())
+                .map(|(_, bin_op)| *bin_op)
                 .ok_or_else(|| format!("Unknown operator: {} for rule {:?}", op_str_from_rule, op_rule))?;
         }
         
-        let right_pair = pairs.next().ok_or_else(|| format!("Missing right operand for operator"))?;
+        let right_pair = pairs.next().ok_or_else(|| "Missing right operand for operator".to_string())?;
         let right = sub_rule_parser(right_pair)?;
         left = Expression::BinaryOperation {
             left: Box::new(left),
@@ -143,6 +101,20 @@ fn build_ast_from_expression(pair: Pair<Rule>) -> Result<Expression, String> {
             // It always has one inner pair representing the start of the expression hierarchy.
             let inner_expr_pair = pair.into_inner().next().ok_or_else(|| "Expression rule is empty".to_string())?;
             build_ast_from_expression(inner_expr_pair)
+        }
+        Rule::yield_expression => {
+            match pair.into_inner().find(|p| p.as_rule() != Rule::yield_keyword) {
+                None => Ok(Expression::Yield(None)),
+                Some(inner) if inner.as_rule() == Rule::yield_from => {
+                    let iterable_pair = inner.into_inner().find(|p| p.as_rule() != Rule::from_keyword).ok_or_else(|| "yield from missing iterable".to_string())?;
+                    Ok(Expression::YieldFrom(Box::new(build_ast_from_expression(iterable_pair)?)))
+                }
+                Some(inner) => Ok(Expression::Yield(Some(Box::new(build_ast_from_expression(inner)?)))),
+            }
+        }
+        Rule::await_expression => {
+            let awaited_pair = pair.into_inner().find(|p| p.as_rule() != Rule::await_keyword).ok_or_else(|| "await missing operand".to_string())?;
+            Ok(Expression::Await(Box::new(build_ast_from_expression(awaited_pair)?)))
         }        Rule::factor => {
             let mut inner_pairs = pair.into_inner();
             let atom_pair = inner_pairs.next().ok_or_else(|| "Factor rule is empty, expected atom".to_string())?;
@@ -156,6 +128,7 @@ fn build_ast_from_expression(pair: Pair<Rule>) -> Result<Expression, String> {
                 match suffix_pair.as_rule() {
                     Rule::call_suffix => {
                         let mut args = Vec::new();
+                        let mut keywords = Vec::new();
                         // call_suffix_pair.into_inner() gives what's inside the parentheses.
                         for inner_pair in suffix_pair.into_inner() {
                             if inner_pair.as_rule() == Rule::argument_list {
@@ -167,12 +140,10 @@ fn build_ast_from_expression(pair: Pair<Rule>) -> Result<Expression, String> {
                                             // Parse the argument content
                                             let arg_content = arg_pair.into_inner().next().ok_or("Empty argument")?;
                                             if arg_content.as_rule() == Rule::keyword_argument {
-                                                // For function calls, we'll ignore keyword arguments for now
-                                                // and just parse the value part
                                                 let mut kw_inner = arg_content.into_inner();
-                                                let _name = kw_inner.next(); // Skip the name
+                                                let name_pair = kw_inner.next().ok_or("Keyword argument missing name")?;
                                                 let value_pair = kw_inner.next().ok_or("Keyword argument missing value")?;
-                                                args.push(build_ast_from_expression(value_pair)?);
+                                                keywords.push((name_pair.as_str().to_string(), build_ast_from_expression(value_pair)?));
                                             } else {
                                                 // Regular expression argument
                                                 args.push(build_ast_from_expression(arg_content)?);
@@ -184,7 +155,7 @@ fn build_ast_from_expression(pair: Pair<Rule>) -> Result<Expression, String> {
                                         }
                                     }
                                 }
-                            } else if matches!(inner_pair.as_rule(), Rule::logical_or | Rule::expression) {
+                            } else if matches!(inner_pair.as_rule(), Rule::conditional_expression | Rule::logical_or | Rule::expression) {
                                 // Single argument case - the grammar might produce a direct expression instead of argument_list
                                 args.push(build_ast_from_expression(inner_pair)?);
                             } else {
@@ -203,6 +174,7 @@ fn build_ast_from_expression(pair: Pair<Rule>) -> Result<Expression, String> {
                         current_expr = Expression::Call {
                             callee: Box::new(current_expr),
                             args,
+                            keywords,
                         };
                     }
                     Rule::attr_access => {
@@ -255,11 +227,52 @@ fn build_ast_from_expression(pair: Pair<Rule>) -> Result<Expression, String> {
             }
         }
         Rule::comparison_identity_membership => {
-            build_recursive_ast_from_binary_expr_rule(pair.into_inner(), build_ast_from_expression, &[], Some(&[
+            const COMPARISON_OPS: &[(&str, BinOp)] = &[
                 ("==", BinOp::Eq), ("!=", BinOp::NotEq), (">", BinOp::Gt), ("<", BinOp::Lt),
                 (">=", BinOp::GtEq), ("<=", BinOp::LtEq), ("is", BinOp::Is),
                 ("is not", BinOp::IsNot), ("in", BinOp::In), ("not in", BinOp::NotIn)
-            ]))
+            ];
+            if pair.clone().into_inner().count() <= 3 {
+                return build_recursive_ast_from_binary_expr_rule(pair.into_inner(), build_ast_from_expression, &[], Some(COMPARISON_OPS));
+            }
+            // `a < b < c` means `a < b and b < c` with `b` evaluated once, not `(a < b) < c`
+            let mut inner = pair.into_inner();
+            let left = build_ast_from_expression(inner.next().ok_or("Comparison missing left operand")?)?;
+            let mut ops = Vec::new();
+            let mut comparators = Vec::new();
+            while let Some(op_pair) = inner.next() {
+                let op = COMPARISON_OPS.iter()
+                    .find(|(s, _)| *s == op_pair.as_str())
+                    .map(|(_, bin_op)| *bin_op)
+                    .ok_or_else(|| format!("Unknown comparison operator: {}", op_pair.as_str()))?;
+                let operand_pair = inner.next().ok_or("Missing right operand for comparison")?;
+                ops.push(op);
+                comparators.push(build_ast_from_expression(operand_pair)?);
+            }
+            Ok(Expression::Compare { left: Box::new(left), ops, comparators })
+        }
+        Rule::conditional_expression => {
+            // conditional_expression = { logical_or ~ (ternary_if_keyword ~ logical_or ~ ternary_else_keyword ~ expression)? }
+            let mut inner = pair.into_inner().filter(|p| !matches!(p.as_rule(), Rule::ternary_if_keyword | Rule::ternary_else_keyword));
+            let body = build_ast_from_expression(inner.next().ok_or("Conditional expression is empty")?)?;
+            match inner.next() {
+                None => Ok(body),
+                Some(test_pair) => {
+                    let test = build_ast_from_expression(test_pair)?;
+                    let orelse_pair = inner.next().ok_or("Conditional expression missing else branch")?;
+                    Ok(Expression::IfExp {
+                        test: Box::new(test),
+                        body: Box::new(body),
+                        orelse: Box::new(build_ast_from_expression(orelse_pair)?),
+                    })
+                }
+            }
+        }
+        Rule::named_expression => {
+            let mut inner = pair.into_inner();
+            let target = inner.next().ok_or("Assignment expression missing target")?.as_str().to_string();
+            let value_pair = inner.next().ok_or("Assignment expression missing value")?;
+            Ok(Expression::NamedExpr { target, value: Box::new(build_ast_from_expression(value_pair)?) })
         }
         Rule::bitwise_or => {
             build_recursive_ast_from_binary_expr_rule(pair.into_inner(), build_ast_from_expression, &[
@@ -324,7 +337,9 @@ fn build_ast_from_expression(pair: Pair<Rule>) -> Result<Expression, String> {
             ], None)
         }        Rule::string_literal => {
             let full_str = pair.as_str();
-            let (_quote_char, content) = if full_str.starts_with('"') {
+            let (_quote_char, content) = if full_str.len() >= 6 && (full_str.starts_with("\"\"\"") || full_str.starts_with("'''")) {
+                (full_str.as_bytes()[0] as char, &full_str[3..full_str.len()-3])
+            } else if full_str.starts_with('"') {
                 ('"', &full_str[1..full_str.len()-1])
             } else if full_str.starts_with('\'') {
                 ('\'', &full_str[1..full_str.len()-1])
@@ -334,13 +349,16 @@ fn build_ast_from_expression(pair: Pair<Rule>) -> Result<Expression, String> {
             // Process escape sequences
             let processed_content = process_escape_sequences(content)?;
             Ok(Expression::StringLiteral(processed_content))
-        }Rule::integer_literal => {
-            let val = pair.as_str().parse::<i64>().map_err(|e| format!("Invalid integer: {}", e))?;
-            Ok(Expression::IntegerLiteral(val))
-        }        Rule::float_literal => {
-            let val = pair.as_str().parse::<f64>().map_err(|e| format!("Invalid float: {}", e))?;
+        }Rule::integer_literal => Ok(Expression::IntegerLiteral(parse_integer_literal(pair.as_str())?)),
+        Rule::float_literal => {
+            let val = pair.as_str().replace('_', "").parse::<f64>().map_err(|e| format!("Invalid float: {}", e))?;
             Ok(Expression::FloatLiteral(val))
         }
+        Rule::imaginary_literal => {
+            let digits = pair.as_str().replace('_', "");
+            let val = digits[..digits.len() - 1].parse::<f64>().map_err(|e| format!("Invalid imaginary literal: {}", e))?;
+            Ok(Expression::ImaginaryLiteral(val))
+        }
         Rule::boolean_literal => {
             let val = match pair.as_str() {
                 "True" => true,
@@ -380,9 +398,8 @@ fn build_ast_from_expression(pair: Pair<Rule>) -> Result<Expression, String> {
             }
             // Else, no parameter_list, params remains empty.            // The next (or first, if no params) pair must be the expression body
             let body_expr_pair = inner_pairs.next().ok_or_else(|| "Lambda expression missing body".to_string())?;
-            // Since expression is a silent rule (_{ logical_or }), the actual rule will be logical_or
-            // We can accept either expression or logical_or as valid body rules
-            if body_expr_pair.as_rule() != Rule::expression && body_expr_pair.as_rule() != Rule::logical_or {
+            // Since expression is a silent rule (_{ conditional_expression }), the actual rule will be conditional_expression
+            if !matches!(body_expr_pair.as_rule(), Rule::expression | Rule::conditional_expression | Rule::logical_or) {
                  return Err(format!("Expected expression for lambda body, got {:?} with content '{}'", body_expr_pair.as_rule(), body_expr_pair.as_str()));
             }
 
@@ -477,12 +494,6 @@ fn build_ast_from_expression(pair: Pair<Rule>) -> Result<Expression, String> {
                 Err("frozenset() expects a list literal".to_string())
             }
         }
-        Rule::complex_literal => {
-            let mut inner = pair.into_inner();
-            let real = build_ast_from_expression(inner.next().unwrap())?;
-            let imag = build_ast_from_expression(inner.next().unwrap())?;
-            Ok(Expression::ComplexLiteral(Box::new(real), Box::new(imag)))
-        }
         Rule::generator_expression => {
             parse_generator_expression(pair)
         }
@@ -511,15 +522,28 @@ fn build_ast_from_assign_target(pair: Pair<Rule>) -> Result<Expression, String>
         Rule::identifier => {
             Ok(Expression::Identifier(pair.as_str().to_string()))
         }
-        // Support attribute chains: identifier ('.' identifier)*
+        // Support attribute and subscript chains: identifier ('.' identifier | '[' expression ']')*
         Rule::assign_target => {
             let mut inner = pair.into_inner();
             let mut expr = Expression::Identifier(inner.next().unwrap().as_str().to_string());
-            for attr in inner {
-                expr = Expression::AttributeAccess {
-                    object: Box::new(expr),
-                    attr: attr.as_str().to_string(),
-                };
+            for suffix in inner {
+                match suffix.as_rule() {
+                    Rule::attr_access => {
+                        let attr_name = suffix.into_inner().next().ok_or("Missing attribute name")?;
+                        expr = Expression::AttributeAccess {
+                            object: Box::new(expr),
+                            attr: attr_name.as_str().to_string(),
+                        };
+                    }
+                    Rule::index_access => {
+                        let index_expr = suffix.into_inner().next().ok_or("Missing index expression")?;
+                        expr = Expression::Index {
+                            object: Box::new(expr),
+                            index: Box::new(build_ast_from_expression(index_expr)?),
+                        };
+                    }
+                    _ => return Err(format!("Unexpected assign_target suffix: {:?}", suffix.as_rule())),
+                }
             }
             Ok(expr)
         }
@@ -640,6 +664,9 @@ fn build_ast_from_statement(pair: Pair<Rule>) -> Result<AstNode, String> {
                     for arg_pair in inner_pair.into_inner() {
                         if arg_pair.as_rule() == Rule::argument {
                             let arg_content = arg_pair.into_inner().next().ok_or("Empty argument")?;
+                            if arg_content.as_rule() == Rule::keyword_argument {
+                                return Err(format!("print() keyword arguments are not supported: '{}'", arg_content.as_str()));
+                            }
                             args.push(build_ast_from_expression(arg_content)?);
                         }
                     }
@@ -685,6 +712,29 @@ fn build_ast_from_statement(pair: Pair<Rule>) -> Result<AstNode, String> {
                 operator,
                 value: Box::new(value_expr),
             }))
+        }
+        Rule::chained_assignment => {
+            let mut parts: Vec<_> = specific_statement_pair.into_inner().collect();
+            let value_pair = parts.pop().ok_or("Chained assignment missing value")?;
+            let targets = parts.into_iter().map(build_ast_from_assign_target).collect::<Result<Vec<_>, _>>()?;
+            Ok(AstNode::Statement(Statement::ChainedAssignment {
+                targets,
+                value: Box::new(build_ast_from_expression(value_pair)?),
+            }))
+        }
+        Rule::assert_statement => {
+            let mut inner = specific_statement_pair.into_inner().filter(|p| p.as_rule() != Rule::assert_keyword);
+            let test = build_ast_from_expression(inner.next().ok_or("Assert statement missing condition")?)?;
+            let msg = inner.next().map(build_ast_from_expression).transpose()?;
+            Ok(AstNode::Statement(Statement::Assert { test: Box::new(test), msg: msg.map(Box::new) }))
+        }
+        Rule::del_statement => {
+            let targets = specific_statement_pair
+                .into_inner()
+                .filter(|p| p.as_rule() != Rule::del_keyword)
+                .map(build_ast_from_assign_target)
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(AstNode::Statement(Statement::Delete(targets)))
         }        Rule::if_statement => {
             let mut inner_rules = specific_statement_pair.into_inner(); // condition, block, elif_clause*, else_clause?
             let condition_expr = build_ast_from_expression(inner_rules.next().unwrap())?;
@@ -794,12 +844,18 @@ fn build_ast_from_statement(pair: Pair<Rule>) -> Result<AstNode, String> {
                 }
             }
             
+            let else_body = inner_rules.next().map(parse_loop_else_clause).transpose()?;
             Ok(AstNode::Statement(Statement::While {
                 condition: Box::new(condition_expr),
                 body,
+                else_body,
             }))
         }Rule::for_statement => {
-            let mut inner_rules = specific_statement_pair.into_inner(); // for_target, expression, block
+            let mut inner_rules = specific_statement_pair.into_inner(); // async_keyword?, for_target, expression, block
+            let is_async = inner_rules.peek().map(|p| p.as_rule() == Rule::async_keyword).unwrap_or(false);
+            if is_async {
+                inner_rules.next();
+            }
             let target_pair = inner_rules.next().unwrap();
             let vars = parse_for_target(target_pair)?;
             let iterable_expr = build_ast_from_expression(inner_rules.next().unwrap())?;
@@ -823,14 +879,17 @@ fn build_ast_from_statement(pair: Pair<Rule>) -> Result<AstNode, String> {
                 }
             }
             
+            let else_body = inner_rules.next().map(parse_loop_else_clause).transpose()?;
             Ok(AstNode::Statement(Statement::For {
                 vars,
                 iterable: Box::new(iterable_expr),
                 body,
+                else_body,
+                is_async,
             }))
         }Rule::function_definition => {
             // specific_statement_pair is Rule::function_definition
-            let mut func_def_inner = specific_statement_pair.into_inner(); // decorator*, def, identifier, parameter_list?, block
+            let mut func_def_inner = specific_statement_pair.into_inner(); // decorator*, async_keyword?, def, identifier, parameter_list?, block
             
             // Parse decorators first
             let mut decorators = Vec::new();
@@ -843,6 +902,11 @@ fn build_ast_from_statement(pair: Pair<Rule>) -> Result<AstNode, String> {
                 }
             }
             
+            let is_async = func_def_inner.peek().map(|p| p.as_rule() == Rule::async_keyword).unwrap_or(false);
+            if is_async {
+                func_def_inner.next();
+            }
+
             // Skip "def" keyword - it's implicit in the grammar
             let name = func_def_inner.next().unwrap().as_str().to_string();
             
@@ -883,6 +947,7 @@ fn build_ast_from_statement(pair: Pair<Rule>) -> Result<AstNode, String> {
                 params,
                 body,
                 decorators,
+                is_async,
             }))
         }
         Rule::return_statement => {
@@ -895,13 +960,11 @@ fn build_ast_from_statement(pair: Pair<Rule>) -> Result<AstNode, String> {
             Ok(AstNode::Statement(Statement::Return(expr)))
         }
         Rule::yield_statement => {
-            let mut inner_rules = specific_statement_pair.into_inner();
-            let expr = if let Some(expr_pair) = inner_rules.next() {
-                Some(Box::new(build_ast_from_expression(expr_pair)?))
-            } else {
-                None
-            };
-            Ok(AstNode::Statement(Statement::Yield(expr)))
+            let yield_pair = specific_statement_pair.into_inner().next().ok_or_else(|| "Yield statement missing yield expression".to_string())?;
+            match build_ast_from_expression(yield_pair)? {
+                Expression::Yield(expr) => Ok(AstNode::Statement(Statement::Yield(expr))),
+                yield_from => Ok(AstNode::Statement(Statement::ExpressionStatement(Box::new(yield_from)))),
+            }
         }
         Rule::expression_statement => {
             let expr_pair = specific_statement_pair.into_inner().next().ok_or_else(|| "Expression statement missing expression".to_string())?;
@@ -913,13 +976,23 @@ fn build_ast_from_statement(pair: Pair<Rule>) -> Result<AstNode, String> {
         Rule::pass_statement => Ok(AstNode::Statement(Statement::Pass)),
         Rule::class_definition => {
             // specific_statement_pair is Rule::class_definition
-            let mut class_def_inner = specific_statement_pair.into_inner();
+            let mut class_def_inner = specific_statement_pair.into_inner().peekable();
+            let mut decorators = Vec::new();
+            while let Some(decorator_pair) = class_def_inner.next_if(|p| p.as_rule() == Rule::decorator) {
+                decorators.push(parse_decorator(decorator_pair)?);
+            }
             let name = class_def_inner.next().unwrap().as_str().to_string();
-            // Optionally parse base class (not used yet)
-            let mut maybe_base = None;
-            if let Some(peeked) = class_def_inner.peek() {
-                if peeked.as_rule() == Rule::identifier {
-                    maybe_base = Some(class_def_inner.next().unwrap().as_str().to_string());
+            // Base classes and class keywords share the call argument syntax
+            let mut bases = Vec::new();
+            let mut keywords = Vec::new();
+            if let Some(args_pair) = class_def_inner.next_if(|p| p.as_rule() == Rule::argument_list) {
+                for arg_pair in args_pair.into_inner() {
+                    let arg_content = arg_pair.into_inner().next().ok_or("Empty argument")?;
+                    match parse_argument(arg_content)? {
+                        Argument::Positional(base) if keywords.is_empty() => bases.push(base),
+                        Argument::Positional(_) => return Err(format!("Class '{}': positional base follows keyword argument", name)),
+                        Argument::Keyword(keyword, value) => keywords.push((keyword, value)),
+                    }
                 }
             }            let block_pair = class_def_inner.next().ok_or_else(|| format!("Class '{}' missing block.", name))?;
             if block_pair.as_rule() != Rule::block { return Err(format!("Class '{}' expected block, got {:?}.", name, block_pair.as_rule())); }
@@ -946,7 +1019,9 @@ fn build_ast_from_statement(pair: Pair<Rule>) -> Result<AstNode, String> {
             }
             Ok(AstNode::Statement(Statement::ClassDef {
                 name,
-                base: maybe_base,
+                bases,
+                keywords,
+                decorators,
                 body,
             }))
         }
@@ -983,8 +1058,9 @@ fn build_ast_from_statement(pair: Pair<Rule>) -> Result<AstNode, String> {
                     Rule::except_clause => {
                         let except_pair = inner.next().unwrap();
                         let mut except_inner = except_pair.into_inner();
+                        // `expression` is a silent rule, so the type shows up as its inner pair
                         let exception_type = if let Some(p) = except_inner.peek() {
-                            if p.as_rule() == Rule::expression {
+                            if !matches!(p.as_rule(), Rule::identifier | Rule::block) {
                                 Some(build_ast_from_expression(except_inner.next().unwrap())?)
                             } else { None }
                         } else { None };
@@ -1076,6 +1152,35 @@ fn build_ast_from_statement(pair: Pair<Rule>) -> Result<AstNode, String> {
                 finally_body,
             }))
         }
+        Rule::import_statement => {
+            let mut inner = specific_statement_pair.into_inner().filter(|p| !matches!(p.as_rule(), Rule::import_keyword | Rule::as_keyword));
+            let module = inner.next().ok_or_else(|| "Import statement missing module name".to_string())?.as_str().to_string();
+            let alias = inner.next().map(|p| p.as_str().to_string());
+            Ok(AstNode::Statement(Statement::Import { module, alias }))
+        }
+        Rule::from_import_statement => {
+            let mut inner = specific_statement_pair.into_inner().filter(|p| !matches!(p.as_rule(), Rule::from_keyword | Rule::import_keyword));
+            let module = inner.next().ok_or_else(|| "from-import missing module name".to_string())?.as_str().to_string();
+            let names = inner
+                .map(|name_pair| {
+                    let mut parts = name_pair.into_inner().filter(|p| p.as_rule() == Rule::identifier);
+                    let name = parts.next().map(|p| p.as_str().to_string()).unwrap_or_default();
+                    (name, parts.next().map(|p| p.as_str().to_string()))
+                })
+                .collect();
+            Ok(AstNode::Statement(Statement::ImportFrom { module, names }))
+        }
+        Rule::annotated_assignment => {
+            let mut inner = specific_statement_pair.into_inner();
+            let target = build_ast_from_assign_target(inner.next().ok_or("Annotated assignment missing target")?)?;
+            let annotation = build_ast_from_expression(inner.next().ok_or("Annotated assignment missing annotation")?)?;
+            let value = inner.next().map(build_ast_from_expression).transpose()?;
+            Ok(AstNode::Statement(Statement::AnnotatedAssignment {
+                target: Box::new(target),
+                annotation: Box::new(annotation),
+                value: value.map(Box::new),
+            }))
+        }
         Rule::raise_statement => {
             let mut inner = specific_statement_pair.into_inner();
             let expr = if let Some(expr_pair) = inner.next() {
@@ -1086,21 +1191,19 @@ fn build_ast_from_statement(pair: Pair<Rule>) -> Result<AstNode, String> {
             Ok(AstNode::Statement(Statement::Raise(expr)))
         }
         Rule::with_statement => {
-            let mut with_inner = specific_statement_pair.into_inner();
+            let with_inner = specific_statement_pair.into_inner();
             let mut items = Vec::new();
             let mut body = Vec::new();
+            let mut is_async = false;
             
             // Parse with items first
-            while let Some(pair) = with_inner.next() {
+            for pair in with_inner {
                 match pair.as_rule() {
+                    Rule::async_keyword => is_async = true,
                     Rule::with_item => {
                         let mut item_inner = pair.into_inner();
                         let context_expr = build_ast_from_expression(item_inner.next().unwrap())?;
-                        let optional_vars = if let Some(var_pair) = item_inner.next() {
-                            Some(var_pair.as_str().to_string())
-                        } else {
-                            None
-                        };
+                        let optional_vars = item_inner.next().map(|var_pair| var_pair.as_str().to_string());
                         items.push(crate::ast::WithItem { context_expr, optional_vars });
                     }
                     Rule::block => {
@@ -1126,7 +1229,29 @@ fn build_ast_from_statement(pair: Pair<Rule>) -> Result<AstNode, String> {
                 }
             }
             
-            Ok(AstNode::Statement(Statement::With { items, body }))
+            Ok(AstNode::Statement(Statement::With { items, body, is_async }))
+        }
+        Rule::match_statement => {
+            let mut subject = None;
+            let mut cases = Vec::new();
+            for pair in specific_statement_pair.into_inner() {
+                match pair.as_rule() {
+                    Rule::match_subject => {
+                        // `match a, b:` (or `match a,:`) matches a tuple
+                        let is_tuple = pair.as_str().trim_end().ends_with(',');
+                        let mut items = pair.into_inner().map(build_ast_from_expression).collect::<Result<Vec<_>, _>>()?;
+                        subject = Some(if items.len() == 1 && !is_tuple {
+                            items.remove(0)
+                        } else {
+                            Expression::TupleLiteral(items)
+                        });
+                    }
+                    Rule::case_clause => cases.push(parse_case_clause(pair)?),
+                    _ => {} // match keyword, INDENT, DEDENT
+                }
+            }
+            let subject = subject.ok_or_else(|| "Match statement missing subject".to_string())?;
+            Ok(AstNode::Statement(Statement::Match { subject: Box::new(subject), cases }))
         }
         _ => Err(format!(
             "Unhandled specific statement rule: {:?}\nContent: '{}'",
@@ -1136,17 +1261,36 @@ fn build_ast_from_statement(pair: Pair<Rule>) -> Result<AstNode, String> {
     }
 }
 
+/// Why a source file did not parse, and the 1-based line and column it points at
+#[derive(Debug, Clone)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    // The full text `parse_eppx_string` reports
+    report: String,
+}
+
+impl SyntaxError {
+    fn at(line: usize, column: usize, message: String) -> Self {
+        SyntaxError { line, column, report: message.clone(), message }
+    }
+}
+
 // Renamed from parse_eppx_string_final
-pub fn parse_eppx_string(input: &str) -> Result<Vec<AstNode>, String> {    let preprocessed = preprocess_indentation(input);
-    println!("Preprocessed input:");
-    println!("{}", preprocessed);
-    println!("--- End preprocessed input ---");
+pub fn parse_eppx_string(input: &str) -> Result<Vec<AstNode>, String> {
+    parse_eppx_located(input).map_err(|e| e.report)
+}
+
+// Like parse_eppx_string, but says where the error is, for the language server
+pub fn parse_eppx_located(input: &str) -> Result<Vec<AstNode>, SyntaxError> {
+    let source = TokenizedSource::new(input).map_err(|e| SyntaxError::at(e.line, 1, e.to_string()))?;
 
-    match EppParser::parse(Rule::program, &preprocessed) {
+    match parse_program(&source.text) {
         Ok(mut pairs) => {
-            let program_pair = pairs.next().ok_or_else(|| "Empty program".to_string())?;
+            let program_pair = pairs.next().ok_or_else(|| SyntaxError::at(1, 1, "Empty program".to_string()))?;
             if program_pair.as_rule() != Rule::program {
-                return Err(format!("Expected Rule::program, got {:?}", program_pair.as_rule()));
+                return Err(SyntaxError::at(1, 1, format!("Expected Rule::program, got {:?}", program_pair.as_rule())));
             }
 
             let mut ast_nodes = Vec::new();
@@ -1154,7 +1298,12 @@ pub fn parse_eppx_string(input: &str) -> Result<Vec<AstNode>, String> {    let p
             for pair in program_pair.into_inner() {
                 match pair.as_rule() {
                     Rule::statement | Rule::function_definition | Rule::class_definition => {
-                        ast_nodes.push(build_ast_from_statement(pair)?);
+                        let (line, column) = pair.as_span().start_pos().line_col();
+                        let node = build_ast_from_statement(pair).map_err(|message| {
+                            let (line, column) = source.original_position(line, column);
+                            SyntaxError::at(line, column, message)
+                        })?;
+                        ast_nodes.push(node);
                     }
                     Rule::COMMENT | Rule::WHITESPACE => {
                         // skip
@@ -1167,15 +1316,332 @@ pub fn parse_eppx_string(input: &str) -> Result<Vec<AstNode>, String> {    let p
             }
             Ok(ast_nodes)
         }
-        Err(e) => Err(format!("Parse failed: {}\nDetails: {}\nInput: '{}'", e, e.variant.message(), input)),
+        Err(e) => {
+            let e = source.remap_error(e);
+            let (line, column) = match e.line_col {
+                pest::error::LineColLocation::Pos(pos) | pest::error::LineColLocation::Span(pos, _) => pos,
+            };
+            Err(SyntaxError {
+                line,
+                column,
+                message: e.variant.message().into_owned(),
+                report: format!("Parse failed: {}\nDetails: {}\nInput: '{}'", e, e.variant.message(), input),
+            })
+        }
+    }
+}
+
+// Parses the tokenized `text` of a whole file
+fn parse_program(text: &str) -> Result<Pairs<'_, Rule>, pest::error::Error<Rule>> {
+    let pairs = EppParser::parse(Rule::program, text)?;
+    for pair in pairs.clone() {
+        check_statement_separators(pair, text)?;
+    }
+    Ok(pairs)
+}
+
+// The grammar skips newlines like any other whitespace, so a newline ending
+// each statement is checked here: two statements on one line need a `;`
+// between them, and `b = 2 3` is an error rather than two statements
+fn check_statement_separators(pair: Pair<Rule>, text: &str) -> Result<(), pest::error::Error<Rule>> {
+    let mut previous_end = None;
+    for inner in pair.into_inner() {
+        if matches!(inner.as_rule(), Rule::statement | Rule::function_definition | Rule::class_definition) {
+            let start = inner.as_span().start();
+            if previous_end.is_some_and(|end| !text[end..start].contains(['\n', ';'])) {
+                let message = "expected a newline or `;` before this statement".to_string();
+                return Err(pest::error::Error::new_from_pos(pest::error::ErrorVariant::CustomError { message }, inner.as_span().start_pos()));
+            }
+            previous_end = Some(start + without_trailing_comments(inner.as_str()).len());
+        }
+        check_statement_separators(inner, text)?;
+    }
+    Ok(())
+}
+
+// Every token of a source file, for `eppx build --emit tokens`
+pub fn tokenize_eppx_string(input: &str) -> Result<Vec<Token>, String> {
+    tokenizer::tokenize_all(input).map_err(|e| e.to_string())
+}
+
+// The source with its layout made explicit, as the grammar sees it
+pub fn preprocess_eppx_string(input: &str) -> Result<String, String> {
+    Ok(TokenizedSource::new(input).map_err(|e| e.to_string())?.text)
+}
+
+// The value of one literal token such as `0x1F` or `'it\'s'`, read as the parser reads it
+pub fn parse_literal(token: &str) -> Option<Expression> {
+    [Rule::string_literal, Rule::imaginary_literal, Rule::float_literal, Rule::integer_literal].into_iter().find_map(|rule| {
+        let pair = EppParser::parse(rule, token).ok()?.next()?;
+        if pair.as_str().len() != token.len() {
+            return None;
+        }
+        build_ast_from_expression(pair).ok()
+    })
+}
+
+// `text` without the whitespace and comments at its end
+fn without_trailing_comments(text: &str) -> &str {
+    let mut text = text.trim_end();
+    loop {
+        let line_start = text.rfind('\n').map_or(0, |newline| newline + 1);
+        let mut quote = None;
+        let mut chars = text[line_start..].char_indices();
+        let mut comment = None;
+        while let Some((offset, c)) = chars.next() {
+            match (c, quote) {
+                ('\\', Some(_)) => {
+                    chars.next();
+                }
+                ('"' | '\'', None) => quote = Some(c),
+                (c, Some(open)) if c == open => quote = None,
+                ('#', None) => {
+                    comment = Some(line_start + offset);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        match comment {
+            Some(offset) => text = text[..offset].trim_end(),
+            None => return text,
+        }
+    }
+}
+
+/// Where a statement, or a clause such as `elif` or `except`, is in the original source
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatementSpan {
+    /// 1-based line and column of its first character
+    pub start: (usize, usize),
+    /// 1-based line and column just past its last character
+    pub end: (usize, usize),
+    /// Whether it has a block: `if`, `def`, `else:`, `case ...:` and so on
+    pub compound: bool,
+}
+
+// The spans of every statement and clause in source order, which is the order
+// a walk over the AST meets them, for the formatter to put comments back
+pub fn statement_spans(input: &str) -> Result<Vec<StatementSpan>, String> {
+    let source = TokenizedSource::new(input).map_err(|e| e.to_string())?;
+    let mut pairs = parse_program(&source.text).map_err(|e| source.remap_error(e).to_string())?;
+    let mut spans: Vec<StatementSpan> = Vec::new();
+    let mut pending: Vec<Pair<Rule>> = pairs.next().map(|program| program.into_inner().rev().collect()).unwrap_or_default();
+    while let Some(pair) = pending.pop() {
+        let compound = match pair.as_rule() {
+            Rule::statement => pair.clone().into_inner().next().is_some_and(|inner| {
+                matches!(
+                    inner.as_rule(),
+                    Rule::if_statement | Rule::while_statement | Rule::for_statement | Rule::function_definition
+                        | Rule::class_definition | Rule::try_statement | Rule::with_statement | Rule::match_statement
+                )
+            }),
+            Rule::function_definition | Rule::class_definition | Rule::elif_clause | Rule::else_clause
+            | Rule::except_clause | Rule::try_else_clause | Rule::finally_clause | Rule::case_clause => true,
+            _ => {
+                pending.extend(pair.into_inner().rev());
+                continue;
+            }
+        };
+        let (line, column) = pair.as_span().start_pos().line_col();
+        let start = source.original_position(line, column);
+        // Pest counts the whitespace and comments after a rule's last token as part of it
+        let text = without_trailing_comments(pair.as_str());
+        let end = match text.rfind('\n') {
+            Some(newline) => (line + text.matches('\n').count(), text[newline + 1..].chars().count() + 1),
+            None => (line, column + text.chars().count()),
+        };
+        // A `statement` and the definition it wraps start at the same place
+        if spans.last().is_none_or(|last| last.start != start) {
+            spans.push(StatementSpan { start, end: source.original_position(end.0, end.1), compound });
+        }
+        pending.extend(pair.into_inner().rev());
     }
+    Ok(spans)
 }
 
-// Renamed from parse_eppx_file_final
-pub fn parse_eppx_file(file_path: &Path) -> Result<Vec<AstNode>, String> {
-    let content = fs::read_to_string(file_path)
-        .map_err(|e| format!("Failed to read file {}: {}", file_path.display(), e))?;
-    parse_eppx_string(&content) // Calls renamed parse_eppx_string
+fn parse_case_clause(pair: Pair<Rule>) -> Result<MatchCase, String> {
+    let mut pattern = None;
+    let mut guard = None;
+    let mut body = Vec::new();
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::case_pattern => {
+                let pattern_pair = inner.into_inner().next().ok_or_else(|| "Case clause missing pattern".to_string())?;
+                pattern = Some(parse_pattern(pattern_pair)?);
+            }
+            Rule::case_guard => {
+                let guard_pair = inner.into_inner().next().ok_or_else(|| "Case guard missing condition".to_string())?;
+                guard = Some(build_ast_from_expression(guard_pair)?);
+            }
+            Rule::block => {
+                for block_inner in inner.into_inner() {
+                    match block_inner.as_rule() {
+                        Rule::indented_statements => {
+                            for stmt_pair in block_inner.into_inner() {
+                                if matches!(stmt_pair.as_rule(), Rule::statement | Rule::function_definition | Rule::class_definition) {
+                                    body.push(build_ast_from_statement(stmt_pair)?);
+                                }
+                            }
+                        }
+                        Rule::statement | Rule::function_definition | Rule::class_definition => {
+                            body.push(build_ast_from_statement(block_inner)?);
+                        }
+                        _ => { /* Skip INDENT, DEDENT, WHITESPACE, COMMENT */ }
+                    }
+                }
+            }
+            _ => {} // case keyword
+        }
+    }
+    let pattern = pattern.ok_or_else(|| "Case clause missing pattern".to_string())?;
+    Ok(MatchCase { pattern, guard, body })
+}
+
+// The `else:` block of a `for` or `while` loop
+fn parse_loop_else_clause(pair: Pair<Rule>) -> Result<Vec<AstNode>, String> {
+    let block_pair = pair.into_inner().next().ok_or_else(|| "Loop else clause missing block".to_string())?;
+    let mut body = Vec::new();
+    for inner_pair in block_pair.into_inner() {
+        match inner_pair.as_rule() {
+            Rule::indented_statements => {
+                for stmt_pair in inner_pair.into_inner() {
+                    if matches!(stmt_pair.as_rule(), Rule::statement | Rule::function_definition | Rule::class_definition) {
+                        body.push(build_ast_from_statement(stmt_pair)?);
+                    }
+                }
+            }
+            Rule::statement | Rule::function_definition | Rule::class_definition => {
+                body.push(build_ast_from_statement(inner_pair)?);
+            }
+            _ => { /* Skip INDENT, DEDENT, WHITESPACE, COMMENT */ }
+        }
+    }
+    Ok(body)
+}
+
+// `a.b.c` as nested attribute accesses
+fn dotted_name_expression(name: &str) -> Expression {
+    let mut parts = name.split('.');
+    let mut expr = Expression::Identifier(parts.next().unwrap_or_default().to_string());
+    for attr in parts {
+        expr = Expression::AttributeAccess { object: Box::new(expr), attr: attr.to_string() };
+    }
+    expr
+}
+
+/// Parses decimal, `0x`, `0o` and `0b` integers, ignoring `_` separators.
+fn parse_integer_literal(text: &str) -> Result<i64, String> {
+    let digits = text.replace('_', "");
+    let (radix, body) = match digits.get(..2) {
+        Some("0x") | Some("0X") => (16, &digits[2..]),
+        Some("0o") | Some("0O") => (8, &digits[2..]),
+        Some("0b") | Some("0B") => (2, &digits[2..]),
+        _ => (10, digits.as_str()),
+    };
+    i64::from_str_radix(body, radix).map_err(|e| match e.kind() {
+        std::num::IntErrorKind::PosOverflow => format!("integer literal too large: {}", text),
+        _ => format!("Invalid integer: {}", e),
+    })
+}
+
+fn parse_literal_pattern(pair: Pair<Rule>) -> Result<Expression, String> {
+    let mut negate = false;
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::pattern_minus => negate = true,
+            Rule::singleton_pattern => {
+                return Ok(match inner.as_str() {
+                    "None" => Expression::NoneLiteral,
+                    "True" => Expression::BooleanLiteral(true),
+                    _ => Expression::BooleanLiteral(false),
+                });
+            }
+            _ => {
+                return Ok(match build_ast_from_expression(inner)? {
+                    Expression::IntegerLiteral(i) if negate => Expression::IntegerLiteral(-i),
+                    Expression::FloatLiteral(f) if negate => Expression::FloatLiteral(-f),
+                    Expression::ImaginaryLiteral(f) if negate => Expression::ImaginaryLiteral(-f),
+                    literal => literal,
+                });
+            }
+        }
+    }
+    Err("Empty literal pattern".to_string())
+}
+
+fn parse_pattern(pair: Pair<Rule>) -> Result<Pattern, String> {
+    match pair.as_rule() {
+        Rule::pattern => {
+            let mut inner = pair.into_inner().filter(|p| p.as_rule() != Rule::as_keyword);
+            let pattern = parse_pattern(inner.next().ok_or_else(|| "Empty pattern".to_string())?)?;
+            Ok(match inner.next() {
+                Some(name) => Pattern::As(Box::new(pattern), name.as_str().to_string()),
+                None => pattern,
+            })
+        }
+        Rule::or_pattern => {
+            let mut alternatives = pair.into_inner().map(parse_pattern).collect::<Result<Vec<_>, _>>()?;
+            if alternatives.len() == 1 {
+                Ok(alternatives.remove(0))
+            } else {
+                Ok(Pattern::Or(alternatives))
+            }
+        }
+        Rule::open_sequence_pattern | Rule::sequence_pattern => {
+            Ok(Pattern::Sequence(pair.into_inner().map(parse_pattern).collect::<Result<Vec<_>, _>>()?))
+        }
+        Rule::star_pattern => {
+            let name = pair.into_inner().next().ok_or_else(|| "Star pattern missing name".to_string())?.as_str();
+            Ok(Pattern::Star(if name == "_" { None } else { Some(name.to_string()) }))
+        }
+        Rule::literal_pattern => Ok(Pattern::Literal(parse_literal_pattern(pair)?)),
+        Rule::value_pattern => Ok(Pattern::Value(dotted_name_expression(pair.as_str()))),
+        Rule::wildcard_pattern => Ok(Pattern::Wildcard),
+        Rule::capture_pattern => Ok(Pattern::Capture(pair.as_str().to_string())),
+        Rule::mapping_pattern => {
+            let mut entries = Vec::new();
+            let mut rest = None;
+            for item in pair.into_inner() {
+                match item.as_rule() {
+                    Rule::double_star_pattern => {
+                        rest = Some(item.into_inner().next().ok_or_else(|| "** pattern missing name".to_string())?.as_str().to_string());
+                    }
+                    _ => {
+                        let mut kv = item.into_inner();
+                        let key_pair = kv.next().ok_or_else(|| "Mapping pattern missing key".to_string())?;
+                        let key = match key_pair.as_rule() {
+                            Rule::value_pattern => dotted_name_expression(key_pair.as_str()),
+                            _ => parse_literal_pattern(key_pair)?,
+                        };
+                        let value = parse_pattern(kv.next().ok_or_else(|| "Mapping pattern missing value".to_string())?)?;
+                        entries.push((key, value));
+                    }
+                }
+            }
+            Ok(Pattern::Mapping { entries, rest })
+        }
+        Rule::class_pattern => {
+            let mut inner = pair.into_inner();
+            let name = inner.next().ok_or_else(|| "Class pattern missing class name".to_string())?.as_str().to_string();
+            let mut positional = Vec::new();
+            let mut keywords = Vec::new();
+            for arg in inner {
+                if arg.as_rule() == Rule::keyword_pattern {
+                    let mut kw = arg.into_inner();
+                    let attr = kw.next().ok_or_else(|| "Keyword pattern missing name".to_string())?.as_str().to_string();
+                    let value = parse_pattern(kw.next().ok_or_else(|| "Keyword pattern missing value".to_string())?)?;
+                    keywords.push((attr, value));
+                } else if !keywords.is_empty() {
+                    return Err(format!("positional patterns follow keyword patterns in class pattern '{}'", name));
+                } else {
+                    positional.push(parse_pattern(arg)?);
+                }
+            }
+            Ok(Pattern::Class { name, positional, keywords })
+        }
+        rule => Err(format!("Unexpected pattern rule: {:?}", rule)),
+    }
 }
 
 // Function to parse comprehension_for clause
@@ -1289,3 +1755,28 @@ fn parse_generator_expression(pair: Pair<Rule>) -> Result<Expression, String> {
         comprehension,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statements_on_one_line_need_a_semicolon() {
+        let error = parse_eppx_located("a = 1\nb = 2 3\n").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 7));
+        let error = parse_eppx_located("a = 1\ne = f'{a} x'\n").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 6));
+        assert!(error.message.contains("newline or `;`"));
+
+        assert_eq!(parse_eppx_string("a = 1; b = 2\nif a:\n    c = 3; d = 4\n").unwrap().len(), 3);
+        assert_eq!(parse_eppx_string("x = [1,\n     2]\ny = (x  # comment\n)\n").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_unclosed_bracket_at_the_end_is_reported_on_the_last_line() {
+        let error = parse_eppx_located("a = 1\nprint((a\n").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 9));
+        let error = parse_eppx_located("def f():\n    x = (1,").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 12));
+    }
+}
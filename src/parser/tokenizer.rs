@@ -0,0 +1,530 @@
+//! Tokenizer: splits an E++ source file into tokens, turning the indentation
+//! of its logical lines into INDENT/DEDENT tokens. The parser only takes the
+//! layout tokens from here (as the explicit @INDENT@/@DEDENT@ markers the
+//! grammar expects) and leaves the rest to pest; `--emit tokens` lists them all.
+
+use pest::error::{Error, LineColLocation};
+use pest::Position;
+
+use super::Rule;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Indent,
+    Dedent,
+    /// End of a logical line
+    Newline,
+    /// `;` between two simple statements
+    Semicolon,
+    /// A `\` that joins the next physical line onto this one
+    Continuation,
+    /// An identifier or keyword
+    Name,
+    Number,
+    /// A string literal with its prefix and quotes (`f"..."`, `'''...'''`)
+    String,
+    /// An operator or delimiter (`+`, `**=`, `(`, `:`, ...)
+    Op,
+    /// A `#` comment, up to the end of its line
+    Comment,
+}
+
+impl TokenKind {
+    /// Name used when listing tokens, as Python's tokenize module spells them
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenKind::Indent => "INDENT",
+            TokenKind::Dedent => "DEDENT",
+            TokenKind::Newline => "NEWLINE",
+            TokenKind::Semicolon => "SEMI",
+            TokenKind::Continuation => "CONTINUATION",
+            TokenKind::Name => "NAME",
+            TokenKind::Number => "NUMBER",
+            TokenKind::String => "STRING",
+            TokenKind::Op => "OP",
+            TokenKind::Comment => "COMMENT",
+        }
+    }
+
+    /// Whether the token shapes the layout of logical lines and blocks
+    pub fn is_layout(self) -> bool {
+        matches!(self, TokenKind::Indent | TokenKind::Dedent | TokenKind::Newline | TokenKind::Semicolon | TokenKind::Continuation)
+    }
+}
+
+/// A token; `line` and `column` are 1-based positions in the original source,
+/// `start..end` its bytes there (empty for INDENT, DEDENT and NEWLINE).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub line: usize,
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Token {
+    /// The token as written in `source`
+    pub fn text(self, source: &str) -> &str {
+        &source[self.start..self.end]
+    }
+}
+
+/// Operators of more than one character, longest first
+const LONG_OPERATORS: &[&str] = &[
+    "**=", "//=", ">>=", "<<=", "...", "**", "//", "==", "!=", "<=", ">=", "->", ":=", "+=", "-=", "*=", "/=", "%=", "&=",
+    "|=", "^=", "@=", "<<", ">>",
+];
+
+/// Leading whitespace measured the two ways Python compares it: tabs to the
+/// next multiple of 8, and tabs as a single column. Indentation is only
+/// consistent when both measures order lines the same way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Indentation {
+    tab8: usize,
+    tab1: usize,
+}
+
+impl Indentation {
+    fn push(&mut self, ch: char) {
+        if ch == '\t' {
+            self.tab8 = (self.tab8 / 8 + 1) * 8;
+        } else {
+            self.tab8 += 1;
+        }
+        self.tab1 += 1;
+    }
+}
+
+/// Inconsistent indentation, found on a 1-based line of the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (line {})", self.message, self.line)
+    }
+}
+
+fn tab_error(line: usize) -> LayoutError {
+    LayoutError { line, message: "TabError: inconsistent use of tabs and spaces in indentation".to_string() }
+}
+
+/// Splits `source` into its layout tokens. Lines inside brackets, strings or
+/// after a `\` continuation never change the indentation level.
+pub fn tokenize(source: &str) -> Result<Vec<Token>, LayoutError> {
+    Ok(tokenize_all(source)?.into_iter().filter(|token| token.kind.is_layout()).collect())
+}
+
+/// Splits `source` into all of its tokens, in source order.
+pub fn tokenize_all(source: &str) -> Result<Vec<Token>, LayoutError> {
+    match lex(source) {
+        (tokens, None) => Ok(tokens),
+        (_, Some(error)) => Err(error),
+    }
+}
+
+/// All of the tokens of `source`, lexing on past inconsistent indentation, for
+/// the tools that read a file without running it: lint, fmt and the language server.
+pub fn tokenize_lenient(source: &str) -> Vec<Token> {
+    lex(source).0
+}
+
+/// The tokens of `source` and its first layout error, if any. Lexing goes on
+/// past an inconsistently indented line as if its indentation were consistent.
+fn lex(source: &str) -> (Vec<Token>, Option<LayoutError>) {
+    let chars: Vec<char> = source.chars().collect();
+    // Byte offset of every character, and of the end of the source
+    let offsets: Vec<usize> = source.char_indices().map(|(offset, _)| offset).chain([source.len()]).collect();
+    let token = |kind, line, column, from: usize, to: usize| Token { kind, line, column, start: offsets[from], end: offsets[to] };
+    let mut tokens = Vec::new();
+    let mut error = None;
+    let mut indents = vec![Indentation::default()];
+    let mut depth = 0usize;
+    let mut line = 1;
+    let mut column = 1;
+    let mut at_line_start = true;
+    let mut continued = false;
+    let mut has_content = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        if at_line_start {
+            at_line_start = false;
+            let mut indentation = Indentation::default();
+            while i < chars.len() && matches!(chars[i], ' ' | '\t' | '\x0c') {
+                if chars[i] == '\x0c' {
+                    // A form feed resets the indentation count, as in Python
+                    indentation = Indentation::default();
+                } else {
+                    indentation.push(chars[i]);
+                }
+                i += 1;
+                column += 1;
+            }
+            let blank = i >= chars.len() || matches!(chars[i], '\n' | '\r' | '#');
+            if !blank && depth == 0 && !continued {
+                let current = *indents.last().unwrap();
+                if indentation.tab8 > current.tab8 {
+                    if indentation.tab1 <= current.tab1 {
+                        error = error.or(Some(tab_error(line)));
+                    }
+                    indents.push(indentation);
+                    tokens.push(token(TokenKind::Indent, line, column, i, i));
+                } else {
+                    while indentation.tab8 < indents.last().unwrap().tab8 {
+                        indents.pop();
+                        tokens.push(token(TokenKind::Dedent, line, column, i, i));
+                    }
+                    let outer = *indents.last().unwrap();
+                    if indentation.tab8 != outer.tab8 {
+                        error = error.or(Some(LayoutError {
+                            line,
+                            message: "IndentationError: unindent does not match any outer indentation level".to_string(),
+                        }));
+                    } else if indentation.tab1 != outer.tab1 {
+                        error = error.or(Some(tab_error(line)));
+                    }
+                }
+            }
+            continued = false;
+            continue;
+        }
+
+        let ch = chars[i];
+        let start = i;
+        let kind = match ch {
+            '\n' => {
+                if depth == 0 && has_content {
+                    tokens.push(token(TokenKind::Newline, line, column, i, i));
+                    has_content = false;
+                }
+                i += 1;
+                line += 1;
+                column = 1;
+                at_line_start = true;
+                continue;
+            }
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                // A CRLF line's comment ends before the \r
+                let end = if i > start + 1 && chars[i - 1] == '\r' { i - 1 } else { i };
+                tokens.push(token(TokenKind::Comment, line, column, start, end));
+                column += i - start;
+                continue;
+            }
+            '\\' if chars.get(i + 1) == Some(&'\n') || (chars.get(i + 1) == Some(&'\r') && chars.get(i + 2) == Some(&'\n')) => {
+                tokens.push(token(TokenKind::Continuation, line, column, i, i + 1));
+                i += if chars[i + 1] == '\r' { 3 } else { 2 };
+                line += 1;
+                column = 1;
+                at_line_start = true;
+                continued = true;
+                continue;
+            }
+            '"' | '\'' => {
+                i += scan_string(&chars[i..]).0;
+                TokenKind::String
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                // String prefixes such as f"..." belong to the string
+                let prefix = i - start <= 2 && chars[start..i].iter().all(|c| "rRbBfFuU".contains(*c));
+                if prefix && matches!(chars.get(i), Some('"' | '\'')) {
+                    i += scan_string(&chars[i..]).0;
+                    TokenKind::String
+                } else {
+                    TokenKind::Name
+                }
+            }
+            c if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())) => {
+                // 1.5, 1_000, 0x1F, 2j, and exponents with a sign (1e-3)
+                let hex = matches!(chars.get(i + 1), Some('x' | 'X'));
+                while i < chars.len() {
+                    let c = chars[i];
+                    let sign = matches!(c, '+' | '-') && matches!(chars[i - 1], 'e' | 'E') && !hex;
+                    if !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || sign) {
+                        break;
+                    }
+                    i += 1;
+                }
+                TokenKind::Number
+            }
+            c if c.is_whitespace() => {
+                i += 1;
+                column += 1;
+                continue;
+            }
+            ';' if depth == 0 => {
+                i += 1;
+                TokenKind::Semicolon
+            }
+            _ => {
+                match ch {
+                    '(' | '[' | '{' => depth += 1,
+                    ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+                i += LONG_OPERATORS.iter().find(|op| rest.starts_with(*op)).map_or(1, |op| op.len());
+                TokenKind::Op
+            }
+        };
+        tokens.push(token(kind, line, column, start, i));
+        has_content = true;
+        for &c in &chars[start..i] {
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+    }
+
+    if has_content {
+        tokens.push(token(TokenKind::Newline, line, column, i, i));
+    }
+    if !at_line_start {
+        // The file doesn't end in a newline; closing dedents go after its last line
+        line += 1;
+        column = 1;
+    }
+    while indents.len() > 1 {
+        indents.pop();
+        tokens.push(token(TokenKind::Dedent, line, column, i, i));
+    }
+    (tokens, error)
+}
+
+/// Measures the string literal starting at `chars[0]`, returning how many
+/// characters it spans and how many newlines it contains. Single-quoted
+/// strings stop at the end of the line so an unterminated literal is reported
+/// by the parser rather than swallowing the rest of the file.
+fn scan_string(chars: &[char]) -> (usize, usize) {
+    let quote = chars[0];
+    let triple = chars.len() >= 3 && chars[1] == quote && chars[2] == quote;
+    let mut i = if triple { 3 } else { 1 };
+    let mut lines = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                if chars[i + 1] == '\n' {
+                    lines += 1;
+                }
+                i += 2;
+                continue;
+            }
+            '\n' if !triple => break,
+            '\n' => lines += 1,
+            c if c == quote => {
+                if !triple {
+                    return (i + 1, lines);
+                }
+                if chars.len() >= i + 3 && chars[i + 1] == quote && chars[i + 2] == quote {
+                    return (i + 3, lines);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    (i, lines)
+}
+
+/// The text handed to pest, with a map back to the original source.
+pub struct TokenizedSource<'a> {
+    original: &'a str,
+    pub text: String,
+    /// Original line number for every line of `text`
+    line_origins: Vec<usize>,
+}
+
+impl<'a> TokenizedSource<'a> {
+    pub fn new(original: &'a str) -> Result<Self, LayoutError> {
+        let tokens = tokenize(original)?;
+        let mut text = String::with_capacity(original.len());
+        let mut line_origins = Vec::new();
+        let mut pending = tokens.iter().peekable();
+
+        for (index, physical) in original.split('\n').enumerate() {
+            let line = index + 1;
+            let mut physical = physical.to_string();
+            while let Some(token) = pending.next_if(|token| token.line == line) {
+                match token.kind {
+                    TokenKind::Indent | TokenKind::Dedent => {
+                        text.push_str(if token.kind == TokenKind::Indent { "@INDENT@\n" } else { "@DEDENT@\n" });
+                        line_origins.push(line);
+                    }
+                    TokenKind::Continuation => {
+                        // Blank out the backslash so columns still line up
+                        let offset = physical.char_indices().nth(token.column - 1).map(|(offset, _)| offset).unwrap_or(0);
+                        physical.replace_range(offset..offset + 1, " ");
+                    }
+                    _ => {}
+                }
+            }
+            text.push_str(&physical);
+            text.push('\n');
+            line_origins.push(line);
+        }
+        // Dedents closing the file sit past its last line
+        let past_the_end = line_origins.last().copied().unwrap_or(0) + 1;
+        for _ in pending {
+            text.push_str("@DEDENT@\n");
+            line_origins.push(past_the_end);
+        }
+
+        Ok(TokenizedSource { original, text, line_origins })
+    }
+
+    /// The 1-based line and column in the original source of a position in `text`.
+    pub fn original_position(&self, line: usize, column: usize) -> (usize, usize) {
+        let text_line = self.text.split('\n').nth(line - 1).unwrap_or("");
+        // Past the last line with code in it (an unclosed bracket or block at the
+        // end of the file), point just after that line's end
+        let content = self.original.trim_end();
+        let last_line = content.split('\n').count();
+        let end_of_file = (last_line, content.rsplit('\n').next().unwrap_or("").trim_end_matches('\r').chars().count() + 1);
+        let original_line = match self.line_origins.get(line - 1) {
+            Some(&original_line) if original_line <= last_line => original_line,
+            _ => return end_of_file,
+        };
+        // Marker lines have no counterpart in the source; point at the line they precede
+        let column = if text_line.starts_with("@INDENT@") || text_line.starts_with("@DEDENT@") { 1 } else { column };
+        (original_line, column)
+    }
+
+    /// Re-anchors a pest error on `text` to the same place in the original source.
+    pub fn remap_error(&self, error: Error<Rule>) -> Error<Rule> {
+        let (line, column) = match error.line_col {
+            LineColLocation::Pos(pos) | LineColLocation::Span(pos, _) => pos,
+        };
+        let (original_line, column) = self.original_position(line, column);
+
+        let line_start: usize = self.original.split('\n').take(original_line - 1).map(|l| l.len() + 1).sum();
+        let source_line = self.original[line_start.min(self.original.len())..].split('\n').next().unwrap_or("");
+        let column_offset = source_line.char_indices().nth(column - 1).map(|(offset, _)| offset).unwrap_or(source_line.len());
+        match Position::new(self.original, (line_start + column_offset).min(self.original.len())) {
+            Some(position) => Error::new_from_pos(error.variant, position),
+            None => error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<&'static str> {
+        tokenize(source).unwrap().iter().map(|token| token.kind.name()).collect()
+    }
+
+    #[test]
+    fn test_indent_and_dedent() {
+        let source = "if x:\n    y = 1\n    if y:\n        z = 2\nw = 3\n";
+        assert_eq!(
+            kinds(source),
+            ["NEWLINE", "INDENT", "NEWLINE", "NEWLINE", "INDENT", "NEWLINE", "DEDENT", "DEDENT", "NEWLINE"]
+        );
+        let tokens = tokenize(source).unwrap();
+        assert_eq!((tokens[1].line, tokens[1].column), (2, 5));
+        assert_eq!((tokens[6].line, tokens[6].column), (5, 1));
+    }
+
+    #[test]
+    fn test_dedents_close_the_file() {
+        let tokens = tokenize("def f():\n    return 1").unwrap();
+        let last = tokens.last().unwrap();
+        assert_eq!(last.kind, TokenKind::Dedent);
+        assert_eq!((last.line, last.column), (3, 1));
+    }
+
+    #[test]
+    fn test_brackets_strings_and_comments_keep_the_layout() {
+        let source = "x = [1,\n        2]\ns = \"\"\"a\n  b\"\"\"\n# comment\n\n    # indented comment\ny = (x  # trailing\n)\n";
+        assert_eq!(kinds(source), ["NEWLINE", "NEWLINE", "NEWLINE"]);
+    }
+
+    #[test]
+    fn test_semicolons_and_continuations() {
+        assert_eq!(kinds("a = 1; b = 2\n"), ["SEMI", "NEWLINE"]);
+        assert_eq!(kinds("f(a; b)\n"), ["NEWLINE"]);
+        let tokens = tokenize("x = 1 + \\\n    2\ny = 3\n").unwrap();
+        assert_eq!(tokens.iter().map(|token| token.kind.name()).collect::<Vec<_>>(), ["CONTINUATION", "NEWLINE", "NEWLINE"]);
+        assert_eq!((tokens[0].line, tokens[0].column), (1, 9));
+    }
+
+    #[test]
+    fn test_inconsistent_indentation() {
+        let error = tokenize("if x:\n        y = 1\n    z = 2\n").unwrap_err();
+        assert_eq!(error.line, 3);
+        assert!(error.message.starts_with("IndentationError"));
+
+        let error = tokenize("if x:\n\ty = 1\n        z = 2\n").unwrap_err();
+        assert_eq!(error.line, 3);
+        assert!(error.message.starts_with("TabError"));
+    }
+
+    #[test]
+    fn test_all_tokens_with_their_text() {
+        let source = "x = f'{a}' + 0x1F**2 # done\nif é >= .5e-3:\n    y[1] //= 2;\n";
+        let tokens = tokenize_all(source).unwrap();
+        let listed: Vec<(&str, &str)> = tokens.iter().map(|token| (token.kind.name(), token.text(source))).collect();
+        assert_eq!(
+            listed,
+            [
+                ("NAME", "x"), ("OP", "="), ("STRING", "f'{a}'"), ("OP", "+"), ("NUMBER", "0x1F"), ("OP", "**"), ("NUMBER", "2"),
+                ("COMMENT", "# done"), ("NEWLINE", ""),
+                ("NAME", "if"), ("NAME", "é"), ("OP", ">="), ("NUMBER", ".5e-3"), ("OP", ":"), ("NEWLINE", ""),
+                ("INDENT", ""), ("NAME", "y"), ("OP", "["), ("NUMBER", "1"), ("OP", "]"), ("OP", "//="), ("NUMBER", "2"),
+                ("SEMI", ";"), ("NEWLINE", ""), ("DEDENT", ""),
+            ]
+        );
+        // Columns count characters, offsets count bytes
+        assert_eq!((tokens[11].line, tokens[11].column, tokens[11].start), (2, 6, 34));
+    }
+
+    #[test]
+    fn test_lenient_tokens_go_on_past_layout_errors() {
+        let source = "if x:\n        y = 1\n    z = 2  # after\n";
+        assert!(tokenize_all(source).is_err());
+        let tokens = tokenize_lenient(source);
+        let names: Vec<&str> = tokens.iter().filter(|token| !token.kind.is_layout()).map(|token| token.text(source)).collect();
+        assert_eq!(names, ["if", "x", ":", "y", "=", "1", "z", "=", "2", "# after"]);
+    }
+
+    #[test]
+    fn test_multiline_strings_and_crlf_comments() {
+        let source = "s = '''a\nb''' # c\r\nt = 1\r\n";
+        let tokens = tokenize_all(source).unwrap();
+        let comment = tokens.iter().find(|token| token.kind == TokenKind::Comment).unwrap();
+        assert_eq!((comment.text(source), comment.line, comment.column), ("# c", 2, 6));
+        let t = tokens.iter().find(|token| token.text(source) == "t").unwrap();
+        assert_eq!((t.line, t.column), (3, 1));
+    }
+
+    #[test]
+    fn test_tokenized_source_maps_back_to_the_original() {
+        let source = TokenizedSource::new("if x:\n    y = 1\nz = 2\n").unwrap();
+        assert_eq!(source.text, "if x:\n@INDENT@\n    y = 1\n@DEDENT@\nz = 2\n\n");
+        assert_eq!(source.original_position(3, 5), (2, 5));
+        assert_eq!(source.original_position(4, 3), (3, 1));
+        assert_eq!(source.original_position(5, 3), (3, 3));
+    }
+
+    #[test]
+    fn test_positions_past_the_end_point_at_the_last_line() {
+        let source = TokenizedSource::new("a = 1\nprint((a\n\n").unwrap();
+        assert_eq!(source.original_position(5, 1), (2, 9));
+        let source = TokenizedSource::new("def f():\n    x = (1,").unwrap();
+        assert_eq!(source.original_position(4, 1), (2, 12));
+    }
+}
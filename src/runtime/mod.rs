@@ -1,8 +1,6 @@
 use std::collections::HashMap;
 use crate::ast::AstNode;
 
-pub mod file;
-
 // Runtime value types for file objects
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
@@ -5,6 +5,8 @@
 #include <vector>
 #include <map>
 #include <set>
+#include <unordered_set>
+#include <unordered_map>
 #include <iostream>
 #include <sstream>
 #include <algorithm>
@@ -13,26 +15,526 @@
 #include <functional>
 #include <type_traits>
 #include <iomanip>
-#include <fstream>
 #include <memory>
 #include <stdexcept>
-#include <fstream>
 #include <optional>
 #include <tuple>
 #include <cmath>
+#include <complex>
 #include <cstdint>
+#include <cstdio>
+#include <cstdlib>
+
+// Type-independent runtime (file I/O, str methods and conversions, the exception
+// hierarchy) is in the Rust runtime library declared by eppx_runtime.h. What
+// stays here is generic over the program's own types and so can't cross a C ABI:
+// eppx_variant, the containers and formatting. Generator and coroutine frames,
+// match statements and file objects are in headers of their own
+// (eppx_generator.hpp, eppx_asyncio.hpp, eppx_match.hpp, eppx_file.hpp), which
+// the generated code only includes when the program uses them.
+#include "eppx_runtime.h"
+
+// Python complex: a pair of doubles. `.real`/`.imag` are plain members;
+// arithmetic mixes freely with ints and floats.
+struct eppx_complex {
+    double real = 0.0;
+    double imag = 0.0;
+
+    eppx_complex() = default;
+    eppx_complex(double real_part, double imag_part) : real(real_part), imag(imag_part) {}
+    explicit eppx_complex(const std::complex<double>& c) : real(c.real()), imag(c.imag()) {}
+
+    std::complex<double> to_std() const { return {real, imag}; }
+    eppx_complex conjugate() const { return {real, -imag}; }
+};
+
+template<typename T>
+struct eppx_is_complex_operand : std::bool_constant<std::is_arithmetic_v<T> || std::is_same_v<T, eppx_complex>> {};
+
+// Enables the mixed operators below when one side is complex and the other a number
+template<typename A, typename B>
+using eppx_complex_op_t = std::enable_if_t<
+    (std::is_same_v<A, eppx_complex> || std::is_same_v<B, eppx_complex>)
+    && eppx_is_complex_operand<A>::value && eppx_is_complex_operand<B>::value, eppx_complex>;
+
+template<typename T>
+std::complex<double> eppx_as_std_complex(const T& value) {
+    if constexpr (std::is_same_v<T, eppx_complex>) {
+        return value.to_std();
+    } else {
+        return {static_cast<double>(value), 0.0};
+    }
+}
+
+template<typename A, typename B>
+eppx_complex_op_t<A, B> operator+(const A& left, const B& right) {
+    return eppx_complex(eppx_as_std_complex(left) + eppx_as_std_complex(right));
+}
+
+template<typename A, typename B>
+eppx_complex_op_t<A, B> operator-(const A& left, const B& right) {
+    return eppx_complex(eppx_as_std_complex(left) - eppx_as_std_complex(right));
+}
+
+template<typename A, typename B>
+eppx_complex_op_t<A, B> operator*(const A& left, const B& right) {
+    return eppx_complex(eppx_as_std_complex(left) * eppx_as_std_complex(right));
+}
+
+template<typename A, typename B>
+eppx_complex_op_t<A, B> operator/(const A& left, const B& right) {
+    std::complex<double> divisor = eppx_as_std_complex(right);
+    if (divisor == std::complex<double>(0.0, 0.0)) {
+        throw std::domain_error("complex division by zero");
+    }
+    return eppx_complex(eppx_as_std_complex(left) / divisor);
+}
+
+// `**`: complex when either side is complex, as in Python; 0 to a negative or
+// complex power has no value
+template<typename A, typename B>
+eppx_complex_op_t<A, B> eppx_power(const A& base, const B& exponent) {
+    std::complex<double> b = eppx_as_std_complex(base);
+    std::complex<double> e = eppx_as_std_complex(exponent);
+    if (b == std::complex<double>(0.0, 0.0)) {
+        if (e == std::complex<double>(0.0, 0.0)) {
+            return eppx_complex(1.0, 0.0);
+        }
+        if (e.imag() != 0.0 || e.real() < 0.0) {
+            throw std::domain_error("0.0 to a negative or complex power");
+        }
+        return eppx_complex();
+    }
+    // Small integer powers multiply out exactly, as CPython does: (1+2j) ** 2 is (-3+4j)
+    if (e.imag() == 0.0 && e.real() == std::trunc(e.real()) && std::abs(e.real()) <= 100.0) {
+        long long n = static_cast<long long>(std::abs(e.real()));
+        std::complex<double> result(1.0, 0.0);
+        for (std::complex<double> square = b; n > 0; n >>= 1, square *= square) {
+            if (n & 1) {
+                result *= square;
+            }
+        }
+        return eppx_complex(e.real() < 0.0 ? std::complex<double>(1.0, 0.0) / result : result);
+    }
+    return eppx_complex(std::pow(b, e));
+}
+
+// Between real numbers `**` keeps its integer result
+template<typename A, typename B, typename = std::enable_if_t<std::is_arithmetic_v<A> && std::is_arithmetic_v<B>>>
+long long eppx_power(const A& base, const B& exponent) {
+    return static_cast<long long>(std::pow(base, exponent));
+}
+
+inline eppx_complex operator-(const eppx_complex& value) { return {-value.real, -value.imag}; }
+inline eppx_complex operator+(const eppx_complex& value) { return value; }
+
+template<typename A, typename B, typename = eppx_complex_op_t<A, B>>
+bool operator==(const A& left, const B& right) {
+    return eppx_as_std_complex(left) == eppx_as_std_complex(right);
+}
+
+template<typename A, typename B, typename = eppx_complex_op_t<A, B>>
+bool operator!=(const A& left, const B& right) {
+    return !(left == right);
+}
+
+// Shortest text that reads back as the same double, without a trailing ".0"
+// (the form complex parts take in Python's repr)
+inline std::string eppx_complex_part_repr(double value) {
+    if (std::isnan(value)) return "nan";
+    if (std::isinf(value)) return value < 0 ? "-inf" : "inf";
+    char buffer[32];
+    for (int precision = 1; precision <= 17; ++precision) {
+        std::snprintf(buffer, sizeof(buffer), "%.*g", precision, value);
+        if (std::strtod(buffer, nullptr) == value) {
+            break;
+        }
+    }
+    return buffer;
+}
+
+// (1+2j), (1.5-0.5j), or 2j when the real part is +0
+inline std::string eppx_complex_repr(const eppx_complex& value) {
+    std::string imag = eppx_complex_part_repr(value.imag) + "j";
+    if (value.real == 0.0 && !std::signbit(value.real)) {
+        return imag;
+    }
+    std::string sign = (std::signbit(value.imag) || std::isnan(value.imag)) && imag[0] == '-' ? "" : "+";
+    return "(" + eppx_complex_part_repr(value.real) + sign + imag + ")";
+}
+
+inline std::ostream& operator<<(std::ostream& os, const eppx_complex& value) {
+    return os << eppx_complex_repr(value);
+}
+
+// Memory management: lists live on the heap and are shared through
+// reference-counted eppx_list_ref handles, so `b = a` or passing a list to a
+// function aliases it as in Python. Reference counting frees a list as soon as
+// the last handle goes away; objects that only keep each other alive
+// (`a.append(a)`) are found by the cycle collector in eppx_gc::collect().
+namespace eppx_gc {
+
+// Base of every heap object that can take part in a reference cycle
+struct object {
+    long long refcount = 0;
+    object* prev = nullptr; // list of all tracked objects
+    object* next = nullptr;
+    long long gc_refs = 0;  // scratch space for collect()
+    bool reachable = false;
+
+    object();
+    object(const object&) = delete;
+    object& operator=(const object&) = delete;
+    virtual ~object();
+
+    // Calls `visit` on each object this one holds a reference to
+    virtual void traverse(const std::function<void(object*)>& visit) = 0;
+    // Drops all references to other objects
+    virtual void clear() = 0;
+
+    void incref() { ++refcount; }
+    void decref() {
+        if (--refcount == 0) {
+            delete this;
+        }
+    }
+};
+
+struct collector_state {
+    object* first = nullptr;
+    bool enabled = true;
+    bool collecting = false;
+    long long allocations = 0;   // since the last collection
+    long long threshold = 700;   // CPython's generation 0 threshold
+};
+
+inline collector_state& state() {
+    static collector_state instance;
+    return instance;
+}
+
+// Frees every tracked object that is unreachable from outside the tracked
+// objects (CPython's algorithm): references held by tracked objects are
+// subtracted from each reference count, whatever is left over is referenced
+// from the program, and anything those roots can't reach is garbage.
+// Returns the number of objects freed.
+inline long long collect() {
+    collector_state& gc = state();
+    if (gc.collecting) {
+        return 0;
+    }
+    gc.collecting = true;
+    gc.allocations = 0;
+    for (object* o = gc.first; o; o = o->next) {
+        o->gc_refs = o->refcount;
+        o->reachable = false;
+    }
+    for (object* o = gc.first; o; o = o->next) {
+        o->traverse([](object* child) { --child->gc_refs; });
+    }
+    std::vector<object*> pending;
+    for (object* o = gc.first; o; o = o->next) {
+        if (o->gc_refs > 0) {
+            o->reachable = true;
+            pending.push_back(o);
+        }
+    }
+    while (!pending.empty()) {
+        object* o = pending.back();
+        pending.pop_back();
+        o->traverse([&pending](object* child) {
+            if (!child->reachable) {
+                child->reachable = true;
+                pending.push_back(child);
+            }
+        });
+    }
+    std::vector<object*> garbage;
+    for (object* o = gc.first; o; o = o->next) {
+        if (!o->reachable) {
+            garbage.push_back(o);
+        }
+    }
+    // Keep the garbage alive while clearing it so no object is freed twice
+    for (object* o : garbage) o->incref();
+    for (object* o : garbage) o->clear();
+    for (object* o : garbage) o->decref();
+    gc.collecting = false;
+    return static_cast<long long>(garbage.size());
+}
+
+inline object::object() {
+    collector_state& gc = state();
+    if (gc.enabled && ++gc.allocations > gc.threshold) {
+        collect();
+    }
+    next = gc.first;
+    if (gc.first) {
+        gc.first->prev = this;
+    }
+    gc.first = this;
+}
+
+inline object::~object() {
+    collector_state& gc = state();
+    if (prev) {
+        prev->next = next;
+    } else {
+        gc.first = next;
+    }
+    if (next) {
+        next->prev = prev;
+    }
+}
+
+} // namespace eppx_gc
 
-// Forward declaration for recursive variant
 struct eppx_variant;
+struct eppx_list_object;
+
+// Calls `visit` on each object `value` holds a reference to, and drops those
+// references (overloads below, after the containers they take)
+inline void eppx_visit_refs(const eppx_variant& value, const std::function<void(eppx_gc::object*)>& visit);
+
+// Handle to a heap-allocated list; copies share the list
+class eppx_list_ref {
+public:
+    eppx_list_ref();                                 // a new empty list
+    eppx_list_ref(std::vector<eppx_variant> items);  // a new list holding `items`
+    eppx_list_ref(const eppx_list_ref& other);
+    eppx_list_ref& operator=(const eppx_list_ref& other);
+    ~eppx_list_ref();
+
+    std::vector<eppx_variant>& operator*() const;
+    std::vector<eppx_variant>* operator->() const;
+    eppx_list_object* get() const { return object_; }
+
+private:
+    eppx_list_object* object_;
+};
+
+// Dicts, sets and class instances are shared the same way as lists: variables
+// hold an eppx_ref handle to a reference-counted eppx_shared_object, so `e = d`,
+// `f(d)` and `return d` alias the object instead of copying it. Variants hold
+// these handles as well, so dicts, sets and instances can be stored in lists,
+// dicts and instance members and take part in reference cycles like lists do
+template<typename K, typename V>
+class eppx_dict;
+
+template<typename T>
+void eppx_visit_refs(const T& value, const std::function<void(eppx_gc::object*)>& visit);
+template<typename T>
+void eppx_clear_refs(T& value);
+template<typename T>
+void eppx_write_object(std::ostream& os, const T& value);
+
+// Base of the objects behind eppx_ref handles; instances held by a variant
+// are printed through it
+struct eppx_shared_base : eppx_gc::object {
+    virtual void write(std::ostream& os) const = 0;
+};
+
+// Classes from the program carry their name, unlike the runtime's containers
+template<typename T, typename = void>
+inline constexpr bool eppx_is_instance_v = false;
+template<typename T>
+inline constexpr bool eppx_is_instance_v<T, std::void_t<decltype(T::__name__)>> = true;
+
+// Instances on the heap by their address, so a method returning `self` can
+// hand out the instance's own handle
+inline std::unordered_map<const void*, eppx_shared_base*>& eppx_instance_objects() {
+    static std::unordered_map<const void*, eppx_shared_base*> objects;
+    return objects;
+}
+
+template<typename T>
+struct eppx_shared_object : eppx_shared_base {
+    T value;
+
+    explicit eppx_shared_object(T initial) : value(std::move(initial)) {
+        if constexpr (eppx_is_instance_v<T>) {
+            eppx_instance_objects()[&value] = this;
+        }
+    }
+
+    ~eppx_shared_object() override {
+        if constexpr (eppx_is_instance_v<T>) {
+            eppx_instance_objects().erase(&value);
+        }
+    }
+
+    void traverse(const std::function<void(eppx_gc::object*)>& visit) override {
+        eppx_visit_refs(value, visit);
+    }
+
+    void clear() override {
+        eppx_clear_refs(value);
+    }
+
+    void write(std::ostream& os) const override {
+        eppx_write_object(os, value);
+    }
+};
+
+// Handle to a heap-allocated dict, set or instance; copies share the object
+template<typename T>
+class eppx_ref {
+public:
+    eppx_ref() : eppx_ref(T{}) {}  // a new empty object, for frame members
+    // A new object holding `value`; implicit so `d = {...}` rebinds a handle
+    eppx_ref(T value) : object_(new eppx_shared_object<T>(std::move(value))) {
+        object_->incref();
+    }
+    // Another handle to an existing object
+    explicit eppx_ref(eppx_shared_object<T>* object) : object_(object) {
+        object_->incref();
+    }
+    eppx_ref(const eppx_ref& other) : object_(other.object_) {
+        object_->incref();
+    }
+    eppx_ref& operator=(const eppx_ref& other) {
+        other.object_->incref();
+        eppx_shared_object<T>* old = object_;
+        object_ = other.object_;
+        old->decref();
+        return *this;
+    }
+    ~eppx_ref() {
+        object_->decref();
+    }
+
+    T& operator*() const { return object_->value; }
+    T* operator->() const { return &object_->value; }
+    eppx_shared_object<T>* get() const { return object_; }
+
+private:
+    eppx_shared_object<T>* object_;
+};
+
+template<typename T>
+inline constexpr bool eppx_is_ref_v = false;
+template<typename T>
+inline constexpr bool eppx_is_ref_v<eppx_ref<T>> = true;
+
+using eppx_dict_ref = eppx_ref<eppx_dict<eppx_variant, eppx_variant>>;
+using eppx_set_ref = eppx_ref<std::set<eppx_variant>>;
+
+// A class instance held by a variant; copies share the instance
+class eppx_object_ref {
+public:
+    template<typename T, typename = std::enable_if_t<!std::is_same_v<T, eppx_dict<eppx_variant, eppx_variant>> && !std::is_same_v<T, std::set<eppx_variant>>>>
+    eppx_object_ref(const eppx_ref<T>& ref) : object_(ref.get()) {
+        object_->incref();
+    }
+    explicit eppx_object_ref(eppx_shared_base* object) : object_(object) {
+        object_->incref();
+    }
+    eppx_object_ref(const eppx_object_ref& other) : object_(other.object_) {
+        object_->incref();
+    }
+    eppx_object_ref& operator=(const eppx_object_ref& other) {
+        other.object_->incref();
+        eppx_shared_base* old = object_;
+        object_ = other.object_;
+        old->decref();
+        return *this;
+    }
+    ~eppx_object_ref() {
+        object_->decref();
+    }
+
+    eppx_shared_base* get() const { return object_; }
+
+    // The handle to the instance, or nullptr when it is of another class
+    template<typename T>
+    std::optional<eppx_ref<T>> as() const {
+        auto* object = dynamic_cast<eppx_shared_object<T>*>(object_);
+        return object ? std::optional<eppx_ref<T>>(eppx_ref<T>(object)) : std::nullopt;
+    }
+
+private:
+    eppx_shared_base* object_;
+};
+
+// Instances are the same object only when they are the same instance
+inline bool operator==(const eppx_object_ref& left, const eppx_object_ref& right) {
+    return left.get() == right.get();
+}
+
+inline bool operator<(const eppx_object_ref& left, const eppx_object_ref& right) {
+    return left.get() < right.get();
+}
 
-// Basic variant type for E++ values - recursive to support nested lists
-using eppx_variant_base = std::variant<long long, std::string, double, bool, std::vector<eppx_variant>>;
+inline std::ostream& operator<<(std::ostream& os, const eppx_object_ref& ref) {
+    ref.get()->write(os);
+    return os;
+}
+
+// Basic variant type for E++ values - recursive to support nested lists.
+// std::nullptr_t is None (e.g. the result of dict.get() on a missing key).
+using eppx_variant_base = std::variant<long long, std::string, double, bool, eppx_list_ref, std::nullptr_t, eppx_complex,
+                                       eppx_dict_ref, eppx_set_ref, eppx_object_ref>;
 
 struct eppx_variant : public eppx_variant_base {
     using eppx_variant_base::eppx_variant_base;
     using eppx_variant_base::operator=;
 };
 
+struct eppx_list_object : eppx_gc::object {
+    std::vector<eppx_variant> items;
+
+    explicit eppx_list_object(std::vector<eppx_variant> initial) : items(std::move(initial)) {}
+
+    void traverse(const std::function<void(eppx_gc::object*)>& visit) override {
+        for (const auto& item : items) {
+            eppx_visit_refs(item, visit);
+        }
+    }
+
+    void clear() override {
+        std::vector<eppx_variant> dropped;
+        dropped.swap(items);
+    }
+};
+
+inline eppx_list_ref::eppx_list_ref() : eppx_list_ref(std::vector<eppx_variant>{}) {}
+
+inline eppx_list_ref::eppx_list_ref(std::vector<eppx_variant> items) : object_(new eppx_list_object(std::move(items))) {
+    object_->incref();
+}
+
+inline eppx_list_ref::eppx_list_ref(const eppx_list_ref& other) : object_(other.object_) {
+    object_->incref();
+}
+
+inline eppx_list_ref& eppx_list_ref::operator=(const eppx_list_ref& other) {
+    other.object_->incref();
+    eppx_list_object* old = object_;
+    object_ = other.object_;
+    old->decref();
+    return *this;
+}
+
+inline eppx_list_ref::~eppx_list_ref() {
+    object_->decref();
+}
+
+inline std::vector<eppx_variant>& eppx_list_ref::operator*() const { return object_->items; }
+inline std::vector<eppx_variant>* eppx_list_ref::operator->() const { return &object_->items; }
+
+// Lists compare by contents; `is` compares the handles' objects
+inline bool operator==(const eppx_list_ref& left, const eppx_list_ref& right);
+inline bool operator<(const eppx_list_ref& left, const eppx_list_ref& right);
+
+// The list behind a handle, or any other value unchanged: variables that may
+// hold a shared list are read through this
+template<typename T>
+T& eppx_items(T& value) { return value; }
+template<typename T>
+const T& eppx_items(const T& value) { return value; }
+inline std::vector<eppx_variant>& eppx_items(eppx_list_ref& list) { return *list; }
+inline std::vector<eppx_variant>& eppx_items(const eppx_list_ref& list) { return *list; }
+
 // Helper functions for variant conversion
 inline double variant_to_double(const eppx_variant& v) {
     if (std::holds_alternative<double>(v)) {
@@ -65,10 +567,14 @@ inline std::string variant_to_string(const eppx_variant& v) {
         return std::to_string(std::get<double>(v));
     } else if (std::holds_alternative<bool>(v)) {
         return std::get<bool>(v) ? "True" : "False";
+    } else if (std::holds_alternative<eppx_complex>(v)) {
+        return eppx_complex_repr(std::get<eppx_complex>(v));
     }
     return "";
 }
 
+size_t eppx_len(const eppx_variant& var);
+
 inline bool variant_to_bool(const eppx_variant& v) {
     if (std::holds_alternative<bool>(v)) {
         return std::get<bool>(v);
@@ -78,16 +584,37 @@ inline bool variant_to_bool(const eppx_variant& v) {
         return std::get<double>(v) != 0.0;
     } else if (std::holds_alternative<std::string>(v)) {
         return !std::get<std::string>(v).empty();
-    } else if (std::holds_alternative<std::vector<eppx_variant>>(v)) {
-        return !std::get<std::vector<eppx_variant>>(v).empty();
+    } else if (std::holds_alternative<eppx_list_ref>(v)) {
+        return !std::get<eppx_list_ref>(v)->empty();
+    } else if (std::holds_alternative<eppx_complex>(v)) {
+        const eppx_complex& c = std::get<eppx_complex>(v);
+        return c.real != 0.0 || c.imag != 0.0;
+    } else if (std::holds_alternative<eppx_dict_ref>(v) || std::holds_alternative<eppx_set_ref>(v)) {
+        return eppx_len(v) > 0;
     }
-    return false;
+    return std::holds_alternative<eppx_object_ref>(v);
+}
+
+// Arithmetic between variants turns complex when either side is complex
+inline bool eppx_complex_arithmetic(const eppx_variant& left, const eppx_variant& right) {
+    return std::holds_alternative<eppx_complex>(left) || std::holds_alternative<eppx_complex>(right);
+}
+
+inline eppx_complex variant_to_complex(const eppx_variant& v) {
+    if (std::holds_alternative<eppx_complex>(v)) {
+        return std::get<eppx_complex>(v);
+    }
+    return {variant_to_double(v), 0.0};
 }
 
 // Output operator for eppx_variant
-std::ostream& operator<<(std::ostream& os, const eppx_variant& var) {
+inline std::ostream& operator<<(std::ostream& os, const eppx_variant& var) {
     std::visit([&os](const auto& value) {
-        os << value;
+        if constexpr (std::is_same_v<std::decay_t<decltype(value)>, std::nullptr_t>) {
+            os << "None";
+        } else {
+            os << value;
+        }
     }, var);
     return os;
 }
@@ -102,7 +629,10 @@ std::ostream& operator<<(std::ostream& os, const std::pair<T1, T2>& p) {
 // Forward declarations for output operators (defined after classes)
 
 // Arithmetic operators for eppx_variant
-eppx_variant operator*(const eppx_variant& left, const eppx_variant& right) {
+inline eppx_variant operator*(const eppx_variant& left, const eppx_variant& right) {
+    if (eppx_complex_arithmetic(left, right)) {
+        return variant_to_complex(left) * variant_to_complex(right);
+    }
     if (std::holds_alternative<long long>(left) && std::holds_alternative<long long>(right)) {
         return std::get<long long>(left) * std::get<long long>(right);
     } else if (std::holds_alternative<double>(left) || std::holds_alternative<double>(right)) {
@@ -111,13 +641,16 @@ eppx_variant operator*(const eppx_variant& left, const eppx_variant& right) {
     return 0LL; // fallback
 }
 
-eppx_variant operator%(const eppx_variant& left, const eppx_variant& right) {
+inline eppx_variant operator%(const eppx_variant& left, const eppx_variant& right) {
     long long l = variant_to_ll(left);
     long long r = variant_to_ll(right);
     return l % r;
 }
 
-eppx_variant operator+(const eppx_variant& left, const eppx_variant& right) {
+inline eppx_variant operator+(const eppx_variant& left, const eppx_variant& right) {
+    if (eppx_complex_arithmetic(left, right)) {
+        return variant_to_complex(left) + variant_to_complex(right);
+    }
     if (std::holds_alternative<long long>(left) && std::holds_alternative<long long>(right)) {
         return std::get<long long>(left) + std::get<long long>(right);
     } else if (std::holds_alternative<double>(left) || std::holds_alternative<double>(right)) {
@@ -128,7 +661,10 @@ eppx_variant operator+(const eppx_variant& left, const eppx_variant& right) {
     return 0LL; // fallback
 }
 
-eppx_variant operator-(const eppx_variant& left, const eppx_variant& right) {
+inline eppx_variant operator-(const eppx_variant& left, const eppx_variant& right) {
+    if (eppx_complex_arithmetic(left, right)) {
+        return variant_to_complex(left) - variant_to_complex(right);
+    }
     if (std::holds_alternative<long long>(left) && std::holds_alternative<long long>(right)) {
         return std::get<long long>(left) - std::get<long long>(right);
     } else if (std::holds_alternative<double>(left) || std::holds_alternative<double>(right)) {
@@ -137,188 +673,1473 @@ eppx_variant operator-(const eppx_variant& left, const eppx_variant& right) {
     return 0LL; // fallback
 }
 
-eppx_variant operator/(const eppx_variant& left, const eppx_variant& right) {
-    double l = variant_to_double(left);
-    double r = variant_to_double(right);
-    return l / r;
-}
+inline eppx_variant operator/(const eppx_variant& left, const eppx_variant& right) {
+    if (eppx_complex_arithmetic(left, right)) {
+        return variant_to_complex(left) / variant_to_complex(right);
+    }
+    double l = variant_to_double(left);
+    double r = variant_to_double(right);
+    return l / r;
+}
+
+// Comparison operators for eppx_variant
+inline bool operator==(const eppx_variant& left, const eppx_variant& right) {
+    if (eppx_complex_arithmetic(left, right)) {
+        // 1 == 1+0j, but a complex never equals a string, list or None
+        auto numeric = [](const eppx_variant& v) {
+            return std::holds_alternative<eppx_complex>(v) || std::holds_alternative<long long>(v)
+                || std::holds_alternative<double>(v) || std::holds_alternative<bool>(v);
+        };
+        return numeric(left) && numeric(right) && variant_to_complex(left) == variant_to_complex(right);
+    }
+    if (left.index() != right.index()) {
+        return false;
+    }
+    return std::visit([](const auto& l, const auto& r) -> bool {
+        if constexpr (std::is_same_v<std::decay_t<decltype(l)>, std::decay_t<decltype(r)>>) {
+            return l == r;
+        } else {
+            return false;
+        }
+    }, left, right);
+}
+
+inline bool operator!=(const eppx_variant& left, const eppx_variant& right) {
+    return !(left == right);
+}
+
+inline bool operator==(const eppx_list_ref& left, const eppx_list_ref& right) {
+    return left.get() == right.get() || *left == *right;
+}
+
+inline bool operator!=(const eppx_list_ref& left, const eppx_list_ref& right) {
+    return !(left == right);
+}
+
+inline bool operator<(const eppx_list_ref& left, const eppx_list_ref& right) {
+    return std::lexicographical_compare(left->begin(), left->end(), right->begin(), right->end());
+}
+
+// Lists currently being printed; one that contains itself prints as [...]
+inline std::vector<const void*>& eppx_repr_active() {
+    static std::vector<const void*> active;
+    return active;
+}
+
+inline bool eppx_repr_enter(const void* list) {
+    auto& active = eppx_repr_active();
+    if (std::find(active.begin(), active.end(), list) != active.end()) {
+        return false;
+    }
+    active.push_back(list);
+    return true;
+}
+
+inline void eppx_repr_leave(const void* list) {
+    auto& active = eppx_repr_active();
+    active.erase(std::find(active.begin(), active.end(), list));
+}
+
+inline std::ostream& operator<<(std::ostream& os, const std::vector<eppx_variant>& items) {
+    if (!eppx_repr_enter(&items)) {
+        return os << "[...]";
+    }
+    os << "[";
+    for (size_t i = 0; i < items.size(); ++i) {
+        if (i > 0) os << ", ";
+        os << items[i];
+    }
+    eppx_repr_leave(&items);
+    return os << "]";
+}
+
+inline std::ostream& operator<<(std::ostream& os, const eppx_list_ref& list) {
+    return os << *list;
+}
+
+inline bool operator<(const eppx_variant& left, const eppx_variant& right) {
+    if (std::holds_alternative<long long>(left) && std::holds_alternative<long long>(right)) {
+        return std::get<long long>(left) < std::get<long long>(right);
+    } else if (std::holds_alternative<double>(left) || std::holds_alternative<double>(right)) {
+        return variant_to_double(left) < variant_to_double(right);
+    } else if (std::holds_alternative<std::string>(left) && std::holds_alternative<std::string>(right)) {
+        return std::get<std::string>(left) < std::get<std::string>(right);
+    } else if (std::holds_alternative<eppx_object_ref>(left) && std::holds_alternative<eppx_object_ref>(right)) {
+        // Instances have no order of their own; this keeps distinct ones apart in a set
+        return std::get<eppx_object_ref>(left) < std::get<eppx_object_ref>(right);
+    }
+    return false;
+}
+
+inline bool operator<=(const eppx_variant& left, const eppx_variant& right) {
+    return left < right || left == right;
+}
+
+inline bool operator>(const eppx_variant& left, const eppx_variant& right) {
+    return !(left <= right);
+}
+
+inline bool operator>=(const eppx_variant& left, const eppx_variant& right) {
+    return !(left < right);
+}
+
+
+
+// Range function (already exists)
+inline std::vector<long long> eppx_range(long long n) {
+    std::vector<long long> result;
+    result.reserve(n);
+    for (long long i = 0; i < n; ++i) {
+        result.push_back(i);
+    }
+    return result;
+}
+
+// Range function overloads
+inline std::vector<long long> eppx_range(long long start, long long stop) {
+    std::vector<long long> result;
+    if (start < stop) {
+        result.reserve(stop - start);
+        for (long long i = start; i < stop; ++i) {
+            result.push_back(i);
+        }
+    }
+    return result;
+}
+
+inline std::vector<long long> eppx_range(long long start, long long stop, long long step) {
+    std::vector<long long> result;
+    if (step > 0 && start < stop) {
+        for (long long i = start; i < stop; i += step) {
+            result.push_back(i);
+        }
+    } else if (step < 0 && start > stop) {
+        for (long long i = start; i > stop; i += step) {
+            result.push_back(i);
+        }
+    }
+    return result;
+}
+
+// range() over dynamically typed bounds (e.g. generator locals)
+inline std::vector<long long> eppx_range(const eppx_variant& n) {
+    return eppx_range(variant_to_ll(n));
+}
+
+inline std::vector<long long> eppx_range(const eppx_variant& start, const eppx_variant& stop) {
+    return eppx_range(variant_to_ll(start), variant_to_ll(stop));
+}
+
+inline std::vector<long long> eppx_range(const eppx_variant& start, const eppx_variant& stop, const eppx_variant& step) {
+    return eppx_range(variant_to_ll(start), variant_to_ll(stop), variant_to_ll(step));
+}
+
+// String representation functions (hex, bin, oct) are in the runtime library
+std::string eppx_hex(long long n);
+std::string eppx_bin(long long n);
+std::string eppx_oct(long long n);
+
+// Collection functions
+template<typename Container>
+auto eppx_sum(const Container& container) -> typename Container::value_type {
+    return std::accumulate(container.begin(), container.end(), typename Container::value_type{});
+}
+
+// Specialized sum for eppx_variant vectors
+template<>
+inline eppx_variant eppx_sum(const std::vector<eppx_variant>& container) {
+    eppx_variant sum = 0LL;
+    for (const auto& item : container) {
+        if (std::holds_alternative<long long>(sum) && std::holds_alternative<long long>(item)) {
+            sum = std::get<long long>(sum) + std::get<long long>(item);
+        } else if (std::holds_alternative<double>(sum) || std::holds_alternative<double>(item)) {
+            double s = variant_to_double(sum);
+            double i = variant_to_double(item);
+            sum = s + i;
+        }
+    }
+    return sum;
+}
+
+template<typename Container>
+bool eppx_all(const Container& container) {
+    return std::all_of(container.begin(), container.end(), 
+                      [](const auto& item) { return variant_to_bool(item); });
+}
+
+template<typename Container>
+bool eppx_any(const Container& container) {
+    return std::any_of(container.begin(), container.end(), 
+                      [](const auto& item) { return variant_to_bool(item); });
+}
+
+template<typename Container>
+Container eppx_reversed(Container container) {
+    std::reverse(container.begin(), container.end());
+    return container;
+}
+
+template<typename Container>
+Container eppx_sorted(Container container) {
+    std::sort(container.begin(), container.end());
+    return container;
+}
+
+// Collection converters
+template<typename T>
+std::vector<T> eppx_to_list(const std::vector<T>& vec) {
+    return vec; // Already a list
+}
+
+template<typename T>
+std::vector<T> eppx_to_list(const std::set<T>& s) {
+    return std::vector<T>(s.begin(), s.end());
+}
+
+template<typename T>
+std::set<T> eppx_to_set(const std::vector<T>& vec) {
+    return std::set<T>(vec.begin(), vec.end());
+}
+
+// List conversion function (alias for eppx_to_list)
+template<typename Container>
+auto eppx_list(const Container& container) -> std::vector<typename Container::value_type> {
+    return std::vector<typename Container::value_type>(container.begin(), container.end());
+}
+
+// Python exception types raised by the container runtime
+class KeyErrorException : public std::runtime_error {
+public:
+    explicit KeyErrorException(const std::string& key) : std::runtime_error(key) {}
+};
+
+class ValueErrorException : public std::runtime_error {
+public:
+    explicit ValueErrorException(const std::string& msg) : std::runtime_error(msg) {}
+};
+
+class IndexErrorException : public std::out_of_range {
+public:
+    explicit IndexErrorException(const std::string& msg) : std::out_of_range(msg) {}
+};
+
+// dataclasses.FrozenInstanceError, a subclass of AttributeError
+class FrozenInstanceErrorException : public std::runtime_error {
+public:
+    explicit FrozenInstanceErrorException(const std::string& msg) : std::runtime_error(msg) {}
+};
+
+class AssertionErrorException : public std::runtime_error {
+public:
+    explicit AssertionErrorException(const std::string& msg) : std::runtime_error(msg) {}
+};
+
+// OSError and its subclasses (FileNotFoundError, PermissionError, ...)
+class OSErrorException : public std::runtime_error {
+public:
+    OSErrorException(const std::string& type, const std::string& msg) : std::runtime_error(msg), type_name(type) {}
+    std::string type_name;
+};
+
+// Strict weak ordering for dict keys: numbers compare by value, everything
+// else by type first so mixed-type keys never collapse into one entry.
+struct eppx_key_less {
+    static int rank(const eppx_variant& v) {
+        if (std::holds_alternative<long long>(v) || std::holds_alternative<double>(v) || std::holds_alternative<bool>(v)) {
+            return 0;
+        }
+        return static_cast<int>(v.index()) + 1;
+    }
+
+    bool operator()(const eppx_variant& left, const eppx_variant& right) const {
+        int lr = rank(left);
+        int rr = rank(right);
+        if (lr != rr) {
+            return lr < rr;
+        }
+        if (lr == 0) {
+            return variant_to_double(left) < variant_to_double(right);
+        }
+        if (std::holds_alternative<std::string>(left)) {
+            return std::get<std::string>(left) < std::get<std::string>(right);
+        }
+        if (std::holds_alternative<eppx_list_ref>(left)) {
+            const auto& l = *std::get<eppx_list_ref>(left);
+            const auto& r = *std::get<eppx_list_ref>(right);
+            return std::lexicographical_compare(l.begin(), l.end(), r.begin(), r.end(), eppx_key_less{});
+        }
+        if (std::holds_alternative<eppx_complex>(left)) {
+            const eppx_complex& l = std::get<eppx_complex>(left);
+            const eppx_complex& r = std::get<eppx_complex>(right);
+            return l.real < r.real || (l.real == r.real && l.imag < r.imag);
+        }
+        if (std::holds_alternative<eppx_object_ref>(left)) {
+            return std::get<eppx_object_ref>(left) < std::get<eppx_object_ref>(right);
+        }
+        return false;
+    }
+
+    template<typename T>
+    bool operator()(const T& left, const T& right) const {
+        return left < right;
+    }
+};
+
+// Insertion-ordered dictionary (matches CPython 3.7+ dict ordering)
+template<typename K, typename V>
+class eppx_dict {
+private:
+    std::vector<std::pair<K, V>> entries_;
+    std::map<K, size_t, eppx_key_less> index_;
+
+    void reindex_from(size_t pos) {
+        for (size_t i = pos; i < entries_.size(); ++i) {
+            index_[entries_[i].first] = i;
+        }
+    }
+
+public:
+    using key_type = K;
+    using mapped_type = V;
+    using value_type = K; // iterating a dict yields its keys
+
+    class key_iterator {
+    private:
+        typename std::vector<std::pair<K, V>>::const_iterator it_;
+    public:
+        using iterator_category = std::forward_iterator_tag;
+        using value_type = K;
+        using difference_type = std::ptrdiff_t;
+        using pointer = const K*;
+        using reference = const K&;
+
+        explicit key_iterator(typename std::vector<std::pair<K, V>>::const_iterator it) : it_(it) {}
+        const K& operator*() const { return it_->first; }
+        const K* operator->() const { return &it_->first; }
+        key_iterator& operator++() { ++it_; return *this; }
+        key_iterator operator++(int) { key_iterator tmp = *this; ++it_; return tmp; }
+        bool operator!=(const key_iterator& other) const { return it_ != other.it_; }
+        bool operator==(const key_iterator& other) const { return it_ == other.it_; }
+    };
+
+    eppx_dict() = default;
+    eppx_dict(std::initializer_list<std::pair<K, V>> init) {
+        for (const auto& entry : init) {
+            set(entry.first, entry.second);
+        }
+    }
+
+    size_t size() const { return entries_.size(); }
+    bool empty() const { return entries_.empty(); }
+    key_iterator begin() const { return key_iterator(entries_.begin()); }
+    key_iterator end() const { return key_iterator(entries_.end()); }
+    const std::vector<std::pair<K, V>>& entries() const { return entries_; }
+
+    bool contains(const K& key) const { return index_.count(key) > 0; }
+
+    V* find(const K& key) {
+        auto it = index_.find(key);
+        return it == index_.end() ? nullptr : &entries_[it->second].second;
+    }
+
+    const V* find(const K& key) const {
+        auto it = index_.find(key);
+        return it == index_.end() ? nullptr : &entries_[it->second].second;
+    }
+
+    const V& at(const K& key) const {
+        const V* value = find(key);
+        if (!value) {
+            throw KeyErrorException(eppx_key_repr(key));
+        }
+        return *value;
+    }
+
+    void set(const K& key, const V& value) {
+        if (V* existing = find(key)) {
+            *existing = value;
+        } else {
+            index_[key] = entries_.size();
+            entries_.emplace_back(key, value);
+        }
+    }
+
+    bool erase(const K& key) {
+        auto it = index_.find(key);
+        if (it == index_.end()) {
+            return false;
+        }
+        size_t pos = it->second;
+        index_.erase(it);
+        entries_.erase(entries_.begin() + static_cast<long>(pos));
+        reindex_from(pos);
+        return true;
+    }
+
+    void clear() {
+        entries_.clear();
+        index_.clear();
+    }
+
+    static std::string eppx_key_repr(const K& key) {
+        std::ostringstream ss;
+        if constexpr (std::is_same_v<K, std::string>) {
+            ss << "'" << key << "'";
+        } else if constexpr (std::is_same_v<K, eppx_variant>) {
+            if (std::holds_alternative<std::string>(key)) {
+                ss << "'" << std::get<std::string>(key) << "'";
+            } else {
+                ss << key;
+            }
+        } else {
+            ss << key;
+        }
+        return ss.str();
+    }
+};
+
+template<typename K, typename V>
+std::ostream& operator<<(std::ostream& os, const eppx_dict<K, V>& d) {
+    if (!eppx_repr_enter(&d)) {
+        return os << "{...}";
+    }
+    os << "{";
+    bool first = true;
+    for (const auto& entry : d.entries()) {
+        if (!first) os << ", ";
+        os << entry.first << ": " << entry.second;
+        first = false;
+    }
+    eppx_repr_leave(&d);
+    return os << "}";
+}
+
+template<typename K, typename V>
+bool operator==(const eppx_dict<K, V>& left, const eppx_dict<K, V>& right) {
+    if (left.size() != right.size()) {
+        return false;
+    }
+    for (const auto& entry : left.entries()) {
+        const V* other = right.find(entry.first);
+        if (!other || !(*other == entry.second)) {
+            return false;
+        }
+    }
+    return true;
+}
+
+// The list, dict, set or instance a variant holds a handle to, or nullptr
+// for other values
+inline eppx_gc::object* eppx_variant_object(const eppx_variant& value) {
+    if (const auto* list = std::get_if<eppx_list_ref>(&value)) {
+        return list->get();
+    } else if (const auto* dict = std::get_if<eppx_dict_ref>(&value)) {
+        return dict->get();
+    } else if (const auto* set = std::get_if<eppx_set_ref>(&value)) {
+        return set->get();
+    } else if (const auto* object = std::get_if<eppx_object_ref>(&value)) {
+        return object->get();
+    }
+    return nullptr;
+}
+
+// Reference traversal for the cycle collector: variants and containers
+// visit the handles they hold, and instances visit their members through the
+// eppx_traverse() that codegen gives classes with members that may hold one
+inline void eppx_visit_refs(const eppx_variant& value, const std::function<void(eppx_gc::object*)>& visit) {
+    if (eppx_gc::object* object = eppx_variant_object(value)) {
+        visit(object);
+    }
+}
+
+inline void eppx_visit_refs(const eppx_list_ref& list, const std::function<void(eppx_gc::object*)>& visit) {
+    visit(list.get());
+}
+
+template<typename T>
+void eppx_visit_refs(const eppx_ref<T>& ref, const std::function<void(eppx_gc::object*)>& visit) {
+    visit(ref.get());
+}
+
+template<typename K, typename V>
+void eppx_visit_refs(const eppx_dict<K, V>& d, const std::function<void(eppx_gc::object*)>& visit) {
+    for (const auto& entry : d.entries()) {
+        eppx_visit_refs(entry.first, visit);
+        eppx_visit_refs(entry.second, visit);
+    }
+}
+
+template<typename T>
+void eppx_visit_refs(const std::set<T>& s, const std::function<void(eppx_gc::object*)>& visit) {
+    for (const auto& item : s) {
+        eppx_visit_refs(item, visit);
+    }
+}
+
+template<typename T, typename = void>
+inline constexpr bool eppx_has_traverse_v = false;
+template<typename T>
+inline constexpr bool eppx_has_traverse_v<T, std::void_t<decltype(&T::eppx_traverse)>> = true;
+
+template<typename T>
+void eppx_visit_refs(const T& value, const std::function<void(eppx_gc::object*)>& visit) {
+    if constexpr (eppx_has_traverse_v<T>) {
+        value.eppx_traverse(visit);
+    }
+}
+
+// Drops the references an unreachable object holds, so the collector can
+// break the cycle; the contents are moved out first, as for lists
+template<typename K, typename V>
+void eppx_clear_refs(eppx_dict<K, V>& d) {
+    eppx_dict<K, V> dropped;
+    std::swap(dropped, d);
+}
+
+template<typename T>
+void eppx_clear_refs(std::set<T>& s) {
+    std::set<T> dropped;
+    dropped.swap(s);
+}
+
+template<typename T>
+void eppx_clear_refs(T& value) {
+    if constexpr (eppx_has_traverse_v<T>) {
+        value.eppx_clear();
+    }
+}
+
+inline void eppx_clear_ref(eppx_variant& value) {
+    eppx_variant dropped = nullptr;
+    std::swap(dropped, value);
+}
+
+template<typename T>
+void eppx_clear_ref(T&) {}
+
+template<typename T, typename = void>
+inline constexpr bool eppx_has_str_v = false;
+template<typename T>
+inline constexpr bool eppx_has_str_v<T, std::void_t<decltype(std::declval<T&>().__str__())>> = true;
+
+template<typename T, typename = void>
+inline constexpr bool eppx_has_repr_v = false;
+template<typename T>
+inline constexpr bool eppx_has_repr_v<T, std::void_t<decltype(std::declval<T&>().__repr__())>> = true;
+
+// How an object behind a handle prints: containers print their contents, and
+// instances use __str__ or __repr__ or Python's default
+template<typename T>
+void eppx_write_object(std::ostream& os, const T& value) {
+    if constexpr (eppx_has_str_v<T>) {
+        os << const_cast<T&>(value).__str__();
+    } else if constexpr (eppx_has_repr_v<T>) {
+        os << const_cast<T&>(value).__repr__();
+    } else if constexpr (eppx_is_instance_v<T>) {
+        os << "<__main__." << T::__name__ << " object at " << static_cast<const void*>(&value) << ">";
+    } else {
+        os << value;
+    }
+}
+
+template<typename T>
+eppx_ref<T> eppx_share(T value) {
+    return eppx_ref<T>(std::move(value));
+}
+
+template<typename T>
+T& eppx_items(eppx_ref<T>& ref) { return *ref; }
+template<typename T>
+T& eppx_items(const eppx_ref<T>& ref) { return *ref; }
+
+template<typename T>
+std::ostream& operator<<(std::ostream& os, const eppx_ref<T>& ref) {
+    return os << *ref;
+}
+
+// Dicts and sets compare by contents, instances by identity
+template<typename T>
+bool operator==(const eppx_ref<T>& left, const eppx_ref<T>& right) {
+    if constexpr (eppx_is_instance_v<T>) {
+        return left.get() == right.get();
+    } else {
+        return left.get() == right.get() || *left == *right;
+    }
+}
+
+template<typename T>
+bool operator<(const eppx_ref<T>& left, const eppx_ref<T>& right) {
+    return left.get() < right.get();
+}
+
+// The result of a call as a value: functions that return a variable hand back
+// its handle, and value uses copy the object out of it
+template<typename T>
+T eppx_value(T value) { return value; }
+inline std::vector<eppx_variant> eppx_value(const eppx_list_ref& list) { return *list; }
+template<typename T>
+T eppx_value(const eppx_ref<T>& ref) { return *ref; }
+
+// Python-style index normalisation (negative indices count from the end)
+// Convert a C++ value into a container element type; unsigned/narrow integers
+// (e.g. the size_t from len()) are widened to long long so they fit eppx_variant
+template<typename T, typename U>
+T eppx_box(const U& value) {
+    if constexpr (std::is_integral_v<U> && !std::is_same_v<U, bool> && !std::is_same_v<U, long long>) {
+        return T(static_cast<long long>(value));
+    } else {
+        return T(value);
+    }
+}
+
+inline long long eppx_normalize_index(long long idx, size_t size) {
+    return idx < 0 ? idx + static_cast<long long>(size) : idx;
+}
+
+// Subscripting and item assignment for containers
+template<typename K, typename V, typename Key>
+V eppx_index(const eppx_dict<K, V>& d, const Key& key) {
+    return d.at(eppx_box<K>(key));
+}
+
+template<typename T>
+T eppx_index(const std::vector<T>& vec, long long idx) {
+    long long i = eppx_normalize_index(idx, vec.size());
+    if (i < 0 || i >= static_cast<long long>(vec.size())) {
+        throw IndexErrorException("list index out of range");
+    }
+    return vec[static_cast<size_t>(i)];
+}
+
+template<typename K, typename V, typename Key, typename Value>
+void eppx_setitem(eppx_dict<K, V>& d, const Key& key, const Value& value) {
+    d.set(eppx_box<K>(key), eppx_box<V>(value));
+}
+
+template<typename T, typename Value>
+void eppx_setitem(std::vector<T>& vec, long long idx, const Value& value) {
+    long long i = eppx_normalize_index(idx, vec.size());
+    if (i < 0 || i >= static_cast<long long>(vec.size())) {
+        throw IndexErrorException("list assignment index out of range");
+    }
+    vec[static_cast<size_t>(i)] = eppx_box<T>(value);
+}
+
+// `del d[k]` and `del xs[i]`
+template<typename K, typename V, typename Key>
+void eppx_delitem(eppx_dict<K, V>& d, const Key& key) {
+    K boxed = eppx_box<K>(key);
+    if (!d.erase(boxed)) {
+        throw KeyErrorException(eppx_dict<K, V>::eppx_key_repr(boxed));
+    }
+}
+
+template<typename T>
+void eppx_delitem(std::vector<T>& vec, long long idx) {
+    long long i = eppx_normalize_index(idx, vec.size());
+    if (i < 0 || i >= static_cast<long long>(vec.size())) {
+        throw IndexErrorException("list assignment index out of range");
+    }
+    vec.erase(vec.begin() + i);
+}
+
+// `del name` and `del obj.attr`: C++ can't unbind a variable or remove a
+// struct member, so the value is reset instead, releasing what it held
+template<typename T>
+void eppx_del(T& value) {
+    if constexpr (std::is_default_constructible_v<T>) {
+        value = T{};
+    }
+}
+
+// Membership test (`x in container`); the string overload is in the runtime library
+inline bool eppx_contains(const std::string& haystack, const std::string& needle);
+
+template<typename T, typename U>
+bool eppx_contains(const std::vector<T>& vec, const U& item) {
+    return std::find_if(vec.begin(), vec.end(), [&](const T& x) { return x == item; }) != vec.end();
+}
+
+template<typename T, typename U>
+bool eppx_contains(const std::set<T>& s, const U& item) {
+    return std::find_if(s.begin(), s.end(), [&](const T& x) { return x == item; }) != s.end();
+}
+
+template<typename T, typename U>
+bool eppx_contains(const std::unordered_set<T>& s, const U& item) {
+    return std::find_if(s.begin(), s.end(), [&](const T& x) { return x == item; }) != s.end();
+}
+
+template<typename K, typename V, typename U>
+bool eppx_contains(const eppx_dict<K, V>& d, const U& key) {
+    return d.contains(eppx_box<K>(key));
+}
+
+inline bool eppx_contains(const eppx_variant& container, const eppx_variant& item) {
+    if (std::holds_alternative<std::string>(container) && std::holds_alternative<std::string>(item)) {
+        return eppx_contains(std::get<std::string>(container), std::get<std::string>(item));
+    } else if (std::holds_alternative<eppx_list_ref>(container)) {
+        return eppx_contains(*std::get<eppx_list_ref>(container), item);
+    } else if (const auto* dict = std::get_if<eppx_dict_ref>(&container)) {
+        return (*dict)->contains(item);
+    } else if (const auto* set = std::get_if<eppx_set_ref>(&container)) {
+        return (*set)->count(item) > 0;
+    }
+    throw std::runtime_error("argument of type is not iterable");
+}
+
+// Tuple unpacking helper: works for std::tuple/std::pair results of builtins
+// (enumerate, zip, dict.items) and for tuples stored as eppx_variant lists.
+template<size_t I, typename... Ts>
+auto eppx_unpack(const std::tuple<Ts...>& t) -> decltype(std::get<I>(t)) {
+    return std::get<I>(t);
+}
+
+template<size_t I, typename A, typename B>
+auto eppx_unpack(const std::pair<A, B>& p) -> decltype(std::get<I>(p)) {
+    return std::get<I>(p);
+}
+
+template<size_t I, typename T>
+T eppx_unpack(const std::vector<T>& vec) {
+    if (I >= vec.size()) {
+        throw ValueErrorException("not enough values to unpack");
+    }
+    return vec[I];
+}
+
+template<size_t I>
+eppx_variant eppx_unpack(const eppx_variant& v) {
+    return eppx_unpack<I>(*std::get<eppx_list_ref>(v));
+}
+
+// Container methods. Codegen lowers `obj.method(args)` for the Python
+// container API to `eppx_method_<name>(obj, args)`; overloads below pick the
+// list/dict/set implementation and EPPX_METHOD_FALLBACK forwards to a member
+// function for user-defined classes that declare a method of the same name.
+#define EPPX_METHOD_FALLBACK(name) \
+    template<typename Obj, typename... Args> \
+    auto eppx_method_##name(Obj& obj, Args&&... args) -> decltype(obj.name(std::forward<Args>(args)...)) { \
+        return obj.name(std::forward<Args>(args)...); \
+    }
+
+EPPX_METHOD_FALLBACK(append)
+EPPX_METHOD_FALLBACK(extend)
+EPPX_METHOD_FALLBACK(insert)
+EPPX_METHOD_FALLBACK(pop)
+EPPX_METHOD_FALLBACK(remove)
+EPPX_METHOD_FALLBACK(index)
+EPPX_METHOD_FALLBACK(count)
+EPPX_METHOD_FALLBACK(sort)
+EPPX_METHOD_FALLBACK(reverse)
+EPPX_METHOD_FALLBACK(copy)
+EPPX_METHOD_FALLBACK(clear)
+EPPX_METHOD_FALLBACK(keys)
+EPPX_METHOD_FALLBACK(values)
+EPPX_METHOD_FALLBACK(items)
+EPPX_METHOD_FALLBACK(get)
+EPPX_METHOD_FALLBACK(setdefault)
+EPPX_METHOD_FALLBACK(update)
+EPPX_METHOD_FALLBACK(add)
+EPPX_METHOD_FALLBACK(discard)
+EPPX_METHOD_FALLBACK(intersection)
+EPPX_METHOD_FALLBACK(difference)
+
+// list methods
+template<typename T, typename U>
+void eppx_method_append(std::vector<T>& vec, const U& item) {
+    vec.push_back(eppx_box<T>(item));
+}
+
+template<typename T, typename Iterable>
+void eppx_method_extend(std::vector<T>& vec, const Iterable& items) {
+    for (const auto& item : items) {
+        vec.push_back(eppx_box<T>(item));
+    }
+}
+
+template<typename T, typename U>
+void eppx_method_insert(std::vector<T>& vec, long long idx, const U& item) {
+    long long size = static_cast<long long>(vec.size());
+    long long i = eppx_normalize_index(idx, vec.size());
+    i = std::max(0LL, std::min(i, size));
+    vec.insert(vec.begin() + i, eppx_box<T>(item));
+}
+
+template<typename T>
+T eppx_method_pop(std::vector<T>& vec, long long idx = -1) {
+    if (vec.empty()) {
+        throw IndexErrorException("pop from empty list");
+    }
+    long long i = eppx_normalize_index(idx, vec.size());
+    if (i < 0 || i >= static_cast<long long>(vec.size())) {
+        throw IndexErrorException("pop index out of range");
+    }
+    T item = vec[static_cast<size_t>(i)];
+    vec.erase(vec.begin() + i);
+    return item;
+}
+
+template<typename T, typename U>
+void eppx_method_remove(std::vector<T>& vec, const U& item) {
+    auto it = std::find_if(vec.begin(), vec.end(), [&](const T& x) { return x == item; });
+    if (it == vec.end()) {
+        throw ValueErrorException("list.remove(x): x not in list");
+    }
+    vec.erase(it);
+}
+
+template<typename T, typename U>
+long long eppx_method_index(const std::vector<T>& vec, const U& item) {
+    auto it = std::find_if(vec.begin(), vec.end(), [&](const T& x) { return x == item; });
+    if (it == vec.end()) {
+        throw ValueErrorException("list.index(x): x not in list");
+    }
+    return static_cast<long long>(it - vec.begin());
+}
+
+template<typename T, typename U>
+long long eppx_method_count(const std::vector<T>& vec, const U& item) {
+    return static_cast<long long>(std::count_if(vec.begin(), vec.end(), [&](const T& x) { return x == item; }));
+}
+
+// str.count and str.index are in the runtime library (see eppx_upper)
+inline long long eppx_method_count(const std::string& s, const std::string& sub);
+inline long long eppx_method_index(const std::string& s, const std::string& sub);
+
+// sort(key=None, reverse=False): stable, like CPython's list.sort
+template<typename T>
+void eppx_method_sort(std::vector<T>& vec, std::nullptr_t = nullptr, bool reverse = false) {
+    if (reverse) {
+        std::stable_sort(vec.begin(), vec.end(), [](const T& a, const T& b) { return b < a; });
+    } else {
+        std::stable_sort(vec.begin(), vec.end(), [](const T& a, const T& b) { return a < b; });
+    }
+}
+
+template<typename T, typename Key>
+void eppx_method_sort(std::vector<T>& vec, Key key, bool reverse = false) {
+    std::stable_sort(vec.begin(), vec.end(), [&](const T& a, const T& b) {
+        return reverse ? key(b) < key(a) : key(a) < key(b);
+    });
+}
+
+template<typename T>
+void eppx_method_reverse(std::vector<T>& vec) {
+    std::reverse(vec.begin(), vec.end());
+}
+
+template<typename T>
+std::vector<T> eppx_method_copy(const std::vector<T>& vec) {
+    return vec;
+}
+
+template<typename T>
+void eppx_method_clear(std::vector<T>& vec) {
+    vec.clear();
+}
+
+// dict methods
+template<typename K, typename V>
+std::vector<K> eppx_method_keys(const eppx_dict<K, V>& d) {
+    return std::vector<K>(d.begin(), d.end());
+}
+
+template<typename K, typename V>
+std::vector<V> eppx_method_values(const eppx_dict<K, V>& d) {
+    std::vector<V> result;
+    for (const auto& entry : d.entries()) {
+        result.push_back(entry.second);
+    }
+    return result;
+}
+
+template<typename K, typename V>
+std::vector<std::tuple<K, V>> eppx_method_items(const eppx_dict<K, V>& d) {
+    std::vector<std::tuple<K, V>> result;
+    for (const auto& entry : d.entries()) {
+        result.emplace_back(entry.first, entry.second);
+    }
+    return result;
+}
+
+template<typename K, typename V, typename Key>
+V eppx_method_get(const eppx_dict<K, V>& d, const Key& key) {
+    const V* value = d.find(eppx_box<K>(key));
+    if (value) {
+        return *value;
+    }
+    if constexpr (std::is_constructible_v<V, std::nullptr_t>) {
+        return V(nullptr);
+    } else {
+        return V{};
+    }
+}
+
+template<typename K, typename V, typename Key, typename Default>
+V eppx_method_get(const eppx_dict<K, V>& d, const Key& key, const Default& default_value) {
+    const V* value = d.find(eppx_box<K>(key));
+    return value ? *value : eppx_box<V>(default_value);
+}
+
+template<typename K, typename V, typename Key, typename Default>
+V eppx_method_setdefault(eppx_dict<K, V>& d, const Key& key, const Default& default_value) {
+    if (const V* value = d.find(eppx_box<K>(key))) {
+        return *value;
+    }
+    d.set(eppx_box<K>(key), eppx_box<V>(default_value));
+    return eppx_box<V>(default_value);
+}
+
+template<typename K, typename V>
+void eppx_method_update(eppx_dict<K, V>& d, const eppx_dict<K, V>& other) {
+    for (const auto& entry : other.entries()) {
+        d.set(entry.first, entry.second);
+    }
+}
+
+// d.update(other, a=1): the keywords are merged after `other`
+template<typename K, typename V>
+void eppx_method_update(eppx_dict<K, V>& d, const eppx_dict<K, V>& other, const eppx_dict<K, V>& keywords) {
+    eppx_method_update(d, other);
+    eppx_method_update(d, keywords);
+}
+
+template<typename K, typename V, typename Key>
+V eppx_method_pop(eppx_dict<K, V>& d, const Key& key) {
+    V value = d.at(eppx_box<K>(key));
+    d.erase(eppx_box<K>(key));
+    return value;
+}
+
+template<typename K, typename V, typename Key, typename Default>
+V eppx_method_pop(eppx_dict<K, V>& d, const Key& key, const Default& default_value) {
+    const V* value = d.find(eppx_box<K>(key));
+    if (!value) {
+        return eppx_box<V>(default_value);
+    }
+    V result = *value;
+    d.erase(eppx_box<K>(key));
+    return result;
+}
+
+template<typename K, typename V>
+eppx_dict<K, V> eppx_method_copy(const eppx_dict<K, V>& d) {
+    return d;
+}
+
+template<typename K, typename V>
+void eppx_method_clear(eppx_dict<K, V>& d) {
+    d.clear();
+}
+
+// set methods
+template<typename T, typename U>
+void eppx_method_add(std::set<T>& s, const U& item) {
+    s.insert(eppx_box<T>(item));
+}
+
+template<typename T, typename U>
+void eppx_method_discard(std::set<T>& s, const U& item) {
+    s.erase(eppx_box<T>(item));
+}
+
+template<typename T, typename U>
+void eppx_method_remove(std::set<T>& s, const U& item) {
+    if (s.erase(eppx_box<T>(item)) == 0) {
+        throw KeyErrorException(variant_to_string(eppx_variant(item)));
+    }
+}
+
+template<typename T, typename Iterable>
+std::set<T> eppx_method_union(const std::set<T>& s, const Iterable& other) {
+    std::set<T> result = s;
+    for (const auto& item : other) {
+        result.insert(eppx_box<T>(item));
+    }
+    return result;
+}
+
+template<typename T, typename Iterable>
+std::set<T> eppx_method_intersection(const std::set<T>& s, const Iterable& other) {
+    std::set<T> result;
+    for (const auto& item : other) {
+        if (s.count(eppx_box<T>(item))) {
+            result.insert(eppx_box<T>(item));
+        }
+    }
+    return result;
+}
+
+template<typename T, typename Iterable>
+std::set<T> eppx_method_difference(const std::set<T>& s, const Iterable& other) {
+    std::set<T> result = s;
+    for (const auto& item : other) {
+        result.erase(eppx_box<T>(item));
+    }
+    return result;
+}
+
+template<typename T>
+std::set<T> eppx_method_copy(const std::set<T>& s) {
+    return s;
+}
+
+template<typename T>
+void eppx_method_clear(std::set<T>& s) {
+    s.clear();
+}
+
+// Methods of a container reached through a variant (`grid[0].append(7)`,
+// `d["k"].append(2)`): the variant holds the container's handle, so the call
+// changes the shared container. Results come back as variants.
+template<typename F, typename Container>
+eppx_variant eppx_call_method(F& call, Container& container, const char* type_name, const char* method) {
+    if constexpr (std::is_invocable_v<F&, Container&>) {
+        if constexpr (std::is_void_v<std::invoke_result_t<F&, Container&>>) {
+            call(container);
+            return nullptr;
+        } else {
+            return eppx_to_variant(call(container));
+        }
+    } else {
+        throw std::runtime_error(std::string("AttributeError: '") + type_name + "' object has no attribute '" + method + "'");
+    }
+}
+
+template<typename F>
+eppx_variant eppx_call_method(const eppx_variant& obj, const char* method, F call) {
+    if (const auto* list = std::get_if<eppx_list_ref>(&obj)) {
+        return eppx_call_method(call, **list, "list", method);
+    } else if (const auto* dict = std::get_if<eppx_dict_ref>(&obj)) {
+        return eppx_call_method(call, **dict, "dict", method);
+    } else if (const auto* set = std::get_if<eppx_set_ref>(&obj)) {
+        return eppx_call_method(call, **set, "set", method);
+    }
+    throw std::runtime_error(std::string("AttributeError: object has no attribute '") + method + "'");
+}
+
+#define EPPX_VARIANT_METHOD(name) \
+    template<typename... Args> \
+    eppx_variant eppx_method_##name(const eppx_variant& obj, const Args&... args) { \
+        return eppx_call_method(obj, #name, [&](auto& container) -> decltype(eppx_method_##name(container, args...)) { \
+            return eppx_method_##name(container, args...); \
+        }); \
+    }
+
+EPPX_VARIANT_METHOD(append)
+EPPX_VARIANT_METHOD(extend)
+EPPX_VARIANT_METHOD(insert)
+EPPX_VARIANT_METHOD(pop)
+EPPX_VARIANT_METHOD(remove)
+EPPX_VARIANT_METHOD(index)
+EPPX_VARIANT_METHOD(count)
+EPPX_VARIANT_METHOD(sort)
+EPPX_VARIANT_METHOD(reverse)
+EPPX_VARIANT_METHOD(copy)
+EPPX_VARIANT_METHOD(clear)
+EPPX_VARIANT_METHOD(keys)
+EPPX_VARIANT_METHOD(values)
+EPPX_VARIANT_METHOD(items)
+EPPX_VARIANT_METHOD(get)
+EPPX_VARIANT_METHOD(setdefault)
+EPPX_VARIANT_METHOD(update)
+EPPX_VARIANT_METHOD(add)
+EPPX_VARIANT_METHOD(discard)
+EPPX_VARIANT_METHOD(union)
+EPPX_VARIANT_METHOD(intersection)
+EPPX_VARIANT_METHOD(difference)
+
+// Item assignment and deletion on a list or dict reached through a variant
+template<typename Key, typename Value>
+void eppx_setitem(const eppx_variant& obj, const Key& key, const Value& value) {
+    if (const auto* list = std::get_if<eppx_list_ref>(&obj)) {
+        eppx_setitem(**list, variant_to_ll(eppx_box<eppx_variant>(key)), value);
+    } else if (const auto* dict = std::get_if<eppx_dict_ref>(&obj)) {
+        eppx_setitem(**dict, key, value);
+    } else {
+        throw std::runtime_error("TypeError: object does not support item assignment");
+    }
+}
+
+template<typename Key>
+void eppx_delitem(const eppx_variant& obj, const Key& key) {
+    if (const auto* list = std::get_if<eppx_list_ref>(&obj)) {
+        eppx_delitem(**list, variant_to_ll(eppx_box<eppx_variant>(key)));
+    } else if (const auto* dict = std::get_if<eppx_dict_ref>(&obj)) {
+        eppx_delitem(**dict, key);
+    } else {
+        throw std::runtime_error("TypeError: object does not support item deletion");
+    }
+}
+
+// dict-aware conversions
+template<typename K, typename V>
+std::vector<K> eppx_to_list(const eppx_dict<K, V>& d) {
+    return eppx_method_keys(d);
+}
+
+template<typename K, typename V>
+std::vector<K> eppx_sorted(const eppx_dict<K, V>& d) {
+    std::vector<K> keys = eppx_method_keys(d);
+    std::sort(keys.begin(), keys.end());
+    return keys;
+}
+
+template<typename Container, typename Key>
+auto eppx_sorted(const Container& container, Key key, bool reverse = false) {
+    std::vector<typename Container::value_type> result(container.begin(), container.end());
+    eppx_method_sort(result, key, reverse);
+    return result;
+}
+
+// ---------------------------------------------------------------------------
+// Exceptions that end iteration and the program, and the conversions and
+// exception matching generated code relies on; the generator frames
+// themselves are in eppx_generator.hpp
+// ---------------------------------------------------------------------------
+
+class StopIterationException : public std::exception {
+public:
+    eppx_variant value = nullptr; // the generator's return value
+
+    StopIterationException() = default;
+    explicit StopIterationException(eppx_variant return_value) : value(std::move(return_value)) {}
+
+    const char* what() const noexcept override {
+        return "StopIteration";
+    }
+};
+
+// str(e) of a StopIteration is its value, and empty without one
+inline std::ostream& operator<<(std::ostream& os, const StopIterationException& e) {
+    if (!std::holds_alternative<std::nullptr_t>(e.value)) {
+        os << e.value;
+    }
+    return os;
+}
+
+class GeneratorExitException : public std::exception {
+public:
+    const char* what() const noexcept override {
+        return "GeneratorExit";
+    }
+};
+
+// Raised inside a task by Task.cancel(); like GeneratorExit it is not an Exception
+class CancelledErrorException : public std::exception {
+public:
+    const char* what() const noexcept override {
+        return "CancelledError";
+    }
+};
+
+// Raised by sys.exit(); main() turns it into the process exit status. It is not
+// a std::exception, so `except Exception` handlers (catch std::exception) let it through
+class SystemExitException {
+public:
+    int code = 0;
+    std::string message; // printed to stderr by sys.exit("message"), which exits with 1
+
+    explicit SystemExitException(int exit_code) : code(exit_code), text_(std::to_string(exit_code)) {}
+    explicit SystemExitException(std::string exit_message) : code(1), message(exit_message), text_(std::move(exit_message)) {}
+
+    // str(e) in an `except SystemExit as e` handler
+    const char* what() const noexcept {
+        return text_.c_str();
+    }
+
+private:
+    std::string text_;
+};
+
+// Exit status for a SystemExit that reached main()
+inline int eppx_exit_status(const SystemExitException& e) {
+    if (!e.message.empty()) {
+        std::cout.flush();
+        std::cerr << e.message << std::endl;
+    }
+    return e.code;
+}
+
+// Convert any runtime value into an eppx_variant; tuples and pairs (enumerate,
+// zip, dict.items) become the vector representation used for E++ tuples
+template<typename T>
+eppx_variant eppx_to_variant(const T& value);
 
-// Comparison operators for eppx_variant
-bool operator==(const eppx_variant& left, const eppx_variant& right) {
-    if (left.index() != right.index()) {
-        return false;
-    }
-    return std::visit([](const auto& l, const auto& r) {
-        return l == r;
-    }, left, right);
+template<typename A, typename B>
+eppx_variant eppx_to_variant(const std::pair<A, B>& value) {
+    return std::vector<eppx_variant>{eppx_to_variant(value.first), eppx_to_variant(value.second)};
 }
 
-bool operator!=(const eppx_variant& left, const eppx_variant& right) {
-    return !(left == right);
+template<typename... Ts>
+eppx_variant eppx_to_variant(const std::tuple<Ts...>& value) {
+    std::vector<eppx_variant> items;
+    std::apply([&items](const auto&... item) { (items.push_back(eppx_to_variant(item)), ...); }, value);
+    return items;
 }
 
-bool operator<(const eppx_variant& left, const eppx_variant& right) {
-    if (std::holds_alternative<long long>(left) && std::holds_alternative<long long>(right)) {
-        return std::get<long long>(left) < std::get<long long>(right);
-    } else if (std::holds_alternative<double>(left) || std::holds_alternative<double>(right)) {
-        return variant_to_double(left) < variant_to_double(right);
-    } else if (std::holds_alternative<std::string>(left) && std::holds_alternative<std::string>(right)) {
-        return std::get<std::string>(left) < std::get<std::string>(right);
+template<typename T>
+eppx_variant eppx_to_variant(const std::vector<T>& value) {
+    std::vector<eppx_variant> items;
+    for (const auto& item : value) {
+        items.push_back(eppx_to_variant(item));
     }
-    return false;
+    return items;
 }
 
-bool operator<=(const eppx_variant& left, const eppx_variant& right) {
-    return left < right || left == right;
+template<typename T>
+eppx_variant eppx_to_variant(const T& value) {
+    if constexpr (eppx_is_instance_v<T>) {
+        // An instance made for the value (`yield P(1)`) goes on the heap
+        return eppx_share(value);
+    } else if constexpr (std::is_pointer_v<T> && eppx_is_instance_v<std::remove_pointer_t<T>>) {
+        // `self` in a method: the instance's own handle when it lives on the heap
+        auto& objects = eppx_instance_objects();
+        auto found = objects.find(value);
+        if (found != objects.end()) {
+            return eppx_object_ref(found->second);
+        }
+        return eppx_share(*value);
+    } else {
+        return eppx_box<eppx_variant>(value);
+    }
 }
 
-bool operator>(const eppx_variant& left, const eppx_variant& right) {
-    return !(left <= right);
-}
+// Can eppx_to_variant() represent a T?
+template<typename T>
+struct eppx_variant_like : std::bool_constant<std::is_arithmetic_v<T> || std::is_convertible_v<T, eppx_variant>> {};
 
-bool operator>=(const eppx_variant& left, const eppx_variant& right) {
-    return !(left < right);
-}
+template<typename A, typename B>
+struct eppx_variant_like<std::pair<A, B>> : std::bool_constant<eppx_variant_like<A>::value && eppx_variant_like<B>::value> {};
 
+template<typename... Ts>
+struct eppx_variant_like<std::tuple<Ts...>> : std::bool_constant<(eppx_variant_like<Ts>::value && ...)> {};
 
+// Lists keep their container type so list methods apply to them
+template<typename T>
+struct eppx_variant_like<std::vector<T>> : std::false_type {};
 
-// Range function (already exists)
-std::vector<long long> eppx_range(long long n) {
-    std::vector<long long> result;
-    result.reserve(n);
-    for (long long i = 0; i < n; ++i) {
-        result.push_back(i);
+// Containers and handles, which a variant could hold but which keep their own
+// type where their methods are called on them
+template<typename T>
+inline constexpr bool eppx_is_container_v = eppx_is_ref_v<T> || std::is_same_v<T, eppx_list_ref>;
+template<typename K, typename V>
+inline constexpr bool eppx_is_container_v<eppx_dict<K, V>> = true;
+template<typename T>
+inline constexpr bool eppx_is_container_v<std::set<T>> = true;
+
+// Type of a generator frame member first bound to a value of type T: plain
+// values live in an eppx_variant so they can be rebound to anything, other
+// objects (generators, tasks, queues, dicts, ...) keep their own type, and so
+// do list and dict handles, so a frame shares the object it was handed
+template<typename T>
+using eppx_member_t = std::conditional_t<
+    eppx_variant_like<std::decay_t<T>>::value && !eppx_is_container_v<std::decay_t<T>>,
+    eppx_variant,
+    std::decay_t<T>>;
+
+template<typename Target, typename Value>
+void eppx_store(Target& target, const Value& value) {
+    if constexpr (std::is_same_v<Target, eppx_variant>) {
+        target = eppx_to_variant(value);
+    } else if constexpr (std::is_same_v<Value, eppx_variant> && std::is_same_v<Target, std::vector<eppx_variant>>) {
+        target = *std::get<eppx_list_ref>(value);
+    } else if constexpr (std::is_assignable_v<Target&, const Value&>) {
+        target = value;
+    } else {
+        // A handle stored into a member of the object's own type copies it
+        target = eppx_items(value);
     }
-    return result;
 }
 
-// Range function overloads
-std::vector<long long> eppx_range(long long start, long long stop) {
-    std::vector<long long> result;
-    if (start < stop) {
-        result.reserve(stop - start);
-        for (long long i = start; i < stop; ++i) {
-            result.push_back(i);
-        }
+// An argument held in a variant (a generator or coroutine local) passed to a
+// constructor, whose parameters are integers
+inline long long eppx_param(const eppx_variant& value) { return variant_to_ll(value); }
+template<typename T>
+const T& eppx_param(const T& value) { return value; }
+
+// Python truthiness for conditions on values of any runtime type
+template<typename T>
+bool eppx_truthy(const T& value) {
+    if constexpr (std::is_same_v<T, eppx_variant>) {
+        return variant_to_bool(value);
+    } else if constexpr (std::is_constructible_v<bool, T>) {
+        return static_cast<bool>(value);
+    } else {
+        return !value.empty();
+    }
+}
+
+// `a if cond else b`: only the chosen branch is evaluated. Branches of the
+// same type keep it, numbers (but not bools) meet at their common type and
+// anything else becomes an eppx_variant
+template<typename Then, typename Else>
+auto eppx_ifexp(bool cond, Then then_branch, Else else_branch) {
+    using A = std::decay_t<decltype(then_branch())>;
+    using B = std::decay_t<decltype(else_branch())>;
+    if constexpr (std::is_same_v<A, B>) {
+        return cond ? then_branch() : else_branch();
+    } else if constexpr (std::is_arithmetic_v<A> && std::is_arithmetic_v<B>
+                         && !std::is_same_v<A, bool> && !std::is_same_v<B, bool>) {
+        using R = std::common_type_t<A, B>;
+        return cond ? static_cast<R>(then_branch()) : static_cast<R>(else_branch());
+    } else {
+        return cond ? eppx_to_variant(then_branch()) : eppx_to_variant(else_branch());
     }
-    return result;
 }
 
-std::vector<long long> eppx_range(long long start, long long stop, long long step) {
-    std::vector<long long> result;
-    if (step > 0 && start < stop) {
-        for (long long i = start; i < stop; i += step) {
-            result.push_back(i);
-        }
-    } else if (step < 0 && start > stop) {
-        for (long long i = start; i > stop; i += step) {
-            result.push_back(i);
-        }
-    }
+// Copies a string returned by the runtime library and releases it
+inline std::string eppx_rt_take(eppx_rt_string s) {
+    std::string result = s.data ? std::string(s.data, s.len) : std::string();
+    eppx_rt_string_free(s);
     return result;
 }
 
-// String representation functions
-std::string eppx_hex(long long n) {
-    std::stringstream ss;
-    ss << "0x" << std::hex << n;
-    return ss.str();
+// Is `exception` the builtin exception type `base` or derived from it?
+inline bool eppx_rt_is_subclass(const std::string& exception, const std::string& base) {
+    return eppx_rt_exception_is_subclass(exception.data(), exception.size(), base.data(), base.size());
 }
 
-std::string eppx_bin(long long n) {
-    if (n == 0) return "0b0";
-    
-    std::string binary = "";
-    long long temp = std::abs(n);
-    while (temp > 0) {
-        binary = (temp % 2 == 0 ? "0" : "1") + binary;
-        temp /= 2;
-    }
-    return std::string("0b") + (n < 0 ? "-" : "") + binary;
+// Builds the exception raised by gen.throw(...)
+inline std::exception_ptr eppx_make_exception(const std::string& type_name, const std::string& message) {
+    if (type_name == "StopIteration") return std::make_exception_ptr(StopIterationException());
+    if (type_name == "GeneratorExit") return std::make_exception_ptr(GeneratorExitException());
+    if (type_name == "CancelledError") return std::make_exception_ptr(CancelledErrorException());
+    if (type_name == "KeyError") return std::make_exception_ptr(KeyErrorException(message));
+    if (type_name == "ValueError") return std::make_exception_ptr(ValueErrorException(message));
+    if (type_name == "IndexError") return std::make_exception_ptr(IndexErrorException(message));
+    if (type_name == "AssertionError") return std::make_exception_ptr(AssertionErrorException(message));
+    if (type_name == "FrozenInstanceError") return std::make_exception_ptr(FrozenInstanceErrorException(message));
+    if (eppx_rt_is_subclass(type_name, "OSError")) return std::make_exception_ptr(OSErrorException(type_name, message));
+    return std::make_exception_ptr(std::runtime_error(message.empty() ? type_name : message));
 }
 
-std::string eppx_oct(long long n) {
-    std::stringstream ss;
-    ss << "0o" << std::oct << n;
-    return ss.str();
+// Throws the exception recorded by the last failed runtime library call
+[[noreturn]] inline void eppx_rt_raise() {
+    std::string type_name = eppx_rt_take(eppx_rt_last_error_type());
+    std::rethrow_exception(eppx_make_exception(type_name, eppx_rt_take(eppx_rt_last_error_message())));
 }
 
-// Collection functions
-template<typename Container>
-auto eppx_sum(const Container& container) -> typename Container::value_type {
-    return std::accumulate(container.begin(), container.end(), typename Container::value_type{});
+// Does an `except <type_name>` clause handle this exception?  Exceptions
+// without a dedicated runtime class match any clause except the ones below.
+inline bool eppx_exception_matches(const std::exception_ptr& exc, const std::string& type_name) {
+    if (type_name == "BaseException") return true;
+    try {
+        std::rethrow_exception(exc);
+    } catch (const GeneratorExitException&) {
+        return eppx_rt_is_subclass("GeneratorExit", type_name);
+    } catch (const CancelledErrorException&) {
+        return eppx_rt_is_subclass("CancelledError", type_name);
+    } catch (const SystemExitException&) {
+        return eppx_rt_is_subclass("SystemExit", type_name);
+    } catch (const StopIterationException&) {
+        return eppx_rt_is_subclass("StopIteration", type_name);
+    } catch (const KeyErrorException&) {
+        return eppx_rt_is_subclass("KeyError", type_name);
+    } catch (const IndexErrorException&) {
+        return eppx_rt_is_subclass("IndexError", type_name);
+    } catch (const ValueErrorException&) {
+        return eppx_rt_is_subclass("ValueError", type_name);
+    } catch (const AssertionErrorException&) {
+        return eppx_rt_is_subclass("AssertionError", type_name);
+    } catch (const FrozenInstanceErrorException&) {
+        return eppx_rt_is_subclass("FrozenInstanceError", type_name);
+    } catch (const OSErrorException& e) {
+        return eppx_rt_is_subclass(e.type_name, type_name);
+    } catch (...) {
+        return type_name != "GeneratorExit" && type_name != "CancelledError" && type_name != "SystemExit" && type_name != "StopIteration" && type_name != "KeyError"
+            && type_name != "IndexError" && type_name != "LookupError" && type_name != "ValueError" && type_name != "AssertionError"
+            && type_name != "FrozenInstanceError";
+    }
 }
 
-// Specialized sum for eppx_variant vectors
-template<>
-eppx_variant eppx_sum(const std::vector<eppx_variant>& container) {
-    eppx_variant sum = 0LL;
-    for (const auto& item : container) {
-        if (std::holds_alternative<long long>(sum) && std::holds_alternative<long long>(item)) {
-            sum = std::get<long long>(sum) + std::get<long long>(item);
-        } else if (std::holds_alternative<double>(sum) || std::holds_alternative<double>(item)) {
-            double s = variant_to_double(sum);
-            double i = variant_to_double(item);
-            sum = s + i;
-        }
+// The StopIteration a generator's `except StopIteration as e` caught
+inline StopIterationException eppx_stop_iteration(const std::exception_ptr& exc) {
+    try {
+        std::rethrow_exception(exc);
+    } catch (const StopIterationException& e) {
+        return e;
+    } catch (...) {
+        return StopIterationException();
     }
-    return sum;
 }
 
-template<typename Container>
-bool eppx_all(const Container& container) {
-    return std::all_of(container.begin(), container.end(), 
-                      [](const auto& item) { return variant_to_bool(item); });
+inline std::string eppx_exception_message(const std::exception_ptr& exc) {
+    try {
+        std::rethrow_exception(exc);
+    } catch (const std::exception& e) {
+        return e.what();
+    } catch (...) {
+        return "unknown exception";
+    }
 }
 
-template<typename Container>
-bool eppx_any(const Container& container) {
-    return std::any_of(container.begin(), container.end(), 
-                      [](const auto& item) { return variant_to_bool(item); });
+// close() on a file object, which is held by pointer (generators have their
+// own overload in eppx_generator.hpp)
+template<typename Obj>
+auto eppx_method_close(Obj& obj) -> decltype(obj->close()) {
+    return obj->close();
 }
 
-template<typename Container>
-Container eppx_reversed(Container container) {
-    std::reverse(container.begin(), container.end());
-    return container;
+// The gc module: control over the cycle collector
+namespace gc {
+
+inline long long collect() { return eppx_gc::collect(); }
+inline void enable() { eppx_gc::state().enabled = true; }
+inline void disable() { eppx_gc::state().enabled = false; }
+inline bool isenabled() { return eppx_gc::state().enabled; }
+
+} // namespace gc
+
+namespace sys {
+
+// The program's path followed by its arguments (`eppx run file.eppx -- args`)
+inline std::vector<std::string> argv;
+
+inline void eppx_set_argv(int argc, char** args) {
+    argv.assign(args, args + argc);
 }
 
-template<typename Container>
-Container eppx_sorted(Container container) {
-    std::sort(container.begin(), container.end());
-    return container;
+// sys.exit() unwinds like Python's SystemExit, so finally blocks and with statements still run
+[[noreturn]] inline void exit(long long code = 0) {
+    throw SystemExitException(static_cast<int>(code));
 }
 
-// Collection converters
-template<typename T>
-std::vector<T> eppx_to_list(const std::vector<T>& vec) {
-    return vec; // Already a list
+[[noreturn]] inline void exit(const std::string& message) {
+    throw SystemExitException(message);
 }
 
-template<typename T>
-std::vector<T> eppx_to_list(const std::set<T>& s) {
-    return std::vector<T>(s.begin(), s.end());
+// References to a list, including the one held by the argument itself (as in
+// CPython). Other values are copied into the call, so that is their only one.
+inline long long getrefcount(eppx_list_ref list) {
+    return list.get()->refcount;
 }
 
 template<typename T>
-std::set<T> eppx_to_set(const std::vector<T>& vec) {
-    return std::set<T>(vec.begin(), vec.end());
+long long getrefcount(eppx_ref<T> ref) {
+    return ref.get()->refcount;
 }
 
-// List conversion function (alias for eppx_to_list)
-template<typename Container>
-auto eppx_list(const Container& container) -> std::vector<typename Container::value_type> {
-    return std::vector<typename Container::value_type>(container.begin(), container.end());
+inline long long getrefcount(eppx_variant value) {
+    const eppx_gc::object* object = eppx_variant_object(value);
+    return object ? object->refcount : 1;
 }
 
+template<typename T>
+long long getrefcount(const T&) {
+    return 1;
+}
+
+} // namespace sys
 // I/O functions
-std::string eppx_input() {
+inline std::string eppx_input() {
     std::string line;
     std::getline(std::cin, line);
     return line;
 }
 
-std::string eppx_input(const std::string& prompt) {
+inline std::string eppx_input(const std::string& prompt) {
     std::cout << prompt;
     std::string line;
     std::getline(std::cin, line);
@@ -326,7 +2147,7 @@ std::string eppx_input(const std::string& prompt) {
 }
 
 // Type functions
-std::string eppx_type(const eppx_variant& var) {
+inline std::string eppx_type(const eppx_variant& var) {
     return std::visit([](const auto& v) -> std::string {
         using T = std::decay_t<decltype(v)>;
         if constexpr (std::is_same_v<T, long long>) {
@@ -345,22 +2166,22 @@ std::string eppx_type(const eppx_variant& var) {
 
 // Note: isinstance, callable, and hasattr are implemented later with improved versions
 
-eppx_variant eppx_getattr(const eppx_variant& obj, const std::string& name) {
+inline eppx_variant eppx_getattr(const eppx_variant& obj, const std::string& name) {
     // Stub implementation - would need object system
     throw std::runtime_error("getattr not implemented for this type");
 }
 
-eppx_variant eppx_getattr(const eppx_variant& obj, const std::string& name, const eppx_variant& default_value) {
+inline eppx_variant eppx_getattr(const eppx_variant& obj, const std::string& name, const eppx_variant& default_value) {
     // Stub implementation - would need object system
     return default_value;
 }
 
-void eppx_setattr(const eppx_variant& obj, const std::string& name, const eppx_variant& value) {
+inline void eppx_setattr(const eppx_variant& obj, const std::string& name, const eppx_variant& value) {
     // Stub implementation - would need object system
     throw std::runtime_error("setattr not implemented for this type");
 }
 
-void eppx_delattr(const eppx_variant& obj, const std::string& name) {
+inline void eppx_delattr(const eppx_variant& obj, const std::string& name) {
     // Stub implementation - would need object system
     throw std::runtime_error("delattr not implemented for this type");
 }
@@ -388,16 +2209,20 @@ auto eppx_len(const Container& container) -> size_t {
 }
 
 // String length specialization
-size_t eppx_len(const std::string& str) {
+inline size_t eppx_len(const std::string& str) {
     return str.length();
 }
 
 // eppx_variant length specialization
-size_t eppx_len(const eppx_variant& var) {
+inline size_t eppx_len(const eppx_variant& var) {
     if (std::holds_alternative<std::string>(var)) {
         return std::get<std::string>(var).length();
-    } else if (std::holds_alternative<std::vector<eppx_variant>>(var)) {
-        return std::get<std::vector<eppx_variant>>(var).size();
+    } else if (std::holds_alternative<eppx_list_ref>(var)) {
+        return std::get<eppx_list_ref>(var)->size();
+    } else if (const auto* dict = std::get_if<eppx_dict_ref>(&var)) {
+        return (*dict)->size();
+    } else if (const auto* set = std::get_if<eppx_set_ref>(&var)) {
+        return (*set)->size();
     }
     // For other types, return 0 or throw an error
     throw std::runtime_error("len() not supported for this type");
@@ -425,231 +2250,49 @@ auto eppx_min(const Container& container) -> typename Container::value_type {
     return *std::min_element(container.begin(), container.end());
 }
 
-// File I/O functions
-class EppxFile {
-private:
-    std::string filepath;
-    std::string mode;
-    std::fstream file_stream;
-    bool is_open;
-    std::streampos position;
-
-public:
-    EppxFile(const std::string& path, const std::string& file_mode) 
-        : filepath(path), mode(file_mode), is_open(false), position(0) {}
-
-    bool open() {
-        std::ios_base::openmode open_mode = std::ios_base::in;
-        
-        if (mode.find('w') != std::string::npos) {
-            open_mode = std::ios_base::out | std::ios_base::trunc;
-        } else if (mode.find('a') != std::string::npos) {
-            open_mode = std::ios_base::out | std::ios_base::app;
-        } else if (mode.find('r') != std::string::npos) {
-            open_mode = std::ios_base::in;
-        }
-        
-        if (mode.find('+') != std::string::npos) {
-            open_mode |= std::ios_base::in | std::ios_base::out;
-        }
-        
-        if (mode.find('b') != std::string::npos) {
-            open_mode |= std::ios_base::binary;
-        }
-
-        file_stream.open(filepath, open_mode);
-        is_open = file_stream.is_open();
-        return is_open;
-    }
-
-    std::string read(int size = -1) {
-        if (!is_open) {
-            throw std::runtime_error("I/O operation on closed file");
-        }
-        
-        std::string content;
-        if (size == -1) {
-            // Read entire file
-            file_stream.seekg(0, std::ios::end);
-            content.reserve(file_stream.tellg());
-            file_stream.seekg(0, std::ios::beg);
-            content.assign((std::istreambuf_iterator<char>(file_stream)),
-                          std::istreambuf_iterator<char>());
-        } else {
-            // Read specific number of characters
-            content.resize(size);
-            file_stream.read(&content[0], size);
-            content.resize(file_stream.gcount());
-        }
-        return content;
-    }
-
-    std::string readline(int size = -1) {
-        if (!is_open) {
-            throw std::runtime_error("I/O operation on closed file");
-        }
-        
-        std::string line;
-        std::getline(file_stream, line);
-        if (size != -1 && line.length() > size) {
-            line = line.substr(0, size);
-        }
-        return line + "\n"; // Python readline includes newline
-    }
-
-    std::vector<std::string> readlines(int hint = -1) {
-        if (!is_open) {
-            throw std::runtime_error("I/O operation on closed file");
-        }
-        
-        std::vector<std::string> lines;
-        std::string line;
-        int total_size = 0;
-        
-        while (std::getline(file_stream, line)) {
-            line += "\n";
-            if (hint != -1 && total_size + line.length() > hint) {
-                break;
-            }
-            lines.push_back(line);
-            total_size += line.length();
-        }
-        return lines;
-    }
-
-    int write(const std::string& data) {
-        if (!is_open) {
-            throw std::runtime_error("I/O operation on closed file");
-        }
-        
-        file_stream << data;
-        return data.length();
-    }
-
-    void writelines(const std::vector<std::string>& lines) {
-        for (const auto& line : lines) {
-            write(line);
-        }
-    }
-
-    void close() {
-        if (is_open) {
-            file_stream.close();
-            is_open = false;
-        }
-    }
-
-    void flush() {
-        if (is_open) {
-            file_stream.flush();
-        }
-    }
-
-    std::streampos seek(std::streamoff offset, std::ios_base::seekdir whence = std::ios_base::beg) {
-        if (!is_open) {
-            throw std::runtime_error("I/O operation on closed file");
-        }
-        
-        file_stream.seekg(offset, whence);
-        file_stream.seekp(offset, whence);
-        return file_stream.tellg();
-    }
-
-    std::streampos tell() {
-        if (!is_open) {
-            throw std::runtime_error("I/O operation on closed file");
-        }
-        return file_stream.tellg();
-    }
-
-    bool readable() const {
-        return is_open && (mode.find('r') != std::string::npos || mode.find('+') != std::string::npos);
-    }
-
-    bool writable() const {
-        return is_open && (mode.find('w') != std::string::npos || 
-                          mode.find('a') != std::string::npos || 
-                          mode.find('+') != std::string::npos);
-    }
-
-    bool seekable() const {
-        return is_open;
-    }
-
-    bool closed() const {
-        return !is_open;
-    }
+// String utility functions
+inline std::string eppx_upper(const std::string& s) {
+    return eppx_rt_take(eppx_rt_str_upper(s.data(), s.size()));
+}
 
-    std::string get_mode() const {
-        return mode;
-    }
+inline std::string eppx_upper(const eppx_variant& v) {
+    return eppx_upper(variant_to_string(v));
+}
 
-    std::string get_name() const {
-        return filepath;
-    }
-};
+inline std::string eppx_lower(const std::string& s) {
+    return eppx_rt_take(eppx_rt_str_lower(s.data(), s.size()));
+}
 
-// File I/O builtin functions
-std::shared_ptr<EppxFile> eppx_open(const std::string& filepath, 
-                                    const std::string& mode = "r",
-                                    int buffering = -1,
-                                    const std::string& encoding = "",
-                                    const std::string& errors = "strict",
-                                    const std::string& newline = "",
-                                    bool closefd = true) {
-    auto file_obj = std::make_shared<EppxFile>(filepath, mode);
-    if (!file_obj->open()) {
-        if (mode.find('r') != std::string::npos) {
-            throw std::runtime_error("No such file or directory: '" + filepath + "'");
-        } else {
-            throw std::runtime_error("Could not open file: '" + filepath + "'");
-        }
-    }
-    return file_obj;
+inline std::string eppx_lower(const eppx_variant& v) {
+    return eppx_lower(variant_to_string(v));
 }
 
-// Context manager for files
-// (Needed for Python-style with open(...) as ...)
-template<typename FileType>
-class EppxFileContextManager {
-private:
-    FileType file_obj;
-    bool should_close;
-public:
-    EppxFileContextManager(FileType f) : file_obj(f), should_close(true) {}
-    FileType& __enter__() { return file_obj; }
-    bool __exit__(const std::string& exc_type = "", const std::string& exc_val = "", const std::string& exc_tb = "") {
-        if (should_close && file_obj) {
-            file_obj->close();
-        }
-        return false; // Don't suppress exceptions
-    }
-};
+inline bool eppx_contains(const std::string& haystack, const std::string& needle) {
+    return eppx_rt_str_contains(haystack.data(), haystack.size(), needle.data(), needle.size());
+}
 
-template<typename FileType>
-EppxFileContextManager<FileType> eppx_with_file(FileType file_obj) {
-    return EppxFileContextManager<FileType>(file_obj);
+inline long long eppx_method_count(const std::string& s, const std::string& sub) {
+    return eppx_rt_str_count(s.data(), s.size(), sub.data(), sub.size());
 }
 
-// String utility functions
-std::string eppx_upper(const std::string& s) {
-    std::string result = s;
-    std::transform(result.begin(), result.end(), result.begin(), ::toupper);
-    return result;
+inline long long eppx_method_index(const std::string& s, const std::string& sub) {
+    long long index = eppx_rt_str_index(s.data(), s.size(), sub.data(), sub.size());
+    if (index < 0) {
+        eppx_rt_raise();
+    }
+    return index;
 }
 
-std::string eppx_upper(const eppx_variant& v) {
-    return eppx_upper(variant_to_string(v));
+inline std::string eppx_hex(long long n) {
+    return eppx_rt_take(eppx_rt_int_to_radix(n, 16));
 }
 
-std::string eppx_lower(const std::string& s) {
-    std::string result = s;
-    std::transform(result.begin(), result.end(), result.begin(), ::tolower);
-    return result;
+inline std::string eppx_bin(long long n) {
+    return eppx_rt_take(eppx_rt_int_to_radix(n, 2));
 }
 
-std::string eppx_lower(const eppx_variant& v) {
-    return eppx_lower(variant_to_string(v));
+inline std::string eppx_oct(long long n) {
+    return eppx_rt_take(eppx_rt_int_to_radix(n, 8));
 }
 
 // Iterator and generator support
@@ -823,11 +2466,11 @@ public:
 };
 
 // Global iterator storage (simplified approach)
-static std::map<std::string, IteratorLL> ll_iterators;
-static int iterator_counter = 0;
+inline std::map<std::string, IteratorLL> ll_iterators;
+inline int iterator_counter = 0;
 
 // iter() function for eppx_variant vectors
-std::string iter(const std::vector<eppx_variant>& container) {
+inline std::string iter(const std::vector<eppx_variant>& container) {
     std::string iter_id = "iter_" + std::to_string(iterator_counter++);
     ll_iterators[iter_id] = IteratorLL(container);
     return iter_id;
@@ -840,7 +2483,7 @@ auto next(Generator& gen) -> decltype(gen.next_value()) {
 }
 
 // next() function for eppx_variant iterators
-eppx_variant next(const std::string& iter_id) {
+inline eppx_variant next(const std::string& iter_id) {
     auto it = ll_iterators.find(iter_id);
     if (it != ll_iterators.end()) {
         return it->second.next();
@@ -849,7 +2492,7 @@ eppx_variant next(const std::string& iter_id) {
 }
 
 // ASCII representation function
-std::string eppx_ascii(const eppx_variant& obj) {
+inline std::string eppx_ascii(const eppx_variant& obj) {
     std::string result = "\"";
     std::string str_val = variant_to_string(obj);
     for (char c : str_val) {
@@ -867,7 +2510,7 @@ std::string eppx_ascii(const eppx_variant& obj) {
 }
 
 // Breakpoint function (debugging stub)
-void eppx_breakpoint() {
+inline void eppx_breakpoint() {
     std::cout << "Breakpoint reached. Press Enter to continue..." << std::endl;
     std::cin.get();
 }
@@ -939,39 +2582,39 @@ public:
 };
 
 // Bytearray and bytes factory functions
-EppxByteArray eppx_bytearray() {
+inline EppxByteArray eppx_bytearray() {
     return EppxByteArray();
 }
 
-EppxByteArray eppx_bytearray(const std::string& str) {
+inline EppxByteArray eppx_bytearray(const std::string& str) {
     return EppxByteArray(str);
 }
 
-EppxByteArray eppx_bytearray(const std::vector<int>& values) {
+inline EppxByteArray eppx_bytearray(const std::vector<int>& values) {
     return EppxByteArray(values);
 }
 
-EppxBytes eppx_bytes() {
+inline EppxBytes eppx_bytes() {
     return EppxBytes();
 }
 
-EppxBytes eppx_bytes(const std::string& str) {
+inline EppxBytes eppx_bytes(const std::string& str) {
     return EppxBytes(str);
 }
 
-EppxBytes eppx_bytes(const std::vector<int>& values) {
+inline EppxBytes eppx_bytes(const std::vector<int>& values) {
     return EppxBytes(values);
 }
 
 // Improved callable function
-bool eppx_callable(const eppx_variant& obj) {
+inline bool eppx_callable(const eppx_variant& obj) {
     // In a real implementation, this would check if the object has a __call__ method
     // For now, we'll return false for all basic types
     return false;
 }
 
 // Directory listing function
-std::vector<std::string> eppx_dir(const eppx_variant& obj) {
+inline std::vector<std::string> eppx_dir(const eppx_variant& obj) {
     // Simplified implementation - would need full object introspection
     std::vector<std::string> attributes;
     
@@ -986,7 +2629,7 @@ std::vector<std::string> eppx_dir(const eppx_variant& obj) {
                      "rjust", "rpartition", "rsplit", "rstrip", "split",
                      "splitlines", "startswith", "strip", "swapcase", "title",
                      "translate", "upper", "zfill"};
-    } else if (std::holds_alternative<std::vector<eppx_variant>>(obj)) {
+    } else if (std::holds_alternative<eppx_list_ref>(obj)) {
         // List methods
         attributes = {"append", "clear", "copy", "count", "extend", "index",
                      "insert", "pop", "remove", "reverse", "sort"};
@@ -1004,11 +2647,11 @@ std::vector<std::string> eppx_dir(const eppx_variant& obj) {
 }
 
 // Divmod function
-std::pair<long long, long long> eppx_divmod(long long a, long long b) {
+inline std::pair<long long, long long> eppx_divmod(long long a, long long b) {
     return std::make_pair(a / b, a % b);
 }
 
-std::pair<double, double> eppx_divmod(double a, double b) {
+inline std::pair<double, double> eppx_divmod(double a, double b) {
     double quotient = std::floor(a / b);
     double remainder = a - quotient * b;
     return std::make_pair(quotient, remainder);
@@ -1026,7 +2669,7 @@ std::vector<std::pair<size_t, typename Container::value_type>> eppx_enumerate(co
 }
 
 // Eval function (simplified - would need full parser)
-eppx_variant eppx_eval(const std::string& expression) {
+inline eppx_variant eppx_eval(const std::string& expression) {
     // Extremely simplified eval - only handles basic arithmetic
     // In a real implementation, this would parse and evaluate the expression
     try {
@@ -1044,13 +2687,13 @@ eppx_variant eppx_eval(const std::string& expression) {
 }
 
 // Exec function (stub)
-void eppx_exec(const std::string& code) {
+inline void eppx_exec(const std::string& code) {
     // Stub implementation - would need full interpreter
     throw std::runtime_error("exec() not fully implemented");
 }
 
 // Format function
-std::string eppx_format(const eppx_variant& value, const std::string& format_spec = "") {
+inline std::string eppx_format(const eppx_variant& value, const std::string& format_spec = "") {
     if (format_spec.empty()) {
         return variant_to_string(value);
     }
@@ -1074,7 +2717,7 @@ std::string eppx_format(const eppx_variant& value, const std::string& format_spe
 }
 
 // Globals function (stub)
-std::map<std::string, eppx_variant> eppx_globals() {
+inline std::map<std::string, eppx_variant> eppx_globals() {
     // Stub implementation - would need access to global scope
     std::map<std::string, eppx_variant> globals;
     globals["__name__"] = std::string("__main__");
@@ -1083,12 +2726,12 @@ std::map<std::string, eppx_variant> eppx_globals() {
 }
 
 // Improved hasattr function
-bool eppx_hasattr(const eppx_variant& obj, const std::string& name) {
+inline bool eppx_hasattr(const eppx_variant& obj, const std::string& name) {
     // Simplified implementation based on type
     if (std::holds_alternative<std::string>(obj)) {
         std::vector<std::string> string_attrs = {"upper", "lower", "strip", "split", "replace", "find"};
         return std::find(string_attrs.begin(), string_attrs.end(), name) != string_attrs.end();
-    } else if (std::holds_alternative<std::vector<eppx_variant>>(obj)) {
+    } else if (std::holds_alternative<eppx_list_ref>(obj)) {
         std::vector<std::string> list_attrs = {"append", "extend", "pop", "remove", "index", "count"};
         return std::find(list_attrs.begin(), list_attrs.end(), name) != list_attrs.end();
     }
@@ -1096,7 +2739,7 @@ bool eppx_hasattr(const eppx_variant& obj, const std::string& name) {
 }
 
 // Hash function
-size_t eppx_hash(const eppx_variant& obj) {
+inline size_t eppx_hash(const eppx_variant& obj) {
     return std::visit([](const auto& value) -> size_t {
         using T = std::decay_t<decltype(value)>;
         if constexpr (std::is_same_v<T, long long>) {
@@ -1114,7 +2757,7 @@ size_t eppx_hash(const eppx_variant& obj) {
 }
 
 // Help function
-void eppx_help(const eppx_variant& obj = eppx_variant{}) {
+inline void eppx_help(const eppx_variant& obj = eppx_variant{}) {
     if (std::holds_alternative<std::string>(obj)) {
         std::cout << "Help on built-in function " << std::get<std::string>(obj) << std::endl;
     } else {
@@ -1123,25 +2766,73 @@ void eppx_help(const eppx_variant& obj = eppx_variant{}) {
     }
 }
 
-// ID function (memory address)
-uintptr_t eppx_id(const eppx_variant& obj) {
+// ID function (memory address); lists, dicts, sets and instances are
+// identified by their shared object
+inline uintptr_t eppx_id(const eppx_variant& obj) {
+    if (const eppx_gc::object* object = eppx_variant_object(obj)) {
+        return reinterpret_cast<uintptr_t>(object);
+    }
     return reinterpret_cast<uintptr_t>(&obj);
 }
 
+inline uintptr_t eppx_id(const eppx_list_ref& list) {
+    return reinterpret_cast<uintptr_t>(list.get());
+}
+
+template<typename T>
+uintptr_t eppx_id(const eppx_ref<T>& ref) {
+    return reinterpret_cast<uintptr_t>(ref.get());
+}
+
+// Identity comparison: lists, dicts, sets and instances are the same object only
+// when they share storage; other values have no identity of their own and
+// compare by value
+inline const eppx_gc::object* eppx_identity(const eppx_list_ref& list) {
+    return list.get();
+}
+
+template<typename T>
+const eppx_gc::object* eppx_identity(const eppx_ref<T>& ref) {
+    return ref.get();
+}
+
+inline const eppx_gc::object* eppx_identity(const eppx_variant& value) {
+    return eppx_variant_object(value);
+}
+
+template<typename T>
+const eppx_gc::object* eppx_identity(const T&) {
+    return nullptr;
+}
+
+template<typename A, typename B>
+bool eppx_is(const A& a, const B& b) {
+    const eppx_gc::object* left = eppx_identity(a);
+    const eppx_gc::object* right = eppx_identity(b);
+    if constexpr (eppx_is_ref_v<A> || eppx_is_ref_v<B>) {
+        return left == right;
+    } else {
+        if (left || right) {
+            return left == right;
+        }
+        return a == b;
+    }
+}
+
 // Improved isinstance function
-bool eppx_isinstance(const eppx_variant& obj, const std::string& type_name) {
+inline bool eppx_isinstance(const eppx_variant& obj, const std::string& type_name) {
     std::string obj_type = eppx_type(obj);
     return obj_type.find(type_name) != std::string::npos;
 }
 
 // Issubclass function (stub)
-bool eppx_issubclass(const std::string& subclass, const std::string& baseclass) {
+inline bool eppx_issubclass(const std::string& subclass, const std::string& baseclass) {
     // Simplified implementation - would need full class hierarchy
     return subclass == baseclass;
 }
 
 // Locals function (stub)
-std::map<std::string, eppx_variant> eppx_locals() {
+inline std::map<std::string, eppx_variant> eppx_locals() {
     // Stub implementation - would need access to local scope
     return std::map<std::string, eppx_variant>();
 }
@@ -1162,7 +2853,7 @@ public:
     std::string get_format() const { return format; }
 };
 
-EppxMemoryView eppx_memoryview(const EppxBytes& bytes_obj) {
+inline EppxMemoryView eppx_memoryview(const EppxBytes& bytes_obj) {
     return EppxMemoryView(bytes_obj.to_string().data(), bytes_obj.size());
 }
 
@@ -1174,12 +2865,12 @@ public:
     virtual size_t hash() const { return reinterpret_cast<size_t>(this); }
 };
 
-EppxObject eppx_object() {
+inline EppxObject eppx_object() {
     return EppxObject();
 }
 
 // Repr function
-std::string eppx_repr(const eppx_variant& obj) {
+inline std::string eppx_repr(const eppx_variant& obj) {
     return std::visit([](const auto& value) -> std::string {
         using T = std::decay_t<decltype(value)>;
         if constexpr (std::is_same_v<T, std::string>) {
@@ -1190,20 +2881,86 @@ std::string eppx_repr(const eppx_variant& obj) {
             return std::to_string(value);
         } else if constexpr (std::is_same_v<T, bool>) {
             return value ? "True" : "False";
-        } else if constexpr (std::is_same_v<T, std::vector<eppx_variant>>) {
+        } else if constexpr (std::is_same_v<T, eppx_list_ref>) {
+            if (!eppx_repr_enter(&*value)) {
+                return "[...]";
+            }
             std::string result = "[";
-            for (size_t i = 0; i < value.size(); ++i) {
+            for (size_t i = 0; i < value->size(); ++i) {
                 if (i > 0) result += ", ";
-                result += eppx_repr(value[i]);
+                result += eppx_repr((*value)[i]);
             }
             result += "]";
+            eppx_repr_leave(&*value);
             return result;
+        } else if constexpr (std::is_same_v<T, eppx_dict_ref> || std::is_same_v<T, eppx_set_ref> || std::is_same_v<T, eppx_object_ref>) {
+            std::ostringstream ss;
+            ss << value;
+            return ss.str();
         } else {
             return "<object>";
         }
     }, obj);
 }
 
+// Objects of classes that define __repr__ (dataclasses and enums synthesize one)
+template<typename T, typename = decltype(std::declval<T&>().__repr__())>
+std::string eppx_repr(const T& obj) {
+    return const_cast<T&>(obj).__repr__();
+}
+
+// repr() of a dataclass field or enum value, whatever its C++ type
+template<typename T>
+std::string eppx_field_repr(const T& value) {
+    if constexpr (std::is_same_v<T, std::string>) {
+        std::string result = "'";
+        for (char c : value) {
+            if (c == '\\' || c == '\'') result += '\\';
+            result += c;
+        }
+        return result + "'";
+    } else if constexpr (std::is_same_v<T, bool>) {
+        return value ? "True" : "False";
+    } else if constexpr (std::is_floating_point_v<T>) {
+        std::string text = eppx_complex_part_repr(value);
+        if (text.find_first_of(".ein") == std::string::npos) text += ".0";
+        return text;
+    } else if constexpr (std::is_integral_v<T>) {
+        return std::to_string(value);
+    } else if constexpr (std::is_same_v<T, std::nullptr_t>) {
+        return "None";
+    } else if constexpr (std::is_same_v<T, eppx_variant>) {
+        if (std::holds_alternative<std::string>(value)) return eppx_field_repr(std::get<std::string>(value));
+        if (std::holds_alternative<double>(value)) return eppx_field_repr(std::get<double>(value));
+        if (std::holds_alternative<std::nullptr_t>(value)) return "None";
+        if (std::holds_alternative<eppx_list_ref>(value)) return eppx_field_repr(*std::get<eppx_list_ref>(value));
+        return variant_to_string(value);
+    } else if constexpr (std::is_same_v<T, std::vector<eppx_variant>>) {
+        std::string result = "[";
+        for (size_t i = 0; i < value.size(); ++i) {
+            if (i > 0) result += ", ";
+            result += eppx_field_repr(value[i]);
+        }
+        return result + "]";
+    } else {
+        return eppx_repr(value);
+    }
+}
+
+template<typename T, typename = void>
+struct eppx_is_frozen : std::false_type {};
+
+template<typename T>
+struct eppx_is_frozen<T, std::void_t<decltype(T::__eppx_frozen__)>> : std::bool_constant<T::__eppx_frozen__> {};
+
+// Assigning to a field of a frozen dataclass raises, as in Python
+template<typename T>
+void eppx_check_setattr(const T&, const char* attr) {
+    if constexpr (eppx_is_frozen<T>::value) {
+        throw FrozenInstanceErrorException(std::string("cannot assign to field '") + attr + "'");
+    }
+}
+
 // Slice class
 class EppxSlice {
 private:
@@ -1236,14 +2993,14 @@ public:
     }
 };
 
-EppxSlice eppx_slice(std::optional<long long> start = std::nullopt, 
+inline EppxSlice eppx_slice(std::optional<long long> start = std::nullopt, 
                      std::optional<long long> stop = std::nullopt, 
                      std::optional<long long> step = std::nullopt) {
     return EppxSlice(start, stop, step);
 }
 
 // Vars function
-std::map<std::string, eppx_variant> eppx_vars(const eppx_variant& obj = eppx_variant{}) {
+inline std::map<std::string, eppx_variant> eppx_vars(const eppx_variant& obj = eppx_variant{}) {
     // Simplified implementation - would need object introspection
     return std::map<std::string, eppx_variant>();
 }
@@ -1294,25 +3051,46 @@ eppx_zip(const Container1& c1, const Container2& c2, const Container3& c3) {
 }
 
 // Import function (stub)
-eppx_variant eppx_import(const std::string& module_name) {
+inline eppx_variant eppx_import(const std::string& module_name) {
     // Stub implementation - would need full module system
     throw std::runtime_error("__import__() not implemented");
 }
 
-// Character conversion functions (chr and ord)
-std::string eppx_chr(long long code) {
-    if (code < 0 || code > 1114111) {
-        throw std::runtime_error("chr() arg not in range(0x110000)");
+// Character conversion functions (chr and ord), on code points encoded as UTF-8
+inline std::string eppx_chr(long long code) {
+    eppx_rt_string result = eppx_rt_chr(code);
+    if (!result.data) {
+        eppx_rt_raise();
     }
-    return std::string(1, static_cast<char>(code));
+    return eppx_rt_take(result);
 }
 
-long long eppx_ord(const std::string& char_str) {
-    if (char_str.length() != 1) {
-        throw std::runtime_error("ord() expected a character, but string of length " + 
-                                std::to_string(char_str.length()) + " found");
+inline long long eppx_ord(const std::string& char_str) {
+    long long code = eppx_rt_ord(char_str.data(), char_str.size());
+    if (code < 0) {
+        eppx_rt_raise();
     }
-    return static_cast<long long>(static_cast<unsigned char>(char_str[0]));
+    return code;
+}
+
+// complex(), complex(x) and complex(real, imag); either argument may itself be complex
+inline eppx_complex eppx_make_complex() {
+    return {};
+}
+
+template<typename Real>
+eppx_complex eppx_make_complex(const Real& real) {
+    if constexpr (std::is_same_v<Real, eppx_variant>) {
+        return variant_to_complex(real);
+    } else {
+        return eppx_complex(eppx_as_std_complex(real));
+    }
+}
+
+template<typename Real, typename Imag>
+eppx_complex eppx_make_complex(const Real& real, const Imag& imag) {
+    // complex(a, b) is a + b*1j
+    return eppx_make_complex(real) + eppx_make_complex(imag) * eppx_complex(0.0, 1.0);
 }
 
 // Absolute value functions
@@ -1325,14 +3103,21 @@ auto eppx_abs(T value) -> T {
     }
 }
 
+// abs() of a complex number is its magnitude
+inline double eppx_abs(const eppx_complex& value) {
+    return std::hypot(value.real, value.imag);
+}
+
 // Specialized abs for eppx_variant
-eppx_variant eppx_abs(const eppx_variant& value) {
+inline eppx_variant eppx_abs(const eppx_variant& value) {
     return std::visit([](const auto& v) -> eppx_variant {
         using T = std::decay_t<decltype(v)>;
         if constexpr (std::is_same_v<T, long long>) {
             return std::abs(v);
         } else if constexpr (std::is_same_v<T, double>) {
             return std::abs(v);
+        } else if constexpr (std::is_same_v<T, eppx_complex>) {
+            return eppx_abs(v);
         } else {
             return v; // Return unchanged for non-numeric types
         }
@@ -1340,12 +3125,12 @@ eppx_variant eppx_abs(const eppx_variant& value) {
 }
 
 // Round function with precision
-double eppx_round(double value, int ndigits = 0) {
+inline double eppx_round(double value, int ndigits = 0) {
     double factor = std::pow(10.0, ndigits);
     return std::round(value * factor) / factor;
 }
 
-eppx_variant eppx_round(const eppx_variant& value, int ndigits = 0) {
+inline eppx_variant eppx_round(const eppx_variant& value, int ndigits = 0) {
     double val = variant_to_double(value);
     return eppx_round(val, ndigits);
 }
@@ -1430,7 +3215,7 @@ public:
     // In a real implementation, this would provide access to parent class methods
 };
 
-EppxSuper eppx_super() {
+inline EppxSuper eppx_super() {
     return EppxSuper();
 }
 
@@ -1449,35 +3234,50 @@ public:
     std::string get_mode() const { return mode; }
 };
 
-EppxCodeObject eppx_compile(const std::string& source, const std::string& filename, const std::string& mode) {
+inline EppxCodeObject eppx_compile(const std::string& source, const std::string& filename, const std::string& mode) {
     // Stub implementation - would need full compiler
     return EppxCodeObject(source, filename, mode);
 }
 
 // Output operators for new classes (defined after class definitions)
-std::ostream& operator<<(std::ostream& os, const EppxByteArray& ba) {
+inline std::ostream& operator<<(std::ostream& os, const EppxByteArray& ba) {
     os << "bytearray(b'" << ba.to_string() << "')";
     return os;
 }
 
-std::ostream& operator<<(std::ostream& os, const EppxBytes& b) {
+inline std::ostream& operator<<(std::ostream& os, const EppxBytes& b) {
     os << "b'" << b.to_string() << "'";
     return os;
 }
 
-std::ostream& operator<<(std::ostream& os, const EppxObject& obj) {
+inline std::ostream& operator<<(std::ostream& os, const EppxObject& obj) {
     os << obj.to_string();
     return os;
 }
 
-std::ostream& operator<<(std::ostream& os, const EppxSlice& s) {
+inline std::ostream& operator<<(std::ostream& os, const EppxSlice& s) {
     os << s.to_string();
     return os;
 }
 
-std::ostream& operator<<(std::ostream& os, const EppxMemoryView& mv) {
+inline std::ostream& operator<<(std::ostream& os, const EppxMemoryView& mv) {
     os << "<memory at 0x" << std::hex << reinterpret_cast<uintptr_t>(mv.data()) << ">";
     return os;
 }
 
+// str(x): numbers keep their std::to_string form; other values, such as the
+// variants match captures hold, read as print() shows them
+template<typename T>
+std::string eppx_str(const T& value) {
+    if constexpr (std::is_arithmetic_v<T>) {
+        return std::to_string(value);
+    } else if constexpr (std::is_convertible_v<T, std::string>) {
+        return std::string(value);
+    } else {
+        std::ostringstream out;
+        out << value;
+        return out.str();
+    }
+}
+
 #endif // EPPX_BUILTINS_HPP
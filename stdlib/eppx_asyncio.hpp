@@ -0,0 +1,586 @@
+#ifndef EPPX_ASYNCIO_HPP
+#define EPPX_ASYNCIO_HPP
+
+#include <chrono>
+#include <deque>
+#include <thread>
+
+#include "eppx_generator.hpp"
+
+// ---------------------------------------------------------------------------
+// Coroutines and asyncio
+//
+// An `async def` compiles to the same kind of frame as a generator, and
+// `await x` delegates to x's frame like `yield from`. A coroutine only really
+// suspends at a leaf awaitable (sleep, a queue, an unfinished task, ...) that
+// cannot produce its result yet: the leaf first tells the event loop how the
+// current task will be woken up, then the suspension travels up through every
+// awaiting frame to the loop. The loop is single-threaded; it runs ready tasks
+// in FIFO order and only blocks when every task is waiting on a timer.
+// Spurious wake-ups are harmless because leaves re-check their condition each
+// time they are resumed.
+// ---------------------------------------------------------------------------
+
+struct eppx_task_state {
+    eppx_generator coro;
+    bool done = false;
+    bool cancelled = false;
+    bool scheduled = false;        // currently in the ready queue
+    bool cancel_requested = false; // raise CancelledError at the next step
+    eppx_variant result = nullptr;
+    std::exception_ptr exception;
+    std::vector<std::shared_ptr<eppx_task_state>> waiters; // tasks awaiting this one
+};
+
+using eppx_task_ptr = std::shared_ptr<eppx_task_state>;
+
+class eppx_event_loop {
+private:
+    using clock = std::chrono::steady_clock;
+
+    std::deque<eppx_task_ptr> ready_;
+    std::multimap<clock::time_point, eppx_task_ptr> timers_;
+    std::vector<eppx_task_ptr> tasks_;
+    eppx_task_ptr current_;
+    bool reschedule_ = true; // false once the current task has arranged its own wake-up
+
+    void finish(const eppx_task_ptr& task, const eppx_variant& result, std::exception_ptr exc) {
+        task->done = true;
+        task->result = result;
+        task->exception = exc;
+        for (const auto& waiter : task->waiters) {
+            wake(waiter);
+        }
+        task->waiters.clear();
+    }
+
+    void step(const eppx_task_ptr& task) {
+        task->scheduled = false;
+        if (task->done) {
+            return;
+        }
+        current_ = task;
+        reschedule_ = true;
+        try {
+            if (task->cancel_requested) {
+                task->cancel_requested = false;
+                task->coro.throw_(std::make_exception_ptr(CancelledErrorException()));
+            } else {
+                task->coro.next_value();
+            }
+            current_ = nullptr;
+            if (reschedule_) {
+                wake(task);
+            }
+        } catch (const StopIterationException& stop) {
+            current_ = nullptr;
+            finish(task, stop.value, nullptr);
+        } catch (const CancelledErrorException&) {
+            current_ = nullptr;
+            task->cancelled = true;
+            finish(task, nullptr, std::current_exception());
+        } catch (...) {
+            current_ = nullptr;
+            finish(task, nullptr, std::current_exception());
+        }
+    }
+
+    // Runs one task (or waits for the next timer); false if nothing can ever run again
+    bool run_once() {
+        if (ready_.empty()) {
+            if (timers_.empty()) {
+                return false;
+            }
+            std::this_thread::sleep_until(timers_.begin()->first);
+        }
+        auto now = clock::now();
+        while (!timers_.empty() && timers_.begin()->first <= now) {
+            wake(timers_.begin()->second);
+            timers_.erase(timers_.begin());
+        }
+        if (!ready_.empty()) {
+            eppx_task_ptr task = ready_.front();
+            ready_.pop_front();
+            step(task);
+        }
+        return true;
+    }
+
+public:
+    bool running = false;
+
+    static eppx_event_loop& get() {
+        static eppx_event_loop loop;
+        return loop;
+    }
+
+    const eppx_task_ptr& current() const {
+        if (!current_) {
+            throw std::runtime_error("RuntimeError: no running event loop");
+        }
+        return current_;
+    }
+
+    eppx_task_ptr spawn(eppx_generator coro) {
+        auto task = std::make_shared<eppx_task_state>();
+        task->coro = coro;
+        tasks_.push_back(task);
+        wake(task);
+        return task;
+    }
+
+    void wake(const eppx_task_ptr& task) {
+        if (!task->done && !task->scheduled) {
+            task->scheduled = true;
+            ready_.push_back(task);
+        }
+    }
+
+    // The current task sleeps until one of `waiters` is woken by its owner
+    void wait_on(std::vector<eppx_task_ptr>& waiters) {
+        const eppx_task_ptr& task = current();
+        if (std::find(waiters.begin(), waiters.end(), task) == waiters.end()) {
+            waiters.push_back(task);
+        }
+        reschedule_ = false;
+    }
+
+    void wait_until(clock::time_point deadline) {
+        timers_.emplace(deadline, current());
+        reschedule_ = false;
+    }
+
+    void wake_all(std::vector<eppx_task_ptr>& waiters) {
+        for (const auto& waiter : waiters) {
+            wake(waiter);
+        }
+        waiters.clear();
+    }
+
+    void run_until_complete(const eppx_task_ptr& main) {
+        while (!main->done) {
+            if (!run_once()) {
+                throw std::runtime_error("RuntimeError: event loop stalled: every task is waiting and nothing can wake them");
+            }
+        }
+    }
+
+    // Like asyncio.run(): tasks still pending when the main coroutine returns are cancelled
+    void cancel_pending() {
+        for (const auto& task : tasks_) {
+            if (!task->done) {
+                task->cancel_requested = true;
+                wake(task);
+            }
+        }
+        while (!ready_.empty() && run_once()) {
+        }
+        tasks_.clear();
+        timers_.clear();
+        ready_.clear();
+    }
+};
+
+// Frame of a runtime awaitable: poll() either stores the result and returns
+// true, or registers a wake-up with the event loop and returns false
+class eppx_poll_frame : public eppx_generator_frame {
+private:
+    std::function<bool(eppx_variant&)> poll_;
+
+public:
+    explicit eppx_poll_frame(std::function<bool(eppx_variant&)> poll) : poll_(std::move(poll)) {}
+
+    bool resume() override {
+        _raise_thrown();
+        if (poll_(_return_value)) {
+            return false;
+        }
+        _current = nullptr;
+        _awaiting = true;
+        _state = 1;
+        return true;
+    }
+};
+
+inline eppx_generator eppx_make_awaitable(std::function<bool(eppx_variant&)> poll) {
+    return eppx_generator(std::make_shared<eppx_poll_frame>(std::move(poll)));
+}
+
+// An awaitable that completes immediately
+inline eppx_generator eppx_ready(eppx_variant value = nullptr) {
+    return eppx_make_awaitable([value](eppx_variant& result) {
+        result = value;
+        return true;
+    });
+}
+
+// `__anext__` of an async generator: suspensions caused by its awaits are
+// passed through, the first yielded value becomes the result
+class eppx_anext_frame : public eppx_generator_frame {
+private:
+    eppx_generator agen_;
+    std::shared_ptr<bool> exhausted_;
+
+public:
+    eppx_anext_frame(eppx_generator agen, std::shared_ptr<bool> exhausted)
+        : agen_(std::move(agen)), exhausted_(std::move(exhausted)) {}
+
+    bool resume() override {
+        eppx_variant value;
+        try {
+            if (_thrown) {
+                std::exception_ptr exc = _thrown;
+                _thrown = nullptr;
+                value = agen_.throw_(exc);
+            } else {
+                value = agen_.next_value();
+            }
+        } catch (const StopIterationException&) {
+            *exhausted_ = true;
+            _return_value = nullptr;
+            return false;
+        }
+        if (agen_.awaiting()) {
+            _current = nullptr;
+            _awaiting = true;
+            _state = 1;
+            return true;
+        }
+        _return_value = value;
+        return false;
+    }
+};
+
+// State of an `async for` loop
+class eppx_async_iterator {
+private:
+    eppx_generator agen_;
+    std::shared_ptr<bool> exhausted_ = std::make_shared<bool>(false);
+
+public:
+    eppx_async_iterator() = default;
+    explicit eppx_async_iterator(eppx_generator agen) : agen_(std::move(agen)) {}
+
+    eppx_generator anext() {
+        return eppx_generator(std::make_shared<eppx_anext_frame>(agen_, exhausted_));
+    }
+
+    bool exhausted() const { return *exhausted_; }
+};
+
+namespace asyncio {
+
+class Task {
+private:
+    eppx_task_ptr state_;
+
+public:
+    Task() = default;
+    explicit Task(eppx_task_ptr state) : state_(std::move(state)) {}
+
+    const eppx_task_ptr& state() const { return state_; }
+
+    bool done() const { return state_->done; }
+    bool cancelled() const { return state_->cancelled; }
+
+    eppx_variant result() const {
+        if (!state_->done) {
+            throw std::runtime_error("InvalidStateError: Result is not set.");
+        }
+        if (state_->exception) {
+            std::rethrow_exception(state_->exception);
+        }
+        return state_->result;
+    }
+
+    bool cancel() {
+        if (state_->done) {
+            return false;
+        }
+        state_->cancel_requested = true;
+        eppx_event_loop::get().wake(state_);
+        return true;
+    }
+};
+
+inline Task create_task(eppx_generator coro) {
+    return Task(eppx_event_loop::get().spawn(coro));
+}
+
+inline eppx_variant run(eppx_generator main) {
+    eppx_event_loop& loop = eppx_event_loop::get();
+    if (loop.running) {
+        throw std::runtime_error("RuntimeError: asyncio.run() cannot be called from a running event loop");
+    }
+    loop.running = true;
+    eppx_task_ptr task = loop.spawn(main);
+    try {
+        loop.run_until_complete(task);
+    } catch (...) {
+        loop.cancel_pending();
+        loop.running = false;
+        throw;
+    }
+    loop.cancel_pending();
+    loop.running = false;
+    if (task->exception) {
+        std::rethrow_exception(task->exception);
+    }
+    return task->result;
+}
+
+template<typename T>
+eppx_generator sleep(const T& seconds, eppx_variant value = nullptr) {
+    double delay = variant_to_double(eppx_to_variant(seconds));
+    auto deadline = std::make_shared<std::optional<std::chrono::steady_clock::time_point>>();
+    return eppx_make_awaitable([delay, deadline, value](eppx_variant& result) {
+        eppx_event_loop& loop = eppx_event_loop::get();
+        auto now = std::chrono::steady_clock::now();
+        if (!*deadline) {
+            // sleep(0) still gives every other ready task a turn
+            *deadline = now + std::chrono::duration_cast<std::chrono::steady_clock::duration>(
+                std::chrono::duration<double>(std::max(delay, 0.0)));
+            if (delay > 0) {
+                loop.wait_until(**deadline);
+            }
+            return false;
+        }
+        if (now < **deadline) {
+            loop.wait_until(**deadline);
+            return false;
+        }
+        result = value;
+        return true;
+    });
+}
+
+// Runs every awaitable concurrently; the result is the list of their results
+// in argument order, or the first exception raised
+inline eppx_task_ptr eppx_as_task(eppx_generator coro) { return eppx_event_loop::get().spawn(coro); }
+inline eppx_task_ptr eppx_as_task(const Task& task) { return task.state(); }
+
+template<typename... Awaitables>
+eppx_generator gather(const Awaitables&... awaitables) {
+    std::vector<eppx_task_ptr> tasks{eppx_as_task(awaitables)...};
+    return eppx_make_awaitable([tasks](eppx_variant& result) mutable {
+        for (const auto& task : tasks) {
+            if (task->done && task->exception) {
+                std::rethrow_exception(task->exception);
+            }
+        }
+        for (const auto& task : tasks) {
+            if (!task->done) {
+                eppx_event_loop::get().wait_on(task->waiters);
+                return false;
+            }
+        }
+        std::vector<eppx_variant> results;
+        for (const auto& task : tasks) {
+            results.push_back(task->result);
+        }
+        result = results;
+        return true;
+    });
+}
+
+class Queue {
+private:
+    struct State {
+        std::deque<eppx_variant> items;
+        long long maxsize = 0;
+        long long unfinished = 0;
+        std::vector<eppx_task_ptr> getters;
+        std::vector<eppx_task_ptr> putters;
+        std::vector<eppx_task_ptr> joiners;
+    };
+    std::shared_ptr<State> state_ = std::make_shared<State>();
+
+public:
+    Queue() = default;
+    explicit Queue(long long maxsize) { state_->maxsize = maxsize; }
+
+    long long qsize() const { return static_cast<long long>(state_->items.size()); }
+    bool empty() const { return state_->items.empty(); }
+    bool full() const { return state_->maxsize > 0 && qsize() >= state_->maxsize; }
+
+    template<typename T>
+    void put_nowait(const T& item) {
+        if (full()) {
+            throw std::runtime_error("QueueFull");
+        }
+        state_->items.push_back(eppx_to_variant(item));
+        state_->unfinished++;
+        eppx_event_loop::get().wake_all(state_->getters);
+    }
+
+    eppx_variant get_nowait() {
+        if (empty()) {
+            throw std::runtime_error("QueueEmpty");
+        }
+        eppx_variant item = state_->items.front();
+        state_->items.pop_front();
+        eppx_event_loop::get().wake_all(state_->putters);
+        return item;
+    }
+
+    template<typename T>
+    eppx_generator put(const T& item) {
+        Queue queue = *this;
+        eppx_variant value = eppx_to_variant(item);
+        return eppx_make_awaitable([queue, value](eppx_variant&) mutable {
+            if (queue.full()) {
+                eppx_event_loop::get().wait_on(queue.state_->putters);
+                return false;
+            }
+            queue.put_nowait(value);
+            return true;
+        });
+    }
+
+    eppx_generator get() {
+        Queue queue = *this;
+        return eppx_make_awaitable([queue](eppx_variant& result) mutable {
+            if (queue.empty()) {
+                eppx_event_loop::get().wait_on(queue.state_->getters);
+                return false;
+            }
+            result = queue.get_nowait();
+            return true;
+        });
+    }
+
+    void task_done() {
+        if (state_->unfinished <= 0) {
+            throw ValueErrorException("task_done() called too many times");
+        }
+        if (--state_->unfinished == 0) {
+            eppx_event_loop::get().wake_all(state_->joiners);
+        }
+    }
+
+    eppx_generator join() {
+        auto state = state_;
+        return eppx_make_awaitable([state](eppx_variant&) {
+            if (state->unfinished > 0) {
+                eppx_event_loop::get().wait_on(state->joiners);
+                return false;
+            }
+            return true;
+        });
+    }
+};
+
+// Semaphore with `value` permits; Lock is the single-permit case
+class Semaphore {
+protected:
+    struct State {
+        long long value = 1;
+        std::vector<eppx_task_ptr> waiters;
+    };
+    std::shared_ptr<State> state_ = std::make_shared<State>();
+
+public:
+    Semaphore() = default;
+    explicit Semaphore(long long value) { state_->value = value; }
+
+    bool locked() const { return state_->value <= 0; }
+
+    eppx_generator acquire() {
+        auto state = state_;
+        return eppx_make_awaitable([state](eppx_variant& result) {
+            if (state->value <= 0) {
+                eppx_event_loop::get().wait_on(state->waiters);
+                return false;
+            }
+            state->value--;
+            result = true;
+            return true;
+        });
+    }
+
+    void release() {
+        state_->value++;
+        eppx_event_loop::get().wake_all(state_->waiters);
+    }
+
+    eppx_generator __aenter__() { return acquire(); }
+
+    template<typename... Args>
+    eppx_generator __aexit__(const Args&...) {
+        release();
+        return eppx_ready();
+    }
+};
+
+class Lock : public Semaphore {
+public:
+    Lock() = default;
+
+    void release() {
+        if (!locked()) {
+            throw std::runtime_error("RuntimeError: Lock is not acquired.");
+        }
+        Semaphore::release();
+    }
+
+    template<typename... Args>
+    eppx_generator __aexit__(const Args&...) {
+        release();
+        return eppx_ready();
+    }
+};
+
+class Event {
+private:
+    struct State {
+        bool flag = false;
+        std::vector<eppx_task_ptr> waiters;
+    };
+    std::shared_ptr<State> state_ = std::make_shared<State>();
+
+public:
+    bool is_set() const { return state_->flag; }
+
+    void set() {
+        state_->flag = true;
+        eppx_event_loop::get().wake_all(state_->waiters);
+    }
+
+    void clear() { state_->flag = false; }
+
+    eppx_generator wait() {
+        auto state = state_;
+        return eppx_make_awaitable([state](eppx_variant& result) {
+            if (!state->flag) {
+                eppx_event_loop::get().wait_on(state->waiters);
+                return false;
+            }
+            result = true;
+            return true;
+        });
+    }
+};
+
+} // namespace asyncio
+
+// What `await x` delegates to
+inline eppx_generator eppx_awaitable(eppx_generator coro) {
+    return coro;
+}
+
+inline eppx_generator eppx_awaitable(const asyncio::Task& task) {
+    eppx_task_ptr state = task.state();
+    return eppx_make_awaitable([state](eppx_variant& result) {
+        if (!state->done) {
+            eppx_event_loop::get().wait_on(state->waiters);
+            return false;
+        }
+        if (state->exception) {
+            std::rethrow_exception(state->exception);
+        }
+        result = state->result;
+        return true;
+    });
+}
+
+#endif // EPPX_ASYNCIO_HPP
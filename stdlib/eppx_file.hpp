@@ -0,0 +1,155 @@
+#ifndef EPPX_FILE_HPP
+#define EPPX_FILE_HPP
+
+#include "builtins.hpp"
+
+// File objects returned by open(); the file itself is managed by the runtime library
+class EppxFile {
+private:
+    std::string filepath;
+    std::string mode;
+    int64_t handle = -1;
+
+    template<typename T>
+    static T check(T result) {
+        if (result < 0) {
+            eppx_rt_raise();
+        }
+        return result;
+    }
+
+    static std::string check(eppx_rt_string result) {
+        if (!result.data) {
+            eppx_rt_raise();
+        }
+        return eppx_rt_take(result);
+    }
+
+public:
+    EppxFile(const std::string& path, const std::string& file_mode)
+        : filepath(path), mode(file_mode) {}
+    EppxFile(const EppxFile&) = delete;
+    EppxFile& operator=(const EppxFile&) = delete;
+    ~EppxFile() {
+        if (handle >= 0) {
+            eppx_rt_file_close(handle);
+        }
+    }
+
+    void open() {
+        handle = check(eppx_rt_file_open(filepath.data(), filepath.size(), mode.data(), mode.size()));
+    }
+
+    std::string read(long long size = -1) {
+        return check(eppx_rt_file_read(handle, size));
+    }
+
+    std::string readline(long long size = -1) {
+        return check(eppx_rt_file_readline(handle, size));
+    }
+
+    std::vector<std::string> readlines(long long hint = -1) {
+        std::vector<std::string> lines;
+        long long total_size = 0;
+        for (std::string line = readline(); !line.empty(); line = readline()) {
+            total_size += line.size();
+            lines.push_back(line);
+            if (hint > 0 && total_size >= hint) {
+                break;
+            }
+        }
+        return lines;
+    }
+
+    long long write(const std::string& data) {
+        return check(eppx_rt_file_write(handle, data.data(), data.size()));
+    }
+
+    void writelines(const std::vector<std::string>& lines) {
+        for (const auto& line : lines) {
+            write(line);
+        }
+    }
+
+    void close() {
+        if (handle >= 0) {
+            int64_t closing = handle;
+            handle = -1;
+            check(eppx_rt_file_close(closing));
+        }
+    }
+
+    void flush() {
+        check(eppx_rt_file_flush(handle));
+    }
+
+    long long seek(long long offset, int whence = 0) {
+        return check(eppx_rt_file_seek(handle, offset, whence));
+    }
+
+    long long tell() {
+        return check(eppx_rt_file_tell(handle));
+    }
+
+    bool readable() const {
+        return eppx_rt_file_readable(handle);
+    }
+
+    bool writable() const {
+        return eppx_rt_file_writable(handle);
+    }
+
+    bool seekable() const {
+        return handle >= 0;
+    }
+
+    bool closed() const {
+        return handle < 0;
+    }
+
+    std::string get_mode() const {
+        return mode;
+    }
+
+    std::string get_name() const {
+        return filepath;
+    }
+};
+
+// File I/O builtin functions
+inline std::shared_ptr<EppxFile> eppx_open(const std::string& filepath, 
+                                    const std::string& mode = "r",
+                                    int buffering = -1,
+                                    const std::string& encoding = "",
+                                    const std::string& errors = "strict",
+                                    const std::string& newline = "",
+                                    bool closefd = true) {
+    auto file_obj = std::make_shared<EppxFile>(filepath, mode);
+    file_obj->open();
+    return file_obj;
+}
+
+// Context manager for files
+// (Needed for Python-style with open(...) as ...)
+template<typename FileType>
+class EppxFileContextManager {
+private:
+    FileType file_obj;
+    bool should_close;
+public:
+    EppxFileContextManager(FileType f) : file_obj(f), should_close(true) {}
+    FileType& __enter__() { return file_obj; }
+    bool __exit__(const std::string& exc_type = "", const std::string& exc_val = "", const std::string& exc_tb = "") {
+        if (should_close && file_obj) {
+            file_obj->close();
+        }
+        return false; // Don't suppress exceptions
+    }
+};
+
+template<typename FileType>
+EppxFileContextManager<FileType> eppx_with_file(FileType file_obj) {
+    return EppxFileContextManager<FileType>(file_obj);
+}
+
+#endif // EPPX_FILE_HPP
@@ -0,0 +1,281 @@
+#ifndef EPPX_GENERATOR_HPP
+#define EPPX_GENERATOR_HPP
+
+#include "builtins.hpp"
+
+// ---------------------------------------------------------------------------
+// Generators
+//
+// A generator function is compiled into a subclass of eppx_generator_frame
+// whose resume() runs the body as a state machine: every yield is a resume
+// point and all locals live in the frame. eppx_generator is the shared handle
+// returned by calling the function (copies refer to the same frame, as in
+// Python). Included by programs that define a generator or coroutine.
+// ---------------------------------------------------------------------------
+
+class eppx_generator_frame {
+public:
+    int _state = 0;                    // next block to run; -1 once finished
+    bool _running = false;
+    eppx_variant _current = nullptr;   // value of the last yield
+    eppx_variant _sent = nullptr;      // value passed in by send()
+    eppx_variant _return_value = nullptr;
+    std::exception_ptr _thrown;        // injected at the paused yield by throw()/close()
+    std::exception_ptr _exception;     // exception being dispatched to a handler block
+    bool _awaiting = false;            // last suspension was an await, not a yielded value
+
+    virtual ~eppx_generator_frame() = default;
+
+    // Runs until the next yield (returns true, value in _current) or until the
+    // body finishes (returns false, value in _return_value).
+    virtual bool resume() = 0;
+
+    // Called at every resume point: raise whatever throw()/close() injected
+    void _raise_thrown() {
+        if (_thrown) {
+            std::exception_ptr exc = _thrown;
+            _thrown = nullptr;
+            std::rethrow_exception(exc);
+        }
+    }
+};
+
+class eppx_generator {
+private:
+    std::shared_ptr<eppx_generator_frame> frame_;
+
+    eppx_variant step() {
+        if (frame_->_running) {
+            throw ValueErrorException("generator already executing");
+        }
+        frame_->_running = true;
+        bool yielded = false;
+        try {
+            yielded = frame_->resume();
+        } catch (...) {
+            frame_->_running = false;
+            frame_->_state = -1;
+            throw;
+        }
+        frame_->_running = false;
+        if (yielded) {
+            return frame_->_current;
+        }
+        frame_->_state = -1;
+        throw StopIterationException(frame_->_return_value);
+    }
+
+public:
+    using value_type = eppx_variant;
+
+    class iterator {
+    private:
+        eppx_generator* gen_ = nullptr;
+        eppx_variant value_ = nullptr;
+
+        void advance() {
+            try {
+                value_ = gen_->next_value();
+            } catch (const StopIterationException&) {
+                gen_ = nullptr;
+            }
+        }
+
+    public:
+        using iterator_category = std::input_iterator_tag;
+        using value_type = eppx_variant;
+        using difference_type = std::ptrdiff_t;
+        using pointer = const eppx_variant*;
+        using reference = const eppx_variant&;
+
+        iterator() = default;
+        explicit iterator(eppx_generator* gen) : gen_(gen) { advance(); }
+        const eppx_variant& operator*() const { return value_; }
+        const eppx_variant* operator->() const { return &value_; }
+        iterator& operator++() { advance(); return *this; }
+        iterator operator++(int) { iterator tmp = *this; advance(); return tmp; }
+        bool operator==(const iterator& other) const { return gen_ == other.gen_; }
+        bool operator!=(const iterator& other) const { return gen_ != other.gen_; }
+    };
+
+    eppx_generator() = default;
+    explicit eppx_generator(std::shared_ptr<eppx_generator_frame> frame) : frame_(std::move(frame)) {}
+
+    bool finished() const { return frame_->_state < 0; }
+
+    // An async generator suspends both to yield values and to await
+    bool awaiting() const { return frame_->_awaiting; }
+
+    eppx_variant send(const eppx_variant& value) {
+        if (finished()) {
+            throw StopIterationException();
+        }
+        if (frame_->_state == 0 && !std::holds_alternative<std::nullptr_t>(value)) {
+            throw std::runtime_error("TypeError: can't send non-None value to a just-started generator");
+        }
+        frame_->_sent = value;
+        return step();
+    }
+
+    eppx_variant next_value() { return send(nullptr); }
+
+    eppx_variant throw_(std::exception_ptr exc) {
+        if (finished() || frame_->_state == 0) {
+            // A generator that never started (or already ended) raises immediately
+            frame_->_state = -1;
+            std::rethrow_exception(exc);
+        }
+        frame_->_sent = nullptr;
+        frame_->_thrown = exc;
+        return step();
+    }
+
+    void close() {
+        if (finished() || frame_->_state == 0) {
+            frame_->_state = -1;
+            return;
+        }
+        try {
+            throw_(std::make_exception_ptr(GeneratorExitException()));
+        } catch (const GeneratorExitException&) {
+            return;
+        } catch (const StopIterationException&) {
+            return;
+        }
+        throw std::runtime_error("RuntimeError: generator ignored GeneratorExit");
+    }
+
+    // Iteration shares the frame, so a for loop consumes the generator
+    iterator begin() const { return iterator(const_cast<eppx_generator*>(this)); }
+    iterator end() const { return iterator(); }
+};
+
+inline eppx_variant eppx_method_send(eppx_generator& gen, const eppx_variant& value) {
+    return gen.send(value);
+}
+
+inline eppx_variant eppx_method_throw(eppx_generator& gen, std::exception_ptr exc) {
+    return gen.throw_(exc);
+}
+
+inline void eppx_method_close(eppx_generator& gen) {
+    gen.close();
+}
+
+
+inline std::vector<eppx_variant> eppx_to_list(eppx_generator gen) {
+    return std::vector<eppx_variant>(gen.begin(), gen.end());
+}
+
+// Type-erased iterator used by for loops inside generator frames, where the
+// loop state has to survive across yields
+class eppx_iterator {
+private:
+    std::function<bool(eppx_variant&)> next_;
+
+public:
+    eppx_iterator() = default;
+
+    template<typename Iterable>
+    explicit eppx_iterator(const Iterable& iterable) {
+        auto holder = std::make_shared<Iterable>(iterable);
+        auto it = std::make_shared<decltype(holder->begin())>(holder->begin());
+        next_ = [holder, it](eppx_variant& out) {
+            if (!(*it != holder->end())) {
+                return false;
+            }
+            out = eppx_to_variant(**it);
+            ++*it;
+            return true;
+        };
+    }
+
+    explicit eppx_iterator(const eppx_variant& iterable) {
+        if (std::holds_alternative<eppx_list_ref>(iterable)) {
+            *this = eppx_iterator(*std::get<eppx_list_ref>(iterable));
+        } else if (std::holds_alternative<std::string>(iterable)) {
+            *this = eppx_iterator(std::get<std::string>(iterable));
+        } else {
+            throw std::runtime_error("TypeError: object is not iterable");
+        }
+    }
+
+    explicit eppx_iterator(const std::string& iterable) {
+        auto holder = std::make_shared<std::string>(iterable);
+        auto index = std::make_shared<size_t>(0);
+        next_ = [holder, index](eppx_variant& out) {
+            if (*index >= holder->size()) {
+                return false;
+            }
+            out = std::string(1, (*holder)[(*index)++]);
+            return true;
+        };
+    }
+
+    explicit eppx_iterator(eppx_generator gen) {
+        auto holder = std::make_shared<eppx_generator>(gen);
+        next_ = [holder](eppx_variant& out) {
+            try {
+                out = holder->next_value();
+                return true;
+            } catch (const StopIterationException&) {
+                return false;
+            }
+        };
+    }
+
+    bool next(eppx_variant& out) { return next_ && next_(out); }
+};
+
+// State of a `yield from` delegation: sub-generators receive send()/throw()
+// and their return value becomes the value of the expression; any other
+// iterable is simply drained.
+class eppx_yield_from {
+private:
+    std::optional<eppx_generator> gen_;
+    eppx_iterator iter_;
+
+public:
+    eppx_variant result = nullptr;
+
+    eppx_yield_from() = default;
+    explicit eppx_yield_from(eppx_generator gen) : gen_(gen) {}
+    template<typename Iterable>
+    explicit eppx_yield_from(const Iterable& iterable) : iter_(iterable) {}
+
+    // Returns true with the next value to yield, false once delegation is over
+    bool step(const eppx_variant& sent, eppx_variant& out) {
+        if (!gen_) {
+            return iter_.next(out);
+        }
+        try {
+            out = gen_->send(sent);
+            return true;
+        } catch (const StopIterationException& stop) {
+            result = stop.value;
+            return false;
+        }
+    }
+
+    bool throw_into(std::exception_ptr exc, eppx_variant& out) {
+        if (!gen_) {
+            std::rethrow_exception(exc);
+        }
+        try {
+            std::rethrow_exception(exc);
+        } catch (const GeneratorExitException&) {
+            gen_->close();
+            throw;
+        } catch (...) {
+        }
+        try {
+            out = gen_->throw_(exc);
+            return true;
+        } catch (const StopIterationException& stop) {
+            result = stop.value;
+            return false;
+        }
+    }
+};
+
+#endif // EPPX_GENERATOR_HPP
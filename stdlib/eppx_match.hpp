@@ -0,0 +1,240 @@
+#ifndef EPPX_MATCH_HPP
+#define EPPX_MATCH_HPP
+
+#include "builtins.hpp"
+
+// ---------------------------------------------------------------------------
+// Structural pattern matching
+//
+// A `match` statement compiles to a decision tree built from the tests below,
+// applied to the subject and to the parts of it reached through the
+// eppx_match_* accessors. Every helper accepts a value of any runtime type: a
+// test on a value of the wrong kind is false and an accessor on it yields
+// None, so each branch of the tree compiles whatever the subject's type is.
+// Lists and tuples share one representation, so sequence patterns and the
+// list()/tuple() class patterns accept either.
+// ---------------------------------------------------------------------------
+
+template<typename T>
+struct eppx_is_list : std::false_type {};
+
+template<typename T>
+struct eppx_is_list<std::vector<T>> : std::true_type {};
+
+template<typename T>
+struct eppx_is_dict : std::false_type {};
+
+template<typename K, typename V>
+struct eppx_is_dict<eppx_dict<K, V>> : std::true_type {};
+
+template<typename T>
+struct eppx_is_set : std::false_type {};
+
+template<typename T>
+struct eppx_is_set<std::set<T>> : std::true_type {};
+
+template<typename T, typename U, typename = void>
+struct eppx_equality_comparable : std::false_type {};
+
+template<typename T, typename U>
+struct eppx_equality_comparable<T, U, std::void_t<decltype(std::declval<const T&>() == std::declval<const U&>())>> : std::true_type {};
+
+inline bool eppx_is_number(const eppx_variant& v) {
+    return std::holds_alternative<long long>(v) || std::holds_alternative<double>(v) || std::holds_alternative<bool>(v);
+}
+
+// Literal and value patterns: numbers compare by value (1 matches 1.0 and True)
+template<typename T, typename U>
+bool eppx_match_eq(const T& subject, const U& value) {
+    if constexpr (eppx_variant_like<T>::value && eppx_variant_like<U>::value) {
+        eppx_variant s = eppx_to_variant(subject);
+        eppx_variant v = eppx_to_variant(value);
+        if (std::holds_alternative<long long>(s) && std::holds_alternative<long long>(v)) {
+            return std::get<long long>(s) == std::get<long long>(v);
+        }
+        if (eppx_is_number(s) && eppx_is_number(v)) {
+            return variant_to_double(s) == variant_to_double(v);
+        }
+        return s == v;
+    } else if constexpr (eppx_equality_comparable<T, U>::value) {
+        return static_cast<bool>(subject == value);
+    } else {
+        return false;
+    }
+}
+
+// None, True and False match by identity
+template<typename T, typename U>
+bool eppx_match_is(const T& subject, const U& singleton) {
+    if constexpr (std::is_same_v<T, eppx_variant>) {
+        return subject == eppx_variant(singleton);
+    } else if constexpr (std::is_same_v<T, U>) {
+        return subject == singleton;
+    } else {
+        return false;
+    }
+}
+
+// Sequence patterns (strings are not sequences here, as in Python)
+template<typename T>
+bool eppx_match_sequence(const T& subject) {
+    if constexpr (std::is_same_v<T, eppx_variant>) {
+        return std::holds_alternative<eppx_list_ref>(subject);
+    } else {
+        return eppx_is_list<T>::value;
+    }
+}
+
+template<typename T>
+long long eppx_match_len(const T& subject) {
+    if constexpr (std::is_same_v<T, eppx_variant>) {
+        const auto* list = std::get_if<eppx_list_ref>(&subject);
+        return list ? static_cast<long long>((*list)->size()) : -1;
+    } else if constexpr (eppx_is_list<T>::value) {
+        return static_cast<long long>(subject.size());
+    } else {
+        return -1;
+    }
+}
+
+// Element of a sequence whose length has been checked; negative indices
+// address the elements after a star pattern
+template<typename T>
+auto eppx_match_item(const T& subject, long long index) {
+    if constexpr (eppx_is_list<T>::value) {
+        return typename T::value_type(subject[static_cast<size_t>(eppx_normalize_index(index, subject.size()))]);
+    } else if constexpr (std::is_same_v<T, eppx_variant>) {
+        const auto& items = *std::get<eppx_list_ref>(subject);
+        return items[static_cast<size_t>(eppx_normalize_index(index, items.size()))];
+    } else {
+        return eppx_variant(nullptr);
+    }
+}
+
+// What a star pattern captures: the elements between the first `before`
+// and the last `after` ones, as a list
+template<typename T>
+auto eppx_match_slice(const T& subject, long long before, long long after) {
+    if constexpr (eppx_is_list<T>::value) {
+        return T(subject.begin() + before, subject.end() - after);
+    } else if constexpr (std::is_same_v<T, eppx_variant>) {
+        const auto& items = *std::get<eppx_list_ref>(subject);
+        return std::vector<eppx_variant>(items.begin() + before, items.end() - after);
+    } else {
+        return std::vector<eppx_variant>{};
+    }
+}
+
+// Mapping patterns
+template<typename T>
+bool eppx_match_mapping(const T&) {
+    return eppx_is_dict<T>::value;
+}
+
+template<typename T, typename Key>
+bool eppx_match_has_key(const T& subject, const Key& key) {
+    if constexpr (eppx_is_dict<T>::value) {
+        return subject.contains(eppx_box<typename T::key_type>(key));
+    } else {
+        return false;
+    }
+}
+
+template<typename T, typename Key>
+auto eppx_match_get(const T& subject, const Key& key) {
+    if constexpr (eppx_is_dict<T>::value) {
+        return subject.at(eppx_box<typename T::key_type>(key));
+    } else {
+        return eppx_variant(nullptr);
+    }
+}
+
+// What `**rest` captures: a copy of the mapping without the matched keys
+template<typename T, typename... Keys>
+auto eppx_match_rest(const T& subject, const Keys&... keys) {
+    if constexpr (eppx_is_dict<T>::value) {
+        T rest = subject;
+        (rest.erase(eppx_box<typename T::key_type>(keys)), ...);
+        return rest;
+    } else {
+        return eppx_dict<eppx_variant, eppx_variant>{};
+    }
+}
+
+// Class patterns on user-defined classes: instances are C++ values, so the
+// isinstance test is decided by the subject's static type
+template<typename Cls, typename T>
+constexpr bool eppx_match_class(const T&) {
+    return std::is_base_of_v<Cls, T>;
+}
+
+// Attribute of a class pattern subject; `get` is only instantiated when the
+// subject is a Cls
+template<typename Cls, typename T, typename Getter>
+auto eppx_match_attr(const T& subject, Getter get) {
+    if constexpr (std::is_base_of_v<Cls, T>) {
+        return get(static_cast<const Cls&>(subject));
+    } else {
+        return eppx_variant(nullptr);
+    }
+}
+
+// Class patterns on builtin types: int(), float(), str(), bool(), list(),
+// tuple(), dict() and set()
+template<typename T>
+bool eppx_match_builtin(const T& subject, const std::string& type_name) {
+    if constexpr (std::is_same_v<T, eppx_variant>) {
+        if (type_name == "int") {
+            return std::holds_alternative<long long>(subject) || std::holds_alternative<bool>(subject);
+        } else if (type_name == "float") {
+            return std::holds_alternative<double>(subject);
+        } else if (type_name == "str") {
+            return std::holds_alternative<std::string>(subject);
+        } else if (type_name == "bool") {
+            return std::holds_alternative<bool>(subject);
+        } else if (type_name == "list" || type_name == "tuple") {
+            return std::holds_alternative<eppx_list_ref>(subject);
+        }
+        return false;
+    } else if constexpr (std::is_same_v<T, bool>) {
+        return type_name == "bool" || type_name == "int";
+    } else if constexpr (std::is_integral_v<T>) {
+        return type_name == "int";
+    } else if constexpr (std::is_floating_point_v<T>) {
+        return type_name == "float";
+    } else if constexpr (std::is_same_v<T, std::string>) {
+        return type_name == "str";
+    } else if constexpr (eppx_is_list<T>::value) {
+        return type_name == "list" || type_name == "tuple";
+    } else if constexpr (eppx_is_dict<T>::value) {
+        return type_name == "dict";
+    } else if constexpr (eppx_is_set<T>::value) {
+        return type_name == "set";
+    } else {
+        return false;
+    }
+}
+
+// What the sub-pattern of int(x), str(x), ... receives: the subject as that
+// builtin type (only meaningful once eppx_match_builtin has accepted it)
+template<typename Target, typename T>
+Target eppx_match_as(const T& subject) {
+    if constexpr (std::is_same_v<T, Target>) {
+        return subject;
+    } else if constexpr (std::is_same_v<T, eppx_variant>) {
+        if constexpr (std::is_same_v<Target, long long>) {
+            return variant_to_ll(subject);
+        } else if (const Target* value = std::get_if<Target>(&subject)) {
+            return *value;
+        }
+        return Target{};
+    } else if constexpr (std::is_arithmetic_v<T> && std::is_arithmetic_v<Target>) {
+        return static_cast<Target>(subject);
+    } else if constexpr (eppx_is_list<T>::value && std::is_same_v<Target, std::vector<eppx_variant>>) {
+        return *std::get<eppx_list_ref>(eppx_to_variant(subject));
+    } else {
+        return Target{};
+    }
+}
+
+#endif // EPPX_MATCH_HPP
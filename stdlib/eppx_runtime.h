@@ -0,0 +1,55 @@
+/* C interface of the E++ runtime library (libeppx_runtime, built from runtime/).
+ * Calls that can fail return -1 or a string with a null `data` and record the
+ * Python exception for eppx_rt_last_error_type/eppx_rt_last_error_message. */
+#ifndef EPPX_RUNTIME_H
+#define EPPX_RUNTIME_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A UTF-8 string owned by the caller; release it with eppx_rt_string_free */
+typedef struct {
+    char* data;
+    size_t len;
+} eppx_rt_string;
+
+void eppx_rt_string_free(eppx_rt_string s);
+
+/* Errors */
+eppx_rt_string eppx_rt_last_error_type(void);
+eppx_rt_string eppx_rt_last_error_message(void);
+bool eppx_rt_exception_is_subclass(const char* exception, size_t exception_len, const char* base, size_t base_len);
+
+/* Strings */
+eppx_rt_string eppx_rt_str_upper(const char* data, size_t len);
+eppx_rt_string eppx_rt_str_lower(const char* data, size_t len);
+bool eppx_rt_str_contains(const char* data, size_t len, const char* sub, size_t sub_len);
+int64_t eppx_rt_str_count(const char* data, size_t len, const char* sub, size_t sub_len);
+int64_t eppx_rt_str_index(const char* data, size_t len, const char* sub, size_t sub_len);
+eppx_rt_string eppx_rt_chr(int64_t code);
+int64_t eppx_rt_ord(const char* data, size_t len);
+/* radix is 16, 8 or 2 */
+eppx_rt_string eppx_rt_int_to_radix(int64_t n, uint32_t radix);
+
+/* Files, by handle; a negative size reads to the end */
+int64_t eppx_rt_file_open(const char* path, size_t path_len, const char* mode, size_t mode_len);
+eppx_rt_string eppx_rt_file_read(int64_t file, int64_t size);
+eppx_rt_string eppx_rt_file_readline(int64_t file, int64_t size);
+int64_t eppx_rt_file_write(int64_t file, const char* data, size_t len);
+int32_t eppx_rt_file_flush(int64_t file);
+int32_t eppx_rt_file_close(int64_t file);
+int64_t eppx_rt_file_seek(int64_t file, int64_t offset, int32_t whence);
+int64_t eppx_rt_file_tell(int64_t file);
+bool eppx_rt_file_readable(int64_t file);
+bool eppx_rt_file_writable(int64_t file);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* EPPX_RUNTIME_H */
@@ -1,8 +1,19 @@
-// Integration test for E++ inheritance (calls the transpiler and checks output)
-// This is a placeholder. Actual test would invoke the CLI or transpiler and check output.
+// Integration test for E++ inheritance: runs the compiler and checks the C++ it generates
+use std::fs;
+use std::process::Command;
+
 #[test]
 fn test_inheritance() {
-    // This would run the E++ transpiler and C++ compiler, then check output
-    // For now, just assert true as a placeholder
-    assert!(true);
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("inheritance.eppx");
+    fs::write(&source, "class Animal:\n    def speak(self):\n        return 1\n\nclass Dog(Animal):\n    def speak(self):\n        return 2\n\nprint(Dog().speak())\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_eppx-lang"))
+        .args(["build", "-q", "--emit", "cpp"])
+        .arg(&source)
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let cpp = String::from_utf8(output.stdout).unwrap();
+    assert!(cpp.contains("struct Dog : public Animal {"), "{}", cpp);
 }